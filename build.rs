@@ -1,11 +1,18 @@
+#[cfg(feature = "cli")]
 extern crate vergen_git2;
 
+#[cfg(feature = "cli")]
 use anyhow::Result;
+#[cfg(feature = "cli")]
 use vergen_git2::{BuildBuilder, Emitter, Git2Builder};
 
+#[cfg(feature = "cli")]
 pub fn main() -> Result<()> {
     Emitter::default()
         .add_instructions(&BuildBuilder::all_build()?)?
         .add_instructions(&Git2Builder::all_git()?)?
         .emit()
 }
+
+#[cfg(not(feature = "cli"))]
+pub fn main() {}
@@ -0,0 +1,53 @@
+/*
+ * C interface of the FIT file archiver.
+ *
+ * Build the shared library with:
+ *
+ *   cargo rustc --lib --release --features capi --crate-type cdylib
+ *
+ * All strings returned by this interface are owned by the caller and must be
+ * released with fitarchiver_string_free(). Activities must be released with
+ * fitarchiver_activity_free().
+ */
+
+#ifndef FITARCHIVER_H
+#define FITARCHIVER_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Opaque handle to the activity data extracted from a FIT file. */
+typedef struct FitArchiverActivity FitArchiverActivity;
+
+/* Parse a FIT file. Returns NULL on error. */
+FitArchiverActivity *fitarchiver_parse_file(const char *path);
+
+/* Release activity data returned by fitarchiver_parse_file(). */
+void fitarchiver_activity_free(FitArchiverActivity *activity);
+
+/* UTC timestamp of the activity start in seconds since the epoch. */
+int64_t fitarchiver_activity_timestamp(const FitArchiverActivity *activity);
+
+/* Expand a template string with '%' and '$' tags. Returns NULL on error. */
+char *fitarchiver_expand_template(const FitArchiverActivity *activity,
+                                  const char *template_string);
+
+/* Path of the archive file in the given directory. Returns NULL on error. */
+char *fitarchiver_archive_path(const FitArchiverActivity *activity,
+                               const char *directory,
+                               const char *template_string);
+
+/* Release a string returned by this interface. */
+void fitarchiver_string_free(char *string);
+
+/* Message of the last error in the calling thread or NULL. Owned by the library. */
+const char *fitarchiver_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* FITARCHIVER_H */
@@ -0,0 +1,227 @@
+//! # Backups of replaced archive files
+//!
+//! Archive files replaced by another file with the same archive path, i.e. by the overwrite
+//! conflict policies, are copied to a backup directory first. Each run keeps the replaced files
+//! in a snapshot directory named after the time they were replaced, with the structure of the
+//! archive. Old snapshots are removed by the gc subcommand.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use crate::prune::RetentionAge;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Path of the backup directory relative to the archive directory
+pub const BACKUP_PATH: &str = ".fitarchiver/backup";
+
+/// Format of the names of the snapshot directories
+const SNAPSHOT_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Copy an archive file that is about to be replaced to the backup directory
+///
+/// A file backed up before in the same snapshot is kept, it is the version before the run.
+///
+/// # Arguments
+///
+/// * `directory` - Base directory of the archive.
+/// * `archive_path` - Path of the archive file.
+/// * `now` - Time the file is replaced.
+pub(crate) fn backup_file(
+    directory: &Path,
+    archive_path: &Path,
+    now: DateTime<Utc>,
+) -> Result<PathBuf> {
+    // files outside of the archive keep only their name
+    let relative = match archive_path.strip_prefix(directory) {
+        Ok(relative) => relative,
+        Err(_) => Path::new(archive_path.file_name().unwrap_or_default()),
+    };
+    let backup_path = directory
+        .join(BACKUP_PATH)
+        .join(now.format(SNAPSHOT_FORMAT).to_string())
+        .join(relative);
+    if backup_path.exists() {
+        return Ok(backup_path);
+    }
+
+    let msg = format!(
+        "Unable to back up '{}' to '{}'",
+        archive_path.display(),
+        backup_path.display()
+    );
+    if let Some(parent) = backup_path.parent() {
+        fs::create_dir_all(parent).map_err(|err| ArchiverError::io(&msg, err))?;
+    }
+    fs::copy(archive_path, &backup_path).map_err(|err| ArchiverError::io(&msg, err))?;
+    Ok(backup_path)
+}
+
+/// Options controlling the removal of old backups
+#[derive(Debug, Clone)]
+pub struct GcOptions {
+    /// Base directory of the archive
+    pub directory: PathBuf,
+    /// Minimum age of the backups to remove
+    pub keep: RetentionAge,
+    /// Do not modify the archive, just show what will happen
+    pub dry_run: bool,
+}
+
+impl Default for GcOptions {
+    fn default() -> Self {
+        GcOptions {
+            directory: PathBuf::from("."),
+            keep: RetentionAge::Days(30),
+            dry_run: false,
+        }
+    }
+}
+
+/// Returns the number of files contained in a directory and its sub directories
+///
+/// # Arguments
+///
+/// * `directory` - Directory to search.
+fn count_files(directory: &Path) -> usize {
+    fs::read_dir(directory)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| match entry.path().is_dir() {
+                    true => count_files(&entry.path()),
+                    false => 1,
+                })
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Remove the snapshots of replaced archive files older than the retention age
+///
+/// # Arguments
+///
+/// * `options` - Gc options.
+/// * `now` - Current time the age of the snapshots is based on.
+fn gc_at(options: &GcOptions, now: DateTime<Utc>) -> Result<String> {
+    let Some(cutoff) = options.keep.cutoff(now) else {
+        let msg = format!("Invalid age {:?}", options.keep);
+        return Err(ArchiverError::new(&msg));
+    };
+
+    let backup_directory = options.directory.join(BACKUP_PATH);
+    let mut snapshots: Vec<(PathBuf, DateTime<Utc>)> = match fs::read_dir(&backup_directory) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| {
+                // other directories are never removed
+                let name = entry.file_name().to_string_lossy().to_string();
+                let time = NaiveDateTime::parse_from_str(&name, SNAPSHOT_FORMAT).ok()?;
+                Some((entry.path(), time.and_utc()))
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    snapshots.sort();
+
+    let mut remove_counter: usize = 0;
+    let mut file_counter: usize = 0;
+    let mut error_counter: usize = 0;
+    for (path, time) in &snapshots {
+        if *time >= cutoff {
+            continue;
+        }
+        let files = count_files(path);
+        let status = if options.dry_run {
+            "dry run"
+        } else if let Err(err) = fs::remove_dir_all(path) {
+            let msg = format!("Unable to remove backup '{}'", path.display());
+            eprintln!("{}", ArchiverError::io(&msg, err));
+            error_counter += 1;
+            continue;
+        } else {
+            "removed"
+        };
+        println!("'{}' ... {} files {}", path.display(), files, status);
+        remove_counter += 1;
+        file_counter += files;
+    }
+
+    let msg = format!(
+        "Removed {} of {} backups with {} files",
+        remove_counter,
+        snapshots.len(),
+        file_counter
+    );
+    let err = if error_counter == 0 {
+        String::new()
+    } else {
+        format!("with {} errors.", error_counter)
+    };
+
+    Ok([msg, err].join(" "))
+}
+
+/// Remove the snapshots of replaced archive files older than the retention age
+///
+/// # Arguments
+///
+/// `options` - Gc options.
+pub fn gc(options: &GcOptions) -> Result<String> {
+    gc_at(options, Utc::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test backing up replaced files and removing old backups
+    fn test_gc() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let directory = tmpdir.path();
+        let archive_path = directory.join("2023/07/run.fit");
+        fs::create_dir_all(archive_path.parent().unwrap()).unwrap();
+
+        let first = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+        let second = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap();
+        fs::write(&archive_path, "first").unwrap();
+        let backup_path = super::backup_file(directory, &archive_path, first).unwrap();
+        assert_eq!(
+            directory.join(".fitarchiver/backup/20250101T120000Z/2023/07/run.fit"),
+            backup_path
+        );
+        // the version before the run is kept
+        fs::write(&archive_path, "second").unwrap();
+        super::backup_file(directory, &archive_path, first).unwrap();
+        assert_eq!("first", fs::read_to_string(&backup_path).unwrap());
+        super::backup_file(directory, &archive_path, second).unwrap();
+
+        let mut options = super::GcOptions {
+            directory: directory.to_path_buf(),
+            keep: super::RetentionAge::Days(30),
+            dry_run: true,
+        };
+        let now = Utc.with_ymd_and_hms(2025, 3, 15, 0, 0, 0).unwrap();
+        assert_eq!(
+            "Removed 1 of 2 backups with 1 files ",
+            super::gc_at(&options, now).unwrap()
+        );
+        assert!(backup_path.exists());
+
+        options.dry_run = false;
+        assert_eq!(
+            "Removed 1 of 2 backups with 1 files ",
+            super::gc_at(&options, now).unwrap()
+        );
+        assert!(!backup_path.exists());
+        assert_eq!(
+            "Removed 0 of 1 backups with 0 files ",
+            super::gc_at(&options, now).unwrap()
+        );
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
@@ -0,0 +1,193 @@
+//! # C interface
+//!
+//! C functions exposing the FIT file parsing and the template expansion so that non-Rust tools
+//! can reuse the naming logic of the archiver. The matching declarations are contained in
+//! `include/fitarchiver.h`.
+//!
+//! All strings returned by this interface are owned by the caller and must be released with
+//! `fitarchiver_string_free()`. Activities must be released with `fitarchiver_activity_free()`.
+
+use crate::fitarchiver::{archive_path, parse_fit_file, ActivityData};
+use crate::template::expand_formatstring;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::path::Path;
+use std::ptr;
+
+thread_local! {
+    /// Message of the last error that occurred in the current thread
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Opaque handle to the activity data extracted from a FIT file
+pub struct FitArchiverActivity {
+    activity_data: ActivityData,
+}
+
+/// Remember error message for `fitarchiver_last_error()`
+fn set_last_error(msg: &str) {
+    LAST_ERROR.with(|last_error| {
+        *last_error.borrow_mut() = CString::new(msg.replace('\0', "")).ok();
+    });
+}
+
+/// Returns a string slice for a C string or `None` if it is NULL or not valid UTF-8
+///
+/// # Safety
+///
+/// `string` must be NULL or point to a NUL terminated string.
+unsafe fn to_str<'a>(string: *const c_char, name: &str) -> Option<&'a str> {
+    if string.is_null() {
+        set_last_error(&format!("'{}' is NULL", name));
+        return None;
+    }
+    match CStr::from_ptr(string).to_str() {
+        Ok(val) => Some(val),
+        Err(_) => {
+            set_last_error(&format!("'{}' is not valid UTF-8", name));
+            None
+        }
+    }
+}
+
+/// Returns a newly allocated C string or NULL if the string contains a NUL character
+fn to_c_string(string: String) -> *mut c_char {
+    match CString::new(string) {
+        Ok(val) => val.into_raw(),
+        Err(_) => {
+            set_last_error("Result contains a NUL character");
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Parse a FIT file and return the extracted activity data
+///
+/// Returns NULL on error, the reason can be retrieved with `fitarchiver_last_error()`.
+///
+/// # Safety
+///
+/// `path` must be NULL or point to a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn fitarchiver_parse_file(path: *const c_char) -> *mut FitArchiverActivity {
+    let Some(path) = to_str(path, "path") else {
+        return ptr::null_mut();
+    };
+
+    match parse_fit_file(Path::new(path)) {
+        Ok(activity_data) => Box::into_raw(Box::new(FitArchiverActivity { activity_data })),
+        Err(err) => {
+            set_last_error(&err.details());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Release activity data returned by `fitarchiver_parse_file()`
+///
+/// # Safety
+///
+/// `activity` must be NULL or a pointer returned by `fitarchiver_parse_file()` that has not been
+/// released before.
+#[no_mangle]
+pub unsafe extern "C" fn fitarchiver_activity_free(activity: *mut FitArchiverActivity) {
+    if !activity.is_null() {
+        drop(Box::from_raw(activity));
+    }
+}
+
+/// Returns the UTC timestamp of the activity start in seconds since the epoch
+///
+/// # Safety
+///
+/// `activity` must be a valid pointer returned by `fitarchiver_parse_file()`.
+#[no_mangle]
+pub unsafe extern "C" fn fitarchiver_activity_timestamp(
+    activity: *const FitArchiverActivity,
+) -> i64 {
+    match activity.as_ref() {
+        Some(activity) => activity.activity_data.timestamp.timestamp(),
+        None => {
+            set_last_error("'activity' is NULL");
+            0
+        }
+    }
+}
+
+/// Expand a template string with the data of an activity
+///
+/// Returns NULL on error, the reason can be retrieved with `fitarchiver_last_error()`.
+///
+/// # Safety
+///
+/// `activity` must be a valid pointer returned by `fitarchiver_parse_file()`. `template` must be
+/// NULL or point to a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn fitarchiver_expand_template(
+    activity: *const FitArchiverActivity,
+    template: *const c_char,
+) -> *mut c_char {
+    let Some(activity) = activity.as_ref() else {
+        set_last_error("'activity' is NULL");
+        return ptr::null_mut();
+    };
+    let Some(template) = to_str(template, "template") else {
+        return ptr::null_mut();
+    };
+
+    to_c_string(expand_formatstring(template, &activity.activity_data))
+}
+
+/// Returns the path of the archive file for an activity, exactly as the archiver computes it
+///
+/// Returns NULL on error, the reason can be retrieved with `fitarchiver_last_error()`.
+///
+/// # Safety
+///
+/// `activity` must be a valid pointer returned by `fitarchiver_parse_file()`. `directory` and
+/// `template` must be NULL or point to NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn fitarchiver_archive_path(
+    activity: *const FitArchiverActivity,
+    directory: *const c_char,
+    template: *const c_char,
+) -> *mut c_char {
+    let Some(activity) = activity.as_ref() else {
+        set_last_error("'activity' is NULL");
+        return ptr::null_mut();
+    };
+    let Some(directory) = to_str(directory, "directory") else {
+        return ptr::null_mut();
+    };
+    let Some(template) = to_str(template, "template") else {
+        return ptr::null_mut();
+    };
+
+    let path = archive_path(Path::new(directory), template, &activity.activity_data);
+    to_c_string(path.display().to_string())
+}
+
+/// Release a string returned by this interface
+///
+/// # Safety
+///
+/// `string` must be NULL or a pointer returned by this interface that has not been released
+/// before.
+#[no_mangle]
+pub unsafe extern "C" fn fitarchiver_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Returns the message of the last error in the current thread or NULL
+///
+/// The string is owned by the library and valid until the next call into the library from the
+/// same thread. It must not be released.
+#[no_mangle]
+pub extern "C" fn fitarchiver_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| match &*last_error.borrow() {
+        Some(msg) => msg.as_ptr(),
+        None => ptr::null(),
+    })
+}
@@ -0,0 +1,324 @@
+//! # Checksums of the archive files
+//!
+//! Each archive directory may contain a checksum file listing the SHA-256 hashes of its archive
+//! files in the format of `sha256sum`. The checksums are written when the files are archived and
+//! detect files that are corrupted or missing later, i.e. by bit rot of long-term archives on
+//! spinning disks. They can be checked with `sha256sum -c` as well.
+
+use crate::fitarchiver::{find_fit_files, ArchiverError, Result};
+use crate::objects::file_hash;
+use crate::signature::verify_signatures;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the checksum file in each archive directory
+pub const CHECKSUM_FILE: &str = "SHA256SUMS";
+
+/// Returns the checksums of a checksum file by file name, empty if it does not exist
+///
+/// # Arguments
+///
+/// * `path` - Path of the checksum file.
+fn read_checksums(path: &Path) -> Result<BTreeMap<String, String>> {
+    let content = match fs::read_to_string(path) {
+        Ok(val) => val,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(err) => {
+            let msg = format!("Unable to read checksum file '{}'", path.display());
+            return Err(ArchiverError::io(&msg, err));
+        }
+    };
+    Ok(content
+        .lines()
+        .filter_map(|line| {
+            // the name is preceded by '*' in binary mode of sha256sum
+            let (hash, name) = line.split_once(' ')?;
+            let name = name.strip_prefix([' ', '*'])?;
+            Some((name.to_string(), hash.to_string()))
+        })
+        .collect())
+}
+
+/// Write the checksums of a directory, the checksum file is removed if there are none
+///
+/// # Arguments
+///
+/// * `path` - Path of the checksum file.
+/// * `checksums` - Checksums by file name.
+fn write_checksums(path: &Path, checksums: &BTreeMap<String, String>) -> Result<()> {
+    let msg = format!("Unable to write checksum file '{}'", path.display());
+    if checksums.is_empty() {
+        return match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(ArchiverError::io(&msg, err)),
+            _ => Ok(()),
+        };
+    }
+    let content: String = checksums
+        .iter()
+        .map(|(name, hash)| format!("{}  {}\n", hash, name))
+        .collect();
+
+    // written under a temporary name, so an interrupted run never leaves a partial file
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(".fitarchiver");
+    let temporary_path = PathBuf::from(temporary_path);
+    let result =
+        fs::write(&temporary_path, content).and_then(|_| fs::rename(&temporary_path, path));
+    if let Err(err) = result {
+        let _ = fs::remove_file(&temporary_path);
+        return Err(ArchiverError::io(&msg, err));
+    }
+    Ok(())
+}
+
+/// Returns the checksum file and the file name of an archive file
+///
+/// # Arguments
+///
+/// * `archive_path` - Path of the archive file.
+fn checksum_entry(archive_path: &Path) -> Result<(PathBuf, String)> {
+    match (archive_path.parent(), archive_path.file_name()) {
+        (Some(parent), Some(name)) => Ok((
+            parent.join(CHECKSUM_FILE),
+            name.to_string_lossy().to_string(),
+        )),
+        _ => {
+            let msg = format!("Invalid archive file '{}'", archive_path.display());
+            Err(ArchiverError::new(&msg))
+        }
+    }
+}
+
+/// Add or update the checksum of an archive file in the checksum file of its directory
+///
+/// # Arguments
+///
+/// * `archive_path` - Path of the archive file.
+pub(crate) fn record_checksum(archive_path: &Path) -> Result<()> {
+    let (path, name) = checksum_entry(archive_path)?;
+    let hash = file_hash(archive_path).map_err(|err| {
+        let msg = format!("Unable to read file '{}'", archive_path.display());
+        ArchiverError::io(&msg, err)
+    })?;
+    let mut checksums = read_checksums(&path)?;
+    if checksums.get(&name) == Some(&hash) {
+        return Ok(());
+    }
+    checksums.insert(name, hash);
+    write_checksums(&path, &checksums)
+}
+
+/// Remove the checksum of a deleted archive file from the checksum file of its directory
+///
+/// # Arguments
+///
+/// * `archive_path` - Path of the archive file.
+pub(crate) fn remove_checksum(archive_path: &Path) -> Result<()> {
+    let (path, name) = checksum_entry(archive_path)?;
+    let mut checksums = read_checksums(&path)?;
+    if checksums.remove(&name).is_none() {
+        return Ok(());
+    }
+    write_checksums(&path, &checksums)
+}
+
+/// Move the checksum of a moved archive file to the checksum file of its new directory
+///
+/// Nothing is done if the file had no checksum.
+///
+/// # Arguments
+///
+/// * `archive_path` - Previous path of the archive file.
+/// * `new_path` - New path of the archive file.
+pub(crate) fn move_checksum(archive_path: &Path, new_path: &Path) -> Result<()> {
+    let (path, name) = checksum_entry(archive_path)?;
+    let mut checksums = read_checksums(&path)?;
+    if checksums.remove(&name).is_none() {
+        return Ok(());
+    }
+    write_checksums(&path, &checksums)?;
+    record_checksum(new_path)
+}
+
+/// Options controlling the verification of an archive
+#[derive(Debug, Clone)]
+pub struct VerifyOptions {
+    /// Base directory of the archive
+    pub directory: PathBuf,
+    /// Public key of minisign checking the signatures of the checksum files and the manifest
+    pub public_key: Option<PathBuf>,
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        VerifyOptions {
+            directory: PathBuf::from("."),
+            public_key: None,
+        }
+    }
+}
+
+/// Returns all checksum files contained in a directory and its sub directories
+///
+/// Hidden directories are ignored. The returned paths are sorted.
+///
+/// # Arguments
+///
+/// * `directory` - Directory to search.
+pub(crate) fn find_checksum_files(directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut directories = vec![directory.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let entries = fs::read_dir(&directory).map_err(|err| {
+            let msg = format!("Unable to read directory '{}'", directory.display());
+            ArchiverError::io(&msg, err)
+        })?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if path.is_dir() {
+                directories.push(path);
+            } else if entry.file_name() == CHECKSUM_FILE {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Check the archive files against the checksum files of their directories
+///
+/// Corrupted and missing files are printed, archive files without checksum are counted.
+///
+/// # Arguments
+///
+/// `options` - Verify options.
+pub fn verify(options: &VerifyOptions) -> Result<String> {
+    let mut verified: HashSet<PathBuf> = HashSet::new();
+    let mut ok_counter: usize = 0;
+    let mut corrupted_counter: usize = 0;
+    let mut missing_counter: usize = 0;
+    for path in find_checksum_files(&options.directory)? {
+        let directory = path.parent().unwrap_or(&options.directory);
+        for (name, hash) in read_checksums(&path)? {
+            let archive_path = directory.join(&name);
+            match file_hash(&archive_path) {
+                Ok(actual) if actual == hash => ok_counter += 1,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    println!("'{}' ... missing", archive_path.display());
+                    missing_counter += 1;
+                }
+                result => {
+                    if let Err(err) = result {
+                        let msg = format!("Unable to read file '{}'", archive_path.display());
+                        eprintln!("{}", ArchiverError::io(&msg, err));
+                    }
+                    println!("'{}' ... corrupted", archive_path.display());
+                    corrupted_counter += 1;
+                }
+            }
+            verified.insert(archive_path);
+        }
+    }
+    let unverified = find_fit_files(&options.directory)?
+        .into_iter()
+        .filter(|path| !verified.contains(path))
+        .count();
+    let msg = format!(
+        "Verified {} files, {} corrupted, {} missing, {} without checksum",
+        ok_counter, corrupted_counter, missing_counter, unverified
+    );
+
+    // checksums changed together with the files are only detected by the signatures
+    let Some(public_key) = &options.public_key else {
+        return Ok(msg);
+    };
+    let check = verify_signatures(&options.directory, public_key)?;
+    for path in &check.invalid {
+        println!("'{}' ... bad signature", path.display());
+    }
+    for path in &check.unsigned {
+        println!("'{}' ... unsigned", path.display());
+    }
+    Ok(format!(
+        "{}, {} valid signatures, {} bad, {} unsigned",
+        msg,
+        check.valid,
+        check.invalid.len(),
+        check.unsigned.len()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test recording checksums and verifying the archive files against them
+    fn test_verify() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let directory = tmpdir.path().join("2023");
+        fs::create_dir_all(&directory).unwrap();
+        for name in ["run.fit", "ride.fit", "swim.fit"] {
+            fs::write(directory.join(name), name).unwrap();
+            super::record_checksum(&directory.join(name)).unwrap();
+        }
+        fs::write(directory.join("walk.fit"), "walk").unwrap();
+
+        // the checksum file is compatible with sha256sum
+        let checksums = fs::read_to_string(directory.join(super::CHECKSUM_FILE)).unwrap();
+        assert_eq!(3, checksums.lines().count());
+        let line = checksums.lines().next().unwrap();
+        assert_eq!(64 + 2 + "ride.fit".len(), line.len());
+        assert!(line.ends_with("  ride.fit"));
+
+        let mut options = super::VerifyOptions {
+            directory: tmpdir.path().to_path_buf(),
+            ..Default::default()
+        };
+        assert_eq!(
+            "Verified 3 files, 0 corrupted, 0 missing, 1 without checksum",
+            super::verify(&options).unwrap()
+        );
+
+        fs::write(directory.join("run.fit"), "bit rot").unwrap();
+        fs::remove_file(directory.join("swim.fit")).unwrap();
+        assert_eq!(
+            "Verified 1 files, 1 corrupted, 1 missing, 1 without checksum",
+            super::verify(&options).unwrap()
+        );
+
+        // moved files keep their checksum
+        fs::create_dir_all(tmpdir.path().join("2024")).unwrap();
+        fs::rename(
+            directory.join("ride.fit"),
+            tmpdir.path().join("2024/ride.fit"),
+        )
+        .unwrap();
+        super::move_checksum(
+            &directory.join("ride.fit"),
+            &tmpdir.path().join("2024/ride.fit"),
+        )
+        .unwrap();
+        assert_eq!(
+            "Verified 1 files, 1 corrupted, 1 missing, 1 without checksum",
+            super::verify(&options).unwrap()
+        );
+
+        // checksum files without signature are reported without running minisign
+        options.public_key = Some(tmpdir.path().join("archive.pub"));
+        assert_eq!(
+            "Verified 1 files, 1 corrupted, 1 missing, 1 without checksum, 0 valid signatures, 0 bad, 2 unsigned",
+            super::verify(&options).unwrap()
+        );
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
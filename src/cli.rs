@@ -0,0 +1,3038 @@
+//! # Command line interface
+
+use crate::config;
+use chrono::NaiveDate;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use fitarchiver::{
+    check_template, parse_webhook_url, split_command, usage_stats_path, ConflictPolicy,
+    DedupeAction, DedupeMode, DedupeOptions, DiffOptions, DoctorOptions, DumpOptions,
+    DuplicatePolicy, FsckOptions, GapPolicy, GcOptions, GitMode, HashAlgorithm, HrvFormat,
+    HrvOptions, IgnoreOptions, IndexDumpOptions, IndexLoadOptions, InfoOptions, InvalidPolicy,
+    KindRule, LapFormat, LapsOptions, MigrateOptions, MultisportNaming, NameOptions, Options,
+    OutputFormat, PruneAction, PruneOptions, QueryOptions, RestoreOptions, RetentionAge, Route,
+    SourceDeletion, SportDirectory, StartLocation, StatsFormat, StatsOptions, StatsPeriod,
+    StorageLayout, SuspiciousTimePolicy, TierOptions, TimeMode, Timezone, TouchOptions,
+    UpgradeOptions, UploadService, Variable, VerifyOptions, WatchSource, BACKUP_PATH,
+    CHECKSUM_FILE, DEFAULT_DEVICE_STATE_TEMPLATE, DEFAULT_FILE_TEMPLATE, IGNORE_FILE, INDEX_PATH,
+    MANIFEST_PATH, OBJECTS_PATH, QUARANTINE_PATH, SIGNATURE_EXTENSION, STRAVA_CLIENT_ID_VARIABLE,
+    STRAVA_CLIENT_SECRET_VARIABLE, STRAVA_QUEUE_PATH, STRAVA_REFRESH_TOKEN_VARIABLE,
+    STRAVA_UPLOADS_PATH, USAGE_STATS_PATH, WEBDAV_PASSWORD_VARIABLE, WEBDAV_USER_VARIABLE,
+};
+use std::path::{Path, PathBuf};
+
+/// Returns matched command line arguments
+///
+/// The options of the configuration file are inserted in front of the arguments, so that the
+/// arguments override them. The configuration file of the user is only read if no arguments are
+/// given, the file given with '--config' is always read.
+pub fn parse_arguments(arguments: Option<Vec<&str>>) -> clap::ArgMatches {
+    const VERSION: &str = concat!(
+        env!("VERGEN_GIT_DESCRIBE"),
+        " compiled at ",
+        env!("VERGEN_BUILD_TIMESTAMP")
+    );
+    let mut parser = Command::new("FIT file archiver")
+        .version(VERSION)
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .about(env!("CARGO_PKG_DESCRIPTION"))
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .args_override_self(true)
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .num_args(1)
+                .value_name("file")
+                .help("Read options from a configuration file.")
+                .long_help("Read options from a configuration file instead of '~/.config/fitarchiver/config.toml'. The keys of the TOML file are the long names of the options, i.e. 'file-template = \"%Y/$s\"' or 'move = true'. Options given on the command line override the configuration file."),
+        )
+        .arg(
+            Arg::new("directory")
+                .short('d')
+                .long("directory")
+                .num_args(1)
+                .value_name("archive directory")
+                .default_value(".")
+                .help("Archive base directory.")
+                .long_help("Base directory where the archive is created. An archive directory on an SSH server is given as 'sftp://[user@]host[:port]/path', the files are transferred with the ssh command. An archive directory on a WebDAV server, i.e. Nextcloud, is given as 'webdav://[user@]host[:port]/path', the files are transferred with curl over HTTPS, 'webdav+http://' uses HTTP."),
+        )
+        .arg(
+            Arg::new("file-template")
+                .short('f')
+                .long("file-template")
+                .num_args(1)
+                .value_name("template string")
+                .default_value(DEFAULT_FILE_TEMPLATE)
+                .value_parser(|s: &str| check_template(s).map(|_| String::from(s)))
+                .help("Format string defining the path and name of the archive file in the archive directory.")
+                .long_help(
+"Format template that defines the path and name of the archive file in the archive directory. '/' must be used as a separator for path components. All strftime() tags are supported for expanding the time information of the training. In addition to the time information the following FIT file specific expansions are supported:
+
+  Tag   Description     Example          Default
+  ------------------------------------------------
+  $s    sport type      'running'        'unknown'
+  $S    sport subtype   'trail'          'unknown'
+  $n    sport name      'trail_run'      'unknown'
+  $w    workout name    'temporun_8km'   'unknown'
+  $q    day sequence    '2'              '1'
+  $t    duration        '1h05m'          'unknown'
+  $d    distance        '21.1km'         'unknown'
+  $h    avg heart rate  '152'            'unknown'
+  $o    notes           'hill_repeats'   'unknown'
+  $c    sport code      '1'              'unknown'
+  $C    subsport code   '3'              'unknown'
+  $r    start location  'home'           'unknown'
+  $R    route           'park_loop'      'unknown'
+  $g    city            'bolzano'        'unknown'
+  $G    country         'italy'          'unknown'
+  $H    geohash         'u220d'          'unknown'
+  $k    activity kind   'race'           'training'
+  $W    ISO week        '01'             -
+  $V    ISO year-week   '2025-W01'       -
+  $E    epoch seconds   '1735725600'     -
+  $J    year and day    '2025001'        -
+  $L    number of legs  '3'              'unknown'
+  $l    leg sports      'cycling-hiking' 'unknown'
+  $D    device          'fenix7-3345678' 'unknown'
+  $f    file type       'totals'         'unknown'
+  $i    intervals       '6x(1km_on)'     'unknown'
+  $I    workout steps   '3'              'unknown'
+
+The day sequence is the number of the activity within its day in the timezone of '--timezone' ordered by start time, taking the activities in the index of the archive into account. Notes are lowercase, all characters except letters and digits are replaced by '_' and they are truncated to 32 characters. The numeric FIT codes of the sport and subsport are useful for sports of new devices that have no name yet. The start location groups activities starting within a radius, see '--start-location'. The route is the reference route the activity follows, see '--route'. The city is the nearest place within 25 km of the start and the country the country of the nearest place within 250 km, so starts close to a border may get the neighboring country, see '--places'. The geohash names a cell around the start position, i.e. '${H:4}' gives cells of about 40 by 20 km for bucketing activities by region without geocoding. '${lat}' and '${lon}' expand the latitude and longitude of the start position rounded to two decimals, i.e. '46.50'. The activity kind is classified by rules, see '--kind-rule'. ISO weeks start on Monday, the last days of December may belong to week 1 of the next year. Use '%G/$W' or '$V' instead of '%Y/$W' so that weekly directories are not split across two years. The epoch seconds and the year followed by the zero-padded day of the year are compact numbers sorting like the start time, i.e. for flat names in object storage like '$J-$E-$s'. The legs of multisport activities are counted and listed without transitions, i.e. '${L}leg_${l:short}' gives '3leg_swim-bike-run'. The device is the product followed by the serial number, the file type is i.e. 'activity' or 'monitoring_b'. Activities executed from a structured workout have a signature of its steps, i.e. '15min_wu-6x(3min_on_2min_off)-10min_cd' with the duration and the intensity of each step ('on', 'off', 'wu' for warmup or 'cd' for cooldown), repeated steps in parentheses after the number of repetitions and '-' between the other steps. The number of steps does not count the repeats.
+
+Monitoring and wellness files cover a period instead of an instant. '${begin:...}' and '${end:...}' expand the first and the last timestamp of the file with the strftime() tags of the modifier, i.e. '${begin:%Y%m%d}-${end:%Y%m%d}' gives '20250106-20250112' for a weekly file. Without modifier they use '%Y%m%d'. Files without timestamps in their messages use the start time for both.
+
+User variables defined with '--var', i.e. 'athlete=jan', are expanded by '${var:athlete}'.
+
+Tags can be written as '${s}' to separate them from the following text. The format of numeric tags is selected with a modifier, i.e. '${d:km1}':
+
+  Tag   Modifiers
+  ------------------------------------------------
+  $t    'hms' (1h05m), 'min' (65min), 'int' (3912)
+  $d    'km' (21km), 'km1' to 'km9' (21.1km), 'mi',
+        'mi1' to 'mi9' (13.1mi), 'm' (21098m),
+        'int' (21098)
+  $h    'int' (152)
+  $l    'full' (swimming-cycling), 'short' (swim-bike)
+  $H    '1' to '12' characters (default '5')
+  lat   '0' to '6' decimals (default '2')
+  lon   '0' to '6' decimals (default '2')
+
+NOTE: It is possible that the shell used tries to replace tags. Therefore, the template should be passed as a quoted string.")
+        )
+        .arg(
+            Arg::new("no-template-lint")
+                .long("no-template-lint")
+                .action(ArgAction::SetTrue)
+                .help("Do not warn about file templates whose archive paths are likely to collide.")
+                .long_help("Do not warn about file templates whose archive paths are likely to collide. By default a warning is printed on every run if the template contains no day or no year of the activity, or neither seconds nor the day sequence $q, so activities of different days or starting within the same minute get the same path."),
+        )
+        .arg(
+            Arg::new("device-state-template")
+                .long("device-state-template")
+                .num_args(1)
+                .value_name("template string")
+                .default_value(DEFAULT_DEVICE_STATE_TEMPLATE)
+                .value_parser(|s: &str| check_template(s).map(|_| String::from(s)))
+                .help("Format string defining the path and name of archived device state files.")
+                .long_help("Format string defining the path and name of archived device state files in the archive directory, with the tags of '--file-template'. Device state files are the lifetime totals, goals, settings and device files a device keeps updating, i.e. 'GARMIN/Totals/totals.fit'. They are archived as snapshots apart from the activities, dated by their last update. The default keeps one snapshot per device and day, a later snapshot of the same day replaces the earlier one. Their counters are shown by 'fitarchiver info'."),
+        )
+        .arg(
+            Arg::new("timezone")
+                .long("timezone")
+                .num_args(1)
+                .value_name("timezone")
+                .default_value("UTC")
+                .value_parser(|s: &str| s.parse::<Timezone>())
+                .help("Timezone of the strftime() tags of the templates, i.e. 'local' or 'Europe/Berlin'.")
+                .long_help("Timezone of the strftime() tags of the templates and of the days numbered by the $q tag, i.e. 'local' for the timezone of the system or 'Europe/Berlin'. Named timezones come from the tz database built into the archiver, other timezones can be given as fixed offset to UTC, i.e. '+05:30'. The $E tag is always seconds since 1970-01-01 UTC."),
+        )
+        .arg(
+            Arg::new("time")
+                .long("time")
+                .num_args(1)
+                .value_name("mode")
+                .default_value("timezone")
+                .value_parser(|s: &str| s.parse::<TimeMode>())
+                .help("Local time of the strftime() tags of the templates, 'timezone' or 'local-device'.")
+                .long_help("Local time of the strftime() tags of the templates. 'timezone' converts the UTC start time to the timezone of '--timezone'. 'local-device' uses the local time recorded by the device in the activity message, so each activity gets the time of the place it happened in, i.e. while travelling. Activities without local time of the device fall back to '--timezone'. The days numbered by the $q tag stay the days of '--timezone'."),
+        )
+        .arg(
+            Arg::new("var")
+                .long("var")
+                .num_args(1)
+                .action(ArgAction::Append)
+                .value_name("name=value")
+                .value_parser(|s: &str| s.parse::<Variable>())
+                .help("User variable of the templates, i.e. 'athlete=jan' for '${var:athlete}'.")
+                .long_help("User variable of the templates, i.e. 'athlete=jan' for '${var:athlete}'. The option can be given several times to define site-specific labels like the athlete or the storage box without new tags. Names consist of letters, digits, '_' and '-', the values are inserted into the paths unchanged. A variable given several times takes the last value, templates using undefined variables are rejected."),
+        )
+        .arg(
+            Arg::new("move")
+                .short('m')
+                .long("move")
+                .action(ArgAction::SetTrue)
+                .overrides_with("copy")
+                .help("Move files to archive instead of copying them."),
+        )
+        .arg(
+            Arg::new("copy")
+                .long("copy")
+                .action(ArgAction::SetTrue)
+                .overrides_with("move")
+                .help("Copy files to archive, i.e. if the configuration file enables 'move'."),
+        )
+        .arg(
+            Arg::new("delete-sources")
+                .long("delete-sources")
+                .num_args(1)
+                .value_name("when")
+                .default_value("after-batch")
+                .value_parser(|s: &str| s.parse::<SourceDeletion>())
+                .help("Point in time when the source files are deleted in move mode.")
+                .long_help(
+"Point in time when the source files are deleted in move mode. Each archive file is verified against its source file before. Supported values:
+
+  after-batch        Delete the source files at the end of the run
+                     unless a file failed to be archived or
+                     verified, otherwise keep all of them. Other
+                     errors, i.e. files that are not FIT files or
+                     failed uploads, do not keep the source files.
+  after-clean-batch  Delete the source files at the end of the run
+                     only if it had no errors at all.
+  immediately        Delete each source file as soon as it has been
+                     archived."),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .short('n')
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .help("Do not copy or move the files, just show what will happen.")
+                .long_help("Do not copy or move the files, just show what will happen. The directories and files a real run would create are simulated in memory, so conflicts between files of the same run are reported like in a real run."),
+        )
+        .arg(
+            Arg::new("porcelain")
+                .long("porcelain")
+                .num_args(0..=1)
+                .require_equals(true)
+                .value_name("version")
+                .value_parser(["v1"])
+                .default_missing_value("v1")
+                .help("Print stable, tab separated output for scripts.")
+                .long_help(
+"Print stable, tab separated output for scripts. Each processed file results in one line 'STATUS<TAB>SOURCE<TAB>DEST'. STATUS is one of 'COPIED', 'MOVED', 'DRYRUN', 'IDENTICAL', 'SKIPPED' or 'ERROR'. DEST is empty if the archive path could not be determined. Tab, newline and backslash characters in paths are escaped as '\\t', '\\n' and '\\\\'. The summary is printed to stderr. The format of version 'v1' will not change."),
+        )
+        .arg(
+            Arg::new("print0")
+                .long("print0")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("porcelain")
+                .help("Print the paths of the archived files separated by NUL characters.")
+                .long_help("Print the paths of the archived files separated by NUL characters instead of the human readable output, i.e. for use with 'xargs -0'. Files that could not be archived are omitted. The summary is printed to stderr."),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .num_args(1)
+                .value_name("format")
+                .conflicts_with_all(["porcelain", "print0"])
+                .value_parser(|s: &str| s.parse::<OutputFormat>())
+                .help("Output format, 'text', 'porcelain', 'print0', 'json' or 'grouped'.")
+                .long_help(
+"Output format for the result of processing the files:
+  text       Human readable lines (default).
+  porcelain  Same as '--porcelain'.
+  print0     Same as '--print0'.
+  json       One JSON object per line for scripts. Each processed file results in an object with the type 'file', the 'source' and 'archive' path, the 'action' ('copied', 'moved', 'dry-run', 'identical', 'skipped' or 'error'), the extracted 'activity' data and the 'errors' of the file. Other errors result in an object with the type 'error'. The run ends with an object with the type 'summary' containing the counters and the summary message, which is printed to stderr, too.
+  grouped    Number of archived files per destination directory at the end of the run, i.e. '2024/07/ \u{2014} 13 files', followed by the summary. Errors are printed as usual."),
+        )
+        .arg(
+            Arg::new("print-path")
+                .long("print-path")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["porcelain", "output"])
+                .help("Only print the archive path of each file, do not copy or move anything.")
+                .long_help("Only print the archive path the template produces for each file, one path per line or separated by NUL characters in combination with '--print0'. Nothing is created, copied or moved. The summary is printed to stderr."),
+        )
+        .arg(
+            Arg::new("duplicate-policy")
+                .long("duplicate-policy")
+                .num_args(1)
+                .value_name("policy")
+                .default_value("keep-both")
+                .value_parser(|s: &str| s.parse::<DuplicatePolicy>())
+                .help("Policy for the same activity recorded on several devices.")
+                .long_help(
+"Policy for the same activity recorded on several devices, i.e. a head unit and a watch. Activities with start times within the duplicate window are considered to be the same activity. Supported policies:
+
+  keep-both             Archive all files.
+  keep-richest          Archive only the file with most record
+                        messages and fields.
+  prefer-device=<name>  Archive only the file recorded by a device
+                        whose manufacturer or product name contains
+                        <name>. Falls back to 'keep-richest' if no
+                        file matches."),
+        )
+        .arg(
+            Arg::new("duplicate-window")
+                .long("duplicate-window")
+                .num_args(1)
+                .value_name("seconds")
+                .default_value("60")
+                .value_parser(clap::value_parser!(i64).range(0..))
+                .help("Maximum difference of start times for activities to be considered the same."),
+        )
+        .arg(
+            Arg::new("on-conflict")
+                .long("on-conflict")
+                .num_args(1)
+                .value_name("policy")
+                .default_value("overwrite")
+                .value_parser(|s: &str| s.parse::<ConflictPolicy>())
+                .help("Policy for archive files that already exist.")
+                .long_help(format!(
+"Policy for archive files that already exist with a different content. Archive files with the same content are always kept and counted as already archived. Replaced archive files are kept as backup in '{}', see the gc subcommand. Supported policies:
+
+  overwrite           Replace the existing archive file.
+  skip                Keep the existing archive file.
+  overwrite-if-newer  Replace the existing archive file only if the
+                      source file has a newer modification time and
+                      a different content, i.e. for corrected files
+                      exported again.", BACKUP_PATH)),
+        )
+        .arg(
+            Arg::new("on-invalid")
+                .long("on-invalid")
+                .num_args(1)
+                .value_name("policy")
+                .default_value("error")
+                .value_parser(|s: &str| s.parse::<InvalidPolicy>())
+                .help("Policy for input files that are not FIT files.")
+                .long_help(format!(
+"Policy for input files that are not FIT files, i.e. empty files or other files on a memory card. They are counted apart from damaged FIT files, which are always reported as errors. Supported policies:
+
+  error       Report the file as error.
+  skip        Report the file as skipped.
+  quarantine  Archive the file under its original name and the
+              start of its content hash in the
+              '{}' directory of the archive.", QUARANTINE_PATH)),
+        )
+        .arg(
+            Arg::new("layout")
+                .long("layout")
+                .num_args(1)
+                .value_name("layout")
+                .default_value("plain")
+                .value_parser(|s: &str| s.parse::<StorageLayout>())
+                .help("Layout of the archive files.")
+                .long_help(format!(
+"Layout of the archive files. With a content-addressed layout the content of each file is stored once in '{}/<hash>' in the archive directory and the archive files are links to it. Supported layouts:
+
+  plain     Store the archive files directly.
+  hardlink  Archive files are hard links to the content.
+  symlink   Archive files are relative symbolic links to the
+            content.", OBJECTS_PATH)),
+        )
+        .arg(
+            Arg::new("index")
+                .long("index")
+                .action(ArgAction::SetTrue)
+                .help("Create and maintain the index of the archive.")
+                .long_help(format!("Create and maintain the index of the archive. The activity data of all archived files is stored in '{}' in the archive directory, i.e. to number the activities of a day with '$q' across runs. An existing index is maintained without this option.", INDEX_PATH)),
+        )
+        .arg(
+            Arg::new("no-index")
+                .long("no-index")
+                .action(ArgAction::SetTrue)
+                .overrides_with("index")
+                .help("Do not maintain the index of the archive, even if it exists.")
+                .long_help("Do not maintain the index of the archive, even if it exists, i.e. if the configuration file enables 'index'."),
+        )
+        .arg(
+            Arg::new("manifest")
+                .long("manifest")
+                .action(ArgAction::SetTrue)
+                .help("Append the archived files to the manifest of the archive.")
+                .long_help(format!("Append the archived files to the manifest of the archive. One line per archived file with date, sport, source, destination, hash and run id is appended to '{}' in the archive directory. No manifest is written by default.", MANIFEST_PATH)),
+        )
+        .arg(
+            Arg::new("no-manifest")
+                .long("no-manifest")
+                .action(ArgAction::SetTrue)
+                .overrides_with("manifest")
+                .help("Do not append the archived files to the manifest of the archive.")
+                .long_help("Do not append the archived files to the manifest of the archive, i.e. if the configuration file enables 'manifest'."),
+        )
+        .arg(
+            Arg::new("checksums")
+                .long("checksums")
+                .action(ArgAction::SetTrue)
+                .help("Record the checksums of the archive files for the verify subcommand.")
+                .long_help(format!("Record the SHA-256 checksums of the archive files in a '{}' file in each archive directory, so corrupted or missing files are detected by the verify subcommand. The files have the format of 'sha256sum' and can be checked with 'sha256sum -c' as well. Files archived before get their checksum when they are archived again.", CHECKSUM_FILE)),
+        )
+        .arg(
+            Arg::new("require-mount")
+                .long("require-mount")
+                .action(ArgAction::SetTrue)
+                .help("Fail if the archive directory is not on a mounted file system.")
+                .long_help("Fail if the archive directory is not on a mounted file system, i.e. a network share that is not mounted. Without this option only archive directories below a mountpoint of '/etc/fstab' that is not mounted are rejected, a mountpoint mounted by hand or by an automounter that is missing is silently filled from the local file system."),
+        )
+        .arg(
+            Arg::new("wait")
+                .long("wait")
+                .num_args(1)
+                .value_name("seconds")
+                .default_value("0")
+                .value_parser(clap::value_parser!(u64))
+                .help("Maximum time to wait for the archive directory to become available."),
+        )
+        .arg(
+            Arg::new("webdav-user")
+                .long("webdav-user")
+                .num_args(1)
+                .value_name("user")
+                .help("User of a WebDAV archive directory.")
+                .long_help(format!("User of a WebDAV archive directory given as 'webdav://host/path'. A user given in the URL takes precedence. Defaults to the environment variable {}.", WEBDAV_USER_VARIABLE)),
+        )
+        .arg(
+            Arg::new("webdav-password")
+                .long("webdav-password")
+                .num_args(1)
+                .value_name("password")
+                .help("Password of a WebDAV archive directory.")
+                .long_help(format!("Password of a WebDAV archive directory, i.e. an app password of Nextcloud. Set it in the configuration file or the environment variable {} instead of the command line, where other users can see it.", WEBDAV_PASSWORD_VARIABLE)),
+        )
+        .arg(
+            Arg::new("metadata-from-json")
+                .long("metadata-from-json")
+                .num_args(1)
+                .value_name("pattern")
+                .help("Complete the activity data with JSON exports matching a glob pattern.")
+                .long_help(
+"Complete the activity data with the JSON files matching a glob pattern, i.e. the files of a Garmin Connect bulk export. The activities are joined to the FIT files by the activity ID in the file name or by the start time. Only values missing in the FIT file are completed: the activity type is used as sport name ($n), the activity name as workout name ($w) and the description as notes ($o)."),
+        )
+        .arg(
+            Arg::new("multisport-name")
+                .long("multisport-name")
+                .num_args(1)
+                .value_name("strategy")
+                .default_value("joined")
+                .value_parser(|s: &str| s.parse::<MultisportNaming>())
+                .help("Naming of the sport of multisport activities used by the $s tag.")
+                .long_help("Naming of the sport of multisport activities used by the $s tag:
+
+  joined   all sports, i.e. 'multisport_swimming_transition_cycling'
+  fixed    'multisport'
+  first    sport of the first leg, i.e. 'swimming'
+  detect   'triathlon' (swim, bike, run), 'duathlon' (run, bike, run),
+           'aquathlon' (swim, run), 'aquabike' (swim, bike) or
+           'bike_run' (bike, run), otherwise all sports joined"),
+        )
+        .arg(
+            Arg::new("time-offset")
+                .long("time-offset")
+                .num_args(1)
+                .value_name("seconds")
+                .default_value("0")
+                .allow_negative_numbers(true)
+                .value_parser(clap::value_parser!(i64))
+                .help("Offset added to the timestamps of all activities, i.e. to correct a wrong clock."),
+        )
+        .arg(
+            Arg::new("stale-after")
+                .long("stale-after")
+                .num_args(1)
+                .value_name("days")
+                .default_value("0")
+                .value_parser(clap::value_parser!(u32))
+                .help("Maximum age of an activity relative to the modification time of its file, 0 disables the check.")
+                .long_help("Maximum age in days of an activity relative to the modification time of its file, 0 disables the check. The check is off by default because copying or restoring a file resets its modification time. Enable it, i.e. with 365, when the files come straight from the device."),
+        )
+        .arg(
+            Arg::new("suspicious-time")
+                .long("suspicious-time")
+                .num_args(1)
+                .value_name("policy")
+                .default_value("warn")
+                .value_parser(|s: &str| s.parse::<SuspiciousTimePolicy>())
+                .help("Policy for activities with a suspicious timestamp.")
+                .long_help(format!(
+"Policy for activities with a timestamp in the future or, if enabled, older than '--stale-after' days relative to the modification time of the file, a symptom of a dead GPS clock. A warning is printed for all of them. Supported policies:
+
+  warn        Archive the activity as usual.
+  quarantine  Archive the file under its original name and the
+              start of its content hash in the
+              '{}' directory of the archive.
+
+Once the offset of the clock is known, the files can be archived again with '--time-offset'.", QUARANTINE_PATH)),
+        )
+        .arg(
+            Arg::new("gap-threshold")
+                .long("gap-threshold")
+                .num_args(1)
+                .value_name("seconds")
+                .default_value("300")
+                .value_parser(clap::value_parser!(u32))
+                .help("Minimum length of a gap in the recording while the timer runs, 0 disables the check."),
+        )
+        .arg(
+            Arg::new("on-gaps")
+                .long("on-gaps")
+                .num_args(1)
+                .value_name("policy")
+                .default_value("warn")
+                .value_parser(|s: &str| s.parse::<GapPolicy>())
+                .help("Policy for activities with gaps in their recording.")
+                .long_help(format!(
+"Policy for activities with a gap of at least '--gap-threshold' seconds between two records while the timer was running, i.e. after losing the satellites in a tunnel or a failed auto pause. Pauses of the timer are no gaps. A warning is printed for all of them and they are flagged in the index and the JSON output. Supported policies:
+
+  warn        Archive the activity as usual.
+  quarantine  Archive the file under its original name and the
+              start of its content hash in the
+              '{}' directory of the archive for a review.", QUARANTINE_PATH)),
+        )
+        .arg(
+            Arg::new("require-gps")
+                .long("require-gps")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("indoor-only")
+                .help("Only archive activities with GPS positions.")
+                .long_help("Only archive activities with GPS positions in their records, i.e. for an archive intended for mapping. Activities without positions like trainer sessions are skipped."),
+        )
+        .arg(
+            Arg::new("indoor-only")
+                .long("indoor-only")
+                .action(ArgAction::SetTrue)
+                .help("Only archive activities without GPS positions.")
+                .long_help("Only archive activities without GPS positions in their records, i.e. trainer sessions. Activities with positions are skipped."),
+        )
+        .arg(
+            Arg::new("start-location")
+                .long("start-location")
+                .num_args(1)
+                .action(ArgAction::Append)
+                .value_name("name=lat,lon")
+                .value_parser(|s: &str| s.parse::<StartLocation>())
+                .help("Name of a start location used by the $r tag, i.e. 'home=48.137,11.575'.")
+                .long_help(
+"Name of a start location used by the $r tag, i.e. 'home=48.137,11.575'. The option can be given several times. All activities starting within the radius of a named start location get its name. Other starts are grouped into unnamed start locations called after the coordinates of their earliest start, i.e. '48.150_11.600'. The start positions of the activities in the index of the archive are taken into account, so that the names are stable across several runs."),
+        )
+        .arg(
+            Arg::new("start-radius")
+                .long("start-radius")
+                .num_args(1)
+                .value_name("meters")
+                .default_value("500")
+                .value_parser(clap::value_parser!(f64))
+                .help("Radius of the start locations used by the $r tag."),
+        )
+        .arg(
+            Arg::new("route")
+                .long("route")
+                .num_args(1)
+                .action(ArgAction::Append)
+                .value_name("name=file")
+                .value_parser(|s: &str| s.parse::<Route>())
+                .help("Reference route used by the $R tag, i.e. 'park_loop=park.gpx'.")
+                .long_help(
+"Reference route used by the $R tag, i.e. 'park_loop=park.gpx'. The option can be given several times. The route is read from the tracks and routes of a GPX file or from a FIT course. An activity follows the route if 80% of its track are within the route tolerance of the route and 80% of the route are covered by the track, regardless of the start point and direction. Other activities get 'unknown'."),
+        )
+        .arg(
+            Arg::new("route-tolerance")
+                .long("route-tolerance")
+                .num_args(1)
+                .value_name("meters")
+                .default_value("50")
+                .value_parser(clap::value_parser!(f64))
+                .help("Maximum distance of the track of an activity to the routes of the $R tag."),
+        )
+        .arg(
+            Arg::new("places")
+                .long("places")
+                .num_args(1)
+                .value_name("file")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Places resolving the start position for the $g and $G tags, i.e. 'cities1000.txt'.")
+                .long_help(
+"Places resolving the start position for the $g and $G tags, i.e. 'cities1000.txt'. Without this option a bundled dataset of major cities is used. The file is a GeoNames dump like 'cities1000.txt' from https://download.geonames.org/export/dump/ or has lines with name, ISO country code, latitude and longitude separated by tabs. Lines starting with '#' are ignored."),
+        )
+        .arg(
+            Arg::new("sport-directory")
+                .long("sport-directory")
+                .num_args(1)
+                .action(ArgAction::Append)
+                .value_name("sport=directory")
+                .value_parser(|s: &str| s.parse::<SportDirectory>())
+                .help("Directory of a sport used with templates without sport tags, i.e. 'swimming=pool'.")
+                .long_help(
+"Directory of a sport used with templates without sport tags, i.e. 'swimming=pool'. The option can be given several times. If the file template contains none of the tags $s, $n, $S, $c, $C and $l, the archive files of the sport are stored in the directory relative to the archive directory, i.e. a date only template still separates the sports. A directory can be given for a subsport with '<sport>:<subsport>=<directory>', i.e. 'swimming:lap_swimming=pool', it takes precedence over the directory of the sport. Activities of other sports are stored without directory."),
+        )
+        .arg(
+            Arg::new("kind-rule")
+                .long("kind-rule")
+                .num_args(1)
+                .action(ArgAction::Append)
+                .value_name("kind=conditions")
+                .value_parser(|s: &str| s.parse::<KindRule>())
+                .help("Rule classifying activities for the $k tag, i.e. 'race=name:race|marathon'.")
+                .long_help(
+"Rule classifying activities for the $k tag, i.e. 'commute=sport:cycling,location:home,days:mon-fri,hours:6-9'. The option can be given several times, the first rule whose conditions all match defines the kind. Activities not matching any rule are 'training'. Without rules, activities with 'race', 'marathon' or 'triathlon' in their names or notes are 'race'. Supported conditions:
+
+  name:race|marathon  Keyword in workout name, sport name or
+                      notes.
+  sport:cycling       Sport as given by the $s tag.
+  location:home       Start location as given by the $r tag.
+  days:mon-fri|sun    Days of the week of the start.
+  hours:6-9           Hours of the start (UTC), the end is
+                      exclusive.
+  min-duration:120    Minimum duration in minutes.
+  max-duration:45     Maximum duration in minutes."),
+        )
+        .arg(
+            Arg::new("git")
+                .long("git")
+                .num_args(0..=1)
+                .require_equals(true)
+                .value_name("mode")
+                .default_missing_value("git")
+                .value_parser(|s: &str| s.parse::<GitMode>())
+                .help("Commit the archived files to the git repository of the archive.")
+                .long_help(
+"Commit the archived files of each run to the git repository of the archive directory. The commit message contains the activity data of the files. Large files are supported with git LFS configured in the repository or with git-annex. Supported modes:
+
+  git    Add the files with 'git add'.
+  annex  Add the files with 'git annex add'."),
+        )
+        .arg(
+            Arg::new("upload")
+                .long("upload")
+                .num_args(1)
+                .value_name("service")
+                .value_parser(|s: &str| s.parse::<UploadService>())
+                .help("Upload the newly archived activities to an online service.")
+                .long_help(format!(
+"Upload each newly archived activity to an online service, so archiving is the only command needed after a workout. Files uploaded before are recorded in '{}' and not uploaded again, activities the service already has are skipped. Supported services:
+
+  strava  Upload to Strava. Register an API application at https://www.strava.com/settings/api and authorize it with the scope 'activity:write'. The client ID, the client secret and the refresh token are read from the environment variables {}, {} and {}. The tokens are stored in the configuration directory and refreshed automatically, so the refresh token is needed only once.
+
+The archived files are queued in '{}' and uploaded within the rate limits of the service. When the service rejects an upload because of its rate limit, the uploads pause until its next 15 minute window. Failed connections are retried with an increasing delay. Files still pending, i.e. of a large historical import, are uploaded by the following runs with '--upload'.", STRAVA_UPLOADS_PATH, STRAVA_CLIENT_ID_VARIABLE, STRAVA_CLIENT_SECRET_VARIABLE, STRAVA_REFRESH_TOKEN_VARIABLE, STRAVA_QUEUE_PATH)),
+        )
+        .arg(
+            Arg::new("upload-daily-limit")
+                .long("upload-daily-limit")
+                .num_args(1)
+                .value_name("uploads")
+                .default_value("500")
+                .requires("upload")
+                .value_parser(clap::value_parser!(u32))
+                .help("Maximum number of uploads per day (UTC), further files stay queued for the next day."),
+        )
+        .arg(
+            Arg::new("webhook")
+                .long("webhook")
+                .num_args(1)
+                .value_name("url")
+                .value_parser(|s: &str| parse_webhook_url(s))
+                .help("Post the result of each file and the summary as JSON to a URL.")
+                .long_help("Post the result of each file and the summary of the run as JSON to a HTTP or HTTPS URL, i.e. to log every new activity in a home automation. The payloads are the objects of '--output json': the file objects contain the source and archive path, the action and the activity data like sport and start time. Nothing is posted in a dry run. A failing webhook is reported but does not stop archiving."),
+        )
+        .arg(
+            Arg::new("exec")
+                .long("exec")
+                .num_args(1)
+                .value_name("command")
+                .value_parser(|s: &str| split_command(s).map(|_| String::from(s)))
+                .help("Run a command for each newly archived file.")
+                .long_help("Run a command for each newly copied or moved file, i.e. to convert it or to copy it to another service. The command is split into words like by a shell and run without a shell, quote words containing whitespace with single or double quotes. The placeholders {src}, {dst}, {sport}, {sub_sport} and {timestamp} are replaced by the source path, the archive path, the sport, the sub sport and the start time in RFC 3339 format. The output of the command is written to the standard error. A failing command is reported as error of the file. Nothing is run in a dry run."),
+        )
+        .arg(
+            Arg::new("exec-batch")
+                .long("exec-batch")
+                .num_args(1)
+                .value_name("command")
+                .value_parser(|s: &str| split_command(s).map(|_| String::from(s)))
+                .help("Run a command once with all newly archived files.")
+                .long_help("Run a command once after all files if any files were newly copied or moved, i.e. to sync them to a backup. A word that is just {src} or {dst} is replaced by the source or archive paths of all these files. The command is split and run like with '--exec'. Nothing is run in a dry run."),
+        )
+        .arg(
+            Arg::new("notify")
+                .long("notify")
+                .action(ArgAction::SetTrue)
+                .help("Show a desktop notification with the summary when finished.")
+                .long_help("Show a desktop notification with the summary when finished, i.e. when archiving is started automatically after connecting a device. Uses 'notify-send' on Linux and BSD and the notification center on macOS."),
+        )
+        .arg(
+            Arg::new("sign")
+                .long("sign")
+                .num_args(1)
+                .value_name("secret key")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Sign the checksum files and the manifest with a minisign secret key after each run.")
+                .long_help(format!("Sign the '{}' files of '--checksums' and the manifest with an Ed25519 secret key of minisign after each run, so tampering with the archive is detected by 'fitarchiver verify --public-key'. The signatures are written next to the signed files with the extension '.{}', only files changed since they were signed are signed again. The key must not have a password, create it with 'minisign -G -W -s archive.key -p archive.pub'. Requires the 'minisign' command.", CHECKSUM_FILE, SIGNATURE_EXTENSION)),
+        )
+        .arg(
+            Arg::new("usage-stats")
+                .long("usage-stats")
+                .action(ArgAction::SetTrue)
+                .help("Count the runs, archived files and errors in a local statistics file.")
+                .long_help(format!("Count the runs, the archived files and the errors by category in '{}' of the configuration directory, in total and per month. The file is only stored locally and never transmitted. Show it with 'fitarchiver doctor'. Dry runs are not counted. Set 'usage-stats = true' in the configuration file to enable it permanently.", USAGE_STATS_PATH)),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .action(ArgAction::SetTrue)
+                .help("Print the underlying cause of errors.")
+                .long_help("Print the underlying cause of errors and report the slowest files with their parse and copy durations, i.e. to identify pathological FIT files or slow destinations."),
+        )
+        .arg(
+            Arg::new("recursive")
+                .short('r')
+                .long("recursive")
+                .action(ArgAction::SetTrue)
+                .help("Search directories in the list of files recursively.")
+                .long_help(format!("Search directories in the list of files recursively for FIT files, i.e. to import a whole 'Activity' folder of a device. Without this option only the FIT files directly contained in a directory are archived. Hidden files and directories are ignored, as well as the paths listed in the '{}' files of the directories, see the ignore subcommand. An archive directory inside a searched directory is skipped, directories inside the archive directory cannot be searched recursively.", IGNORE_FILE)),
+        )
+        .arg(
+            Arg::new("max-depth")
+                .long("max-depth")
+                .num_args(1)
+                .value_name("depth")
+                .requires("recursive")
+                .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(1..))
+                .help("Maximum depth of recursively searched directories, 1 for no sub directories."),
+        )
+        .arg(
+            Arg::new("extension")
+                .long("extension")
+                .num_args(1)
+                .action(ArgAction::Append)
+                .value_name("extension")
+                .help("Additional extension of FIT files in searched directories, i.e. 'fit.bak'.")
+                .long_help("Additional extension of FIT files in searched directories, i.e. 'fit.bak' for files renamed by an exporter. The option can be given several times, an empty extension finds files without extension. The extension 'fit' is always searched, extensions are compared regardless of their case."),
+        )
+        .arg(
+            Arg::new("sniff")
+                .long("sniff")
+                .action(ArgAction::SetTrue)
+                .help("Archive files of searched directories with other extensions if they start with a FIT header.")
+                .long_help("Archive files of searched directories with other extensions if they start with a FIT header, so FIT files are not skipped because of their name. The header of each file without a FIT extension is read."),
+        )
+        .arg(
+            Arg::new("assume-readonly-source")
+                .long("assume-readonly-source")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("move")
+                .help("Never modify anything under the source paths.")
+                .long_help("Never write to, lock or delete anything under the source paths, i.e. to archive directly from the storage of a device that must not be modified. Moving files is refused and the archive directory must be separate from the source paths."),
+        )
+        .arg(
+            Arg::new("skip-duplicates")
+                .long("skip-duplicates")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("no-index")
+                .help("Skip files whose content is already archived under any name.")
+                .long_help("Skip files whose content is already archived under any name, i.e. after changing the file template. The hash of each file is compared with the hashes of the archive files in the index. Files archived before the index stored hashes or with another hash algorithm are not taken into account."),
+        )
+        .arg(
+            Arg::new("hash")
+                .long("hash")
+                .num_args(1)
+                .value_name("algorithm")
+                .default_value("sha256")
+                .value_parser(|s: &str| s.parse::<HashAlgorithm>())
+                .conflicts_with("no-index")
+                .help("Algorithm of the content hashes stored in the index.")
+                .long_help(
+"Algorithm of the content hashes stored in the index and compared to skip duplicates. Supported algorithms:
+
+  sha256  SHA-256, the default.
+  xxh64   XXH64, a much faster non-cryptographic hash for huge
+          archives on slow CPUs."),
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .action(ArgAction::SetTrue)
+                .help("Keep running and archive new FIT files of the directories in the list of files.")
+                .long_help("Keep running and archive new FIT files of the directories in the list of files as soon as they appear, i.e. on the mount point of a device. A file is archived when it did not change for one poll interval. Missing directories are polled until they appear."),
+        )
+        .arg(
+            Arg::new("poll-interval")
+                .long("poll-interval")
+                .num_args(1)
+                .value_name("seconds")
+                .default_value("2")
+                .requires("watch")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .help("Time between two polls of the watched directories."),
+        )
+        .arg(
+            Arg::new("watch-source")
+                .long("watch-source")
+                .num_args(1)
+                .action(ArgAction::Append)
+                .value_name("name=directory[;setting]")
+                .requires("watch")
+                .value_parser(|s: &str| s.parse::<WatchSource>())
+                .help("Named directory watched with its own template and filters, i.e. 'zwift=Zwift/Activities;indoor'.")
+                .long_help(
+"Named directory watched with its own template and filters, i.e. 'zwift=Zwift/Activities;indoor'. The option can be given several times to watch the mount point of a device, a Zwift folder and a Dropbox export folder at the same time. The directory is followed by settings separated by ';':
+
+  template=<template>    File template of the source instead of
+                         '--file-template'.
+  extension=<extension>  Additional extension of FIT files instead
+                         of '--extension', may be given several
+                         times.
+  gps                    Only archive activities with GPS positions.
+  indoor                 Only archive activities without GPS
+                         positions.
+
+All other options apply to all sources. The sources are archived one after the other into the same archive and index, so they do not interfere. A file in several watched directories belongs to the first watch source containing it, the directories in the list of files come last. The output of each run starts with the name of the source."),
+        )
+        .arg(
+            Arg::new("control-socket")
+                .long("control-socket")
+                .num_args(1)
+                .value_name("path")
+                .requires("watch")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Unix domain socket to supervise the watch mode.")
+                .long_help("Unix domain socket to supervise the watch mode, i.e. from a desktop applet or a script. Each request is a JSON object on a single line like '{\"command\": \"pause\"}' and is answered by a JSON object with the paused state, the files waiting to be archived and the results of the last runs. Supported commands are 'status', 'rescan' to poll immediately, 'pause' and 'resume'."),
+        )
+        .arg(
+            Arg::new("metrics")
+                .long("metrics")
+                .num_args(1)
+                .value_name("address")
+                .requires("watch")
+                .help("Serve Prometheus metrics of the watch mode over HTTP.")
+                .long_help("Serve Prometheus metrics of the watch mode over HTTP at '/metrics' of the address, i.e. 'localhost:9184'. The metrics count the runs, archived files, errors, parse failures and copied bytes and report the time of the last run and of the last run archiving files."),
+        )
+        .arg(
+            Arg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .num_args(1)
+                .value_name("jobs")
+                .default_value("1")
+                .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(1..))
+                .help("Number of files parsed and archived in parallel.")
+                .long_help("Number of files parsed and archived in parallel, i.e. to speed up importing thousands of files. The output is still printed per file in the order of the files. Files with the same archive path are archived one after another."),
+        )
+        .arg(
+            Arg::new("batch-size")
+                .long("batch-size")
+                .num_args(1)
+                .value_name("files")
+                .default_value("0")
+                .value_parser(clap::value_parser!(usize))
+                .help("Maximum number of files parsed and archived together, 0 for no limit.")
+                .long_help("Maximum number of files parsed and archived together, 0 for no limit. By default all files of a run are processed in one batch, which keeps about 1 kB per file. With a limit larger numbers of files are processed in batches one after another, so the memory does not grow with the number of files, i.e. when importing hundreds of thousands of monitoring files on a NAS with little memory. The guarantees of a run then only hold per batch: activities recorded on several devices are only detected within a batch, the sequence number '$q' only counts within a batch without an index, and in move mode the source files are deleted per batch."),
+        )
+        .arg(
+            Arg::new("export-gpx")
+                .long("export-gpx")
+                .action(ArgAction::SetTrue)
+                .help("Write a GPX file with the track of each archived file.")
+                .long_help("Write a GPX file with the track and a waypoint at the start of each lap of each archived file, i.e. to use the archive with mapping tools. The GPX file is written next to the archive file with the same name and the extension '.gpx', unless '--export-template' is given. For files archived before the GPX file is only written if it is missing."),
+        )
+        .arg(
+            Arg::new("export-template")
+                .long("export-template")
+                .num_args(1)
+                .value_name("template")
+                .requires("export-gpx")
+                .value_parser(|s: &str| check_template(s).map(|_| String::from(s)))
+                .help("Format template of the exported files within the archive directory.")
+                .long_help("Format template of the exported files within the archive directory, i.e. 'exports/%Y/$s-%Y%m%d.gpx', so exported files live in a tree parallel to the archive files. It supports the same tags as the file template, the extension is replaced by the one of the export format."),
+        )
+        .arg(
+            Arg::new("from-device")
+                .long("from-device")
+                .action(ArgAction::SetTrue)
+                .help("Archive the new files of the connected Garmin devices.")
+                .long_help("Archive the new files of the Garmin devices connected as mass storage or over MTP. Devices are found by the folder 'GARMIN/Activity' on the mounted volumes, so no mount point is needed. On Linux MTP devices are accessed through gvfs, i.e. opened once in the file manager, or mounted with jmtpfs, simple-mtpfs or go-mtpfs. Files archived before are reported as already archived and not copied again. With '--watch' the devices are archived whenever they are connected."),
+        )
+        .arg(
+            Arg::new("retry-failed")
+                .long("retry-failed")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("no-index")
+                .help("Archive the files that failed to be archived in previous runs.")
+                .long_help("Archive the files that failed to be archived in previous runs, i.e. because the disk was full or the network share went offline. Files failing to be archived are recorded with the error in the index of the archive until they are archived. Failed files that no longer exist are reported and dropped. Without a list of files only the failed files are archived."),
+        )
+        .arg(
+            Arg::new("files")
+                .num_args(1..)
+                .value_name("files")
+                .required_unless_present_any(["from-device", "retry-failed", "watch-source"])
+                .help("List of FIT files or directories to archive."),
+        )
+        .subcommand(
+            Command::new("dedupe")
+                .about("Search duplicates in an existing archive.")
+                .arg(
+                    Arg::new("directory")
+                        .short('d')
+                        .long("directory")
+                        .num_args(1)
+                        .value_name("archive directory")
+                        .default_value(".")
+                        .help("Archive base directory."),
+                )
+                .arg(
+                    Arg::new("mode")
+                        .long("mode")
+                        .num_args(1)
+                        .value_name("mode")
+                        .default_value("identical")
+                        .value_parser(|s: &str| s.parse::<DedupeMode>())
+                        .help("Kind of duplicates to search for.")
+                        .long_help(
+"Kind of duplicates to search for. Supported modes:
+
+  identical  Files with the same content.
+  activity   Files containing the same activity, i.e. recorded on
+             several devices. The file with most record messages and
+             fields is kept."),
+                )
+                .arg(
+                    Arg::new("remove")
+                        .long("remove")
+                        .action(ArgAction::SetTrue)
+                        .help("Remove the duplicates."),
+                )
+                .arg(
+                    Arg::new("hardlink")
+                        .long("hardlink")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("remove")
+                        .help("Replace identical files by hard links to the kept file."),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .short('n')
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue)
+                        .help("Do not modify the archive, just show what will happen."),
+                ),
+        )
+        .subcommand(
+            Command::new("name")
+                .about("Print or apply the template-derived name of a single file in place.")
+                .arg(
+                    Arg::new("file-template")
+                        .short('f')
+                        .long("file-template")
+                        .num_args(1)
+                        .value_name("template string")
+                        .default_value(DEFAULT_FILE_TEMPLATE)
+                        .value_parser(|s: &str| check_template(s).map(|_| String::from(s)))
+                        .help("Format string defining the name of the file. Only the last path component is used."),
+                )
+                .arg(
+                    Arg::new("timezone")
+                        .long("timezone")
+                        .num_args(1)
+                        .value_name("timezone")
+                        .default_value("UTC")
+                        .value_parser(|s: &str| s.parse::<Timezone>())
+                        .help("Timezone of the strftime() tags of the template, i.e. 'local' or 'Europe/Berlin'."),
+                )
+                .arg(
+                    Arg::new("time")
+                        .long("time")
+                        .num_args(1)
+                        .value_name("mode")
+                        .default_value("timezone")
+                        .value_parser(|s: &str| s.parse::<TimeMode>())
+                        .help("Local time of the strftime() tags of the template, 'timezone' or 'local-device'."),
+                )
+                .arg(
+                    Arg::new("var")
+                        .long("var")
+                        .num_args(1)
+                        .action(ArgAction::Append)
+                        .value_name("name=value")
+                        .value_parser(|s: &str| s.parse::<Variable>())
+                        .help("User variable of the template, i.e. 'athlete=jan' for '${var:athlete}'."),
+                )
+                .arg(
+                    Arg::new("places")
+                        .long("places")
+                        .num_args(1)
+                        .value_name("file")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Places resolving the start position for the $g and $G tags, i.e. 'cities1000.txt'."),
+                )
+                .arg(
+                    Arg::new("apply")
+                        .long("apply")
+                        .action(ArgAction::SetTrue)
+                        .help("Rename the file instead of just printing the new name."),
+                )
+                .arg(
+                    Arg::new("file")
+                        .value_name("file")
+                        .required(true)
+                        .help("FIT file to rename."),
+                ),
+        )
+        .subcommand(
+            Command::new("diff-files")
+                .about("Compare the metadata and summary statistics of two FIT files.")
+                .arg(
+                    Arg::new("messages")
+                        .long("messages")
+                        .action(ArgAction::SetTrue)
+                        .help("Compare the number of messages of each kind, too."),
+                )
+                .arg(
+                    Arg::new("first")
+                        .value_name("first file")
+                        .required(true)
+                        .help("First FIT file."),
+                )
+                .arg(
+                    Arg::new("second")
+                        .value_name("second file")
+                        .required(true)
+                        .help("Second FIT file."),
+                ),
+        )
+        .subcommand(
+            Command::new("dump")
+                .about("Print all decoded messages and fields of a FIT file.")
+                .long_about("Print all decoded messages and fields of a FIT file with their units. This helps to find the fields containing the information of interest, i.e. for a new template tag.")
+                .arg(
+                    Arg::new("mesg")
+                        .long("mesg")
+                        .num_args(1)
+                        .value_name("types")
+                        .value_delimiter(',')
+                        .help("Comma separated list of message types to print, i.e. 'session,lap'."),
+                )
+                .arg(
+                    Arg::new("file")
+                        .value_name("file")
+                        .required(true)
+                        .help("FIT file to dump."),
+                ),
+        )
+        .subcommand(
+            Command::new("info")
+                .about("Print the metadata of a FIT file and the counters of device state files.")
+                .long_about("Print the file type, the device and the time of a FIT file. Activities show their sport, device state files like 'totals.fit' the lifetime sessions, time, distance and calories per sport kept by the device.")
+                .arg(
+                    Arg::new("file")
+                        .value_name("file")
+                        .required(true)
+                        .help("FIT file to summarize."),
+                ),
+        )
+        .subcommand(
+            Command::new("laps")
+                .about("Export each lap of a FIT file to a separate GPX or CSV file.")
+                .long_about("Export each lap of a FIT file to a separate GPX or CSV file, i.e. to analyze interval workouts rep by rep. The files are named after the FIT file and the number of the lap, i.e. 'run-lap01.gpx'.")
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .num_args(1)
+                        .value_name("format")
+                        .default_value("gpx")
+                        .value_parser(|s: &str| s.parse::<LapFormat>())
+                        .help("File format of the exported laps, 'gpx' or 'csv'."),
+                )
+                .arg(
+                    Arg::new("sample-rate")
+                        .long("sample-rate")
+                        .num_args(1)
+                        .value_name("seconds")
+                        .default_value("1")
+                        .value_parser(clap::value_parser!(u64).range(1..))
+                        .help("Aggregate the records to intervals of this length.")
+                        .long_help("Aggregate the records to intervals of this length, i.e. 10 to downsample 1 s recordings to 10 s. Each interval keeps the time and position of its first record, the distance of its last record, the mean altitude and power and the maximum heart rate. 1 keeps all records."),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .num_args(1)
+                        .value_name("directory")
+                        .default_value(".")
+                        .help("Directory receiving the exported files."),
+                )
+                .arg(
+                    Arg::new("export-template")
+                        .short('t')
+                        .long("export-template")
+                        .num_args(1)
+                        .value_name("template")
+                        .help("Format template of the exported files within the output directory.")
+                        .long_help("Format template of the exported files within the output directory, i.e. 'exports/%Y/$s-%Y%m%d.gpx'. It supports the same tags as the file template of the archive, so exported files can live in a tree parallel to the archive. The number of the lap is appended and the extension is replaced by the one of the format. Without a template the files are named after the FIT file."),
+                )
+                .arg(
+                    Arg::new("file")
+                        .value_name("file")
+                        .required(true)
+                        .help("FIT file to export."),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export streams of FIT files for other tools.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("hrv")
+                        .about("Export the RR intervals of heart rate variability recordings to CSV or JSON files.")
+                        .long_about("Export the RR intervals of heart rate variability recordings to CSV or JSON files, i.e. for HRV analysis tools. The files are named after the FIT file, i.e. 'run-hrv.csv'. The CSV files contain one line per interval with the time since the first beat in seconds and the interval in milliseconds. Files without HRV messages are reported as errors.")
+                        .arg(
+                            Arg::new("format")
+                                .long("format")
+                                .num_args(1)
+                                .value_name("format")
+                                .default_value("csv")
+                                .value_parser(|s: &str| s.parse::<HrvFormat>())
+                                .help("File format of the exported intervals, 'csv' or 'json'."),
+                        )
+                        .arg(
+                            Arg::new("output")
+                                .short('o')
+                                .long("output")
+                                .num_args(1)
+                                .value_name("directory")
+                                .default_value(".")
+                                .help("Directory receiving the exported files."),
+                        )
+                        .arg(
+                            Arg::new("files")
+                                .num_args(1..)
+                                .value_name("files")
+                                .required(true)
+                                .help("FIT files to export."),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Check the files of an existing archive against their recorded checksums.")
+                .long_about(format!("Check the files of an existing archive against the checksums recorded with '--checksums' in the '{}' files of the archive directories, i.e. to detect bit rot of long-term archives. Corrupted and missing files are printed, archive files without checksum are counted.", CHECKSUM_FILE))
+                .arg(
+                    Arg::new("directory")
+                        .short('d')
+                        .long("directory")
+                        .num_args(1)
+                        .value_name("archive directory")
+                        .default_value(".")
+                        .help("Archive base directory."),
+                )
+                .arg(
+                    Arg::new("public-key")
+                        .long("public-key")
+                        .num_args(1)
+                        .value_name("file")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Check the signatures of the checksum files and the manifest with a minisign public key.")
+                        .long_help("Check the signatures written with '--sign' with the public key of minisign, i.e. 'archive.pub'. Unlike the checksums alone they detect files changed together with their checksums. Checksum files and the manifest with a bad signature or without signature are printed. Requires the 'minisign' command."),
+                ),
+        )
+        .subcommand(
+            Command::new("fsck")
+                .about("Check that the index and the files of an existing archive match each other.")
+                .long_about("Check that the index and the files of an existing archive match each other. Files missing from the index, i.e. copied into the archive by hand, and index entries whose files are gone are reported. They can be repaired with '--adopt' and '--purge'.")
+                .arg(
+                    Arg::new("directory")
+                        .short('d')
+                        .long("directory")
+                        .num_args(1)
+                        .value_name("archive directory")
+                        .default_value(".")
+                        .help("Archive base directory."),
+                )
+                .arg(
+                    Arg::new("adopt")
+                        .long("adopt")
+                        .action(ArgAction::SetTrue)
+                        .help("Add files missing from the index to the index."),
+                )
+                .arg(
+                    Arg::new("purge")
+                        .long("purge")
+                        .action(ArgAction::SetTrue)
+                        .help("Remove index entries whose files are gone from the index."),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .short('n')
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue)
+                        .help("Do not modify the index, just show what will happen."),
+                ),
+        )
+        .subcommand(
+            Command::new("index")
+                .about("Maintain the index of an existing archive.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("upgrade")
+                        .about("Migrate the index to the current schema.")
+                        .long_about("Migrate the index to the current schema. Columns added by the migration stay empty for files archived before, '--backfill' parses these files again to populate them. An interrupted backfill continues with the remaining files when it is started again.")
+                        .arg(
+                            Arg::new("directory")
+                                .short('d')
+                                .long("directory")
+                                .num_args(1)
+                                .value_name("archive directory")
+                                .default_value(".")
+                                .help("Archive base directory."),
+                        )
+                        .arg(
+                            Arg::new("backfill")
+                                .long("backfill")
+                                .action(ArgAction::SetTrue)
+                                .help("Parse archived files again to populate new columns of the index."),
+                        )
+                        .arg(
+                            Arg::new("jobs")
+                                .short('j')
+                                .long("jobs")
+                                .num_args(1)
+                                .value_name("jobs")
+                                .default_value("1")
+                                .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(1..))
+                                .help("Number of files parsed in parallel."),
+                        ),
+                )
+                .subcommand(
+                    Command::new("dump")
+                        .about("Write the entries of the index to a portable JSON file.")
+                        .long_about("Write the entries of the index to a portable JSON file, i.e. to move an archive to another machine or to merge it into another archive with 'index load'. The paths are relative to the archive directory. Without '--output' the JSON document is printed.")
+                        .arg(
+                            Arg::new("directory")
+                                .short('d')
+                                .long("directory")
+                                .num_args(1)
+                                .value_name("archive directory")
+                                .default_value(".")
+                                .help("Archive base directory."),
+                        )
+                        .arg(
+                            Arg::new("output")
+                                .short('o')
+                                .long("output")
+                                .num_args(1)
+                                .value_name("file")
+                                .value_parser(clap::value_parser!(PathBuf))
+                                .help("File the entries are written to."),
+                        ),
+                )
+                .subcommand(
+                    Command::new("load")
+                        .about("Add the entries of a JSON file written by 'index dump' to the index.")
+                        .long_about("Add the entries of a JSON file written by 'index dump' to the index, creating the index if needed. Entries already indexed with the same content are left alone. An entry whose path is indexed with another content, or whose content is indexed under another path, is reported as conflict and not loaded.")
+                        .arg(
+                            Arg::new("directory")
+                                .short('d')
+                                .long("directory")
+                                .num_args(1)
+                                .value_name("archive directory")
+                                .default_value(".")
+                                .help("Archive base directory."),
+                        )
+                        .arg(
+                            Arg::new("dry-run")
+                                .short('n')
+                                .long("dry-run")
+                                .action(ArgAction::SetTrue)
+                                .help("Do not modify the index, just show what will happen."),
+                        )
+                        .arg(
+                            Arg::new("file")
+                                .num_args(1)
+                                .value_name("file")
+                                .required(true)
+                                .value_parser(clap::value_parser!(PathBuf))
+                                .help("JSON file written by 'index dump'."),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Print the number, duration and distance of the activities per month or year and sport.")
+                .long_about("Print the number, duration and distance of the activities per month or year and sport. The statistics are kept up to date in the index on each run and are printed instantly without reading any FIT file. Archives without index are scanned with '--scan'.")
+                .arg(
+                    Arg::new("directory")
+                        .short('d')
+                        .long("directory")
+                        .num_args(1)
+                        .value_name("archive directory")
+                        .default_value(".")
+                        .help("Archive base directory."),
+                )
+                .arg(
+                    Arg::new("period")
+                        .long("period")
+                        .num_args(1)
+                        .value_name("period")
+                        .default_value("month")
+                        .value_parser(|s: &str| s.parse::<StatsPeriod>())
+                        .help("Period the activities are grouped by, 'month' or 'year'."),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .num_args(1)
+                        .value_name("format")
+                        .default_value("text")
+                        .value_parser(|s: &str| s.parse::<StatsFormat>())
+                        .help("Format of the statistics, 'text', 'json' or 'csv'."),
+                )
+                .arg(
+                    Arg::new("scan")
+                        .long("scan")
+                        .action(ArgAction::SetTrue)
+                        .help("Parse all files of the archive instead of reading the index."),
+                ),
+        )
+        .subcommand(
+            Command::new("query")
+                .about("Print the paths of the files of an existing archive matching all filters.")
+                .long_about("Print the paths of the files of an existing archive matching all filters, ordered by the start time of their activities. The activity data is read from the index of the archive. Archives without an up to date index are scanned by parsing all files.")
+                .arg(
+                    Arg::new("directory")
+                        .short('d')
+                        .long("directory")
+                        .num_args(1)
+                        .value_name("archive directory")
+                        .default_value(".")
+                        .help("Archive base directory."),
+                )
+                .arg(
+                    Arg::new("sport")
+                        .long("sport")
+                        .num_args(1)
+                        .value_name("sport")
+                        .help("Only activities of the sport, as given by the $s tag, i.e. 'running'."),
+                )
+                .arg(
+                    Arg::new("sub-sport")
+                        .long("sub-sport")
+                        .num_args(1)
+                        .value_name("subsport")
+                        .help("Only activities of the subsport, as given by the $S tag, i.e. 'trail'."),
+                )
+                .arg(
+                    Arg::new("year")
+                        .long("year")
+                        .num_args(1)
+                        .value_name("year")
+                        .value_parser(clap::value_parser!(i32))
+                        .help("Only activities of the year (UTC)."),
+                )
+                .arg(
+                    Arg::new("from")
+                        .long("from")
+                        .num_args(1)
+                        .value_name("YYYY-MM-DD")
+                        .value_parser(|s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d"))
+                        .help("Only activities on or after the day (UTC)."),
+                )
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .num_args(1)
+                        .value_name("YYYY-MM-DD")
+                        .value_parser(|s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d"))
+                        .help("Only activities on or before the day (UTC)."),
+                )
+                .arg(
+                    Arg::new("scan")
+                        .long("scan")
+                        .action(ArgAction::SetTrue)
+                        .help("Parse all files of the archive instead of reading the index."),
+                ),
+        )
+        .subcommand(
+            Command::new("restore")
+                .about("Copy archived files back to the location they were archived from.")
+                .long_about("Copy archived files back to the location they were archived from, i.e. onto a device that has been reset. The original location and the recording device of each file are taken from the index of the archive.")
+                .arg(
+                    Arg::new("directory")
+                        .short('d')
+                        .long("directory")
+                        .num_args(1)
+                        .value_name("archive directory")
+                        .default_value(".")
+                        .help("Archive base directory."),
+                )
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .num_args(1)
+                        .value_name("directory")
+                        .help("Restore the files to this directory instead of their original location."),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .short('n')
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue)
+                        .help("Do not copy any files, just show what will happen."),
+                )
+                .arg(
+                    Arg::new("files")
+                        .num_args(1..)
+                        .value_name("files")
+                        .required(true)
+                        .help("Archived FIT files to restore."),
+                ),
+        )
+        .subcommand(
+            Command::new("ignore")
+                .about(format!("Add files and directories to the '{}' files of their directories.", IGNORE_FILE))
+                .long_about(format!("Add files and directories to the '{}' files of their directories, so they are never archived, i.e. test files or the data of other apps on a device. The ignore files use the syntax of '.gitignore' files and can be edited to add patterns.", IGNORE_FILE))
+                .arg(
+                    Arg::new("dry-run")
+                        .short('n')
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue)
+                        .help("Do not modify the ignore files, just show what will happen."),
+                )
+                .arg(
+                    Arg::new("files")
+                        .num_args(1..)
+                        .value_name("files")
+                        .required(true)
+                        .help("Files and directories to ignore."),
+                ),
+        )
+        .subcommand(
+            Command::new("touch")
+                .about("Set the modification time of all files in an existing archive to the start of their activity.")
+                .arg(
+                    Arg::new("directory")
+                        .short('d')
+                        .long("directory")
+                        .num_args(1)
+                        .value_name("archive directory")
+                        .default_value(".")
+                        .help("Archive base directory."),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .short('n')
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue)
+                        .help("Do not modify the archive, just show what will happen."),
+                ),
+        )
+        .subcommand(
+            Command::new("tier")
+                .about("Move old activities of an existing archive to a cold storage directory.")
+                .long_about("Move old activities of an existing archive to a cold storage directory, i.e. a cheaper disk or a mounted cloud bucket. The structure of the archive is kept in the cold directory and the index of the archive is updated to the new location of the files.")
+                .arg(
+                    Arg::new("directory")
+                        .short('d')
+                        .long("directory")
+                        .num_args(1)
+                        .value_name("archive directory")
+                        .default_value(".")
+                        .help("Archive base directory."),
+                )
+                .arg(
+                    Arg::new("cold-directory")
+                        .short('c')
+                        .long("cold-directory")
+                        .num_args(1)
+                        .value_name("cold directory")
+                        .required(true)
+                        .help("Directory receiving the old activities."),
+                )
+                .arg(
+                    Arg::new("older-than")
+                        .long("older-than")
+                        .num_args(1)
+                        .value_name("years")
+                        .default_value("5")
+                        .value_parser(clap::value_parser!(u32))
+                        .help("Minimum age of the activities to move."),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .short('n')
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue)
+                        .help("Do not modify the archive, just show what will happen."),
+                ),
+        )
+        .subcommand(
+            Command::new("gc")
+                .about("Remove old backups of replaced archive files.")
+                .long_about(format!("Remove old backups of replaced archive files. Archive files replaced by another file, i.e. with '--on-conflict overwrite', are kept in a snapshot directory per run in '{}' of the archive. Snapshots older than '--keep' are removed.", BACKUP_PATH))
+                .arg(
+                    Arg::new("directory")
+                        .short('d')
+                        .long("directory")
+                        .num_args(1)
+                        .value_name("archive directory")
+                        .default_value(".")
+                        .help("Archive base directory."),
+                )
+                .arg(
+                    Arg::new("keep")
+                        .long("keep")
+                        .num_args(1)
+                        .value_name("age")
+                        .default_value("30d")
+                        .value_parser(|s: &str| s.parse::<RetentionAge>())
+                        .help("Minimum age of the backups to remove, i.e. '30d', '6w', '18m' or '5y'."),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .short('n')
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue)
+                        .help("Do not modify the archive, just show what will happen."),
+                ),
+        )
+        .subcommand(
+            Command::new("prune")
+                .about("Delete or compress old activities of an existing archive.")
+                .long_about("Delete or compress old activities of an existing archive, i.e. the daily monitoring files of a watch that are only of interest for a limited time. The selected files are only shown unless '--apply' is given, so the policy can be reviewed first. Deleted and compressed files are removed from the index of the archive, directories left empty are removed.")
+                .arg(
+                    Arg::new("directory")
+                        .short('d')
+                        .long("directory")
+                        .num_args(1)
+                        .value_name("archive directory")
+                        .default_value(".")
+                        .help("Archive base directory."),
+                )
+                .arg(
+                    Arg::new("older-than")
+                        .long("older-than")
+                        .num_args(1)
+                        .value_name("age")
+                        .required(true)
+                        .value_parser(|s: &str| s.parse::<RetentionAge>())
+                        .help("Minimum age of the activities to prune, i.e. '5y', '18m', '6w' or '30d'."),
+                )
+                .arg(
+                    Arg::new("sport")
+                        .long("sport")
+                        .num_args(1)
+                        .value_name("sport")
+                        .action(ArgAction::Append)
+                        .help("Only prune activities of the sport, may be given several times.")
+                        .long_help("Only prune activities of the sport, may be given several times. Files that are not activities are selected by their file type, i.e. 'monitoring' selects all monitoring files of a watch."),
+                )
+                .arg(
+                    Arg::new("action")
+                        .long("action")
+                        .num_args(1)
+                        .value_name("action")
+                        .default_value("delete")
+                        .value_parser(|s: &str| s.parse::<PruneAction>())
+                        .help("Action applied to the selected files.")
+                        .long_help(
+"Action applied to the selected files. Supported actions:
+
+  delete    Delete the files.
+  compress  Replace the files by files compressed with gzip, named
+            like the file with the additional extension '.gz'."),
+                )
+                .arg(
+                    Arg::new("apply")
+                        .long("apply")
+                        .action(ArgAction::SetTrue)
+                        .help("Modify the archive, otherwise the selected files are only shown."),
+                ),
+        )
+        .subcommand(
+            Command::new("migrate")
+                .about("Move the files of an existing archive to the paths of another file template.")
+                .long_about("Move the files of an existing archive to the paths of another file template, i.e. after changing the naming scheme. All archive files are parsed again and moved to the paths derived from the new template, directories left empty are removed and the index of the archive is updated to the new location of the files. Files whose new path is taken by another file are kept. The options of the tags are the same as for archiving.")
+                .arg(
+                    Arg::new("directory")
+                        .short('d')
+                        .long("directory")
+                        .num_args(1)
+                        .value_name("archive directory")
+                        .default_value(".")
+                        .help("Archive base directory."),
+                )
+                .arg(
+                    Arg::new("file-template")
+                        .short('f')
+                        .long("file-template")
+                        .num_args(1)
+                        .value_name("template string")
+                        .required(true)
+                        .value_parser(|s: &str| check_template(s).map(|_| String::from(s)))
+                        .help("New format string defining the path and name of the archive files."),
+                )
+                .arg(
+                    Arg::new("timezone")
+                        .long("timezone")
+                        .num_args(1)
+                        .value_name("timezone")
+                        .default_value("UTC")
+                        .value_parser(|s: &str| s.parse::<Timezone>())
+                        .help("Timezone of the strftime() tags of the template, i.e. 'local' or 'Europe/Berlin'."),
+                )
+                .arg(
+                    Arg::new("time")
+                        .long("time")
+                        .num_args(1)
+                        .value_name("mode")
+                        .default_value("timezone")
+                        .value_parser(|s: &str| s.parse::<TimeMode>())
+                        .help("Local time of the strftime() tags of the template, 'timezone' or 'local-device'."),
+                )
+                .arg(
+                    Arg::new("var")
+                        .long("var")
+                        .num_args(1)
+                        .action(ArgAction::Append)
+                        .value_name("name=value")
+                        .value_parser(|s: &str| s.parse::<Variable>())
+                        .help("User variable of the template, i.e. 'athlete=jan' for '${var:athlete}'."),
+                )
+                .arg(
+                    Arg::new("places")
+                        .long("places")
+                        .num_args(1)
+                        .value_name("file")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Places resolving the start position for the $g and $G tags, i.e. 'cities1000.txt'."),
+                )
+                .arg(
+                    Arg::new("multisport-name")
+                        .long("multisport-name")
+                        .num_args(1)
+                        .value_name("strategy")
+                        .default_value("joined")
+                        .value_parser(|s: &str| s.parse::<MultisportNaming>())
+                        .help("Naming of the sport of multisport activities used by the $s tag."),
+                )
+                .arg(
+                    Arg::new("sport-directory")
+                        .long("sport-directory")
+                        .num_args(1)
+                        .action(ArgAction::Append)
+                        .value_name("sport=directory")
+                        .value_parser(|s: &str| s.parse::<SportDirectory>())
+                        .help("Directory of a sport used with templates without sport tags, i.e. 'swimming=pool'."),
+                )
+                .arg(
+                    Arg::new("start-location")
+                        .long("start-location")
+                        .num_args(1)
+                        .action(ArgAction::Append)
+                        .value_name("name=lat,lon")
+                        .value_parser(|s: &str| s.parse::<StartLocation>())
+                        .help("Name of a start location used by the $r tag, i.e. 'home=48.137,11.575'."),
+                )
+                .arg(
+                    Arg::new("start-radius")
+                        .long("start-radius")
+                        .num_args(1)
+                        .value_name("meters")
+                        .default_value("500")
+                        .value_parser(clap::value_parser!(f64))
+                        .help("Radius of the start locations used by the $r tag."),
+                )
+                .arg(
+                    Arg::new("route")
+                        .long("route")
+                        .num_args(1)
+                        .action(ArgAction::Append)
+                        .value_name("name=file")
+                        .value_parser(|s: &str| s.parse::<Route>())
+                        .help("Reference route used by the $R tag, i.e. 'park_loop=park.gpx'."),
+                )
+                .arg(
+                    Arg::new("route-tolerance")
+                        .long("route-tolerance")
+                        .num_args(1)
+                        .value_name("meters")
+                        .default_value("50")
+                        .value_parser(clap::value_parser!(f64))
+                        .help("Maximum distance of the track of an activity to the routes of the $R tag."),
+                )
+                .arg(
+                    Arg::new("kind-rule")
+                        .long("kind-rule")
+                        .num_args(1)
+                        .action(ArgAction::Append)
+                        .value_name("kind=conditions")
+                        .value_parser(|s: &str| s.parse::<KindRule>())
+                        .help("Rule classifying activities for the $k tag, i.e. 'race=name:race|marathon'."),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .short('n')
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue)
+                        .help("Do not modify the archive, just show what will happen."),
+                )
+                .arg(
+                    Arg::new("preview")
+                        .long("preview")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("dry-run")
+                        .help("Do not modify the archive, show the old and new paths side by side.")
+                        .long_help("Do not modify the archive, show the old and the new path of each file that would be moved side by side, followed by the number of files to move and the number of unchanged files. The old paths are red and the new paths green on a terminal, unless the environment variable NO_COLOR is set."),
+                ),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Print the local usage statistics counted with '--usage-stats'.")
+                .long_about("Print the local usage statistics counted with '--usage-stats': the number of runs, archived files and errors by category since the first run and per month, showing how the archive has grown and how often errors occur."),
+        );
+
+    let user_config = arguments.is_none();
+    let arguments: Vec<String> = match arguments {
+        Some(val) => val.into_iter().map(String::from).collect(),
+        None => std::env::args().collect(),
+    };
+
+    // the options of the configuration file do not apply to subcommands
+    if let Ok(matches) = parser.clone().try_get_matches_from(&arguments) {
+        if matches.subcommand().is_some() {
+            return matches;
+        }
+    }
+
+    let configured = match config::arguments(&arguments, &parser, user_config) {
+        Ok(val) => val,
+        Err(msg) => parser.error(clap::error::ErrorKind::Io, msg).exit(),
+    };
+    parser.get_matches_from(
+        arguments
+            .iter()
+            .take(1)
+            .chain(configured.iter())
+            .chain(arguments.iter().skip(1)),
+    )
+}
+
+/// Returns archiver options from matched command line arguments
+pub fn archive_options(matches: &ArgMatches) -> Options {
+    Options {
+        directory: PathBuf::from(matches.get_one::<String>("directory").unwrap()),
+        file_template: matches.get_one::<String>("file-template").unwrap().clone(),
+        template_lint: !matches.get_flag("no-template-lint"),
+        device_state_template: matches
+            .get_one::<String>("device-state-template")
+            .unwrap()
+            .clone(),
+        timezone: matches.get_one::<Timezone>("timezone").unwrap().clone(),
+        time: *matches.get_one::<TimeMode>("time").unwrap(),
+        variables: matches
+            .get_many::<Variable>("var")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        move_files: matches.get_flag("move"),
+        readonly_source: matches.get_flag("assume-readonly-source"),
+        delete_sources: *matches.get_one::<SourceDeletion>("delete-sources").unwrap(),
+        dry_run: matches.get_flag("dry-run"),
+        print_path: matches.get_flag("print-path"),
+        duplicate_policy: matches
+            .get_one::<DuplicatePolicy>("duplicate-policy")
+            .unwrap()
+            .clone(),
+        duplicate_window: *matches.get_one::<i64>("duplicate-window").unwrap(),
+        on_conflict: *matches.get_one::<ConflictPolicy>("on-conflict").unwrap(),
+        conflict_resolver: None,
+        on_invalid: *matches.get_one::<InvalidPolicy>("on-invalid").unwrap(),
+        layout: *matches.get_one::<StorageLayout>("layout").unwrap(),
+        git: matches.get_one::<GitMode>("git").copied(),
+        upload: matches.get_one::<UploadService>("upload").copied(),
+        upload_daily_limit: *matches.get_one::<u32>("upload-daily-limit").unwrap(),
+        webhook: matches.get_one::<String>("webhook").cloned(),
+        exec: matches.get_one::<String>("exec").cloned(),
+        exec_batch: matches.get_one::<String>("exec-batch").cloned(),
+        sign_key: matches.get_one::<PathBuf>("sign").cloned(),
+        usage_stats: match matches.get_flag("usage-stats") {
+            true => usage_stats_path(),
+            false => None,
+        },
+        metadata_from_json: matches.get_one::<String>("metadata-from-json").cloned(),
+        multisport_naming: *matches
+            .get_one::<MultisportNaming>("multisport-name")
+            .unwrap(),
+        time_offset: *matches.get_one::<i64>("time-offset").unwrap(),
+        stale_after: *matches.get_one::<u32>("stale-after").unwrap(),
+        suspicious_time: *matches
+            .get_one::<SuspiciousTimePolicy>("suspicious-time")
+            .unwrap(),
+        gap_threshold: *matches.get_one::<u32>("gap-threshold").unwrap(),
+        on_gaps: *matches.get_one::<GapPolicy>("on-gaps").unwrap(),
+        require_gps: matches.get_flag("require-gps"),
+        indoor_only: matches.get_flag("indoor-only"),
+        start_locations: matches
+            .get_many::<StartLocation>("start-location")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        start_radius: *matches.get_one::<f64>("start-radius").unwrap(),
+        kind_rules: matches
+            .get_many::<KindRule>("kind-rule")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        routes: matches
+            .get_many::<Route>("route")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        route_tolerance: *matches.get_one::<f64>("route-tolerance").unwrap(),
+        places: matches.get_one::<PathBuf>("places").cloned(),
+        sport_directories: matches
+            .get_many::<SportDirectory>("sport-directory")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        verbose: matches.get_flag("verbose"),
+        // an existing index is kept up to date, the duplicates are found in the index
+        index: !matches.get_flag("no-index")
+            && (matches.get_flag("index")
+                || matches.get_flag("skip-duplicates")
+                || Path::new(matches.get_one::<String>("directory").unwrap())
+                    .join(INDEX_PATH)
+                    .exists()),
+        skip_duplicates: matches.get_flag("skip-duplicates"),
+        hash_algorithm: *matches.get_one::<HashAlgorithm>("hash").unwrap(),
+        manifest: matches.get_flag("manifest"),
+        checksums: matches.get_flag("checksums"),
+        require_mount: matches.get_flag("require-mount"),
+        wait: *matches.get_one::<u64>("wait").unwrap(),
+        webdav_user: matches.get_one::<String>("webdav-user").cloned(),
+        webdav_password: matches.get_one::<String>("webdav-password").cloned(),
+        recursive: matches.get_flag("recursive"),
+        max_depth: matches.get_one::<usize>("max-depth").copied(),
+        extensions: matches
+            .get_many::<String>("extension")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        sniff: matches.get_flag("sniff"),
+        watch: matches.get_flag("watch"),
+        poll_interval: *matches.get_one::<u64>("poll-interval").unwrap(),
+        watch_sources: matches
+            .get_many::<WatchSource>("watch-source")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        control_socket: matches.get_one::<PathBuf>("control-socket").cloned(),
+        metrics: matches.get_one::<String>("metrics").cloned(),
+        jobs: *matches.get_one::<usize>("jobs").unwrap(),
+        batch_size: *matches.get_one::<usize>("batch-size").unwrap(),
+        export_gpx: matches.get_flag("export-gpx"),
+        export_template: matches.get_one::<String>("export-template").cloned(),
+        from_device: matches.get_flag("from-device"),
+        retry_failed: matches.get_flag("retry-failed"),
+        output: if let Some(output) = matches.get_one::<OutputFormat>("output") {
+            *output
+        } else if matches.contains_id("porcelain") {
+            OutputFormat::Porcelain
+        } else if matches.get_flag("print0") {
+            OutputFormat::Print0
+        } else {
+            OutputFormat::Text
+        },
+        files: matches
+            .get_many::<String>("files")
+            .unwrap_or_default()
+            .map(PathBuf::from)
+            .collect(),
+    }
+}
+
+/// Returns dedupe options from matched command line arguments of the dedupe subcommand
+pub fn dedupe_options(matches: &ArgMatches) -> DedupeOptions {
+    DedupeOptions {
+        directory: PathBuf::from(matches.get_one::<String>("directory").unwrap()),
+        mode: *matches.get_one::<DedupeMode>("mode").unwrap(),
+        action: if matches.get_flag("remove") {
+            DedupeAction::Remove
+        } else if matches.get_flag("hardlink") {
+            DedupeAction::Hardlink
+        } else {
+            DedupeAction::Report
+        },
+        dry_run: matches.get_flag("dry-run"),
+    }
+}
+
+/// Returns name options from matched command line arguments of the name subcommand
+pub fn name_options(matches: &ArgMatches) -> NameOptions {
+    NameOptions {
+        file: PathBuf::from(matches.get_one::<String>("file").unwrap()),
+        file_template: matches.get_one::<String>("file-template").unwrap().clone(),
+        timezone: matches.get_one::<Timezone>("timezone").unwrap().clone(),
+        time: *matches.get_one::<TimeMode>("time").unwrap(),
+        variables: matches
+            .get_many::<Variable>("var")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        places: matches.get_one::<PathBuf>("places").cloned(),
+        apply: matches.get_flag("apply"),
+    }
+}
+
+/// Returns diff options from matched command line arguments of the diff-files subcommand
+pub fn diff_options(matches: &ArgMatches) -> DiffOptions {
+    DiffOptions {
+        first: PathBuf::from(matches.get_one::<String>("first").unwrap()),
+        second: PathBuf::from(matches.get_one::<String>("second").unwrap()),
+        messages: matches.get_flag("messages"),
+    }
+}
+
+/// Returns dump options from matched command line arguments of the dump subcommand
+pub fn dump_options(matches: &ArgMatches) -> DumpOptions {
+    DumpOptions {
+        file: PathBuf::from(matches.get_one::<String>("file").unwrap()),
+        messages: matches
+            .get_many::<String>("mesg")
+            .map(|messages| messages.cloned().collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// Returns info options from matched command line arguments of the info subcommand
+pub fn info_options(matches: &ArgMatches) -> InfoOptions {
+    InfoOptions {
+        file: PathBuf::from(matches.get_one::<String>("file").unwrap()),
+    }
+}
+
+/// Returns doctor options of the doctor subcommand
+pub fn doctor_options() -> DoctorOptions {
+    DoctorOptions {
+        usage_stats: usage_stats_path(),
+    }
+}
+
+/// Returns laps options from matched command line arguments of the laps subcommand
+pub fn laps_options(matches: &ArgMatches) -> LapsOptions {
+    LapsOptions {
+        file: PathBuf::from(matches.get_one::<String>("file").unwrap()),
+        output: PathBuf::from(matches.get_one::<String>("output").unwrap()),
+        template: matches.get_one::<String>("export-template").cloned(),
+        format: *matches.get_one::<LapFormat>("format").unwrap(),
+        sample_rate: *matches.get_one::<u64>("sample-rate").unwrap(),
+    }
+}
+
+/// Returns HRV options from matched command line arguments of the export hrv subcommand
+pub fn hrv_options(matches: &ArgMatches) -> HrvOptions {
+    HrvOptions {
+        files: matches
+            .get_many::<String>("files")
+            .unwrap()
+            .map(PathBuf::from)
+            .collect(),
+        output: PathBuf::from(matches.get_one::<String>("output").unwrap()),
+        format: *matches.get_one::<HrvFormat>("format").unwrap(),
+    }
+}
+
+/// Returns verify options from matched command line arguments of the verify subcommand
+pub fn verify_options(matches: &ArgMatches) -> VerifyOptions {
+    VerifyOptions {
+        directory: PathBuf::from(matches.get_one::<String>("directory").unwrap()),
+        public_key: matches.get_one::<PathBuf>("public-key").cloned(),
+    }
+}
+
+/// Returns fsck options from matched command line arguments of the fsck subcommand
+pub fn fsck_options(matches: &ArgMatches) -> FsckOptions {
+    FsckOptions {
+        directory: PathBuf::from(matches.get_one::<String>("directory").unwrap()),
+        adopt: matches.get_flag("adopt"),
+        purge: matches.get_flag("purge"),
+        dry_run: matches.get_flag("dry-run"),
+    }
+}
+
+/// Returns upgrade options from matched command line arguments of the index upgrade subcommand
+pub fn upgrade_options(matches: &ArgMatches) -> UpgradeOptions {
+    UpgradeOptions {
+        directory: PathBuf::from(matches.get_one::<String>("directory").unwrap()),
+        backfill: matches.get_flag("backfill"),
+        jobs: *matches.get_one::<usize>("jobs").unwrap(),
+    }
+}
+
+/// Returns index dump options from matched command line arguments of the index dump subcommand
+pub fn index_dump_options(matches: &ArgMatches) -> IndexDumpOptions {
+    IndexDumpOptions {
+        directory: PathBuf::from(matches.get_one::<String>("directory").unwrap()),
+        output: matches.get_one::<PathBuf>("output").cloned(),
+    }
+}
+
+/// Returns index load options from matched command line arguments of the index load subcommand
+pub fn index_load_options(matches: &ArgMatches) -> IndexLoadOptions {
+    IndexLoadOptions {
+        directory: PathBuf::from(matches.get_one::<String>("directory").unwrap()),
+        dry_run: matches.get_flag("dry-run"),
+        input: matches.get_one::<PathBuf>("file").unwrap().clone(),
+    }
+}
+
+/// Returns stats options from matched command line arguments of the stats subcommand
+pub fn stats_options(matches: &ArgMatches) -> StatsOptions {
+    StatsOptions {
+        directory: PathBuf::from(matches.get_one::<String>("directory").unwrap()),
+        period: *matches.get_one::<StatsPeriod>("period").unwrap(),
+        format: *matches.get_one::<StatsFormat>("format").unwrap(),
+        scan: matches.get_flag("scan"),
+    }
+}
+
+/// Returns query options from matched command line arguments of the query subcommand
+pub fn query_options(matches: &ArgMatches) -> QueryOptions {
+    QueryOptions {
+        directory: PathBuf::from(matches.get_one::<String>("directory").unwrap()),
+        sport: matches.get_one::<String>("sport").cloned(),
+        sub_sport: matches.get_one::<String>("sub-sport").cloned(),
+        year: matches.get_one::<i32>("year").copied(),
+        from: matches.get_one::<NaiveDate>("from").copied(),
+        to: matches.get_one::<NaiveDate>("to").copied(),
+        scan: matches.get_flag("scan"),
+    }
+}
+
+/// Returns restore options from matched command line arguments of the restore subcommand
+pub fn restore_options(matches: &ArgMatches) -> RestoreOptions {
+    RestoreOptions {
+        directory: PathBuf::from(matches.get_one::<String>("directory").unwrap()),
+        files: matches
+            .get_many::<String>("files")
+            .unwrap()
+            .map(PathBuf::from)
+            .collect(),
+        target: matches.get_one::<String>("to").map(PathBuf::from),
+        dry_run: matches.get_flag("dry-run"),
+    }
+}
+
+/// Returns ignore options from matched command line arguments of the ignore subcommand
+pub fn ignore_options(matches: &ArgMatches) -> IgnoreOptions {
+    IgnoreOptions {
+        files: matches
+            .get_many::<String>("files")
+            .unwrap()
+            .map(PathBuf::from)
+            .collect(),
+        dry_run: matches.get_flag("dry-run"),
+    }
+}
+
+/// Returns touch options from matched command line arguments of the touch subcommand
+pub fn touch_options(matches: &ArgMatches) -> TouchOptions {
+    TouchOptions {
+        directory: PathBuf::from(matches.get_one::<String>("directory").unwrap()),
+        dry_run: matches.get_flag("dry-run"),
+    }
+}
+
+/// Returns tier options from matched command line arguments of the tier subcommand
+pub fn tier_options(matches: &ArgMatches) -> TierOptions {
+    TierOptions {
+        directory: PathBuf::from(matches.get_one::<String>("directory").unwrap()),
+        cold_directory: PathBuf::from(matches.get_one::<String>("cold-directory").unwrap()),
+        older_than: *matches.get_one::<u32>("older-than").unwrap(),
+        dry_run: matches.get_flag("dry-run"),
+    }
+}
+
+/// Returns gc options from matched command line arguments of the gc subcommand
+pub fn gc_options(matches: &ArgMatches) -> GcOptions {
+    GcOptions {
+        directory: PathBuf::from(matches.get_one::<String>("directory").unwrap()),
+        keep: *matches.get_one::<RetentionAge>("keep").unwrap(),
+        dry_run: matches.get_flag("dry-run"),
+    }
+}
+
+/// Returns prune options from matched command line arguments of the prune subcommand
+pub fn prune_options(matches: &ArgMatches) -> PruneOptions {
+    PruneOptions {
+        directory: PathBuf::from(matches.get_one::<String>("directory").unwrap()),
+        older_than: *matches.get_one::<RetentionAge>("older-than").unwrap(),
+        sports: matches
+            .get_many::<String>("sport")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        action: *matches.get_one::<PruneAction>("action").unwrap(),
+        apply: matches.get_flag("apply"),
+    }
+}
+
+/// Returns migrate options from matched command line arguments of the migrate subcommand
+pub fn migrate_options(matches: &ArgMatches) -> MigrateOptions {
+    MigrateOptions {
+        directory: PathBuf::from(matches.get_one::<String>("directory").unwrap()),
+        file_template: matches.get_one::<String>("file-template").unwrap().clone(),
+        timezone: matches.get_one::<Timezone>("timezone").unwrap().clone(),
+        time: *matches.get_one::<TimeMode>("time").unwrap(),
+        variables: matches
+            .get_many::<Variable>("var")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        multisport_naming: *matches
+            .get_one::<MultisportNaming>("multisport-name")
+            .unwrap(),
+        sport_directories: matches
+            .get_many::<SportDirectory>("sport-directory")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        start_locations: matches
+            .get_many::<StartLocation>("start-location")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        start_radius: *matches.get_one::<f64>("start-radius").unwrap(),
+        kind_rules: matches
+            .get_many::<KindRule>("kind-rule")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        routes: matches
+            .get_many::<Route>("route")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        route_tolerance: *matches.get_one::<f64>("route-tolerance").unwrap(),
+        places: matches.get_one::<PathBuf>("places").cloned(),
+        dry_run: matches.get_flag("dry-run"),
+        preview: matches.get_flag("preview"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use fitarchiver::{
+        ConflictPolicy, DedupeAction, DedupeMode, DuplicatePolicy, GapPolicy, GitMode,
+        HashAlgorithm, HrvFormat, InvalidPolicy, LapFormat, MultisportNaming, OutputFormat,
+        PruneAction, RetentionAge, SourceDeletion, SportDirectory, StatsFormat, StatsPeriod,
+        StorageLayout, SuspiciousTimePolicy, TimeMode, Timezone, Variable, WatchSource,
+    };
+    use std::path::PathBuf;
+
+    #[test]
+    /// Test default options
+    fn test_archive_options_default() {
+        let options = super::archive_options(&super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "source.fit",
+        ])));
+
+        assert_eq!(PathBuf::from("."), options.directory);
+        assert_eq!(fitarchiver::DEFAULT_FILE_TEMPLATE, options.file_template);
+        assert!(options.template_lint);
+        assert_eq!(
+            fitarchiver::DEFAULT_DEVICE_STATE_TEMPLATE,
+            options.device_state_template
+        );
+        assert_eq!(Timezone::default(), options.timezone);
+        assert_eq!(None, options.usage_stats);
+        assert!(!options.move_files);
+        assert!(!options.readonly_source);
+        assert_eq!(SourceDeletion::AfterBatch, options.delete_sources);
+        assert!(!options.dry_run);
+        assert_eq!(OutputFormat::Text, options.output);
+        assert_eq!(DuplicatePolicy::KeepBoth, options.duplicate_policy);
+        assert_eq!(60, options.duplicate_window);
+        assert_eq!(ConflictPolicy::Overwrite, options.on_conflict);
+        assert_eq!(InvalidPolicy::Error, options.on_invalid);
+        assert_eq!(StorageLayout::Plain, options.layout);
+        assert_eq!(None, options.git);
+        assert_eq!(None, options.metadata_from_json);
+        assert_eq!(MultisportNaming::Joined, options.multisport_naming);
+        assert_eq!(0, options.time_offset);
+        assert_eq!(0, options.stale_after);
+        assert_eq!(SuspiciousTimePolicy::Warn, options.suspicious_time);
+        assert_eq!(300, options.gap_threshold);
+        assert_eq!(GapPolicy::Warn, options.on_gaps);
+        assert!(!options.require_gps);
+        assert!(!options.indoor_only);
+        assert!(options.start_locations.is_empty());
+        assert!(options.kind_rules.is_empty());
+        assert!(options.sport_directories.is_empty());
+        assert_eq!(500.0, options.start_radius);
+        assert!(!options.verbose);
+        assert!(!options.index);
+        assert!(!options.skip_duplicates);
+        assert_eq!(HashAlgorithm::Sha256, options.hash_algorithm);
+        assert!(!options.manifest);
+        assert!(!options.checksums);
+        assert!(!options.require_mount);
+        assert_eq!(0, options.wait);
+        assert!(!options.recursive);
+        assert_eq!(None, options.max_depth);
+        assert!(options.extensions.is_empty());
+        assert!(!options.sniff);
+        assert!(!options.watch);
+        assert_eq!(2, options.poll_interval);
+        assert_eq!(None, options.control_socket);
+        assert_eq!(None, options.metrics);
+        assert_eq!(1, options.jobs);
+        assert_eq!(fitarchiver::DEFAULT_BATCH_SIZE, options.batch_size);
+        assert!(!options.export_gpx);
+        assert_eq!(None, options.export_template);
+        assert_eq!(vec![PathBuf::from("source.fit")], options.files);
+    }
+
+    #[test]
+    /// Test options read from a configuration file
+    fn test_archive_options_config() {
+        let tmpdir =
+            tempdir::TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let config = tmpdir.path().join("config.toml");
+        std::fs::write(
+            &config,
+            "directory = \"archive\"\nmove = true\nwait = 30\nstart-location = [\"home=48.1,11.5\"]\n",
+        )
+        .unwrap();
+        let config = config.to_str().unwrap();
+
+        let options = super::archive_options(&super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "--config",
+            config,
+            "source.fit",
+        ])));
+        assert_eq!(PathBuf::from("archive"), options.directory);
+        assert!(options.move_files);
+        assert_eq!(30, options.wait);
+        assert_eq!(1, options.start_locations.len());
+
+        // options on the command line override the configuration file
+        let options = super::archive_options(&super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "--config",
+            config,
+            "-d",
+            "other",
+            "--copy",
+            "--wait",
+            "5",
+            "source.fit",
+        ])));
+        assert_eq!(PathBuf::from("other"), options.directory);
+        assert!(!options.move_files);
+        assert_eq!(5, options.wait);
+    }
+
+    #[test]
+    /// Test desktop notification option
+    fn test_notify() {
+        let matches = super::parse_arguments(Some(vec!["fitarchiver", "source.fit"]));
+        assert!(!matches.get_flag("notify"));
+
+        let matches = super::parse_arguments(Some(vec!["fitarchiver", "--notify", "source.fit"]));
+        assert!(matches.get_flag("notify"));
+    }
+
+    #[test]
+    /// Test NUL separated output option
+    fn test_archive_options_print0() {
+        let options = super::archive_options(&super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "--print0",
+            "source.fit",
+        ])));
+
+        assert_eq!(OutputFormat::Print0, options.output);
+        assert!(!options.print_path);
+    }
+
+    #[test]
+    /// Test print path option
+    fn test_archive_options_print_path() {
+        let options = super::archive_options(&super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "--print-path",
+            "source.fit",
+        ])));
+
+        assert_eq!(OutputFormat::Text, options.output);
+        assert!(options.print_path);
+    }
+
+    #[test]
+    /// Test selecting the output format
+    fn test_archive_options_output() {
+        let options = super::archive_options(&super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "--output",
+            "json",
+            "source.fit",
+        ])));
+        assert_eq!(OutputFormat::Json, options.output);
+
+        let options = super::archive_options(&super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "--output=porcelain",
+            "source.fit",
+        ])));
+        assert_eq!(OutputFormat::Porcelain, options.output);
+
+        let options = super::archive_options(&super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "--output=grouped",
+            "source.fit",
+        ])));
+        assert_eq!(OutputFormat::Grouped, options.output);
+    }
+
+    #[test]
+    /// Test selecting the hash algorithm of the index
+    fn test_archive_options_hash() {
+        let options = super::archive_options(&super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "--hash",
+            "xxh64",
+            "source.fit",
+        ])));
+        assert_eq!(HashAlgorithm::Xxh64, options.hash_algorithm);
+    }
+
+    #[test]
+    /// Test archiving connected devices without a list of files
+    fn test_archive_options_from_device() {
+        let options = super::archive_options(&super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "--from-device",
+        ])));
+        assert!(options.from_device);
+        assert!(options.files.is_empty());
+    }
+
+    #[test]
+    /// Test archiving the files that failed before without a list of files
+    fn test_archive_options_retry_failed() {
+        let options = super::archive_options(&super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "--retry-failed",
+        ])));
+        assert!(options.retry_failed);
+        assert!(options.files.is_empty());
+    }
+
+    #[test]
+    /// Test creating the index on request and maintaining an existing one
+    fn test_archive_options_index() {
+        let tmpdir =
+            tempdir::TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let directory = tmpdir.path().to_str().unwrap();
+        let index = |arguments: &[&str]| {
+            let mut arguments = [&["fitarchiver", "-d", directory], arguments].concat();
+            arguments.push("source.fit");
+            super::archive_options(&super::parse_arguments(Some(arguments))).index
+        };
+        assert!(!index(&[]));
+        assert!(index(&["--index"]));
+        assert!(index(&["--skip-duplicates"]));
+        assert!(!index(&["--index", "--no-index"]));
+
+        std::fs::create_dir_all(tmpdir.path().join(".fitarchiver")).unwrap();
+        std::fs::write(tmpdir.path().join(fitarchiver::INDEX_PATH), "").unwrap();
+        assert!(index(&[]));
+        assert!(!index(&["--no-index"]));
+
+        // cleanup
+        std::fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test options given on the command line
+    fn test_archive_options() {
+        let options = super::archive_options(&super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "-m",
+            "-n",
+            "-v",
+            "--no-index",
+            "--manifest",
+            "--checksums",
+            "--require-mount",
+            "--wait",
+            "30",
+            "--porcelain",
+            "-d",
+            "archive",
+            "-f",
+            "$s/%Y",
+            "--duplicate-policy",
+            "prefer-device=edge",
+            "--duplicate-window",
+            "120",
+            "--on-conflict",
+            "overwrite-if-newer",
+            "--on-invalid",
+            "quarantine",
+            "--layout",
+            "symlink",
+            "--git=annex",
+            "--metadata-from-json",
+            "export/*.json",
+            "--start-location",
+            "home=48.137,11.575",
+            "--start-location",
+            "office=48.15,11.6",
+            "--start-radius",
+            "250",
+            "--kind-rule",
+            "commute=location:office,days:mon-fri",
+            "--sport-directory",
+            "swimming=pool",
+            "--require-gps",
+            "--multisport-name",
+            "detect",
+            "--time-offset",
+            "-3600",
+            "--stale-after",
+            "30",
+            "--suspicious-time",
+            "quarantine",
+            "--on-gaps",
+            "quarantine",
+            "--delete-sources",
+            "immediately",
+            "-r",
+            "--max-depth",
+            "3",
+            "--extension",
+            "fit.bak",
+            "--extension",
+            "",
+            "--sniff",
+            "--watch",
+            "--poll-interval",
+            "10",
+            "--watch-source",
+            "zwift=zwift;indoor",
+            "--control-socket",
+            "/run/user/1000/fitarchiver.sock",
+            "--metrics",
+            "localhost:9184",
+            "-j",
+            "4",
+            "--batch-size",
+            "100",
+            "--export-gpx",
+            "--export-template",
+            "exports/%Y/$s",
+            "--no-template-lint",
+            "--usage-stats",
+            "--sign",
+            "archive.key",
+            "--device-state-template",
+            "state/$D/%Y-$f",
+            "--timezone",
+            "Europe/Berlin",
+            "--time",
+            "local-device",
+            "--var",
+            "athlete=jan",
+            "--var",
+            "box=nas1",
+            "--places",
+            "cities1000.txt",
+            "--exec",
+            "gpsbabel -i garmin_fit -f {dst} -o gpx -F {dst}.gpx",
+            "--exec-batch",
+            "rsync -a {dst} backup:activities/",
+            "first.fit",
+            "second.fit",
+        ])));
+
+        assert_eq!(PathBuf::from("archive"), options.directory);
+        assert_eq!("$s/%Y", options.file_template);
+        assert!(!options.template_lint);
+        assert_eq!("state/$D/%Y-$f", options.device_state_template);
+        assert_eq!(
+            "Europe/Berlin".parse::<Timezone>().unwrap(),
+            options.timezone
+        );
+        assert_eq!(TimeMode::LocalDevice, options.time);
+        assert_eq!(
+            vec![
+                "athlete=jan".parse::<Variable>().unwrap(),
+                "box=nas1".parse().unwrap()
+            ],
+            options.variables
+        );
+        assert_eq!(Some(PathBuf::from("cities1000.txt")), options.places);
+        assert_eq!(fitarchiver::usage_stats_path(), options.usage_stats);
+        assert_eq!(Some(PathBuf::from("archive.key")), options.sign_key);
+        assert!(options.move_files);
+        assert_eq!(SourceDeletion::Immediately, options.delete_sources);
+        assert!(options.dry_run);
+        assert_eq!(OutputFormat::Porcelain, options.output);
+        assert_eq!(
+            DuplicatePolicy::PreferDevice(String::from("edge")),
+            options.duplicate_policy
+        );
+        assert_eq!(120, options.duplicate_window);
+        assert_eq!(ConflictPolicy::OverwriteIfNewer, options.on_conflict);
+        assert_eq!(InvalidPolicy::Quarantine, options.on_invalid);
+        assert_eq!(StorageLayout::Symlink, options.layout);
+        assert_eq!(Some(GitMode::Annex), options.git);
+        assert_eq!(
+            Some(String::from("export/*.json")),
+            options.metadata_from_json
+        );
+        assert_eq!(
+            vec!["home", "office"],
+            options
+                .start_locations
+                .iter()
+                .map(|location| location.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(250.0, options.start_radius);
+        assert_eq!(
+            vec!["commute"],
+            options
+                .kind_rules
+                .iter()
+                .map(|rule| rule.kind.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![SportDirectory {
+                sport: String::from("swimming"),
+                sub_sport: None,
+                directory: String::from("pool"),
+            }],
+            options.sport_directories
+        );
+        assert!(options.require_gps);
+        assert!(!options.indoor_only);
+        assert_eq!(MultisportNaming::Detect, options.multisport_naming);
+        assert_eq!(-3600, options.time_offset);
+        assert_eq!(30, options.stale_after);
+        assert_eq!(SuspiciousTimePolicy::Quarantine, options.suspicious_time);
+        assert_eq!(GapPolicy::Quarantine, options.on_gaps);
+        assert!(options.verbose);
+        assert!(!options.index);
+        assert!(options.manifest);
+        assert!(options.checksums);
+        assert!(options.require_mount);
+        assert_eq!(30, options.wait);
+        assert!(options.recursive);
+        assert_eq!(Some(3), options.max_depth);
+        assert_eq!(
+            vec![String::from("fit.bak"), String::new()],
+            options.extensions
+        );
+        assert!(options.sniff);
+        assert!(options.watch);
+        assert_eq!(10, options.poll_interval);
+        assert_eq!(
+            vec!["zwift=zwift;indoor".parse::<WatchSource>().unwrap()],
+            options.watch_sources
+        );
+        assert_eq!(
+            Some(PathBuf::from("/run/user/1000/fitarchiver.sock")),
+            options.control_socket
+        );
+        assert_eq!(Some(String::from("localhost:9184")), options.metrics);
+        assert_eq!(4, options.jobs);
+        assert_eq!(100, options.batch_size);
+        assert!(options.export_gpx);
+        assert_eq!(Some(String::from("exports/%Y/$s")), options.export_template);
+        assert_eq!(
+            Some(String::from(
+                "gpsbabel -i garmin_fit -f {dst} -o gpx -F {dst}.gpx"
+            )),
+            options.exec
+        );
+        assert_eq!(
+            Some(String::from("rsync -a {dst} backup:activities/")),
+            options.exec_batch
+        );
+        assert_eq!(
+            vec![PathBuf::from("first.fit"), PathBuf::from("second.fit")],
+            options.files
+        );
+    }
+
+    #[test]
+    /// Test options of the dedupe subcommand
+    fn test_dedupe_options() {
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "dedupe",
+            "-n",
+            "-d",
+            "archive",
+            "--mode",
+            "activity",
+            "--remove",
+        ]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::dedupe_options(sub_matches);
+
+        assert_eq!("dedupe", name);
+        assert_eq!(PathBuf::from("archive"), options.directory);
+        assert_eq!(DedupeMode::Activity, options.mode);
+        assert_eq!(DedupeAction::Remove, options.action);
+        assert!(options.dry_run);
+    }
+
+    #[test]
+    /// Test options of the name subcommand
+    fn test_name_options() {
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "name",
+            "--apply",
+            "--var",
+            "athlete=jan",
+            "--places",
+            "places.tsv",
+            "source.fit",
+        ]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::name_options(sub_matches);
+
+        assert_eq!("name", name);
+        assert_eq!(PathBuf::from("source.fit"), options.file);
+        assert_eq!(fitarchiver::DEFAULT_FILE_TEMPLATE, options.file_template);
+        assert_eq!(Timezone::default(), options.timezone);
+        assert_eq!(
+            vec!["athlete=jan".parse::<Variable>().unwrap()],
+            options.variables
+        );
+        assert_eq!(Some(PathBuf::from("places.tsv")), options.places);
+        assert!(options.apply);
+    }
+
+    #[test]
+    /// Test options of the diff-files subcommand
+    fn test_diff_options() {
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "diff-files",
+            "--messages",
+            "watch.fit",
+            "bike.fit",
+        ]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::diff_options(sub_matches);
+
+        assert_eq!("diff-files", name);
+        assert_eq!(PathBuf::from("watch.fit"), options.first);
+        assert_eq!(PathBuf::from("bike.fit"), options.second);
+        assert!(options.messages);
+    }
+
+    #[test]
+    /// Test options of the dump subcommand
+    fn test_dump_options() {
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "dump",
+            "activity.fit",
+            "--mesg",
+            "session,lap",
+        ]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::dump_options(sub_matches);
+
+        assert_eq!("dump", name);
+        assert_eq!(PathBuf::from("activity.fit"), options.file);
+        assert_eq!(vec!["session", "lap"], options.messages);
+
+        let matches = super::parse_arguments(Some(vec!["fitarchiver", "dump", "activity.fit"]));
+        let options = super::dump_options(matches.subcommand().unwrap().1);
+        assert!(options.messages.is_empty());
+    }
+
+    #[test]
+    /// Test options of the info subcommand
+    fn test_info_options() {
+        let matches = super::parse_arguments(Some(vec!["fitarchiver", "info", "totals.fit"]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::info_options(sub_matches);
+
+        assert_eq!("info", name);
+        assert_eq!(PathBuf::from("totals.fit"), options.file);
+    }
+
+    #[test]
+    /// Test options of the laps subcommand
+    fn test_laps_options() {
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "laps",
+            "--format",
+            "csv",
+            "--sample-rate",
+            "10",
+            "-o",
+            "laps",
+            "-t",
+            "exports/%Y/$s",
+            "activity.fit",
+        ]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::laps_options(sub_matches);
+
+        assert_eq!("laps", name);
+        assert_eq!(PathBuf::from("activity.fit"), options.file);
+        assert_eq!(PathBuf::from("laps"), options.output);
+        assert_eq!(Some(String::from("exports/%Y/$s")), options.template);
+        assert_eq!(LapFormat::Csv, options.format);
+        assert_eq!(10, options.sample_rate);
+
+        let matches = super::parse_arguments(Some(vec!["fitarchiver", "laps", "activity.fit"]));
+        let options = super::laps_options(matches.subcommand().unwrap().1);
+        assert_eq!(PathBuf::from("."), options.output);
+        assert_eq!(None, options.template);
+        assert_eq!(LapFormat::Gpx, options.format);
+        assert_eq!(1, options.sample_rate);
+    }
+
+    #[test]
+    /// Test options of the fsck subcommand
+    fn test_fsck_options() {
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "fsck",
+            "-d",
+            "archive",
+            "--adopt",
+            "-n",
+        ]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::fsck_options(sub_matches);
+
+        assert_eq!("fsck", name);
+        assert_eq!(PathBuf::from("archive"), options.directory);
+        assert!(options.adopt);
+        assert!(!options.purge);
+        assert!(options.dry_run);
+    }
+
+    #[test]
+    /// Test options of the index upgrade subcommand
+    fn test_upgrade_options() {
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "index",
+            "upgrade",
+            "-d",
+            "archive",
+            "--backfill",
+            "-j",
+            "4",
+        ]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let (name_upgrade, sub_matches) = sub_matches.subcommand().unwrap();
+        let options = super::upgrade_options(sub_matches);
+
+        assert_eq!("index", name);
+        assert_eq!("upgrade", name_upgrade);
+        assert_eq!(PathBuf::from("archive"), options.directory);
+        assert!(options.backfill);
+        assert_eq!(4, options.jobs);
+    }
+
+    #[test]
+    /// Test options of the index load subcommand
+    fn test_index_load_options() {
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "index",
+            "load",
+            "-d",
+            "archive",
+            "-n",
+            "index.json",
+        ]));
+        let (_, sub_matches) = matches.subcommand().unwrap();
+        let (name, sub_matches) = sub_matches.subcommand().unwrap();
+        let options = super::index_load_options(sub_matches);
+
+        assert_eq!("load", name);
+        assert_eq!(PathBuf::from("archive"), options.directory);
+        assert!(options.dry_run);
+        assert_eq!(PathBuf::from("index.json"), options.input);
+    }
+
+    #[test]
+    /// Test options of the export hrv subcommand
+    fn test_hrv_options() {
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "export",
+            "hrv",
+            "--format",
+            "json",
+            "-o",
+            "hrv",
+            "first.fit",
+            "second.fit",
+        ]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let (name_hrv, sub_matches) = sub_matches.subcommand().unwrap();
+        let options = super::hrv_options(sub_matches);
+
+        assert_eq!("export", name);
+        assert_eq!("hrv", name_hrv);
+        assert_eq!(HrvFormat::Json, options.format);
+        assert_eq!(PathBuf::from("hrv"), options.output);
+        assert_eq!(2, options.files.len());
+    }
+
+    #[test]
+    /// Test options of the verify subcommand
+    fn test_verify_options() {
+        let matches = super::parse_arguments(Some(vec!["fitarchiver", "verify", "-d", "archive"]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::verify_options(sub_matches);
+
+        assert_eq!("verify", name);
+        assert_eq!(PathBuf::from("archive"), options.directory);
+        assert_eq!(None, options.public_key);
+
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "verify",
+            "--public-key",
+            "archive.pub",
+        ]));
+        let options = super::verify_options(matches.subcommand().unwrap().1);
+        assert_eq!(Some(PathBuf::from("archive.pub")), options.public_key);
+    }
+
+    #[test]
+    /// Test options of the stats subcommand
+    fn test_stats_options() {
+        let matches = super::parse_arguments(Some(vec!["fitarchiver", "stats", "-d", "archive"]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::stats_options(sub_matches);
+
+        assert_eq!("stats", name);
+        assert_eq!(PathBuf::from("archive"), options.directory);
+        assert_eq!(StatsPeriod::Month, options.period);
+        assert_eq!(StatsFormat::Text, options.format);
+        assert!(!options.scan);
+
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "stats",
+            "--period",
+            "year",
+            "--format",
+            "csv",
+            "--scan",
+        ]));
+        let (_, sub_matches) = matches.subcommand().unwrap();
+        let options = super::stats_options(sub_matches);
+
+        assert_eq!(StatsPeriod::Year, options.period);
+        assert_eq!(StatsFormat::Csv, options.format);
+        assert!(options.scan);
+    }
+
+    #[test]
+    /// Test options of the query subcommand
+    fn test_query_options() {
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "query",
+            "-d",
+            "archive",
+            "--sport",
+            "running",
+            "--sub-sport",
+            "trail",
+            "--from",
+            "2023-06-01",
+            "--to",
+            "2023-08-31",
+        ]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::query_options(sub_matches);
+
+        assert_eq!("query", name);
+        assert_eq!(PathBuf::from("archive"), options.directory);
+        assert_eq!(Some(String::from("running")), options.sport);
+        assert_eq!(Some(String::from("trail")), options.sub_sport);
+        assert_eq!(None, options.year);
+        assert_eq!(NaiveDate::from_ymd_opt(2023, 6, 1), options.from);
+        assert_eq!(NaiveDate::from_ymd_opt(2023, 8, 31), options.to);
+        assert!(!options.scan);
+    }
+
+    #[test]
+    /// Test options of the restore subcommand
+    fn test_restore_options() {
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "restore",
+            "-d",
+            "archive",
+            "--to",
+            "restored",
+            "archive/2023/07/run.fit",
+        ]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::restore_options(sub_matches);
+
+        assert_eq!("restore", name);
+        assert_eq!(PathBuf::from("archive"), options.directory);
+        assert_eq!(
+            vec![PathBuf::from("archive/2023/07/run.fit")],
+            options.files
+        );
+        assert_eq!(Some(PathBuf::from("restored")), options.target);
+        assert!(!options.dry_run);
+    }
+
+    #[test]
+    /// Test options of the ignore subcommand
+    fn test_ignore_options() {
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "ignore",
+            "-n",
+            "Activity/test.fit",
+            "Apps",
+        ]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::ignore_options(sub_matches);
+
+        assert_eq!("ignore", name);
+        assert_eq!(
+            vec![PathBuf::from("Activity/test.fit"), PathBuf::from("Apps")],
+            options.files
+        );
+        assert!(options.dry_run);
+    }
+
+    #[test]
+    /// Test options of the touch subcommand
+    fn test_touch_options() {
+        let matches =
+            super::parse_arguments(Some(vec!["fitarchiver", "touch", "-d", "archive", "-n"]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::touch_options(sub_matches);
+
+        assert_eq!("touch", name);
+        assert_eq!(PathBuf::from("archive"), options.directory);
+        assert!(options.dry_run);
+    }
+
+    #[test]
+    /// Test options of the tier subcommand
+    fn test_tier_options() {
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "tier",
+            "-c",
+            "/mnt/cold",
+            "--older-than",
+            "3",
+        ]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::tier_options(sub_matches);
+
+        assert_eq!("tier", name);
+        assert_eq!(PathBuf::from("."), options.directory);
+        assert_eq!(PathBuf::from("/mnt/cold"), options.cold_directory);
+        assert_eq!(3, options.older_than);
+        assert!(!options.dry_run);
+    }
+
+    #[test]
+    /// Test options of the gc subcommand
+    fn test_gc_options() {
+        let matches = super::parse_arguments(Some(vec!["fitarchiver", "gc", "--keep", "6w", "-n"]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::gc_options(sub_matches);
+
+        assert_eq!("gc", name);
+        assert_eq!(PathBuf::from("."), options.directory);
+        assert_eq!(RetentionAge::Weeks(6), options.keep);
+        assert!(options.dry_run);
+    }
+
+    #[test]
+    /// Test options of the prune subcommand
+    fn test_prune_options() {
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "prune",
+            "--older-than",
+            "5y",
+            "--sport",
+            "monitoring",
+            "--sport",
+            "walking",
+            "--action",
+            "compress",
+        ]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::prune_options(sub_matches);
+
+        assert_eq!("prune", name);
+        assert_eq!(PathBuf::from("."), options.directory);
+        assert_eq!(RetentionAge::Years(5), options.older_than);
+        assert_eq!(vec!["monitoring", "walking"], options.sports);
+        assert_eq!(PruneAction::Compress, options.action);
+        assert!(!options.apply);
+    }
+
+    #[test]
+    /// Test options of the migrate subcommand
+    fn test_migrate_options() {
+        let matches = super::parse_arguments(Some(vec![
+            "fitarchiver",
+            "migrate",
+            "-d",
+            "archive",
+            "-f",
+            "$s/%Y/%Y-%m-%d-$q",
+            "--timezone",
+            "UTC",
+            "--time",
+            "local-device",
+            "--kind-rule",
+            "race=name:race",
+            "--preview",
+        ]));
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        let options = super::migrate_options(sub_matches);
+
+        assert_eq!("migrate", name);
+        assert_eq!(PathBuf::from("archive"), options.directory);
+        assert_eq!("$s/%Y/%Y-%m-%d-$q", options.file_template);
+        assert_eq!(Timezone::default(), options.timezone);
+        assert_eq!(TimeMode::LocalDevice, options.time);
+        assert_eq!(MultisportNaming::Joined, options.multisport_naming);
+        assert_eq!(1, options.kind_rules.len());
+        assert_eq!(500.0, options.start_radius);
+        assert!(!options.dry_run);
+        assert!(options.preview);
+    }
+}
@@ -0,0 +1,197 @@
+//! # Plausibility of activity timestamps
+//!
+//! Devices with a dead or reset GPS clock record activities with timestamps in the future or
+//! years before the file was written. Such activities are reported and optionally archived in a
+//! quarantine directory instead of being filed under the wrong year.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use crate::objects::file_hash;
+use chrono::{DateTime, TimeDelta, Utc};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Path of the quarantine directory relative to the archive directory
+pub const QUARANTINE_PATH: &str = "quarantine";
+
+/// Number of characters of the content hash in the names of quarantined files
+const QUARANTINE_HASH_LENGTH: usize = 8;
+
+/// Tolerated difference of timestamps in the future in seconds, i.e. for clocks of other systems
+const FUTURE_TOLERANCE: i64 = 3600;
+
+/// Policy for activities with a suspicious timestamp
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SuspiciousTimePolicy {
+    /// Print a warning and archive the activity as usual
+    #[default]
+    Warn,
+    /// Print a warning and archive the file in the quarantine directory under its original name
+    Quarantine,
+}
+
+impl FromStr for SuspiciousTimePolicy {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "warn" => Ok(SuspiciousTimePolicy::Warn),
+            "quarantine" => Ok(SuspiciousTimePolicy::Quarantine),
+            _ => {
+                let msg = format!(
+                    "Invalid suspicious time policy '{}', expected 'warn' or 'quarantine'",
+                    s
+                );
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+/// Returns the reason why a timestamp is suspicious or `None` if it is plausible
+///
+/// # Arguments
+///
+/// * `timestamp` - Start time of the activity.
+/// * `modified` - Modification time of the FIT file if available.
+/// * `now` - Current time.
+/// * `stale_after` - Maximum age in days of the activity relative to the modification time of
+///   the file, 0 disables the check.
+pub(crate) fn suspicious_timestamp(
+    timestamp: DateTime<Utc>,
+    modified: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+    stale_after: u32,
+) -> Option<String> {
+    if timestamp > now + TimeDelta::seconds(FUTURE_TOLERANCE) {
+        return Some(String::from("is in the future"));
+    }
+    match modified {
+        Some(modified)
+            if stale_after > 0 && timestamp < modified - TimeDelta::days(stale_after.into()) =>
+        {
+            Some(format!(
+                "is more than {} days older than the file modified at {}",
+                stale_after,
+                modified.format("%Y-%m-%d %H:%M:%S")
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the path of a file in the quarantine directory
+///
+/// Devices reuse names like 'ACTIVITY.FIT', so the start of the content hash is appended to the
+/// name, i.e. 'ACTIVITY-1a2b3c4d.FIT'. Different files with the same name do not collide and a
+/// file quarantined again is identical to its first copy.
+///
+/// # Arguments
+///
+/// * `directory` - Base directory of the archive.
+/// * `source_path` - Path of the source file.
+pub(crate) fn quarantine_path(directory: &Path, source_path: &Path) -> PathBuf {
+    let mut path = directory.join(QUARANTINE_PATH);
+    let Some(name) = source_path.file_name() else {
+        return path;
+    };
+    // an unreadable file is reported when it is archived
+    match file_hash(source_path) {
+        Ok(hash) => {
+            let name = Path::new(name);
+            let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+            let suffix = &hash[..QUARANTINE_HASH_LENGTH];
+            path.push(match name.extension() {
+                Some(extension) => {
+                    format!("{}-{}.{}", stem, suffix, extension.to_string_lossy())
+                }
+                None => format!("{}-{}", stem, suffix),
+            });
+        }
+        Err(_) => path.push(name),
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test detecting timestamps in the future and stale timestamps
+    fn test_suspicious_timestamp() {
+        let now = chrono::Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let modified = chrono::Utc.with_ymd_and_hms(2024, 5, 31, 12, 0, 0).unwrap();
+        let check = |timestamp, stale_after| {
+            super::suspicious_timestamp(timestamp, Some(modified), now, stale_after)
+        };
+
+        assert_eq!(None, check(modified, 365));
+        assert_eq!(
+            None,
+            check(
+                chrono::Utc.with_ymd_and_hms(2024, 6, 1, 12, 30, 0).unwrap(),
+                365
+            )
+        );
+        assert_eq!(
+            Some(String::from("is in the future")),
+            check(
+                chrono::Utc.with_ymd_and_hms(2031, 1, 1, 0, 0, 0).unwrap(),
+                365
+            )
+        );
+        let stale = chrono::Utc.with_ymd_and_hms(2004, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(
+            Some(String::from(
+                "is more than 365 days older than the file modified at 2024-05-31 12:00:00"
+            )),
+            check(stale, 365)
+        );
+        assert_eq!(None, check(stale, 0));
+        assert_eq!(None, super::suspicious_timestamp(stale, None, now, 365));
+    }
+
+    #[test]
+    /// Test paths of quarantined files
+    fn test_quarantine_path() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive");
+        let first = tmpdir.path().join("watch/ACTIVITY.FIT");
+        let second = tmpdir.path().join("bike/ACTIVITY.FIT");
+        for (path, content) in [(&first, "first"), (&second, "second")] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        // files with the same name from different devices do not collide
+        let first_path = super::quarantine_path(&archive_dir, &first);
+        assert_eq!(
+            archive_dir.join("quarantine/ACTIVITY-a7937b64.FIT"),
+            first_path
+        );
+        assert_ne!(first_path, super::quarantine_path(&archive_dir, &second));
+
+        // the same content is quarantined under the same name again
+        fs::write(&second, "first").unwrap();
+        assert_eq!(first_path, super::quarantine_path(&archive_dir, &second));
+
+        // unreadable files keep their name
+        assert_eq!(
+            PathBuf::from("archive/quarantine/run.fit"),
+            super::quarantine_path(Path::new("archive"), Path::new("/mnt/watch/run.fit"))
+        );
+        assert_eq!(
+            super::SuspiciousTimePolicy::Quarantine,
+            "quarantine".parse().unwrap()
+        );
+        "delete"
+            .parse::<super::SuspiciousTimePolicy>()
+            .expect_err("error expected");
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
@@ -0,0 +1,346 @@
+//! # Configuration file
+//!
+//! Options repeated on every invocation are read from a configuration file in TOML format. The
+//! keys are the long names of the command line options, i.e. `file-template`, and their values
+//! are inserted in front of the command line arguments, so that options given on the command
+//! line override them. Tables only group the options:
+//!
+//! ```toml
+//! # archive on the NAS
+//! directory = "~/backup/activities"
+//! file-template = "%Y/%m/%Y-%m-%d-%H%M%S-$s"
+//! move = true
+//!
+//! [locations]
+//! start-location = [
+//!     "home=48.137,11.575",
+//!     "office=48.15,11.6",
+//! ]
+//! ```
+
+use clap::{ArgAction, Command};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Path of the configuration file relative to the configuration directory of the user
+const CONFIG_PATH: &str = "fitarchiver/config.toml";
+
+/// Value of a key in the configuration file
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    /// Boolean, i.e. `true`
+    Bool(bool),
+    /// String or number, i.e. `"archive"` or `30`
+    Text(String),
+    /// Array of strings or numbers, i.e. `["a", "b"]`
+    List(Vec<String>),
+}
+
+/// Returns the path of the configuration file of the user
+///
+/// The configuration directory is `$XDG_CONFIG_HOME` or `~/.config`.
+fn default_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|directory| !directory.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|directory| directory.join(CONFIG_PATH))
+}
+
+/// Returns a string with a leading '~/' replaced by the home directory of the user
+///
+/// # Arguments
+///
+/// * `text` - Value of the configuration file.
+fn expand_home(text: String) -> String {
+    match (text.strip_prefix("~/"), env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home.trim_end_matches('/'), rest),
+        _ => text,
+    }
+}
+
+/// Returns the value of an option
+///
+/// # Arguments
+///
+/// * `key` - Name of the option.
+/// * `value` - TOML value of the option.
+fn option_value(key: &str, value: toml::Value) -> Result<Value, String> {
+    let text = |value: toml::Value| match value {
+        toml::Value::String(text) => Some(expand_home(text)),
+        toml::Value::Integer(number) => Some(number.to_string()),
+        toml::Value::Float(number) => Some(number.to_string()),
+        _ => None,
+    };
+    match value {
+        toml::Value::Boolean(value) => Ok(Value::Bool(value)),
+        toml::Value::Array(values) => values
+            .into_iter()
+            .map(text)
+            .collect::<Option<Vec<_>>>()
+            .map(Value::List)
+            .ok_or_else(|| format!("invalid array of option '{}'", key)),
+        value => text(value)
+            .map(Value::Text)
+            .ok_or_else(|| format!("invalid value of option '{}'", key)),
+    }
+}
+
+/// Add the options of a table and of the tables it contains in order
+///
+/// # Arguments
+///
+/// * `table` - TOML table.
+/// * `entries` - Options and their values.
+fn add_options(table: toml::Table, entries: &mut Vec<(String, Value)>) -> Result<(), String> {
+    for (key, value) in table {
+        match value {
+            toml::Value::Table(table) => add_options(table, entries)?,
+            value => {
+                let key = key.replace('_', "-");
+                let value = option_value(&key, value)?;
+                entries.push((key, value));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the keys and values of a configuration file in order
+///
+/// Tables only group the options, i.e. `[archive]`, their keys are options like the keys outside
+/// of tables.
+///
+/// # Arguments
+///
+/// * `content` - Content of the configuration file.
+fn parse_config(content: &str) -> Result<Vec<(String, Value)>, String> {
+    let table = content.parse::<toml::Table>().map_err(|err| {
+        let line = err
+            .span()
+            .map_or(1, |span| content[..span.start].matches('\n').count() + 1);
+        format!("line {}: {}", line, err.message())
+    })?;
+    let mut entries = Vec::new();
+    add_options(table, &mut entries)?;
+    Ok(entries)
+}
+
+/// Returns the command line arguments defined by a configuration file
+///
+/// # Arguments
+///
+/// * `content` - Content of the configuration file.
+/// * `command` - Command line parser whose options may be configured.
+fn config_arguments(content: &str, command: &Command) -> Result<Vec<String>, String> {
+    let mut command = command.clone();
+    command.build();
+
+    let mut arguments = Vec::new();
+    for (key, value) in parse_config(content)? {
+        let Some(arg) = command
+            .get_arguments()
+            .filter(|arg| !["config", "help", "version"].contains(&arg.get_id().as_str()))
+            .find(|arg| arg.get_long() == Some(key.as_str()))
+        else {
+            return Err(format!("unknown option '{}'", key));
+        };
+        let flag = matches!(arg.get_action(), ArgAction::SetTrue);
+        let optional = arg
+            .get_num_args()
+            .is_some_and(|range| range.min_values() == 0);
+
+        match value {
+            Value::Bool(true) if flag || optional => arguments.push(format!("--{}", key)),
+            Value::Bool(false) if flag || optional => (),
+            Value::Bool(value) => arguments.push(format!("--{}={}", key, value)),
+            _ if flag => return Err(format!("option '{}' expects true or false", key)),
+            Value::Text(value) => arguments.push(format!("--{}={}", key, value)),
+            Value::List(values) => {
+                arguments.extend(values.iter().map(|value| format!("--{}={}", key, value)))
+            }
+        }
+    }
+    Ok(arguments)
+}
+
+/// Returns the path of the configuration file given with '--config'
+///
+/// # Arguments
+///
+/// * `arguments` - Command line arguments.
+fn explicit_path(arguments: &[String]) -> Option<PathBuf> {
+    let mut arguments = arguments.iter().take_while(|argument| *argument != "--");
+    while let Some(argument) = arguments.next() {
+        if argument == "--config" {
+            return arguments.next().map(PathBuf::from);
+        }
+        if let Some(path) = argument.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+/// Returns the command line arguments defined by the configuration file
+///
+/// The configuration file given with '--config' must exist. The configuration file of the user
+/// is optional and only read if `user_config` is true.
+///
+/// # Arguments
+///
+/// * `arguments` - Command line arguments.
+/// * `command` - Command line parser whose options may be configured.
+/// * `user_config` - Read the configuration file of the user if '--config' is not given.
+pub fn arguments(
+    arguments: &[String],
+    command: &Command,
+    user_config: bool,
+) -> Result<Vec<String>, String> {
+    let (path, required) = match explicit_path(arguments) {
+        Some(path) => (path, true),
+        None => match default_path().filter(|_| user_config) {
+            Some(path) => (path, false),
+            None => return Ok(Vec::new()),
+        },
+    };
+
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound && !required => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(format!(
+                "Unable to read config file '{}': {}",
+                path.display(),
+                err
+            ))
+        }
+    };
+    config_arguments(&content, command)
+        .map_err(|msg| format!("Invalid config file '{}', {}", path.display(), msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{Arg, ArgAction, Command};
+    use std::path::PathBuf;
+
+    /// Returns a command line parser with options of all kinds
+    fn command() -> Command {
+        Command::new("test")
+            .arg(Arg::new("directory").long("directory").num_args(1))
+            .arg(Arg::new("move").long("move").action(ArgAction::SetTrue))
+            .arg(Arg::new("wait").long("wait").num_args(1))
+            .arg(
+                Arg::new("git")
+                    .long("git")
+                    .num_args(0..=1)
+                    .require_equals(true),
+            )
+            .arg(
+                Arg::new("start-location")
+                    .long("start-location")
+                    .action(ArgAction::Append),
+            )
+            .arg(Arg::new("files").num_args(1..))
+    }
+
+    #[test]
+    /// Test converting a configuration file to command line arguments
+    fn test_config_arguments() {
+        let content = r#"
+            # archive on the NAS
+            directory = "/mnt/nas/a \"b\"" # trailing comment
+            move = true
+            git = false
+            wait = 30
+            start_location = ['home=48.1,11.5', "office=48.2,11.6",]
+        "#;
+        assert_eq!(
+            vec![
+                "--directory=/mnt/nas/a \"b\"",
+                "--move",
+                "--wait=30",
+                "--start-location=home=48.1,11.5",
+                "--start-location=office=48.2,11.6",
+            ],
+            super::config_arguments(content, &command()).unwrap()
+        );
+
+        for content in [
+            "unknown = 1",
+            "files = \"a.fit\"",
+            "move = \"yes\"",
+            "directory = \"unterminated",
+            "directory = archive",
+            "directory = \"a\" \"b\"",
+            "directory = 1979-05-27",
+            "start-location = [true]",
+            "[[section]]\nmove = true",
+        ] {
+            super::config_arguments(content, &command()).expect_err(content);
+        }
+    }
+
+    #[test]
+    /// Test configuration files with multi-line arrays, tables and comments
+    fn test_config_arguments_tables() {
+        let content = r#"
+            move = true # comment after a value
+
+            # options grouped in tables
+            [archive]
+            directory = '/mnt/nas/activities'
+
+            [archive.locations]
+            start-location = [
+                "home=48.1,11.5", # comment in an array
+                "office=48.2,11.6",
+            ]
+
+            [watch]
+            wait = 2.5
+        "#;
+        assert_eq!(
+            vec![
+                "--move",
+                "--directory=/mnt/nas/activities",
+                "--start-location=home=48.1,11.5",
+                "--start-location=office=48.2,11.6",
+                "--wait=2.5",
+            ],
+            super::config_arguments(content, &command()).unwrap()
+        );
+
+        // the keys of tables are options as well
+        let err = super::config_arguments("[archive]\nunknown = 1", &command()).unwrap_err();
+        assert_eq!("unknown option 'unknown'", err);
+        let err = super::config_arguments("move = true\nmove = false", &command()).unwrap_err();
+        assert!(err.starts_with("line 2: "), "{}", err);
+    }
+
+    #[test]
+    /// Test finding the configuration file given on the command line
+    fn test_explicit_path() {
+        let arguments = |arguments: &[&str]| -> Vec<String> {
+            arguments
+                .iter()
+                .map(|argument| argument.to_string())
+                .collect()
+        };
+        assert_eq!(
+            Some(PathBuf::from("a.toml")),
+            super::explicit_path(&arguments(&["fitarchiver", "--config", "a.toml", "x.fit"]))
+        );
+        assert_eq!(
+            Some(PathBuf::from("b.toml")),
+            super::explicit_path(&arguments(&["fitarchiver", "--config=b.toml"]))
+        );
+        assert_eq!(
+            None,
+            super::explicit_path(&arguments(&["fitarchiver", "--", "--config=b.toml"]))
+        );
+    }
+}
@@ -0,0 +1,313 @@
+//! # Control socket of the watch mode
+//!
+//! A desktop applet or a script supervises the archiver through a Unix domain socket. Each
+//! request is a JSON object on a single line, i.e. `{"command": "pause"}`, and is answered by a
+//! JSON object on a single line with the status of the archiver:
+//!
+//! ```json
+//! {"ok": true, "paused": false, "queue": ["/media/GARMIN/Garmin/Activity/run.fit"], "results": []}
+//! ```
+//!
+//! Supported commands are `status`, `rescan`, `pause` and `resume`.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Number of runs whose results are reported
+const LAST_RESULTS: usize = 10;
+
+/// Result of archiving the files that were ready at one poll
+#[derive(Debug, Clone)]
+struct RunResult {
+    /// Time when the run finished
+    finished: DateTime<Utc>,
+    /// Files archived in the run
+    files: Vec<PathBuf>,
+    /// Summary of the run or the error that prevented it
+    summary: String,
+    /// The run failed
+    failed: bool,
+}
+
+/// State shared between the watch loop and the control socket
+#[derive(Debug, Default)]
+struct State {
+    /// New files are not archived
+    paused: bool,
+    /// The watched directories are polled without waiting for the poll interval
+    rescan: bool,
+    /// Files waiting to be archived
+    queue: Vec<PathBuf>,
+    /// Results of the last runs, the latest first
+    results: VecDeque<RunResult>,
+}
+
+/// Status and commands of the watch mode
+#[derive(Debug, Default)]
+pub(crate) struct Control {
+    /// State shared with the control socket
+    state: Mutex<State>,
+    /// Wakes up the watch loop on a command
+    wakeup: Condvar,
+}
+
+impl Control {
+    /// Returns the locked state, a panic of another thread does not leave it inconsistent
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Returns true if archiving is paused
+    pub(crate) fn paused(&self) -> bool {
+        self.state().paused
+    }
+
+    /// Set the files waiting to be archived
+    ///
+    /// # Arguments
+    ///
+    /// * `queue` - Files waiting to be archived.
+    pub(crate) fn set_queue(&self, queue: Vec<PathBuf>) {
+        self.state().queue = queue;
+    }
+
+    /// Remember the result of a run
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - Files archived in the run.
+    /// * `result` - Summary of the run or its error.
+    pub(crate) fn add_result(&self, files: &[PathBuf], result: &Result<String>) {
+        let (summary, failed) = match result {
+            Ok(msg) => (msg.trim_end().to_string(), false),
+            Err(err) => (err.to_string(), true),
+        };
+        let mut state = self.state();
+        state.results.push_front(RunResult {
+            finished: Utc::now(),
+            files: files.to_vec(),
+            summary,
+            failed,
+        });
+        state.results.truncate(LAST_RESULTS);
+    }
+
+    /// Wait for the poll interval or until a rescan is requested
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Poll interval.
+    pub(crate) fn wait(&self, timeout: Duration) {
+        let state = self.state();
+        let (mut state, _) = self
+            .wakeup
+            .wait_timeout_while(state, timeout, |state| !state.rescan)
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        state.rescan = false;
+    }
+
+    /// Returns the status of the archiver as JSON object
+    fn status(&self) -> Value {
+        let state = self.state();
+        json!({
+            "ok": true,
+            "paused": state.paused,
+            "queue": state.queue,
+            "results": state
+                .results
+                .iter()
+                .map(|result| {
+                    json!({
+                        "finished": result.finished.to_rfc3339_opts(SecondsFormat::Secs, true),
+                        "files": result.files,
+                        "summary": result.summary,
+                        "failed": result.failed,
+                    })
+                })
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// Returns the response to a request
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - JSON object with the command.
+    fn handle(&self, request: &str) -> Value {
+        let command = serde_json::from_str::<Value>(request)
+            .ok()
+            .and_then(|request| request.get("command")?.as_str().map(String::from));
+        match command.as_deref() {
+            Some("status") => (),
+            Some("rescan") => self.state().rescan = true,
+            Some("pause") => self.state().paused = true,
+            Some("resume") => {
+                let mut state = self.state();
+                state.paused = false;
+                state.rescan = true;
+            }
+            Some(command) => {
+                return json!({"ok": false, "error": format!("Unknown command '{}'", command)})
+            }
+            None => return json!({"ok": false, "error": format!("Invalid request '{}'", request)}),
+        }
+        self.wakeup.notify_all();
+        self.status()
+    }
+}
+
+/// Serve the control socket in a background thread
+///
+/// A stale socket file left by a previous run is replaced, a socket still in use is an error.
+///
+/// # Arguments
+///
+/// * `control` - Status and commands of the watch mode.
+/// * `path` - Path of the socket.
+#[cfg(unix)]
+pub(crate) fn listen(control: &Arc<Control>, path: &Path) -> Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::thread;
+
+    if UnixStream::connect(path).is_ok() {
+        let msg = format!("Control socket '{}' is already in use", path.display());
+        return Err(ArchiverError::new(&msg));
+    }
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path).map_err(|err| {
+        let msg = format!("Unable to create control socket '{}'", path.display());
+        ArchiverError::io(&msg, err)
+    })?;
+
+    let control = Arc::clone(control);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let control = Arc::clone(&control);
+            // a client keeping its connection open does not block other clients
+            thread::spawn(move || {
+                let Ok(mut writer) = stream.try_clone() else {
+                    return;
+                };
+                for request in BufReader::new(stream).lines().map_while(|line| line.ok()) {
+                    if request.trim().is_empty() {
+                        continue;
+                    }
+                    let response = control.handle(&request);
+                    if writeln!(writer, "{}", response).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Serve the control socket in a background thread
+///
+/// Unix domain sockets are not supported on this platform.
+///
+/// # Arguments
+///
+/// * `control` - Status and commands of the watch mode.
+/// * `path` - Path of the socket.
+#[cfg(not(unix))]
+pub(crate) fn listen(_control: &Arc<Control>, path: &Path) -> Result<()> {
+    let msg = format!(
+        "Unable to create control socket '{}', not supported on this platform",
+        path.display()
+    );
+    Err(ArchiverError::new(&msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    /// Test handling the commands of the control socket
+    fn test_control_handle() {
+        let control = super::Control::default();
+        control.set_queue(vec![PathBuf::from("run.fit")]);
+
+        let status = control.handle(r#"{"command": "status"}"#);
+        assert_eq!(Some(true), status["ok"].as_bool());
+        assert_eq!(Some(false), status["paused"].as_bool());
+        assert_eq!(Some("run.fit"), status["queue"][0].as_str());
+
+        assert!(!control.paused());
+        control.handle(r#"{"command": "pause"}"#);
+        assert!(control.paused());
+        control.handle(r#"{"command": "resume"}"#);
+        assert!(!control.paused());
+
+        control.add_result(
+            &[PathBuf::from("run.fit")],
+            &Ok(String::from("Processed 1 files ")),
+        );
+        let status = control.handle(r#"{"command": "status"}"#);
+        assert_eq!(
+            Some("Processed 1 files"),
+            status["results"][0]["summary"].as_str()
+        );
+
+        for request in [r#"{"command": "stop"}"#, "pause"] {
+            assert_eq!(Some(false), control.handle(request)["ok"].as_bool());
+        }
+    }
+
+    #[test]
+    /// Test interrupting the poll interval with a rescan
+    fn test_control_wait() {
+        let control = Arc::new(super::Control::default());
+        let started = Instant::now();
+        control.wait(Duration::from_millis(10));
+        assert!(started.elapsed() >= Duration::from_millis(10));
+
+        let remote = Arc::clone(&control);
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            remote.handle(r#"{"command": "rescan"}"#);
+        });
+        let started = Instant::now();
+        control.wait(Duration::from_secs(60));
+        assert!(started.elapsed() < Duration::from_secs(60));
+        thread.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    /// Test sending commands over the control socket
+    fn test_listen() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixStream;
+
+        let tmpdir =
+            tempdir::TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let path = tmpdir.path().join("control.sock");
+        let control = Arc::new(super::Control::default());
+        super::listen(&control, &path).unwrap();
+        super::listen(&control, &path).expect_err("error expected");
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        writeln!(stream, r#"{{"command": "pause"}}"#).unwrap();
+        let mut response = String::new();
+        BufReader::new(&stream).read_line(&mut response).unwrap();
+        let response: serde_json::Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(Some(true), response["paused"].as_bool());
+        assert!(control.paused());
+
+        // cleanup
+        std::fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
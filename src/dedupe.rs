@@ -0,0 +1,364 @@
+//! # Duplicate detection in an existing archive
+
+use crate::fitarchiver::{
+    find_duplicates, find_fit_files, parse_fit_file, same_content, same_file, ArchiverError,
+    DuplicatePolicy, Result,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Kind of duplicates to search for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DedupeMode {
+    /// Files with the same content
+    #[default]
+    Identical,
+    /// Files containing the same activity, i.e. recorded on several devices or exported again
+    Activity,
+}
+
+impl FromStr for DedupeMode {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "identical" => Ok(DedupeMode::Identical),
+            "activity" => Ok(DedupeMode::Activity),
+            _ => {
+                let msg = format!(
+                    "Invalid dedupe mode '{}', expected 'identical' or 'activity'",
+                    s
+                );
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+/// Action performed for duplicates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DedupeAction {
+    /// Only report the duplicates
+    #[default]
+    Report,
+    /// Remove the duplicates
+    Remove,
+    /// Replace the duplicates by hard links to the kept file
+    Hardlink,
+}
+
+/// Options controlling the duplicate detection
+#[derive(Debug, Clone)]
+pub struct DedupeOptions {
+    /// Base directory of the archive
+    pub directory: PathBuf,
+    /// Kind of duplicates to search for
+    pub mode: DedupeMode,
+    /// Action performed for duplicates
+    pub action: DedupeAction,
+    /// Do not modify the archive, just show what will happen
+    pub dry_run: bool,
+}
+
+impl Default for DedupeOptions {
+    fn default() -> Self {
+        DedupeOptions {
+            directory: PathBuf::from("."),
+            mode: DedupeMode::default(),
+            action: DedupeAction::default(),
+            dry_run: false,
+        }
+    }
+}
+
+/// Returns groups of files with the same content
+///
+/// The first file of every group is the one that is kept.
+///
+/// # Arguments
+///
+/// `files` - Sorted list of files.
+fn identical_groups(files: &[PathBuf]) -> Result<Vec<Vec<PathBuf>>> {
+    let mut groups: Vec<Vec<PathBuf>> = Vec::new();
+
+    for file in files {
+        let size = match fs::metadata(file) {
+            Ok(val) => val.len(),
+            Err(err) => {
+                let msg = format!("Unable to get size of '{}'", file.display());
+                return Err(ArchiverError::io(&msg, err));
+            }
+        };
+
+        let mut found = false;
+        for group in groups.iter_mut() {
+            // files that are already hard linked are no duplicates anymore
+            if same_file(&group[0], file) {
+                found = true;
+                break;
+            }
+            let group_size = fs::metadata(&group[0]).map(|m| m.len()).unwrap_or(u64::MAX);
+            if group_size == size && same_content(&group[0], file).unwrap_or(false) {
+                group.push(file.clone());
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            groups.push(vec![file.clone()]);
+        }
+    }
+
+    groups.retain(|group| group.len() > 1);
+    Ok(groups)
+}
+
+/// Returns groups of files containing the same activity
+///
+/// The first file of every group is the one that is kept, it is the file with most record
+/// messages and fields.
+///
+/// # Arguments
+///
+/// `files` - Sorted list of files.
+fn activity_groups(files: &[PathBuf]) -> Vec<Vec<PathBuf>> {
+    let parsed: Vec<_> = files
+        .iter()
+        .map(|file| match parse_fit_file(file) {
+            Ok(val) => Some(val),
+            Err(err) => {
+                eprintln!("{}", err);
+                None
+            }
+        })
+        .collect();
+    let activities: Vec<_> = parsed.iter().map(|p| p.as_ref()).collect();
+    let skipped = find_duplicates(&activities, &DuplicatePolicy::KeepRichest, 0);
+
+    // collect the duplicates of every kept file
+    let mut groups: Vec<Vec<PathBuf>> = Vec::new();
+    for (i, file) in files.iter().enumerate() {
+        let Some(activity_data) = activities[i] else {
+            continue;
+        };
+        if skipped[i] {
+            continue;
+        }
+        let mut group = vec![file.clone()];
+        for (j, duplicate) in files.iter().enumerate() {
+            if skipped[j]
+                && activities[j].is_some_and(|other| other.timestamp == activity_data.timestamp)
+            {
+                group.push(duplicate.clone());
+            }
+        }
+        if group.len() > 1 {
+            groups.push(group);
+        }
+    }
+
+    groups
+}
+
+/// Replace a file by a hard link to another file
+///
+/// # Arguments
+///
+/// `keep_path` - Path to the file that is kept.
+/// `duplicate_path` - Path to the duplicate that is replaced.
+fn hardlink_file(keep_path: &Path, duplicate_path: &Path) -> Result<()> {
+    // create the link next to the duplicate and rename it to replace the duplicate atomically
+    let mut link_path = duplicate_path.as_os_str().to_owned();
+    link_path.push(".fitarchiver-link");
+    let link_path = PathBuf::from(link_path);
+
+    if let Err(err) = fs::hard_link(keep_path, &link_path) {
+        let msg = format!("Unable to create hard link '{}'", link_path.display());
+        return Err(ArchiverError::io(&msg, err));
+    }
+    if let Err(err) = fs::rename(&link_path, duplicate_path) {
+        let _ = fs::remove_file(&link_path);
+        let msg = format!("Unable to replace file '{}'", duplicate_path.display());
+        return Err(ArchiverError::io(&msg, err));
+    }
+    Ok(())
+}
+
+/// Search duplicates in an existing archive
+///
+/// # Arguments
+///
+/// `options` - Dedupe options.
+pub fn dedupe(options: &DedupeOptions) -> Result<String> {
+    if options.mode == DedupeMode::Activity && options.action == DedupeAction::Hardlink {
+        return Err(ArchiverError::new(
+            "Files containing the same activity have different content and cannot be hard linked",
+        ));
+    }
+
+    let files = find_fit_files(&options.directory)?;
+    let groups = match options.mode {
+        DedupeMode::Identical => identical_groups(&files)?,
+        DedupeMode::Activity => activity_groups(&files),
+    };
+
+    let mut duplicate_counter: usize = 0;
+    let mut error_counter: usize = 0;
+    for group in &groups {
+        let keep_path = &group[0];
+        for duplicate_path in &group[1..] {
+            let result = if options.dry_run {
+                Ok("dry run")
+            } else {
+                match options.action {
+                    DedupeAction::Report => Ok("duplicate"),
+                    DedupeAction::Remove => match fs::remove_file(duplicate_path) {
+                        Ok(_) => Ok("removed"),
+                        Err(err) => {
+                            let msg =
+                                format!("Unable to remove file '{}'", duplicate_path.display());
+                            Err(ArchiverError::io(&msg, err))
+                        }
+                    },
+                    DedupeAction::Hardlink => {
+                        hardlink_file(keep_path, duplicate_path).map(|_| "hard linked")
+                    }
+                }
+            };
+
+            match result {
+                Ok(status) => {
+                    println!(
+                        "'{}' -> '{}' ... {}",
+                        duplicate_path.display(),
+                        keep_path.display(),
+                        status
+                    );
+                    duplicate_counter += 1;
+                }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    error_counter += 1;
+                }
+            }
+        }
+    }
+
+    let msg = format!(
+        "Found {} duplicates of {} files",
+        duplicate_counter,
+        groups.len()
+    );
+    let err = if error_counter == 0 {
+        String::new()
+    } else {
+        format!("with {} errors.", error_counter)
+    };
+
+    Ok([msg, err].join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    /// Create an archive with duplicates
+    fn create_archive(tmpdir: &TempDir) -> Vec<PathBuf> {
+        let test_data = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test")
+            .join("test_data_01.fit");
+        let files = vec![
+            tmpdir.path().join("2023/07/a.fit"),
+            tmpdir.path().join("2023/07/b.fit"),
+            tmpdir.path().join("2023/08/c.fit"),
+        ];
+        for file in &files {
+            fs::create_dir_all(file.parent().unwrap())
+                .expect("error during creating temporary directory");
+        }
+        fs::copy(&test_data, &files[0]).expect("unable to create test file");
+        fs::copy(&test_data, &files[1]).expect("unable to create test file");
+        fs::write(&files[2], "other content").expect("unable to create test file");
+        files
+    }
+
+    #[test]
+    /// Test reporting identical files
+    fn test_dedupe_report() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let files = create_archive(&tmpdir);
+
+        let options = super::DedupeOptions {
+            directory: tmpdir.path().to_path_buf(),
+            ..Default::default()
+        };
+        assert_eq!(
+            vec![vec![files[0].clone(), files[1].clone()]],
+            super::identical_groups(&super::find_fit_files(tmpdir.path()).unwrap()).unwrap()
+        );
+        assert_eq!(
+            "Found 1 duplicates of 1 files ",
+            super::dedupe(&options).unwrap()
+        );
+        assert!(files.iter().all(|file| file.exists()));
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test removing files containing the same activity
+    fn test_dedupe_remove_activity() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let files = create_archive(&tmpdir);
+
+        let options = super::DedupeOptions {
+            directory: tmpdir.path().to_path_buf(),
+            mode: super::DedupeMode::Activity,
+            action: super::DedupeAction::Remove,
+            ..Default::default()
+        };
+        super::dedupe(&options).unwrap();
+        assert!(files[0].exists());
+        assert!(!files[1].exists());
+        assert!(files[2].exists());
+
+        // hard links are not possible for files with different content
+        let options = super::DedupeOptions {
+            action: super::DedupeAction::Hardlink,
+            ..options
+        };
+        super::dedupe(&options).expect_err("error expected");
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test replacing identical files by hard links
+    fn test_dedupe_hardlink() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let files = create_archive(&tmpdir);
+
+        let options = super::DedupeOptions {
+            directory: tmpdir.path().to_path_buf(),
+            action: super::DedupeAction::Hardlink,
+            ..Default::default()
+        };
+        super::dedupe(&options).unwrap();
+        assert!(files.iter().all(|file| file.exists()));
+        assert!(crate::fitarchiver::same_file(&files[0], &files[1]));
+
+        // hard linked files are no duplicates anymore
+        assert_eq!(
+            "Found 0 duplicates of 0 files ",
+            super::dedupe(&options).unwrap()
+        );
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
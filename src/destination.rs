@@ -0,0 +1,437 @@
+//! # Availability of the archive directory
+//!
+//! An archive directory on a network share that is not mounted looks like an ordinary local
+//! directory. Archiving into it would silently fill the mountpoint on the local file system.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use std::fs;
+use std::io;
+use std::path::{self, Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Interval between checks while waiting for the archive directory
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Space that is kept free on the file system of the archive in bytes
+const SPACE_MARGIN: u64 = 16 * 1024 * 1024;
+
+/// File system table with the mountpoints of the system
+const FSTAB_PATH: &str = "/etc/fstab";
+
+/// File systems currently mounted for this process
+const MOUNTS_PATH: &str = "/proc/self/mounts";
+
+/// Returns the mountpoints of a mount table like '/etc/fstab' or '/proc/self/mounts'
+///
+/// Comments and entries without a directory as mountpoint, i.e. swap, are skipped.
+///
+/// # Arguments
+///
+/// * `table` - Content of the mount table.
+fn mountpoints(table: &str) -> Vec<PathBuf> {
+    table
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(unescape_mountpoint)
+        .filter(|mountpoint| mountpoint.is_absolute())
+        .collect()
+}
+
+/// Returns a mountpoint of a mount table with the octal escapes replaced, i.e. '\040' by ' '
+///
+/// # Arguments
+///
+/// * `field` - Mountpoint field of the mount table.
+fn unescape_mountpoint(field: &str) -> PathBuf {
+    let mut result = String::new();
+    let mut rest = field;
+    while let Some(start) = rest.find('\\') {
+        result.push_str(&rest[..start]);
+        let escape = rest.get(start + 1..start + 4).unwrap_or_default();
+        match u8::from_str_radix(escape, 8) {
+            Ok(code) if escape.len() == 3 => {
+                result.push(char::from(code));
+                rest = &rest[start + 4..];
+            }
+            _ => {
+                result.push('\\');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    PathBuf::from(result)
+}
+
+/// Returns the mountpoint of the file system table containing the path that is not mounted
+///
+/// The root file system is always mounted. Existing mountpoints have their symbolic links resolved
+/// before they are compared with the path and checked.
+///
+/// # Arguments
+///
+/// * `path` - Absolute path without symbolic links.
+/// * `fstab` - Content of the file system table.
+/// * `is_mounted` - Returns true if a mountpoint without symbolic links is mounted.
+fn unmounted_mountpoint(
+    path: &Path,
+    fstab: &str,
+    is_mounted: impl Fn(&Path) -> bool,
+) -> Option<PathBuf> {
+    mountpoints(fstab)
+        .into_iter()
+        .filter(|mountpoint| mountpoint.parent().is_some())
+        .find(|mountpoint| {
+            let resolved = fs::canonicalize(mountpoint).unwrap_or_else(|_| mountpoint.clone());
+            path.starts_with(&resolved) && !is_mounted(&resolved)
+        })
+}
+
+/// Returns true if the directory is the root of a mounted file system
+///
+/// # Arguments
+///
+/// * `directory` - Directory that is not the root directory.
+#[cfg(unix)]
+fn is_mount_root(directory: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (
+        fs::metadata(directory),
+        directory.parent().map(fs::metadata),
+    ) {
+        (Ok(metadata), Some(Ok(parent))) => metadata.dev() != parent.dev(),
+        _ => false,
+    }
+}
+
+/// Returns true if the directory is the root of a mounted file system
+///
+/// Mountpoints cannot be detected on this platform, therefore all directories are accepted.
+///
+/// # Arguments
+///
+/// * `directory` - Directory that is not the root directory.
+#[cfg(not(unix))]
+fn is_mount_root(_directory: &Path) -> bool {
+    true
+}
+
+/// Returns true if the directory or one of its parents is the root of a mounted file system
+///
+/// The root directory itself is not taken into account.
+///
+/// # Arguments
+///
+/// * `directory` - Existing directory.
+#[cfg(unix)]
+fn is_mounted(directory: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut path = fs::canonicalize(directory)?;
+    while let Some(parent) = path.parent() {
+        if fs::metadata(&path)?.dev() != fs::metadata(parent)?.dev() {
+            return Ok(true);
+        }
+        path = parent.to_path_buf();
+    }
+    Ok(false)
+}
+
+/// Returns true if the directory or one of its parents is the root of a mounted file system
+///
+/// Mountpoints cannot be detected on this platform, therefore all directories are accepted.
+///
+/// # Arguments
+///
+/// * `directory` - Existing directory.
+#[cfg(not(unix))]
+fn is_mounted(_directory: &Path) -> io::Result<bool> {
+    Ok(true)
+}
+
+/// Check that the archive directory is available
+///
+/// The nearest existing directory of the archive directory must be readable. This detects
+/// network shares that are mounted but offline. An archive directory below a mountpoint of
+/// '/etc/fstab' that is not mounted is rejected, so a share that is not mounted is not filled
+/// from the local file system.
+///
+/// # Arguments
+///
+/// * `directory` - Base directory of the archive, it does not need to exist.
+/// * `require_mount` - The archive directory must be on a mounted file system.
+pub(crate) fn check_destination(directory: &Path, require_mount: bool) -> Result<()> {
+    let absolute = match path::absolute(directory) {
+        Ok(val) => val,
+        Err(err) => {
+            let msg = format!(
+                "Unable to resolve archive directory '{}'",
+                directory.display()
+            );
+            return Err(ArchiverError::io(&msg, err));
+        }
+    };
+    let Some(existing) = absolute.ancestors().find(|path| path.exists()) else {
+        let msg = format!(
+            "Archive directory '{}' is not accessible",
+            directory.display()
+        );
+        return Err(ArchiverError::new(&msg));
+    };
+
+    if let Err(err) = fs::read_dir(existing) {
+        let msg = format!(
+            "Archive directory '{}' is not accessible",
+            directory.display()
+        );
+        return Err(ArchiverError::io(&msg, err));
+    }
+
+    // the mountpoints of the tables have their symbolic links resolved
+    let resolved = match fs::canonicalize(existing) {
+        Ok(val) => val.join(absolute.strip_prefix(existing).unwrap_or(Path::new(""))),
+        Err(_) => absolute.clone(),
+    };
+    let fstab = fs::read_to_string(FSTAB_PATH).unwrap_or_default();
+    let mounted = fs::read_to_string(MOUNTS_PATH)
+        .ok()
+        .map(|table| mountpoints(&table));
+    let is_mountpoint_mounted = |mountpoint: &Path| match &mounted {
+        Some(mounted) => mounted.iter().any(|path| path == mountpoint),
+        None => is_mount_root(mountpoint),
+    };
+    if let Some(mountpoint) = unmounted_mountpoint(&resolved, &fstab, is_mountpoint_mounted) {
+        let msg = format!(
+            "Archive directory '{}' is below the mountpoint '{}' of '{}', which is not mounted",
+            directory.display(),
+            mountpoint.display(),
+            FSTAB_PATH
+        );
+        return Err(ArchiverError::new(&msg));
+    }
+
+    if require_mount {
+        match is_mounted(existing) {
+            Ok(true) => (),
+            Ok(false) => {
+                let msg = format!(
+                    "Archive directory '{}' is not on a mounted file system",
+                    directory.display()
+                );
+                return Err(ArchiverError::new(&msg));
+            }
+            Err(err) => {
+                let msg = format!(
+                    "Unable to check mountpoint of archive directory '{}'",
+                    directory.display()
+                );
+                return Err(ArchiverError::io(&msg, err));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns a size in bytes as human readable text
+///
+/// # Arguments
+///
+/// * `size` - Size in bytes.
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Check that the file system of the archive directory has enough free space
+///
+/// A margin is kept free in addition to the required space, so that the file system does not
+/// run full while archiving.
+///
+/// # Arguments
+///
+/// * `directory` - Base directory of the archive, it does not need to exist.
+/// * `required` - Size of the files to archive in bytes.
+pub(crate) fn check_free_space(directory: &Path, required: u64) -> Result<()> {
+    let msg = format!(
+        "Unable to determine free space of archive directory '{}'",
+        directory.display()
+    );
+    let absolute = path::absolute(directory).map_err(|err| ArchiverError::io(&msg, err))?;
+    let Some(existing) = absolute.ancestors().find(|path| path.exists()) else {
+        return Err(ArchiverError::new(&msg));
+    };
+    let available = fs4::available_space(existing).map_err(|err| ArchiverError::io(&msg, err))?;
+
+    if required.saturating_add(SPACE_MARGIN) > available {
+        let msg = format!(
+            "Not enough free space in archive directory '{}', {} required but only {} available",
+            directory.display(),
+            format_size(required.saturating_add(SPACE_MARGIN)),
+            format_size(available)
+        );
+        return Err(ArchiverError::new(&msg));
+    }
+    Ok(())
+}
+
+/// Wait until the archive directory is available
+///
+/// # Arguments
+///
+/// * `directory` - Base directory of the archive, it does not need to exist.
+/// * `require_mount` - The archive directory must be on a mounted file system.
+/// * `timeout` - Maximum time to wait in seconds, `0` checks only once.
+pub(crate) fn wait_for_destination(
+    directory: &Path,
+    require_mount: bool,
+    timeout: u64,
+) -> Result<()> {
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    loop {
+        match check_destination(directory, require_mount) {
+            Ok(_) => return Ok(()),
+            Err(err) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(err);
+                }
+                let interval = RETRY_INTERVAL.min(remaining);
+                eprintln!("{}, retrying in {} seconds", err, interval.as_secs().max(1));
+                thread::sleep(interval);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test checking an available archive directory
+    fn test_check_destination() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+
+        super::check_destination(tmpdir.path(), false).unwrap();
+        super::check_destination(&tmpdir.path().join("archive_dir/2023"), false).unwrap();
+        super::wait_for_destination(tmpdir.path(), false, 0).unwrap();
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test checking the free space of the archive directory
+    fn test_check_free_space() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+
+        super::check_free_space(&tmpdir.path().join("archive_dir/2023"), 0).unwrap();
+        let err = super::check_free_space(tmpdir.path(), u64::MAX).expect_err("error expected");
+        assert!(err.to_string().starts_with("Not enough free space"));
+
+        assert_eq!("512 B", super::format_size(512));
+        assert_eq!("1.5 KiB", super::format_size(1536));
+        assert_eq!("16.0 MiB", super::format_size(super::SPACE_MARGIN));
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test detecting archive directories below mountpoints that are not mounted
+    fn test_unmounted_mountpoint() {
+        let fstab = "# <file system> <mount point> <type> <options> <dump> <pass>
+UUID=1234 / ext4 defaults 0 1
+/swapfile none swap sw 0 0
+nas:/activities /mnt/nas nfs noauto 0 0
+//nas/my\\040share /mnt/my\\040share cifs noauto 0 0
+";
+        assert_eq!(
+            vec![
+                PathBuf::from("/"),
+                PathBuf::from("/mnt/nas"),
+                PathBuf::from("/mnt/my share")
+            ],
+            super::mountpoints(fstab)
+        );
+        assert_eq!(PathBuf::from("a\\b"), super::unescape_mountpoint("a\\b"));
+
+        let unmounted = |path: &str| {
+            super::unmounted_mountpoint(Path::new(path), fstab, |mountpoint| {
+                mountpoint == Path::new("/")
+            })
+        };
+        assert_eq!(
+            Some(PathBuf::from("/mnt/nas")),
+            unmounted("/mnt/nas/activities")
+        );
+        assert_eq!(Some(PathBuf::from("/mnt/nas")), unmounted("/mnt/nas"));
+        assert_eq!(
+            Some(PathBuf::from("/mnt/my share")),
+            unmounted("/mnt/my share/2023")
+        );
+        assert_eq!(None, unmounted("/mnt/nasbackup"));
+        assert_eq!(None, unmounted("/home/jan/activities"));
+
+        // mounted shares are accepted
+        assert_eq!(
+            None,
+            super::unmounted_mountpoint(Path::new("/mnt/nas/activities"), fstab, |_| true)
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    /// Test detecting archive directories below mountpoints that are symbolic links
+    fn test_unmounted_mountpoint_symlink() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let base = fs::canonicalize(tmpdir.path()).unwrap();
+        fs::create_dir_all(base.join("run/media")).unwrap();
+        std::os::unix::fs::symlink(base.join("run/media"), base.join("media")).unwrap();
+
+        // the mount table contains the mountpoint with the symbolic link resolved
+        let fstab = format!(
+            "/dev/sdb1 {} vfat noauto 0 0\n",
+            base.join("media").display()
+        );
+        let path = base.join("run/media/activities");
+        assert_eq!(
+            None,
+            super::unmounted_mountpoint(&path, &fstab, |mountpoint| {
+                mountpoint == base.join("run/media")
+            })
+        );
+        assert_eq!(
+            Some(base.join("media")),
+            super::unmounted_mountpoint(&path, &fstab, |_| false)
+        );
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    /// Test detection of mounted file systems
+    fn test_is_mounted() {
+        assert!(!super::is_mounted(std::path::Path::new("/")).unwrap());
+        assert!(super::is_mounted(std::path::Path::new("/proc/self")).unwrap());
+        super::check_destination(std::path::Path::new("/"), true).expect_err("error expected");
+    }
+}
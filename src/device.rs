@@ -0,0 +1,285 @@
+//! # Devices connected as mass storage or over MTP
+//!
+//! Garmin devices are found by the `GARMIN/Activity` folder on the root of a mounted volume, so
+//! the mount point does not need to be known. On Linux the FAT volumes of `/proc/mounts` are
+//! searched, on macOS the volumes in `/Volumes` and on Windows the drive letters.
+//!
+//! Newer watches only offer MTP. On Linux their files are accessed through the file systems of
+//! gvfs, i.e. the file manager of the desktop, or of the FUSE tools `jmtpfs`, `simple-mtpfs` and
+//! `go-mtpfs`. Each storage of an MTP device is a sub directory of its mount point, i.e.
+//! 'Internal Storage/GARMIN/Activity'.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File systems used by devices connected as mass storage
+#[cfg(target_os = "linux")]
+const DEVICE_FILE_SYSTEMS: [&str; 4] = ["vfat", "msdos", "exfat", "fuseblk"];
+
+/// File systems of the FUSE tools giving access to MTP devices
+#[cfg(target_os = "linux")]
+const MTP_FILE_SYSTEMS: [&str; 3] = ["fuse.jmtpfs", "fuse.simple-mtpfs", "fuse.go-mtpfs"];
+
+/// Mounted volume that may be a device
+#[derive(Debug, Clone, PartialEq)]
+struct Volume {
+    /// Mount point of the volume
+    mount_point: PathBuf,
+    /// The device is connected over MTP, its storages are sub directories of the mount point
+    mtp: bool,
+}
+
+/// Returns the volumes listed in the format of `/proc/mounts`
+///
+/// Only file systems used by devices are returned.
+///
+/// # Arguments
+///
+/// * `content` - Content of `/proc/mounts`.
+#[cfg(target_os = "linux")]
+fn parse_mounts(content: &str) -> Vec<Volume> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let mount_point = PathBuf::from(unescape_mount_point(fields.nth(1)?));
+            let file_system = fields.next()?;
+            if DEVICE_FILE_SYSTEMS.contains(&file_system) {
+                Some(Volume {
+                    mount_point,
+                    mtp: false,
+                })
+            } else if MTP_FILE_SYSTEMS.contains(&file_system) {
+                Some(Volume {
+                    mount_point,
+                    mtp: true,
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Returns the MTP devices accessed through gvfs, i.e. 'mtp:host=091e_4f42_0000cafe0123'
+///
+/// # Arguments
+///
+/// * `gvfs_directory` - Directory of the gvfs mounts of the user.
+#[cfg(target_os = "linux")]
+fn gvfs_volumes(gvfs_directory: &Path) -> Vec<Volume> {
+    fs::read_dir(gvfs_directory)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with("mtp:"))
+                .map(|entry| Volume {
+                    mount_point: entry.path(),
+                    mtp: true,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns a mount point with the octal escapes of `/proc/mounts` replaced, i.e. '\040'
+///
+/// # Arguments
+///
+/// * `mount_point` - Escaped mount point.
+#[cfg(target_os = "linux")]
+fn unescape_mount_point(mount_point: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut rest = mount_point.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..3)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match (byte, escaped) {
+            (b'\\', Some(value)) => {
+                bytes.push(value);
+                rest = &tail[3..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+/// Returns the volumes that may be devices
+#[cfg(target_os = "linux")]
+fn volumes() -> Vec<Volume> {
+    let mut volumes = fs::read_to_string("/proc/mounts")
+        .map(|content| parse_mounts(&content))
+        .unwrap_or_default();
+    if let Some(runtime_directory) = std::env::var_os("XDG_RUNTIME_DIR") {
+        volumes.extend(gvfs_volumes(&Path::new(&runtime_directory).join("gvfs")));
+    }
+    volumes
+}
+
+/// Returns the volumes that may be devices
+#[cfg(target_os = "macos")]
+fn volumes() -> Vec<Volume> {
+    fs::read_dir("/Volumes")
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| Volume {
+                    mount_point: entry.path(),
+                    mtp: false,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the volumes that may be devices
+#[cfg(windows)]
+fn volumes() -> Vec<Volume> {
+    (b'D'..=b'Z')
+        .map(|letter| PathBuf::from(format!("{}:\\", char::from(letter))))
+        .filter(|path| path.exists())
+        .map(|mount_point| Volume {
+            mount_point,
+            mtp: false,
+        })
+        .collect()
+}
+
+/// Returns the volumes that may be devices
+///
+/// Volumes cannot be listed on this platform.
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn volumes() -> Vec<Volume> {
+    Vec::new()
+}
+
+/// Returns a sub directory with a name matching regardless of case
+///
+/// # Arguments
+///
+/// * `directory` - Directory to search.
+/// * `name` - Name of the sub directory.
+fn sub_directory(directory: &Path, name: &str) -> Option<PathBuf> {
+    fs::read_dir(directory)
+        .ok()?
+        .flatten()
+        .find(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .eq_ignore_ascii_case(name)
+                && entry.path().is_dir()
+        })
+        .map(|entry| entry.path())
+}
+
+/// Returns the activity folder of a device, i.e. 'GARMIN/Activity' or 'GARMIN/ACTIVITY'
+///
+/// # Arguments
+///
+/// * `mount_point` - Mount point of the volume.
+fn activity_folder(mount_point: &Path) -> Option<PathBuf> {
+    sub_directory(&sub_directory(mount_point, "GARMIN")?, "Activity")
+}
+
+/// Returns the activity folders of a volume
+///
+/// The activity folders of all storages are returned for MTP devices.
+///
+/// # Arguments
+///
+/// * `volume` - Volume that may be a device.
+fn volume_activity_folders(volume: &Volume) -> Vec<PathBuf> {
+    if !volume.mtp {
+        return activity_folder(&volume.mount_point).into_iter().collect();
+    }
+    fs::read_dir(&volume.mount_point)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| activity_folder(&entry.path()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the activity folders of all connected devices
+///
+/// An error is returned if no device is connected.
+pub(crate) fn device_activity_folders() -> Result<Vec<PathBuf>> {
+    let folders: Vec<PathBuf> = volumes().iter().flat_map(volume_activity_folders).collect();
+    if folders.is_empty() {
+        let msg =
+            "No device with a 'GARMIN/Activity' folder found on the mounted volumes or MTP devices";
+        return Err(ArchiverError::new(msg));
+    }
+    Ok(folders)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test finding the activity folders of devices
+    fn test_activity_folder() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let device = tmpdir.path().join("FR965");
+        fs::create_dir_all(device.join("Garmin/ACTIVITY")).unwrap();
+        assert_eq!(
+            Some(device.join("Garmin/ACTIVITY")),
+            super::activity_folder(&device)
+        );
+        fs::create_dir_all(tmpdir.path().join("STICK/GARMIN")).unwrap();
+        assert_eq!(None, super::activity_folder(&tmpdir.path().join("STICK")));
+
+        // the storages of MTP devices are sub directories
+        let mtp = tmpdir.path().join("mtp:host=091e_4f42");
+        fs::create_dir_all(mtp.join("Internal Storage/GARMIN/Activity")).unwrap();
+        let volume = super::Volume {
+            mount_point: mtp.clone(),
+            mtp: true,
+        };
+        assert_eq!(
+            vec![mtp.join("Internal Storage/GARMIN/Activity")],
+            super::volume_activity_folders(&volume)
+        );
+        #[cfg(target_os = "linux")]
+        assert_eq!(vec![volume], super::gvfs_volumes(tmpdir.path()));
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    /// Test reading the mount points of devices
+    fn test_parse_mounts() {
+        let content = "proc /proc proc rw 0 0\n\
+            /dev/sda1 / ext4 rw 0 0\n\
+            /dev/sdb /media/me/GARMIN vfat rw,nosuid 0 0\n\
+            /dev/sdc /run/media/me/My\\040Watch exfat rw 0 0\n\
+            jmtpfs /home/me/watch fuse.jmtpfs rw 0 0\n";
+        let volume = |mount_point: &str, mtp| super::Volume {
+            mount_point: PathBuf::from(mount_point),
+            mtp,
+        };
+        assert_eq!(
+            vec![
+                volume("/media/me/GARMIN", false),
+                volume("/run/media/me/My Watch", false),
+                volume("/home/me/watch", true),
+            ],
+            super::parse_mounts(content)
+        );
+    }
+}
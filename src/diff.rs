@@ -0,0 +1,253 @@
+//! # Comparison of two FIT files
+
+use crate::fitarchiver::{parse_fit_file, read_fit_file, ActivityData, ArchiverError, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Options controlling the comparison of two files
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// First FIT file
+    pub first: PathBuf,
+    /// Second FIT file
+    pub second: PathBuf,
+    /// Compare the number of messages of each kind
+    pub messages: bool,
+}
+
+/// Returns an optional numeric value formatted with a unit
+///
+/// # Arguments
+///
+/// * `value` - Numeric value.
+/// * `precision` - Number of decimal places.
+/// * `unit` - Unit of the value.
+fn format_value(value: Option<f64>, precision: usize, unit: &str) -> String {
+    match value {
+        Some(value) => format!("{:.*} {}", precision, value, unit),
+        None => String::from("-"),
+    }
+}
+
+/// Returns the extracted metadata and summary statistics of an activity as rows of a table
+///
+/// # Arguments
+///
+/// * `activity_data` - Activity data of the file.
+/// * `size` - Size of the file in bytes.
+fn summary(activity_data: &ActivityData, size: u64) -> Vec<(String, String)> {
+    vec![
+        (
+            String::from("start time"),
+            activity_data.timestamp.to_rfc3339(),
+        ),
+        (String::from("sport"), activity_data.sport.clone()),
+        (String::from("sub sport"), activity_data.sub_sport.clone()),
+        (String::from("sport name"), activity_data.sport_name.clone()),
+        (
+            String::from("workout name"),
+            activity_data.workout_name.clone(),
+        ),
+        (
+            String::from("manufacturer"),
+            activity_data.manufacturer.clone(),
+        ),
+        (String::from("product"), activity_data.product.clone()),
+        (
+            String::from("duration"),
+            format_value(activity_data.duration, 1, "s"),
+        ),
+        (
+            String::from("distance"),
+            format_value(activity_data.distance, 1, "m"),
+        ),
+        (
+            String::from("avg heart rate"),
+            format_value(activity_data.heart_rate, 0, "bpm"),
+        ),
+        (
+            String::from("notes"),
+            activity_data.notes.replace('\n', " "),
+        ),
+        (
+            String::from("records"),
+            activity_data.record_count.to_string(),
+        ),
+        (
+            String::from("fields"),
+            activity_data.field_count.to_string(),
+        ),
+        (String::from("size"), format!("{} bytes", size)),
+    ]
+}
+
+/// Returns the number of messages of each kind in a FIT file
+///
+/// # Arguments
+///
+/// * `path` - Path of the FIT file.
+fn message_counts(path: &Path) -> Result<BTreeMap<String, usize>> {
+    let mut counts = BTreeMap::new();
+    for data in read_fit_file(path)? {
+        *counts.entry(data.kind().to_string()).or_insert(0) += 1;
+    }
+    Ok(counts)
+}
+
+/// Returns the rows of the message counts of both files
+///
+/// # Arguments
+///
+/// * `first` - Message counts of the first file.
+/// * `second` - Message counts of the second file.
+fn message_rows(
+    first: &BTreeMap<String, usize>,
+    second: &BTreeMap<String, usize>,
+) -> Vec<(String, String, String)> {
+    let mut kinds: Vec<&String> = first.keys().chain(second.keys()).collect();
+    kinds.sort();
+    kinds.dedup();
+
+    kinds
+        .into_iter()
+        .map(|kind| {
+            (
+                format!("messages {}", kind),
+                first.get(kind).copied().unwrap_or(0).to_string(),
+                second.get(kind).copied().unwrap_or(0).to_string(),
+            )
+        })
+        .collect()
+}
+
+/// Returns a table of rows, differing rows are marked with '*'
+///
+/// # Arguments
+///
+/// * `header` - Names of both files.
+/// * `rows` - Name of the row and values of both files.
+fn format_table(header: (&str, &str), rows: &[(String, String, String)]) -> String {
+    let name_width = rows.iter().map(|row| row.0.len()).max().unwrap_or(0);
+    let first_width = rows
+        .iter()
+        .map(|row| row.1.chars().count())
+        .chain([header.0.chars().count()])
+        .max()
+        .unwrap_or(0);
+
+    let mut lines = vec![format!(
+        "  {:name_width$}  {:first_width$}  {}",
+        "", header.0, header.1
+    )];
+    for (name, first, second) in rows {
+        let marker = if first != second { '*' } else { ' ' };
+        lines.push(format!(
+            "{} {:name_width$}  {:first_width$}  {}",
+            marker, name, first, second
+        ));
+    }
+    lines
+        .iter()
+        .map(|line| line.trim_end())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the size of a file in bytes
+///
+/// # Arguments
+///
+/// * `path` - Path of the file.
+fn file_size(path: &Path) -> Result<u64> {
+    match fs::metadata(path) {
+        Ok(metadata) => Ok(metadata.len()),
+        Err(err) => {
+            let msg = format!("Unable to read metadata of '{}'", path.display());
+            Err(ArchiverError::io(&msg, err))
+        }
+    }
+}
+
+/// Compare the metadata and summary statistics of two FIT files side by side
+///
+/// # Arguments
+///
+/// `options` - Diff options.
+pub fn diff_files(options: &DiffOptions) -> Result<String> {
+    let first = summary(&parse_fit_file(&options.first)?, file_size(&options.first)?);
+    let second = summary(
+        &parse_fit_file(&options.second)?,
+        file_size(&options.second)?,
+    );
+
+    let mut rows: Vec<(String, String, String)> = first
+        .into_iter()
+        .zip(second)
+        .map(|((name, first), (_, second))| (name, first, second))
+        .collect();
+    if options.messages {
+        rows.extend(message_rows(
+            &message_counts(&options.first)?,
+            &message_counts(&options.second)?,
+        ));
+    }
+
+    let differences = rows.iter().filter(|row| row.1 != row.2).count();
+    let header = (
+        options.first.display().to_string(),
+        options.second.display().to_string(),
+    );
+    Ok(format!(
+        "{}\n\nFound {} differences",
+        format_table((&header.0, &header.1), &rows),
+        differences
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    #[test]
+    /// Test comparing a file with itself
+    fn test_diff_files() {
+        let file = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test")
+            .join("test_data_01.fit");
+        let mut options = super::DiffOptions {
+            first: file.clone(),
+            second: file.clone(),
+            messages: false,
+        };
+
+        let result = super::diff_files(&options).unwrap();
+        assert!(result.contains("  sport           running"));
+        assert!(!result.contains("messages"));
+        assert!(result.ends_with("Found 0 differences"));
+
+        options.messages = true;
+        let result = super::diff_files(&options).unwrap();
+        assert!(result.contains("  messages record"));
+        assert!(result.ends_with("Found 0 differences"));
+
+        options.second = file.with_file_name("corrupted.fit");
+        super::diff_files(&options).expect_err("error expected");
+    }
+
+    #[test]
+    /// Test marking differences in the table
+    fn test_format_table() {
+        let first = BTreeMap::from([(String::from("record"), 2), (String::from("lap"), 1)]);
+        let second = BTreeMap::from([(String::from("record"), 2)]);
+        let rows = super::message_rows(&first, &second);
+
+        assert_eq!(
+            "                   a.fit  b.fit\n\
+             * messages lap     1      0\n  \
+             messages record  2      2",
+            super::format_table(("a.fit", "b.fit"), &rows)
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! # Raw message inspection of FIT files
+
+use crate::fitarchiver::{read_fit_file, ArchiverError, Result};
+use fitparser::profile::field_types::MesgNum;
+use std::path::PathBuf;
+
+/// Options controlling the dump of a FIT file
+#[derive(Debug, Clone, Default)]
+pub struct DumpOptions {
+    /// FIT file to dump
+    pub file: PathBuf,
+    /// Types of the messages to dump, i.e. 'session', all messages if empty
+    pub messages: Vec<String>,
+}
+
+/// Returns a value of a field as text
+///
+/// # Arguments
+///
+/// * `value` - Value of the field.
+fn format_value(value: &fitparser::Value) -> String {
+    match value {
+        fitparser::Value::Array(values) => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(format_value)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        value => value.to_string(),
+    }
+}
+
+/// Returns all decoded messages and fields of a FIT file as text
+///
+/// # Arguments
+///
+/// `options` - Dump options.
+pub fn dump(options: &DumpOptions) -> Result<String> {
+    for message in &options.messages {
+        if let MesgNum::Value(_) = MesgNum::from(message.as_str()) {
+            let msg = format!("Invalid message type '{}'", message);
+            return Err(ArchiverError::new(&msg));
+        }
+    }
+
+    let parsed_data = read_fit_file(&options.file)?;
+    let total = parsed_data.len();
+    let mut lines = Vec::new();
+    let mut counter: usize = 0;
+    for (number, data) in parsed_data.iter().enumerate() {
+        let kind = data.kind().to_string();
+        if !options.messages.is_empty() && !options.messages.contains(&kind) {
+            continue;
+        }
+
+        lines.push(format!("#{} {}", number, kind));
+        for field in data.fields() {
+            let value = format_value(field.value());
+            if field.units().is_empty() {
+                lines.push(format!(
+                    "  {} ({}): {}",
+                    field.name(),
+                    field.number(),
+                    value
+                ));
+            } else {
+                lines.push(format!(
+                    "  {} ({}): {} {}",
+                    field.name(),
+                    field.number(),
+                    value,
+                    field.units()
+                ));
+            }
+        }
+        counter += 1;
+    }
+    lines.push(format!("Dumped {} of {} messages", counter, total));
+
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    #[test]
+    /// Test dumping messages of a FIT file
+    fn test_dump() {
+        let mut options = super::DumpOptions {
+            file: PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("test")
+                .join("test_data_01.fit"),
+            messages: vec![String::from("session")],
+        };
+
+        let result = super::dump(&options).unwrap();
+        assert!(result.contains(" session\n"));
+        assert!(result.contains("  total_distance (9): 10.77 m\n"));
+        assert!(!result.contains(" file_id\n"));
+        assert!(result.ends_with("Dumped 1 of 160 messages"));
+
+        options.messages.clear();
+        let result = super::dump(&options).unwrap();
+        assert!(result.starts_with("#0 file_id\n"));
+        assert!(result.ends_with("Dumped 160 of 160 messages"));
+
+        options.messages = vec![String::from("sessions")];
+        super::dump(&options).expect_err("error expected");
+    }
+
+    #[test]
+    /// Test formatting array values
+    fn test_format_value() {
+        let value = fitparser::Value::Array(vec![
+            fitparser::Value::UInt8(1),
+            fitparser::Value::String(String::from("two")),
+        ]);
+        assert_eq!("[1, two]", super::format_value(&value));
+    }
+}
@@ -0,0 +1,234 @@
+//! # Commands run for archived files
+//!
+//! External commands chain custom conversions or uploads to archiving. A command is split into
+//! words like by a shell, quoted with single or double quotes, and run without a shell, so paths
+//! and activity data never need escaping. The placeholders `{src}`, `{dst}`, `{sport}`,
+//! `{sub_sport}` and `{timestamp}` in its words are replaced by the data of the archived file.
+//!
+//! The batch command is run once after all files, a word that is just `{src}` or `{dst}` is
+//! replaced by the paths of all archived files, i.e. 'rsync -a {dst} backup:activities/'.
+
+use crate::fitarchiver::{ActivityData, ArchiverError, Result};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Returns the words of a command
+///
+/// Words are separated by whitespace, quotes group words with whitespace and a backslash escapes
+/// the next character outside of single quotes.
+///
+/// # Arguments
+///
+/// * `command` - Command line.
+pub fn split_command(command: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None | Some('"'), '\\') => {
+                if let Some(c) = chars.next() {
+                    word.get_or_insert_with(String::new).push(c);
+                }
+            }
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    if quote.is_some() || words.is_empty() {
+        let msg = format!(
+            "Invalid command '{}', expected a program and its arguments",
+            command
+        );
+        return Err(ArchiverError::new(&msg));
+    }
+    Ok(words)
+}
+
+/// Returns the words of the command for an archived file with the placeholders replaced
+///
+/// # Arguments
+///
+/// * `words` - Words of the command.
+/// * `source_path` - Path of the source file.
+/// * `archive_path` - Path of the archive file.
+/// * `activity_data` - Activity data of the file.
+fn expand_words(
+    words: &[String],
+    source_path: &Path,
+    archive_path: &Path,
+    activity_data: &ActivityData,
+) -> Vec<String> {
+    let timestamp = activity_data
+        .timestamp
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    words
+        .iter()
+        .map(|word| {
+            word.replace("{src}", &source_path.to_string_lossy())
+                .replace("{dst}", &archive_path.to_string_lossy())
+                .replace("{sport}", &activity_data.sport)
+                .replace("{sub_sport}", &activity_data.sub_sport)
+                .replace("{timestamp}", &timestamp)
+        })
+        .collect()
+}
+
+/// Returns the words of the batch command with the paths of all archived files
+///
+/// # Arguments
+///
+/// * `words` - Words of the command.
+/// * `archived` - Source and archive paths of the archived files.
+fn expand_batch_words(words: &[String], archived: &[(PathBuf, PathBuf)]) -> Vec<String> {
+    words
+        .iter()
+        .flat_map(|word| match word.as_str() {
+            "{src}" => archived
+                .iter()
+                .map(|(source_path, _)| source_path.to_string_lossy().into_owned())
+                .collect(),
+            "{dst}" => archived
+                .iter()
+                .map(|(_, archive_path)| archive_path.to_string_lossy().into_owned())
+                .collect(),
+            _ => vec![word.clone()],
+        })
+        .collect()
+}
+
+/// Run a command, its output is written to the standard error of the archiver
+///
+/// The standard output of the archiver is kept for the results, i.e. in JSON.
+///
+/// # Arguments
+///
+/// * `words` - Program and arguments.
+fn run(words: &[String]) -> Result<()> {
+    let msg = format!("Unable to run '{}'", words.join(" "));
+    let Some((program, args)) = words.split_first() else {
+        return Err(ArchiverError::new(&msg));
+    };
+    let status = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(io::stderr())
+        .status()
+        .map_err(|err| ArchiverError::io(&msg, err))?;
+    if !status.success() {
+        let msg = format!("Command '{}' failed with {}", words.join(" "), status);
+        return Err(ArchiverError::new(&msg));
+    }
+    Ok(())
+}
+
+/// Run the command for an archived file
+///
+/// # Arguments
+///
+/// * `command` - Command with placeholders.
+/// * `source_path` - Path of the source file.
+/// * `archive_path` - Path of the archive file.
+/// * `activity_data` - Activity data of the file.
+pub(crate) fn run_exec(
+    command: &str,
+    source_path: &Path,
+    archive_path: &Path,
+    activity_data: &ActivityData,
+) -> Result<()> {
+    let words = split_command(command)?;
+    run(&expand_words(
+        &words,
+        source_path,
+        archive_path,
+        activity_data,
+    ))
+}
+
+/// Run the batch command for all archived files
+///
+/// # Arguments
+///
+/// * `command` - Command with placeholders.
+/// * `archived` - Source and archive paths of the archived files.
+pub(crate) fn run_exec_batch(command: &str, archived: &[(PathBuf, PathBuf)]) -> Result<()> {
+    let words = split_command(command)?;
+    run(&expand_batch_words(&words, archived))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    /// Test splitting commands into words
+    fn test_split_command() {
+        assert_eq!(
+            vec!["gpsbabel", "-i", "garmin_fit", "-f", "{dst}"],
+            super::split_command("gpsbabel -i garmin_fit  -f {dst}").unwrap()
+        );
+        assert_eq!(
+            vec!["cp", "{dst}", "/mnt/My Watch/", "it's", "", "a\"b"],
+            super::split_command(r#"cp {dst} '/mnt/My Watch/' "it's" '' a\"b"#).unwrap()
+        );
+        super::split_command("cp 'unterminated").expect_err("error expected");
+        super::split_command("  ").expect_err("error expected");
+    }
+
+    #[test]
+    /// Test replacing the placeholders of commands
+    fn test_expand_words() {
+        let activity_data = crate::fitarchiver::ActivityData {
+            sport: String::from("running"),
+            sub_sport: String::from("trail"),
+            timestamp: chrono::Utc
+                .with_ymd_and_hms(2023, 7, 26, 6, 20, 15)
+                .unwrap(),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+        let words =
+            super::split_command("convert {src} --out={dst}.gpx {sport}/{sub_sport} {timestamp}")
+                .unwrap();
+        assert_eq!(
+            vec![
+                "convert",
+                "a.fit",
+                "--out=2023/run.fit.gpx",
+                "running/trail",
+                "2023-07-26T06:20:15Z"
+            ],
+            super::expand_words(
+                &words,
+                Path::new("a.fit"),
+                Path::new("2023/run.fit"),
+                &activity_data
+            )
+        );
+
+        let archived = vec![
+            (PathBuf::from("a.fit"), PathBuf::from("2023/run.fit")),
+            (PathBuf::from("b.fit"), PathBuf::from("2023/ride.fit")),
+        ];
+        let words = super::split_command("rsync -a {dst} backup:{dst}").unwrap();
+        assert_eq!(
+            vec![
+                "rsync",
+                "-a",
+                "2023/run.fit",
+                "2023/ride.fit",
+                "backup:{dst}"
+            ],
+            super::expand_batch_words(&words, &archived)
+        );
+    }
+}
@@ -2,134 +2,887 @@
 
 #![warn(missing_docs)]
 
-use aho_corasick::AhoCorasick;
-use chrono::{DateTime, TimeZone, Utc};
-use clap::{Arg, ArgAction, Command};
+use crate::backup::backup_file;
+use crate::checksum::record_checksum;
+use crate::clock::{quarantine_path, suspicious_timestamp, SuspiciousTimePolicy};
+use crate::device::device_activity_folders;
+use crate::exec::{run_exec, run_exec_batch};
+use crate::gaps::{format_gap, GapPolicy, GapTracker, DEFAULT_GAP_THRESHOLD};
+use crate::geocode::{geocode, load_places};
+use crate::git::{commit_files, commit_line, GitMode};
+use crate::hash::HashAlgorithm;
+use crate::ignore::IgnoreRules;
+use crate::index::Index;
+use crate::kind::{activity_kind, KindRule};
+use crate::laps::write_gpx;
+use crate::location::{degrees, start_locations, Position, StartLocation};
+use crate::manifest::Manifest;
+use crate::metadata::{enrich, load_metadata};
+use crate::objects::{store_object, StorageLayout};
+use crate::overlay::Overlay;
+use crate::resolver::{ask_resolver, ConflictResolver, Resolution};
+use crate::route::{add_track_point, load_routes, match_route, Route};
+use crate::signature::sign_archive;
+use crate::sports::{
+    multisport_name, sport_code, sport_directory, sport_name, sub_sport_code, sub_sport_name,
+    MultisportNaming, SportDirectory,
+};
+use crate::state::{is_device_state, DEFAULT_DEVICE_STATE_TEMPLATE};
+use crate::storage::{open_storage, StorageBackend};
+use crate::template::{
+    check_template, check_variables, expand_formatstring, has_sport_tag, lint_template, Variable,
+};
+use crate::timezone::{TimeMode, Timezone};
+use crate::upload::{UploadService, Uploader, DEFAULT_UPLOAD_DAILY_LIMIT};
+use crate::usage::record_run;
+use crate::watch::WatchSource;
+use crate::webhook::post_webhook;
+use crate::workout::{step_count, workout_signature, workout_step, WorkoutStep};
+use chrono::{DateTime, FixedOffset, NaiveDate, Offset, TimeDelta, TimeZone, Utc};
+use fitparser::de::{FitObject, FitStreamProcessor};
+use std::collections::{BTreeMap, HashSet};
 use std::error::Error;
 use std::fmt;
 use std::fs::{self, File};
-use std::path::Path;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
+/// Error returned by the archiver
 #[derive(Debug)]
-pub struct ArchiverError {
-    details: String,
+pub enum ArchiverError {
+    /// Error described by a message
+    Message(String),
+    /// File system operation failed
+    Io {
+        /// Description of the failed operation
+        context: String,
+        /// Underlying I/O error
+        source: io::Error,
+    },
+    /// FIT file could not be parsed
+    Parse {
+        /// Description of the failed operation
+        context: String,
+        /// Underlying error of the FIT file parser
+        source: fitparser::Error,
+    },
+    /// Access to the archive index failed
+    Index {
+        /// Description of the failed operation
+        context: String,
+        /// Underlying database error
+        source: rusqlite::Error,
+    },
 }
 
 impl ArchiverError {
-    fn new(msg: &str) -> ArchiverError {
-        ArchiverError {
-            details: msg.to_string(),
+    pub(crate) fn new(msg: &str) -> ArchiverError {
+        ArchiverError::Message(msg.to_string())
+    }
+
+    pub(crate) fn io(msg: &str, source: io::Error) -> ArchiverError {
+        ArchiverError::Io {
+            context: msg.to_string(),
+            source,
+        }
+    }
+
+    pub(crate) fn parse(msg: &str, source: fitparser::Error) -> ArchiverError {
+        ArchiverError::Parse {
+            context: msg.to_string(),
+            source,
+        }
+    }
+
+    pub(crate) fn index(msg: &str, source: rusqlite::Error) -> ArchiverError {
+        ArchiverError::Index {
+            context: msg.to_string(),
+            source,
+        }
+    }
+
+    /// Returns the error message including the underlying cause
+    pub fn details(&self) -> String {
+        match self.source() {
+            Some(source) => format!("{}: {}", self, source),
+            None => self.to_string(),
         }
     }
 }
 
 impl fmt::Display for ArchiverError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.details)
+        match self {
+            ArchiverError::Message(msg) => write!(f, "{}", msg),
+            ArchiverError::Io { context, .. } => write!(f, "{}", context),
+            ArchiverError::Parse { context, .. } => write!(f, "{}", context),
+            ArchiverError::Index { context, .. } => write!(f, "{}", context),
+        }
     }
 }
 
 impl Error for ArchiverError {
-    fn description(&self) -> &str {
-        &self.details
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ArchiverError::Message(_) => None,
+            ArchiverError::Io { source, .. } => Some(source),
+            ArchiverError::Parse { source, .. } => Some(source.as_ref()),
+            ArchiverError::Index { source, .. } => Some(source),
+        }
     }
 }
 
-type Result<T> = std::result::Result<T, ArchiverError>;
+pub(crate) type Result<T> = std::result::Result<T, ArchiverError>;
 
 /// Information extracted from a FIT file
 #[derive(Debug)]
-struct ActivityData {
+pub(crate) struct ActivityData {
     /// Sport type, i.e. 'running'
-    sport: String,
+    pub(crate) sport: String,
     /// Sport name, i.e. 'trail_run' (Name of the activity started on the watch)
-    sport_name: String,
+    pub(crate) sport_name: String,
     /// Sport sub type, i.e. 'trail'
-    sub_sport: String,
+    pub(crate) sub_sport: String,
+    /// Sports of the legs of a multisport activity in order without transitions, i.e. 'swimming'
+    pub(crate) legs: Vec<String>,
+    /// Numeric FIT code of the sport type, i.e. 1 for 'running'
+    pub(crate) sport_code: Option<u8>,
+    /// Numeric FIT code of the sport sub type, i.e. 3 for 'trail'
+    pub(crate) sub_sport_code: Option<u8>,
     /// Workout name, i.e. 'temporun_8km'
-    workout_name: String,
+    pub(crate) workout_name: String,
+    /// Number of steps of the structured workout without repeats, 0 without workout
+    pub(crate) workout_steps: usize,
+    /// Signature of the structure of the workout, i.e. '6x(3min_on_2min_off)'
+    pub(crate) workout_structure: String,
     /// UTC timestamp of activity start
-    timestamp: DateTime<Utc>,
+    pub(crate) timestamp: DateTime<Utc>,
+    /// Offset of the timezone of the templates at the start, UTC unless set by the options
+    pub(crate) utc_offset: FixedOffset,
+    /// Offset of the local time of the recording device, from the activity message
+    pub(crate) device_offset: Option<FixedOffset>,
+    /// User variables of the templates, i.e. 'athlete=jan'
+    pub(crate) variables: Vec<Variable>,
+    /// First and last UTC timestamp of the messages, i.e. the period of a monitoring file
+    pub(crate) period: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    /// Manufacturer of the recording device, i.e. 'garmin'
+    pub(crate) manufacturer: String,
+    /// Product name of the recording device, i.e. 'edge_1040'
+    pub(crate) product: String,
+    /// Serial number of the recording device
+    pub(crate) serial_number: Option<u32>,
+    /// Number of record messages
+    pub(crate) record_count: usize,
+    /// Number of fields in all record messages
+    pub(crate) field_count: usize,
+    /// Number of record messages containing a position
+    pub(crate) position_count: usize,
+    /// Total timer time in seconds
+    pub(crate) duration: Option<f64>,
+    /// Total distance in meters
+    pub(crate) distance: Option<f64>,
+    /// Average heart rate in beats per minute
+    pub(crate) heart_rate: Option<f64>,
+    /// Notes of the activity, i.e. made in Garmin Connect
+    pub(crate) notes: String,
+    /// Ordinal of the activity within its day in the timezone of the templates, starting at 1
+    pub(crate) day_sequence: usize,
+    /// Latitude and longitude of the start position in degrees
+    pub(crate) start_position: Option<Position>,
+    /// Name of the start location, i.e. 'home'
+    pub(crate) start_location: String,
+    /// Positions of the records thinned to a minimum spacing, dropped once the route is known
+    pub(crate) track: Vec<Position>,
+    /// Name of the reference route the activity follows, i.e. 'park_loop'
+    pub(crate) route: String,
+    /// Name of the place nearest to the start, i.e. 'bolzano'
+    pub(crate) city: String,
+    /// Name of the country of the start, i.e. 'italy'
+    pub(crate) country: String,
+    /// Kind of the activity, i.e. 'race'
+    pub(crate) kind: String,
+    /// The timestamp is implausible, i.e. recorded with a dead GPS clock
+    pub(crate) suspicious_time: bool,
+    /// Longest time between two records in seconds while the timer was running
+    pub(crate) longest_gap: Option<u32>,
+    /// The recording has a gap of at least the gap threshold, i.e. from a tunnel
+    pub(crate) has_gaps: bool,
+    /// Type of the FIT file, i.e. 'activity' or 'monitoring_b'
+    pub(crate) file_type: String,
 }
 
 impl ActivityData {
     /// Returns an initialized activity data structure with default values
-    fn new() -> ActivityData {
+    pub(crate) fn new() -> ActivityData {
         ActivityData {
             sport: String::from("unknown"),
             sport_name: String::from("unknown"),
             sub_sport: String::from("unknown"),
+            legs: Vec::new(),
+            sport_code: None,
+            sub_sport_code: None,
             workout_name: String::from("unknown"),
+            workout_steps: 0,
+            workout_structure: String::from("unknown"),
             timestamp: chrono::Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+            utc_offset: chrono::Utc.fix(),
+            device_offset: None,
+            variables: Vec::new(),
+            period: None,
+            manufacturer: String::from("unknown"),
+            product: String::from("unknown"),
+            serial_number: None,
+            record_count: 0,
+            field_count: 0,
+            position_count: 0,
+            duration: None,
+            distance: None,
+            heart_rate: None,
+            notes: String::new(),
+            day_sequence: 1,
+            start_position: None,
+            start_location: String::from("unknown"),
+            track: Vec::new(),
+            route: String::from("unknown"),
+            city: String::from("unknown"),
+            country: String::from("unknown"),
+            kind: String::from("unknown"),
+            suspicious_time: false,
+            longest_gap: None,
+            has_gaps: false,
+            file_type: String::from("unknown"),
+        }
+    }
+
+    /// Returns the start time in the timezone of the templates
+    pub(crate) fn local_timestamp(&self) -> DateTime<FixedOffset> {
+        self.timestamp.with_timezone(&self.utc_offset)
+    }
+
+    /// Returns the first timestamp of the messages or the start time if there is none
+    pub(crate) fn begin(&self) -> DateTime<Utc> {
+        self.period.map_or(self.timestamp, |(begin, _)| begin)
+    }
+
+    /// Returns the last timestamp of the messages or the start time if there is none
+    pub(crate) fn end(&self) -> DateTime<Utc> {
+        self.period.map_or(self.timestamp, |(_, end)| end)
+    }
+
+    /// Returns the identifier of the recording device, i.e. 'garmin/edge_1040/3456789012'
+    pub(crate) fn device(&self) -> String {
+        match self.serial_number {
+            Some(serial_number) => {
+                format!("{}/{}/{}", self.manufacturer, self.product, serial_number)
+            }
+            None => format!("{}/{}", self.manufacturer, self.product),
+        }
+    }
+
+    /// Returns true if the activity was recorded by a matching device
+    ///
+    /// The device matches if the given name is contained in the manufacturer or product name,
+    /// ignoring case.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - Name of the device.
+    fn recorded_by(&self, device: &str) -> bool {
+        let device = device.trim().to_lowercase().replace(' ', "_");
+        self.manufacturer.contains(&device) || self.product.contains(&device)
+    }
+
+    /// Returns true if the activity passes the position filters of the options
+    ///
+    /// Activities with GPS contain at least one record message with a position, indoor
+    /// activities like trainer sessions contain none.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Archiver options.
+    fn matches_position_filter(&self, options: &Options) -> bool {
+        let has_position = self.position_count > 0;
+        !(options.require_gps && !has_position || options.indoor_only && has_position)
+    }
+}
+
+/// Returns which activities are skipped because they are duplicates of another activity
+///
+/// Activities with start times within the given window are considered to be the same activity
+/// recorded on different devices. The returned vector contains `true` for every activity that
+/// is skipped according to the policy.
+///
+/// # Arguments
+///
+/// * `activities` - Activity data of all files, `None` for files that could not be parsed.
+/// * `policy` - Policy selecting the activity that is archived.
+/// * `window` - Maximum difference of the start times in seconds.
+pub(crate) fn find_duplicates(
+    activities: &[Option<&ActivityData>],
+    policy: &DuplicatePolicy,
+    window: i64,
+) -> Vec<bool> {
+    let mut skip = vec![false; activities.len()];
+    if *policy == DuplicatePolicy::KeepBoth {
+        return skip;
+    }
+
+    // sort activities by start time to find groups of overlapping starts
+    let mut order: Vec<usize> = (0..activities.len())
+        .filter(|&i| activities[i].is_some())
+        .collect();
+    order.sort_by_key(|&i| activities[i].unwrap().timestamp);
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for i in order {
+        let timestamp = activities[i].unwrap().timestamp;
+        match groups.last_mut() {
+            Some(group)
+                if (timestamp - activities[group[0]].unwrap().timestamp).num_seconds()
+                    <= window =>
+            {
+                group.push(i)
+            }
+            _ => groups.push(vec![i]),
+        }
+    }
+
+    for group in groups.iter().filter(|group| group.len() > 1) {
+        // restrict candidates to the preferred device if any file matches it
+        let mut candidates: Vec<usize> = match policy {
+            DuplicatePolicy::PreferDevice(device) => group
+                .iter()
+                .copied()
+                .filter(|&i| activities[i].unwrap().recorded_by(device))
+                .collect(),
+            _ => Vec::new(),
+        };
+        if candidates.is_empty() {
+            candidates = group.clone();
+        }
+
+        // keep the candidate with most data, the first one given wins a tie
+        let keep = candidates
+            .iter()
+            .copied()
+            .max_by_key(|&i| {
+                let activity_data = activities[i].unwrap();
+                (
+                    activity_data.record_count,
+                    activity_data.field_count,
+                    std::cmp::Reverse(i),
+                )
+            })
+            .unwrap();
+        for &i in group {
+            skip[i] = i != keep;
+        }
+    }
+
+    skip
+}
+
+/// Returns the ordinal of every activity within its day ordered by start time
+///
+/// Activities with the same start time share the ordinal, therefore an activity archived again
+/// keeps its ordinal. Skipped activities are not taken into account, `1` is returned for them and
+/// for files that could not be parsed. The days are the days of the timezone of the templates.
+///
+/// # Arguments
+///
+/// * `activities` - Activity data of all files, `None` for files that could not be parsed.
+/// * `skipped` - Activities that are skipped because they are duplicates.
+/// * `known` - Start times of activities that are already archived.
+/// * `timezone` - Timezone of the templates.
+pub(crate) fn day_sequences(
+    activities: &[Option<&ActivityData>],
+    skipped: &[bool],
+    known: &[DateTime<Utc>],
+    timezone: &Timezone,
+) -> Vec<usize> {
+    let mut timestamps: Vec<DateTime<Utc>> = activities
+        .iter()
+        .zip(skipped)
+        .filter_map(|(activity_data, &skipped)| match activity_data {
+            Some(activity_data) if !skipped => Some(activity_data.timestamp),
+            _ => None,
+        })
+        .chain(known.iter().copied())
+        .collect();
+    timestamps.sort();
+    timestamps.dedup();
+
+    activities
+        .iter()
+        .zip(skipped)
+        .map(|(activity_data, &skipped)| match activity_data {
+            Some(activity_data) if !skipped => {
+                let day = timezone.date(activity_data.timestamp);
+                timestamps
+                    .iter()
+                    .filter(|&&timestamp| {
+                        timezone.date(timestamp) == day && timestamp < activity_data.timestamp
+                    })
+                    .count()
+                    + 1
+            }
+            _ => 1,
+        })
+        .collect()
+}
+
+/// Returns all FIT files contained in a directory and its sub directories
+///
+/// Hidden files and directories are ignored. The returned paths are sorted.
+///
+/// # Arguments
+///
+/// * `directory` - Directory to search.
+pub(crate) fn find_fit_files(directory: &Path) -> Result<Vec<PathBuf>> {
+    find_fit_files_within(directory, None, false, None, &[], false)
+}
+
+/// Returns true if a file found in a directory is a FIT file
+///
+/// Files with the extension 'fit' in any case are FIT files, as well as files with one of the
+/// additional extensions, i.e. 'fit.bak' for 'run.fit.bak' or an empty extension for files
+/// without extension. Other files are FIT files if they start with a FIT header and sniffing is
+/// requested.
+///
+/// # Arguments
+///
+/// * `path` - Path of the file.
+/// * `extensions` - Additional extensions of FIT files.
+/// * `sniff` - Check the header of files with other extensions.
+fn is_fit_file(path: &Path, extensions: &[String], sniff: bool) -> bool {
+    if path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("fit"))
+    {
+        return true;
+    }
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+    let known = extensions.iter().any(|extension| {
+        match extension.trim_start_matches('.').to_lowercase().as_str() {
+            "" => path.extension().is_none(),
+            extension => name
+                .strip_suffix(extension)
+                .and_then(|stem| stem.strip_suffix('.'))
+                .is_some_and(|stem| !stem.is_empty()),
+        }
+    });
+    known || sniff && has_fit_header(path).unwrap_or(false)
+}
+
+/// Returns all FIT files contained in a directory up to a maximum depth of sub directories
+///
+/// Hidden files and directories are ignored. The returned paths are sorted.
+///
+/// # Arguments
+///
+/// * `directory` - Directory to search.
+/// * `max_depth` - Maximum depth, 1 for the files of the directory itself, `None` for no limit.
+/// * `honor_ignore` - Skip the paths listed in the ignore files of the directories.
+/// * `excluded` - Absolute path of a directory that is not searched, i.e. the archive directory.
+/// * `extensions` - Additional extensions of FIT files.
+/// * `sniff` - Check the header of files with other extensions.
+fn find_fit_files_within(
+    directory: &Path,
+    max_depth: Option<usize>,
+    honor_ignore: bool,
+    excluded: Option<&Path>,
+    extensions: &[String],
+    sniff: bool,
+) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut directories = vec![(directory.to_path_buf(), 1, IgnoreRules::default())];
+
+    while let Some((directory, depth, rules)) = directories.pop() {
+        let rules = match honor_ignore {
+            true => rules.with_directory(&directory)?,
+            false => rules,
+        };
+        let entries = match fs::read_dir(&directory) {
+            Ok(val) => val,
+            Err(err) => {
+                let msg = format!("Unable to read directory '{}'", directory.display());
+                return Err(ArchiverError::io(&msg, err));
+            }
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.')
+                || rules.is_ignored(&path, path.is_dir())
+            {
+                continue;
+            }
+            if path.is_dir() {
+                if excluded.is_some_and(|excluded| {
+                    std::path::absolute(&path).is_ok_and(|path| path == excluded)
+                }) {
+                    continue;
+                }
+                if max_depth.is_none_or(|max_depth| depth < max_depth) {
+                    directories.push((path, depth + 1, rules.clone()));
+                }
+            } else if is_fit_file(&path, extensions, sniff) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Returns the files to archive with directories replaced by the FIT files they contain
+///
+/// Directories are searched recursively if requested, otherwise only the FIT files directly
+/// contained in them are archived. Besides the extension 'fit' in any case, the additional
+/// extensions of the options are searched and with sniffing the headers of all other files.
+/// Files in the list of files are archived regardless of their extension. The paths
+/// listed in the ignore files of the directories are skipped. The activity folders of connected
+/// devices are added if requested.
+///
+/// An archive directory located inside a source directory is not searched, so archive files are
+/// never archived again.
+///
+/// # Arguments
+///
+/// `options` - Archiver options.
+pub(crate) fn input_files(options: &Options) -> Result<Vec<PathBuf>> {
+    check_source_tree(options)?;
+    let max_depth = if options.recursive {
+        options.max_depth
+    } else {
+        Some(1)
+    };
+    let archive_directory = std::path::absolute(&options.directory).ok();
+    let mut files = Vec::with_capacity(options.files.len());
+    for path in &options.files {
+        if path.is_dir() {
+            files.extend(find_fit_files_within(
+                path,
+                max_depth,
+                true,
+                archive_directory.as_deref(),
+                &options.extensions,
+                options.sniff,
+            )?);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    if options.from_device {
+        for folder in device_activity_folders()? {
+            files.extend(find_fit_files_within(
+                &folder,
+                Some(1),
+                true,
+                None,
+                &options.extensions,
+                options.sniff,
+            )?);
+        }
+    }
+    if options.retry_failed {
+        for path in failed_files(options)? {
+            // a failed file given again is archived once
+            if !files
+                .iter()
+                .any(|file| std::path::absolute(file).is_ok_and(|file| file == path))
+            {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Returns the source files that failed to be archived in previous runs
+///
+/// Failed files that no longer exist are reported and dropped from the index.
+///
+/// # Arguments
+///
+/// * `options` - Archiver options.
+fn failed_files(options: &Options) -> Result<Vec<PathBuf>> {
+    let writable = !options.dry_run && !options.print_path;
+    let Some(index) = Index::open(&options.directory, writable)? else {
+        return Ok(Vec::new());
+    };
+    let mut files = Vec::new();
+    for (path, reason) in index.failures()? {
+        if path.exists() {
+            files.push(path);
+            continue;
+        }
+        eprintln!(
+            "Failed file '{}' no longer exists, it failed with: {}",
+            path.display(),
+            reason
+        );
+        if writable {
+            index.remove_failure(&path)?;
         }
     }
+    Ok(files)
 }
 
-/// Returns an expanded format string with '%' and '$' replaced
+/// Check that recursively searched directories are not located inside the archive directory
+///
+/// Searching the archive directory itself would find the archive files, i.e. a watched directory
+/// would archive its own archive files again on every poll.
 ///
-/// '%' tag are expanded using the timestamp of the acticity data. The '$' tag
-/// are expanded using other data from the activity.
+/// # Arguments
+///
+/// `options` - Archiver options.
+pub(crate) fn check_source_tree(options: &Options) -> Result<()> {
+    if !options.recursive {
+        return Ok(());
+    }
+    let Ok(directory) = std::path::absolute(&options.directory) else {
+        return Ok(());
+    };
+    for path in options.files.iter().filter(|path| path.is_dir()) {
+        if std::path::absolute(path).is_ok_and(|source| source.starts_with(&directory)) {
+            let msg = format!(
+                "Unable to search '{}' recursively, it is located inside the archive directory '{}'",
+                path.display(),
+                options.directory.display()
+            );
+            return Err(ArchiverError::new(&msg));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the path of the archive file for the given activity
 ///
 /// # Arguments
 ///
+/// * `base_directory` - Base directory of the archive.
 /// * `formatstring` - A format string containing '%' and '$' tags.
 /// * `activity_data` - Data that will be used for expansion of the tags.
-fn expand_formatstring(formatstring: &str, activity_data: &ActivityData) -> String {
-    // the following code is not the most efficient one but makes the mappings obvious
-
-    // first define the mappings as slice for better visibility ...
-    let mappings = [
-        ["$s", activity_data.sport.as_str()],
-        ["$n", activity_data.sport_name.as_str()],
-        ["$S", activity_data.sub_sport.as_str()],
-        ["$w", activity_data.workout_name.as_str()],
-    ];
-
-    // ... then convert the slice to the required vectors
-    let tags: Vec<&str> = mappings.iter().map(|x| x[0]).collect();
-    let substitutions: Vec<&str> = mappings.iter().map(|x| x[1]).collect();
-
-    // replace all '$' tags with their substitutions (activity)
-    let result = AhoCorasick::new(tags)
-        .unwrap()
-        .replace_all(formatstring, &substitutions);
-
-    // replace all '%' tags with their substitions (timestamp)
-    activity_data
-        .timestamp
-        .format(&result.to_string())
-        .to_string()
+pub(crate) fn archive_path(
+    base_directory: &Path,
+    formatstring: &str,
+    activity_data: &ActivityData,
+) -> PathBuf {
+    base_directory
+        .join(expand_formatstring(formatstring, activity_data))
+        .with_extension("fit")
 }
 
-/// Returns activity data extracted from given FIT file
+/// Returns the path of a file exported from the given activity
+///
+/// Exports use their own template, so derived files can live in a tree parallel to the archive.
+/// An extension given in the template is replaced by the extension of the export format.
 ///
 /// # Arguments
 ///
-/// * `path` - Path of the FIT file
-fn parse_fit_file(path: &Path) -> Result<ActivityData> {
-    let mut activity_data = ActivityData::new();
-    let mut sports: Vec<String> = Vec::new();
+/// * `base_directory` - Base directory of the exports.
+/// * `formatstring` - A format string containing '%' and '$' tags.
+/// * `activity_data` - Data that will be used for expansion of the tags.
+/// * `extension` - File extension of the export format.
+pub(crate) fn export_path(
+    base_directory: &Path,
+    formatstring: &str,
+    activity_data: &ActivityData,
+    extension: &str,
+) -> PathBuf {
+    base_directory
+        .join(expand_formatstring(formatstring, activity_data))
+        .with_extension(extension)
+}
 
+/// Returns the bytes contained in a memo field
+///
+/// # Arguments
+///
+/// * `value` - Value of the memo field.
+fn memo_bytes(value: &fitparser::Value) -> Vec<u8> {
+    match value {
+        fitparser::Value::Byte(val) | fitparser::Value::UInt8(val) => vec![*val],
+        fitparser::Value::String(val) => val.as_bytes().to_vec(),
+        fitparser::Value::Array(values) => values.iter().flat_map(memo_bytes).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns all decoded messages of a FIT file
+///
+/// # Arguments
+///
+/// * `path` - Path of the FIT file
+pub(crate) fn read_fit_file(path: &Path) -> Result<Vec<fitparser::FitDataRecord>> {
     // open FIT file
     let mut fp = match File::open(path) {
         Ok(fp) => fp,
-        Err(_err) => {
+        Err(err) => {
             let msg = format!("Unable to open '{}'", path.display());
-            return Err(ArchiverError::new(&msg));
+            return Err(ArchiverError::io(&msg, err));
         }
     };
 
     // parse FIT file to data structure
-    let parsed_data = match fitparser::from_reader(&mut fp) {
-        Ok(parsed_data) => parsed_data,
-        Err(_err) => {
+    match fitparser::from_reader(&mut fp) {
+        Ok(parsed_data) => Ok(parsed_data),
+        Err(err) => {
             let msg = format!("Unable to parse '{}'", path.display());
-            return Err(ArchiverError::new(&msg));
+            Err(ArchiverError::parse(&msg, err))
+        }
+    }
+}
+
+/// Messages of a FIT file decoded one at a time
+///
+/// Only the content of the file is kept in memory, not all decoded messages, which take many
+/// times the size of the file for long activities.
+struct FitRecords {
+    /// Path of the FIT file
+    path: PathBuf,
+    /// Content of the FIT file
+    content: Vec<u8>,
+    /// Position of the next message in the content
+    position: usize,
+    /// Decoder keeping the definition messages of the file
+    processor: FitStreamProcessor,
+}
+
+impl FitRecords {
+    /// Returns the messages of a FIT file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the FIT file
+    fn open(path: &Path) -> Result<FitRecords> {
+        let mut content = Vec::new();
+        let read = File::open(path).and_then(|mut fp| io::Read::read_to_end(&mut fp, &mut content));
+        if let Err(err) = read {
+            let msg = format!("Unable to open '{}'", path.display());
+            return Err(ArchiverError::io(&msg, err));
+        }
+        Ok(FitRecords {
+            path: path.to_path_buf(),
+            content,
+            position: 0,
+            processor: FitStreamProcessor::new(),
+        })
+    }
+
+    /// Returns the next decoded message or `None` at the end of the file
+    fn next_record(&mut self) -> Result<Option<fitparser::FitDataRecord>> {
+        while self.position < self.content.len() {
+            let result = self
+                .processor
+                .deserialize_next(&self.content[self.position..])
+                .and_then(|(remaining, object)| {
+                    self.position = self.content.len() - remaining.len();
+                    match object {
+                        // chained FIT files start over with their own definitions
+                        FitObject::Crc(_) => {
+                            self.processor.reset();
+                            Ok(None)
+                        }
+                        FitObject::DataMessage(message) => {
+                            self.processor.decode_message(message).map(Some)
+                        }
+                        _ => Ok(None),
+                    }
+                });
+            match result {
+                Ok(Some(record)) => return Ok(Some(record)),
+                Ok(None) => (),
+                Err(err) => {
+                    let msg = format!("Unable to parse '{}'", self.path.display());
+                    return Err(ArchiverError::parse(&msg, err));
+                }
+            }
         }
+        Ok(None)
+    }
+}
+
+/// Smallest absolute timestamp of FIT files in seconds since 1970, smaller values count the
+/// seconds since the power on of the device
+const MIN_ABSOLUTE_TIMESTAMP: i64 = 631_065_600 + 0x1000_0000;
+
+/// Returns the offset of the local time of the device from an activity message
+///
+/// The offset is rounded to 15 minutes. Returns `None` without local timestamp or if the offset
+/// exceeds the offsets of real timezones.
+///
+/// # Arguments
+///
+/// * `data` - Activity message.
+fn device_offset(data: &fitparser::FitDataRecord) -> Option<FixedOffset> {
+    let timestamp = |name: &str| {
+        data.fields()
+            .iter()
+            .find(|field| field.name() == name)
+            .and_then(|field| match field.value() {
+                fitparser::Value::Timestamp(val) => Some(*val),
+                _ => None,
+            })
     };
+    let utc = timestamp("timestamp")?;
+    // the parser places the local timestamp in the timezone of the system, the wall time of the
+    // device is the time since the FIT epoch in local time
+    let epoch = NaiveDate::from_ymd_opt(1989, 12, 31)?.and_hms_opt(0, 0, 0)?;
+    let system_epoch = chrono::Local.from_local_datetime(&epoch).earliest()?;
+    let wall_time = epoch + (timestamp("local_timestamp")? - system_epoch);
+
+    let seconds = (wall_time - utc.naive_utc()).num_seconds();
+    let seconds = (seconds as f64 / 900.0).round() as i32 * 900;
+    match seconds.abs() <= 14 * 3600 {
+        true => FixedOffset::east_opt(seconds),
+        false => None,
+    }
+}
+
+/// Returns activity data extracted from given FIT file
+///
+/// # Arguments
+///
+/// * `path` - Path of the FIT file
+pub(crate) fn parse_fit_file(path: &Path) -> Result<ActivityData> {
+    let mut activity_data = ActivityData::new();
+    let mut sports: Vec<String> = Vec::new();
+    let mut memo_parts: Vec<(i64, Vec<u8>)> = Vec::new();
+    let mut descriptions: Vec<String> = Vec::new();
+    let mut workout_steps: Vec<WorkoutStep> = Vec::new();
+    let mut gaps = GapTracker::default();
+
+    // iterate over all data elements, only the extracted data is kept
+    let mut records = FitRecords::open(path)?;
+    while let Some(data) = records.next_record()? {
+        // monitoring files cover a period, their messages are spread over it
+        if let Some(fitparser::Value::Timestamp(val)) = data
+            .fields()
+            .iter()
+            .find(|field| field.name() == "timestamp")
+            .map(|field| field.value())
+        {
+            let timestamp: DateTime<Utc> = DateTime::from(*val);
+            if timestamp.timestamp() >= MIN_ABSOLUTE_TIMESTAMP {
+                activity_data.period = Some(match activity_data.period {
+                    Some((begin, end)) => (begin.min(timestamp), end.max(timestamp)),
+                    None => (timestamp, timestamp),
+                });
+            }
+        }
 
-    // iterate over all data elements
-    for data in parsed_data {
         match data.kind() {
             // extract the timestamp of the activity and check it is an activity
             fitparser::profile::field_types::MesgNum::FileId => {
@@ -149,11 +902,115 @@ fn parse_fit_file(path: &Path) -> Result<ActivityData> {
                                 return Err(ArchiverError::new(&msg));
                             }
                         },
+                        "manufacturer" => {
+                            activity_data.manufacturer = field
+                                .value()
+                                .to_string()
+                                .trim()
+                                .to_lowercase()
+                                .replace(' ', "_");
+                        }
+                        "product" | "garmin_product" => {
+                            activity_data.product = field
+                                .value()
+                                .to_string()
+                                .trim()
+                                .to_lowercase()
+                                .replace(' ', "_");
+                        }
+                        "type" => {
+                            activity_data.file_type = field.value().to_string();
+                        }
+                        "serial_number" => {
+                            activity_data.serial_number = match field.value() {
+                                fitparser::Value::UInt32(val) | fitparser::Value::UInt32z(val) => {
+                                    Some(*val)
+                                }
+                                _ => None,
+                            };
+                        }
                         &_ => (), // ignore all other values
                     }
                 }
             }
 
+            // pauses of the timer are no gaps in the recording
+            fitparser::profile::field_types::MesgNum::Event => {
+                let value = |name: &str| {
+                    data.fields()
+                        .iter()
+                        .find(|field| field.name() == name)
+                        .map(|field| field.value().to_string())
+                };
+                if value("event").as_deref() == Some("timer")
+                    && value("event_type").is_some_and(|event_type| event_type.starts_with("stop"))
+                {
+                    gaps.timer_stopped();
+                }
+            }
+
+            // count the records to estimate how much data the activity contains
+            fitparser::profile::field_types::MesgNum::Record => {
+                activity_data.record_count += 1;
+                activity_data.field_count += data.fields().len();
+                let mut has_position = false;
+                let mut latitude = None;
+                let mut longitude = None;
+                for field in data.fields() {
+                    if let ("timestamp", fitparser::Value::Timestamp(val)) =
+                        (field.name(), field.value())
+                    {
+                        gaps.record(DateTime::from(*val));
+                    }
+                    let value: Option<f64> = field.value().clone().try_into().ok();
+                    match (field.name(), value) {
+                        ("position_lat", val) => {
+                            has_position = true;
+                            latitude = val.map(degrees);
+                        }
+                        ("position_long", val) => longitude = val.map(degrees),
+                        _ => (), // ignore all other values
+                    }
+                }
+                if has_position {
+                    activity_data.position_count += 1;
+                }
+                if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+                    add_track_point(&mut activity_data.track, (latitude, longitude));
+                }
+            }
+
+            // extract the summary of the activity, multisport activities contain several sessions
+            fitparser::profile::field_types::MesgNum::Session => {
+                let mut start_latitude = None;
+                let mut start_longitude = None;
+                for field in data.fields() {
+                    let value: Option<f64> = field.value().clone().try_into().ok();
+                    match (field.name(), value) {
+                        ("start_position_lat", Some(val)) => start_latitude = Some(degrees(val)),
+                        ("start_position_long", Some(val)) => start_longitude = Some(degrees(val)),
+                        ("total_timer_time", Some(val)) => {
+                            *activity_data.duration.get_or_insert(0.0) += val
+                        }
+                        ("total_distance", Some(val)) => {
+                            *activity_data.distance.get_or_insert(0.0) += val
+                        }
+                        ("avg_heart_rate", Some(val)) if activity_data.heart_rate.is_none() => {
+                            activity_data.heart_rate = Some(val)
+                        }
+                        _ => (), // ignore all other values
+                    }
+                }
+                // the first session starts the activity
+                if let (None, Some(latitude), Some(longitude)) = (
+                    activity_data.start_position,
+                    start_latitude,
+                    start_longitude,
+                ) {
+                    activity_data.start_position = Some((latitude, longitude));
+                }
+            }
+
             // extract the sport type of the activity
             fitparser::profile::field_types::MesgNum::Sport => {
                 for field in data.fields() {
@@ -174,8 +1031,22 @@ fn parse_fit_file(path: &Path) -> Result<ActivityData> {
                         },
                         "sport" => match &field.value() {
                             fitparser::Value::String(val) => {
-                                sports
-                                    .push(val.trim().to_lowercase().replace(' ', "_").to_string());
+                                let sport = val.trim().to_lowercase().replace(' ', "_");
+                                activity_data.sport_code = sport_code(&sport);
+                                sports.push(sport);
+                            }
+                            // sports of new devices are unknown to the parser
+                            fitparser::Value::SInt64(val) if sport_name(*val).is_some() => {
+                                activity_data.sport_code = u8::try_from(*val).ok();
+                                sports.push(sport_name(*val).unwrap().to_string());
+                            }
+                            fitparser::Value::SInt64(val) => {
+                                activity_data.sport_code = u8::try_from(*val).ok();
+                                eprintln!(
+                                    "Unknown sport code {} in '{}'. Using 'unknown' instead!",
+                                    val,
+                                    path.display()
+                                );
                             }
                             &_ => {
                                 eprintln!(
@@ -190,6 +1061,20 @@ fn parse_fit_file(path: &Path) -> Result<ActivityData> {
                             fitparser::Value::String(val) => {
                                 activity_data.sub_sport =
                                     val.trim().to_lowercase().replace(' ', "_").to_string();
+                                activity_data.sub_sport_code =
+                                    sub_sport_code(&activity_data.sub_sport);
+                            }
+                            // sub sports of new devices are unknown to the parser
+                            fitparser::Value::SInt64(val) => {
+                                activity_data.sub_sport_code = u8::try_from(*val).ok();
+                                match sub_sport_name(*val) {
+                                    Some(name) => activity_data.sub_sport = name.to_string(),
+                                    None => eprintln!(
+                                        "Unknown sub sport code {} in '{}'. Using 'unknown' instead!",
+                                        val,
+                                        path.display()
+                                    ),
+                                }
                             }
                             &_ => {
                                 eprintln!(
@@ -208,8 +1093,8 @@ fn parse_fit_file(path: &Path) -> Result<ActivityData> {
             // extract the wkt_name of the activity
             fitparser::profile::field_types::MesgNum::Workout => {
                 for field in data.fields() {
-                    match field.name() {
-                        "wkt_name" => match &field.value() {
+                    if field.name() == "wkt_name" {
+                        match &field.value() {
                             fitparser::Value::String(val) => {
                                 activity_data.workout_name =
                                     val.trim().to_lowercase().replace(' ', "_").to_string();
@@ -222,102 +1107,549 @@ fn parse_fit_file(path: &Path) -> Result<ActivityData> {
                                     path.display()
                                 );
                             }
-                        },
+                        }
+                    }
+                }
+            }
+
+            // the local time of the device tells the timezone the activity happened in
+            fitparser::profile::field_types::MesgNum::Activity => {
+                activity_data.device_offset = device_offset(&data);
+            }
+
+            // collect the steps of a structured workout
+            fitparser::profile::field_types::MesgNum::WorkoutStep => {
+                workout_steps.push(workout_step(&data, workout_steps.len()));
+            }
+
+            // collect the parts of long notes that are split into several messages
+            fitparser::profile::field_types::MesgNum::MemoGlob => {
+                let mut part_index: i64 = 0;
+                let mut memo: Vec<u8> = Vec::new();
+                for field in data.fields() {
+                    match field.name() {
+                        "part_index" => part_index = field.value().try_into().unwrap_or(0),
+                        "memo" | "data" => memo.extend(memo_bytes(field.value())),
                         &_ => (), // ignore all other values
                     }
                 }
+                memo_parts.push((part_index, memo));
             }
 
-            _ => (), // ignore all other values
+            // extract descriptions of other messages, i.e. from developer fields
+            _ => {
+                for field in data.fields() {
+                    if let ("description", fitparser::Value::String(val)) =
+                        (field.name(), field.value())
+                    {
+                        descriptions.push(val.trim().to_string());
+                    }
+                }
+            }
         }
     }
 
+    // archiving with another threshold flags the gaps again
+    activity_data.longest_gap = gaps.longest_gap();
+    activity_data.has_gaps = activity_data
+        .longest_gap
+        .is_some_and(|gap| gap >= DEFAULT_GAP_THRESHOLD);
+
+    // device state files are created once and updated since, the last update dates the snapshot
+    if let (true, Some((_, end))) = (is_device_state(&activity_data), activity_data.period) {
+        activity_data.timestamp = end;
+    }
+
+    // join the notes of all memo parts and descriptions
+    memo_parts.sort_by_key(|(part_index, _)| *part_index);
+    let memo: Vec<u8> = memo_parts
+        .into_iter()
+        .flat_map(|(_, memo)| memo)
+        .filter(|&byte| byte != 0)
+        .collect();
+    let memo = String::from_utf8_lossy(&memo).trim().to_string();
+    activity_data.notes = std::iter::once(memo)
+        .chain(descriptions)
+        .filter(|notes| !notes.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    activity_data.workout_steps = step_count(&workout_steps);
+    activity_data.workout_structure = workout_signature(&workout_steps);
+
     // build sport value for single- and multisport activities
     if sports.len() == 1 {
-        activity_data.sport = sports.get(0).unwrap().to_string();
+        activity_data.sport = sports.first().unwrap().to_string();
     } else if sports.len() > 1 {
         activity_data.sport = String::from("multisport_") + &sports.join("_");
+        activity_data.sport_code = sport_code("multisport");
     }
+    activity_data.legs = sports
+        .into_iter()
+        .filter(|sport| sport != "transition")
+        .collect();
 
     Ok(activity_data)
 }
 
-/// Returns matched command line arguments
-pub fn parse_arguments(arguments: Option<Vec<&str>>) -> clap::ArgMatches {
-    const VERSION: &'static str = concat!(
-        env!("VERGEN_GIT_DESCRIBE"),
-        " compiled at ",
-        env!("VERGEN_BUILD_TIMESTAMP")
-    );
-    let parser = Command::new("FIT file archiver")
-        .version(VERSION)
-        .author(env!("CARGO_PKG_AUTHORS"))
-        .about(env!("CARGO_PKG_DESCRIPTION"))
-        .arg(
-            Arg::new("directory")
-                .short('d')
-                .long("directory")
-                .num_args(1)
-                .value_name("archive directory")
-                .default_value(".")
-                .help("Archive base directory.")
-                .long_help("Base directory where the archive is created."),
-        )
-        .arg(
-            Arg::new("file-template")
-                .short('f')
-                .long("file-template")
-                .num_args(1)
-                .value_name("template string")
-                .default_value("%Y/%m/%Y-%m-%d-%H%M%S-$s")
-                .help("Format string defining the path and name of the archive file in the archive directory.")
-                .long_help(
-"Format template that defines the path and name of the archive file in the archive directory. '/' must be used as a separator for path components. All strftime() tags are supported for expanding the time information of the training. In addition to the time information the following FIT file specific expansions are supported:
-
-  Tag   Description     Example          Default
-  ------------------------------------------------
-  $s    sport type      'running'        'unknown'
-  $S    sport subtype   'trail'          'unknown'
-  $n    sport name      'trail_run'      'unknown'
-  $w    workout name    'temporun_8km'   'unknown'
-
-NOTE: It is possible that the shell used tries to replace tags. Therefore, the template should be passed as a quoted string.")
-        )
-        .arg(
-            Arg::new("move")
-                .short('m')
-                .long("move")
-                .action(ArgAction::SetTrue)
-                .help("Move files to archive instead of copying them."),
-        )
-        .arg(
-            Arg::new("dry-run")
-                .short('n')
-                .long("dry-run")
-                .action(ArgAction::SetTrue)
-                .help("Do not copy or move the files, just show what will happen."),
-        )
-        .arg(
-            Arg::new("files")
-                .num_args(1..)
-                .value_name("files")
-                .required(true)
-                .help("List of FIT files to archive."),
-        );
+/// Default template for the path and name of the archive file
+pub const DEFAULT_FILE_TEMPLATE: &str = "%Y/%m/%Y-%m-%d-%H%M%S-$s";
 
-    match arguments {
-        Some(val) => parser.get_matches_from(val),
-        None => parser.get_matches(),
+/// Default maximum number of files parsed and archived together, 0 processes the whole run in one
+/// batch
+pub const DEFAULT_BATCH_SIZE: usize = 0;
+
+/// Output format for the result of processing the files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Human readable text
+    #[default]
+    Text,
+    /// Stable tab separated lines `STATUS\tSOURCE\tDEST` for scripts (version 1)
+    Porcelain,
+    /// NUL separated paths of the archived files
+    Print0,
+    /// One JSON object per line for each file, each error and the summary
+    Json,
+    /// Number of archived files per destination directory at the end of the run
+    Grouped,
+}
+
+impl FromStr for OutputFormat {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "porcelain" => Ok(OutputFormat::Porcelain),
+            "print0" => Ok(OutputFormat::Print0),
+            "json" => Ok(OutputFormat::Json),
+            "grouped" => Ok(OutputFormat::Grouped),
+            _ => {
+                let msg = format!(
+                    "Invalid output format '{}', expected 'text', 'porcelain', 'print0', 'json' or 'grouped'",
+                    s
+                );
+                Err(ArchiverError::new(&msg))
+            }
+        }
     }
 }
 
-/// Create directory for archive file.
-///
+/// Result of processing a single file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// File was copied to the archive
+    Copied,
+    /// File was moved to the archive
+    Moved,
+    /// Nothing was done because of a dry run
+    DryRun,
+    /// File was archived before, the archive file has the same content
+    Identical,
+    /// File was not archived because it is a duplicate or the archive file is kept
+    Skipped,
+    /// File could not be archived
+    Error,
+}
+
+impl Status {
+    /// Returns the human readable description of the status
+    fn text(&self) -> &'static str {
+        match self {
+            Status::Copied => "copied",
+            Status::Moved => "moved",
+            Status::DryRun => "dry run",
+            Status::Identical => "already archived",
+            Status::Skipped => "skipped",
+            Status::Error => "error",
+        }
+    }
+
+    /// Returns the status keyword used in porcelain output
+    fn porcelain(&self) -> &'static str {
+        match self {
+            Status::Copied => "COPIED",
+            Status::Moved => "MOVED",
+            Status::DryRun => "DRYRUN",
+            Status::Identical => "IDENTICAL",
+            Status::Skipped => "SKIPPED",
+            Status::Error => "ERROR",
+        }
+    }
+
+    /// Returns the action reported in JSON output
+    fn action(&self) -> &'static str {
+        match self {
+            Status::Copied => "copied",
+            Status::Moved => "moved",
+            Status::DryRun => "dry-run",
+            Status::Identical => "identical",
+            Status::Skipped => "skipped",
+            Status::Error => "error",
+        }
+    }
+}
+
+/// Policy for the same activity recorded on several devices
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// Archive all files
+    #[default]
+    KeepBoth,
+    /// Archive only the file containing most record messages and fields
+    KeepRichest,
+    /// Archive only the file recorded by the given device
+    PreferDevice(String),
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "keep-both" => Ok(DuplicatePolicy::KeepBoth),
+            "keep-richest" => Ok(DuplicatePolicy::KeepRichest),
+            _ => match s.strip_prefix("prefer-device=") {
+                Some(device) if !device.is_empty() => {
+                    Ok(DuplicatePolicy::PreferDevice(String::from(device)))
+                }
+                _ => {
+                    let msg = format!(
+                        "Invalid duplicate policy '{}', expected 'keep-both', 'keep-richest' or 'prefer-device=<device>'",
+                        s
+                    );
+                    Err(ArchiverError::new(&msg))
+                }
+            },
+        }
+    }
+}
+
+/// Policy for an archive file that already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// Replace the existing archive file
+    #[default]
+    Overwrite,
+    /// Keep the existing archive file
+    Skip,
+    /// Replace the existing archive file only if the source is newer and has a different content
+    OverwriteIfNewer,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "skip" => Ok(ConflictPolicy::Skip),
+            "overwrite-if-newer" => Ok(ConflictPolicy::OverwriteIfNewer),
+            _ => {
+                let msg = format!(
+                    "Invalid conflict policy '{}', expected 'overwrite', 'skip' or 'overwrite-if-newer'",
+                    s
+                );
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+/// Policy for input files that are not FIT files, i.e. junk on a memory card
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidPolicy {
+    /// Report the file as error
+    #[default]
+    Error,
+    /// Report the file as skipped
+    Skip,
+    /// Archive the file in the quarantine directory under its original name
+    Quarantine,
+}
+
+impl FromStr for InvalidPolicy {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "error" => Ok(InvalidPolicy::Error),
+            "skip" => Ok(InvalidPolicy::Skip),
+            "quarantine" => Ok(InvalidPolicy::Quarantine),
+            _ => {
+                let msg = format!(
+                    "Invalid policy for invalid files '{}', expected 'error', 'skip' or 'quarantine'",
+                    s
+                );
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+/// Point in time when the source files are deleted in move mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SourceDeletion {
+    /// Delete the source files at the end of the run unless archiving or verifying a file failed
+    #[default]
+    AfterBatch,
+    /// Delete the source files at the end of the run only if the run had no errors at all, i.e.
+    /// no file that is not a FIT file and no failed upload of the queued files
+    AfterCleanBatch,
+    /// Delete each source file as soon as it has been archived
+    Immediately,
+}
+
+impl FromStr for SourceDeletion {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "after-batch" => Ok(SourceDeletion::AfterBatch),
+            "after-clean-batch" => Ok(SourceDeletion::AfterCleanBatch),
+            "immediately" => Ok(SourceDeletion::Immediately),
+            _ => {
+                let msg = format!(
+                    "Invalid source deletion '{}', expected 'after-batch', 'after-clean-batch' or 'immediately'",
+                    s
+                );
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+/// Options controlling the archiver
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Base directory where the archive is created
+    pub directory: PathBuf,
+    /// Format template that defines the path and name of the archive file
+    pub file_template: String,
+    /// Warn about file templates whose archive paths are likely to collide
+    pub template_lint: bool,
+    /// Format template of the archive paths of device state files, i.e. lifetime totals
+    pub device_state_template: String,
+    /// Timezone of the '%' tags of the templates
+    pub timezone: Timezone,
+    /// Source of the local time of the '%' tags of the templates
+    pub time: TimeMode,
+    /// User variables of the templates, i.e. 'athlete=jan' for '${var:athlete}'
+    pub variables: Vec<Variable>,
+    /// Move files to archive instead of copying them
+    pub move_files: bool,
+    /// Never write to, lock or delete anything under the source paths, i.e. device storage
+    pub readonly_source: bool,
+    /// Point in time when the source files are deleted in move mode
+    pub delete_sources: SourceDeletion,
+    /// Do not copy or move the files, just show what will happen
+    pub dry_run: bool,
+    /// Output format for the result of processing the files
+    pub output: OutputFormat,
+    /// Only print the paths of the archive files without touching the file system
+    pub print_path: bool,
+    /// Policy for the same activity recorded on several devices
+    pub duplicate_policy: DuplicatePolicy,
+    /// Maximum difference of start times in seconds for activities to be considered the same
+    pub duplicate_window: i64,
+    /// Policy for an archive file that already exists
+    pub on_conflict: ConflictPolicy,
+    /// Resolver of an embedding application deciding about archive files that already exist
+    /// instead of the conflict policy, `None` for the conflict policy
+    pub conflict_resolver: Option<Arc<dyn ConflictResolver>>,
+    /// Policy for input files that are not FIT files
+    pub on_invalid: InvalidPolicy,
+    /// Layout of the archive files
+    pub layout: StorageLayout,
+    /// Commit the archived files to the git repository of the archive
+    pub git: Option<GitMode>,
+    /// Upload the newly archived activities to an online service
+    pub upload: Option<UploadService>,
+    /// Maximum number of uploads per day, further files stay queued for the next day
+    pub upload_daily_limit: u32,
+    /// URL the result of each file and the summary are posted to as JSON
+    pub webhook: Option<String>,
+    /// Command run for each archived file with placeholders for its paths and activity data
+    pub exec: Option<String>,
+    /// Command run once after all files with the paths of the archived files
+    pub exec_batch: Option<String>,
+    /// Secret key of minisign signing the checksum files and the manifest after each run
+    pub sign_key: Option<PathBuf>,
+    /// File the runs, archived files and errors are counted in, `None` to not count them
+    pub usage_stats: Option<PathBuf>,
+    /// Glob pattern of JSON exports completing the activity data
+    pub metadata_from_json: Option<String>,
+    /// Naming strategy of the sport of multisport activities
+    pub multisport_naming: MultisportNaming,
+    /// Offset in seconds added to the timestamps of all activities, i.e. to correct a wrong clock
+    pub time_offset: i64,
+    /// Maximum age in days of an activity relative to the modification time of its file, 0 to
+    /// skip the check
+    pub stale_after: u32,
+    /// Policy for activities with a suspicious timestamp
+    pub suspicious_time: SuspiciousTimePolicy,
+    /// Minimum length in seconds of a gap in the recording while the timer runs, 0 disables the
+    /// check
+    pub gap_threshold: u32,
+    /// Policy for activities with gaps in their recording
+    pub on_gaps: GapPolicy,
+    /// Only archive activities with GPS positions
+    pub require_gps: bool,
+    /// Only archive activities without GPS positions, i.e. trainer sessions
+    pub indoor_only: bool,
+    /// Start locations labeled by the user
+    pub start_locations: Vec<StartLocation>,
+    /// Radius of the start locations in meters
+    pub start_radius: f64,
+    /// Rules classifying the kind of the activities
+    pub kind_rules: Vec<KindRule>,
+    /// Reference routes matched by the $R tag
+    pub routes: Vec<Route>,
+    /// Maximum distance of the track of an activity to a reference route in meters
+    pub route_tolerance: f64,
+    /// Places resolving the start position for the $g and $G tags, the bundled places if unset
+    pub places: Option<PathBuf>,
+    /// Directories of the sports used with templates without sport tags
+    pub sport_directories: Vec<SportDirectory>,
+    /// Print the underlying cause of errors and the slowest files
+    pub verbose: bool,
+    /// Maintain the index of the archive, it is created if it does not exist
+    pub index: bool,
+    /// Skip files whose content is already archived at any path according to the index
+    pub skip_duplicates: bool,
+    /// Algorithm of the content hashes in the index
+    pub hash_algorithm: HashAlgorithm,
+    /// Append the archived files to the manifest of the archive
+    pub manifest: bool,
+    /// Record the checksums of the archive files in the checksum files of their directories
+    pub checksums: bool,
+    /// The archive directory must be on a mounted file system, i.e. a network share
+    pub require_mount: bool,
+    /// Maximum time in seconds to wait for the archive directory to become available
+    pub wait: u64,
+    /// User of a WebDAV archive directory, `None` for the environment variable
+    pub webdav_user: Option<String>,
+    /// Password of a WebDAV archive directory, `None` for the environment variable
+    pub webdav_password: Option<String>,
+    /// Search directories given in the list of files recursively
+    pub recursive: bool,
+    /// Maximum depth of recursively searched directories, `None` for no limit
+    pub max_depth: Option<usize>,
+    /// Additional extensions of FIT files in searched directories, i.e. 'fit.bak'
+    pub extensions: Vec<String>,
+    /// Archive files of searched directories with other extensions starting with a FIT header
+    pub sniff: bool,
+    /// Keep running and archive new files of the directories in the list of files
+    pub watch: bool,
+    /// Time in seconds between two polls of the watched directories
+    pub poll_interval: u64,
+    /// Named directories watched with their own template and filters
+    pub watch_sources: Vec<WatchSource>,
+    /// Unix domain socket reporting the status of the watch mode and accepting commands
+    pub control_socket: Option<PathBuf>,
+    /// Address of the HTTP endpoint serving Prometheus metrics of the watch mode
+    pub metrics: Option<String>,
+    /// Number of files parsed and archived in parallel
+    pub jobs: usize,
+    /// Maximum number of files parsed and archived together, 0 for no limit
+    pub batch_size: usize,
+    /// Write a GPX file with the track of each archived file
+    pub export_gpx: bool,
+    /// Format template that defines the path of exported files, `None` to write them next to
+    /// the archive files
+    pub export_template: Option<String>,
+    /// Archive the activity folders of the devices connected as mass storage
+    pub from_device: bool,
+    /// Archive the source files that failed to be archived in previous runs
+    pub retry_failed: bool,
+    /// List of FIT files or directories to archive
+    pub files: Vec<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            directory: PathBuf::from("."),
+            file_template: String::from(DEFAULT_FILE_TEMPLATE),
+            template_lint: true,
+            device_state_template: String::from(DEFAULT_DEVICE_STATE_TEMPLATE),
+            timezone: Timezone::default(),
+            time: TimeMode::default(),
+            variables: Vec::new(),
+            move_files: false,
+            readonly_source: false,
+            delete_sources: SourceDeletion::default(),
+            dry_run: false,
+            output: OutputFormat::default(),
+            print_path: false,
+            duplicate_policy: DuplicatePolicy::default(),
+            duplicate_window: 60,
+            on_conflict: ConflictPolicy::default(),
+            conflict_resolver: None,
+            on_invalid: InvalidPolicy::default(),
+            layout: StorageLayout::default(),
+            git: None,
+            upload: None,
+            upload_daily_limit: DEFAULT_UPLOAD_DAILY_LIMIT,
+            webhook: None,
+            exec: None,
+            exec_batch: None,
+            sign_key: None,
+            usage_stats: None,
+            metadata_from_json: None,
+            multisport_naming: MultisportNaming::default(),
+            time_offset: 0,
+            stale_after: 0,
+            suspicious_time: SuspiciousTimePolicy::default(),
+            gap_threshold: DEFAULT_GAP_THRESHOLD,
+            on_gaps: GapPolicy::default(),
+            require_gps: false,
+            indoor_only: false,
+            start_locations: Vec::new(),
+            kind_rules: Vec::new(),
+            routes: Vec::new(),
+            route_tolerance: 50.0,
+            places: None,
+            sport_directories: Vec::new(),
+            start_radius: 500.0,
+            verbose: false,
+            index: false,
+            skip_duplicates: false,
+            hash_algorithm: HashAlgorithm::Sha256,
+            manifest: false,
+            checksums: false,
+            require_mount: false,
+            wait: 0,
+            webdav_user: None,
+            webdav_password: None,
+            recursive: false,
+            max_depth: None,
+            extensions: Vec::new(),
+            sniff: false,
+            watch: false,
+            poll_interval: 2,
+            watch_sources: Vec::new(),
+            control_socket: None,
+            metrics: None,
+            jobs: 1,
+            batch_size: DEFAULT_BATCH_SIZE,
+            export_gpx: false,
+            export_template: None,
+            from_device: false,
+            retry_failed: false,
+            files: Vec::new(),
+        }
+    }
+}
+
+/// Create directory for archive file.
+///
 /// # Arguments
 ///
 /// `archive_path` - Path to the archive file.
-/// `options` - Command line options.
-fn create_archive_directory(archive_path: &Path, options: &clap::ArgMatches) -> Result<String> {
+/// `options` - Archiver options.
+pub(crate) fn create_archive_directory(archive_path: &Path, options: &Options) -> Result<String> {
     // check if destination exists and is a directory, create it if needed
     match archive_path.parent() {
         Some(parent) => match fs::metadata(parent) {
@@ -328,15 +1660,15 @@ fn create_archive_directory(archive_path: &Path, options: &clap::ArgMatches) ->
                 }
             }
             Err(_) => {
-                if !options.get_flag("dry-run") {
-                    match fs::create_dir_all(&parent) {
+                if !options.dry_run {
+                    match fs::create_dir_all(parent) {
                         Ok(_) => (),
-                        Err(_) => {
+                        Err(err) => {
                             let msg = format!(
                                 "Unable to create archive directory '{}'",
                                 parent.display()
                             );
-                            return Err(ArchiverError::new(&msg));
+                            return Err(ArchiverError::io(&msg, err));
                         }
                     }
                 }
@@ -353,448 +1685,3622 @@ fn create_archive_directory(archive_path: &Path, options: &clap::ArgMatches) ->
     Ok(String::from("OK"))
 }
 
-/// Move or copy files
+/// Returns true if both files have the same content
+///
+/// # Arguments
+///
+/// `first_path` - Path to the first file.
+/// `second_path` - Path to the second file.
+pub(crate) fn same_content(first_path: &Path, second_path: &Path) -> io::Result<bool> {
+    if fs::metadata(first_path)?.len() != fs::metadata(second_path)?.len() {
+        return Ok(false);
+    }
+
+    let mut first = BufReader::new(File::open(first_path)?);
+    let mut second = BufReader::new(File::open(second_path)?);
+    loop {
+        let first_buffer = first.fill_buf()?;
+        let second_buffer = second.fill_buf()?;
+        let length = first_buffer.len().min(second_buffer.len());
+        if length == 0 {
+            return Ok(first_buffer.is_empty() && second_buffer.is_empty());
+        }
+        if first_buffer[..length] != second_buffer[..length] {
+            return Ok(false);
+        }
+        first.consume(length);
+        second.consume(length);
+    }
+}
+
+/// Returns true if both paths refer to the same file
+///
+/// # Arguments
+///
+/// `first_path` - Path to the first file.
+/// `second_path` - Path to the second file.
+pub(crate) fn same_file(first_path: &Path, second_path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        if let (Ok(first), Ok(second)) = (fs::metadata(first_path), fs::metadata(second_path)) {
+            return first.dev() == second.dev() && first.ino() == second.ino();
+        }
+    }
+
+    match (fs::canonicalize(first_path), fs::canonicalize(second_path)) {
+        (Ok(first), Ok(second)) => first == second,
+        _ => false,
+    }
+}
+
+/// Returns true if the source file is newer than the archive file and has a different content
+///
+/// # Arguments
+///
+/// `source_path` - Path to the source file.
+/// `archive_path` - Path to the archive file.
+fn is_newer(source_path: &Path, archive_path: &Path) -> Result<bool> {
+    let modified = |path: &Path| match fs::metadata(path).and_then(|m| m.modified()) {
+        Ok(val) => Ok(val),
+        Err(err) => {
+            let msg = format!("Unable to get modification time of '{}'", path.display());
+            Err(ArchiverError::io(&msg, err))
+        }
+    };
+
+    if modified(source_path)? <= modified(archive_path)? {
+        return Ok(false);
+    }
+
+    match same_content(source_path, archive_path) {
+        Ok(val) => Ok(!val),
+        Err(err) => {
+            let msg = format!(
+                "Unable to compare '{}' with '{}'",
+                source_path.display(),
+                archive_path.display()
+            );
+            Err(ArchiverError::io(&msg, err))
+        }
+    }
+}
+
+/// Returns the status of a file conflicting with an existing file or `None` if the existing
+/// file is overwritten
+///
+/// # Arguments
+///
+/// `source_path` - Path to the source file.
+/// `existing_path` - Path to the existing file.
+/// `options` - Archiver options.
+fn resolve_conflict(
+    source_path: &Path,
+    existing_path: &Path,
+    options: &Options,
+) -> Result<Option<Status>> {
+    // an identical archive file means the file has been archived before
+    let identical = match same_content(source_path, existing_path) {
+        Ok(val) => val,
+        Err(err) => {
+            let msg = format!(
+                "Unable to compare '{}' with '{}'",
+                source_path.display(),
+                existing_path.display()
+            );
+            return Err(ArchiverError::io(&msg, err));
+        }
+    };
+    if identical {
+        return Ok(Some(Status::Identical));
+    }
+
+    match options.on_conflict {
+        ConflictPolicy::Overwrite => Ok(None),
+        ConflictPolicy::Skip => Ok(Some(Status::Skipped)),
+        ConflictPolicy::OverwriteIfNewer => {
+            if is_newer(source_path, existing_path)? {
+                Ok(None)
+            } else {
+                Ok(Some(Status::Skipped))
+            }
+        }
+    }
+}
+
+/// Returns true if a file starts with the header of a FIT file
+///
+/// Empty files and files shorter than a header are no FIT files.
+///
+/// # Arguments
+///
+/// `path` - Path to the file.
+fn has_fit_header(path: &Path) -> io::Result<bool> {
+    let mut header = [0u8; 12];
+    let mut file = File::open(path)?;
+    match io::Read::read_exact(&mut file, &mut header) {
+        Ok(_) => Ok(matches!(header[0], 12 | 14) && &header[8..12] == b".FIT"),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns the error reported for a file that is not a FIT file
+///
+/// # Arguments
+///
+/// `path` - Path to the file.
+fn not_fit_file(path: &Path) -> ArchiverError {
+    ArchiverError::new(&format!("'{}' is not a FIT file", path.display()))
+}
+
+/// Archive a file that is not a FIT file in the quarantine directory
+///
+/// # Arguments
+///
+/// `source_path` - Path to the source file.
+/// `options` - Archiver options.
+/// `storage` - Storage backend of the archive.
+fn quarantine_file(
+    source_path: &Path,
+    options: &Options,
+    storage: &dyn StorageBackend,
+) -> Result<(PathBuf, Status)> {
+    let archive_path = quarantine_path(&options.directory, source_path);
+    storage.create_archive_directory(&archive_path, options)?;
+    let status = storage.archive_file(source_path, &archive_path, options)?;
+    Ok((archive_path, status))
+}
+
+/// Simulate moving or copying files in a dry run
+///
+/// The files planned by earlier files of the same run are taken into account, so that conflicts
+/// between them are reported like in a real run.
 ///
 /// # Arguments
 ///
 /// `source_path` - Path to the source file.
 /// `archive_path` - Path to the archive file.
-/// `options` - Command line options.
-fn archive_file(
+/// `options` - Archiver options.
+/// `overlay` - Directories and files planned by the dry run.
+pub(crate) fn simulate_archive_file(
     source_path: &Path,
     archive_path: &Path,
-    options: &clap::ArgMatches,
-) -> Result<String> {
-    let mut msg = format!(
-        "'{}' -> '{}' ... ",
-        source_path.display(),
-        archive_path.display()
-    );
-    if !options.get_flag("dry-run") {
-        match fs::copy(source_path, &archive_path) {
-            Ok(_) => {
-                if options.get_flag("move") {
-                    match fs::remove_file(source_path) {
-                        Ok(_) => {
-                            msg.push_str("moved");
-                        }
-                        Err(_) => {
-                            let msg = format!("Unable to remove file '{}'", source_path.display());
-                            return Err(ArchiverError::new(&msg));
-                        }
-                    }
-                } else {
-                    msg.push_str("copied");
-                }
+    options: &Options,
+    overlay: &mut Overlay,
+) -> Result<Status> {
+    overlay.create_archive_directory(archive_path)?;
+    match overlay.planned_source(archive_path).map(Path::to_path_buf) {
+        Some(planned_path) => {
+            if let Some(status) = resolve_conflict(source_path, &planned_path, options)? {
+                return Ok(status);
             }
-            Err(_) => {
-                let msg = format!("Unable to create file '{}'", archive_path.display());
-                return Err(ArchiverError::new(&msg));
+        }
+        None => {
+            let status = archive_file(source_path, archive_path, options)?;
+            if status != Status::DryRun {
+                return Ok(status);
             }
+        }
+    }
+    overlay.add_file(source_path, archive_path)?;
+    Ok(Status::DryRun)
+}
+
+/// Returns true if archiving a file removes its source file immediately
+///
+/// # Arguments
+///
+/// `options` - Archiver options.
+pub(crate) fn removes_source(options: &Options) -> bool {
+    options.move_files && !options.dry_run && options.delete_sources == SourceDeletion::Immediately
+}
+
+/// Check that archiving cannot modify anything under the source paths
+///
+/// Files are only moved if the source is writable. The archive directory must neither be
+/// located under a source path nor contain one, otherwise archive files might replace source
+/// files.
+///
+/// # Arguments
+///
+/// `options` - Archiver options.
+fn check_readonly_source(options: &Options) -> Result<()> {
+    if options.move_files {
+        return Err(ArchiverError::new(
+            "Unable to move files from a read-only source",
+        ));
+    }
+    let absolute = |path: &Path| {
+        std::path::absolute(path).map_err(|err| {
+            let msg = format!("Unable to resolve path '{}'", path.display());
+            ArchiverError::io(&msg, err)
+        })
+    };
+    let directory = absolute(&options.directory)?;
+    for path in &options.files {
+        let source = absolute(path)?;
+        let source = if source.is_dir() {
+            source.as_path()
+        } else {
+            source.parent().unwrap_or(&source)
         };
-    } else {
-        msg.push_str("dry run");
+        if directory.starts_with(source) || source.starts_with(&directory) {
+            let msg = format!(
+                "Archive directory '{}' must be separate from the read-only source '{}'",
+                options.directory.display(),
+                path.display()
+            );
+            return Err(ArchiverError::new(&msg));
+        }
     }
-    Ok(msg)
+    Ok(())
 }
 
-/// Process all FIT files
+/// Verify that the archive file has the same content as the source file
+///
+/// # Arguments
+///
+/// `source_path` - Path to the source file.
+/// `archive_path` - Path to the archive file.
+fn verify_file(source_path: &Path, archive_path: &Path) -> Result<()> {
+    match same_content(source_path, archive_path) {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            let msg = format!(
+                "Verification of '{}' failed, it differs from '{}'",
+                archive_path.display(),
+                source_path.display()
+            );
+            Err(ArchiverError::new(&msg))
+        }
+        Err(err) => {
+            let msg = format!("Unable to verify '{}'", archive_path.display());
+            Err(ArchiverError::io(&msg, err))
+        }
+    }
+}
+
+/// Returns an archive file at another path with the same content as the source file
+///
+/// # Arguments
+///
+/// `source_path` - Path to the source file.
+/// `archive_path` - Path to the archive file.
+/// `index` - Index of the archive.
+fn archived_copy(
+    source_path: &Path,
+    archive_path: &Path,
+    index: &Index,
+) -> Result<Option<PathBuf>> {
+    let hash = index.file_hash(source_path).map_err(|err| {
+        let msg = format!("Unable to read file '{}'", source_path.display());
+        ArchiverError::io(&msg, err)
+    })?;
+    Ok(index
+        .hash_paths(&hash)?
+        .into_iter()
+        .find(|path| path != archive_path && path.exists()))
+}
+
+/// Returns the results of a function applied to each item using up to `jobs` threads
+///
+/// The threads take the next item as soon as they are done with the previous one, so a few large
+/// files do not stall the others. The results are returned in the order of the items.
 ///
 /// # Arguments
 ///
-/// `options` - Command line options.
-pub fn process_files(options: &clap::ArgMatches) -> Result<String> {
-    let mut file_counter: u16 = 0;
-    let mut error_counter: u16 = 0;
+/// `items` - Items to process.
+/// `jobs` - Maximum number of threads.
+/// `function` - Function applied to each item.
+pub(crate) fn parallel_map<'a, T, R, F>(items: &'a [T], jobs: usize, function: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&'a T) -> R + Sync,
+{
+    if jobs <= 1 || items.len() <= 1 {
+        return items.iter().map(function).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.min(items.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(i) else {
+                            break results;
+                        };
+                        results.push((i, function(item)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("worker thread panicked"))
+            .collect()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
 
-    let base_directory = Path::new(options.get_one::<String>("directory").unwrap().as_str());
-    let files: Vec<&str> = options
-        .get_many::<String>("files")
-        .unwrap()
-        .map(|s| s.as_str())
+/// Result of archiving a file and the time it took
+type ArchiveResult = (Result<Result<Status>>, Duration);
+
+/// Archive the files of a batch that are independent of each other in parallel
+///
+/// A file depends on an earlier file of the batch with the same archive path, as its conflict
+/// is resolved against the earlier one, and with `--skip-duplicates` on an earlier file with the
+/// same content. These files and files with a copy in the archive are left out and archived one
+/// after another when the results are reported. Returns the result for each archived file.
+///
+/// # Arguments
+///
+/// `parsed` - Activity data of the files.
+/// `skipped` - Files skipped as duplicates or by the position filters.
+/// `index` - Index of the archive.
+/// `storage` - Storage backend of the archive.
+/// `options` - Archiver options.
+fn archive_files_parallel(
+    parsed: &[Result<ActivityData>],
+    skipped: &[bool],
+    index: Option<&Index>,
+    storage: &dyn StorageBackend,
+    options: &Options,
+) -> Vec<Option<ArchiveResult>> {
+    let mut candidates: Vec<(usize, &Path, PathBuf)> = options
+        .files
+        .iter()
+        .zip(parsed)
+        .zip(skipped)
+        .enumerate()
+        .filter_map(|(i, ((source_path, parsed), skip))| match (parsed, skip) {
+            (Ok(activity_data), false) => Some((
+                i,
+                source_path.as_path(),
+                destination_path(source_path, activity_data, options),
+            )),
+            _ => None,
+        })
         .collect();
 
-    for file in files {
-        let source_path = Path::new(file);
-        match parse_fit_file(source_path) {
-            Ok(val) => {
-                let archive_path = base_directory
-                    .join(expand_formatstring(
-                        options.get_one::<String>("file-template").unwrap().as_str(),
-                        &val,
-                    ))
-                    .with_extension("fit");
-
-                match create_archive_directory(&archive_path, options) {
-                    Ok(_) => match archive_file(source_path, &archive_path, options) {
-                        Ok(msg) => {
-                            println!("{}", msg);
-                            file_counter += 1;
-                        }
-                        Err(msg) => {
-                            eprintln!("{}", msg);
-                            error_counter += 1;
-                        }
-                    },
-                    Err(e) => return Err(e),
+    let mut destinations = HashSet::new();
+    candidates.retain(|(_, _, archive_path)| destinations.insert(archive_path.clone()));
+
+    if let (Some(index), true) = (index, options.skip_duplicates) {
+        let hashes = parallel_map(&candidates, options.jobs, |(_, source_path, _)| {
+            options.hash_algorithm.file_hash(source_path).ok()
+        });
+        let mut contents = HashSet::new();
+        let mut independent = hashes.into_iter().map(|hash| match hash {
+            Some(hash) => {
+                contents.insert(hash.clone())
+                    && index.hash_paths(&hash).is_ok_and(|paths| paths.is_empty())
+            }
+            None => false,
+        });
+        candidates.retain(|_| independent.next().unwrap_or(false));
+    }
+
+    let archived = parallel_map(
+        &candidates,
+        options.jobs,
+        |(_, source_path, archive_path)| {
+            let started = Instant::now();
+            let archived = storage
+                .create_archive_directory(archive_path, options)
+                .map(|_| storage.archive_file(source_path, archive_path, options));
+            (archived, started.elapsed())
+        },
+    );
+
+    let mut results: Vec<Option<ArchiveResult>> = Vec::new();
+    results.resize_with(options.files.len(), || None);
+    for ((i, _, _), archived) in candidates.iter().zip(archived) {
+        results[*i] = Some(archived);
+    }
+    results
+}
+
+/// Delete the source files of a batch that has been moved to the archive
+///
+/// The source files are only deleted if no file of the batch failed to be archived or verified,
+/// or with `SourceDeletion::AfterCleanBatch` if the batch had no errors at all. Otherwise all of
+/// them are kept. Returns the number of errors and the number of kept files.
+///
+/// # Arguments
+///
+/// `sources` - Source files archived in the batch.
+/// `batch_errors` - Number of errors in the batch.
+/// `archive_errors` - Number of files of the batch that failed to be archived or verified.
+/// `options` - Archiver options.
+fn delete_sources(
+    sources: &[&Path],
+    batch_errors: usize,
+    archive_errors: usize,
+    options: &Options,
+) -> (usize, usize) {
+    let errors = match options.delete_sources {
+        SourceDeletion::AfterCleanBatch => batch_errors,
+        _ => archive_errors,
+    };
+    if errors > 0 {
+        eprintln!(
+            "Keeping {} source files because of errors in the batch",
+            sources.len()
+        );
+        return (0, sources.len());
+    }
+
+    let mut error_counter = 0;
+    for source_path in sources {
+        if let Err(err) = fs::remove_file(source_path) {
+            let msg = format!("Unable to remove file '{}'", source_path.display());
+            print_error(&ArchiverError::io(&msg, err), options);
+            error_counter += 1;
+        }
+    }
+    (error_counter, 0)
+}
+
+/// Move or copy files
+///
+/// # Arguments
+///
+/// `source_path` - Path to the source file.
+/// `archive_path` - Path to the archive file.
+/// `options` - Archiver options.
+pub(crate) fn archive_file(
+    source_path: &Path,
+    archive_path: &Path,
+    options: &Options,
+) -> Result<Status> {
+    // resolve conflicts with an existing archive file
+    if archive_path.exists() {
+        // the source is already the archive file, i.e. when processing the archive itself
+        if same_file(source_path, archive_path) {
+            return Ok(Status::Identical);
+        }
+
+        match resolve_conflict(source_path, archive_path, options)? {
+            Some(Status::Identical) => {
+                if removes_source(options) {
+                    if let Err(err) = fs::remove_file(source_path) {
+                        let msg = format!("Unable to remove file '{}'", source_path.display());
+                        return Err(ArchiverError::io(&msg, err));
+                    }
                 }
+                return Ok(Status::Identical);
             }
-            Err(msg) => eprintln!("{}", msg),
-        };
+            Some(status) => return Ok(status),
+            // the replaced archive file is kept as backup
+            None if !options.dry_run => {
+                backup_file(&options.directory, archive_path, Utc::now())?;
+            }
+            None => (),
+        }
     }
 
-    let msg = format!("Processed {} files", file_counter);
-    let err = if error_counter == 0 {
-        String::new()
+    if !options.dry_run {
+        let result = match options.layout {
+            StorageLayout::Plain => match fs::copy(source_path, archive_path) {
+                Ok(_) => Ok(()),
+                Err(err) => {
+                    let msg = format!("Unable to create file '{}'", archive_path.display());
+                    Err(ArchiverError::io(&msg, err))
+                }
+            },
+            layout => store_object(&options.directory, source_path, archive_path, layout),
+        };
+        match result {
+            Ok(_) if options.move_files => {
+                verify_file(source_path, archive_path)?;
+                if removes_source(options) {
+                    if let Err(err) = fs::remove_file(source_path) {
+                        let msg = format!("Unable to remove file '{}'", source_path.display());
+                        return Err(ArchiverError::io(&msg, err));
+                    }
+                }
+                Ok(Status::Moved)
+            }
+            Ok(_) => Ok(Status::Copied),
+            Err(err) => Err(err),
+        }
     } else {
-        format!("with {} errors.", error_counter)
-    };
+        Ok(Status::DryRun)
+    }
+}
+
+/// Returns a path with tab, newline and backslash characters escaped
+///
+/// # Arguments
+///
+/// `path` - Path to escape.
+pub(crate) fn escape_path(path: &Path) -> String {
+    path.display()
+        .to_string()
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+/// Number of the slowest files reported in verbose mode
+const SLOWEST_FILES: usize = 5;
+
+/// Number of archived files whose index entries are committed together
+const INDEX_COMMIT_INTERVAL: usize = 100;
+
+/// Time spent on processing a single file
+#[derive(Debug)]
+struct FileTiming<'a> {
+    /// Path to the source file
+    source_path: &'a Path,
+    /// Duration of parsing the file
+    parse: Duration,
+    /// Duration of creating the directory and copying or moving the file
+    copy: Duration,
+}
+
+/// Returns a report of the slowest files ordered by their total processing time
+///
+/// # Arguments
+///
+/// `timings` - Time spent on processing each file.
+/// `count` - Maximum number of files in the report.
+fn timing_report(timings: &[FileTiming], count: usize) -> String {
+    let mut slowest: Vec<&FileTiming> = timings.iter().collect();
+    slowest.sort_by_key(|timing| std::cmp::Reverse(timing.parse + timing.copy));
 
-    Ok([msg, err].join(" "))
+    let mut lines = vec![format!(
+        "Slowest {} of {} files:",
+        count.min(timings.len()),
+        timings.len()
+    )];
+    for timing in slowest.iter().take(count) {
+        lines.push(format!(
+            "  {:.3}s (parse {:.3}s, copy {:.3}s) '{}'",
+            (timing.parse + timing.copy).as_secs_f64(),
+            timing.parse.as_secs_f64(),
+            timing.copy.as_secs_f64(),
+            timing.source_path.display()
+        ));
+    }
+    lines.join("\n")
 }
 
-#[cfg(test)]
-mod tests {
-    use chrono::TimeZone;
-    use std::fs::{self, File};
-    use std::path::PathBuf;
-    use tempdir::TempDir;
+/// Returns the activity data of a file as JSON object
+///
+/// # Arguments
+///
+/// `activity_data` - Activity data of the file.
+fn activity_json(activity_data: &ActivityData) -> serde_json::Value {
+    serde_json::json!({
+        "sport": activity_data.sport,
+        "sport_name": activity_data.sport_name,
+        "sub_sport": activity_data.sub_sport,
+        "legs": activity_data.legs,
+        "sport_code": activity_data.sport_code,
+        "sub_sport_code": activity_data.sub_sport_code,
+        "workout_name": activity_data.workout_name,
+        "workout_steps": activity_data.workout_steps,
+        "workout_structure": activity_data.workout_structure,
+        "timestamp": activity_data.timestamp.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        "begin": activity_data.begin().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        "end": activity_data.end().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        "manufacturer": activity_data.manufacturer,
+        "product": activity_data.product,
+        "serial_number": activity_data.serial_number,
+        "record_count": activity_data.record_count,
+        "position_count": activity_data.position_count,
+        "duration": activity_data.duration,
+        "distance": activity_data.distance,
+        "heart_rate": activity_data.heart_rate,
+        "notes": activity_data.notes,
+        "day_sequence": activity_data.day_sequence,
+        "start_position": activity_data.start_position.map(|(latitude, longitude)| {
+            serde_json::json!({"latitude": latitude, "longitude": longitude})
+        }),
+        "start_location": activity_data.start_location,
+        "route": activity_data.route,
+        "city": activity_data.city,
+        "country": activity_data.country,
+        "kind": activity_data.kind,
+        "suspicious_time": activity_data.suspicious_time,
+        "longest_gap": activity_data.longest_gap,
+        "has_gaps": activity_data.has_gaps,
+    })
+}
+
+/// Print the result of processing a single file
+///
+/// The errors that occurred while processing the file are printed before the result, in JSON
+/// output they are part of the object of the file. The object is posted to the webhook as well.
+///
+/// # Arguments
+///
+/// `source_path` - Path to the source file.
+/// `archive_path` - Path to the archive file if it could be determined.
+/// `status` - Result of processing the file.
+/// `activity_data` - Activity data of the file if it could be parsed.
+/// `errors` - Errors that occurred while processing the file.
+/// `options` - Archiver options.
+fn print_status(
+    source_path: &Path,
+    archive_path: Option<&Path>,
+    status: Status,
+    activity_data: Option<&ActivityData>,
+    errors: &[ArchiverError],
+    options: &Options,
+) {
+    let object = || {
+        serde_json::json!({
+            "type": "file",
+            "source": source_path,
+            "archive": archive_path,
+            "action": status.action(),
+            "activity": activity_data.map(activity_json),
+            "errors": errors.iter().map(|err| err.details()).collect::<Vec<_>>(),
+        })
+    };
+    notify_webhook(object, options);
+    if options.output == OutputFormat::Json {
+        println!("{}", object());
+        return;
+    }
+
+    for err in errors {
+        print_error(err, options);
+    }
+    match options.output {
+        OutputFormat::Text => {
+            if let Some(archive_path) = archive_path {
+                println!(
+                    "'{}' -> '{}' ... {}",
+                    source_path.display(),
+                    archive_path.display(),
+                    status.text()
+                );
+            }
+        }
+        OutputFormat::Porcelain => {
+            println!(
+                "{}\t{}\t{}",
+                status.porcelain(),
+                escape_path(source_path),
+                archive_path.map(escape_path).unwrap_or_default()
+            );
+        }
+        OutputFormat::Print0 => {
+            if let (Some(archive_path), false) = (
+                archive_path,
+                matches!(status, Status::Error | Status::Skipped),
+            ) {
+                print_path(archive_path, b'\0');
+            }
+        }
+        // the archived files are reported by directory at the end of the run
+        OutputFormat::Json | OutputFormat::Grouped => (),
+    }
+}
+
+/// Post an object to the webhook, nothing is posted in a dry run
+///
+/// # Arguments
+///
+/// `object` - Returns the object to post.
+/// `options` - Archiver options.
+fn notify_webhook(object: impl FnOnce() -> serde_json::Value, options: &Options) {
+    if let (Some(url), false) = (&options.webhook, options.dry_run || options.print_path) {
+        if let Err(err) = post_webhook(url, &object()) {
+            print_error(&err, options);
+        }
+    }
+}
+
+/// Print an error, including the underlying cause in verbose mode
+///
+/// # Arguments
+///
+/// `err` - Error to print.
+/// `options` - Archiver options.
+fn print_error(err: &ArchiverError, options: &Options) {
+    if options.output == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::json!({"type": "error", "error": err.details()})
+        );
+    } else if options.verbose {
+        eprintln!("{}", err.details());
+    } else {
+        eprintln!("{}", err);
+    }
+}
+
+/// Print a path unmodified followed by a separator
+///
+/// # Arguments
+///
+/// `path` - Path to print.
+/// `separator` - Separator printed after the path.
+fn print_path(path: &Path, separator: u8) {
+    let mut stdout = io::stdout().lock();
+    let _ = stdout.write_all(path.as_os_str().as_encoded_bytes());
+    let _ = stdout.write_all(&[separator]);
+}
+
+/// Returns the template-derived path of an archive file
+///
+/// Templates without sport tags get the sport separation from the sport directories.
+///
+/// # Arguments
+///
+/// * `base_directory` - Base directory of the archive.
+/// * `formatstring` - A format string containing '%' and '$' tags.
+/// * `sport_directories` - Directories of the sports.
+/// * `activity_data` - Data that will be used for expansion of the tags.
+pub(crate) fn template_path(
+    base_directory: &Path,
+    formatstring: &str,
+    sport_directories: &[SportDirectory],
+    activity_data: &ActivityData,
+) -> PathBuf {
+    let directory = match has_sport_tag(formatstring) {
+        true => None,
+        false => sport_directory(
+            &activity_data.sport,
+            &activity_data.sub_sport,
+            sport_directories,
+        ),
+    };
+    match directory {
+        Some(directory) => {
+            archive_path(&base_directory.join(directory), formatstring, activity_data)
+        }
+        None => archive_path(base_directory, formatstring, activity_data),
+    }
+}
+
+/// Returns the path of the archive file, files with a suspicious timestamp or gaps may be
+/// quarantined and device state files are archived apart from the activities
+///
+/// # Arguments
+///
+/// `source_path` - Path to the source file.
+/// `activity_data` - Activity data of the source file.
+/// `options` - Archiver options.
+fn destination_path(
+    source_path: &Path,
+    activity_data: &ActivityData,
+    options: &Options,
+) -> PathBuf {
+    match (activity_data.suspicious_time, options.suspicious_time) {
+        (true, SuspiciousTimePolicy::Quarantine) => {
+            quarantine_path(&options.directory, source_path)
+        }
+        _ if activity_data.has_gaps && options.on_gaps == GapPolicy::Quarantine => {
+            quarantine_path(&options.directory, source_path)
+        }
+        _ if is_device_state(activity_data) => archive_path(
+            &options.directory,
+            &options.device_state_template,
+            activity_data,
+        ),
+        _ => template_path(
+            &options.directory,
+            &options.file_template,
+            &options.sport_directories,
+            activity_data,
+        ),
+    }
+}
+
+/// Check the timestamps of the activities and warn about suspicious ones
+///
+/// # Arguments
+///
+/// `parsed` - Activity data of the files to archive.
+/// `options` - Archiver options.
+fn check_timestamps(parsed: &mut [Result<ActivityData>], options: &Options) {
+    let now = Utc::now();
+    for (source_path, parsed) in options.files.iter().zip(parsed.iter_mut()) {
+        let Ok(activity_data) = parsed else {
+            continue;
+        };
+        // device state files without updates keep the time they were created years ago
+        if is_device_state(activity_data) {
+            continue;
+        }
+        let modified = fs::metadata(source_path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .map(DateTime::<Utc>::from);
+        if let Some(reason) =
+            suspicious_timestamp(activity_data.timestamp, modified, now, options.stale_after)
+        {
+            eprintln!(
+                "Timestamp {} of '{}' {}. Check the clock of the device or use '--time-offset'!",
+                activity_data.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                source_path.display(),
+                reason
+            );
+            activity_data.suspicious_time = true;
+        }
+    }
+}
+
+/// Flag the activities with gaps in their recording and warn about them
+///
+/// # Arguments
+///
+/// `parsed` - Activity data of the files to archive.
+/// `options` - Archiver options.
+fn check_gaps(parsed: &mut [Result<ActivityData>], options: &Options) {
+    for (source_path, parsed) in options.files.iter().zip(parsed.iter_mut()) {
+        let Ok(activity_data) = parsed else {
+            continue;
+        };
+        activity_data.has_gaps = options.gap_threshold > 0
+            && activity_data
+                .longest_gap
+                .is_some_and(|gap| gap >= options.gap_threshold);
+        if let (true, Some(gap)) = (activity_data.has_gaps, activity_data.longest_gap) {
+            eprintln!(
+                "Recording of '{}' has a gap of {} while the timer was running. Check it for a tunnel or a failed auto pause!",
+                source_path.display(),
+                format_gap(gap)
+            );
+        }
+    }
+}
+
+/// Returns the size of all files that will be written to the archive in bytes
+///
+/// Files that are skipped or whose archive file already exists are not taken into account.
+/// Existing archive files are either kept or overwritten and need no additional space.
+///
+/// # Arguments
+///
+/// `parsed` - Activity data of the files to archive.
+/// `skipped` - Files skipped as duplicates or by the position filters.
+/// `options` - Archiver options.
+fn required_space(parsed: &[Result<ActivityData>], skipped: &[bool], options: &Options) -> u64 {
+    options
+        .files
+        .iter()
+        .zip(parsed)
+        .zip(skipped)
+        .filter(|(_, skip)| !**skip)
+        .filter_map(|((source_path, parsed), _)| {
+            let activity_data = parsed.as_ref().ok()?;
+            let archive_path = destination_path(source_path, activity_data, options);
+            if archive_path.exists() {
+                return None;
+            }
+            fs::metadata(source_path)
+                .ok()
+                .map(|metadata| metadata.len())
+        })
+        .sum()
+}
+
+/// Print warnings about a file template whose archive paths are likely to collide
+///
+/// # Arguments
+///
+/// `formatstring` - A format string containing '%' and '$' tags.
+pub(crate) fn print_template_warnings(formatstring: &str) {
+    for warning in lint_template(formatstring) {
+        eprintln!("{} Use '--no-template-lint' to hide this warning.", warning);
+    }
+}
+
+/// Numbers of files by result of processing them
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Summary {
+    /// Files archived, or planned to be archived in a dry run
+    pub(crate) processed: usize,
+    /// Files archived before with the same content
+    pub(crate) identical: usize,
+    /// Files skipped, i.e. duplicates
+    pub(crate) skipped: usize,
+    /// Files that are not FIT files
+    pub(crate) invalid: usize,
+    /// Damaged FIT files
+    pub(crate) damaged: usize,
+    /// Source files kept because of errors in their batch
+    pub(crate) kept: usize,
+    /// Errors that occurred
+    pub(crate) errors: usize,
+    /// Source and archive paths of the files archived by the batch command
+    pub(crate) archived: Vec<(PathBuf, PathBuf)>,
+    /// Size of the archived files in bytes
+    pub(crate) bytes: u64,
+    /// Number of archived files by destination directory relative to the archive directory
+    pub(crate) directories: BTreeMap<PathBuf, usize>,
+}
+
+impl Summary {
+    /// Add the numbers of another batch
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - Summary of the batch.
+    fn add(&mut self, other: &Summary) {
+        self.processed += other.processed;
+        self.identical += other.identical;
+        self.skipped += other.skipped;
+        self.invalid += other.invalid;
+        self.damaged += other.damaged;
+        self.kept += other.kept;
+        self.errors += other.errors;
+        self.archived.extend(other.archived.iter().cloned());
+        self.bytes += other.bytes;
+        for (directory, count) in &other.directories {
+            *self.directories.entry(directory.clone()).or_default() += count;
+        }
+    }
+
+    /// Returns the number of archived files per destination directory, one directory per line
+    fn grouped_report(&self) -> String {
+        self.directories
+            .iter()
+            .map(|(directory, count)| {
+                let directory = match directory.as_os_str().is_empty() {
+                    true => String::from("./"),
+                    false => format!("{}/", directory.display()),
+                };
+                let files = match count {
+                    1 => "file",
+                    _ => "files",
+                };
+                format!("{} \u{2014} {} {}\n", directory, count, files)
+            })
+            .collect()
+    }
+
+    /// Returns the human readable summary
+    fn message(&self) -> String {
+        let mut msg = format!("Processed {} files", self.processed);
+        if self.identical > 0 {
+            msg.push_str(&format!(", {} already archived", self.identical));
+        }
+        if self.skipped > 0 {
+            msg.push_str(&format!(", skipped {} files", self.skipped));
+        }
+        if self.invalid > 0 {
+            msg.push_str(&format!(", {} not FIT files", self.invalid));
+        }
+        if self.damaged > 0 {
+            msg.push_str(&format!(", {} damaged FIT files", self.damaged));
+        }
+        if self.kept > 0 {
+            msg.push_str(&format!(", kept {} source files", self.kept));
+        }
+        let err = if self.errors == 0 {
+            String::new()
+        } else {
+            format!("with {} errors.", self.errors)
+        };
+        [msg, err].join(" ")
+    }
+}
+
+/// Process all FIT files
+///
+/// All files are processed together by default. Only with a batch size other than 0 the files are
+/// processed in batches of that size, so the memory does not grow with the number of files.
+///
+/// # Arguments
+///
+/// `options` - Archiver options.
+pub fn process_files(options: &Options) -> Result<String> {
+    process_files_summary(options).map(|(msg, _)| msg)
+}
+
+/// Process all FIT files and return the summary together with its message
+///
+/// # Arguments
+///
+/// `options` - Archiver options.
+pub(crate) fn process_files_summary(options: &Options) -> Result<(String, Summary)> {
+    check_template(&options.file_template)?;
+    check_template(&options.device_state_template)?;
+    check_variables(&options.file_template, &options.variables)?;
+    check_variables(&options.device_state_template, &options.variables)?;
+    if options.template_lint {
+        print_template_warnings(&options.file_template);
+    }
+    if let Some(template) = &options.export_template {
+        check_template(template)?;
+        check_variables(template, &options.variables)?;
+    }
+    if options.readonly_source {
+        check_readonly_source(options)?;
+    }
+    let (storage, archive_options) = open_storage(options)?;
+
+    // detect an unmounted or offline archive directory before anything is created in it, i.e.
+    // the index read for '--retry-failed'
+    if !options.print_path {
+        storage.check_destination(&archive_options)?;
+    }
+
+    // the sources are searched relative to the archive directory given by the user
+    let options = &Options {
+        files: input_files(options)?,
+        ..archive_options
+    };
+
+    let batch_size = match options.batch_size {
+        0 => options.files.len().max(1),
+        size => size,
+    };
+    let mut summary = Summary::default();
+    // an empty list of files is processed like a batch, i.e. creating the archive
+    for start in (0..options.files.len().max(1)).step_by(batch_size) {
+        let end = options.files.len().min(start + batch_size);
+        let batch = Options {
+            files: options.files[start..end].to_vec(),
+            ..options.clone()
+        };
+        let result = process_batch(&batch, storage.as_ref());
+        if let Err(err) = &result {
+            // i.e. a full disk fails the whole batch
+            record_batch_failure(&batch, err);
+        }
+        summary.add(&result?);
+    }
+
+    // the batch command sees all files, i.e. to sync them to a backup at once
+    if let (Some(command), false) = (&options.exec_batch, summary.archived.is_empty()) {
+        if let Err(err) = run_exec_batch(command, &summary.archived) {
+            print_error(&err, options);
+            summary.errors += 1;
+        }
+    }
+    // the checksums and the manifest of all batches are signed together
+    if let (Some(key), false) = (&options.sign_key, options.dry_run || options.print_path) {
+        if let Err(err) = sign_archive(&options.directory, key) {
+            print_error(&err, options);
+            summary.errors += 1;
+        }
+    }
+
+    // only runs archiving files are counted, never a dry run
+    if let (Some(path), false) = (&options.usage_stats, options.dry_run || options.print_path) {
+        if let Err(err) = record_run(path, &summary, Utc::now()) {
+            print_error(&err, options);
+        }
+    }
+    if options.output == OutputFormat::Grouped {
+        print!("{}", summary.grouped_report());
+    }
+    let msg = summary.message();
+
+    let object = || {
+        serde_json::json!({
+            "type": "summary",
+            "processed": summary.processed,
+            "identical": summary.identical,
+            "skipped": summary.skipped,
+            "invalid": summary.invalid,
+            "damaged": summary.damaged,
+            "kept": summary.kept,
+            "errors": summary.errors,
+            "message": msg.trim_end(),
+        })
+    };
+    notify_webhook(object, options);
+    if options.output == OutputFormat::Json {
+        println!("{}", object());
+    }
+
+    Ok((msg, summary))
+}
+
+/// Record the files of a failed batch in the index, so they are retried with `--retry-failed`
+///
+/// Recording is best effort, the index may be unreachable, i.e. on an offline network share.
+///
+/// # Arguments
+///
+/// `options` - Archiver options with the files of the batch.
+/// `err` - Error of the batch.
+fn record_batch_failure(options: &Options, err: &ArchiverError) {
+    if !options.index || options.dry_run || options.print_path {
+        return;
+    }
+    if let Ok(Some(index)) = Index::open(&options.directory, true) {
+        let reason = err.details();
+        let now = Utc::now();
+        let _ = index.transaction(|index| {
+            options
+                .files
+                .iter()
+                .try_for_each(|path| index.add_failure(path, &reason, now))
+        });
+    }
+}
+
+/// Process a batch of FIT files
+///
+/// All files of the batch are parsed first to detect activities recorded on several devices and
+/// to number the activities of a day, the source files of a moved batch are deleted together.
+///
+/// # Arguments
+///
+/// `options` - Archiver options with the files of the batch.
+/// `storage` - Storage backend of the archive.
+fn process_batch(options: &Options, storage: &dyn StorageBackend) -> Result<Summary> {
+    let mut file_counter: usize = 0;
+    let mut skip_counter: usize = 0;
+    let mut identical_counter: usize = 0;
+    let mut error_counter: usize = 0;
+    // errors of files that failed to be archived or verified keep the sources in move mode
+    let mut archive_errors: usize = 0;
+    let mut invalid_counter: usize = 0;
+    let mut damaged_counter: usize = 0;
+    let mut commit: Vec<(PathBuf, String)> = Vec::new();
+    let mut sources: Vec<&Path> = Vec::new();
+    let mut exec_paths: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let mut directories: BTreeMap<PathBuf, usize> = BTreeMap::new();
+    let mut bytes: u64 = 0;
+    let mut failed: Vec<(&Path, String)> = Vec::new();
+    let mut recovered: Vec<&Path> = Vec::new();
+    let routes = load_routes(&options.routes)?;
+    let places = load_places(options.places.as_deref())?;
+
+    // parse all files first to detect activities recorded on several devices
+    let (mut parsed, mut timings): (Vec<Result<ActivityData>>, Vec<FileTiming>) =
+        parallel_map(&options.files, options.jobs, |source_path| {
+            let started = Instant::now();
+            // the parser accepts empty files as files without messages
+            let parsed = match has_fit_header(source_path) {
+                Ok(false) => Err(not_fit_file(source_path)),
+                _ => parse_fit_file(source_path).map(|mut activity_data| {
+                    // the track is only needed for the route, it is not kept for the whole batch
+                    let track = std::mem::take(&mut activity_data.track);
+                    activity_data.route = match_route(&track, &routes, options.route_tolerance);
+                    (activity_data.city, activity_data.country) =
+                        geocode(&places, activity_data.start_position);
+                    activity_data.utc_offset = options.time.offset(
+                        &options.timezone,
+                        activity_data.timestamp,
+                        activity_data.device_offset,
+                    );
+                    activity_data.variables = options.variables.clone();
+                    activity_data
+                }),
+            };
+            let timing = FileTiming {
+                source_path,
+                parse: started.elapsed(),
+                copy: Duration::ZERO,
+            };
+            (parsed, timing)
+        })
+        .into_iter()
+        .unzip();
+
+    // shorten the joined sports of multisport activities
+    for activity_data in parsed.iter_mut().flatten() {
+        if activity_data.legs.len() > 1 {
+            if let Some(sport) = multisport_name(&activity_data.legs, options.multisport_naming) {
+                activity_data.sport = sport;
+            }
+        }
+    }
+
+    // correct the timestamps of a device with a wrong clock
+    if options.time_offset != 0 {
+        for activity_data in parsed.iter_mut().flatten() {
+            activity_data.timestamp += TimeDelta::seconds(options.time_offset);
+        }
+    }
+    check_timestamps(&mut parsed, options);
+    check_gaps(&mut parsed, options);
+
+    // complete the activity data with companion JSON exports
+    if let Some(pattern) = &options.metadata_from_json {
+        let metadata = load_metadata(pattern)?;
+        for (source_path, parsed) in options.files.iter().zip(parsed.iter_mut()) {
+            if let Ok(activity_data) = parsed {
+                enrich(&metadata, source_path, activity_data);
+            }
+        }
+    }
+
+    let activities: Vec<Option<&ActivityData>> = parsed.iter().map(|r| r.as_ref().ok()).collect();
+    let duplicates = find_duplicates(
+        &activities,
+        &options.duplicate_policy,
+        options.duplicate_window,
+    );
+
+    // activities not passing the position filters are skipped like duplicates
+    let skipped: Vec<bool> = activities
+        .iter()
+        .zip(duplicates)
+        .map(|(activity_data, duplicate)| {
+            duplicate
+                || activity_data
+                    .is_some_and(|activity_data| !activity_data.matches_position_filter(options))
+        })
+        .collect();
+
+    // a dry run only reads an existing index
+    let index = if options.index {
+        Index::open(&options.directory, !options.dry_run && !options.print_path)?.map(|index| {
+            index
+                .with_hash_algorithm(options.hash_algorithm)
+                .with_commit_interval(INDEX_COMMIT_INTERVAL)
+        })
+    } else {
+        None
+    };
+
+    // number the activities of a day across the files and the archive
+    let mut known = Vec::new();
+    if let Some(index) = &index {
+        // the index is queried by UTC days, a local day overlaps the adjacent ones
+        let mut days: Vec<NaiveDate> = activities
+            .iter()
+            .flatten()
+            .map(|activity_data| options.timezone.date(activity_data.timestamp))
+            .flat_map(|day| [day.pred_opt(), Some(day), day.succ_opt()])
+            .flatten()
+            .collect();
+        days.sort();
+        days.dedup();
+        for day in days {
+            known.extend(index.day_timestamps(day)?);
+        }
+    }
+    let sequences = day_sequences(&activities, &skipped, &known, &options.timezone);
+
+    // group the activities by the start locations of the files and the archive
+    let known_positions = match &index {
+        Some(index) => index.start_positions()?,
+        None => Vec::new(),
+    };
+    let locations = start_locations(
+        &activities,
+        &known_positions,
+        &options.start_locations,
+        options.start_radius,
+    );
+
+    for ((parsed, day_sequence), start_location) in parsed.iter_mut().zip(sequences).zip(locations)
+    {
+        if let Ok(activity_data) = parsed {
+            activity_data.day_sequence = day_sequence;
+            activity_data.start_location = start_location;
+            activity_data.kind = activity_kind(activity_data, &options.kind_rules);
+        }
+    }
+
+    // fail early instead of leaving a partial archive on a full file system
+    if !options.dry_run && !options.print_path {
+        storage.check_free_space(options, required_space(&parsed, &skipped, options))?;
+    }
+
+    let mut manifest = if options.manifest && !options.dry_run && !options.print_path {
+        Some(Manifest::open(&options.directory, Utc::now())?)
+    } else {
+        None
+    };
+
+    // invalid credentials are reported before anything is archived
+    let mut uploader = match options.upload {
+        Some(service) if !options.dry_run && !options.print_path => Some(Uploader::open(
+            service,
+            &options.directory,
+            options.upload_daily_limit,
+        )?),
+        _ => None,
+    };
+
+    // archive independent files in parallel, the results are reported in order below
+    let mut copies = if options.jobs > 1
+        && !options.dry_run
+        && !options.print_path
+        && storage.is_parallel()
+        && options.conflict_resolver.is_none()
+    {
+        archive_files_parallel(&parsed, &skipped, index.as_ref(), storage, options)
+    } else {
+        Vec::new()
+    };
+    copies.resize_with(options.files.len(), || None);
+
+    for ((((source_path, parsed), skip), timing), copy) in options
+        .files
+        .iter()
+        .zip(parsed)
+        .zip(skipped)
+        .zip(timings.iter_mut())
+        .zip(copies)
+    {
+        let source_path = source_path.as_path();
+        match parsed {
+            Ok(val) => {
+                let mut archive_path = destination_path(source_path, &val, options);
+
+                if skip {
+                    print_status(
+                        source_path,
+                        Some(&archive_path),
+                        Status::Skipped,
+                        Some(&val),
+                        &[],
+                        options,
+                    );
+                    skip_counter += 1;
+                    continue;
+                }
+
+                // only show the destination without touching the file system
+                if options.print_path {
+                    let separator = match options.output {
+                        OutputFormat::Print0 => b'\0',
+                        _ => b'\n',
+                    };
+                    print_path(&archive_path, separator);
+                    file_counter += 1;
+                    continue;
+                }
+
+                // the same content may be archived under another name, i.e. after a template change
+                let mut archived_before = None;
+                if let (Some(index), true, None) = (&index, options.skip_duplicates, &copy) {
+                    match archived_copy(source_path, &archive_path, index) {
+                        Ok(existing) => archived_before = existing,
+                        Err(err) => {
+                            failed.push((source_path, err.details()));
+                            print_status(
+                                source_path,
+                                Some(&archive_path),
+                                Status::Error,
+                                Some(&val),
+                                &[err],
+                                options,
+                            );
+                            error_counter += 1;
+                            continue;
+                        }
+                    }
+                }
+
+                // the application decides about a conflict with an existing archive file
+                let mut archive_options = None;
+                if let (Some(resolver), None, None) =
+                    (&options.conflict_resolver, &archived_before, &copy)
+                {
+                    let resolution = ask_resolver(
+                        resolver.as_ref(),
+                        source_path,
+                        &archive_path,
+                        &activity_json(&val),
+                    );
+                    match resolution {
+                        Ok(Some(Resolution::Keep)) => archived_before = Some(archive_path.clone()),
+                        Ok(Some(Resolution::Replace)) => {
+                            archive_options = Some(Options {
+                                on_conflict: ConflictPolicy::Overwrite,
+                                ..options.clone()
+                            })
+                        }
+                        Ok(Some(Resolution::Rename(name))) => {
+                            archive_path = archive_path.with_file_name(name)
+                        }
+                        Ok(Some(Resolution::Skip)) => {
+                            print_status(
+                                source_path,
+                                Some(&archive_path),
+                                Status::Skipped,
+                                Some(&val),
+                                &[],
+                                options,
+                            );
+                            skip_counter += 1;
+                            continue;
+                        }
+                        Ok(None) => (),
+                        Err(err) => {
+                            failed.push((source_path, err.details()));
+                            print_status(
+                                source_path,
+                                Some(&archive_path),
+                                Status::Error,
+                                Some(&val),
+                                &[err],
+                                options,
+                            );
+                            error_counter += 1;
+                            continue;
+                        }
+                    }
+                }
+
+                if let Some(existing) = archived_before {
+                    let mut errors = Vec::new();
+                    if options.move_files && !options.dry_run && !same_file(source_path, &existing)
+                    {
+                        if removes_source(options) {
+                            if let Err(err) = fs::remove_file(source_path) {
+                                let msg =
+                                    format!("Unable to remove file '{}'", source_path.display());
+                                errors.push(ArchiverError::io(&msg, err));
+                                error_counter += 1;
+                            }
+                        } else {
+                            sources.push(source_path);
+                        }
+                    }
+                    print_status(
+                        source_path,
+                        Some(&existing),
+                        Status::Identical,
+                        Some(&val),
+                        &errors,
+                        options,
+                    );
+                    recovered.push(source_path);
+                    identical_counter += 1;
+                    continue;
+                }
+
+                let (archived, copy) = copy.unwrap_or_else(|| {
+                    let started = Instant::now();
+                    let archive_options = archive_options.as_ref().unwrap_or(options);
+                    let archived = storage
+                        .create_archive_directory(&archive_path, archive_options)
+                        .map(|_| storage.archive_file(source_path, &archive_path, archive_options));
+                    (archived, started.elapsed())
+                });
+                timing.copy = copy;
+                match archived {
+                    Ok(archived) => match archived {
+                        Ok(status) => {
+                            let mut errors = Vec::new();
+                            if let (Some(manifest), Status::Copied | Status::Moved) =
+                                (&mut manifest, status)
+                            {
+                                if let Err(err) = manifest.append(source_path, &archive_path, &val)
+                                {
+                                    errors.push(err);
+                                    error_counter += 1;
+                                }
+                            }
+                            if let (true, Status::Copied | Status::Moved | Status::Identical) =
+                                (options.checksums && !options.dry_run, status)
+                            {
+                                if let Err(err) = record_checksum(&archive_path) {
+                                    errors.push(err);
+                                    error_counter += 1;
+                                }
+                            }
+                            if let Status::Copied | Status::Moved = status {
+                                let path = archive_path
+                                    .strip_prefix(&options.directory)
+                                    .unwrap_or(&archive_path);
+                                commit.push((path.to_path_buf(), commit_line(path, &val)));
+                            }
+                            // a dry run only reads the index
+                            if let (
+                                Some(index),
+                                Status::Copied | Status::Moved | Status::Identical,
+                                false,
+                            ) = (&index, status, options.dry_run)
+                            {
+                                // the original location allows restoring the file, i.e. onto the device
+                                let source = match same_file(source_path, &archive_path) {
+                                    true => None,
+                                    false => std::path::absolute(source_path).ok(),
+                                };
+                                let indexed = index.add(&archive_path, &val).and_then(|_| {
+                                    source.map_or(Ok(()), |source| {
+                                        index.set_source_path(&archive_path, &source)
+                                    })
+                                });
+                                if let Err(err) = indexed {
+                                    errors.push(err);
+                                    error_counter += 1;
+                                }
+                            }
+                            // derived files are written from the archive file, the source may be gone
+                            if let (true, Status::Copied | Status::Moved | Status::Identical) =
+                                (options.export_gpx && !options.dry_run, status)
+                            {
+                                let gpx_path = match &options.export_template {
+                                    Some(template) => {
+                                        export_path(&options.directory, template, &val, "gpx")
+                                    }
+                                    None => archive_path.with_extension("gpx"),
+                                };
+                                // files archived before are only exported if the export is missing
+                                if status != Status::Identical || !gpx_path.exists() {
+                                    if let Err(err) = write_gpx(&archive_path, &gpx_path) {
+                                        errors.push(err);
+                                        error_counter += 1;
+                                    }
+                                }
+                            }
+                            // uploaded within the rate limit after the batch
+                            if let (Some(uploader), Status::Copied | Status::Moved) =
+                                (&mut uploader, status)
+                            {
+                                uploader.enqueue(&archive_path);
+                            }
+                            if let (true, Status::Copied | Status::Moved) =
+                                (!options.dry_run && !options.print_path, status)
+                            {
+                                if let Some(command) = &options.exec {
+                                    if let Err(err) =
+                                        run_exec(command, source_path, &archive_path, &val)
+                                    {
+                                        errors.push(err);
+                                        error_counter += 1;
+                                    }
+                                }
+                                if options.exec_batch.is_some() {
+                                    exec_paths
+                                        .push((source_path.to_path_buf(), archive_path.clone()));
+                                }
+                                // the archive file is remote with a storage backend
+                                bytes += fs::metadata(&archive_path)
+                                    .or_else(|_| fs::metadata(source_path))
+                                    .map_or(0, |metadata| metadata.len());
+                            }
+                            // the source file of a moved batch is deleted at the end of the run
+                            if options.move_files
+                                && !options.dry_run
+                                && options.delete_sources != SourceDeletion::Immediately
+                                && (status == Status::Moved
+                                    || status == Status::Identical
+                                        && !same_file(source_path, &archive_path))
+                            {
+                                sources.push(source_path);
+                            }
+                            print_status(
+                                source_path,
+                                Some(&archive_path),
+                                status,
+                                Some(&val),
+                                &errors,
+                                options,
+                            );
+                            if let Status::Copied | Status::Moved | Status::Identical = status {
+                                recovered.push(source_path);
+                            }
+                            match status {
+                                Status::Identical => identical_counter += 1,
+                                Status::Skipped => skip_counter += 1,
+                                _ => {
+                                    let directory = archive_path
+                                        .parent()
+                                        .map(|parent| {
+                                            parent
+                                                .strip_prefix(&options.directory)
+                                                .unwrap_or(parent)
+                                        })
+                                        .unwrap_or(Path::new(""));
+                                    *directories.entry(directory.to_path_buf()).or_default() += 1;
+                                    file_counter += 1;
+                                }
+                            }
+                        }
+                        Err(err) => {
+                            failed.push((source_path, err.details()));
+                            print_status(
+                                source_path,
+                                Some(&archive_path),
+                                Status::Error,
+                                Some(&val),
+                                &[err],
+                                options,
+                            );
+                            error_counter += 1;
+                            archive_errors += 1;
+                        }
+                    },
+                    Err(e) => return Err(e),
+                }
+            }
+            Err(err) => {
+                // files without FIT header are junk rather than damaged activities
+                let is_fit = match has_fit_header(source_path) {
+                    Ok(val) => val,
+                    Err(_) => {
+                        print_status(source_path, None, Status::Error, None, &[err], options);
+                        error_counter += 1;
+                        continue;
+                    }
+                };
+                if is_fit {
+                    print_status(source_path, None, Status::Error, None, &[err], options);
+                    damaged_counter += 1;
+                    error_counter += 1;
+                    continue;
+                }
+
+                invalid_counter += 1;
+                let invalid = not_fit_file(source_path);
+                match options.on_invalid {
+                    InvalidPolicy::Error => {
+                        print_status(source_path, None, Status::Error, None, &[invalid], options);
+                        error_counter += 1;
+                    }
+                    InvalidPolicy::Skip => {
+                        print_status(source_path, None, Status::Skipped, None, &[], options);
+                    }
+                    InvalidPolicy::Quarantine => {
+                        match quarantine_file(source_path, options, storage) {
+                            Ok((archive_path, status)) => {
+                                if options.move_files
+                                    && !options.dry_run
+                                    && options.delete_sources != SourceDeletion::Immediately
+                                    && status == Status::Moved
+                                {
+                                    sources.push(source_path);
+                                }
+                                print_status(
+                                    source_path,
+                                    Some(&archive_path),
+                                    status,
+                                    None,
+                                    &[],
+                                    options,
+                                );
+                            }
+                            Err(err) => {
+                                print_status(
+                                    source_path,
+                                    None,
+                                    Status::Error,
+                                    None,
+                                    &[invalid, err],
+                                    options,
+                                );
+                                error_counter += 1;
+                                archive_errors += 1;
+                            }
+                        }
+                    }
+                }
+            }
+        };
+    }
+
+    // failed files are retried with --retry-failed until they are archived
+    if let (Some(index), false) = (&index, options.dry_run || options.print_path) {
+        let now = Utc::now();
+        let recorded = failed
+            .iter()
+            .try_for_each(|(path, reason)| index.add_failure(path, reason, now))
+            .and_then(|_| {
+                recovered
+                    .iter()
+                    .try_for_each(|path| index.remove_failure(path))
+            });
+        if let Err(err) = recorded {
+            print_error(&err, options);
+            error_counter += 1;
+        }
+    }
+
+    // the index entries of the last files are stored before the sources are deleted
+    if let Some(Err(err)) = index.as_ref().map(Index::commit) {
+        print_error(&err, options);
+        error_counter += 1;
+    }
+
+    // version the archive with one commit per batch
+    if let Some(mode) = options.git {
+        if let Err(err) = commit_files(&options.directory, mode, &commit) {
+            print_error(&err, options);
+            error_counter += 1;
+        }
+    }
+
+    // failed uploads keep the sources with 'after-clean-batch', the uploads may continue in
+    // later runs
+    if let Some(uploader) = &mut uploader {
+        match uploader.upload_pending(Utc::now()) {
+            Ok(progress) => {
+                if options.verbose && progress.uploaded > 0 {
+                    eprintln!("Uploaded {} files", progress.uploaded);
+                }
+                for err in &progress.errors {
+                    print_error(err, options);
+                }
+                error_counter += progress.errors.len();
+                if let (true, Some(resume_at)) = (progress.pending > 0, progress.resume_at) {
+                    eprintln!(
+                        "Rate limit of the uploads reached, {} files stay queued until {}. Run with '--upload' again to continue.",
+                        progress.pending,
+                        resume_at.format("%Y-%m-%d %H:%M UTC")
+                    );
+                }
+            }
+            Err(err) => {
+                print_error(&err, options);
+                error_counter += 1;
+            }
+        }
+    }
+
+    // never leave a batch partly on the source and partly in the archive
+    let (deletion_errors, kept) = delete_sources(&sources, error_counter, archive_errors, options);
+    error_counter += deletion_errors;
+
+    // help identifying pathological files and slow destinations
+    if options.verbose && !timings.is_empty() {
+        eprintln!("{}", timing_report(&timings, SLOWEST_FILES));
+    }
+
+    Ok(Summary {
+        processed: file_counter,
+        identical: identical_counter,
+        skipped: skip_counter,
+        invalid: invalid_counter,
+        damaged: damaged_counter,
+        kept,
+        errors: error_counter,
+        archived: exec_paths,
+        bytes,
+        directories,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use std::fs::{self, File};
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test archive path computation
+    fn test_archive_path() {
+        // setup activity data
+        let activity_data = super::ActivityData {
+            sport: String::from("running"),
+            sport_name: String::from("training"),
+            sub_sport: String::from("trail"),
+            workout_name: String::from("interval"),
+            timestamp: chrono::Utc.with_ymd_and_hms(2014, 7, 8, 9, 10, 11).unwrap(),
+            ..super::ActivityData::new()
+        };
+
+        assert_eq!(
+            PathBuf::from("archive/2014/07/2014-07-08-091011-running.fit"),
+            super::archive_path(
+                &PathBuf::from("archive"),
+                "%Y/%m/%Y-%m-%d-%H%M%S-$s",
+                &activity_data
+            )
+        );
+
+        // sport directories are only injected into templates without sport tags
+        let mut options = super::Options {
+            directory: PathBuf::from("archive"),
+            file_template: String::from("%Y/%Y-%m-%d"),
+            sport_directories: vec!["running:trail=trail".parse().unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(
+            PathBuf::from("archive/trail/2014/2014-07-08.fit"),
+            super::destination_path(Path::new("run.fit"), &activity_data, &options)
+        );
+        options.file_template = String::from("%Y/%Y-%m-%d-$s");
+        assert_eq!(
+            PathBuf::from("archive/2014/2014-07-08-running.fit"),
+            super::destination_path(Path::new("run.fit"), &activity_data, &options)
+        );
+    }
+
+    #[test]
+    /// Test converting activity data for JSON output
+    fn test_activity_json() {
+        let activity_data = super::ActivityData {
+            sport: String::from("running"),
+            timestamp: chrono::Utc.with_ymd_and_hms(2014, 7, 8, 9, 10, 11).unwrap(),
+            distance: Some(5000.0),
+            start_position: Some((48.137, 11.575)),
+            ..super::ActivityData::new()
+        };
+        let json = super::activity_json(&activity_data);
+        assert_eq!(Some("running"), json["sport"].as_str());
+        assert_eq!(Some("2014-07-08T09:10:11Z"), json["timestamp"].as_str());
+        assert_eq!(Some(5000.0), json["distance"].as_f64());
+        assert!(json["duration"].is_null());
+        assert_eq!(Some(48.137), json["start_position"]["latitude"].as_f64());
+    }
+
+    #[test]
+    /// Test escaping of paths for porcelain output
+    fn test_escape_path() {
+        assert_eq!(
+            String::from("archive/2014/running.fit"),
+            super::escape_path(&PathBuf::from("archive/2014/running.fit"))
+        );
+        assert_eq!(
+            String::from("archive/a\\tb\\nc\\\\d.fit"),
+            super::escape_path(&PathBuf::from("archive/a\tb\nc\\d.fit"))
+        );
+    }
+
+    #[test]
+    // Test creating the archive directory
+    fn test_create_archive_directory() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let source_path = tmpdir.path().join("source_dir").join("source.fit");
+        let archive_file = tmpdir.path().join("archive_dir").join("archive.fit");
+
+        let options = super::Options {
+            directory: archive_file.parent().unwrap().to_path_buf(),
+            files: vec![source_path.clone()],
+            ..Default::default()
+        };
+
+        assert!(!archive_file.parent().unwrap().exists());
+        super::create_archive_directory(&archive_file, &options)
+            .expect("error during creating directory");
+        assert!(archive_file.parent().unwrap().exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    // Test not creating the archive directory in dry run
+    fn test_create_archive_directory_dry_run() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let source_path = tmpdir.path().join("source_dir").join("source.fit");
+        let archive_file = tmpdir.path().join("archive_dir").join("archive.fit");
+
+        let options = super::Options {
+            dry_run: true,
+            directory: archive_file.parent().unwrap().to_path_buf(),
+            files: vec![source_path.clone()],
+            ..Default::default()
+        };
+
+        assert!(!archive_file.parent().unwrap().exists());
+        super::create_archive_directory(&archive_file, &options)
+            .expect("error during creating directory");
+        assert!(!archive_file.parent().unwrap().exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    // Test failure when parent of archive directory is missing
+    fn test_create_archive_directory_failure_parent_missing() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let source_path = tmpdir.path().join("source_dir").join("source.fit");
+        let archive_path = PathBuf::new();
+
+        let options = super::Options {
+            directory: archive_path.clone(),
+            files: vec![source_path.clone()],
+            ..Default::default()
+        };
+
+        super::create_archive_directory(&archive_path, &options).expect_err("error expected");
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    // Test failure when archive directory is a file
+    fn test_create_archive_directory_failure_file_exists() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let source_path = tmpdir.path().join("source_dir").join("source.fit");
+        let archive_file = tmpdir.path().join("archive_dir").join("archive.fit");
+
+        let options = super::Options {
+            directory: archive_file.parent().unwrap().to_path_buf(),
+            files: vec![source_path.clone()],
+            ..Default::default()
+        };
+
+        std::fs::File::create(archive_file.parent().unwrap())
+            .expect("error during creating directory");
+        super::create_archive_directory(&archive_file, &options).expect_err("error expected");
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    // Test failure when archive directory is not a directory
+    fn test_create_archive_directory_failure_unable_to_create() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let source_path = tmpdir.path().join("source_dir").join("source.fit");
+        let archive_file = PathBuf::from("/").join("archive_dir").join("archive.fit");
+
+        let options = super::Options {
+            directory: archive_file.parent().unwrap().to_path_buf(),
+            files: vec![source_path.clone()],
+            ..Default::default()
+        };
+
+        super::create_archive_directory(&archive_file, &options).expect_err("error expected");
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test dry run
+    fn test_archive_file_dry_run() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let source_path = tmpdir.path().join("source_dir").join("source.fit");
+        let archive_file = tmpdir.path().join("archive_dir").join("archive.fit");
+
+        {
+            // put file creation into a separate scope so the file is closed for the actual test
+            fs::create_dir_all(source_path.parent().unwrap())
+                .expect("error during creating temporary archive directory");
+            fs::create_dir_all(archive_file.parent().unwrap())
+                .expect("error during creating temporary archive directory");
+            File::create(&source_path).expect("unable to create test file");
+        }
+
+        let options = super::Options {
+            dry_run: true,
+            directory: archive_file.parent().unwrap().to_path_buf(),
+            file_template: String::from("archive"),
+            files: vec![source_path.clone()],
+            ..Default::default()
+        };
+
+        assert!(source_path.exists());
+        assert!(!archive_file.exists());
+        super::archive_file(&source_path, &archive_file, &options)
+            .expect("error during archiving file");
+        assert!(source_path.exists());
+        assert!(!archive_file.exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test copying file to archive
+    fn test_archive_file_copy() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let source_path = tmpdir.path().join("source_dir").join("source.fit");
+        let archive_file = tmpdir.path().join("archive_dir").join("archive.fit");
+
+        {
+            // put file creation into a separate scope so the file is closed for the actual test
+            fs::create_dir_all(source_path.parent().unwrap())
+                .expect("error during creating temporary archive directory");
+            fs::create_dir_all(archive_file.parent().unwrap())
+                .expect("error during creating temporary archive directory");
+            File::create(&source_path).expect("unable to create test file");
+        }
+
+        let options = super::Options {
+            directory: archive_file.parent().unwrap().to_path_buf(),
+            file_template: String::from("archive"),
+            files: vec![source_path.clone()],
+            ..Default::default()
+        };
+
+        assert!(source_path.exists());
+        assert!(!archive_file.exists());
+        super::archive_file(&source_path, &archive_file, &options)
+            .expect("error during archiving file");
+        assert!(source_path.exists());
+        assert!(archive_file.exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test moving file to archive
+    fn test_archive_file_move() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let source_path = tmpdir.path().join("source_dir").join("source.fit");
+        let archive_file = tmpdir.path().join("archive_dir").join("archive.fit");
+
+        {
+            // put file creation into a separate scope so the file is closed for the actual test
+            fs::create_dir_all(source_path.parent().unwrap())
+                .expect("error during creating temporary archive directory");
+            fs::create_dir_all(archive_file.parent().unwrap())
+                .expect("error during creating temporary archive directory");
+            File::create(&source_path).expect("unable to create test file");
+        }
+
+        let options = super::Options {
+            move_files: true,
+            delete_sources: super::SourceDeletion::Immediately,
+            directory: archive_file.parent().unwrap().to_path_buf(),
+            file_template: String::from("archive"),
+            files: vec![source_path.clone()],
+            ..Default::default()
+        };
+
+        assert!(source_path.exists());
+        assert!(!archive_file.exists());
+        super::archive_file(&source_path, &archive_file, &options)
+            .expect("error during archiving file");
+        assert!(!source_path.exists());
+        assert!(archive_file.exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test extracting activity data from real FIT file
+    fn test_activity_data_from_file() {
+        // get the directory of the test executable
+        let mut source_path = std::env::current_exe()
+            .unwrap()
+            .parent()
+            .expect("executable directory")
+            .to_path_buf();
+
+        // go up to the repository base directory
+        source_path.pop();
+        source_path.pop();
+        source_path.pop();
+
+        // append location of the test data
+        source_path.push("test");
+        source_path.push("test_data_01.fit");
+
+        let result = super::parse_fit_file(&source_path);
+        assert!(result.is_ok());
+        let activity_data = result.unwrap();
+        assert_eq!(String::from("running"), activity_data.sport);
+        assert_eq!(String::from("trail_run"), activity_data.sport_name);
+        assert_eq!(String::from("trail"), activity_data.sub_sport);
+        assert_eq!(Some(1), activity_data.sport_code);
+        assert_eq!(Some(3), activity_data.sub_sport_code);
+        assert_eq!(vec![String::from("running")], activity_data.legs);
+        assert_eq!(String::from("test_workout"), activity_data.workout_name);
+        assert_eq!(1, activity_data.workout_steps);
+        assert_eq!(String::from("open_on"), activity_data.workout_structure);
+        assert_eq!(
+            chrono::FixedOffset::east_opt(7200),
+            activity_data.device_offset
+        );
+        assert_eq!(String::from("garmin"), activity_data.manufacturer);
+        assert_eq!(String::from("epix_gen2_pro_51"), activity_data.product);
+        assert_eq!(String::from("activity"), activity_data.file_type);
+        assert_eq!(12, activity_data.record_count);
+        assert_eq!(12, activity_data.position_count);
+        assert_eq!(Some(11.13), activity_data.duration);
+        assert_eq!(Some(10.77), activity_data.distance);
+        assert_eq!(Some(91.0), activity_data.heart_rate);
+        assert_eq!(String::new(), activity_data.notes);
+        let (latitude, longitude) = activity_data.start_position.unwrap();
+        assert!((latitude - 48.72368).abs() < 1e-5);
+        assert!((longitude - 9.11380).abs() < 1e-5);
+        assert_eq!(
+            chrono::Utc.with_ymd_and_hms(2023, 7, 26, 6, 22, 4).unwrap(),
+            activity_data.timestamp
+        );
+        assert!(activity_data.begin() <= activity_data.end());
+    }
+
+    #[test]
+    /// Test activity file is missing
+    fn test_activity_data_from_file_failure_file_missing() {
+        // get the directory of the test executable
+        let mut source_path = std::env::current_exe()
+            .unwrap()
+            .parent()
+            .expect("executable directory")
+            .to_path_buf();
+
+        // go up to the repository base directory
+        source_path.pop();
+        source_path.pop();
+        source_path.pop();
+
+        // append location of the test data
+        source_path.push("test");
+        source_path.push("missing.fit");
+
+        super::parse_fit_file(&source_path).expect_err("error expected");
+    }
+
+    #[test]
+    /// Test activity file is corrupted
+    fn test_activity_data_from_file_failure_file_empty() {
+        // get the directory of the test executable
+        let mut source_path = std::env::current_exe()
+            .unwrap()
+            .parent()
+            .expect("executable directory")
+            .to_path_buf();
+
+        // go up to the repository base directory
+        source_path.pop();
+        source_path.pop();
+        source_path.pop();
+
+        // append location of the test data
+        source_path.push("test");
+        source_path.push("corrupted.fit");
+
+        super::parse_fit_file(&source_path).expect_err("error expected");
+    }
+
+    #[test]
+    /// Test extracting the bytes of memo fields
+    fn test_memo_bytes() {
+        use fitparser::Value;
+
+        assert_eq!(
+            b"Hill".to_vec(),
+            super::memo_bytes(&Value::Array(vec![
+                Value::Byte(b'H'),
+                Value::Byte(b'i'),
+                Value::UInt8(b'l'),
+                Value::Byte(b'l'),
+            ]))
+        );
+        assert_eq!(
+            b"run".to_vec(),
+            super::memo_bytes(&Value::String(String::from("run")))
+        );
+        assert!(super::memo_bytes(&Value::Float64(1.0)).is_empty());
+    }
+
+    #[test]
+    /// Test underlying cause of errors is preserved
+    fn test_error_details() {
+        use std::error::Error;
+
+        let err = super::parse_fit_file(&test_data("corrupted.fit")).expect_err("error expected");
+        assert!(matches!(err, super::ArchiverError::Parse { .. }));
+        assert!(err.source().is_some());
+        assert!(err.details().starts_with(&format!("{}: ", err)));
+
+        let err = super::parse_fit_file(&test_data("missing.fit")).expect_err("error expected");
+        assert!(matches!(err, super::ArchiverError::Io { .. }));
+        assert_eq!(
+            std::io::ErrorKind::NotFound,
+            err.source()
+                .unwrap()
+                .downcast_ref::<std::io::Error>()
+                .unwrap()
+                .kind()
+        );
+
+        let err = super::ArchiverError::new("message only");
+        assert!(err.source().is_none());
+        assert_eq!(String::from("message only"), err.details());
+    }
+
+    /// Returns the path of a file in the test data directory
+    fn test_data(name: &str) -> PathBuf {
+        // get the directory of the test executable
+        let mut source_path = std::env::current_exe()
+            .unwrap()
+            .parent()
+            .expect("executable directory")
+            .to_path_buf();
+
+        // go up to the repository base directory
+        source_path.pop();
+        source_path.pop();
+        source_path.pop();
+
+        // append location of the test data
+        source_path.push("test");
+        source_path.push(name);
+        source_path
+    }
+
+    #[test]
+    /// Test printing only the archive path
+    fn test_process_files_print_path() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+
+        let options = super::Options {
+            directory: archive_dir.clone(),
+            print_path: true,
+            files: vec![test_data("test_data_01.fit")],
+            ..Default::default()
+        };
+
+        let result = super::process_files(&options).expect("error during processing files");
+        assert_eq!(String::from("Processed 1 files "), result);
+        assert!(!archive_dir.exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test numbering activities within their day across the files and the index
+    fn test_process_files_day_sequence() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+
+        // an earlier activity of the same day is already archived
+        let index = crate::index::Index::open(&archive_dir, true)
+            .unwrap()
+            .unwrap();
+        let activity_data = super::ActivityData {
+            timestamp: chrono::Utc.with_ymd_and_hms(2023, 7, 26, 3, 0, 0).unwrap(),
+            ..super::ActivityData::new()
+        };
+        index
+            .add(&archive_dir.join("2023-07-26-1.fit"), &activity_data)
+            .unwrap();
+        drop(index);
+
+        let mut options = super::Options {
+            index: true,
+            manifest: true,
+            directory: archive_dir.clone(),
+            file_template: String::from("%Y-%m-%d-$q"),
+            files: vec![test_data("test_data_01.fit")],
+            ..Default::default()
+        };
+        super::process_files(&options).expect("error during processing files");
+        assert!(archive_dir.join("2023-07-26-2.fit").exists());
+
+        // the number is stable when archiving the file again
+        let result = super::process_files(&options).expect("error during processing files");
+        assert_eq!(
+            String::from("Processed 0 files, 1 already archived "),
+            result
+        );
+
+        // without index only the given files are numbered
+        options.index = false;
+        super::process_files(&options).expect("error during processing files");
+        assert!(archive_dir.join("2023-07-26-1.fit").exists());
+
+        // the manifest contains the header and one line per archived file
+        let manifest = fs::read_to_string(archive_dir.join(crate::MANIFEST_PATH)).unwrap();
+        let lines: Vec<&str> = manifest.lines().collect();
+        assert_eq!(3, lines.len());
+        assert!(lines[1].contains("\t2023-07-26-2.fit\t"));
+        assert!(lines[2].contains("\t2023-07-26-1.fit\t"));
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test simulating conflicts between the files of a dry run
+    fn test_simulate_archive_file() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_path = tmpdir.path().join("archive/2023/run.fit");
+        let first = tmpdir.path().join("first.fit");
+        let second = tmpdir.path().join("second.fit");
+        fs::write(&first, "first").unwrap();
+        fs::write(&second, "second").unwrap();
+        let mut options = super::Options {
+            dry_run: true,
+            on_conflict: super::ConflictPolicy::Skip,
+            ..Default::default()
+        };
+
+        let mut overlay = crate::overlay::Overlay::default();
+        let mut simulate = |source_path: &Path, options: &super::Options| {
+            super::simulate_archive_file(source_path, &archive_path, options, &mut overlay).unwrap()
+        };
+        assert_eq!(super::Status::DryRun, simulate(&first, &options));
+        assert_eq!(super::Status::Identical, simulate(&first, &options));
+        assert_eq!(super::Status::Skipped, simulate(&second, &options));
+        options.on_conflict = super::ConflictPolicy::Overwrite;
+        assert_eq!(super::Status::DryRun, simulate(&second, &options));
+        assert_eq!(super::Status::Identical, simulate(&second, &options));
+
+        // nothing is created
+        assert!(!tmpdir.path().join("archive").exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test reporting the slowest files
+    fn test_timing_report() {
+        let timing = |path: &'static str, parse: u64, copy: u64| super::FileTiming {
+            source_path: Path::new(path),
+            parse: Duration::from_millis(parse),
+            copy: Duration::from_millis(copy),
+        };
+        let timings = vec![
+            timing("fast.fit", 1, 2),
+            timing("slow.fit", 10, 1500),
+            timing("medium.fit", 250, 0),
+        ];
+
+        assert_eq!(
+            "Slowest 2 of 3 files:\n  \
+             1.510s (parse 0.010s, copy 1.500s) 'slow.fit'\n  \
+             0.250s (parse 0.250s, copy 0.000s) 'medium.fit'",
+            super::timing_report(&timings, 2)
+        );
+        assert_eq!(
+            "Slowest 3 of 3 files:",
+            super::timing_report(&timings, 5).lines().next().unwrap()
+        );
+    }
+
+    #[test]
+    /// Test deleting the source files of a moved batch only without errors
+    fn test_process_files_move_batch() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+        let first = tmpdir.path().join("111111.fit");
+        let second = tmpdir.path().join("222222.fit");
+        fs::copy(test_data("test_data_01.fit"), &first).unwrap();
+        fs::copy(test_data("test_data_01.fit"), &second).unwrap();
+
+        // the notes of the exports distinguish the copies of the same activity
+        let metadata = tmpdir.path().join("metadata.json");
+        fs::write(
+            &metadata,
+            r#"[{"activityId": 111111, "description": "1"},
+                {"activityId": 222222, "description": "2"}]"#,
+        )
+        .unwrap();
+
+        // the second file cannot be archived
+        fs::create_dir_all(archive_dir.join("2.fit")).unwrap();
+        let mut options = super::Options {
+            directory: archive_dir.clone(),
+            file_template: String::from("$o"),
+            metadata_from_json: Some(metadata.to_string_lossy().to_string()),
+            move_files: true,
+            index: false,
+            files: vec![first.clone(), second.clone()],
+            ..Default::default()
+        };
+        let result = super::process_files(&options).expect("error during processing files");
+        assert_eq!(
+            String::from("Processed 1 files, kept 1 source files with 1 errors."),
+            result
+        );
+        assert!(archive_dir.join("1.fit").exists());
+        assert!(first.exists());
+        assert!(second.exists());
+
+        // all source files are deleted once the whole batch is archived
+        fs::remove_dir(archive_dir.join("2.fit")).unwrap();
+        let result = super::process_files(&options).expect("error during processing files");
+        assert_eq!(
+            String::from("Processed 1 files, 1 already archived "),
+            result
+        );
+        assert!(archive_dir.join("2.fit").is_file());
+        assert!(!first.exists());
+        assert!(!second.exists());
+
+        // a stray file that is not a FIT file does not keep the archived source files
+        let junk = tmpdir.path().join("notes.txt");
+        fs::write(&junk, "notes").unwrap();
+        fs::copy(test_data("test_data_01.fit"), &first).unwrap();
+        options.files = vec![first.clone(), junk.clone()];
+        super::process_files(&options).expect("error during processing files");
+        assert!(!first.exists());
+        assert!(junk.exists());
+
+        // unless any error keeps them
+        fs::copy(test_data("test_data_01.fit"), &first).unwrap();
+        options.delete_sources = super::SourceDeletion::AfterCleanBatch;
+        let result = super::process_files(&options).expect("error during processing files");
+        assert!(result.contains("kept 1 source files"), "{}", result);
+        assert!(first.exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test keeping the source files of a moved batch after a failed upload
+    fn test_process_files_move_upload_failure() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+        let source_path = tmpdir.path().join("source.fit");
+
+        // a valid access token, no request is sent for files uploaded before
+        let config_dir = tmpdir.path().join("config");
+        fs::create_dir_all(config_dir.join("fitarchiver")).unwrap();
+        fs::write(
+            config_dir.join("fitarchiver/strava-token.json"),
+            r#"{"client_id": "1", "client_secret": "s", "refresh_token": "r",
+                "access_token": "a", "expires_at": 4102444800}"#,
+        )
+        .unwrap();
+        std::env::set_var("XDG_CONFIG_HOME", &config_dir);
+        fs::create_dir_all(archive_dir.join(".fitarchiver")).unwrap();
+        let hash = crate::objects::file_hash(&test_data("test_data_01.fit")).unwrap();
+        fs::write(archive_dir.join(crate::STRAVA_UPLOADS_PATH), hash + "\n").unwrap();
+        // the upload of a file queued before fails
+        let queue = r#"{"pending": [{"path": "missing.fit", "attempts": 0, "retry_at": 0}]}"#;
+
+        let mut options = super::Options {
+            directory: archive_dir.clone(),
+            file_template: String::from("%Y-%m-%d"),
+            move_files: true,
+            delete_sources: super::SourceDeletion::AfterCleanBatch,
+            upload: Some(crate::UploadService::Strava),
+            index: false,
+            manifest: false,
+            files: vec![source_path.clone()],
+            ..Default::default()
+        };
+        fs::write(archive_dir.join(crate::STRAVA_QUEUE_PATH), queue).unwrap();
+        fs::copy(test_data("test_data_01.fit"), &source_path).unwrap();
+        let result = super::process_files(&options).expect("error during processing files");
+        assert!(result.contains("kept 1 source files"), "{}", result);
+        assert!(archive_dir.join("2023-07-26.fit").exists());
+        assert!(source_path.exists());
+
+        // only failed archive files keep the sources by default
+        fs::write(archive_dir.join(crate::STRAVA_QUEUE_PATH), queue).unwrap();
+        options.delete_sources = super::SourceDeletion::AfterBatch;
+        super::process_files(&options).expect("error during processing files");
+        assert!(!source_path.exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test quarantining and correcting suspicious timestamps
+    fn test_process_files_suspicious_time() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+
+        // the file was written a year after the activity
+        let source_path = tmpdir.path().join("source.fit");
+        fs::copy(test_data("test_data_01.fit"), &source_path).unwrap();
+        let modified = chrono::Utc.with_ymd_and_hms(2024, 7, 27, 0, 0, 0).unwrap();
+        File::options()
+            .write(true)
+            .open(&source_path)
+            .unwrap()
+            .set_modified(modified.into())
+            .unwrap();
+
+        // the modification time of a copied file is no reference by default
+        let mut options = super::Options {
+            directory: tmpdir.path().join("default_dir"),
+            file_template: String::from("%Y-%m-%d"),
+            suspicious_time: crate::SuspiciousTimePolicy::Quarantine,
+            index: false,
+            files: vec![source_path],
+            ..Default::default()
+        };
+        super::process_files(&options).expect("error during processing files");
+        assert!(tmpdir.path().join("default_dir/2023-07-26.fit").exists());
+
+        options.directory = archive_dir.clone();
+        options.stale_after = 365;
+        super::process_files(&options).expect("error during processing files");
+        assert!(archive_dir
+            .join(crate::QUARANTINE_PATH)
+            .join("source-4764a321.fit")
+            .exists());
+
+        // the corrected timestamp is plausible
+        options.time_offset = 2 * 24 * 3600;
+        super::process_files(&options).expect("error during processing files");
+        assert!(archive_dir.join("2023-07-28.fit").exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test archiving activities with gaps in their recording for a review
+    fn test_process_files_gaps() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+        let activity_data = super::parse_fit_file(&test_data("test_data_01.fit")).unwrap();
+        let longest_gap = activity_data.longest_gap.unwrap();
+        assert!(!activity_data.has_gaps);
+
+        let mut options = super::Options {
+            index: true,
+            directory: archive_dir.clone(),
+            file_template: String::from("%Y-%m-%d"),
+            gap_threshold: longest_gap + 1,
+            on_gaps: crate::GapPolicy::Quarantine,
+            files: vec![test_data("test_data_01.fit")],
+            ..Default::default()
+        };
+        super::process_files(&options).expect("error during processing files");
+        assert!(archive_dir.join("2023-07-26.fit").exists());
+
+        options.gap_threshold = longest_gap;
+        super::process_files(&options).expect("error during processing files");
+        assert!(archive_dir
+            .join(crate::QUARANTINE_PATH)
+            .join("test_data_01-4764a321.fit")
+            .exists());
+        let index = crate::index::Index::open(&archive_dir, false)
+            .unwrap()
+            .unwrap();
+        let entries = index.entries().unwrap();
+        assert_eq!(
+            vec![serde_json::json!(0), serde_json::json!(1)],
+            entries
+                .iter()
+                .map(|entry| entry["has_gaps"].clone())
+                .collect::<Vec<_>>()
+        );
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    /// Test running commands for the archived files
+    fn test_process_files_exec() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+        let batch_dir = tmpdir.path().join("batch");
+        fs::create_dir(&batch_dir).unwrap();
+
+        let mut options = super::Options {
+            directory: archive_dir.clone(),
+            file_template: String::from("%Y-%m-%d"),
+            exec: Some(format!(
+                "cp {{dst}} '{}/{{sport}}-{{timestamp}}.fit'",
+                tmpdir.path().display()
+            )),
+            exec_batch: Some(format!("cp {{src}} '{}'", batch_dir.display())),
+            files: vec![test_data("test_data_01.fit")],
+            ..Default::default()
+        };
+        let msg = super::process_files(&options).expect("error during processing files");
+        assert!(!msg.contains("errors"));
+        let activity_data = super::parse_fit_file(&test_data("test_data_01.fit")).unwrap();
+        let timestamp = activity_data
+            .timestamp
+            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        assert!(tmpdir
+            .path()
+            .join(format!("{}-{}.fit", activity_data.sport, timestamp))
+            .exists());
+        assert!(batch_dir.join("test_data_01.fit").exists());
+
+        // files archived before are not passed to the commands
+        fs::remove_file(batch_dir.join("test_data_01.fit")).unwrap();
+        options.exec = Some(String::from("false"));
+        let msg = super::process_files(&options).expect("error during processing files");
+        assert!(!msg.contains("errors"));
+        assert!(!batch_dir.join("test_data_01.fit").exists());
+
+        // a failing command is an error of the file
+        fs::remove_dir_all(&archive_dir).unwrap();
+        let msg = super::process_files(&options).expect("error during processing files");
+        assert!(msg.ends_with("with 1 errors."));
+        assert!(archive_dir.join("2023-07-26.fit").exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test archiving device state files apart from the activities
+    fn test_process_files_device_state() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+        let totals_path = tmpdir.path().join("totals.fit");
+        crate::state::write_totals_file(&totals_path);
+
+        let options = super::Options {
+            directory: archive_dir.clone(),
+            file_template: String::from("%Y-%m-%d"),
+            files: vec![test_data("test_data_01.fit"), totals_path],
+            ..Default::default()
+        };
+        let msg = super::process_files(&options).expect("error during processing files");
+        assert!(msg.starts_with("Processed 2 files"));
+        assert!(archive_dir.join("2023-07-26.fit").exists());
+        // the snapshot is not quarantined for the old creation time on the device
+        assert!(archive_dir
+            .join("device-state/fenix7-3345678901/2024-07-03-totals.fit")
+            .exists());
+        assert!(!archive_dir.join(crate::QUARANTINE_PATH).exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test handling input files that are not FIT files apart from damaged FIT files
+    fn test_process_files_invalid() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+        let empty_path = tmpdir.path().join("empty.fit");
+        File::create(&empty_path).unwrap();
+        let damaged_path = tmpdir.path().join("damaged.fit");
+        let content = fs::read(test_data("test_data_01.fit")).unwrap();
+        fs::write(&damaged_path, &content[..content.len() / 2]).unwrap();
+        assert!(!super::has_fit_header(&empty_path).unwrap());
+        assert!(!super::has_fit_header(&test_data("corrupted.fit")).unwrap());
+        assert!(super::has_fit_header(&damaged_path).unwrap());
+
+        let mut options = super::Options {
+            directory: archive_dir.clone(),
+            index: false,
+            files: vec![empty_path, damaged_path],
+            ..Default::default()
+        };
+        assert_eq!(
+            "Processed 0 files, 1 not FIT files, 1 damaged FIT files with 2 errors.",
+            super::process_files(&options).unwrap()
+        );
+
+        options.on_invalid = super::InvalidPolicy::Skip;
+        assert_eq!(
+            "Processed 0 files, 1 not FIT files, 1 damaged FIT files with 1 errors.",
+            super::process_files(&options).unwrap()
+        );
+        assert!(!archive_dir.join(crate::QUARANTINE_PATH).exists());
+
+        options.on_invalid = super::InvalidPolicy::Quarantine;
+        assert_eq!(
+            "Processed 0 files, 1 not FIT files, 1 damaged FIT files with 1 errors.",
+            super::process_files(&options).unwrap()
+        );
+        assert!(archive_dir
+            .join(crate::QUARANTINE_PATH)
+            .join("empty-e3b0c442.fit")
+            .exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test skipping activities by the position filters
+    fn test_process_files_position_filter() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+
+        // the test activity contains GPS positions
+        let mut options = super::Options {
+            directory: archive_dir.clone(),
+            file_template: String::from("activity"),
+            indoor_only: true,
+            files: vec![test_data("test_data_01.fit")],
+            ..Default::default()
+        };
+        let result = super::process_files(&options).expect("error during processing files");
+        assert_eq!(String::from("Processed 0 files, skipped 1 files "), result);
+        assert!(!archive_dir.join("activity.fit").exists());
+
+        // a dry run reports the second copy of the same file as archived before
+        let copy = tmpdir.path().join("copy.fit");
+        fs::copy(test_data("test_data_01.fit"), &copy).unwrap();
+        options.indoor_only = false;
+        options.dry_run = true;
+        options.files.push(copy);
+        let result = super::process_files(&options).expect("error during processing files");
+        assert_eq!(
+            String::from("Processed 1 files, 1 already archived "),
+            result
+        );
+        assert!(!archive_dir.join("activity.fit").exists());
+
+        options.dry_run = false;
+        options.files.pop();
+        options.require_gps = true;
+        let result = super::process_files(&options).expect("error during processing files");
+        assert_eq!(String::from("Processed 1 files "), result);
+        assert!(archive_dir.join("activity.fit").exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test refusing to modify a read-only source
+    fn test_check_readonly_source() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let source_dir = tmpdir.path().join("device");
+        fs::create_dir_all(&source_dir).expect("error during creating temporary directory");
+        let mut options = super::Options {
+            directory: tmpdir.path().join("archive"),
+            readonly_source: true,
+            files: vec![source_dir.clone(), source_dir.join("run.fit")],
+            ..Default::default()
+        };
+        super::check_readonly_source(&options).unwrap();
+
+        options.directory = source_dir.join("archive");
+        super::check_readonly_source(&options).expect_err("error expected");
+        options.directory = tmpdir.path().to_path_buf();
+        super::check_readonly_source(&options).expect_err("error expected");
+        options.directory = tmpdir.path().join("archive");
+        options.move_files = true;
+        super::check_readonly_source(&options).expect_err("error expected");
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test counting the archived files per destination directory
+    fn test_process_files_grouped() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+        let source = tmpdir.path().join("source.fit");
+        fs::copy(test_data("test_data_01.fit"), &source).unwrap();
+
+        let options = super::Options {
+            directory: archive_dir.clone(),
+            file_template: String::from("%Y/%m/activity"),
+            files: vec![source.clone()],
+            output: super::OutputFormat::Grouped,
+            ..Default::default()
+        };
+        let (_, mut summary) =
+            super::process_files_summary(&options).expect("error during processing files");
+        assert_eq!(
+            vec![(PathBuf::from("2023/07"), 1)],
+            summary.directories.clone().into_iter().collect::<Vec<_>>()
+        );
+
+        // the directories of all batches are counted together
+        summary.add(&super::Summary {
+            directories: [(PathBuf::from("2023/07"), 2), (PathBuf::new(), 1)].into(),
+            ..Default::default()
+        });
+        assert_eq!(
+            "./ \u{2014} 1 file\n2023/07/ \u{2014} 3 files\n",
+            summary.grouped_report()
+        );
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test retrying the files that failed to be archived
+    fn test_process_files_retry_failed() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+        let source = tmpdir.path().join("source.fit");
+        fs::copy(test_data("test_data_01.fit"), &source).unwrap();
+        // a file in place of the year directory fails the batch like a full disk
+        fs::create_dir_all(&archive_dir).unwrap();
+        fs::write(archive_dir.join("2023"), b"").unwrap();
+
+        let mut options = super::Options {
+            index: true,
+            directory: archive_dir.clone(),
+            file_template: String::from("%Y/activity"),
+            files: vec![source.clone()],
+            ..Default::default()
+        };
+        super::process_files(&options).expect_err("error expected");
+        let failures = || {
+            crate::index::Index::open(&archive_dir, false)
+                .unwrap()
+                .unwrap()
+                .failures()
+                .unwrap()
+        };
+        assert_eq!(1, failures().len());
+        assert_eq!(std::path::absolute(&source).unwrap(), failures()[0].0);
+        assert!(failures()[0].1.contains("is not a directory"));
+
+        // only the failed files are archived again
+        fs::remove_file(archive_dir.join("2023")).unwrap();
+        options.files = Vec::new();
+        options.retry_failed = true;
+        let result = super::process_files(&options).expect("error during processing files");
+        assert_eq!(String::from("Processed 1 files "), result);
+        assert!(archive_dir.join("2023/activity.fit").exists());
+        assert!(failures().is_empty());
+
+        // failed files that are gone are dropped
+        crate::index::Index::open(&archive_dir, true)
+            .unwrap()
+            .unwrap()
+            .add_failure(
+                &tmpdir.path().join("gone.fit"),
+                "Disk full",
+                chrono::Utc::now(),
+            )
+            .unwrap();
+        let result = super::process_files(&options).expect("error during processing files");
+        assert_eq!(String::from("Processed 0 files "), result);
+        assert!(failures().is_empty());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test skipping files already archived under another name
+    fn test_process_files_skip_duplicates() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+        let source = tmpdir.path().join("source.fit");
+        fs::copy(test_data("test_data_01.fit"), &source).unwrap();
+
+        let mut options = super::Options {
+            index: true,
+            directory: archive_dir.clone(),
+            file_template: String::from("activity"),
+            files: vec![source.clone()],
+            ..Default::default()
+        };
+        let result = super::process_files(&options).expect("error during processing files");
+        assert_eq!(String::from("Processed 1 files "), result);
+
+        // the content is found in the index regardless of the template
+        options.file_template = String::from("renamed");
+        options.skip_duplicates = true;
+        options.move_files = true;
+        let result = super::process_files(&options).expect("error during processing files");
+        assert_eq!(
+            String::from("Processed 0 files, 1 already archived "),
+            result
+        );
+        assert!(!archive_dir.join("renamed.fit").exists());
+        assert!(!source.exists());
+
+        // hashes of another algorithm never match
+        fs::copy(test_data("test_data_01.fit"), &source).unwrap();
+        options.hash_algorithm = super::HashAlgorithm::Xxh64;
+        let result = super::process_files(&options).expect("error during processing files");
+        assert_eq!(String::from("Processed 1 files "), result);
+        assert!(archive_dir.join("renamed.fit").exists());
+
+        fs::copy(test_data("test_data_01.fit"), &source).unwrap();
+        options.file_template = String::from("xxh64");
+        let result = super::process_files(&options).expect("error during processing files");
+        assert_eq!(
+            String::from("Processed 0 files, 1 already archived "),
+            result
+        );
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    /// Resolver giving the same decision for all conflicts
+    #[derive(Debug)]
+    struct FixedResolver(crate::Resolution);
+
+    impl crate::ConflictResolver for FixedResolver {
+        fn resolve(
+            &self,
+            _source: &crate::ConflictFile,
+            _activity: &serde_json::Value,
+            _existing: &crate::ConflictFile,
+        ) -> crate::Resolution {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    /// Test resolving conflicts with a resolver of the application
+    fn test_process_files_conflict_resolver() {
+        use crate::Resolution;
+        use std::sync::Arc;
+
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+        let archive_path = archive_dir.join("activity.fit");
+        let source = tmpdir.path().join("source.fit");
+        fs::create_dir_all(&archive_dir).unwrap();
+        fs::write(&archive_path, "old").unwrap();
+        fs::copy(test_data("test_data_01.fit"), &source).unwrap();
+        let mut options = super::Options {
+            directory: archive_dir.clone(),
+            file_template: String::from("activity"),
+            on_conflict: super::ConflictPolicy::Skip,
+            files: vec![source.clone()],
+            ..Default::default()
+        };
+        let resolve = |options: &mut super::Options, resolution| {
+            options.conflict_resolver = Some(Arc::new(FixedResolver(resolution)));
+            super::process_files(options).expect("error during processing files")
+        };
+
+        assert_eq!(
+            String::from("Processed 0 files, skipped 1 files "),
+            resolve(&mut options, Resolution::Skip)
+        );
+        assert_eq!(
+            String::from("Processed 1 files "),
+            resolve(&mut options, Resolution::Rename(String::from("second.fit")))
+        );
+        assert!(super::same_content(&source, &archive_dir.join("second.fit")).unwrap());
+        assert_eq!(
+            String::from("Processed 1 files "),
+            resolve(&mut options, Resolution::Replace)
+        );
+        assert!(super::same_content(&source, &archive_path).unwrap());
+
+        // the existing archive file counts as archived version of the source
+        fs::write(&archive_path, "old").unwrap();
+        options.move_files = true;
+        assert_eq!(
+            String::from("Processed 0 files, 1 already archived "),
+            resolve(&mut options, Resolution::Keep)
+        );
+        assert_eq!("old", fs::read_to_string(&archive_path).unwrap());
+        assert!(!source.exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test writing GPX files next to the archive files or in a parallel tree
+    fn test_process_files_export_gpx() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+        let mut options = super::Options {
+            directory: archive_dir.clone(),
+            file_template: String::from("activity"),
+            export_gpx: true,
+            files: vec![test_data("test_data_01.fit")],
+            ..Default::default()
+        };
+        let result = super::process_files(&options).expect("error during processing files");
+        assert_eq!(String::from("Processed 1 files "), result);
+        assert!(archive_dir.join("activity.gpx").exists());
+
+        // missing exports of files archived before are written
+        options.export_template = Some(String::from("exports/%Y/$s-%Y%m%d.gpx"));
+        let result = super::process_files(&options).expect("error during processing files");
+        assert_eq!(
+            String::from("Processed 0 files, 1 already archived "),
+            result
+        );
+        assert!(archive_dir
+            .join("exports/2023/running-20230726.gpx")
+            .exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test recording the checksums of the archive files
+    fn test_process_files_checksums() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+        let options = super::Options {
+            directory: archive_dir.clone(),
+            file_template: String::from("%Y/activity"),
+            checksums: true,
+            files: vec![test_data("test_data_01.fit")],
+            ..Default::default()
+        };
+        super::process_files(&options).expect("error during processing files");
+        let checksums = fs::read_to_string(archive_dir.join("2023").join(crate::CHECKSUM_FILE))
+            .expect("checksum file expected");
+        assert!(checksums.ends_with("  activity.fit\n"));
+
+        let verify_options = crate::VerifyOptions {
+            directory: archive_dir.clone(),
+            ..Default::default()
+        };
+        assert_eq!(
+            "Verified 1 files, 0 corrupted, 0 missing, 0 without checksum",
+            crate::verify(&verify_options).unwrap()
+        );
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test processing large numbers of files in batches
+    fn test_process_files_batch_size() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+        let mut files = Vec::new();
+        for name in ["a.fit", "b.fit", "c.fit"] {
+            let source = tmpdir.path().join(name);
+            fs::copy(test_data("test_data_01.fit"), &source).unwrap();
+            files.push(source);
+        }
+        files.push(test_data("corrupted.fit"));
+
+        // the summary covers all batches, the source files are deleted per batch
+        let options = super::Options {
+            directory: archive_dir.clone(),
+            file_template: String::from("activity"),
+            move_files: true,
+            delete_sources: super::SourceDeletion::AfterCleanBatch,
+            batch_size: 2,
+            files,
+            ..Default::default()
+        };
+        assert_eq!(
+            "Processed 1 files, 2 already archived, 1 not FIT files, kept 1 source files with 1 errors.",
+            super::process_files(&options).unwrap()
+        );
+        assert!(!tmpdir.path().join("a.fit").exists());
+        assert!(!tmpdir.path().join("b.fit").exists());
+        assert!(tmpdir.path().join("c.fit").exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test processing the files of a batch in parallel
+    fn test_process_files_jobs() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive_dir");
+        let mut files = Vec::new();
+        for name in ["a.fit", "b.fit", "c.fit"] {
+            let source = tmpdir.path().join(name);
+            fs::copy(test_data("test_data_01.fit"), &source).unwrap();
+            files.push(source);
+        }
+        files.push(test_data("corrupted.fit"));
+
+        // files with the same archive path are archived one after another
+        let options = super::Options {
+            directory: archive_dir.clone(),
+            file_template: String::from("activity"),
+            duplicate_policy: super::DuplicatePolicy::KeepBoth,
+            jobs: 4,
+            files,
+            ..Default::default()
+        };
+        let result = super::process_files(&options).expect("error during processing files");
+        assert_eq!(
+            String::from("Processed 1 files, 2 already archived, 1 not FIT files with 1 errors."),
+            result
+        );
+        assert!(archive_dir.join("activity.fit").exists());
+
+        // files with the same content archived in parallel share one object
+        let options = super::Options {
+            directory: tmpdir.path().join("objects_dir"),
+            file_template: String::from("%H%M%S"),
+            layout: crate::objects::StorageLayout::Hardlink,
+            ..options
+        };
+        let mut parsed: Vec<super::Result<super::ActivityData>> = (0..3)
+            .map(|second| {
+                Ok(super::ActivityData {
+                    timestamp: chrono::Utc
+                        .with_ymd_and_hms(2023, 7, 26, 6, 0, second)
+                        .unwrap(),
+                    ..super::ActivityData::new()
+                })
+            })
+            .collect();
+        parsed.push(Err(super::ArchiverError::new("corrupted")));
+        let archived = super::archive_files_parallel(
+            &parsed,
+            &[false, true, false, false],
+            None,
+            &crate::storage::LocalStorage::default(),
+            &options,
+        );
+        assert_eq!(
+            vec![true, false, true, false],
+            archived
+                .iter()
+                .map(|archived| matches!(archived, Some((Ok(Ok(super::Status::Copied)), _))))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            1,
+            fs::read_dir(options.directory.join(crate::objects::OBJECTS_PATH))
+                .unwrap()
+                .count()
+        );
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test applying a function to items in parallel
+    fn test_parallel_map() {
+        let items: Vec<u64> = (0..100).collect();
+        for jobs in [1, 3, 200] {
+            assert_eq!(
+                items.iter().map(|item| item * 2).collect::<Vec<_>>(),
+                super::parallel_map(&items, jobs, |item| item * 2)
+            );
+        }
+    }
 
     #[test]
-    /// Test format string expansion
-    fn test_expand_formatstring() {
-        // setup activity data
-        let activity_data = super::ActivityData {
-            sport: String::from("running"),
-            sport_name: String::from("training"),
-            sub_sport: String::from("trail"),
-            workout_name: String::from("interval"),
-            timestamp: chrono::Utc.with_ymd_and_hms(2014, 7, 8, 9, 10, 11).unwrap(),
+    /// Test numbering activities within their day
+    fn test_day_sequences() {
+        let activity = |day: u32, hour: u32| super::ActivityData {
+            timestamp: chrono::Utc
+                .with_ymd_and_hms(2023, 7, day, hour, 0, 0)
+                .unwrap(),
+            ..super::ActivityData::new()
         };
+        let evening = activity(26, 18);
+        let morning = activity(26, 6);
+        let duplicate = activity(26, 5);
+        let next_day = activity(27, 5);
+        let activities = vec![
+            Some(&evening),
+            Some(&morning),
+            None,
+            Some(&duplicate),
+            Some(&next_day),
+        ];
+        let skipped = vec![false, false, false, true, false];
 
-        // default format string
         assert_eq!(
-            String::from("2014/07/2014-07-08-091011-running"),
-            super::expand_formatstring("%Y/%m/%Y-%m-%d-%H%M%S-$s", &activity_data)
+            vec![2, 1, 1, 1, 1],
+            super::day_sequences(&activities, &skipped, &[], &Default::default())
         );
 
-        // single tags
+        // already archived activities are counted once
+        let known = vec![activity(26, 4).timestamp, morning.timestamp];
         assert_eq!(
-            String::from("running"),
-            super::expand_formatstring("$s", &activity_data)
+            vec![3, 2, 1, 1, 1],
+            super::day_sequences(&activities, &skipped, &known, &Default::default())
         );
+
+        // the evening is the next day east of UTC+6
+        let timezone: crate::Timezone = "+07:00".parse().unwrap();
         assert_eq!(
-            String::from("training"),
-            super::expand_formatstring("$n", &activity_data)
+            vec![1, 2, 1, 1, 2],
+            super::day_sequences(&activities, &skipped, &known, &timezone)
         );
+    }
+
+    /// Returns activity data for testing duplicate detection
+    fn duplicate_activity(second: u32, product: &str, record_count: usize) -> super::ActivityData {
+        super::ActivityData {
+            timestamp: chrono::Utc
+                .with_ymd_and_hms(2023, 7, 26, 6, 22, second)
+                .unwrap(),
+            manufacturer: String::from("garmin"),
+            product: String::from(product),
+            record_count,
+            ..super::ActivityData::new()
+        }
+    }
+
+    #[test]
+    /// Test parsing duplicate policies
+    fn test_duplicate_policy_from_str() {
+        use super::DuplicatePolicy;
+
         assert_eq!(
-            String::from("trail"),
-            super::expand_formatstring("$S", &activity_data)
+            DuplicatePolicy::KeepBoth,
+            "keep-both".parse::<DuplicatePolicy>().unwrap()
         );
         assert_eq!(
-            String::from("interval"),
-            super::expand_formatstring("$w", &activity_data)
+            DuplicatePolicy::KeepRichest,
+            "keep-richest".parse::<DuplicatePolicy>().unwrap()
         );
-
-        // repeated tags
         assert_eq!(
-            String::from("running-running-running-running"),
-            super::expand_formatstring("$s-$s-$s-$s", &activity_data)
+            DuplicatePolicy::PreferDevice(String::from("edge")),
+            "prefer-device=edge".parse::<DuplicatePolicy>().unwrap()
         );
+        "prefer-device="
+            .parse::<DuplicatePolicy>()
+            .expect_err("error expected");
+        "keep-all"
+            .parse::<DuplicatePolicy>()
+            .expect_err("error expected");
     }
 
     #[test]
-    // Test creating the archive directory
-    fn test_create_archive_directory() {
-        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
-        let source_path = tmpdir.path().join("source_dir").join("source.fit");
-        let archive_file = tmpdir.path().join("archive_dir").join("archive.fit");
+    /// Test detection of the same activity recorded on several devices
+    fn test_find_duplicates() {
+        use super::DuplicatePolicy;
 
-        let options = super::parse_arguments(Some(vec![
-            "fitarchiver",
-            "-d",
-            archive_file.parent().unwrap().as_os_str().to_str().unwrap(),
-            source_path.as_os_str().to_str().unwrap(),
-        ]));
+        let watch = duplicate_activity(4, "epix_gen2_pro_51", 100);
+        let head_unit = duplicate_activity(30, "edge_1040", 200);
+        let other = duplicate_activity(4, "epix_gen2_pro_51", 10);
+        let mut other_day = duplicate_activity(4, "edge_1040", 300);
+        other_day.timestamp += chrono::Duration::days(1);
+        let activities = vec![Some(&watch), None, Some(&head_unit), Some(&other_day)];
 
-        assert!(!archive_file.parent().unwrap().exists());
-        super::create_archive_directory(&archive_file, &options)
-            .expect("error during creating directory");
-        assert!(archive_file.parent().unwrap().exists());
+        assert_eq!(
+            vec![false, false, false, false],
+            super::find_duplicates(&activities, &DuplicatePolicy::KeepBoth, 60)
+        );
+        assert_eq!(
+            vec![true, false, false, false],
+            super::find_duplicates(&activities, &DuplicatePolicy::KeepRichest, 60)
+        );
+        assert_eq!(
+            vec![false, false, false, false],
+            super::find_duplicates(&activities, &DuplicatePolicy::KeepRichest, 10)
+        );
+        assert_eq!(
+            vec![false, false, true, false],
+            super::find_duplicates(
+                &activities,
+                &DuplicatePolicy::PreferDevice(String::from("EPIX")),
+                60
+            )
+        );
 
-        // cleanup
-        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
-    }
+        // fall back to the richest file if no file was recorded by the preferred device
+        assert_eq!(
+            vec![true, false, false, false],
+            super::find_duplicates(
+                &activities,
+                &DuplicatePolicy::PreferDevice(String::from("fenix")),
+                60
+            )
+        );
 
-    #[test]
-    // Test not creating the archive directory in dry run
-    fn test_create_archive_directory_dry_run() {
-        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
-        let source_path = tmpdir.path().join("source_dir").join("source.fit");
-        let archive_file = tmpdir.path().join("archive_dir").join("archive.fit");
+        // keep the richest file of the preferred device
+        let activities = vec![Some(&other), Some(&watch), Some(&head_unit)];
+        assert_eq!(
+            vec![true, false, true],
+            super::find_duplicates(
+                &activities,
+                &DuplicatePolicy::PreferDevice(String::from("epix")),
+                60
+            )
+        );
+    }
 
-        let options = super::parse_arguments(Some(vec![
-            "fitarchiver",
-            "-n",
-            "-d",
-            archive_file.parent().unwrap().as_os_str().to_str().unwrap(),
-            source_path.as_os_str().to_str().unwrap(),
-        ]));
+    /// Create source and archive file with given content and modification times
+    fn conflict_files(
+        tmpdir: &TempDir,
+        source_content: &str,
+        archive_content: &str,
+        source_newer: bool,
+    ) -> (PathBuf, PathBuf) {
+        let source_path = tmpdir.path().join("source.fit");
+        let archive_path = tmpdir.path().join("archive.fit");
+        let now = std::time::SystemTime::now();
+        let earlier = now - std::time::Duration::from_secs(3600);
 
-        assert!(!archive_file.parent().unwrap().exists());
-        super::create_archive_directory(&archive_file, &options)
-            .expect("error during creating directory");
-        assert!(!archive_file.parent().unwrap().exists());
+        fs::write(&source_path, source_content).expect("unable to create test file");
+        fs::write(&archive_path, archive_content).expect("unable to create test file");
+        File::options()
+            .write(true)
+            .open(&source_path)
+            .and_then(|f| f.set_modified(if source_newer { now } else { earlier }))
+            .expect("unable to set modification time");
+        File::options()
+            .write(true)
+            .open(&archive_path)
+            .and_then(|f| f.set_modified(if source_newer { earlier } else { now }))
+            .expect("unable to set modification time");
 
-        // cleanup
-        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+        (source_path, archive_path)
     }
 
     #[test]
-    // Test failure when parent of archive directory is missing
-    fn test_create_archive_directory_failure_parent_missing() {
+    /// Test comparing file contents
+    fn test_same_content() {
         let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
-        let source_path = tmpdir.path().join("source_dir").join("source.fit");
-        let archive_path = PathBuf::new();
+        let (source_path, archive_path) = conflict_files(&tmpdir, "same", "same", true);
+        assert!(super::same_content(&source_path, &archive_path).unwrap());
 
-        let options = super::parse_arguments(Some(vec![
-            "fitarchiver",
-            "-d",
-            archive_path.as_os_str().to_str().unwrap(),
-            source_path.as_os_str().to_str().unwrap(),
-        ]));
+        let (source_path, archive_path) = conflict_files(&tmpdir, "same", "diff", true);
+        assert!(!super::same_content(&source_path, &archive_path).unwrap());
 
-        super::create_archive_directory(&archive_path, &options).expect_err("error expected");
+        let (source_path, archive_path) = conflict_files(&tmpdir, "same", "longer", true);
+        assert!(!super::same_content(&source_path, &archive_path).unwrap());
 
         // cleanup
         fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
     }
 
     #[test]
-    // Test failure when archive directory is a file
-    fn test_create_archive_directory_failure_file_exists() {
-        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
-        let source_path = tmpdir.path().join("source_dir").join("source.fit");
-        let archive_file = tmpdir.path().join("archive_dir").join("archive.fit");
+    /// Test conflict policies for existing archive files
+    fn test_archive_file_conflict() {
+        use super::{ConflictPolicy, Status};
 
-        let options = super::parse_arguments(Some(vec![
-            "fitarchiver",
-            "-d",
-            archive_file.parent().unwrap().as_os_str().to_str().unwrap(),
-            source_path.as_os_str().to_str().unwrap(),
-        ]));
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive = |source_path: &PathBuf, archive_path: &PathBuf, policy| {
+            let options = super::Options {
+                directory: tmpdir.path().to_path_buf(),
+                on_conflict: policy,
+                ..Default::default()
+            };
+            super::archive_file(source_path, archive_path, &options)
+                .expect("error during archiving file")
+        };
 
-        std::fs::File::create(&archive_file.parent().unwrap())
-            .expect("error during creating directory");
-        super::create_archive_directory(&archive_file, &options).expect_err("error expected");
+        // overwrite
+        let (source_path, archive_path) = conflict_files(&tmpdir, "new", "old", false);
+        assert_eq!(
+            Status::Copied,
+            archive(&source_path, &archive_path, ConflictPolicy::Overwrite)
+        );
+        assert_eq!("new", fs::read_to_string(&archive_path).unwrap());
+        // the replaced archive file is kept as backup
+        let backups = fs::read_dir(tmpdir.path().join(crate::BACKUP_PATH))
+            .unwrap()
+            .flatten()
+            .map(|snapshot| snapshot.path().join("archive.fit"))
+            .collect::<Vec<_>>();
+        assert_eq!(1, backups.len());
+        assert_eq!("old", fs::read_to_string(&backups[0]).unwrap());
 
-        // cleanup
-        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
-    }
+        // skip
+        let (source_path, archive_path) = conflict_files(&tmpdir, "new", "old", true);
+        assert_eq!(
+            Status::Skipped,
+            archive(&source_path, &archive_path, ConflictPolicy::Skip)
+        );
+        assert_eq!("old", fs::read_to_string(&archive_path).unwrap());
 
-    #[test]
-    // Test failure when archive directory is not a directory
-    fn test_create_archive_directory_failure_unable_to_create() {
-        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
-        let source_path = tmpdir.path().join("source_dir").join("source.fit");
-        let archive_file = PathBuf::from("/").join("archive_dir").join("archive.fit");
+        // overwrite if newer with older source
+        let (source_path, archive_path) = conflict_files(&tmpdir, "new", "old", false);
+        assert_eq!(
+            Status::Skipped,
+            archive(
+                &source_path,
+                &archive_path,
+                ConflictPolicy::OverwriteIfNewer
+            )
+        );
+        assert_eq!("old", fs::read_to_string(&archive_path).unwrap());
 
-        let options = super::parse_arguments(Some(vec![
-            "fitarchiver",
-            "-d",
-            archive_file.parent().unwrap().as_os_str().to_str().unwrap(),
-            source_path.as_os_str().to_str().unwrap(),
-        ]));
+        // overwrite if newer with newer source and same content
+        let (source_path, archive_path) = conflict_files(&tmpdir, "old", "old", true);
+        assert_eq!(
+            Status::Identical,
+            archive(
+                &source_path,
+                &archive_path,
+                ConflictPolicy::OverwriteIfNewer
+            )
+        );
 
-        super::create_archive_directory(&archive_file, &options).expect_err("error expected");
+        // overwrite if newer with newer source and different content
+        let (source_path, archive_path) = conflict_files(&tmpdir, "new", "old", true);
+        assert_eq!(
+            Status::Copied,
+            archive(
+                &source_path,
+                &archive_path,
+                ConflictPolicy::OverwriteIfNewer
+            )
+        );
+        assert_eq!("new", fs::read_to_string(&archive_path).unwrap());
 
         // cleanup
         fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
     }
 
     #[test]
-    /// Test dry run
-    fn test_archive_file_dry_run() {
-        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
-        let source_path = tmpdir.path().join("source_dir").join("source.fit");
-        let archive_file = tmpdir.path().join("archive_dir").join("archive.fit");
+    /// Test identical archive files are recognized as archived before
+    fn test_archive_file_identical() {
+        use super::{ConflictPolicy, Status};
 
-        {
-            // put file creation into a separate scope so the file is closed for the actual test
-            fs::create_dir_all(&source_path.parent().unwrap())
-                .expect("error during creating temporary archive directory");
-            fs::create_dir_all(&archive_file.parent().unwrap())
-                .expect("error during creating temporary archive directory");
-            File::create(&source_path).expect("unable to create test file");
-        }
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
 
-        let options = super::parse_arguments(Some(vec![
-            "fitarchiver",
-            "-n",
-            "-d",
-            archive_file.parent().unwrap().as_os_str().to_str().unwrap(),
-            "-f",
-            "archive",
-            source_path.as_os_str().to_str().unwrap(),
-        ]));
+        // copy keeps the source file
+        let (source_path, archive_path) = conflict_files(&tmpdir, "same", "same", true);
+        let options = super::Options {
+            on_conflict: ConflictPolicy::Skip,
+            ..Default::default()
+        };
+        assert_eq!(
+            Status::Identical,
+            super::archive_file(&source_path, &archive_path, &options).unwrap()
+        );
+        assert!(source_path.exists());
 
+        // move in dry run keeps the source file
+        let options = super::Options {
+            move_files: true,
+            dry_run: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            Status::Identical,
+            super::archive_file(&source_path, &archive_path, &options).unwrap()
+        );
         assert!(source_path.exists());
-        assert!(!archive_file.exists());
-        super::archive_file(&source_path, &archive_file, &options)
-            .expect("error during archiving file");
+
+        // move keeps the source file until the end of the batch
+        let mut options = super::Options {
+            move_files: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            Status::Identical,
+            super::archive_file(&source_path, &archive_path, &options).unwrap()
+        );
         assert!(source_path.exists());
-        assert!(!archive_file.exists());
+
+        // move removes the source file immediately
+        options.delete_sources = super::SourceDeletion::Immediately;
+        assert_eq!(
+            Status::Identical,
+            super::archive_file(&source_path, &archive_path, &options).unwrap()
+        );
+        assert!(!source_path.exists());
+        assert!(archive_path.exists());
 
         // cleanup
         fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
     }
 
     #[test]
-    /// Test copying file to archive
-    fn test_archive_file_copy() {
-        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
-        let source_path = tmpdir.path().join("source_dir").join("source.fit");
-        let archive_file = tmpdir.path().join("archive_dir").join("archive.fit");
+    /// Test archiving a file onto itself is a no-op
+    fn test_archive_file_same_file() {
+        use super::Status;
 
-        {
-            // put file creation into a separate scope so the file is closed for the actual test
-            fs::create_dir_all(&source_path.parent().unwrap())
-                .expect("error during creating temporary archive directory");
-            fs::create_dir_all(&archive_file.parent().unwrap())
-                .expect("error during creating temporary archive directory");
-            File::create(&source_path).expect("unable to create test file");
-        }
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_path = tmpdir.path().join("archive_dir").join("archive.fit");
+        fs::create_dir_all(archive_path.parent().unwrap())
+            .expect("error during creating temporary archive directory");
+        fs::write(&archive_path, "content").expect("unable to create test file");
 
-        let options = super::parse_arguments(Some(vec![
-            "fitarchiver",
-            "-d",
-            archive_file.parent().unwrap().as_os_str().to_str().unwrap(),
-            "-f",
-            "archive",
-            source_path.as_os_str().to_str().unwrap(),
-        ]));
+        // same file reached through a different path
+        let source_path = tmpdir
+            .path()
+            .join("archive_dir")
+            .join("..")
+            .join("archive_dir")
+            .join("archive.fit");
+        assert!(super::same_file(&source_path, &archive_path));
+        assert!(!super::same_file(
+            &tmpdir.path().join("missing.fit"),
+            &archive_path
+        ));
 
-        assert!(source_path.exists());
-        assert!(!archive_file.exists());
-        super::archive_file(&source_path, &archive_file, &options)
-            .expect("error during archiving file");
-        assert!(source_path.exists());
-        assert!(archive_file.exists());
+        let options = super::Options {
+            move_files: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            Status::Identical,
+            super::archive_file(&source_path, &archive_path, &options).unwrap()
+        );
+        assert!(archive_path.exists());
+        assert_eq!("content", fs::read_to_string(&archive_path).unwrap());
 
         // cleanup
         fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
     }
 
     #[test]
-    /// Test moving file to archive
-    fn test_archive_file_move() {
+    /// Test searching FIT files in a directory tree
+    fn test_find_fit_files() {
         let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
-        let source_path = tmpdir.path().join("source_dir").join("source.fit");
-        let archive_file = tmpdir.path().join("archive_dir").join("archive.fit");
-
-        {
-            // put file creation into a separate scope so the file is closed for the actual test
-            fs::create_dir_all(&source_path.parent().unwrap())
-                .expect("error during creating temporary archive directory");
-            fs::create_dir_all(&archive_file.parent().unwrap())
-                .expect("error during creating temporary archive directory");
-            File::create(&source_path).expect("unable to create test file");
+        for path in [
+            "2023/07/b.fit",
+            "2023/07/a.FIT",
+            "2023/08/c.fit",
+            "2023/08/notes.txt",
+            ".hidden/d.fit",
+            "e.fit",
+        ] {
+            let path = tmpdir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap())
+                .expect("error during creating temporary directory");
+            File::create(&path).expect("unable to create test file");
         }
 
-        let options = super::parse_arguments(Some(vec![
-            "fitarchiver",
-            "-m",
-            "-d",
-            archive_file.parent().unwrap().as_os_str().to_str().unwrap(),
-            "-f",
-            "archive",
-            source_path.as_os_str().to_str().unwrap(),
-        ]));
-
-        assert!(source_path.exists());
-        assert!(!archive_file.exists());
-        super::archive_file(&source_path, &archive_file, &options)
-            .expect("error during archiving file");
-        assert!(!source_path.exists());
-        assert!(archive_file.exists());
-
-        // cleanup
-        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
-    }
-
-    #[test]
-    /// Test extracting activity data from real FIT file
-    fn test_activity_data_from_file() {
-        // get the directory of the test executable
-        let mut source_path = std::env::current_exe()
-            .unwrap()
-            .parent()
-            .expect("executable directory")
-            .to_path_buf();
-
-        // go up to the repository base directory
-        source_path.pop();
-        source_path.pop();
-        source_path.pop();
-
-        // append location of the test data
-        source_path.push("test");
-        source_path.push("test_data_01.fit");
-
-        let result = super::parse_fit_file(&source_path);
-        assert!(result.is_ok());
-        let activity_data = result.unwrap();
-        assert_eq!(String::from("running"), activity_data.sport);
-        assert_eq!(String::from("trail_run"), activity_data.sport_name);
-        assert_eq!(String::from("trail"), activity_data.sub_sport);
-        assert_eq!(String::from("test_workout"), activity_data.workout_name);
         assert_eq!(
-            chrono::Utc.with_ymd_and_hms(2023, 7, 26, 6, 22, 4).unwrap(),
-            activity_data.timestamp
+            vec![
+                tmpdir.path().join("2023/07/a.FIT"),
+                tmpdir.path().join("2023/07/b.fit"),
+                tmpdir.path().join("2023/08/c.fit"),
+                tmpdir.path().join("e.fit"),
+            ],
+            super::find_fit_files(tmpdir.path()).unwrap()
+        );
+        super::find_fit_files(&tmpdir.path().join("missing")).expect_err("error expected");
+        assert_eq!(
+            vec![tmpdir.path().join("e.fit")],
+            super::find_fit_files_within(tmpdir.path(), Some(1), false, None, &[], false).unwrap()
         );
-    }
-
-    #[test]
-    /// Test activity file is missing
-    fn test_activity_data_from_file_failure_file_missing() {
-        // get the directory of the test executable
-        let mut source_path = std::env::current_exe()
-            .unwrap()
-            .parent()
-            .expect("executable directory")
-            .to_path_buf();
-
-        // go up to the repository base directory
-        source_path.pop();
-        source_path.pop();
-        source_path.pop();
-
-        // append location of the test data
-        source_path.push("test");
-        source_path.push("missing.fit");
 
-        super::parse_fit_file(&source_path).expect_err("error expected");
-    }
+        // additional extensions and sniffing find FIT files with unusual names
+        fs::copy(
+            test_data("test_data_01.fit"),
+            tmpdir.path().join("f.Fit.BAK"),
+        )
+        .unwrap();
+        fs::copy(test_data("test_data_01.fit"), tmpdir.path().join("g")).unwrap();
+        fs::copy(test_data("test_data_01.fit"), tmpdir.path().join("h.dat")).unwrap();
+        fs::write(tmpdir.path().join("notes"), "notes").unwrap();
+        let extensions = [String::from(".fit.bak"), String::new()];
+        assert_eq!(
+            vec![
+                tmpdir.path().join("e.fit"),
+                tmpdir.path().join("f.Fit.BAK"),
+                tmpdir.path().join("g"),
+                tmpdir.path().join("notes"),
+            ],
+            super::find_fit_files_within(tmpdir.path(), Some(1), false, None, &extensions, false)
+                .unwrap()
+        );
+        assert_eq!(
+            vec![
+                tmpdir.path().join("e.fit"),
+                tmpdir.path().join("f.Fit.BAK"),
+                tmpdir.path().join("g"),
+                tmpdir.path().join("h.dat"),
+            ],
+            super::find_fit_files_within(tmpdir.path(), Some(1), false, None, &[], true).unwrap()
+        );
+        for name in ["f.Fit.BAK", "g", "h.dat", "notes"] {
+            fs::remove_file(tmpdir.path().join(name)).unwrap();
+        }
 
-    #[test]
-    /// Test activity file is corrupted
-    fn test_activity_data_from_file_failure_file_empty() {
-        // get the directory of the test executable
-        let mut source_path = std::env::current_exe()
-            .unwrap()
-            .parent()
-            .expect("executable directory")
-            .to_path_buf();
+        // directories in the list of files are searched up to the maximum depth
+        let mut options = super::Options {
+            files: vec![
+                tmpdir.path().join("2023"),
+                tmpdir.path().join("2023/08/notes.txt"),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            vec![tmpdir.path().join("2023/08/notes.txt")],
+            super::input_files(&options).unwrap()
+        );
+        options.recursive = true;
+        assert_eq!(4, super::input_files(&options).unwrap().len());
+        options.max_depth = Some(1);
+        assert_eq!(1, super::input_files(&options).unwrap().len());
 
-        // go up to the repository base directory
-        source_path.pop();
-        source_path.pop();
-        source_path.pop();
+        // the ignore files of source directories are honored, not the ones of the archive
+        fs::write(
+            tmpdir.path().join("2023").join(crate::ignore::IGNORE_FILE),
+            "/08/\nb.fit\n",
+        )
+        .unwrap();
+        options.max_depth = None;
+        assert_eq!(
+            vec![
+                tmpdir.path().join("2023/07/a.FIT"),
+                tmpdir.path().join("2023/08/notes.txt"),
+            ],
+            super::input_files(&options).unwrap()
+        );
+        assert_eq!(4, super::find_fit_files(tmpdir.path()).unwrap().len());
 
-        // append location of the test data
-        source_path.push("test");
-        source_path.push("corrupted.fit");
+        // an archive directory inside a source directory is not searched
+        options.files = vec![tmpdir.path().to_path_buf()];
+        options.directory = tmpdir.path().join("2023/07");
+        assert_eq!(
+            vec![tmpdir.path().join("e.fit")],
+            super::input_files(&options).unwrap()
+        );
+        options.files = vec![tmpdir.path().join("2023/07")];
+        options.directory = tmpdir.path().to_path_buf();
+        super::input_files(&options).expect_err("error expected");
+        options.recursive = false;
+        assert_eq!(2, super::input_files(&options).unwrap().len());
 
-        super::parse_fit_file(&source_path).expect_err("error expected");
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
     }
 }
@@ -0,0 +1,201 @@
+//! # Consistency of the index and the files of an existing archive
+//!
+//! Files copied into the archive by hand are missing from the index, files removed by hand leave
+//! orphaned entries in the index. Both are reported and can be repaired by adopting the files
+//! into the index or by purging the orphaned entries.
+
+use crate::fitarchiver::{find_fit_files, parse_fit_file, ArchiverError, Result};
+use crate::index::{Index, INDEX_PATH};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// Options controlling the consistency check
+#[derive(Debug, Clone)]
+pub struct FsckOptions {
+    /// Base directory of the archive
+    pub directory: PathBuf,
+    /// Add files missing from the index to the index
+    pub adopt: bool,
+    /// Remove index entries whose files are gone from the index
+    pub purge: bool,
+    /// Do not modify the index, just show what will happen
+    pub dry_run: bool,
+}
+
+impl Default for FsckOptions {
+    fn default() -> Self {
+        FsckOptions {
+            directory: PathBuf::from("."),
+            adopt: false,
+            purge: false,
+            dry_run: false,
+        }
+    }
+}
+
+/// Returns the status of an inconsistency
+///
+/// # Arguments
+///
+/// * `repair` - The inconsistency is repaired.
+/// * `dry_run` - The repair is only shown.
+/// * `found` - Status of an inconsistency that is only reported.
+/// * `repaired` - Status of a repaired inconsistency.
+fn status(
+    repair: bool,
+    dry_run: bool,
+    found: &'static str,
+    repaired: &'static str,
+) -> &'static str {
+    match (repair, dry_run) {
+        (false, _) => found,
+        (true, true) => "dry run",
+        (true, false) => repaired,
+    }
+}
+
+/// Check that the index and the files of an archive match each other
+///
+/// Files in the cold directory of a tiered archive are found via their index entries.
+///
+/// # Arguments
+///
+/// `options` - Fsck options.
+pub fn fsck(options: &FsckOptions) -> Result<String> {
+    if !options.directory.join(INDEX_PATH).exists() {
+        let msg = format!(
+            "No index found in archive '{}'",
+            options.directory.display()
+        );
+        return Err(ArchiverError::new(&msg));
+    }
+    // opened for writing to migrate indexes created by older versions
+    let Some(index) = Index::open(&options.directory, true)? else {
+        return Ok(String::new());
+    };
+
+    let indexed = index.archive_paths()?;
+    let files = find_fit_files(&options.directory)?;
+    let known: HashSet<&PathBuf> = indexed.iter().collect();
+
+    let mut unindexed_counter: usize = 0;
+    let mut error_counter: usize = 0;
+    for path in files.iter().filter(|path| !known.contains(path)) {
+        let result = if options.adopt && !options.dry_run {
+            parse_fit_file(path).and_then(|activity_data| index.add(path, &activity_data))
+        } else {
+            Ok(())
+        };
+        match result {
+            Ok(()) => {
+                println!(
+                    "'{}' ... {}",
+                    path.display(),
+                    status(options.adopt, options.dry_run, "not in index", "adopted")
+                );
+                unindexed_counter += 1;
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                error_counter += 1;
+            }
+        }
+    }
+
+    let mut orphan_counter: usize = 0;
+    for path in indexed.iter().filter(|path| !path.exists()) {
+        let result = if options.purge && !options.dry_run {
+            index.remove(path)
+        } else {
+            Ok(())
+        };
+        match result {
+            Ok(()) => {
+                println!(
+                    "'{}' ... {}",
+                    path.display(),
+                    status(options.purge, options.dry_run, "missing", "purged")
+                );
+                orphan_counter += 1;
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                error_counter += 1;
+            }
+        }
+    }
+
+    let msg = format!(
+        "Found {} files not in index and {} index entries without file",
+        unindexed_counter, orphan_counter
+    );
+    let err = if error_counter == 0 {
+        String::new()
+    } else {
+        format!("with {} errors.", error_counter)
+    };
+
+    Ok([msg, err].join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test finding and repairing files missing from the index and orphaned entries
+    fn test_fsck() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let file = tmpdir.path().join("2023/07/run.fit");
+        fs::create_dir_all(file.parent().unwrap())
+            .expect("error during creating temporary directory");
+        fs::copy(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("test")
+                .join("test_data_01.fit"),
+            &file,
+        )
+        .expect("unable to copy test file");
+        let mut options = super::FsckOptions {
+            directory: tmpdir.path().to_path_buf(),
+            ..Default::default()
+        };
+        super::fsck(&options).expect_err("error expected");
+
+        let index = crate::index::Index::open(tmpdir.path(), true)
+            .unwrap()
+            .unwrap();
+        index
+            .add(
+                &tmpdir.path().join("2023/07/gone.fit"),
+                &crate::fitarchiver::ActivityData::new(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            "Found 1 files not in index and 1 index entries without file ",
+            super::fsck(&options).unwrap()
+        );
+        options.adopt = true;
+        options.purge = true;
+        options.dry_run = true;
+        super::fsck(&options).unwrap();
+        assert_eq!(
+            vec![tmpdir.path().join("2023/07/gone.fit")],
+            index.archive_paths().unwrap()
+        );
+
+        options.dry_run = false;
+        super::fsck(&options).unwrap();
+        assert_eq!(vec![file], index.archive_paths().unwrap());
+        assert_eq!(
+            "Found 0 files not in index and 0 index entries without file ",
+            super::fsck(&options).unwrap()
+        );
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
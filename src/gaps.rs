@@ -0,0 +1,117 @@
+//! # Gaps in the recording of activities
+//!
+//! A device that loses the satellites in a tunnel or fails to pause automatically leaves a gap
+//! between two records while the timer is running. Pauses of the timer are recorded as timer
+//! events and are no gaps. Activities with gaps are reported and optionally archived in the
+//! quarantine directory to review them before they end up in the archive.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use chrono::{DateTime, Utc};
+use std::str::FromStr;
+
+/// Default minimum length of a gap in seconds
+pub const DEFAULT_GAP_THRESHOLD: u32 = 300;
+
+/// Policy for activities with gaps in their recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GapPolicy {
+    /// Print a warning and archive the activity as usual
+    #[default]
+    Warn,
+    /// Print a warning and archive the file in the quarantine directory under its original name
+    Quarantine,
+}
+
+impl FromStr for GapPolicy {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "warn" => Ok(GapPolicy::Warn),
+            "quarantine" => Ok(GapPolicy::Quarantine),
+            _ => {
+                let msg = format!(
+                    "Invalid gap policy '{}', expected 'warn' or 'quarantine'",
+                    s
+                );
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+/// Tracker of the time between the records of an activity while the timer is running
+#[derive(Debug, Clone, Default)]
+pub(crate) struct GapTracker {
+    /// Timestamp of the previous record
+    previous: Option<DateTime<Utc>>,
+    /// The timer was stopped since the previous record
+    paused: bool,
+    /// Longest time between two records in seconds
+    longest: Option<u32>,
+}
+
+impl GapTracker {
+    /// Track the timestamp of a record
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - Timestamp of the record.
+    pub(crate) fn record(&mut self, timestamp: DateTime<Utc>) {
+        if let (Some(previous), false) = (self.previous, self.paused) {
+            let gap = u32::try_from((timestamp - previous).num_seconds()).unwrap_or(0);
+            self.longest = Some(self.longest.map_or(gap, |longest| longest.max(gap)));
+        }
+        self.previous = Some(timestamp);
+        self.paused = false;
+    }
+
+    /// Track a stop of the timer, i.e. a manual or automatic pause
+    pub(crate) fn timer_stopped(&mut self) {
+        self.paused = true;
+    }
+
+    /// Returns the longest time between two records in seconds, `None` for less than two records
+    pub(crate) fn longest_gap(&self) -> Option<u32> {
+        self.longest
+    }
+}
+
+/// Returns a gap as minutes and seconds, i.e. '12m05s'
+///
+/// # Arguments
+///
+/// * `gap` - Length of the gap in seconds.
+pub(crate) fn format_gap(gap: u32) -> String {
+    format!("{}m{:02}s", gap / 60, gap % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, TimeZone};
+
+    #[test]
+    /// Test tracking the gaps between records
+    fn test_gap_tracker() {
+        let start = chrono::Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let mut tracker = super::GapTracker::default();
+        tracker.record(start);
+        assert_eq!(None, tracker.longest_gap());
+        tracker.record(start + TimeDelta::seconds(1));
+        tracker.record(start + TimeDelta::seconds(8));
+        assert_eq!(Some(7), tracker.longest_gap());
+
+        // pauses of the timer are no gaps
+        tracker.timer_stopped();
+        tracker.record(start + TimeDelta::seconds(3600));
+        assert_eq!(Some(7), tracker.longest_gap());
+        tracker.record(start + TimeDelta::seconds(4325));
+        assert_eq!(Some(725), tracker.longest_gap());
+        assert_eq!("12m05s", super::format_gap(725));
+
+        assert_eq!(super::GapPolicy::Quarantine, "quarantine".parse().unwrap());
+        "review"
+            .parse::<super::GapPolicy>()
+            .expect_err("error expected");
+    }
+}
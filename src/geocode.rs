@@ -0,0 +1,206 @@
+//! # Reverse geocoding of start positions
+//!
+//! The start position of an activity is resolved offline to the nearest known place and its
+//! country, i.e. for an archive layout like '2024/italy/bolzano'. A dataset of major places is
+//! bundled. A GeoNames dump like 'cities1000.txt' or a file in the format of the bundled
+//! dataset resolves smaller towns.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use crate::location::{distance, Position};
+use std::fs;
+use std::path::Path;
+
+/// Places bundled with the archiver, name, country code, latitude and longitude per line
+const BUNDLED_PLACES: &str = include_str!("../data/places.tsv");
+
+/// Names of the countries by ISO 3166-1 alpha-2 code
+const COUNTRIES: &str = include_str!("../data/countries.tsv");
+
+/// Maximum distance of a start to its place in meters
+const PLACE_RADIUS: f64 = 25_000.0;
+
+/// Maximum distance of a start to the nearest place defining its country in meters
+const COUNTRY_RADIUS: f64 = 250_000.0;
+
+/// Named place with its country
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Place {
+    /// Name of the place, i.e. 'Bolzano'
+    name: String,
+    /// Name of the country, i.e. 'Italy'
+    country: String,
+    /// Latitude and longitude in degrees
+    position: Position,
+}
+
+/// Returns the name of a country, the code itself for unknown codes
+///
+/// # Arguments
+///
+/// * `code` - ISO 3166-1 alpha-2 code of the country, i.e. 'IT'.
+fn country_name(code: &str) -> String {
+    COUNTRIES
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .find(|(known, _)| known.eq_ignore_ascii_case(code))
+        .map_or(code.to_string(), |(_, name)| name.to_string())
+}
+
+/// Returns the places of a dataset
+///
+/// Each line is either a place of the bundled format with name, country code, latitude and
+/// longitude separated by tabs, or a line of a GeoNames dump. Empty lines and lines starting
+/// with '#' are ignored.
+///
+/// # Arguments
+///
+/// * `text` - Content of the dataset.
+/// * `source` - Name of the dataset used in errors.
+fn parse_places(text: &str, source: &str) -> Result<Vec<Place>> {
+    let mut places = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        // GeoNames: id, name, ascii name, alternate names, latitude, longitude, feature class,
+        // feature code, country code, ...
+        let (name, code, latitude, longitude) = match fields.len() {
+            4 => (fields[0], fields[1], fields[2], fields[3]),
+            len if len >= 9 => (fields[1], fields[8], fields[4], fields[5]),
+            _ => ("", "", "", ""),
+        };
+        match (latitude.parse::<f64>(), longitude.parse::<f64>()) {
+            (Ok(latitude), Ok(longitude)) if !name.is_empty() => places.push(Place {
+                name: name.to_string(),
+                country: country_name(code),
+                position: (latitude, longitude),
+            }),
+            _ => {
+                let msg = format!("Invalid place in line {} of '{}'", number + 1, source);
+                return Err(ArchiverError::new(&msg));
+            }
+        }
+    }
+    Ok(places)
+}
+
+/// Returns the places used to resolve the start positions
+///
+/// # Arguments
+///
+/// * `path` - Dataset of places, `None` for the bundled places.
+pub(crate) fn load_places(path: Option<&Path>) -> Result<Vec<Place>> {
+    match path {
+        Some(path) => {
+            let text = fs::read_to_string(path).map_err(|err| {
+                let msg = format!("Unable to read places '{}'", path.display());
+                ArchiverError::io(&msg, err)
+            })?;
+            parse_places(&text, &path.display().to_string())
+        }
+        None => parse_places(BUNDLED_PLACES, "bundled places"),
+    }
+}
+
+/// Returns a name usable in file names, i.e. 'cortina_d_ampezzo' for "Cortina d'Ampezzo"
+///
+/// # Arguments
+///
+/// * `name` - Name of a place or country.
+fn path_name(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Returns the place and the country of a start position, 'unknown' if they are too far away
+///
+/// The place is the nearest place within 25 km, the country is the country of the nearest place
+/// within 250 km, so the country of starts close to a border may be the neighbor.
+///
+/// # Arguments
+///
+/// * `places` - Known places.
+/// * `position` - Start position of the activity.
+pub(crate) fn geocode(places: &[Place], position: Option<Position>) -> (String, String) {
+    let unknown = || String::from("unknown");
+    let Some(position) = position else {
+        return (unknown(), unknown());
+    };
+    // a degree of latitude is about 111 km, farther places are skipped without computing
+    let max_latitude = COUNTRY_RADIUS / 111_000.0;
+    let nearest = places
+        .iter()
+        .filter(|place| (place.position.0 - position.0).abs() <= max_latitude)
+        .map(|place| (distance(place.position, position), place))
+        .filter(|(meters, _)| *meters <= COUNTRY_RADIUS)
+        .min_by(|(first, _), (second, _)| first.total_cmp(second));
+
+    match nearest {
+        Some((meters, place)) if meters <= PLACE_RADIUS => {
+            (path_name(&place.name), path_name(&place.country))
+        }
+        Some((_, place)) => (unknown(), path_name(&place.country)),
+        None => (unknown(), unknown()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test resolving start positions to places and countries
+    fn test_geocode() {
+        let places = super::load_places(None).unwrap();
+        assert_eq!(
+            (String::from("bolzano"), String::from("italy")),
+            super::geocode(&places, Some((46.49, 11.33)))
+        );
+        assert_eq!(
+            (String::from("stuttgart"), String::from("germany")),
+            super::geocode(&places, Some((48.7237, 9.1138)))
+        );
+        // between the places of the dataset only the country is known
+        assert_eq!(
+            (String::from("unknown"), String::from("italy")),
+            super::geocode(&places, Some((46.2, 11.8)))
+        );
+        // middle of the Atlantic
+        assert_eq!(
+            (String::from("unknown"), String::from("unknown")),
+            super::geocode(&places, Some((40.0, -40.0)))
+        );
+        assert_eq!(
+            (String::from("unknown"), String::from("unknown")),
+            super::geocode(&places, None)
+        );
+
+        // GeoNames dump with a small town
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let path = tmpdir.path().join("cities1000.txt");
+        fs::write(
+            &path,
+            "3165524\tSankt Ulrich in Gröden\tOrtisei\t\t46.57\t11.67\tP\tPPL\tIT\t\n",
+        )
+        .unwrap();
+        let places = super::load_places(Some(&path)).unwrap();
+        assert_eq!(
+            (
+                String::from("sankt_ulrich_in_gröden"),
+                String::from("italy")
+            ),
+            super::geocode(&places, Some((46.575, 11.68)))
+        );
+        fs::write(&path, "Ortisei\tIT\tnorth\t11.67\n").unwrap();
+        super::load_places(Some(&path)).expect_err("error expected");
+        super::load_places(Some(&tmpdir.path().join("missing.txt"))).expect_err("error expected");
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
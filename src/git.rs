@@ -0,0 +1,184 @@
+//! # Versioning of the archive with git
+//!
+//! The archived files of each run are committed to a git repository in the archive directory.
+//! The history of the archive is versioned and can be synchronized via git remotes. Large files
+//! are supported with git LFS configured in the repository or with git-annex.
+
+use crate::fitarchiver::{ActivityData, ArchiverError, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+/// Tool adding the archived files to the repository
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GitMode {
+    /// Add the files with 'git add', including git LFS
+    #[default]
+    Git,
+    /// Add the files with 'git annex add'
+    Annex,
+}
+
+impl FromStr for GitMode {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "git" => Ok(GitMode::Git),
+            "annex" => Ok(GitMode::Annex),
+            _ => {
+                let msg = format!("Invalid git mode '{}', expected 'git' or 'annex'", s);
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+/// Returns the line describing an archived activity in the commit message
+///
+/// # Arguments
+///
+/// * `path` - Path of the archive file relative to the archive directory.
+/// * `activity_data` - Activity data of the archive file.
+pub(crate) fn commit_line(path: &Path, activity_data: &ActivityData) -> String {
+    format!(
+        "{} {} ({}) {}",
+        activity_data.timestamp.format("%Y-%m-%d %H:%M:%S"),
+        activity_data.sport,
+        activity_data.sport_name,
+        path.display()
+    )
+}
+
+/// Run git in the archive directory
+///
+/// # Arguments
+///
+/// * `directory` - Base directory of the archive.
+/// * `args` - Arguments of git.
+/// * `paths` - Paths the command is limited to.
+fn git(directory: &Path, args: &[&str], paths: &[PathBuf]) -> Result<()> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(args)
+        .arg("--")
+        .args(paths)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => {
+            let msg = format!(
+                "Unable to run 'git {}' in '{}': {}",
+                args.join(" "),
+                directory.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            Err(ArchiverError::new(&msg))
+        }
+        Err(err) => {
+            let msg = format!("Unable to run 'git {}'", args.join(" "));
+            Err(ArchiverError::io(&msg, err))
+        }
+    }
+}
+
+/// Commit archived files to the git repository of the archive
+///
+/// # Arguments
+///
+/// * `directory` - Base directory of the archive, part of a git repository.
+/// * `mode` - Tool adding the files to the repository.
+/// * `files` - Paths of the archive files relative to the archive directory and their lines
+///   in the commit message.
+pub(crate) fn commit_files(
+    directory: &Path,
+    mode: GitMode,
+    files: &[(PathBuf, String)],
+) -> Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    let paths: Vec<PathBuf> = files.iter().map(|(path, _)| path.clone()).collect();
+
+    match mode {
+        GitMode::Git => git(directory, &["add"], &paths)?,
+        GitMode::Annex => git(directory, &["annex", "add", "--quiet"], &paths)?,
+    }
+
+    let subject = if files.len() == 1 {
+        String::from("Archive 1 activity")
+    } else {
+        format!("Archive {} activities", files.len())
+    };
+    let body: Vec<&str> = files.iter().map(|(_, line)| line.as_str()).collect();
+    git(
+        directory,
+        &["commit", "--quiet", "-m", &subject, "-m", &body.join("\n")],
+        &paths,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test the description of an activity in the commit message
+    fn test_commit_line() {
+        let activity_data = crate::fitarchiver::ActivityData {
+            timestamp: chrono::Utc.with_ymd_and_hms(2023, 7, 26, 6, 22, 4).unwrap(),
+            sport: String::from("running"),
+            sport_name: String::from("trail_run"),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+        assert_eq!(
+            "2023-07-26 06:22:04 running (trail_run) 2023/07/run.fit",
+            super::commit_line(Path::new("2023/07/run.fit"), &activity_data)
+        );
+    }
+
+    #[test]
+    /// Test committing files to a repository
+    fn test_commit_files() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .arg("-C")
+                .arg(tmpdir.path())
+                .args(args)
+                .output()
+                .expect("unable to run git")
+        };
+        git(&["init", "--quiet"]);
+        git(&["config", "user.name", "Test"]);
+        git(&["config", "user.email", "test@example.com"]);
+
+        fs::create_dir_all(tmpdir.path().join("2023")).unwrap();
+        fs::write(tmpdir.path().join("2023/a.fit"), "a").unwrap();
+        fs::write(tmpdir.path().join("2023/b.fit"), "b").unwrap();
+        fs::write(tmpdir.path().join("other"), "other").unwrap();
+        let files = vec![
+            (PathBuf::from("2023/a.fit"), String::from("first activity")),
+            (PathBuf::from("2023/b.fit"), String::from("second activity")),
+        ];
+        super::commit_files(tmpdir.path(), super::GitMode::Git, &files).unwrap();
+
+        let log = git(&["log", "--format=%B", "--name-only"]);
+        assert_eq!(
+            "Archive 2 activities\n\nfirst activity\nsecond activity\n\n\n2023/a.fit\n2023/b.fit\n",
+            String::from_utf8_lossy(&log.stdout)
+        );
+
+        // files outside of a repository cannot be committed
+        let other = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        super::commit_files(other.path(), super::GitMode::Git, &files).expect_err("error expected");
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
@@ -0,0 +1,250 @@
+//! # Content hashes of archive files
+//!
+//! The hashes stored in the index detect files whose content is already archived. SHA-256 is the
+//! default, XXH64 is a much faster non-cryptographic alternative for huge archives on slow CPUs,
+//! i.e. a NAS. Hashes other than SHA-256 carry the name of their algorithm as prefix, so hashes
+//! of different algorithms in the same index never match each other.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use crate::objects::file_hash;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Algorithm of the content hashes in the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashAlgorithm {
+    /// SHA-256
+    #[default]
+    Sha256,
+    /// XXH64 with seed 0
+    Xxh64,
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "xxh64" => Ok(HashAlgorithm::Xxh64),
+            _ => {
+                let msg = format!(
+                    "Invalid hash algorithm '{}', expected 'sha256' or 'xxh64'",
+                    s
+                );
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+impl HashAlgorithm {
+    /// Returns the hash of the content of a file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the file.
+    pub(crate) fn file_hash(&self, path: &Path) -> io::Result<String> {
+        match self {
+            HashAlgorithm::Sha256 => file_hash(path),
+            HashAlgorithm::Xxh64 => {
+                let mut file = File::open(path)?;
+                let mut hasher = Xxh64::new();
+                let mut buffer = [0u8; 65536];
+                loop {
+                    let count = file.read(&mut buffer)?;
+                    if count == 0 {
+                        break;
+                    }
+                    hasher.update(&buffer[..count]);
+                }
+                Ok(format!("xxh64:{:016x}", hasher.finish()))
+            }
+        }
+    }
+}
+
+const PRIME64_1: u64 = 0x9E37_79B1_85EB_CA87;
+const PRIME64_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const PRIME64_3: u64 = 0x1656_67B1_9E37_79F9;
+const PRIME64_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const PRIME64_5: u64 = 0x27D4_EB2F_1656_67C5;
+
+/// Streaming XXH64 hasher with seed 0
+struct Xxh64 {
+    /// Accumulators of the four lanes
+    lanes: [u64; 4],
+    /// Input not yet consumed by a full stripe
+    buffer: [u8; 32],
+    /// Number of bytes in the buffer
+    buffered: usize,
+    /// Total number of input bytes
+    length: u64,
+}
+
+/// Mix an input word into an accumulator
+fn round(accumulator: u64, input: u64) -> u64 {
+    accumulator
+        .wrapping_add(input.wrapping_mul(PRIME64_2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME64_1)
+}
+
+/// Merge the accumulator of a lane into the hash
+fn merge_round(hash: u64, lane: u64) -> u64 {
+    (hash ^ round(0, lane))
+        .wrapping_mul(PRIME64_1)
+        .wrapping_add(PRIME64_4)
+}
+
+/// Returns the little endian word at the start of a slice
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().unwrap())
+}
+
+impl Xxh64 {
+    fn new() -> Self {
+        Xxh64 {
+            lanes: [
+                PRIME64_1.wrapping_add(PRIME64_2),
+                PRIME64_2,
+                0,
+                0u64.wrapping_sub(PRIME64_1),
+            ],
+            buffer: [0; 32],
+            buffered: 0,
+            length: 0,
+        }
+    }
+
+    /// Consume a full stripe of 32 bytes
+    fn stripe(&mut self, stripe: &[u8]) {
+        for (i, lane) in self.lanes.iter_mut().enumerate() {
+            *lane = round(*lane, read_u64(&stripe[i * 8..]));
+        }
+    }
+
+    fn update(&mut self, mut input: &[u8]) {
+        self.length += input.len() as u64;
+        if self.buffered > 0 {
+            let count = input.len().min(32 - self.buffered);
+            self.buffer[self.buffered..self.buffered + count].copy_from_slice(&input[..count]);
+            self.buffered += count;
+            input = &input[count..];
+            if self.buffered < 32 {
+                return;
+            }
+            let buffer = self.buffer;
+            self.stripe(&buffer);
+            self.buffered = 0;
+        }
+        let mut stripes = input.chunks_exact(32);
+        for stripe in &mut stripes {
+            self.stripe(stripe);
+        }
+        let remainder = stripes.remainder();
+        self.buffer[..remainder.len()].copy_from_slice(remainder);
+        self.buffered = remainder.len();
+    }
+
+    fn finish(&self) -> u64 {
+        let mut hash = if self.length >= 32 {
+            let [v1, v2, v3, v4] = self.lanes;
+            let hash = v1
+                .rotate_left(1)
+                .wrapping_add(v2.rotate_left(7))
+                .wrapping_add(v3.rotate_left(12))
+                .wrapping_add(v4.rotate_left(18));
+            self.lanes
+                .iter()
+                .fold(hash, |hash, lane| merge_round(hash, *lane))
+        } else {
+            PRIME64_5
+        };
+        hash = hash.wrapping_add(self.length);
+
+        let mut tail = &self.buffer[..self.buffered];
+        while tail.len() >= 8 {
+            hash = (hash ^ round(0, read_u64(tail)))
+                .rotate_left(27)
+                .wrapping_mul(PRIME64_1)
+                .wrapping_add(PRIME64_4);
+            tail = &tail[8..];
+        }
+        if tail.len() >= 4 {
+            let word = u32::from_le_bytes(tail[..4].try_into().unwrap()) as u64;
+            hash = (hash ^ word.wrapping_mul(PRIME64_1))
+                .rotate_left(23)
+                .wrapping_mul(PRIME64_2)
+                .wrapping_add(PRIME64_3);
+            tail = &tail[4..];
+        }
+        for byte in tail {
+            hash = (hash ^ (*byte as u64).wrapping_mul(PRIME64_5))
+                .rotate_left(11)
+                .wrapping_mul(PRIME64_1);
+        }
+
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(PRIME64_2);
+        hash ^= hash >> 29;
+        hash = hash.wrapping_mul(PRIME64_3);
+        hash ^ (hash >> 32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::str::FromStr;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test XXH64 against known hashes, independent of how the input is split
+    fn test_xxh64() {
+        for (input, expected) in [
+            ("", 0xef46db3751d8e999u64),
+            ("abc", 0x44bc2cf5ad770999),
+            (
+                "Nobody inspects the spammish repetition",
+                0xfbcea83c8a378bf1,
+            ),
+        ] {
+            let mut hasher = super::Xxh64::new();
+            hasher.update(input.as_bytes());
+            assert_eq!(expected, hasher.finish(), "{}", input);
+
+            let mut hasher = super::Xxh64::new();
+            for byte in input.as_bytes().chunks(5) {
+                hasher.update(byte);
+            }
+            assert_eq!(expected, hasher.finish(), "{}", input);
+        }
+    }
+
+    #[test]
+    /// Test hashing the content of a file with each algorithm
+    fn test_hash_algorithm() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let file = tmpdir.path().join("content");
+        fs::write(&file, "abc").expect("unable to create test file");
+
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            super::HashAlgorithm::Sha256.file_hash(&file).unwrap()
+        );
+        assert_eq!(
+            "xxh64:44bc2cf5ad770999",
+            super::HashAlgorithm::from_str("xxh64")
+                .unwrap()
+                .file_hash(&file)
+                .unwrap()
+        );
+        super::HashAlgorithm::from_str("blake3").expect_err("error expected");
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
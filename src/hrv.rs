@@ -0,0 +1,273 @@
+//! # Export of heart rate variability streams
+//!
+//! Devices recording heart rate variability store the RR intervals, the time between two heart
+//! beats, in HRV messages of up to five intervals each. The intervals of an activity are written
+//! to a CSV or JSON file, so HRV analysis tools can read them without parsing FIT files.
+
+use crate::fitarchiver::{read_fit_file, ArchiverError, Result};
+use fitparser::profile::field_types::MesgNum;
+use fitparser::{FitDataRecord, Value};
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Largest valid RR interval in seconds, larger values mark unused slots of a message
+const MAX_INTERVAL: f64 = 65.534;
+
+/// File format of the exported RR intervals
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HrvFormat {
+    /// Comma separated values with one line per interval
+    #[default]
+    Csv,
+    /// JSON object with an array of the intervals
+    Json,
+}
+
+impl FromStr for HrvFormat {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(HrvFormat::Csv),
+            "json" => Ok(HrvFormat::Json),
+            _ => {
+                let msg = format!("Invalid HRV format '{}', expected 'csv' or 'json'", s);
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+impl HrvFormat {
+    /// Returns the file extension of the format
+    fn extension(&self) -> &'static str {
+        match self {
+            HrvFormat::Csv => "csv",
+            HrvFormat::Json => "json",
+        }
+    }
+}
+
+/// Options controlling the export of heart rate variability streams
+#[derive(Debug, Clone)]
+pub struct HrvOptions {
+    /// FIT files to export
+    pub files: Vec<PathBuf>,
+    /// Directory receiving the exported files
+    pub output: PathBuf,
+    /// File format of the exported intervals
+    pub format: HrvFormat,
+}
+
+impl Default for HrvOptions {
+    fn default() -> Self {
+        HrvOptions {
+            files: Vec::new(),
+            output: PathBuf::from("."),
+            format: HrvFormat::default(),
+        }
+    }
+}
+
+/// Returns the RR intervals of the HRV messages in milliseconds
+///
+/// # Arguments
+///
+/// * `records` - Messages of a FIT file.
+fn rr_intervals(records: &[FitDataRecord]) -> Vec<f64> {
+    let mut intervals = Vec::new();
+    for data in records.iter().filter(|data| data.kind() == MesgNum::Hrv) {
+        for field in data.fields().iter().filter(|field| field.name() == "time") {
+            let values = match field.value() {
+                Value::Array(values) => values.clone(),
+                value => vec![value.clone()],
+            };
+            intervals.extend(
+                values
+                    .into_iter()
+                    .filter_map(|value| TryInto::<f64>::try_into(value).ok())
+                    .filter(|seconds| *seconds > 0.0 && *seconds <= MAX_INTERVAL)
+                    .map(|seconds| (seconds * 1000.0).round()),
+            );
+        }
+    }
+    intervals
+}
+
+/// Returns RR intervals as CSV with the time since the first beat in seconds
+///
+/// # Arguments
+///
+/// * `intervals` - RR intervals in milliseconds.
+fn format_csv(intervals: &[f64]) -> String {
+    let mut lines = vec![String::from("time,rr")];
+    let mut time = 0.0;
+    for interval in intervals {
+        time += interval;
+        lines.push(format!("{:.3},{}", time / 1000.0, interval));
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Returns RR intervals as JSON object
+///
+/// # Arguments
+///
+/// * `path` - Path of the FIT file.
+/// * `intervals` - RR intervals in milliseconds.
+fn format_json(path: &Path, intervals: &[f64]) -> String {
+    json!({
+        "file": path.display().to_string(),
+        "unit": "ms",
+        "rr_intervals": intervals,
+    })
+    .to_string()
+}
+
+/// Write the RR intervals of a FIT file and return the path of the exported file
+///
+/// # Arguments
+///
+/// * `path` - Path of the FIT file.
+/// * `options` - HRV options.
+fn export_file(path: &Path, options: &HrvOptions) -> Result<(PathBuf, usize)> {
+    let intervals = rr_intervals(&read_fit_file(path)?);
+    if intervals.is_empty() {
+        let msg = format!("No HRV data in '{}'", path.display());
+        return Err(ArchiverError::new(&msg));
+    }
+
+    let export_path = options.output.join(format!(
+        "{}-hrv.{}",
+        path.file_stem().unwrap_or_default().to_string_lossy(),
+        options.format.extension()
+    ));
+    let content = match options.format {
+        HrvFormat::Csv => format_csv(&intervals),
+        HrvFormat::Json => format_json(path, &intervals),
+    };
+    let msg = format!("Unable to create file '{}'", export_path.display());
+    fs::create_dir_all(&options.output).map_err(|err| ArchiverError::io(&msg, err))?;
+    fs::write(&export_path, content).map_err(|err| ArchiverError::io(&msg, err))?;
+    Ok((export_path, intervals.len()))
+}
+
+/// Write the RR intervals of each FIT file to a separate CSV or JSON file
+///
+/// The files are named after the FIT file, i.e. 'run-hrv.csv'.
+///
+/// # Arguments
+///
+/// `options` - HRV options.
+pub fn export_hrv(options: &HrvOptions) -> Result<String> {
+    let mut export_counter: usize = 0;
+    let mut error_counter: usize = 0;
+    for path in &options.files {
+        match export_file(path, options) {
+            Ok((export_path, count)) => {
+                println!(
+                    "'{}' -> '{}' ... {} intervals",
+                    path.display(),
+                    export_path.display(),
+                    count
+                );
+                export_counter += 1;
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                error_counter += 1;
+            }
+        }
+    }
+
+    let msg = format!(
+        "Exported {} of {} files",
+        export_counter,
+        options.files.len()
+    );
+    let err = if error_counter == 0 {
+        String::new()
+    } else {
+        format!("with {} errors.", error_counter)
+    };
+
+    Ok([msg, err].join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use fitparser::profile::field_types::MesgNum;
+    use fitparser::{FitDataField, FitDataRecord, Value};
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test reading and formatting RR intervals
+    fn test_rr_intervals() {
+        let mut hrv = FitDataRecord::new(MesgNum::Hrv);
+        hrv.push(FitDataField::new(
+            String::from("time"),
+            0,
+            None,
+            Value::Array(vec![
+                Value::Float64(0.812),
+                Value::Float64(0.8),
+                Value::Float64(65.535),
+            ]),
+            String::from("s"),
+        ));
+        let mut single = FitDataRecord::new(MesgNum::Hrv);
+        single.push(FitDataField::new(
+            String::from("time"),
+            0,
+            None,
+            Value::Float64(0.7905),
+            String::from("s"),
+        ));
+        let mut record = FitDataRecord::new(MesgNum::Record);
+        record.push(FitDataField::new(
+            String::from("time"),
+            0,
+            None,
+            Value::Float64(1.0),
+            String::from("s"),
+        ));
+
+        let intervals = super::rr_intervals(&[hrv, record, single]);
+        assert_eq!(vec![812.0, 800.0, 791.0], intervals);
+        assert_eq!(
+            "time,rr\n0.812,812\n1.612,800\n2.403,791\n",
+            super::format_csv(&intervals)
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&super::format_json(&PathBuf::from("run.fit"), &intervals))
+                .unwrap();
+        assert_eq!(Some(3), json["rr_intervals"].as_array().map(Vec::len));
+        assert_eq!(Some("run.fit"), json["file"].as_str());
+    }
+
+    #[test]
+    /// Test exporting files without HRV data
+    fn test_export_hrv() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let options = super::HrvOptions {
+            files: vec![PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("test")
+                .join("test_data_01.fit")],
+            output: tmpdir.path().join("hrv"),
+            format: super::HrvFormat::Json,
+        };
+        assert_eq!(
+            "Exported 0 of 1 files with 1 errors.",
+            super::export_hrv(&options).unwrap()
+        );
+        assert!(!tmpdir.path().join("hrv").exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
@@ -0,0 +1,318 @@
+//! # Ignore files of source directories
+//!
+//! A `.fitarchiverignore` file in a source directory lists files and directories that are never
+//! archived, i.e. test files or the data of other apps on a device. The syntax is the one of
+//! `.gitignore` files: blank lines and lines starting with '#' are skipped, '!' re-includes a path
+//! ignored by a previous pattern, a trailing '/' matches only directories and patterns containing
+//! a '/' are relative to the directory of the ignore file. The patterns of an ignore file apply to
+//! its directory and all sub directories, the last matching pattern decides.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use glob::{MatchOptions, Pattern};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Name of the ignore file in source directories
+pub const IGNORE_FILE: &str = ".fitarchiverignore";
+
+/// Options for matching patterns like git, '*' does not match '/'
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Pattern of an ignore file
+#[derive(Debug, Clone)]
+struct IgnorePattern {
+    /// Directory of the ignore file
+    base: PathBuf,
+    /// Glob pattern
+    pattern: Pattern,
+    /// Re-include matching paths
+    negated: bool,
+    /// Match only directories
+    directory_only: bool,
+    /// Match the path relative to the base directory instead of the name
+    anchored: bool,
+}
+
+impl IgnorePattern {
+    /// Returns true if the pattern matches a path
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path within the base directory.
+    /// * `is_dir` - The path is a directory.
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            path.strip_prefix(&self.base).is_ok_and(|relative| {
+                self.pattern
+                    .matches_with(&relative.to_string_lossy(), MATCH_OPTIONS)
+            })
+        } else {
+            path.file_name().is_some_and(|name| {
+                self.pattern
+                    .matches_with(&name.to_string_lossy(), MATCH_OPTIONS)
+            })
+        }
+    }
+}
+
+/// Patterns of the ignore files of a directory and its parents
+#[derive(Debug, Clone, Default)]
+pub(crate) struct IgnoreRules {
+    /// Patterns in the order of precedence, the last one decides
+    patterns: Vec<IgnorePattern>,
+}
+
+impl IgnoreRules {
+    /// Returns the rules extended by the ignore file of a directory, if it has one
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - Directory that may contain an ignore file.
+    pub(crate) fn with_directory(&self, directory: &Path) -> Result<IgnoreRules> {
+        let path = directory.join(IGNORE_FILE);
+        let content = match fs::read_to_string(&path) {
+            Ok(val) => val,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(self.clone()),
+            Err(err) => {
+                let msg = format!("Unable to read ignore file '{}'", path.display());
+                return Err(ArchiverError::io(&msg, err));
+            }
+        };
+
+        let mut rules = self.clone();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(line) => (true, line),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (directory_only, line) = match line.strip_suffix('/') {
+                Some(line) => (true, line),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            let pattern = Pattern::new(line.trim_start_matches('/')).map_err(|_| {
+                let msg = format!(
+                    "Invalid pattern '{}' in ignore file '{}' line {}",
+                    line,
+                    path.display(),
+                    i + 1
+                );
+                ArchiverError::new(&msg)
+            })?;
+            rules.patterns.push(IgnorePattern {
+                base: directory.to_path_buf(),
+                pattern,
+                negated,
+                directory_only,
+                anchored,
+            });
+        }
+        Ok(rules)
+    }
+
+    /// Returns true if a path is ignored
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of a file or directory.
+    /// * `is_dir` - The path is a directory.
+    pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir))
+            .is_some_and(|pattern| !pattern.negated)
+    }
+}
+
+/// Options controlling the generation of ignore files
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreOptions {
+    /// Files and directories to ignore
+    pub files: Vec<PathBuf>,
+    /// Do not modify the ignore files, just show what will happen
+    pub dry_run: bool,
+}
+
+/// Add a pattern to the ignore file of a directory
+///
+/// # Arguments
+///
+/// * `directory` - Directory of the ignore file.
+/// * `pattern` - Pattern to add.
+fn append_pattern(directory: &Path, pattern: &str) -> Result<()> {
+    let path = directory.join(IGNORE_FILE);
+    let msg = format!("Unable to write ignore file '{}'", path.display());
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|err| ArchiverError::io(&msg, err))?;
+    // a previous line may lack its line break
+    let separator = match fs::read(&path) {
+        Ok(content) if content.last().is_some_and(|byte| *byte != b'\n') => "\n",
+        _ => "",
+    };
+    writeln!(file, "{}{}", separator, pattern).map_err(|err| ArchiverError::io(&msg, err))
+}
+
+/// Add files and directories to the ignore files of their parent directories
+///
+/// # Arguments
+///
+/// `options` - Ignore options.
+pub fn ignore(options: &IgnoreOptions) -> Result<String> {
+    let mut ignore_counter: usize = 0;
+    let mut error_counter: usize = 0;
+    for path in &options.files {
+        // file names without directory are relative to the current directory
+        let resolved = match path.parent() {
+            Some(parent) if parent.as_os_str().is_empty() => &Path::new(".").join(path),
+            _ => path,
+        };
+        let result = fs::symlink_metadata(resolved)
+            .map_err(|err| {
+                let msg = format!("Unable to read '{}'", path.display());
+                ArchiverError::io(&msg, err)
+            })
+            .and_then(|metadata| {
+                let directory = resolved.parent().unwrap_or(Path::new("."));
+                let rules = IgnoreRules::default().with_directory(directory)?;
+                if rules.is_ignored(resolved, metadata.is_dir()) {
+                    return Ok("already ignored");
+                }
+                if options.dry_run {
+                    return Ok("dry run");
+                }
+                // special characters of the name are escaped, so it matches only itself
+                let name = resolved.file_name().unwrap_or_default().to_string_lossy();
+                let mut pattern = format!("/{}", Pattern::escape(&name));
+                if metadata.is_dir() {
+                    pattern.push('/');
+                }
+                append_pattern(directory, &pattern).map(|_| "ignored")
+            });
+
+        match result {
+            Ok(status) => {
+                println!("'{}' ... {}", path.display(), status);
+                if status != "already ignored" {
+                    ignore_counter += 1;
+                }
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                error_counter += 1;
+            }
+        }
+    }
+
+    let msg = format!(
+        "Ignored {} of {} files",
+        ignore_counter,
+        options.files.len()
+    );
+    let err = if error_counter == 0 {
+        String::new()
+    } else {
+        format!("with {} errors.", error_counter)
+    };
+
+    Ok([msg, err].join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test matching the patterns of ignore files like git
+    fn test_ignore_rules() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let root = tmpdir.path();
+        fs::create_dir_all(root.join("Activity/Test")).unwrap();
+        fs::write(
+            root.join(super::IGNORE_FILE),
+            "# junk of other apps\ntest*.fit\n!test_keep.fit\nApps/\n/Activity/Test\n\\#hash.fit\n",
+        )
+        .unwrap();
+        fs::write(root.join("Activity").join(super::IGNORE_FILE), "*.FIT\n").unwrap();
+
+        let rules = super::IgnoreRules::default().with_directory(root).unwrap();
+        assert!(rules.is_ignored(&root.join("test_01.fit"), false));
+        assert!(rules.is_ignored(&root.join("Activity/test_02.fit"), false));
+        assert!(!rules.is_ignored(&root.join("test_keep.fit"), false));
+        assert!(!rules.is_ignored(&root.join("run.fit"), false));
+        assert!(rules.is_ignored(&root.join("Apps"), true));
+        assert!(!rules.is_ignored(&root.join("Apps"), false));
+        assert!(rules.is_ignored(&root.join("Activity/Test"), true));
+        assert!(!rules.is_ignored(&root.join("Other/Activity/Test"), true));
+        assert!(rules.is_ignored(&root.join("#hash.fit"), false));
+
+        // patterns of sub directories apply only within them
+        let nested = rules.with_directory(&root.join("Activity")).unwrap();
+        assert!(nested.is_ignored(&root.join("Activity/RUN.FIT"), false));
+        assert!(!rules.is_ignored(&root.join("RUN.FIT"), false));
+        assert!(nested.is_ignored(&root.join("Activity/test_03.fit"), false));
+
+        fs::write(root.join(super::IGNORE_FILE), "[z-a\n").unwrap();
+        super::IgnoreRules::default()
+            .with_directory(root)
+            .expect_err("error expected");
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test adding files and directories to ignore files
+    fn test_ignore() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let root = tmpdir.path();
+        fs::create_dir_all(root.join("Apps")).unwrap();
+        fs::write(root.join("test[1].fit"), "").unwrap();
+        fs::write(root.join(super::IGNORE_FILE), "# junk").unwrap();
+
+        let mut options = super::IgnoreOptions {
+            files: vec![
+                root.join("test[1].fit"),
+                root.join("Apps"),
+                root.join("gone.fit"),
+            ],
+            dry_run: true,
+        };
+        assert_eq!(
+            "Ignored 2 of 3 files with 1 errors.",
+            super::ignore(&options).unwrap()
+        );
+        assert_eq!(
+            "# junk",
+            fs::read_to_string(root.join(super::IGNORE_FILE)).unwrap()
+        );
+
+        options.dry_run = false;
+        options.files.pop();
+        assert_eq!("Ignored 2 of 2 files ", super::ignore(&options).unwrap());
+        assert_eq!(
+            "# junk\n/test[[]1[]].fit\n/Apps/\n",
+            fs::read_to_string(root.join(super::IGNORE_FILE)).unwrap()
+        );
+        assert_eq!("Ignored 0 of 2 files ", super::ignore(&options).unwrap());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
@@ -0,0 +1,1130 @@
+//! # Archive index
+//!
+//! SQLite database in the archive directory containing the activity data of all archived files.
+//! It allows to answer questions about the archive without parsing all archive files again.
+
+use crate::fitarchiver::{ActivityData, ArchiverError, Result};
+use crate::hash::HashAlgorithm;
+use crate::location::Position;
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::cell::Cell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Path of the index database relative to the archive directory
+pub const INDEX_PATH: &str = ".fitarchiver/index.sqlite";
+
+/// Maximum time to wait for another process writing to the index, i.e. a second watch mode
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Statements migrating the database schema, the `user_version` of the database is the number
+/// of applied migrations
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE activities (
+        path TEXT PRIMARY KEY,
+        timestamp INTEGER NOT NULL,
+        sport TEXT NOT NULL,
+        sport_name TEXT NOT NULL,
+        sub_sport TEXT NOT NULL,
+        workout_name TEXT NOT NULL,
+        manufacturer TEXT NOT NULL,
+        product TEXT NOT NULL
+    );
+    CREATE INDEX activities_timestamp ON activities (timestamp);",
+    "ALTER TABLE activities ADD COLUMN notes TEXT NOT NULL DEFAULT '';",
+    "ALTER TABLE activities ADD COLUMN start_latitude REAL;
+    ALTER TABLE activities ADD COLUMN start_longitude REAL;",
+    // monthly rollups per sport maintained by triggers on every change of the activities
+    "ALTER TABLE activities ADD COLUMN duration REAL;
+    ALTER TABLE activities ADD COLUMN distance REAL;
+    CREATE TABLE monthly_stats (
+        month TEXT NOT NULL,
+        sport TEXT NOT NULL,
+        count INTEGER NOT NULL,
+        duration REAL NOT NULL,
+        distance REAL NOT NULL,
+        PRIMARY KEY (month, sport)
+    );
+    INSERT INTO monthly_stats
+        SELECT strftime('%Y-%m', timestamp, 'unixepoch'), sport, count(*), total(duration),
+            total(distance)
+        FROM activities GROUP BY 1, 2;
+    CREATE TRIGGER activities_insert AFTER INSERT ON activities BEGIN
+        INSERT INTO monthly_stats VALUES (
+            strftime('%Y-%m', NEW.timestamp, 'unixepoch'), NEW.sport, 1,
+            coalesce(NEW.duration, 0), coalesce(NEW.distance, 0))
+        ON CONFLICT (month, sport) DO UPDATE SET count = count + 1,
+            duration = duration + excluded.duration, distance = distance + excluded.distance;
+    END;
+    CREATE TRIGGER activities_delete AFTER DELETE ON activities BEGIN
+        UPDATE monthly_stats SET count = count - 1,
+            duration = duration - coalesce(OLD.duration, 0),
+            distance = distance - coalesce(OLD.distance, 0)
+        WHERE month = strftime('%Y-%m', OLD.timestamp, 'unixepoch') AND sport = OLD.sport;
+        DELETE FROM monthly_stats WHERE count <= 0;
+    END;
+    CREATE TRIGGER activities_update AFTER UPDATE OF timestamp, sport, duration, distance
+        ON activities BEGIN
+        UPDATE monthly_stats SET count = count - 1,
+            duration = duration - coalesce(OLD.duration, 0),
+            distance = distance - coalesce(OLD.distance, 0)
+        WHERE month = strftime('%Y-%m', OLD.timestamp, 'unixepoch') AND sport = OLD.sport;
+        DELETE FROM monthly_stats WHERE count <= 0;
+        INSERT INTO monthly_stats VALUES (
+            strftime('%Y-%m', NEW.timestamp, 'unixepoch'), NEW.sport, 1,
+            coalesce(NEW.duration, 0), coalesce(NEW.distance, 0))
+        ON CONFLICT (month, sport) DO UPDATE SET count = count + 1,
+            duration = duration + excluded.duration, distance = distance + excluded.distance;
+    END;",
+    // content hashes to find identical files regardless of their path
+    "ALTER TABLE activities ADD COLUMN hash TEXT;
+    CREATE INDEX activities_hash ON activities (hash);",
+    // schema version of each entry to find entries missing the columns added later
+    "ALTER TABLE activities ADD COLUMN entry_version INTEGER NOT NULL DEFAULT 0;",
+    // original location and recording device of each file, i.e. to restore it onto the device
+    "ALTER TABLE activities ADD COLUMN source_path TEXT;
+    ALTER TABLE activities ADD COLUMN device TEXT;",
+    // activities to review, i.e. with a tunnel dropout
+    "ALTER TABLE activities ADD COLUMN has_gaps INTEGER;",
+    // source files that failed to be archived, the entries of the activities stay complete
+    "CREATE TABLE failures (
+        source_path TEXT PRIMARY KEY,
+        reason TEXT NOT NULL,
+        timestamp INTEGER NOT NULL
+    );
+    UPDATE activities SET entry_version = 9 WHERE entry_version = 8;",
+];
+
+/// Statistics of the activities of a sport within a month
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MonthlyStats {
+    /// Month of the activities, i.e. '2023-07'
+    pub(crate) month: String,
+    /// Sport type, i.e. 'running'
+    pub(crate) sport: String,
+    /// Number of activities
+    pub(crate) count: u32,
+    /// Total timer time in seconds
+    pub(crate) duration: f64,
+    /// Total distance in meters
+    pub(crate) distance: f64,
+}
+
+/// Index of an archive
+pub(crate) struct Index {
+    /// Base directory of the archive
+    directory: PathBuf,
+    /// Connection to the index database
+    connection: Connection,
+    /// Algorithm of the content hashes of new entries
+    hash_algorithm: HashAlgorithm,
+    /// Number of changes stored together in one transaction
+    commit_interval: usize,
+    /// Number of changes in the open transaction of the commit interval, `None` if there is none
+    uncommitted: Cell<Option<usize>>,
+}
+
+impl Index {
+    /// Returns the opened index of an archive
+    ///
+    /// The index is created if `create` is true. Otherwise an existing index is opened read
+    /// only and `None` is returned if the archive has no index yet or its schema is outdated,
+    /// as it cannot be migrated in read only mode.
+    ///
+    /// The index is written in WAL mode, so other processes, i.e. a query, read it while files
+    /// are archived. Processes writing at the same time wait for each other. As WAL relies on
+    /// shared memory, the index on a network share must not be used from several hosts at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - Base directory of the archive.
+    /// * `create` - Create the index if needed and open it for writing.
+    pub(crate) fn open(directory: &Path, create: bool) -> Result<Option<Index>> {
+        let path = directory.join(INDEX_PATH);
+        let msg = format!("Unable to open index '{}'", path.display());
+
+        let connection = if create {
+            if let Some(parent) = path.parent() {
+                if let Err(err) = fs::create_dir_all(parent) {
+                    return Err(ArchiverError::io(&msg, err));
+                }
+            }
+            Connection::open(&path)
+        } else if path.exists() {
+            Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        } else {
+            return Ok(None);
+        };
+        let connection = connection.map_err(|err| ArchiverError::index(&msg, err))?;
+        connection
+            .busy_timeout(BUSY_TIMEOUT)
+            .map_err(|err| ArchiverError::index(&msg, err))?;
+
+        let index = Index {
+            directory: directory.to_path_buf(),
+            connection,
+            hash_algorithm: HashAlgorithm::default(),
+            commit_interval: 1,
+            uncommitted: Cell::new(None),
+        };
+        if create {
+            // the journal mode is stored in the database, synchronizing on each checkpoint only
+            // risks the last changes on a power loss, never the consistency of the index
+            index
+                .connection
+                .query_row("PRAGMA journal_mode = WAL", [], |row| {
+                    row.get::<_, String>(0)
+                })
+                .and_then(|_| {
+                    index
+                        .connection
+                        .execute_batch("PRAGMA synchronous = NORMAL")
+                })
+                .map_err(|err| ArchiverError::index(&msg, err))?;
+            index.create_schema()?;
+        } else if index.version()? < MIGRATIONS.len() {
+            return Ok(None);
+        }
+        Ok(Some(index))
+    }
+
+    /// Returns the index hashing the content of new entries with another algorithm
+    ///
+    /// # Arguments
+    ///
+    /// * `hash_algorithm` - Algorithm of the content hashes.
+    pub(crate) fn with_hash_algorithm(mut self, hash_algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = hash_algorithm;
+        self
+    }
+
+    /// Returns the index storing the given number of changes together in one transaction
+    ///
+    /// Committing each change on its own waits for the disk every time, i.e. when archiving
+    /// thousands of files. The changes of an interval not yet committed are committed with
+    /// `commit` or when the index is closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `commit_interval` - Number of changes per transaction.
+    pub(crate) fn with_commit_interval(mut self, commit_interval: usize) -> Self {
+        self.commit_interval = commit_interval.max(1);
+        self
+    }
+
+    /// Commit the changes of the current commit interval
+    pub(crate) fn commit(&self) -> Result<()> {
+        if self.uncommitted.take().is_some() {
+            self.connection
+                .execute_batch("COMMIT")
+                .map_err(|err| ArchiverError::index("Unable to store changes in index", err))?;
+        }
+        Ok(())
+    }
+
+    /// Execute a statement changing the index within the transaction of the commit interval
+    ///
+    /// Statements of a transaction started with `transaction` are not committed in between.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - Statement changing the index.
+    /// * `parameters` - Parameters of the statement.
+    fn execute<P: rusqlite::Params>(&self, sql: &str, parameters: P) -> rusqlite::Result<usize> {
+        let uncommitted = match self.uncommitted.get() {
+            None if self.commit_interval > 1 && self.connection.is_autocommit() => {
+                self.connection.execute_batch("BEGIN IMMEDIATE")?;
+                Some(0)
+            }
+            uncommitted => uncommitted,
+        };
+        self.uncommitted.set(uncommitted);
+        let changed = self.connection.execute(sql, parameters)?;
+        match uncommitted.map(|count| count + 1) {
+            Some(count) if count >= self.commit_interval => {
+                self.uncommitted.set(None);
+                self.connection.execute_batch("COMMIT")?;
+            }
+            count => self.uncommitted.set(count),
+        }
+        Ok(changed)
+    }
+
+    /// Returns the hash of the content of a file as stored in the index
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the file.
+    pub(crate) fn file_hash(&self, path: &Path) -> io::Result<String> {
+        self.hash_algorithm.file_hash(path)
+    }
+
+    /// Returns the number of migrations applied to the index
+    pub(crate) fn version(&self) -> Result<usize> {
+        self.connection
+            .query_row("PRAGMA user_version", [], |row| row.get::<_, u32>(0))
+            .map(|version| version as usize)
+            .map_err(|err| ArchiverError::index("Unable to read index version", err))
+    }
+
+    /// Create the tables of the index or migrate them to the current schema
+    fn create_schema(&self) -> Result<()> {
+        let msg = "Unable to create index";
+        let version = self.version()?;
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let sql = format!(
+                "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+                migration,
+                i + 1
+            );
+            self.connection
+                .execute_batch(&sql)
+                .map_err(|err| ArchiverError::index(msg, err))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the path of an archive file relative to the archive directory
+    ///
+    /// # Arguments
+    ///
+    /// * `archive_path` - Path of the archive file.
+    fn relative_path(&self, archive_path: &Path) -> String {
+        archive_path
+            .strip_prefix(&self.directory)
+            .unwrap_or(archive_path)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Run a function in a transaction, its changes are only stored if it succeeds
+    ///
+    /// # Arguments
+    ///
+    /// * `function` - Function changing the index.
+    pub(crate) fn transaction<T>(&self, function: impl FnOnce(&Index) -> Result<T>) -> Result<T> {
+        let msg = "Unable to store changes in index";
+        self.commit()?;
+        self.connection
+            .execute_batch("BEGIN")
+            .map_err(|err| ArchiverError::index(msg, err))?;
+        match function(self) {
+            Ok(value) => {
+                self.connection
+                    .execute_batch("COMMIT")
+                    .map_err(|err| ArchiverError::index(msg, err))?;
+                Ok(value)
+            }
+            Err(err) => {
+                let _ = self.connection.execute_batch("ROLLBACK");
+                Err(err)
+            }
+        }
+    }
+
+    /// Add or update the entry of an archive file
+    ///
+    /// The SHA-256 hash of the content of the archive file is stored, too.
+    ///
+    /// # Arguments
+    ///
+    /// * `archive_path` - Path of the archive file.
+    /// * `activity_data` - Activity data of the archive file.
+    pub(crate) fn add(&self, archive_path: &Path, activity_data: &ActivityData) -> Result<()> {
+        self.execute(
+            "INSERT INTO activities
+                (path, timestamp, sport, sport_name, sub_sport, workout_name, manufacturer, product,
+                notes, start_latitude, start_longitude, duration, distance, hash, entry_version,
+                device, has_gaps)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)
+                ON CONFLICT (path) DO UPDATE SET timestamp = excluded.timestamp,
+                sport = excluded.sport, sport_name = excluded.sport_name,
+                sub_sport = excluded.sub_sport, workout_name = excluded.workout_name,
+                manufacturer = excluded.manufacturer, product = excluded.product,
+                notes = excluded.notes, start_latitude = excluded.start_latitude,
+                start_longitude = excluded.start_longitude, duration = excluded.duration,
+                distance = excluded.distance, hash = excluded.hash,
+                entry_version = excluded.entry_version, device = excluded.device,
+                has_gaps = excluded.has_gaps",
+            params![
+                self.relative_path(archive_path),
+                activity_data.timestamp.timestamp(),
+                activity_data.sport,
+                activity_data.sport_name,
+                activity_data.sub_sport,
+                activity_data.workout_name,
+                activity_data.manufacturer,
+                activity_data.product,
+                activity_data.notes,
+                activity_data.start_position.map(|position| position.0),
+                activity_data.start_position.map(|position| position.1),
+                activity_data.duration,
+                activity_data.distance,
+                self.file_hash(archive_path).ok(),
+                MIGRATIONS.len() as u32,
+                activity_data.device(),
+                activity_data.has_gaps,
+            ],
+        )
+        .map(|_| ())
+        .map_err(|err| {
+            let msg = format!("Unable to add '{}' to index", archive_path.display());
+            ArchiverError::index(&msg, err)
+        })
+    }
+
+    /// Fill the columns of an entry that were added to the schema after the entry was created
+    ///
+    /// Only empty columns are filled, values corrected at archiving time, i.e. the notes of
+    /// companion JSON exports, are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `archive_path` - Path of the archive file.
+    /// * `activity_data` - Activity data parsed from the archive file.
+    pub(crate) fn backfill(&self, archive_path: &Path, activity_data: &ActivityData) -> Result<()> {
+        self.execute(
+            "UPDATE activities SET
+                notes = CASE WHEN notes = '' THEN ?2 ELSE notes END,
+                start_latitude = CASE WHEN start_latitude IS NULL THEN ?3
+                    ELSE start_latitude END,
+                start_longitude = CASE WHEN start_latitude IS NULL THEN ?4
+                    ELSE start_longitude END,
+                duration = coalesce(duration, ?5), distance = coalesce(distance, ?6),
+                hash = coalesce(hash, ?7), entry_version = ?8, device = coalesce(device, ?9),
+                has_gaps = coalesce(has_gaps, ?10)
+                WHERE path = ?1",
+            params![
+                self.relative_path(archive_path),
+                activity_data.notes,
+                activity_data.start_position.map(|position| position.0),
+                activity_data.start_position.map(|position| position.1),
+                activity_data.duration,
+                activity_data.distance,
+                self.file_hash(archive_path).ok(),
+                MIGRATIONS.len() as u32,
+                activity_data.device(),
+                activity_data.has_gaps,
+            ],
+        )
+        .map(|_| ())
+        .map_err(|err| {
+            let msg = format!("Unable to backfill '{}' in index", archive_path.display());
+            ArchiverError::index(&msg, err)
+        })
+    }
+
+    /// Record the original location of an archive file
+    ///
+    /// The first recorded location is kept when the same file is archived again from another
+    /// location.
+    ///
+    /// # Arguments
+    ///
+    /// * `archive_path` - Path of the archive file.
+    /// * `source_path` - Absolute path of the source file.
+    pub(crate) fn set_source_path(&self, archive_path: &Path, source_path: &Path) -> Result<()> {
+        self.execute(
+            "UPDATE activities SET source_path = coalesce(source_path, ?2) WHERE path = ?1",
+            params![
+                self.relative_path(archive_path),
+                source_path.to_string_lossy()
+            ],
+        )
+        .map(|_| ())
+        .map_err(|err| {
+            let msg = format!("Unable to add '{}' to index", archive_path.display());
+            ArchiverError::index(&msg, err)
+        })
+    }
+
+    /// Returns the original location and recording device of an archive file
+    ///
+    /// # Arguments
+    ///
+    /// * `archive_path` - Path of the archive file.
+    pub(crate) fn source(&self, archive_path: &Path) -> Result<Option<(PathBuf, String)>> {
+        let msg = format!("Unable to read '{}' from index", archive_path.display());
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT source_path, coalesce(device, '') FROM activities
+                    WHERE path = ?1 AND source_path IS NOT NULL",
+            )
+            .map_err(|err| ArchiverError::index(&msg, err))?;
+        let mut rows = statement
+            .query_map([self.relative_path(archive_path)], |row| {
+                Ok((PathBuf::from(row.get::<_, String>(0)?), row.get(1)?))
+            })
+            .map_err(|err| ArchiverError::index(&msg, err))?;
+        rows.next()
+            .transpose()
+            .map_err(|err| ArchiverError::index(&msg, err))
+    }
+
+    /// Change the path of an archive file that has been moved
+    ///
+    /// The path is stored relative to the archive directory if the new location is inside of
+    /// it, otherwise the absolute path is stored.
+    ///
+    /// # Arguments
+    ///
+    /// * `archive_path` - Previous path of the archive file.
+    /// * `new_path` - New path of the archive file.
+    pub(crate) fn relocate(&self, archive_path: &Path, new_path: &Path) -> Result<()> {
+        self.execute(
+            "UPDATE activities SET path = ?2 WHERE path = ?1",
+            params![
+                self.relative_path(archive_path),
+                self.relative_path(new_path)
+            ],
+        )
+        .map(|_| ())
+        .map_err(|err| {
+            let msg = format!("Unable to relocate '{}' in index", archive_path.display());
+            ArchiverError::index(&msg, err)
+        })
+    }
+
+    /// Remove the entry of an archive file
+    ///
+    /// # Arguments
+    ///
+    /// * `archive_path` - Path of the archive file.
+    pub(crate) fn remove(&self, archive_path: &Path) -> Result<()> {
+        self.execute(
+            "DELETE FROM activities WHERE path = ?1",
+            params![self.relative_path(archive_path)],
+        )
+        .map(|_| ())
+        .map_err(|err| {
+            let msg = format!("Unable to remove '{}' from index", archive_path.display());
+            ArchiverError::index(&msg, err)
+        })
+    }
+
+    /// Record a source file that failed to be archived, replacing an earlier failure of it
+    ///
+    /// # Arguments
+    ///
+    /// * `source_path` - Path of the source file.
+    /// * `reason` - Error that occurred.
+    /// * `time` - Time of the failure.
+    pub(crate) fn add_failure(
+        &self,
+        source_path: &Path,
+        reason: &str,
+        time: DateTime<Utc>,
+    ) -> Result<()> {
+        let path = std::path::absolute(source_path).unwrap_or(source_path.to_path_buf());
+        self.execute(
+            "INSERT INTO failures (source_path, reason, timestamp) VALUES (?1, ?2, ?3)
+                ON CONFLICT (source_path) DO UPDATE SET reason = excluded.reason,
+                timestamp = excluded.timestamp",
+            params![path.to_string_lossy(), reason, time.timestamp()],
+        )
+        .map(|_| ())
+        .map_err(|err| {
+            let msg = format!("Unable to add failure of '{}' to index", path.display());
+            ArchiverError::index(&msg, err)
+        })
+    }
+
+    /// Remove the failure of a source file, i.e. after it has been archived
+    ///
+    /// # Arguments
+    ///
+    /// * `source_path` - Path of the source file.
+    pub(crate) fn remove_failure(&self, source_path: &Path) -> Result<()> {
+        let path = std::path::absolute(source_path).unwrap_or(source_path.to_path_buf());
+        self.execute(
+            "DELETE FROM failures WHERE source_path = ?1",
+            params![path.to_string_lossy()],
+        )
+        .map(|_| ())
+        .map_err(|err| {
+            let msg = format!(
+                "Unable to remove failure of '{}' from index",
+                path.display()
+            );
+            ArchiverError::index(&msg, err)
+        })
+    }
+
+    /// Returns the absolute paths of the source files that failed to be archived and the reasons
+    /// ordered by path
+    pub(crate) fn failures(&self) -> Result<Vec<(PathBuf, String)>> {
+        let msg = "Unable to read failures from index";
+        let mut statement = self
+            .connection
+            .prepare("SELECT source_path, reason FROM failures ORDER BY source_path")
+            .map_err(|err| ArchiverError::index(msg, err))?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((PathBuf::from(row.get::<_, String>(0)?), row.get(1)?))
+            })
+            .map_err(|err| ArchiverError::index(msg, err))?;
+        rows.map(|row| row.map_err(|err| ArchiverError::index(msg, err)))
+            .collect()
+    }
+
+    /// Returns the paths of all archive files in the index ordered by path
+    pub(crate) fn archive_paths(&self) -> Result<Vec<PathBuf>> {
+        self.query_paths("SELECT path FROM activities ORDER BY path", [])
+    }
+
+    /// Returns the paths of all archive files whose entries lack columns of the current schema
+    pub(crate) fn outdated_paths(&self) -> Result<Vec<PathBuf>> {
+        self.query_paths(
+            "SELECT path FROM activities WHERE entry_version < ?1 ORDER BY path",
+            [MIGRATIONS.len() as u32],
+        )
+    }
+
+    /// Returns the paths of all archive files with the given content ordered by path
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - SHA-256 hash of the content as hex string.
+    pub(crate) fn hash_paths(&self, hash: &str) -> Result<Vec<PathBuf>> {
+        self.query_paths(
+            "SELECT path FROM activities WHERE hash = ?1 ORDER BY path",
+            [hash],
+        )
+    }
+
+    /// Returns the paths of the archive files of a sport and time range ordered by start time
+    ///
+    /// # Arguments
+    ///
+    /// * `sport` - Sport of the activities, `None` for all sports.
+    /// * `sub_sport` - Subsport of the activities, `None` for all subsports.
+    /// * `start` - Earliest start time of the activities.
+    /// * `end` - Exclusive latest start time of the activities.
+    pub(crate) fn query_paths_by(
+        &self,
+        sport: Option<&str>,
+        sub_sport: Option<&str>,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+    ) -> Result<Vec<PathBuf>> {
+        self.query_paths(
+            "SELECT path FROM activities
+                WHERE (?1 IS NULL OR sport = ?1) AND (?2 IS NULL OR sub_sport = ?2)
+                AND (?3 IS NULL OR timestamp >= ?3) AND (?4 IS NULL OR timestamp < ?4)
+                ORDER BY timestamp, path",
+            params![
+                sport,
+                sub_sport,
+                start.map(|start| start.timestamp()),
+                end.map(|end| end.timestamp())
+            ],
+        )
+    }
+
+    /// Returns the paths of the archive files selected by a query
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - Query selecting the paths.
+    /// * `parameters` - Parameters of the query.
+    fn query_paths<P: rusqlite::Params>(&self, sql: &str, parameters: P) -> Result<Vec<PathBuf>> {
+        let msg = "Unable to read paths from index";
+
+        let mut statement = self
+            .connection
+            .prepare(sql)
+            .map_err(|err| ArchiverError::index(msg, err))?;
+        let rows = statement
+            .query_map(parameters, |row| row.get::<_, String>(0))
+            .map_err(|err| ArchiverError::index(msg, err))?;
+        rows.map(|path| {
+            path.map(|path| self.directory.join(path))
+                .map_err(|err| ArchiverError::index(msg, err))
+        })
+        .collect()
+    }
+
+    /// Returns the start times of all archived activities of a day (UTC)
+    ///
+    /// # Arguments
+    ///
+    /// * `day` - Day of the activities.
+    pub(crate) fn day_timestamps(&self, day: NaiveDate) -> Result<Vec<DateTime<Utc>>> {
+        let start = day.and_hms_opt(0, 0, 0).unwrap().and_utc();
+        let end = start + TimeDelta::days(1);
+        let msg = format!("Unable to read activities of {} from index", day);
+
+        let mut statement = self
+            .connection
+            .prepare("SELECT timestamp FROM activities WHERE timestamp >= ?1 AND timestamp < ?2")
+            .map_err(|err| ArchiverError::index(&msg, err))?;
+        let rows = statement
+            .query_map(params![start.timestamp(), end.timestamp()], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map_err(|err| ArchiverError::index(&msg, err))?;
+
+        let mut timestamps = Vec::new();
+        for row in rows {
+            let timestamp = row.map_err(|err| ArchiverError::index(&msg, err))?;
+            if let Some(timestamp) = DateTime::from_timestamp(timestamp, 0) {
+                timestamps.push(timestamp);
+            }
+        }
+        Ok(timestamps)
+    }
+
+    /// Returns the statistics of all months and sports ordered by month and sport
+    pub(crate) fn monthly_stats(&self) -> Result<Vec<MonthlyStats>> {
+        let msg = "Unable to read statistics from index";
+
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT month, sport, count, duration, distance FROM monthly_stats
+                    ORDER BY month, sport",
+            )
+            .map_err(|err| ArchiverError::index(msg, err))?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok(MonthlyStats {
+                    month: row.get(0)?,
+                    sport: row.get(1)?,
+                    count: row.get(2)?,
+                    duration: row.get(3)?,
+                    distance: row.get(4)?,
+                })
+            })
+            .map_err(|err| ArchiverError::index(msg, err))?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|err| ArchiverError::index(msg, err))
+    }
+
+    /// Returns the names of the columns of the entries in the order of the schema
+    pub(crate) fn columns(&self) -> Result<Vec<String>> {
+        let msg = "Unable to read columns of index";
+
+        let mut statement = self
+            .connection
+            .prepare("SELECT name FROM pragma_table_info('activities') ORDER BY cid")
+            .map_err(|err| ArchiverError::index(msg, err))?;
+        let rows = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(|err| ArchiverError::index(msg, err))?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|err| ArchiverError::index(msg, err))
+    }
+
+    /// Returns all entries ordered by path as JSON objects with one member per column
+    ///
+    /// Paths are stored relative to the archive directory, so the entries are independent of
+    /// the location of the archive.
+    pub(crate) fn entries(&self) -> Result<Vec<serde_json::Map<String, serde_json::Value>>> {
+        let msg = "Unable to read entries from index";
+        let columns = self.columns()?;
+
+        let mut statement = self
+            .connection
+            .prepare("SELECT * FROM activities ORDER BY path")
+            .map_err(|err| ArchiverError::index(msg, err))?;
+        let rows = statement
+            .query_map([], |row| {
+                let mut entry = serde_json::Map::new();
+                for (i, column) in columns.iter().enumerate() {
+                    let value = match row.get_ref(i)? {
+                        ValueRef::Integer(val) => serde_json::Value::from(val),
+                        ValueRef::Real(val) => serde_json::Value::from(val),
+                        ValueRef::Text(val) => {
+                            serde_json::Value::from(String::from_utf8_lossy(val).into_owned())
+                        }
+                        ValueRef::Null | ValueRef::Blob(_) => serde_json::Value::Null,
+                    };
+                    entry.insert(column.clone(), value);
+                }
+                Ok(entry)
+            })
+            .map_err(|err| ArchiverError::index(msg, err))?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|err| ArchiverError::index(msg, err))
+    }
+
+    /// Returns the content hash of the entry of a path, `None` if there is no entry
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the archive file as stored in the index.
+    pub(crate) fn entry_hash(&self, path: &str) -> Result<Option<Option<String>>> {
+        self.connection
+            .query_row(
+                "SELECT hash FROM activities WHERE path = ?1",
+                [path],
+                |row| row.get::<_, Option<String>>(0),
+            )
+            .optional()
+            .map_err(|err| {
+                let msg = format!("Unable to read '{}' from index", path);
+                ArchiverError::index(&msg, err)
+            })
+    }
+
+    /// Insert an entry given as JSON object with one member per column
+    ///
+    /// Columns missing in the entry get their default value. Members that are no column of
+    /// the schema are rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - Entry with relative path as written by `entries`.
+    pub(crate) fn insert_entry(
+        &self,
+        entry: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<()> {
+        let path = entry
+            .get("path")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or_default();
+        let columns = self.columns()?;
+        if let Some(name) = entry.keys().find(|name| !columns.contains(name)) {
+            let msg = format!("Unknown column '{}' of entry '{}'", name, path);
+            return Err(ArchiverError::new(&msg));
+        }
+
+        let mut values = Vec::new();
+        for value in entry.values() {
+            values.push(match value {
+                serde_json::Value::Null => Value::Null,
+                serde_json::Value::Bool(val) => Value::Integer(i64::from(*val)),
+                serde_json::Value::Number(val) => match val.as_i64() {
+                    Some(val) => Value::Integer(val),
+                    None => Value::Real(val.as_f64().unwrap_or_default()),
+                },
+                serde_json::Value::String(val) => Value::Text(val.clone()),
+                _ => {
+                    let msg = format!("Invalid value '{}' of entry '{}'", value, path);
+                    return Err(ArchiverError::new(&msg));
+                }
+            });
+        }
+        let sql = format!(
+            "INSERT INTO activities ({}) VALUES ({})",
+            entry.keys().cloned().collect::<Vec<_>>().join(", "),
+            vec!["?"; values.len()].join(", ")
+        );
+        self.execute(&sql, rusqlite::params_from_iter(values))
+            .map(|_| ())
+            .map_err(|err| {
+                let msg = format!("Unable to add '{}' to index", path);
+                ArchiverError::index(&msg, err)
+            })
+    }
+
+    /// Returns the start times and positions of all archived activities with a position
+    pub(crate) fn start_positions(&self) -> Result<Vec<(DateTime<Utc>, Position)>> {
+        let msg = "Unable to read start positions from index";
+
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT timestamp, start_latitude, start_longitude FROM activities
+                    WHERE start_latitude IS NOT NULL AND start_longitude IS NOT NULL
+                    ORDER BY timestamp",
+            )
+            .map_err(|err| ArchiverError::index(msg, err))?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, f64>(1)?,
+                    row.get::<_, f64>(2)?,
+                ))
+            })
+            .map_err(|err| ArchiverError::index(msg, err))?;
+
+        let mut positions = Vec::new();
+        for row in rows {
+            let (timestamp, latitude, longitude) =
+                row.map_err(|err| ArchiverError::index(msg, err))?;
+            if let Some(timestamp) = DateTime::from_timestamp(timestamp, 0) {
+                positions.push((timestamp, (latitude, longitude)));
+            }
+        }
+        Ok(positions)
+    }
+}
+
+impl Drop for Index {
+    fn drop(&mut self) {
+        // errors cannot be reported here, `commit` reports them before
+        let _ = self.commit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test adding activities to the index
+    fn test_index_add() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+
+        // an archive without index is not indexed in read only mode
+        assert!(super::Index::open(tmpdir.path(), false).unwrap().is_none());
+
+        let index = super::Index::open(tmpdir.path(), true).unwrap().unwrap();
+        let activity_data = crate::fitarchiver::ActivityData {
+            timestamp: chrono::Utc.with_ymd_and_hms(2014, 7, 8, 9, 10, 11).unwrap(),
+            start_position: Some((48.137, 11.575)),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+        let archive_path = tmpdir.path().join("2014/07/run.fit");
+        index.add(&archive_path, &activity_data).unwrap();
+        // adding the same file again replaces the entry
+        index.add(&archive_path, &activity_data).unwrap();
+        index
+            .add(&PathBuf::from("other/2014/07/run.fit"), &activity_data)
+            .unwrap();
+
+        let index = super::Index::open(tmpdir.path(), false).unwrap().unwrap();
+        let day = NaiveDate::from_ymd_opt(2014, 7, 8).unwrap();
+        assert_eq!(
+            vec![activity_data.timestamp, activity_data.timestamp],
+            index.day_timestamps(day).unwrap()
+        );
+        assert!(index
+            .day_timestamps(day.succ_opt().unwrap())
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            vec![
+                (activity_data.timestamp, (48.137, 11.575)),
+                (activity_data.timestamp, (48.137, 11.575))
+            ],
+            index.start_positions().unwrap()
+        );
+
+        // moved files keep their entry
+        let index = super::Index::open(tmpdir.path(), true).unwrap().unwrap();
+        index
+            .relocate(&archive_path, &PathBuf::from("/cold/2014/07/run.fit"))
+            .unwrap();
+        let path: String = index
+            .connection
+            .query_row(
+                "SELECT path FROM activities WHERE path LIKE '/cold/%'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!("/cold/2014/07/run.fit", path);
+        assert_eq!(
+            vec![
+                PathBuf::from("/cold/2014/07/run.fit"),
+                tmpdir.path().join("other/2014/07/run.fit")
+            ],
+            index.archive_paths().unwrap()
+        );
+        index
+            .remove(&PathBuf::from("/cold/2014/07/run.fit"))
+            .unwrap();
+        assert_eq!(1, index.archive_paths().unwrap().len());
+
+        // the last failure of a source file is kept until it is archived
+        let time = chrono::Utc.with_ymd_and_hms(2014, 7, 9, 0, 0, 0).unwrap();
+        index
+            .add_failure(Path::new("/media/run.fit"), "Disk full", time)
+            .unwrap();
+        index
+            .add_failure(Path::new("/media/run.fit"), "NAS offline", time)
+            .unwrap();
+        assert_eq!(
+            vec![(PathBuf::from("/media/run.fit"), String::from("NAS offline"))],
+            index.failures().unwrap()
+        );
+        index.remove_failure(Path::new("/media/run.fit")).unwrap();
+        assert!(index.failures().unwrap().is_empty());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test committing the changes of several files together while other processes read
+    fn test_index_commit_interval() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let index = super::Index::open(tmpdir.path(), true)
+            .unwrap()
+            .unwrap()
+            .with_commit_interval(2);
+        let mode: String = index
+            .connection
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!("wal", mode);
+
+        // readers see the changes of complete intervals and are not blocked by the writer
+        let activity_data = crate::fitarchiver::ActivityData::new();
+        let reader = super::Index::open(tmpdir.path(), false).unwrap().unwrap();
+        index
+            .add(&tmpdir.path().join("a.fit"), &activity_data)
+            .unwrap();
+        assert!(reader.archive_paths().unwrap().is_empty());
+        index
+            .add(&tmpdir.path().join("b.fit"), &activity_data)
+            .unwrap();
+        assert_eq!(2, reader.archive_paths().unwrap().len());
+        index
+            .add(&tmpdir.path().join("c.fit"), &activity_data)
+            .unwrap();
+        assert_eq!(2, reader.archive_paths().unwrap().len());
+        index.commit().unwrap();
+        assert_eq!(3, reader.archive_paths().unwrap().len());
+
+        // a transaction is committed as a whole
+        index
+            .add(&tmpdir.path().join("d.fit"), &activity_data)
+            .unwrap();
+        index
+            .transaction(|index| {
+                index.remove(&tmpdir.path().join("a.fit"))?;
+                index.remove(&tmpdir.path().join("b.fit"))?;
+                index.remove(&tmpdir.path().join("c.fit"))
+            })
+            .unwrap();
+        assert_eq!(
+            vec![tmpdir.path().join("d.fit")],
+            reader.archive_paths().unwrap()
+        );
+
+        // the last changes are committed when the index is closed
+        index
+            .add(&tmpdir.path().join("e.fit"), &activity_data)
+            .unwrap();
+        drop(index);
+        assert_eq!(2, reader.archive_paths().unwrap().len());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test updating the monthly statistics on changes of the activities
+    fn test_index_monthly_stats() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let index = super::Index::open(tmpdir.path(), true).unwrap().unwrap();
+        let activity = |month: u32, sport: &str, distance: f64| crate::fitarchiver::ActivityData {
+            timestamp: chrono::Utc
+                .with_ymd_and_hms(2023, month, 2, 6, 0, 0)
+                .unwrap(),
+            sport: String::from(sport),
+            duration: Some(600.0),
+            distance: Some(distance),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+        let stats = |month: &str, sport: &str, count: u32, duration: f64, distance: f64| {
+            super::MonthlyStats {
+                month: String::from(month),
+                sport: String::from(sport),
+                count,
+                duration,
+                distance,
+            }
+        };
+
+        index
+            .add(
+                &tmpdir.path().join("a.fit"),
+                &activity(7, "running", 1000.0),
+            )
+            .unwrap();
+        index
+            .add(
+                &tmpdir.path().join("b.fit"),
+                &activity(7, "running", 2000.0),
+            )
+            .unwrap();
+        index
+            .add(
+                &tmpdir.path().join("c.fit"),
+                &activity(8, "cycling", 9000.0),
+            )
+            .unwrap();
+        assert_eq!(
+            vec![
+                stats("2023-07", "running", 2, 1200.0, 3000.0),
+                stats("2023-08", "cycling", 1, 600.0, 9000.0)
+            ],
+            index.monthly_stats().unwrap()
+        );
+
+        // replacing an entry moves it to its new month and sport
+        index
+            .add(
+                &tmpdir.path().join("a.fit"),
+                &activity(8, "cycling", 4000.0),
+            )
+            .unwrap();
+        index
+            .connection
+            .execute("DELETE FROM activities WHERE path = 'b.fit'", [])
+            .unwrap();
+        assert_eq!(
+            vec![stats("2023-08", "cycling", 2, 1200.0, 13000.0)],
+            index.monthly_stats().unwrap()
+        );
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test migrating an index created with an older schema
+    fn test_index_migration() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let path = tmpdir.path().join(super::INDEX_PATH);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        // create an index with the first version of the schema
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        connection
+            .execute_batch(&format!(
+                "{} PRAGMA user_version = 1;",
+                super::MIGRATIONS[0]
+            ))
+            .unwrap();
+        drop(connection);
+
+        let index = super::Index::open(tmpdir.path(), true).unwrap().unwrap();
+        let activity_data = crate::fitarchiver::ActivityData {
+            notes: String::from("Hill repeats"),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+        index
+            .add(&tmpdir.path().join("run.fit"), &activity_data)
+            .unwrap();
+
+        let version: usize = index
+            .connection
+            .query_row("PRAGMA user_version", [], |row| row.get::<_, u32>(0))
+            .unwrap() as usize;
+        assert_eq!(super::MIGRATIONS.len(), version);
+        let notes: String = index
+            .connection
+            .query_row("SELECT notes FROM activities", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!("Hill repeats", notes);
+        assert_eq!(1, index.monthly_stats().unwrap()[0].count);
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
@@ -0,0 +1,190 @@
+//! # Summary of a single FIT file
+
+use crate::fitarchiver::{parse_fit_file, read_fit_file, ActivityData, Result};
+use crate::state::{device_name, is_device_state};
+use fitparser::profile::field_types::MesgNum;
+use std::path::PathBuf;
+
+/// Options controlling the summary of a FIT file
+#[derive(Debug, Clone, Default)]
+pub struct InfoOptions {
+    /// FIT file to summarize
+    pub file: PathBuf,
+}
+
+/// Lifetime totals of a sport kept by a device
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Totals {
+    /// Sport of the totals, i.e. 'running'
+    sport: String,
+    /// Number of sessions
+    sessions: Option<f64>,
+    /// Timer time in seconds
+    timer_time: Option<f64>,
+    /// Distance in meters
+    distance: Option<f64>,
+    /// Calories in kcal
+    calories: Option<f64>,
+}
+
+/// Returns the totals per sport of a totals file
+///
+/// # Arguments
+///
+/// * `records` - Decoded messages of the file.
+fn totals(records: &[fitparser::FitDataRecord]) -> Vec<Totals> {
+    records
+        .iter()
+        .filter(|data| data.kind() == MesgNum::Totals)
+        .map(|data| {
+            let mut totals = Totals {
+                sport: String::from("all"),
+                ..Default::default()
+            };
+            for field in data.fields() {
+                let value: Option<f64> = field.value().clone().try_into().ok();
+                match field.name() {
+                    "sport" => totals.sport = field.value().to_string(),
+                    "sessions" => totals.sessions = value,
+                    "timer_time" => totals.timer_time = value,
+                    "distance" => totals.distance = value,
+                    "calories" => totals.calories = value,
+                    _ => (), // ignore all other values
+                }
+            }
+            totals
+        })
+        .collect()
+}
+
+/// Returns the counters of the totals of a sport, i.e. '152 sessions, 210:05 h, 1834.2 km'
+///
+/// # Arguments
+///
+/// * `totals` - Totals of the sport.
+fn format_totals(totals: &Totals) -> String {
+    let mut counters = Vec::new();
+    if let Some(sessions) = totals.sessions {
+        counters.push(format!("{} sessions", sessions));
+    }
+    if let Some(timer_time) = totals.timer_time {
+        let minutes = (timer_time / 60.0).round() as u64;
+        counters.push(format!("{}:{:02} h", minutes / 60, minutes % 60));
+    }
+    if let Some(distance) = totals.distance {
+        counters.push(format!("{:.1} km", distance / 1000.0));
+    }
+    if let Some(calories) = totals.calories {
+        counters.push(format!("{} kcal", calories));
+    }
+    counters.join(", ")
+}
+
+/// Returns the metadata of a file as rows of a table
+///
+/// # Arguments
+///
+/// * `activity_data` - Activity data of the file.
+fn summary(activity_data: &ActivityData) -> Vec<(String, String)> {
+    let mut rows = vec![
+        (String::from("file type"), activity_data.file_type.clone()),
+        (
+            String::from("manufacturer"),
+            activity_data.manufacturer.clone(),
+        ),
+        (String::from("device"), device_name(activity_data)),
+        (String::from("time"), activity_data.timestamp.to_rfc3339()),
+    ];
+    if !is_device_state(activity_data) {
+        rows.extend([
+            (String::from("sport"), activity_data.sport.clone()),
+            (String::from("sub sport"), activity_data.sub_sport.clone()),
+            (
+                String::from("records"),
+                activity_data.record_count.to_string(),
+            ),
+        ]);
+        if activity_data.workout_steps > 0 {
+            rows.push((
+                String::from("workout"),
+                format!(
+                    "{} steps, {}",
+                    activity_data.workout_steps, activity_data.workout_structure
+                ),
+            ));
+        }
+    }
+    rows
+}
+
+/// Returns the metadata of a FIT file and the counters of device state files
+///
+/// # Arguments
+///
+/// `options` - Info options.
+pub fn info(options: &InfoOptions) -> Result<String> {
+    let activity_data = parse_fit_file(&options.file)?;
+    let mut rows = summary(&activity_data);
+    if is_device_state(&activity_data) {
+        rows.extend(
+            totals(&read_fit_file(&options.file)?)
+                .iter()
+                .map(|totals| (format!("totals {}", totals.sport), format_totals(totals))),
+        );
+    }
+
+    let name_width = rows.iter().map(|row| row.0.len()).max().unwrap_or(0);
+    Ok(rows
+        .iter()
+        .map(|(name, value)| format!("{:name_width$}  {}", name, value))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test the summary of a totals file
+    fn test_info() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let totals_path = tmpdir.path().join("totals.fit");
+        crate::state::write_totals_file(&totals_path);
+
+        let records = crate::fitarchiver::read_fit_file(&totals_path).unwrap();
+        assert_eq!(
+            vec![
+                super::Totals {
+                    sport: String::from("running"),
+                    sessions: Some(152.0),
+                    timer_time: Some(756300.0),
+                    distance: Some(1834215.0),
+                    calories: Some(123456.0),
+                },
+                super::Totals {
+                    sport: String::from("cycling"),
+                    sessions: Some(97.0),
+                    timer_time: Some(1206000.0),
+                    distance: Some(8412900.0),
+                    calories: Some(201000.0),
+                },
+            ],
+            super::totals(&records)
+        );
+
+        let options = super::InfoOptions { file: totals_path };
+        assert_eq!(
+            [
+                "file type       totals",
+                "manufacturer    garmin",
+                "device          fenix7-3345678901",
+                "time            2024-07-03T09:46:40+00:00",
+                "totals running  152 sessions, 210:05 h, 1834.2 km, 123456 kcal",
+                "totals cycling  97 sessions, 335:00 h, 8412.9 km, 201000 kcal",
+            ]
+            .join("\n"),
+            super::info(&options).unwrap()
+        );
+    }
+}
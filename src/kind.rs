@@ -0,0 +1,302 @@
+//! # Kind of activities
+//!
+//! Activities are classified by rules into kinds like 'commute', 'race' or 'training', i.e. to
+//! archive races into their own folder. Each rule names a kind and lists conditions on the
+//! activity data which all have to match. The first matching rule defines the kind.
+
+use crate::fitarchiver::{ActivityData, ArchiverError, Result};
+use chrono::{Datelike, Timelike, Weekday};
+use std::str::FromStr;
+
+/// Kind of activities not matching any rule
+const DEFAULT_KIND: &str = "training";
+
+/// Rules used if none are given by the user
+const DEFAULT_RULES: &[&str] = &["race=name:race|marathon|triathlon"];
+
+/// Condition of a kind rule
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    /// One of the keywords is contained in the workout name, sport name or notes
+    Keywords(Vec<String>),
+    /// The sport matches
+    Sport(String),
+    /// The start location matches, see `$r`
+    Location(String),
+    /// The activity starts on one of the days of the week
+    Days(Vec<Weekday>),
+    /// The activity starts within the hours (UTC), the end is exclusive
+    Hours(u32, u32),
+    /// The activity lasts at least the number of minutes
+    MinDuration(f64),
+    /// The activity lasts at most the number of minutes
+    MaxDuration(f64),
+}
+
+/// Rule defining a kind of activities
+#[derive(Debug, Clone, PartialEq)]
+pub struct KindRule {
+    /// Name of the kind, i.e. 'commute'
+    pub kind: String,
+    /// Conditions that all have to match
+    conditions: Vec<Condition>,
+}
+
+/// Returns the days of the week of a list like 'mon-fri|sun'
+///
+/// # Arguments
+///
+/// * `s` - List of days and ranges of days separated by '|'.
+fn parse_days(s: &str) -> Option<Vec<Weekday>> {
+    let mut days = Vec::new();
+    for range in s.split('|') {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        let mut day = first.trim().parse::<Weekday>().ok()?;
+        let last = last.trim().parse::<Weekday>().ok()?;
+        // ranges wrap around the end of the week, i.e. 'fri-mon'
+        days.push(day);
+        while day != last {
+            day = day.succ();
+            days.push(day);
+        }
+    }
+    Some(days)
+}
+
+/// Returns the start and end hour of a range like '7-9'
+///
+/// # Arguments
+///
+/// * `s` - Range of hours.
+fn parse_hours(s: &str) -> Option<(u32, u32)> {
+    let (start, end) = s.split_once('-')?;
+    let start: u32 = start.trim().parse().ok()?;
+    let end: u32 = end.trim().parse().ok()?;
+    (start < 24 && end <= 24 && start != end).then_some((start, end))
+}
+
+impl FromStr for Condition {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        let (key, value) = s.split_once(':').ok_or(())?;
+        let value = value.trim();
+        let minutes = || {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|val| *val >= 0.0)
+                .ok_or(())
+        };
+        match key.trim() {
+            "name" => {
+                let keywords: Vec<String> = value
+                    .split('|')
+                    .map(|keyword| keyword.trim().to_lowercase())
+                    .filter(|keyword| !keyword.is_empty())
+                    .collect();
+                match keywords.is_empty() {
+                    true => Err(()),
+                    false => Ok(Condition::Keywords(keywords)),
+                }
+            }
+            "sport" if !value.is_empty() => Ok(Condition::Sport(value.to_string())),
+            "location" if !value.is_empty() => Ok(Condition::Location(value.to_string())),
+            "days" => parse_days(value).map(Condition::Days).ok_or(()),
+            "hours" => parse_hours(value)
+                .map(|(start, end)| Condition::Hours(start, end))
+                .ok_or(()),
+            "min-duration" => minutes().map(Condition::MinDuration),
+            "max-duration" => minutes().map(Condition::MaxDuration),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FromStr for KindRule {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            let msg = format!(
+                "Invalid kind rule '{}', expected '<kind>=<condition>[,<condition>...]'",
+                s
+            );
+            ArchiverError::new(&msg)
+        };
+
+        let (kind, conditions) = s.split_once('=').ok_or_else(invalid)?;
+        let kind = kind.trim();
+        if kind.is_empty()
+            || !kind
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(invalid());
+        }
+        let conditions = conditions
+            .split(',')
+            .map(|condition| condition.parse::<Condition>())
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+
+        Ok(KindRule {
+            kind: kind.to_string(),
+            conditions,
+        })
+    }
+}
+
+impl Condition {
+    /// Returns true if the activity matches the condition
+    ///
+    /// # Arguments
+    ///
+    /// * `activity_data` - Activity data of the file.
+    fn matches(&self, activity_data: &ActivityData) -> bool {
+        match self {
+            Condition::Keywords(keywords) => {
+                let text = [
+                    &activity_data.workout_name,
+                    &activity_data.sport_name,
+                    &activity_data.notes,
+                ]
+                .map(|text| text.to_lowercase())
+                .join(" ");
+                keywords.iter().any(|keyword| text.contains(keyword))
+            }
+            Condition::Sport(sport) => activity_data.sport == *sport,
+            Condition::Location(location) => activity_data.start_location == *location,
+            Condition::Days(days) => days.contains(&activity_data.timestamp.weekday()),
+            Condition::Hours(start, end) => {
+                let hour = activity_data.timestamp.hour();
+                match start < end {
+                    true => (*start..*end).contains(&hour),
+                    // ranges wrap around midnight, i.e. '22-6'
+                    false => hour >= *start || hour < *end,
+                }
+            }
+            Condition::MinDuration(minutes) => activity_data
+                .duration
+                .is_some_and(|duration| duration >= minutes * 60.0),
+            Condition::MaxDuration(minutes) => activity_data
+                .duration
+                .is_some_and(|duration| duration <= minutes * 60.0),
+        }
+    }
+}
+
+/// Returns the kind of an activity
+///
+/// Without rules given by the user, activities with 'race', 'marathon' or 'triathlon' in their
+/// names or notes are races.
+///
+/// # Arguments
+///
+/// * `activity_data` - Activity data of the file.
+/// * `rules` - Kind rules given by the user.
+pub(crate) fn activity_kind(activity_data: &ActivityData, rules: &[KindRule]) -> String {
+    let default_rules: Vec<KindRule>;
+    let rules = if rules.is_empty() {
+        default_rules = DEFAULT_RULES
+            .iter()
+            .map(|rule| rule.parse().unwrap())
+            .collect();
+        &default_rules
+    } else {
+        rules
+    };
+
+    rules
+        .iter()
+        .find(|rule| {
+            rule.conditions
+                .iter()
+                .all(|condition| condition.matches(activity_data))
+        })
+        .map_or(DEFAULT_KIND, |rule| rule.kind.as_str())
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fitarchiver::ActivityData;
+    use chrono::TimeZone;
+
+    #[test]
+    /// Test parsing kind rules given by the user
+    fn test_kind_rule_from_str() {
+        for rule in [
+            "commute=sport:cycling,location:home,days:mon-fri,hours:6-9,max-duration:45",
+            "race=name:race|marathon",
+            "long=min-duration:120,days:fri-mon|wed,hours:22-6",
+        ] {
+            rule.parse::<super::KindRule>().unwrap();
+        }
+        for rule in [
+            "commute",
+            "=name:race",
+            "my/kind=name:race",
+            "race=name:",
+            "race=name",
+            "race=speed:fast",
+            "early=hours:7-7",
+            "early=hours:7-25",
+            "weekend=days:saturday-funday",
+            "short=max-duration:-5",
+        ] {
+            rule.parse::<super::KindRule>().expect_err("error expected");
+        }
+    }
+
+    #[test]
+    /// Test classifying activities by rules
+    fn test_activity_kind() {
+        let rules: Vec<super::KindRule> = [
+            "commute=sport:cycling,location:home,days:mon-fri,hours:6-9,max-duration:45",
+            "race=name:race|marathon",
+            "night=hours:22-6",
+        ]
+        .iter()
+        .map(|rule| rule.parse().unwrap())
+        .collect();
+        // Wednesday morning
+        let commute = || ActivityData {
+            sport: String::from("cycling"),
+            start_location: String::from("home"),
+            timestamp: chrono::Utc.with_ymd_and_hms(2023, 7, 26, 7, 30, 0).unwrap(),
+            duration: Some(1800.0),
+            ..ActivityData::new()
+        };
+        assert_eq!("commute", super::activity_kind(&commute(), &rules));
+
+        let long = ActivityData {
+            duration: Some(3600.0),
+            ..commute()
+        };
+        assert_eq!("training", super::activity_kind(&long, &rules));
+
+        let weekend = ActivityData {
+            timestamp: chrono::Utc.with_ymd_and_hms(2023, 7, 29, 7, 30, 0).unwrap(),
+            ..commute()
+        };
+        assert_eq!("training", super::activity_kind(&weekend, &rules));
+
+        let race = ActivityData {
+            sport: String::from("running"),
+            notes: String::from("City Marathon"),
+            ..commute()
+        };
+        assert_eq!("race", super::activity_kind(&race, &rules));
+
+        let night = ActivityData {
+            timestamp: chrono::Utc.with_ymd_and_hms(2023, 7, 26, 23, 0, 0).unwrap(),
+            ..commute()
+        };
+        assert_eq!("night", super::activity_kind(&night, &rules));
+
+        // races are detected without rules given by the user
+        assert_eq!("race", super::activity_kind(&race, &[]));
+        assert_eq!("training", super::activity_kind(&commute(), &[]));
+    }
+}
@@ -0,0 +1,609 @@
+//! # Export of the laps of an activity
+//!
+//! The records of an activity are split at the laps of the activity, so that interval workouts
+//! can be analyzed rep by rep. Each lap is written to a separate GPX or CSV file. Whole
+//! activities are exported to GPX for mapping tools, with a waypoint at the start of each lap.
+//! Records are optionally downsampled, i.e. from 1 s to 10 s, to keep bulk exports manageable.
+
+use crate::fitarchiver::{export_path, parse_fit_file, read_fit_file, ArchiverError, Result};
+use crate::location::{degrees, Position};
+use crate::template::check_template;
+use chrono::{DateTime, Utc};
+use fitparser::profile::field_types::MesgNum;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// File format of the exported laps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LapFormat {
+    /// GPS exchange format with one track per lap
+    #[default]
+    Gpx,
+    /// Comma separated values with one line per record
+    Csv,
+}
+
+impl FromStr for LapFormat {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "gpx" => Ok(LapFormat::Gpx),
+            "csv" => Ok(LapFormat::Csv),
+            _ => {
+                let msg = format!("Invalid lap format '{}', expected 'gpx' or 'csv'", s);
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+impl LapFormat {
+    /// Returns the file extension of the format
+    fn extension(&self) -> &'static str {
+        match self {
+            LapFormat::Gpx => "gpx",
+            LapFormat::Csv => "csv",
+        }
+    }
+}
+
+/// Options controlling the export of laps
+#[derive(Debug, Clone)]
+pub struct LapsOptions {
+    /// FIT file to export
+    pub file: PathBuf,
+    /// Directory receiving the exported files
+    pub output: PathBuf,
+    /// Format template that defines the path and name of the exported files within the output
+    /// directory, `None` to name them after the FIT file
+    pub template: Option<String>,
+    /// File format of the exported laps
+    pub format: LapFormat,
+    /// Length of the intervals the records are aggregated to in seconds, 1 to keep all records
+    pub sample_rate: u64,
+}
+
+impl Default for LapsOptions {
+    fn default() -> Self {
+        LapsOptions {
+            file: PathBuf::new(),
+            output: PathBuf::from("."),
+            template: None,
+            format: LapFormat::default(),
+            sample_rate: 1,
+        }
+    }
+}
+
+/// Start and end time of a lap
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Lap {
+    /// Start time of the lap
+    start: DateTime<Utc>,
+    /// End time of the lap
+    end: DateTime<Utc>,
+}
+
+/// Values of a record message
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Point {
+    /// Time of the record
+    timestamp: Option<DateTime<Utc>>,
+    /// Latitude and longitude in degrees
+    position: Option<Position>,
+    /// Altitude in meters
+    altitude: Option<f64>,
+    /// Distance since the start of the activity in meters
+    distance: Option<f64>,
+    /// Heart rate in beats per minute
+    heart_rate: Option<f64>,
+    /// Power in watts
+    power: Option<f64>,
+}
+
+/// Returns the laps and the records of a FIT file
+///
+/// # Arguments
+///
+/// * `path` - Path of the FIT file.
+fn read_laps(path: &Path) -> Result<(Vec<Lap>, Vec<Point>)> {
+    let mut laps = Vec::new();
+    let mut points = Vec::new();
+    for data in read_fit_file(path)? {
+        let timestamp = |name: &str| {
+            data.fields()
+                .iter()
+                .find(|field| field.name() == name)
+                .and_then(|field| match field.value() {
+                    fitparser::Value::Timestamp(val) => Some(DateTime::<Utc>::from(*val)),
+                    _ => None,
+                })
+        };
+        let number = |name: &str| {
+            data.fields()
+                .iter()
+                .find(|field| field.name() == name)
+                .and_then(|field| field.value().clone().try_into().ok())
+        };
+
+        match data.kind() {
+            MesgNum::Lap => {
+                if let (Some(start), Some(end)) = (timestamp("start_time"), timestamp("timestamp"))
+                {
+                    laps.push(Lap { start, end });
+                }
+            }
+            MesgNum::Record => {
+                let position = match (number("position_lat"), number("position_long")) {
+                    (Some(latitude), Some(longitude)) => {
+                        Some((degrees(latitude), degrees(longitude)))
+                    }
+                    _ => None,
+                };
+                points.push(Point {
+                    timestamp: timestamp("timestamp"),
+                    position,
+                    altitude: number("enhanced_altitude").or_else(|| number("altitude")),
+                    distance: number("distance"),
+                    heart_rate: number("heart_rate"),
+                    power: number("power"),
+                });
+            }
+            _ => (), // ignore all other messages
+        }
+    }
+    laps.sort_by_key(|lap| lap.start);
+    Ok((laps, points))
+}
+
+/// Returns the records of a lap
+///
+/// # Arguments
+///
+/// * `lap` - Start and end time of the lap.
+/// * `points` - Records of the activity.
+fn lap_points(lap: &Lap, points: &[Point]) -> Vec<Point> {
+    points
+        .iter()
+        .filter(|point| {
+            point
+                .timestamp
+                .is_some_and(|timestamp| lap.start <= timestamp && timestamp <= lap.end)
+        })
+        .copied()
+        .collect()
+}
+
+/// Returns the mean of the present values, `None` if all values are missing
+///
+/// # Arguments
+///
+/// * `values` - Optional values.
+fn mean(values: impl Iterator<Item = Option<f64>>) -> Option<f64> {
+    let values: Vec<f64> = values.flatten().collect();
+    match values.is_empty() {
+        true => None,
+        false => Some(values.iter().sum::<f64>() / values.len() as f64),
+    }
+}
+
+/// Returns the records aggregated to intervals of a fixed length
+///
+/// The intervals start at the first record. Each aggregated record has the time and position of
+/// the first record of its interval, the distance of the last one, the mean altitude and power
+/// and the maximum heart rate. Records without time are left out.
+///
+/// # Arguments
+///
+/// * `points` - Records in chronological order.
+/// * `sample_rate` - Length of the intervals in seconds, 1 to keep all records.
+fn downsample(points: &[Point], sample_rate: u64) -> Vec<Point> {
+    if sample_rate <= 1 {
+        return points.to_vec();
+    }
+    let Some(start) = points.iter().find_map(|point| point.timestamp) else {
+        return Vec::new();
+    };
+
+    let mut intervals: Vec<(i64, Vec<&Point>)> = Vec::new();
+    for point in points {
+        let Some(timestamp) = point.timestamp else {
+            continue;
+        };
+        let interval = (timestamp - start)
+            .num_seconds()
+            .div_euclid(sample_rate as i64);
+        match intervals.last_mut() {
+            Some((last, group)) if *last == interval => group.push(point),
+            _ => intervals.push((interval, vec![point])),
+        }
+    }
+
+    intervals
+        .into_iter()
+        .map(|(_, group)| Point {
+            timestamp: group[0].timestamp,
+            position: group.iter().find_map(|point| point.position),
+            altitude: mean(group.iter().map(|point| point.altitude)),
+            distance: group.iter().rev().find_map(|point| point.distance),
+            heart_rate: group
+                .iter()
+                .filter_map(|point| point.heart_rate)
+                .reduce(f64::max),
+            power: mean(group.iter().map(|point| point.power)),
+        })
+        .collect()
+}
+
+/// Returns an optional value as text, empty if it is missing
+///
+/// # Arguments
+///
+/// * `value` - Optional value.
+fn optional_text(value: Option<f64>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// Returns the records of a lap as CSV
+///
+/// # Arguments
+///
+/// * `points` - Records of the lap.
+fn format_csv(points: &[Point]) -> String {
+    let mut lines = vec![String::from(
+        "timestamp,latitude,longitude,altitude,distance,heart_rate,power",
+    )];
+    for point in points {
+        lines.push(format!(
+            "{},{},{},{},{},{},{}",
+            point
+                .timestamp
+                .map(|timestamp| timestamp.to_rfc3339())
+                .unwrap_or_default(),
+            optional_text(point.position.map(|position| position.0)),
+            optional_text(point.position.map(|position| position.1)),
+            optional_text(point.altitude),
+            optional_text(point.distance),
+            optional_text(point.heart_rate),
+            optional_text(point.power)
+        ));
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Returns a text with the special characters of XML escaped
+///
+/// # Arguments
+///
+/// * `text` - Text to escape.
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Returns the GPX element of a point, `None` if the point has no position
+///
+/// # Arguments
+///
+/// * `element` - Name of the element, i.e. 'trkpt'.
+/// * `indent` - Indentation of the element.
+/// * `point` - Record of the activity.
+/// * `name` - Name of the point.
+fn gpx_point(element: &str, indent: &str, point: &Point, name: Option<&str>) -> Option<String> {
+    let (latitude, longitude) = point.position?;
+    let mut lines = vec![format!(
+        r#"{}<{} lat="{:.7}" lon="{:.7}">"#,
+        indent, element, latitude, longitude
+    )];
+    if let Some(altitude) = point.altitude {
+        lines.push(format!("{}  <ele>{}</ele>", indent, altitude));
+    }
+    if let Some(timestamp) = point.timestamp {
+        lines.push(format!(
+            "{}  <time>{}</time>",
+            indent,
+            timestamp.format("%Y-%m-%dT%H:%M:%SZ")
+        ));
+    }
+    if let Some(name) = name {
+        lines.push(format!("{}  <name>{}</name>", indent, escape_xml(name)));
+    }
+    lines.push(format!("{}</{}>", indent, element));
+    Some(lines.join("\n"))
+}
+
+/// Returns records as GPX track, records without position are left out
+///
+/// # Arguments
+///
+/// * `name` - Name of the track.
+/// * `points` - Records of the track.
+/// * `waypoints` - Named points preceding the track.
+fn format_gpx(name: &str, points: &[Point], waypoints: &[(String, Point)]) -> String {
+    let mut lines = vec![
+        String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#),
+        String::from(
+            r#"<gpx version="1.1" creator="fitarchiver" xmlns="http://www.topografix.com/GPX/1/1">"#,
+        ),
+    ];
+    lines.extend(
+        waypoints
+            .iter()
+            .filter_map(|(name, point)| gpx_point("wpt", "  ", point, Some(name))),
+    );
+    lines.push(String::from("  <trk>"));
+    lines.push(format!("    <name>{}</name>", escape_xml(name)));
+    lines.push(String::from("    <trkseg>"));
+    lines.extend(
+        points
+            .iter()
+            .filter_map(|point| gpx_point("trkpt", "      ", point, None)),
+    );
+    lines.push(String::from("    </trkseg>"));
+    lines.push(String::from("  </trk>"));
+    lines.push(String::from("</gpx>"));
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Write each lap of a FIT file to a separate GPX or CSV file
+///
+/// The files are named after the FIT file and the number of the lap, i.e. 'run-lap01.gpx'. With
+/// a template they are named after the expanded template instead, i.e.
+/// 'exports/2023/running-20230726-lap01.gpx'. With a sample rate above 1 s the records of each
+/// lap are aggregated to intervals of that length.
+///
+/// # Arguments
+///
+/// `options` - Laps options.
+pub fn export_laps(options: &LapsOptions) -> Result<String> {
+    if let Some(template) = &options.template {
+        check_template(template)?;
+    }
+    let (laps, points) = read_laps(&options.file)?;
+    if laps.is_empty() {
+        let msg = format!("No laps in '{}'", options.file.display());
+        return Err(ArchiverError::new(&msg));
+    }
+
+    let extension = options.format.extension();
+    let base_path = match &options.template {
+        Some(template) => export_path(
+            &options.output,
+            template,
+            &parse_fit_file(&options.file)?,
+            extension,
+        ),
+        None => options.output.join(format!(
+            "{}.{}",
+            options
+                .file
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy(),
+            extension
+        )),
+    };
+    let directory = base_path.parent().unwrap_or(&options.output);
+    if let Err(err) = fs::create_dir_all(directory) {
+        let msg = format!(
+            "Unable to create output directory '{}'",
+            directory.display()
+        );
+        return Err(ArchiverError::io(&msg, err));
+    }
+
+    let stem = base_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    for (number, lap) in laps.iter().enumerate() {
+        let name = format!("{}-lap{:02}", stem, number + 1);
+        let points = downsample(&lap_points(lap, &points), options.sample_rate);
+        let content = match options.format {
+            LapFormat::Gpx => format_gpx(&name, &points, &[]),
+            LapFormat::Csv => format_csv(&points),
+        };
+
+        let path = directory.join(&name).with_extension(extension);
+        if let Err(err) = fs::write(&path, content) {
+            let msg = format!("Unable to create file '{}'", path.display());
+            return Err(ArchiverError::io(&msg, err));
+        }
+        println!("'{}' ... {} records", path.display(), points.len());
+    }
+
+    Ok(format!("Exported {} laps", laps.len()))
+}
+
+/// Write the track of a FIT file to a GPX file with a waypoint at the start of each lap
+///
+/// The directory of the GPX file is created if needed.
+///
+/// # Arguments
+///
+/// * `path` - Path of the FIT file.
+/// * `gpx_path` - Path of the GPX file.
+pub(crate) fn write_gpx(path: &Path, gpx_path: &Path) -> Result<()> {
+    let (laps, points) = read_laps(path)?;
+    let waypoints: Vec<(String, Point)> = laps
+        .iter()
+        .enumerate()
+        .filter_map(|(number, lap)| {
+            let start = lap_points(lap, &points)
+                .into_iter()
+                .find(|point| point.position.is_some())?;
+            Some((format!("Lap {}", number + 1), start))
+        })
+        .collect();
+    let name = gpx_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let msg = format!("Unable to create file '{}'", gpx_path.display());
+    if let Some(parent) = gpx_path.parent() {
+        fs::create_dir_all(parent).map_err(|err| ArchiverError::io(&msg, err))?;
+    }
+    fs::write(gpx_path, format_gpx(&name, &points, &waypoints))
+        .map_err(|err| ArchiverError::io(&msg, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test exporting the laps of a FIT file
+    fn test_export_laps() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let mut options = super::LapsOptions {
+            file: PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("test")
+                .join("test_data_01.fit"),
+            output: tmpdir.path().join("laps"),
+            template: None,
+            format: super::LapFormat::Csv,
+            sample_rate: 1,
+        };
+
+        assert_eq!("Exported 1 laps", super::export_laps(&options).unwrap());
+        let csv = fs::read_to_string(tmpdir.path().join("laps/test_data_01-lap01.csv")).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(13, lines.len());
+        assert_eq!(
+            "timestamp,latitude,longitude,altitude,distance,heart_rate,power",
+            lines[0]
+        );
+        assert!(lines[1].starts_with("2023-07-26T06:22:04+00:00,48.72"));
+        assert!(lines[1].ends_with(",434,0,86,0"));
+
+        options.sample_rate = 3600;
+        super::export_laps(&options).unwrap();
+        let csv = fs::read_to_string(tmpdir.path().join("laps/test_data_01-lap01.csv")).unwrap();
+        assert_eq!(2, csv.lines().count());
+        assert!(csv
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("2023-07-26T06:22:04+00:00,48.72"));
+        options.sample_rate = 1;
+
+        options.format = super::LapFormat::Gpx;
+        super::export_laps(&options).unwrap();
+        let gpx = fs::read_to_string(tmpdir.path().join("laps/test_data_01-lap01.gpx")).unwrap();
+        assert!(gpx.contains("<name>test_data_01-lap01</name>"));
+        assert_eq!(12, gpx.matches("<trkpt ").count());
+        assert!(gpx.contains("<time>2023-07-26T06:22:04Z</time>"));
+
+        // exports with their own template live in a parallel tree
+        options.template = Some(String::from("exports/%Y/$s-%Y%m%d.gpx"));
+        super::export_laps(&options).unwrap();
+        let gpx = tmpdir
+            .path()
+            .join("laps/exports/2023/running-20230726-lap01.gpx");
+        assert!(fs::read_to_string(gpx)
+            .unwrap()
+            .contains("<name>running-20230726-lap01</name>"));
+        options.template = Some(String::from("${x}"));
+        super::export_laps(&options).expect_err("error expected");
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test splitting records at laps
+    fn test_lap_points() {
+        let time = |second| {
+            chrono::Utc
+                .with_ymd_and_hms(2023, 7, 26, 6, 0, second)
+                .unwrap()
+        };
+        let point = |second| super::Point {
+            timestamp: Some(time(second)),
+            ..Default::default()
+        };
+        let points = vec![
+            point(0),
+            point(10),
+            point(20),
+            point(30),
+            super::Point::default(),
+        ];
+        let lap = super::Lap {
+            start: time(10),
+            end: time(20),
+        };
+        assert_eq!(vec![point(10), point(20)], super::lap_points(&lap, &points));
+    }
+
+    #[test]
+    /// Test aggregating records to intervals
+    fn test_downsample() {
+        let point = |second, heart_rate, power| super::Point {
+            timestamp: Some(
+                chrono::Utc
+                    .with_ymd_and_hms(2023, 7, 26, 6, 0, second)
+                    .unwrap(),
+            ),
+            position: Some((48.0 + second as f64 / 1000.0, 9.0)),
+            distance: Some(second as f64 * 3.0),
+            heart_rate: Some(heart_rate),
+            power,
+            ..Default::default()
+        };
+        let points = vec![
+            point(5, 120.0, Some(200.0)),
+            point(6, 130.0, Some(300.0)),
+            point(14, 125.0, None),
+            point(15, 140.0, Some(250.0)),
+            super::Point::default(),
+        ];
+
+        let samples = super::downsample(&points, 10);
+        assert_eq!(2, samples.len());
+        assert_eq!(points[0].timestamp, samples[0].timestamp);
+        assert_eq!(points[0].position, samples[0].position);
+        assert_eq!(Some(42.0), samples[0].distance);
+        assert_eq!(Some(130.0), samples[0].heart_rate);
+        assert_eq!(Some(250.0), samples[0].power);
+        assert_eq!(points[3].timestamp, samples[1].timestamp);
+        assert_eq!(Some(140.0), samples[1].heart_rate);
+        assert_eq!(Some(250.0), samples[1].power);
+
+        assert_eq!(points, super::downsample(&points, 1));
+        assert!(super::downsample(&[], 10).is_empty());
+    }
+
+    #[test]
+    /// Test exporting the track of a FIT file with the starts of the laps as waypoints
+    fn test_write_gpx() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let gpx_path = tmpdir.path().join("2023/07/run.gpx");
+        super::write_gpx(
+            &PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("test")
+                .join("test_data_01.fit"),
+            &gpx_path,
+        )
+        .unwrap();
+
+        let gpx = fs::read_to_string(&gpx_path).unwrap();
+        assert_eq!(12, gpx.matches("<trkpt ").count());
+        assert_eq!(1, gpx.matches("<wpt ").count());
+        assert!(gpx.contains("<name>Lap 1</name>"));
+        assert!(gpx.contains("<name>run</name>"));
+        assert!(gpx.find("<wpt ").unwrap() < gpx.find("<trk>").unwrap());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
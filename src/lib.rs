@@ -0,0 +1,113 @@
+//! # FIT file archiver
+//!
+//! `fitarchiver` is a tool to copy or move FIT files based on information contained in the file.
+//! The library part allows to reuse the parsing and naming logic in other applications.
+
+mod backup;
+mod checksum;
+mod clock;
+mod control;
+mod dedupe;
+mod destination;
+mod device;
+mod diff;
+mod dump;
+mod exec;
+mod fitarchiver;
+mod fsck;
+mod gaps;
+mod geocode;
+mod git;
+mod hash;
+mod hrv;
+mod ignore;
+mod index;
+mod info;
+mod kind;
+mod laps;
+mod location;
+mod manifest;
+mod metadata;
+mod metrics;
+mod migrate;
+mod name;
+mod objects;
+mod overlay;
+mod portable;
+mod prune;
+mod query;
+mod remote;
+mod resolver;
+mod restore;
+mod route;
+mod signature;
+mod sports;
+mod ssh;
+mod state;
+mod stats;
+mod storage;
+mod template;
+mod tier;
+mod timezone;
+mod touch;
+mod upgrade;
+mod upload;
+mod usage;
+mod watch;
+mod webdav;
+mod webhook;
+mod workout;
+
+pub use crate::backup::{gc, GcOptions, BACKUP_PATH};
+pub use crate::checksum::{verify, VerifyOptions, CHECKSUM_FILE};
+pub use crate::clock::{SuspiciousTimePolicy, QUARANTINE_PATH};
+pub use crate::dedupe::*;
+pub use crate::diff::*;
+pub use crate::dump::*;
+pub use crate::exec::split_command;
+pub use crate::fitarchiver::*;
+pub use crate::fsck::*;
+pub use crate::gaps::{GapPolicy, DEFAULT_GAP_THRESHOLD};
+pub use crate::git::GitMode;
+pub use crate::hash::HashAlgorithm;
+pub use crate::hrv::*;
+pub use crate::ignore::{ignore, IgnoreOptions, IGNORE_FILE};
+pub use crate::index::INDEX_PATH;
+pub use crate::info::*;
+pub use crate::kind::KindRule;
+pub use crate::laps::*;
+pub use crate::location::StartLocation;
+pub use crate::manifest::MANIFEST_PATH;
+pub use crate::migrate::*;
+pub use crate::name::*;
+pub use crate::objects::{StorageLayout, OBJECTS_PATH};
+pub use crate::portable::*;
+pub use crate::prune::*;
+pub use crate::query::*;
+pub use crate::resolver::{ConflictFile, ConflictResolver, Resolution};
+pub use crate::restore::*;
+pub use crate::route::Route;
+pub use crate::signature::SIGNATURE_EXTENSION;
+pub use crate::sports::{MultisportNaming, SportDirectory};
+pub use crate::state::DEFAULT_DEVICE_STATE_TEMPLATE;
+pub use crate::stats::*;
+pub use crate::template::{check_template, Variable};
+pub use crate::tier::*;
+pub use crate::timezone::{TimeMode, Timezone};
+pub use crate::touch::*;
+pub use crate::upgrade::*;
+pub use crate::upload::{
+    UploadService, DEFAULT_UPLOAD_DAILY_LIMIT, STRAVA_CLIENT_ID_VARIABLE,
+    STRAVA_CLIENT_SECRET_VARIABLE, STRAVA_QUEUE_PATH, STRAVA_REFRESH_TOKEN_VARIABLE,
+    STRAVA_UPLOADS_PATH,
+};
+pub use crate::usage::{doctor, usage_stats_path, DoctorOptions, USAGE_STATS_PATH};
+pub use crate::watch::{watch, WatchSource};
+pub use crate::webdav::{WEBDAV_PASSWORD_VARIABLE, WEBDAV_USER_VARIABLE};
+pub use crate::webhook::parse_webhook_url;
+
+#[cfg(feature = "capi")]
+pub mod capi;
+
+#[cfg(feature = "python")]
+mod python;
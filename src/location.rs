@@ -0,0 +1,302 @@
+//! # Start locations of activities
+//!
+//! The start coordinates of the activities are clustered into start locations without the need
+//! of geocoding. Each start location covers all starts within a radius around its center. The
+//! centers are either labeled by the user or defined by the earliest start of a cluster.
+
+use crate::fitarchiver::{ActivityData, ArchiverError, Result};
+use chrono::{DateTime, Utc};
+use std::str::FromStr;
+
+/// Mean radius of the earth in meters
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// Characters of the geohash, each encoding 5 bits
+const GEOHASH_CHARACTERS: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// Position as latitude and longitude in degrees
+pub(crate) type Position = (f64, f64);
+
+/// Start location labeled by the user
+#[derive(Debug, Clone, PartialEq)]
+pub struct StartLocation {
+    /// Name of the start location, i.e. 'home'
+    pub name: String,
+    /// Latitude of the center in degrees
+    pub latitude: f64,
+    /// Longitude of the center in degrees
+    pub longitude: f64,
+}
+
+impl FromStr for StartLocation {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            let msg = format!(
+                "Invalid start location '{}', expected '<name>=<latitude>,<longitude>'",
+                s
+            );
+            ArchiverError::new(&msg)
+        };
+
+        let (name, coordinates) = s.split_once('=').ok_or_else(invalid)?;
+        let (latitude, longitude) = coordinates.split_once(',').ok_or_else(invalid)?;
+        let latitude: f64 = latitude.trim().parse().map_err(|_| invalid())?;
+        let longitude: f64 = longitude.trim().parse().map_err(|_| invalid())?;
+        if name.trim().is_empty()
+            || !(-90.0..=90.0).contains(&latitude)
+            || !(-180.0..=180.0).contains(&longitude)
+        {
+            return Err(invalid());
+        }
+
+        Ok(StartLocation {
+            name: name.trim().to_string(),
+            latitude,
+            longitude,
+        })
+    }
+}
+
+/// Returns a position in degrees converted from semicircles used by FIT files
+///
+/// # Arguments
+///
+/// * `semicircles` - Coordinate in semicircles.
+pub(crate) fn degrees(semicircles: f64) -> f64 {
+    semicircles * 180.0 / 2f64.powi(31)
+}
+
+/// Returns the great circle distance between two positions in meters
+///
+/// # Arguments
+///
+/// * `first` - First position.
+/// * `second` - Second position.
+pub(crate) fn distance(first: Position, second: Position) -> f64 {
+    let (lat1, lon1) = (first.0.to_radians(), first.1.to_radians());
+    let (lat2, lon2) = (second.0.to_radians(), second.1.to_radians());
+    let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+/// Returns the geohash of a position, i.e. 'u220d' for Bolzano
+///
+/// Positions sharing the first characters of their geohash are in the same cell, 5 characters
+/// give cells of about 5 km.
+///
+/// # Arguments
+///
+/// * `position` - Position to encode.
+/// * `precision` - Number of characters.
+pub(crate) fn geohash(position: Position, precision: usize) -> String {
+    let mut latitude = (-90.0, 90.0);
+    let mut longitude = (-180.0, 180.0);
+    let mut hash = String::new();
+    let mut bits = 0;
+    let mut index = 0;
+    // the bits alternate between longitude and latitude, starting with the longitude
+    let mut even = true;
+    while hash.len() < precision {
+        let (range, value) = match even {
+            true => (&mut longitude, position.1),
+            false => (&mut latitude, position.0),
+        };
+        let middle = (range.0 + range.1) / 2.0;
+        index <<= 1;
+        if value >= middle {
+            index |= 1;
+            range.0 = middle;
+        } else {
+            range.1 = middle;
+        }
+        even = !even;
+        bits += 1;
+        if bits == 5 {
+            hash.push(GEOHASH_CHARACTERS[index] as char);
+            bits = 0;
+            index = 0;
+        }
+    }
+    hash
+}
+
+/// Returns the name of an unlabeled start location
+///
+/// # Arguments
+///
+/// * `position` - Center of the start location.
+fn position_name(position: Position) -> String {
+    format!("{:.3}_{:.3}", position.0, position.1)
+}
+
+/// Returns the nearest start location within the radius
+///
+/// # Arguments
+///
+/// * `centers` - Names and centers of the start locations.
+/// * `position` - Start position of an activity.
+/// * `radius` - Radius of the start locations in meters.
+fn nearest(centers: &[(String, Position)], position: Position, radius: f64) -> Option<&str> {
+    centers
+        .iter()
+        .map(|(name, center)| (name, distance(*center, position)))
+        .filter(|(_, distance)| *distance <= radius)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(name, _)| name.as_str())
+}
+
+/// Returns the start location of each activity
+///
+/// Starts not covered by a labeled start location define new start locations in chronological
+/// order. The starts of activities already archived are taken into account, so that the names
+/// are stable across several runs.
+///
+/// # Arguments
+///
+/// * `activities` - Activity data of the files, `None` for files that could not be parsed.
+/// * `known` - Start times and positions of the activities already archived.
+/// * `labels` - Start locations labeled by the user.
+/// * `radius` - Radius of the start locations in meters.
+pub(crate) fn start_locations(
+    activities: &[Option<&ActivityData>],
+    known: &[(DateTime<Utc>, Position)],
+    labels: &[StartLocation],
+    radius: f64,
+) -> Vec<String> {
+    let mut starts: Vec<(DateTime<Utc>, Position)> = activities
+        .iter()
+        .flatten()
+        .filter_map(|activity_data| {
+            activity_data
+                .start_position
+                .map(|position| (activity_data.timestamp, position))
+        })
+        .chain(known.iter().copied())
+        .collect();
+    starts.sort_by_key(|(timestamp, _)| *timestamp);
+
+    let mut centers: Vec<(String, Position)> = labels
+        .iter()
+        .map(|label| (label.name.clone(), (label.latitude, label.longitude)))
+        .collect();
+    for (_, position) in starts {
+        if nearest(&centers, position, radius).is_none() {
+            centers.push((position_name(position), position));
+        }
+    }
+
+    activities
+        .iter()
+        .map(|activity_data| {
+            activity_data
+                .and_then(|activity_data| activity_data.start_position)
+                .and_then(|position| nearest(&centers, position, radius))
+                .unwrap_or("unknown")
+                .to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    #[test]
+    /// Test parsing start locations labeled by the user
+    fn test_start_location_from_str() {
+        assert_eq!(
+            super::StartLocation {
+                name: String::from("home"),
+                latitude: 48.137,
+                longitude: 11.575,
+            },
+            "home=48.137, 11.575".parse().unwrap()
+        );
+        "home"
+            .parse::<super::StartLocation>()
+            .expect_err("error expected");
+        "=48.1,11.5"
+            .parse::<super::StartLocation>()
+            .expect_err("error expected");
+        "home=91,11.5"
+            .parse::<super::StartLocation>()
+            .expect_err("error expected");
+        "home=48.1,east"
+            .parse::<super::StartLocation>()
+            .expect_err("error expected");
+    }
+
+    #[test]
+    /// Test distances and coordinate conversion
+    fn test_distance() {
+        assert_eq!(90.0, super::degrees(2f64.powi(30)));
+        // one degree of latitude is about 111 km
+        let distance = super::distance((48.0, 11.0), (49.0, 11.0));
+        assert!((distance - 111_195.0).abs() < 1.0, "{}", distance);
+        assert_eq!(0.0, super::distance((48.0, 11.0), (48.0, 11.0)));
+    }
+
+    #[test]
+    /// Test encoding positions as geohash
+    fn test_geohash() {
+        assert_eq!("u4pruydqqvj", super::geohash((57.64911, 10.40744), 11));
+        assert_eq!("u220d", super::geohash((46.4983, 11.3548), 5));
+        assert_eq!("6gkzwgjz", super::geohash((-25.382708, -49.265506), 8));
+        assert_eq!("s", super::geohash((0.0, 0.0), 1));
+    }
+
+    #[test]
+    /// Test clustering start positions into start locations
+    fn test_start_locations() {
+        let activity = |hour: u32, position: Option<(f64, f64)>| crate::fitarchiver::ActivityData {
+            timestamp: chrono::Utc
+                .with_ymd_and_hms(2023, 7, 26, hour, 0, 0)
+                .unwrap(),
+            start_position: position,
+            ..crate::fitarchiver::ActivityData::new()
+        };
+        let home = activity(6, Some((48.1372, 11.5755)));
+        let near_home = activity(7, Some((48.1380, 11.5760)));
+        let office = activity(8, Some((48.1500, 11.6000)));
+        let near_office = activity(9, Some((48.1510, 11.6010)));
+        let indoor = activity(10, None);
+        let activities = vec![
+            Some(&near_office),
+            Some(&office),
+            Some(&home),
+            None,
+            Some(&near_home),
+            Some(&indoor),
+        ];
+        let labels = vec![super::StartLocation {
+            name: String::from("home"),
+            latitude: 48.137,
+            longitude: 11.575,
+        }];
+
+        assert_eq!(
+            vec![
+                "48.150_11.600",
+                "48.150_11.600",
+                "home",
+                "unknown",
+                "home",
+                "unknown"
+            ],
+            super::start_locations(&activities, &[], &labels, 500.0)
+        );
+
+        // an earlier archived start defines the center
+        let known = vec![(
+            chrono::Utc.with_ymd_and_hms(2023, 7, 1, 8, 0, 0).unwrap(),
+            (48.1505, 11.6005),
+        )];
+        assert_eq!(
+            "48.151_11.601",
+            super::start_locations(&activities, &known, &labels, 500.0)[1]
+        );
+    }
+}
@@ -4,17 +4,117 @@
 
 use std::process::ExitCode;
 
-mod fitarchiver;
+mod cli;
+mod config;
+mod notify;
 
 mod my_module {
     // your code here
 }
 
 fn main() -> ExitCode {
-    match fitarchiver::process_files(&fitarchiver::parse_arguments(None)) {
-        Ok(val) => println!("{}", val),
+    let matches = cli::parse_arguments(None);
+    let result = match matches.subcommand() {
+        Some(("dedupe", sub_matches)) => {
+            Some(fitarchiver::dedupe(&cli::dedupe_options(sub_matches)))
+        }
+        Some(("name", sub_matches)) => {
+            Some(fitarchiver::name_file(&cli::name_options(sub_matches)))
+        }
+        Some(("diff-files", sub_matches)) => {
+            Some(fitarchiver::diff_files(&cli::diff_options(sub_matches)))
+        }
+        Some(("dump", sub_matches)) => Some(fitarchiver::dump(&cli::dump_options(sub_matches))),
+        Some(("doctor", _)) => Some(fitarchiver::doctor(&cli::doctor_options())),
+        Some(("info", sub_matches)) => Some(fitarchiver::info(&cli::info_options(sub_matches))),
+        Some(("verify", sub_matches)) => {
+            Some(fitarchiver::verify(&cli::verify_options(sub_matches)))
+        }
+        Some(("fsck", sub_matches)) => Some(fitarchiver::fsck(&cli::fsck_options(sub_matches))),
+        Some(("index", sub_matches)) => match sub_matches.subcommand() {
+            Some(("upgrade", sub_matches)) => Some(fitarchiver::upgrade_index(
+                &cli::upgrade_options(sub_matches),
+            )),
+            Some(("dump", sub_matches)) => Some(fitarchiver::dump_index(&cli::index_dump_options(
+                sub_matches,
+            ))),
+            Some(("load", sub_matches)) => Some(fitarchiver::load_index(&cli::index_load_options(
+                sub_matches,
+            ))),
+            _ => None,
+        },
+        Some(("export", sub_matches)) => match sub_matches.subcommand() {
+            Some(("hrv", sub_matches)) => {
+                Some(fitarchiver::export_hrv(&cli::hrv_options(sub_matches)))
+            }
+            _ => None,
+        },
+        Some(("stats", sub_matches)) => Some(fitarchiver::stats(&cli::stats_options(sub_matches))),
+        Some(("ignore", sub_matches)) => {
+            Some(fitarchiver::ignore(&cli::ignore_options(sub_matches)))
+        }
+        Some(("query", sub_matches)) => Some(fitarchiver::query(&cli::query_options(sub_matches))),
+        Some(("restore", sub_matches)) => {
+            Some(fitarchiver::restore(&cli::restore_options(sub_matches)))
+        }
+        Some(("touch", sub_matches)) => Some(fitarchiver::touch(&cli::touch_options(sub_matches))),
+        Some(("tier", sub_matches)) => Some(fitarchiver::tier(&cli::tier_options(sub_matches))),
+        Some(("gc", sub_matches)) => Some(fitarchiver::gc(&cli::gc_options(sub_matches))),
+        Some(("prune", sub_matches)) => Some(fitarchiver::prune(&cli::prune_options(sub_matches))),
+        Some(("migrate", sub_matches)) => {
+            Some(fitarchiver::migrate(&cli::migrate_options(sub_matches)))
+        }
+        Some(("laps", sub_matches)) => {
+            Some(fitarchiver::export_laps(&cli::laps_options(sub_matches)))
+        }
+        _ => None,
+    };
+    if let Some(result) = result {
+        match result {
+            Ok(val) => println!("{}", val),
+            Err(val) => eprintln!("ERROR: {}", val.details()),
+        };
+        return ExitCode::SUCCESS;
+    }
+
+    let options = cli::archive_options(&matches);
+    let result = if options.watch {
+        fitarchiver::watch(&options)
+    } else {
+        fitarchiver::process_files(&options)
+    };
+    match &result {
+        Ok(val) => {
+            if matches!(
+                options.output,
+                fitarchiver::OutputFormat::Text | fitarchiver::OutputFormat::Grouped
+            ) && !options.print_path
+            {
+                println!("{}", val);
+            } else {
+                eprintln!("{}", val);
+            }
+        }
+        Err(val) if options.output == fitarchiver::OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::json!({"type": "error", "error": val.details()})
+            );
+            eprintln!("ERROR: {}", val.details());
+        }
+        Err(val) if options.verbose => eprintln!("ERROR: {}", val.details()),
         Err(val) => eprintln!("ERROR: {}", val),
     };
 
+    if matches.get_flag("notify") {
+        let notification = match &result {
+            Ok(val) => notify::notify("FIT file archiver", val),
+            Err(val) => notify::notify("FIT file archiver failed", &val.to_string()),
+        };
+        if let Err(err) = notification {
+            eprintln!("ERROR: Unable to show notification: {}", err);
+        }
+    }
+
     ExitCode::SUCCESS
 }
@@ -0,0 +1,150 @@
+//! # Run manifest of the archive
+//!
+//! Tab separated file in the archive directory with one line appended per archived file. It
+//! provides a greppable audit trail of all runs independent of the index.
+
+use crate::fitarchiver::{escape_path, ActivityData, ArchiverError, Result};
+use crate::objects::file_hash;
+use chrono::{DateTime, Utc};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Path of the manifest relative to the archive directory
+pub const MANIFEST_PATH: &str = "MANIFEST.tsv";
+
+/// Header line of the manifest
+const MANIFEST_HEADER: &str = "date\tsport\tsource\tdestination\thash\trun\n";
+
+/// Manifest of an archive opened for appending
+pub(crate) struct Manifest {
+    /// Base directory of the archive
+    directory: PathBuf,
+    /// Manifest file
+    file: File,
+    /// Identifier of the current run
+    run_id: String,
+}
+
+impl Manifest {
+    /// Returns the manifest of an archive opened for appending, it is created if needed
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - Base directory of the archive.
+    /// * `started` - Start time of the current run.
+    pub(crate) fn open(directory: &Path, started: DateTime<Utc>) -> Result<Manifest> {
+        let path = directory.join(MANIFEST_PATH);
+        let msg = format!("Unable to open manifest '{}'", path.display());
+
+        fs::create_dir_all(directory).map_err(|err| ArchiverError::io(&msg, err))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|err| ArchiverError::io(&msg, err))?;
+        let is_empty = file
+            .metadata()
+            .map_err(|err| ArchiverError::io(&msg, err))?
+            .len()
+            == 0;
+        if is_empty {
+            file.write_all(MANIFEST_HEADER.as_bytes())
+                .map_err(|err| ArchiverError::io(&msg, err))?;
+        }
+
+        Ok(Manifest {
+            directory: directory.to_path_buf(),
+            file,
+            run_id: format!(
+                "{}-{}",
+                started.format("%Y%m%dT%H%M%SZ"),
+                std::process::id()
+            ),
+        })
+    }
+
+    /// Append the line of an archived file
+    ///
+    /// # Arguments
+    ///
+    /// * `source_path` - Path of the source file.
+    /// * `archive_path` - Path of the archive file.
+    /// * `activity_data` - Activity data of the archive file.
+    pub(crate) fn append(
+        &mut self,
+        source_path: &Path,
+        archive_path: &Path,
+        activity_data: &ActivityData,
+    ) -> Result<()> {
+        let hash = file_hash(archive_path).map_err(|err| {
+            let msg = format!("Unable to read file '{}'", archive_path.display());
+            ArchiverError::io(&msg, err)
+        })?;
+        let line = format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\n",
+            activity_data.timestamp.to_rfc3339(),
+            activity_data.sport,
+            escape_path(source_path),
+            escape_path(
+                archive_path
+                    .strip_prefix(&self.directory)
+                    .unwrap_or(archive_path)
+            ),
+            hash,
+            self.run_id
+        );
+
+        // a single write keeps the lines of concurrent runs intact
+        self.file.write_all(line.as_bytes()).map_err(|err| {
+            let msg = format!("Unable to add '{}' to manifest", archive_path.display());
+            ArchiverError::io(&msg, err)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test appending archived files to the manifest
+    fn test_manifest_append() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_path = tmpdir.path().join("2023/run\t1.fit");
+        fs::create_dir_all(archive_path.parent().unwrap()).unwrap();
+        fs::write(&archive_path, "abc").unwrap();
+        let started = chrono::Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let activity_data = crate::fitarchiver::ActivityData {
+            timestamp: chrono::Utc.with_ymd_and_hms(2023, 7, 26, 6, 22, 4).unwrap(),
+            sport: String::from("running"),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+
+        for _ in 0..2 {
+            let mut manifest = super::Manifest::open(tmpdir.path(), started).unwrap();
+            manifest
+                .append(
+                    std::path::Path::new("source.fit"),
+                    &archive_path,
+                    &activity_data,
+                )
+                .unwrap();
+        }
+
+        let line = format!(
+            "2023-07-26T06:22:04+00:00\trunning\tsource.fit\t2023/run\\t1.fit\t\
+             ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\t20240102T030405Z-{}\n",
+            std::process::id()
+        );
+        assert_eq!(
+            format!("{}{}{}", super::MANIFEST_HEADER, line, line),
+            fs::read_to_string(tmpdir.path().join(super::MANIFEST_PATH)).unwrap()
+        );
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
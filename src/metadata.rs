@@ -0,0 +1,332 @@
+//! # Activity metadata from companion JSON exports
+//!
+//! Bulk exports of Garmin Connect contain JSON files with the names, types and descriptions of
+//! the activities that are missing in the FIT files. The activities are joined to the FIT files
+//! by the activity ID in the file name or by the start time.
+
+use crate::fitarchiver::{ActivityData, ArchiverError, Result};
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// Maximum difference of start times in seconds for joining an activity without ID
+const START_TOLERANCE: i64 = 60;
+
+/// Metadata of an activity from a JSON export
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct ActivityMetadata {
+    /// Activity ID of Garmin Connect
+    pub(crate) id: Option<u64>,
+    /// Start time of the activity
+    pub(crate) start: Option<DateTime<Utc>>,
+    /// Name of the activity
+    pub(crate) name: Option<String>,
+    /// Type of the activity, i.e. 'trail_running'
+    pub(crate) activity_type: Option<String>,
+    /// Description of the activity
+    pub(crate) description: Option<String>,
+}
+
+/// Returns a string value of a JSON object
+///
+/// # Arguments
+///
+/// * `object` - JSON object.
+/// * `keys` - Keys of the value in order of preference.
+fn string_value(object: &serde_json::Map<String, Value>, keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .filter_map(|key| object.get(*key))
+        .filter_map(|value| match value {
+            Value::String(val) => Some(val.trim().to_string()),
+            // the type is an object in exports of single activities
+            Value::Object(val) => val.get("typeKey").and_then(Value::as_str).map(String::from),
+            _ => None,
+        })
+        .find(|val| !val.is_empty())
+}
+
+/// Returns the start time of an activity in a JSON object
+///
+/// # Arguments
+///
+/// * `object` - JSON object.
+fn start_time(object: &serde_json::Map<String, Value>) -> Option<DateTime<Utc>> {
+    // milliseconds since the epoch
+    for key in ["beginTimestamp", "startTimeGmt"] {
+        if let Some(millis) = object.get(key).and_then(Value::as_f64) {
+            return DateTime::from_timestamp_millis(millis as i64);
+        }
+    }
+    // date and time in UTC
+    let text = string_value(object, &["startTimeGMT", "startTimeGmt"])?;
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(&text, format).ok())
+        .map(|val| val.and_utc())
+}
+
+/// Collect the activities contained in a JSON value
+///
+/// # Arguments
+///
+/// * `value` - JSON value.
+/// * `activities` - Collected activities.
+fn collect_activities(value: &Value, activities: &mut Vec<ActivityMetadata>) {
+    match value {
+        Value::Array(values) => {
+            for value in values {
+                collect_activities(value, activities);
+            }
+        }
+        Value::Object(object) => {
+            let id = object.get("activityId").and_then(Value::as_u64);
+            let start = start_time(object);
+            if id.is_some() || start.is_some() {
+                activities.push(ActivityMetadata {
+                    id,
+                    start,
+                    name: string_value(object, &["activityName", "name"]),
+                    activity_type: string_value(object, &["activityTypeDTO", "activityType"]),
+                    description: string_value(object, &["description"]),
+                });
+            } else {
+                for value in object.values() {
+                    collect_activities(value, activities);
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Returns the metadata of all activities in the JSON files matching a pattern
+///
+/// # Arguments
+///
+/// * `pattern` - Glob pattern of the JSON files, i.e. 'export/DI_CONNECT/*/*.json'.
+pub(crate) fn load_metadata(pattern: &str) -> Result<Vec<ActivityMetadata>> {
+    let paths = match glob::glob(pattern) {
+        Ok(val) => val,
+        Err(err) => {
+            let msg = format!("Invalid metadata pattern '{}': {}", pattern, err);
+            return Err(ArchiverError::new(&msg));
+        }
+    };
+
+    let mut activities = Vec::new();
+    for path in paths.flatten() {
+        let content = match fs::read_to_string(&path) {
+            Ok(val) => val,
+            Err(err) => {
+                let msg = format!("Unable to read metadata '{}'", path.display());
+                return Err(ArchiverError::io(&msg, err));
+            }
+        };
+        match serde_json::from_str::<Value>(&content) {
+            Ok(value) => collect_activities(&value, &mut activities),
+            Err(err) => {
+                let msg = format!("Unable to parse metadata '{}': {}", path.display(), err);
+                return Err(ArchiverError::new(&msg));
+            }
+        }
+    }
+    Ok(activities)
+}
+
+/// Returns the activity ID contained in the name of a FIT file, i.e. '12345678901_ACTIVITY.fit'
+///
+/// # Arguments
+///
+/// * `path` - Path of the FIT file.
+fn file_activity_id(path: &Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_string_lossy();
+    stem.split(|c: char| !c.is_ascii_digit())
+        .filter(|digits| digits.len() >= 6)
+        .max_by_key(|digits| digits.len())
+        .and_then(|digits| digits.parse().ok())
+}
+
+/// Returns the metadata belonging to a FIT file
+///
+/// # Arguments
+///
+/// * `metadata` - Metadata of all activities.
+/// * `path` - Path of the FIT file.
+/// * `activity_data` - Activity data of the FIT file.
+fn find_metadata<'a>(
+    metadata: &'a [ActivityMetadata],
+    path: &Path,
+    activity_data: &ActivityData,
+) -> Option<&'a ActivityMetadata> {
+    if let Some(id) = file_activity_id(path) {
+        if let Some(found) = metadata.iter().find(|entry| entry.id == Some(id)) {
+            return Some(found);
+        }
+    }
+    metadata
+        .iter()
+        .filter_map(|entry| {
+            let difference = (entry.start? - activity_data.timestamp).abs();
+            (difference <= TimeDelta::seconds(START_TOLERANCE)).then_some((difference, entry))
+        })
+        .min_by_key(|(difference, _)| *difference)
+        .map(|(_, entry)| entry)
+}
+
+/// Returns a name normalized like the names extracted from FIT files
+///
+/// # Arguments
+///
+/// * `name` - Name to normalize.
+fn normalize(name: &str) -> String {
+    name.trim().to_lowercase().replace(' ', "_")
+}
+
+/// Complete the activity data of a FIT file with the metadata of its activity
+///
+/// Only values missing in the FIT file are completed. The activity type is used as sport name
+/// (`$n`), the activity name as workout name (`$w`) and the description as notes (`$o`).
+///
+/// # Arguments
+///
+/// * `metadata` - Metadata of all activities.
+/// * `path` - Path of the FIT file.
+/// * `activity_data` - Activity data of the FIT file.
+pub(crate) fn enrich(metadata: &[ActivityMetadata], path: &Path, activity_data: &mut ActivityData) {
+    let Some(found) = find_metadata(metadata, path, activity_data) else {
+        return;
+    };
+    if let (true, Some(activity_type)) =
+        (activity_data.sport_name == "unknown", &found.activity_type)
+    {
+        activity_data.sport_name = normalize(activity_type);
+    }
+    if let (true, Some(name)) = (activity_data.workout_name == "unknown", &found.name) {
+        activity_data.workout_name = normalize(name);
+    }
+    if let (true, Some(description)) = (activity_data.notes.is_empty(), &found.description) {
+        activity_data.notes = description.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use std::fs;
+    use std::path::Path;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test loading activities of bulk and single activity exports
+    fn test_load_metadata() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        fs::write(
+            tmpdir.path().join("user_summarizedActivities.json"),
+            r#"[{"summarizedActivitiesExport": [
+                {"activityId": 11831012345, "name": "Munich Trail Running",
+                 "activityType": "trail_running", "beginTimestamp": 1690352524000.0},
+                {"activityId": 11831012346, "name": "Evening Ride"}
+            ]}]"#,
+        )
+        .unwrap();
+        fs::write(
+            tmpdir.path().join("activity_11831012347.json"),
+            r#"{"activityId": 11831012347, "activityName": "Hill Repeats",
+                "description": "6x 400m", "activityTypeDTO": {"typeKey": "running"},
+                "summaryDTO": {"distance": 5000.0}, "startTimeGMT": "2023-07-27 06:00:00"}"#,
+        )
+        .unwrap();
+        fs::write(tmpdir.path().join("other.json"), r#"{"settings": [1, 2]}"#).unwrap();
+
+        let pattern = tmpdir.path().join("*.json");
+        let metadata = super::load_metadata(&pattern.to_string_lossy()).unwrap();
+        assert_eq!(
+            vec![
+                super::ActivityMetadata {
+                    id: Some(11831012347),
+                    start: Some(chrono::Utc.with_ymd_and_hms(2023, 7, 27, 6, 0, 0).unwrap()),
+                    name: Some(String::from("Hill Repeats")),
+                    activity_type: Some(String::from("running")),
+                    description: Some(String::from("6x 400m")),
+                },
+                super::ActivityMetadata {
+                    id: Some(11831012345),
+                    start: Some(chrono::Utc.with_ymd_and_hms(2023, 7, 26, 6, 22, 4).unwrap()),
+                    name: Some(String::from("Munich Trail Running")),
+                    activity_type: Some(String::from("trail_running")),
+                    description: None,
+                },
+                super::ActivityMetadata {
+                    id: Some(11831012346),
+                    name: Some(String::from("Evening Ride")),
+                    ..Default::default()
+                },
+            ],
+            metadata
+        );
+
+        fs::write(tmpdir.path().join("broken.json"), "{").unwrap();
+        super::load_metadata(&pattern.to_string_lossy()).expect_err("error expected");
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test joining metadata by activity ID and start time
+    fn test_enrich() {
+        let metadata = vec![
+            super::ActivityMetadata {
+                id: Some(11831012345),
+                name: Some(String::from("Munich Trail Running")),
+                description: Some(String::from("Great views")),
+                ..Default::default()
+            },
+            super::ActivityMetadata {
+                start: Some(
+                    chrono::Utc
+                        .with_ymd_and_hms(2023, 7, 26, 6, 22, 34)
+                        .unwrap(),
+                ),
+                activity_type: Some(String::from("trail_running")),
+                ..Default::default()
+            },
+        ];
+        let activity = || crate::fitarchiver::ActivityData {
+            timestamp: chrono::Utc.with_ymd_and_hms(2023, 7, 26, 6, 22, 4).unwrap(),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+
+        let mut activity_data = activity();
+        super::enrich(
+            &metadata,
+            Path::new("export/11831012345_ACTIVITY.fit"),
+            &mut activity_data,
+        );
+        assert_eq!("munich_trail_running", activity_data.workout_name);
+        assert_eq!("Great views", activity_data.notes);
+        assert_eq!("unknown", activity_data.sport_name);
+
+        let mut activity_data = activity();
+        super::enrich(&metadata, Path::new("activity.fit"), &mut activity_data);
+        assert_eq!("trail_running", activity_data.sport_name);
+        assert_eq!("unknown", activity_data.workout_name);
+
+        // values of the FIT file are kept
+        let mut activity_data = crate::fitarchiver::ActivityData {
+            workout_name: String::from("tempo_run"),
+            ..activity()
+        };
+        super::enrich(&metadata, Path::new("11831012345.fit"), &mut activity_data);
+        assert_eq!("tempo_run", activity_data.workout_name);
+
+        // activities too far apart are not joined
+        let mut activity_data = crate::fitarchiver::ActivityData {
+            timestamp: chrono::Utc.with_ymd_and_hms(2023, 7, 26, 8, 0, 0).unwrap(),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+        super::enrich(&metadata, Path::new("activity.fit"), &mut activity_data);
+        assert_eq!("unknown", activity_data.sport_name);
+    }
+}
@@ -0,0 +1,244 @@
+//! # Prometheus metrics of the watch mode
+//!
+//! With `--metrics` the watch mode serves its counters over HTTP in the text format of
+//! Prometheus, i.e. at `http://localhost:9184/metrics`. The timestamps of the last run and of the
+//! last run archiving files allow alerting on a headless server when imports stop.
+
+use crate::fitarchiver::{ArchiverError, Result, Summary};
+use chrono::{DateTime, Utc};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+/// Counters of the runs since the watch mode started
+#[derive(Debug, Clone, Default)]
+struct Counters {
+    /// Runs archiving the files that were ready at one poll
+    runs: usize,
+    /// Archived files
+    archived: usize,
+    /// Errors, a failed run counts as one error
+    errors: usize,
+    /// Damaged FIT files and files that are not FIT files
+    parse_failures: usize,
+    /// Size of the archived files in bytes
+    bytes: u64,
+    /// Time when the last run finished
+    last_run: Option<DateTime<Utc>>,
+    /// Time when the last run archiving files finished
+    last_archive: Option<DateTime<Utc>>,
+}
+
+/// Metrics shared between the watch loop and the HTTP endpoint
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    /// Counters shared with the HTTP endpoint
+    counters: Mutex<Counters>,
+}
+
+impl Metrics {
+    /// Returns the locked counters, a panic of another thread does not leave them inconsistent
+    fn counters(&self) -> MutexGuard<'_, Counters> {
+        self.counters
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Count the result of a run
+    ///
+    /// # Arguments
+    ///
+    /// * `result` - Summary of the run or its error.
+    /// * `now` - Time when the run finished.
+    pub(crate) fn add_result(&self, result: &Result<(String, Summary)>, now: DateTime<Utc>) {
+        let mut counters = self.counters();
+        counters.runs += 1;
+        counters.last_run = Some(now);
+        match result {
+            Ok((_, summary)) => {
+                counters.archived += summary.processed;
+                counters.errors += summary.errors;
+                counters.parse_failures += summary.damaged + summary.invalid;
+                counters.bytes += summary.bytes;
+                if summary.processed > 0 {
+                    counters.last_archive = Some(now);
+                }
+            }
+            Err(_) => counters.errors += 1,
+        }
+    }
+
+    /// Returns the metrics in the text format of Prometheus
+    fn render(&self) -> String {
+        let counters = self.counters().clone();
+        let timestamp = |time: Option<DateTime<Utc>>| time.map_or(0, |time| time.timestamp());
+        [
+            (
+                "fitarchiver_runs_total",
+                "counter",
+                "Runs archiving new files.",
+                counters.runs.to_string(),
+            ),
+            (
+                "fitarchiver_files_archived_total",
+                "counter",
+                "Archived files.",
+                counters.archived.to_string(),
+            ),
+            (
+                "fitarchiver_errors_total",
+                "counter",
+                "Errors while archiving files.",
+                counters.errors.to_string(),
+            ),
+            (
+                "fitarchiver_parse_failures_total",
+                "counter",
+                "Damaged FIT files and files that are not FIT files.",
+                counters.parse_failures.to_string(),
+            ),
+            (
+                "fitarchiver_bytes_copied_total",
+                "counter",
+                "Size of the archived files in bytes.",
+                counters.bytes.to_string(),
+            ),
+            (
+                "fitarchiver_last_run_timestamp_seconds",
+                "gauge",
+                "Time of the last run, 0 before the first run.",
+                timestamp(counters.last_run).to_string(),
+            ),
+            (
+                "fitarchiver_last_archive_timestamp_seconds",
+                "gauge",
+                "Time of the last run archiving files, 0 before the first one.",
+                timestamp(counters.last_archive).to_string(),
+            ),
+        ]
+        .iter()
+        .map(|(name, kind, help, value)| {
+            format!(
+                "# HELP {} {}\n# TYPE {} {}\n{} {}\n",
+                name, help, name, kind, name, value
+            )
+        })
+        .collect()
+    }
+}
+
+/// Answer a single HTTP request, only `GET /metrics` is supported
+///
+/// # Arguments
+///
+/// * `metrics` - Metrics of the watch mode.
+/// * `stream` - Connection of the client.
+fn respond(metrics: &Metrics, stream: TcpStream) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut lines = BufReader::new(stream).lines();
+    let request = lines.next().transpose()?.unwrap_or_default();
+    // the headers are not needed but must be read before answering
+    for line in lines {
+        if line?.is_empty() {
+            break;
+        }
+    }
+
+    let mut words = request.split_whitespace();
+    let (status, body) = match (words.next(), words.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", String::from("Not found\n")),
+    };
+    write!(
+        writer,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// Serve the metrics over HTTP in a background thread
+///
+/// Returns the address the endpoint listens on, i.e. with the port chosen for port 0.
+///
+/// # Arguments
+///
+/// * `metrics` - Metrics of the watch mode.
+/// * `address` - Address and port to listen on, i.e. 'localhost:9184'.
+pub(crate) fn serve(metrics: &Arc<Metrics>, address: &str) -> Result<SocketAddr> {
+    let msg = format!("Unable to serve metrics on '{}'", address);
+    let listener = TcpListener::bind(address).map_err(|err| ArchiverError::io(&msg, err))?;
+    let local_address = listener
+        .local_addr()
+        .map_err(|err| ArchiverError::io(&msg, err))?;
+
+    let metrics = Arc::clone(metrics);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let metrics = Arc::clone(&metrics);
+            // a slow client does not block the scraper
+            thread::spawn(move || {
+                let _ = respond(&metrics, stream);
+            });
+        }
+    });
+    Ok(local_address)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+
+    #[test]
+    /// Test counting runs and serving the metrics
+    fn test_metrics() {
+        let metrics = Arc::new(super::Metrics::default());
+        let address = super::serve(&metrics, "127.0.0.1:0").unwrap();
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        assert!(get("/metrics").contains("\nfitarchiver_last_run_timestamp_seconds 0\n"));
+
+        let summary = crate::fitarchiver::Summary {
+            processed: 2,
+            errors: 1,
+            damaged: 1,
+            bytes: 4096,
+            ..Default::default()
+        };
+        let first = chrono::Utc.with_ymd_and_hms(2026, 10, 2, 8, 0, 0).unwrap();
+        let second = chrono::Utc.with_ymd_and_hms(2026, 10, 2, 9, 0, 0).unwrap();
+        metrics.add_result(&Ok((String::new(), summary)), first);
+        metrics.add_result(
+            &Err(crate::fitarchiver::ArchiverError::new("Unable to archive")),
+            second,
+        );
+
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        for line in [
+            "# TYPE fitarchiver_runs_total counter",
+            "fitarchiver_runs_total 2",
+            "fitarchiver_files_archived_total 2",
+            "fitarchiver_errors_total 2",
+            "fitarchiver_parse_failures_total 1",
+            "fitarchiver_bytes_copied_total 4096",
+            "fitarchiver_last_run_timestamp_seconds 1790931600",
+            "fitarchiver_last_archive_timestamp_seconds 1790928000",
+        ] {
+            assert!(response.lines().any(|val| val == line), "{}", line);
+        }
+
+        assert!(get("/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+        super::serve(&metrics, &address.to_string()).expect_err("error expected");
+    }
+}
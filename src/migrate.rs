@@ -0,0 +1,425 @@
+//! # Migration of an existing archive to another file template
+//!
+//! The archive files are parsed again and moved to the paths derived from the new template, so a
+//! changed naming scheme does not require to import all activities from scratch. The index is
+//! updated to the new location of the files.
+
+use crate::checksum::move_checksum;
+use crate::clock::QUARANTINE_PATH;
+use crate::fitarchiver::{
+    day_sequences, find_fit_files, parse_fit_file, same_file, template_path, ActivityData,
+    ArchiverError, Result, DEFAULT_FILE_TEMPLATE,
+};
+use crate::geocode::{geocode, load_places};
+use crate::index::{Index, INDEX_PATH};
+use crate::kind::{activity_kind, KindRule};
+use crate::location::{start_locations, StartLocation};
+use crate::objects::{store_object, StorageLayout, OBJECTS_PATH};
+use crate::route::{load_routes, match_route, Route};
+use crate::sports::{multisport_name, MultisportNaming, SportDirectory};
+use crate::template::{check_template, check_variables, Variable};
+use crate::tier::move_file;
+use crate::timezone::{TimeMode, Timezone};
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+
+/// Options controlling the migration of an archive
+#[derive(Debug, Clone)]
+pub struct MigrateOptions {
+    /// Base directory of the archive
+    pub directory: PathBuf,
+    /// New format string of the archive files
+    pub file_template: String,
+    /// Naming strategy of the sport of multisport activities
+    pub multisport_naming: MultisportNaming,
+    /// Directories of the sports used with templates without sport tags
+    pub sport_directories: Vec<SportDirectory>,
+    /// Named start locations used by the $r tag
+    pub start_locations: Vec<StartLocation>,
+    /// Radius of the start locations in meters
+    pub start_radius: f64,
+    /// Rules classifying the activities for the $k tag
+    pub kind_rules: Vec<KindRule>,
+    /// Reference routes matched by the $R tag
+    pub routes: Vec<Route>,
+    /// Maximum distance of the track of an activity to a reference route in meters
+    pub route_tolerance: f64,
+    /// Places resolving the start position for the $g and $G tags, the bundled places if unset
+    pub places: Option<PathBuf>,
+    /// Timezone of the '%' tags of the template
+    pub timezone: Timezone,
+    /// Source of the local time of the '%' tags of the template
+    pub time: TimeMode,
+    /// User variables of the template, i.e. 'athlete=jan' for '${var:athlete}'
+    pub variables: Vec<Variable>,
+    /// Do not modify the archive, just show what will happen
+    pub dry_run: bool,
+    /// Do not modify the archive, show the moves as old and new path side by side
+    pub preview: bool,
+}
+
+impl Default for MigrateOptions {
+    fn default() -> Self {
+        MigrateOptions {
+            directory: PathBuf::from("."),
+            file_template: String::from(DEFAULT_FILE_TEMPLATE),
+            multisport_naming: MultisportNaming::default(),
+            sport_directories: Vec::new(),
+            start_locations: Vec::new(),
+            start_radius: 500.0,
+            kind_rules: Vec::new(),
+            routes: Vec::new(),
+            route_tolerance: 50.0,
+            places: None,
+            timezone: Timezone::default(),
+            time: TimeMode::default(),
+            variables: Vec::new(),
+            dry_run: false,
+            preview: false,
+        }
+    }
+}
+
+/// Move an archive file to its new path
+///
+/// Symbolic links to objects are created again, so their relative targets stay valid.
+///
+/// # Arguments
+///
+/// * `directory` - Base directory of the archive.
+/// * `path` - Path of the archive file.
+/// * `new_path` - New path of the archive file.
+fn migrate_file(directory: &Path, path: &Path, new_path: &Path) -> Result<()> {
+    let is_symlink = fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_symlink());
+    if !is_symlink {
+        return move_file(path, new_path);
+    }
+
+    if let Some(parent) = new_path.parent() {
+        if let Err(err) = fs::create_dir_all(parent) {
+            let msg = format!("Unable to create directory '{}'", parent.display());
+            return Err(ArchiverError::io(&msg, err));
+        }
+    }
+    store_object(directory, path, new_path, StorageLayout::Symlink)?;
+    fs::remove_file(path).map_err(|err| {
+        let msg = format!("Unable to remove file '{}'", path.display());
+        ArchiverError::io(&msg, err)
+    })
+}
+
+/// Remove the directories left empty by moving a file, up to the base directory
+///
+/// # Arguments
+///
+/// * `directory` - Base directory of the archive.
+/// * `path` - Previous path of the moved file.
+pub(crate) fn remove_empty_directories(directory: &Path, path: &Path) {
+    for parent in path.ancestors().skip(1) {
+        // removing fails for directories that are not empty
+        if parent == directory || !parent.starts_with(directory) || fs::remove_dir(parent).is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// Returns the lines of a preview showing the old and the new path of each moved file
+///
+/// The paths are relative to the archive directory and aligned in two columns. The old paths
+/// are red and the new paths green if colors are enabled.
+///
+/// # Arguments
+///
+/// * `directory` - Base directory of the archive.
+/// * `moves` - Old and new paths of the moved files.
+/// * `unchanged` - Number of files already matching the template.
+/// * `color` - Highlight the paths with ANSI colors.
+fn preview_lines(
+    directory: &Path,
+    moves: &[(PathBuf, PathBuf)],
+    unchanged: usize,
+    color: bool,
+) -> Vec<String> {
+    let relative = |path: &Path| {
+        path.strip_prefix(directory)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
+    let moves: Vec<(String, String)> = moves
+        .iter()
+        .map(|(path, new_path)| (relative(path), relative(new_path)))
+        .collect();
+    let width = moves
+        .iter()
+        .map(|(path, _)| path.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let (old, new, reset) = match color {
+        true => ("\x1b[31m", "\x1b[32m", "\x1b[0m"),
+        false => ("", "", ""),
+    };
+    let mut lines: Vec<String> = moves
+        .iter()
+        .map(|(path, new_path)| {
+            let padding = " ".repeat(width - path.chars().count());
+            format!(
+                "{}{}{}{}  \u{2192}  {}{}{}",
+                old, path, reset, padding, new, new_path, reset
+            )
+        })
+        .collect();
+    lines.push(format!(
+        "{} files to move, {} files unchanged",
+        moves.len(),
+        unchanged
+    ));
+    lines
+}
+
+/// Move the files of an existing archive to the paths derived from another file template
+///
+/// All archive files are parsed again, files in the quarantine and object directories are kept.
+///
+/// # Arguments
+///
+/// `options` - Migrate options.
+pub fn migrate(options: &MigrateOptions) -> Result<String> {
+    check_template(&options.file_template)?;
+    check_variables(&options.file_template, &options.variables)?;
+    let dry_run = options.dry_run || options.preview;
+
+    // an archive without index does not get one
+    let index = if !dry_run && options.directory.join(INDEX_PATH).exists() {
+        Index::open(&options.directory, true)?
+    } else {
+        None
+    };
+
+    let files: Vec<PathBuf> = find_fit_files(&options.directory)?
+        .into_iter()
+        .filter(|path| {
+            !path.starts_with(options.directory.join(QUARANTINE_PATH))
+                && !path.starts_with(options.directory.join(OBJECTS_PATH))
+        })
+        .collect();
+    let routes = load_routes(&options.routes)?;
+    let places = load_places(options.places.as_deref())?;
+    let mut parsed: Vec<Result<ActivityData>> = files
+        .iter()
+        .map(|path| {
+            parse_fit_file(path).map(|mut activity_data| {
+                let track = std::mem::take(&mut activity_data.track);
+                activity_data.route = match_route(&track, &routes, options.route_tolerance);
+                (activity_data.city, activity_data.country) =
+                    geocode(&places, activity_data.start_position);
+                activity_data.utc_offset = options.time.offset(
+                    &options.timezone,
+                    activity_data.timestamp,
+                    activity_data.device_offset,
+                );
+                activity_data.variables = options.variables.clone();
+                activity_data
+            })
+        })
+        .collect();
+
+    // the tags depending on other activities are derived from the whole archive
+    for activity_data in parsed.iter_mut().flatten() {
+        if activity_data.legs.len() > 1 {
+            if let Some(sport) = multisport_name(&activity_data.legs, options.multisport_naming) {
+                activity_data.sport = sport;
+            }
+        }
+    }
+    let activities: Vec<Option<&ActivityData>> =
+        parsed.iter().map(|val| val.as_ref().ok()).collect();
+    let sequences = day_sequences(
+        &activities,
+        &vec![false; activities.len()],
+        &[],
+        &options.timezone,
+    );
+    let locations = start_locations(
+        &activities,
+        &[],
+        &options.start_locations,
+        options.start_radius,
+    );
+    for ((parsed, day_sequence), start_location) in parsed.iter_mut().zip(sequences).zip(locations)
+    {
+        if let Ok(activity_data) = parsed {
+            activity_data.day_sequence = day_sequence;
+            activity_data.start_location = start_location;
+            activity_data.kind = activity_kind(activity_data, &options.kind_rules);
+        }
+    }
+
+    let mut move_counter: usize = 0;
+    let mut error_counter: usize = 0;
+    let mut moves: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for (path, parsed) in files.iter().zip(parsed) {
+        let result = parsed.and_then(|activity_data| {
+            let new_path = template_path(
+                &options.directory,
+                &options.file_template,
+                &options.sport_directories,
+                &activity_data,
+            );
+            if new_path == *path {
+                return Ok(None);
+            }
+            if new_path.exists() && !same_file(path, &new_path) {
+                let msg = format!(
+                    "Unable to move '{}', '{}' already exists",
+                    path.display(),
+                    new_path.display()
+                );
+                return Err(ArchiverError::new(&msg));
+            }
+            if dry_run {
+                return Ok(Some((new_path, "dry run")));
+            }
+
+            migrate_file(&options.directory, path, &new_path)?;
+            move_checksum(path, &new_path)?;
+            remove_empty_directories(&options.directory, path);
+            if let Some(index) = &index {
+                index.relocate(path, &new_path)?;
+            }
+            Ok(Some((new_path, "moved")))
+        });
+
+        match result {
+            Ok(Some((new_path, _))) if options.preview => {
+                moves.push((path.clone(), new_path));
+                move_counter += 1;
+            }
+            Ok(Some((new_path, status))) => {
+                println!(
+                    "'{}' -> '{}' ... {}",
+                    path.display(),
+                    new_path.display(),
+                    status
+                );
+                move_counter += 1;
+            }
+            Ok(None) => (),
+            Err(err) => {
+                eprintln!("{}", err);
+                error_counter += 1;
+            }
+        }
+    }
+
+    if options.preview {
+        let color = io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none();
+        let unchanged = files.len() - move_counter - error_counter;
+        for line in preview_lines(&options.directory, &moves, unchanged, color) {
+            println!("{}", line);
+        }
+    }
+
+    let msg = format!("Moved {} of {} files", move_counter, files.len());
+    let err = if error_counter == 0 {
+        String::new()
+    } else {
+        format!("with {} errors.", error_counter)
+    };
+
+    Ok([msg, err].join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test moving the files of an archive to the paths of another template
+    fn test_migrate() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let directory = tmpdir.path().to_path_buf();
+        let archive_options = crate::fitarchiver::Options {
+            directory: directory.clone(),
+            files: vec![PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("test")
+                .join("test_data_01.fit")],
+            index: true,
+            ..Default::default()
+        };
+        crate::fitarchiver::process_files(&archive_options).expect("unable to archive test file");
+        let file = crate::fitarchiver::find_fit_files(&directory).unwrap()[0].clone();
+
+        let mut options = super::MigrateOptions {
+            directory: directory.clone(),
+            file_template: String::from("$s/%Y/%Y-%m-%d-$q"),
+            dry_run: true,
+            ..Default::default()
+        };
+        let new_file = directory.join("running/2023/2023-07-26-1.fit");
+        assert_eq!("Moved 1 of 1 files ", super::migrate(&options).unwrap());
+        assert!(file.exists());
+        assert!(!new_file.exists());
+
+        // the preview lists the moves side by side
+        options.dry_run = false;
+        options.preview = true;
+        assert_eq!("Moved 1 of 1 files ", super::migrate(&options).unwrap());
+        assert!(file.exists());
+        let moves = vec![
+            (directory.join("a/run.fit"), directory.join("running/1.fit")),
+            (
+                directory.join("a/b/ride.fit"),
+                directory.join("cycling/1.fit"),
+            ),
+        ];
+        assert_eq!(
+            vec![
+                "a/run.fit     \u{2192}  running/1.fit",
+                "a/b/ride.fit  \u{2192}  cycling/1.fit",
+                "2 files to move, 3 files unchanged",
+            ],
+            super::preview_lines(&directory, &moves, 3, false)
+        );
+        assert_eq!(
+            "\x1b[31ma/run.fit\x1b[0m     \u{2192}  \x1b[32mrunning/1.fit\x1b[0m",
+            super::preview_lines(&directory, &moves, 3, true)[0]
+        );
+        options.preview = false;
+
+        options.dry_run = false;
+        assert_eq!("Moved 1 of 1 files ", super::migrate(&options).unwrap());
+        assert!(!file.exists());
+        assert!(new_file.exists());
+        // the directories of the previous template are removed
+        assert!(!file.parent().unwrap().exists());
+
+        // the index points to the new location
+        let connection = rusqlite::Connection::open(directory.join(super::INDEX_PATH)).unwrap();
+        let path: String = connection
+            .query_row("SELECT path FROM activities", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!("running/2023/2023-07-26-1.fit", path);
+
+        // files already matching the template are kept
+        assert_eq!("Moved 0 of 1 files ", super::migrate(&options).unwrap());
+
+        // existing paths are not overwritten
+        options.file_template = String::from("$s/%Y/%Y-%m-%d-$q-copy");
+        fs::create_dir(directory.join("running/2023/2023-07-26-1-copy.fit")).unwrap();
+        assert_eq!(
+            "Moved 0 of 1 files with 1 errors.",
+            super::migrate(&options).unwrap()
+        );
+        assert!(new_file.exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
@@ -0,0 +1,168 @@
+//! # Renaming of single files in place
+
+use crate::fitarchiver::{
+    archive_path, parse_fit_file, same_file, ActivityData, ArchiverError, Result,
+    DEFAULT_FILE_TEMPLATE,
+};
+use crate::geocode::{geocode, load_places};
+use crate::template::{check_template, check_variables, Variable};
+use crate::timezone::{TimeMode, Timezone};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Options controlling the renaming of a single file
+#[derive(Debug, Clone)]
+pub struct NameOptions {
+    /// FIT file to rename
+    pub file: PathBuf,
+    /// Format string, only the last path component is used for the file name
+    pub file_template: String,
+    /// Timezone of the '%' tags of the template
+    pub timezone: Timezone,
+    /// Source of the local time of the '%' tags of the template
+    pub time: TimeMode,
+    /// User variables of the template, i.e. 'athlete=jan' for '${var:athlete}'
+    pub variables: Vec<Variable>,
+    /// Places resolving the start position for the $g and $G tags, the bundled places if unset
+    pub places: Option<PathBuf>,
+    /// Rename the file instead of just printing the new name
+    pub apply: bool,
+}
+
+impl Default for NameOptions {
+    fn default() -> Self {
+        NameOptions {
+            file: PathBuf::new(),
+            file_template: String::from(DEFAULT_FILE_TEMPLATE),
+            timezone: Timezone::default(),
+            time: TimeMode::default(),
+            variables: Vec::new(),
+            places: None,
+            apply: false,
+        }
+    }
+}
+
+/// Returns the path of a file renamed in place according to the template
+///
+/// # Arguments
+///
+/// * `path` - Path of the FIT file.
+/// * `formatstring` - A format string containing '%' and '$' tags.
+/// * `activity_data` - Data that will be used for expansion of the tags.
+fn renamed_path(path: &Path, formatstring: &str, activity_data: &ActivityData) -> Result<PathBuf> {
+    match archive_path(Path::new(""), formatstring, activity_data).file_name() {
+        Some(name) => Ok(path.with_file_name(name)),
+        None => {
+            let msg = format!("Template '{}' does not produce a file name", formatstring);
+            Err(ArchiverError::new(&msg))
+        }
+    }
+}
+
+/// Print or apply the template-derived name of a single file
+///
+/// # Arguments
+///
+/// `options` - Name options.
+pub fn name_file(options: &NameOptions) -> Result<String> {
+    check_template(&options.file_template)?;
+    check_variables(&options.file_template, &options.variables)?;
+    let places = load_places(options.places.as_deref())?;
+    let mut activity_data = parse_fit_file(&options.file)?;
+    (activity_data.city, activity_data.country) = geocode(&places, activity_data.start_position);
+    activity_data.utc_offset = options.time.offset(
+        &options.timezone,
+        activity_data.timestamp,
+        activity_data.device_offset,
+    );
+    activity_data.variables = options.variables.clone();
+    let new_path = renamed_path(&options.file, &options.file_template, &activity_data)?;
+
+    if !options.apply {
+        return Ok(new_path.display().to_string());
+    }
+
+    if new_path.exists() {
+        if same_file(&options.file, &new_path) {
+            return Ok(format!("'{}' ... already named", options.file.display()));
+        }
+        let msg = format!(
+            "Unable to rename '{}', '{}' already exists",
+            options.file.display(),
+            new_path.display()
+        );
+        return Err(ArchiverError::new(&msg));
+    }
+
+    match fs::rename(&options.file, &new_path) {
+        Ok(_) => Ok(format!(
+            "'{}' -> '{}' ... renamed",
+            options.file.display(),
+            new_path.display()
+        )),
+        Err(err) => {
+            let msg = format!("Unable to rename '{}'", options.file.display());
+            Err(ArchiverError::io(&msg, err))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test printing and applying the name of a single file
+    fn test_name_file() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let file = tmpdir.path().join("mail attachment.fit");
+        fs::copy(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("test")
+                .join("test_data_01.fit"),
+            &file,
+        )
+        .expect("unable to create test file");
+        let new_path = tmpdir.path().join("2023-07-26-062204-running.fit");
+
+        let mut options = super::NameOptions {
+            file: file.clone(),
+            ..Default::default()
+        };
+        assert_eq!(
+            new_path.display().to_string(),
+            super::name_file(&options).unwrap()
+        );
+        assert!(file.exists());
+
+        options.apply = true;
+        super::name_file(&options).unwrap();
+        assert!(!file.exists());
+        assert!(new_path.exists());
+
+        // applying the name again does not change anything
+        options.file = new_path.clone();
+        assert!(super::name_file(&options)
+            .unwrap()
+            .ends_with("already named"));
+
+        // user variables are inserted into the name
+        options.file_template = String::from("%Y-${var:athlete}");
+        super::name_file(&options).expect_err("error expected");
+        options.variables = vec!["athlete=jan".parse().unwrap()];
+        options.apply = false;
+        assert!(super::name_file(&options)
+            .unwrap()
+            .ends_with("2023-jan.fit"));
+
+        // the file name must not be empty
+        options.file_template = String::new();
+        super::name_file(&options).expect_err("error expected");
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
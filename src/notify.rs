@@ -0,0 +1,100 @@
+//! # Desktop notifications
+//!
+//! Notifications are shown with the tools of the desktop environment, `notify-send` on Linux and
+//! BSD and the notification center via `osascript` on macOS.
+
+use std::io;
+use std::process::{Command, Stdio};
+
+/// Returns a string quoted for AppleScript
+///
+/// # Arguments
+///
+/// * `text` - Text to quote.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Returns the command showing a notification
+///
+/// # Arguments
+///
+/// * `summary` - Title of the notification.
+/// * `body` - Text of the notification.
+#[cfg(target_os = "macos")]
+fn notification_command(summary: &str, body: &str) -> Option<Command> {
+    let mut command = Command::new("osascript");
+    command.arg("-e").arg(format!(
+        "display notification {} with title {}",
+        applescript_string(body),
+        applescript_string(summary)
+    ));
+    Some(command)
+}
+
+/// Returns the command showing a notification
+///
+/// # Arguments
+///
+/// * `summary` - Title of the notification.
+/// * `body` - Text of the notification.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn notification_command(summary: &str, body: &str) -> Option<Command> {
+    let mut command = Command::new("notify-send");
+    command.arg("--app-name=fitarchiver").arg(summary).arg(body);
+    Some(command)
+}
+
+/// Returns the command showing a notification
+///
+/// Notifications are not supported on this platform.
+///
+/// # Arguments
+///
+/// * `summary` - Title of the notification.
+/// * `body` - Text of the notification.
+#[cfg(not(unix))]
+fn notification_command(_summary: &str, _body: &str) -> Option<Command> {
+    None
+}
+
+/// Show a desktop notification
+///
+/// # Arguments
+///
+/// * `summary` - Title of the notification.
+/// * `body` - Text of the notification.
+pub fn notify(summary: &str, body: &str) -> io::Result<()> {
+    let Some(mut command) = notification_command(summary, body) else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "notifications are not supported on this platform",
+        ));
+    };
+    let status = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "notification command failed with {}",
+            status
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    /// Test quoting text for AppleScript
+    fn test_applescript_string() {
+        assert_eq!(
+            r#""12 files \"archived\" in C:\\fit""#,
+            super::applescript_string(r#"12 files "archived" in C:\fit"#)
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! # Content-addressed storage of archive files
+//!
+//! The content of each archive file is stored once in the objects directory of the archive under
+//! its hash. The template-derived archive paths are links to these objects. Importing the same
+//! file again does not need additional space and alternative views of the archive are cheap.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Directory of the objects relative to the archive directory
+pub const OBJECTS_PATH: &str = "objects";
+
+/// Counter making the names of temporary objects unique within the process
+static TEMPORARY_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Layout of the archive files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageLayout {
+    /// Archive files are stored at the template-derived paths
+    #[default]
+    Plain,
+    /// Archive files are hard links to the objects
+    Hardlink,
+    /// Archive files are relative symbolic links to the objects
+    Symlink,
+}
+
+impl FromStr for StorageLayout {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "plain" => Ok(StorageLayout::Plain),
+            "hardlink" => Ok(StorageLayout::Hardlink),
+            "symlink" => Ok(StorageLayout::Symlink),
+            _ => {
+                let msg = format!(
+                    "Invalid storage layout '{}', expected 'plain', 'hardlink' or 'symlink'",
+                    s
+                );
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+/// Returns the SHA-256 hash of the content of a file as hex string
+///
+/// # Arguments
+///
+/// * `path` - Path to the file.
+pub(crate) fn file_hash(path: &Path) -> io::Result<String> {
+    content_hash(File::open(path)?)
+}
+
+/// Returns the SHA-256 hash of the content read from a reader as hex string
+///
+/// # Arguments
+///
+/// * `reader` - Reader of the content, i.e. a file.
+pub(crate) fn content_hash(mut reader: impl Read) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 8192];
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Returns the path of a link target relative to the directory of the link
+///
+/// # Arguments
+///
+/// * `directory` - Base directory of the archive.
+/// * `archive_path` - Path of the link in the archive directory.
+/// * `object_path` - Path of the object in the archive directory.
+fn relative_target(directory: &Path, archive_path: &Path, object_path: &Path) -> PathBuf {
+    let depth = archive_path
+        .parent()
+        .and_then(|parent| parent.strip_prefix(directory).ok())
+        .map(|parent| {
+            parent
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .count()
+        })
+        .unwrap_or(0);
+
+    let mut target = PathBuf::new();
+    for _ in 0..depth {
+        target.push("..");
+    }
+    target.push(object_path.strip_prefix(directory).unwrap_or(object_path));
+    target
+}
+
+/// Create a symbolic link
+///
+/// # Arguments
+///
+/// * `target` - Target of the link.
+/// * `link_path` - Path of the link.
+#[cfg(unix)]
+fn symlink(target: &Path, link_path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link_path)
+}
+
+/// Create a symbolic link
+///
+/// # Arguments
+///
+/// * `target` - Target of the link.
+/// * `link_path` - Path of the link.
+#[cfg(windows)]
+fn symlink(target: &Path, link_path: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link_path)
+}
+
+/// Store the content of a file as object and link it to the archive path
+///
+/// An existing archive file is replaced by the link.
+///
+/// # Arguments
+///
+/// * `directory` - Base directory of the archive.
+/// * `source_path` - Path to the source file.
+/// * `archive_path` - Path to the archive file.
+/// * `layout` - Kind of link to create.
+pub(crate) fn store_object(
+    directory: &Path,
+    source_path: &Path,
+    archive_path: &Path,
+    layout: StorageLayout,
+) -> Result<()> {
+    let hash = match file_hash(source_path) {
+        Ok(val) => val,
+        Err(err) => {
+            let msg = format!("Unable to read file '{}'", source_path.display());
+            return Err(ArchiverError::io(&msg, err));
+        }
+    };
+    let object_path = directory.join(OBJECTS_PATH).join(hash);
+
+    // the content is stored only once
+    if !object_path.exists() {
+        let msg = format!("Unable to create object '{}'", object_path.display());
+        if let Some(parent) = object_path.parent() {
+            fs::create_dir_all(parent).map_err(|err| ArchiverError::io(&msg, err))?;
+        }
+        // copied under a temporary name, so files with the same content archived in parallel
+        // never see a partial object
+        let mut temporary_path = object_path.as_os_str().to_owned();
+        temporary_path.push(format!(
+            ".fitarchiver-{}-{}",
+            std::process::id(),
+            TEMPORARY_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temporary_path = PathBuf::from(temporary_path);
+        let result = fs::copy(source_path, &temporary_path)
+            .and_then(|_| fs::rename(&temporary_path, &object_path));
+        if let Err(err) = result {
+            let _ = fs::remove_file(&temporary_path);
+            return Err(ArchiverError::io(&msg, err));
+        }
+    }
+
+    // create the link next to the archive file and rename it to replace the archive file
+    let mut link_path = archive_path.as_os_str().to_owned();
+    link_path.push(".fitarchiver-link");
+    let link_path = PathBuf::from(link_path);
+    let _ = fs::remove_file(&link_path);
+
+    let result = match layout {
+        StorageLayout::Plain | StorageLayout::Hardlink => fs::hard_link(&object_path, &link_path),
+        StorageLayout::Symlink => symlink(
+            &relative_target(directory, archive_path, &object_path),
+            &link_path,
+        ),
+    };
+    if let Err(err) = result {
+        let msg = format!("Unable to create link '{}'", archive_path.display());
+        return Err(ArchiverError::io(&msg, err));
+    }
+    if let Err(err) = fs::rename(&link_path, archive_path) {
+        let _ = fs::remove_file(&link_path);
+        let msg = format!("Unable to create file '{}'", archive_path.display());
+        return Err(ArchiverError::io(&msg, err));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test hashing the content of a file
+    fn test_file_hash() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let file = tmpdir.path().join("content");
+        fs::write(&file, "abc").expect("unable to create test file");
+
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            super::file_hash(&file).unwrap()
+        );
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test link targets relative to the archive directory
+    fn test_relative_target() {
+        let directory = Path::new("archive");
+        let object_path = directory.join("objects/abc");
+
+        assert_eq!(
+            PathBuf::from("../../objects/abc"),
+            super::relative_target(directory, &directory.join("2023/07/run.fit"), &object_path)
+        );
+        assert_eq!(
+            PathBuf::from("objects/abc"),
+            super::relative_target(directory, &directory.join("run.fit"), &object_path)
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    /// Test storing the same content several times
+    fn test_store_object() {
+        use std::os::unix::fs::MetadataExt;
+
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let source = tmpdir.path().join("source.fit");
+        fs::write(&source, "content").expect("unable to create test file");
+        let directory = tmpdir.path().join("archive");
+        let first = directory.join("2023/first.fit");
+        let second = directory.join("2023/second.fit");
+        fs::create_dir_all(first.parent().unwrap())
+            .expect("error during creating temporary directory");
+
+        super::store_object(&directory, &source, &first, super::StorageLayout::Hardlink).unwrap();
+        super::store_object(&directory, &source, &second, super::StorageLayout::Symlink).unwrap();
+
+        let objects: Vec<_> = fs::read_dir(directory.join(super::OBJECTS_PATH))
+            .unwrap()
+            .collect();
+        assert_eq!(1, objects.len());
+        assert_eq!(2, fs::metadata(&first).unwrap().nlink());
+        assert!(fs::symlink_metadata(&second).unwrap().is_symlink());
+        assert_eq!("content", fs::read_to_string(&second).unwrap());
+
+        // an existing archive file is replaced
+        super::store_object(&directory, &source, &second, super::StorageLayout::Hardlink).unwrap();
+        assert!(!fs::symlink_metadata(&second).unwrap().is_symlink());
+        assert_eq!(3, fs::metadata(&first).unwrap().nlink());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
@@ -0,0 +1,104 @@
+//! # In-memory overlay of the archive for dry runs
+//!
+//! A dry run does not touch the file system. The directories and files a real run would create
+//! are recorded in an overlay instead, so that conflicts between the files of the same run are
+//! reported exactly like in a real run.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Directories and files planned by a dry run
+#[derive(Debug, Default)]
+pub(crate) struct Overlay {
+    /// Planned archive files and their source files
+    files: HashMap<PathBuf, PathBuf>,
+    /// Planned directories
+    directories: HashSet<PathBuf>,
+}
+
+impl Overlay {
+    /// Returns the source file of a planned archive file
+    ///
+    /// # Arguments
+    ///
+    /// * `archive_path` - Path to the archive file.
+    pub(crate) fn planned_source(&self, archive_path: &Path) -> Option<&Path> {
+        self.files.get(archive_path).map(PathBuf::as_path)
+    }
+
+    /// Plan the directory of an archive file including all its parents
+    ///
+    /// # Arguments
+    ///
+    /// * `archive_path` - Path to the archive file.
+    pub(crate) fn create_archive_directory(&mut self, archive_path: &Path) -> Result<()> {
+        let Some(parent) = archive_path.parent() else {
+            return Ok(());
+        };
+        if let Some(file) = parent
+            .ancestors()
+            .find(|directory| self.files.contains_key(*directory))
+        {
+            let msg = format!("'{}' exists but is not a directory", file.display());
+            return Err(ArchiverError::new(&msg));
+        }
+        self.directories.extend(
+            parent
+                .ancestors()
+                .filter(|directory| !directory.as_os_str().is_empty())
+                .map(Path::to_path_buf),
+        );
+        Ok(())
+    }
+
+    /// Plan an archive file
+    ///
+    /// # Arguments
+    ///
+    /// * `source_path` - Path to the source file.
+    /// * `archive_path` - Path to the archive file.
+    pub(crate) fn add_file(&mut self, source_path: &Path, archive_path: &Path) -> Result<()> {
+        if self.directories.contains(archive_path) {
+            let msg = format!("'{}' exists but is a directory", archive_path.display());
+            return Err(ArchiverError::new(&msg));
+        }
+        self.files
+            .insert(archive_path.to_path_buf(), source_path.to_path_buf());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    #[test]
+    /// Test planning directories and files
+    fn test_overlay() {
+        let mut overlay = super::Overlay::default();
+
+        overlay
+            .create_archive_directory(Path::new("archive/2023/07/run.fit"))
+            .unwrap();
+        overlay
+            .add_file(
+                Path::new("source/run.fit"),
+                Path::new("archive/2023/07/run.fit"),
+            )
+            .unwrap();
+        assert_eq!(
+            Some(Path::new("source/run.fit")),
+            overlay.planned_source(Path::new("archive/2023/07/run.fit"))
+        );
+        assert_eq!(None, overlay.planned_source(Path::new("archive/2023/07")));
+
+        // a planned file cannot be a directory and vice versa
+        overlay
+            .create_archive_directory(Path::new("archive/2023/07/run.fit/other.fit"))
+            .expect_err("error expected");
+        overlay
+            .add_file(Path::new("source/other.fit"), Path::new("archive/2023"))
+            .expect_err("error expected");
+    }
+}
@@ -0,0 +1,288 @@
+//! # Portable copy of the index
+//!
+//! The entries of the index are dumped to a JSON document, so the metadata of an archive moves
+//! with its files to another machine or is merged into the index of another archive. The paths
+//! are relative to the archive directory. Loading an entry whose path or content is already
+//! indexed with different data is reported as conflict and the entry is not loaded.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use crate::index::{Index, INDEX_PATH};
+use std::fs;
+use std::path::PathBuf;
+
+/// Member of the dump containing the version of the index schema
+const VERSION_MEMBER: &str = "fitarchiver_index";
+
+/// Options controlling the dump of the index
+#[derive(Debug, Clone)]
+pub struct IndexDumpOptions {
+    /// Base directory of the archive
+    pub directory: PathBuf,
+    /// File the dump is written to, `None` to return it
+    pub output: Option<PathBuf>,
+}
+
+impl Default for IndexDumpOptions {
+    fn default() -> Self {
+        IndexDumpOptions {
+            directory: PathBuf::from("."),
+            output: None,
+        }
+    }
+}
+
+/// Options controlling the loading of a dump into the index
+#[derive(Debug, Clone)]
+pub struct IndexLoadOptions {
+    /// Base directory of the archive
+    pub directory: PathBuf,
+    /// Do not modify the index, just report what would be loaded
+    pub dry_run: bool,
+    /// File containing the dump
+    pub input: PathBuf,
+}
+
+impl Default for IndexLoadOptions {
+    fn default() -> Self {
+        IndexLoadOptions {
+            directory: PathBuf::from("."),
+            dry_run: false,
+            input: PathBuf::new(),
+        }
+    }
+}
+
+/// Dump all entries of the index as JSON document
+///
+/// The document is returned if no output file is given.
+///
+/// # Arguments
+///
+/// `options` - Dump options.
+pub fn dump_index(options: &IndexDumpOptions) -> Result<String> {
+    let Some(index) = Index::open(&options.directory, false)? else {
+        let msg = format!(
+            "No index of the current version found in archive '{}', use 'index upgrade'",
+            options.directory.display()
+        );
+        return Err(ArchiverError::new(&msg));
+    };
+    let entries = index.entries()?;
+    let count = entries.len();
+    let dump = serde_json::json!({
+        VERSION_MEMBER: index.version()?,
+        "entries": entries,
+    });
+    let dump = serde_json::to_string_pretty(&dump).unwrap_or_default();
+
+    match &options.output {
+        Some(path) => {
+            fs::write(path, dump + "\n").map_err(|err| {
+                let msg = format!("Unable to write file '{}'", path.display());
+                ArchiverError::io(&msg, err)
+            })?;
+            Ok(format!("Dumped {} entries to '{}'", count, path.display()))
+        }
+        None => Ok(dump),
+    }
+}
+
+/// Load the entries of a dump into the index of an archive
+///
+/// Entries that are already indexed with the same content are left alone. An entry conflicts if
+/// its path is indexed with another content or its content is indexed under another path.
+///
+/// # Arguments
+///
+/// `options` - Load options.
+pub fn load_index(options: &IndexLoadOptions) -> Result<String> {
+    let content = fs::read_to_string(&options.input).map_err(|err| {
+        let msg = format!("Unable to read file '{}'", options.input.display());
+        ArchiverError::io(&msg, err)
+    })?;
+    let invalid = || {
+        let msg = format!(
+            "File '{}' is no dump of a fitarchiver index",
+            options.input.display()
+        );
+        ArchiverError::new(&msg)
+    };
+    let dump: serde_json::Value = serde_json::from_str(&content).map_err(|_| invalid())?;
+    let (Some(version), Some(entries)) = (
+        dump.get(VERSION_MEMBER).and_then(serde_json::Value::as_u64),
+        dump.get("entries").and_then(serde_json::Value::as_array),
+    ) else {
+        return Err(invalid());
+    };
+
+    // a dry run only reads an existing index
+    let index = match options.dry_run && !options.directory.join(INDEX_PATH).exists() {
+        true => None,
+        false => Index::open(&options.directory, !options.dry_run)?,
+    };
+    if let Some(index) = &index {
+        if version > index.version()? as u64 {
+            let msg = format!(
+                "Dump '{}' was created by a newer version of fitarchiver",
+                options.input.display()
+            );
+            return Err(ArchiverError::new(&msg));
+        }
+    }
+
+    let mut load_counter: usize = 0;
+    let mut unchanged_counter: usize = 0;
+    let mut conflict_counter: usize = 0;
+    let mut load = |index: Option<&Index>| -> Result<()> {
+        for entry in entries {
+            let Some(entry) = entry.as_object() else {
+                return Err(invalid());
+            };
+            let Some(path) = entry.get("path").and_then(serde_json::Value::as_str) else {
+                return Err(invalid());
+            };
+            let hash = entry.get("hash").and_then(serde_json::Value::as_str);
+
+            let conflict = match index {
+                Some(index) => match index.entry_hash(path)? {
+                    Some(indexed) if indexed.as_deref() == hash => {
+                        unchanged_counter += 1;
+                        continue;
+                    }
+                    Some(_) => Some(String::from("another content is indexed at this path")),
+                    None => match hash {
+                        Some(hash) => index.hash_paths(hash)?.first().map(|existing| {
+                            format!("the content is indexed as '{}'", existing.display())
+                        }),
+                        None => None,
+                    },
+                },
+                None => None,
+            };
+            if let Some(conflict) = conflict {
+                eprintln!("'{}' ... conflict, {}", path, conflict);
+                conflict_counter += 1;
+                continue;
+            }
+
+            if let (Some(index), false) = (index, options.dry_run) {
+                index.insert_entry(entry)?;
+            }
+            println!("'{}' ... loaded", path);
+            load_counter += 1;
+        }
+        Ok(())
+    };
+    // the index is only changed if all entries are valid
+    match &index {
+        Some(index) if !options.dry_run => index.transaction(|index| load(Some(index)))?,
+        _ => load(index.as_ref())?,
+    }
+
+    let verb = if options.dry_run {
+        "Would load"
+    } else {
+        "Loaded"
+    };
+    let mut msg = format!("{} {} entries", verb, load_counter);
+    if unchanged_counter > 0 {
+        msg.push_str(&format!(", {} unchanged", unchanged_counter));
+    }
+    let err = if conflict_counter == 0 {
+        String::new()
+    } else {
+        format!("with {} conflicts.", conflict_counter)
+    };
+
+    Ok([msg, err].join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test moving the entries of an index to the index of another archive
+    fn test_dump_load_index() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let source = tmpdir.path().join("source");
+        let target = tmpdir.path().join("target");
+        let file = source.join("2023/07/run.fit");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::copy(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("test")
+                .join("test_data_01.fit"),
+            &file,
+        )
+        .unwrap();
+        let index = crate::index::Index::open(&source, true).unwrap().unwrap();
+        index
+            .add(&file, &crate::fitarchiver::parse_fit_file(&file).unwrap())
+            .unwrap();
+        let dump_path = tmpdir.path().join("index.json");
+        let options = super::IndexDumpOptions {
+            directory: source.clone(),
+            output: Some(dump_path.clone()),
+        };
+        assert_eq!(
+            format!("Dumped 1 entries to '{}'", dump_path.display()),
+            super::dump_index(&options).unwrap()
+        );
+        let dump: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&dump_path).unwrap()).unwrap();
+        assert_eq!("2023/07/run.fit", dump["entries"][0]["path"]);
+        assert_eq!("running", dump["entries"][0]["sport"]);
+
+        let mut options = super::IndexLoadOptions {
+            directory: target.clone(),
+            dry_run: true,
+            input: dump_path.clone(),
+        };
+        assert_eq!(
+            String::from("Would load 1 entries "),
+            super::load_index(&options).unwrap()
+        );
+        assert!(!target.exists());
+        options.dry_run = false;
+        assert_eq!(
+            String::from("Loaded 1 entries "),
+            super::load_index(&options).unwrap()
+        );
+        let index = crate::index::Index::open(&target, false).unwrap().unwrap();
+        assert_eq!(
+            index.entries().unwrap(),
+            crate::index::Index::open(&source, false)
+                .unwrap()
+                .unwrap()
+                .entries()
+                .unwrap()
+        );
+        assert_eq!(
+            String::from("Loaded 0 entries, 1 unchanged "),
+            super::load_index(&options).unwrap()
+        );
+
+        // the same path or content with other data conflicts
+        let mut changed = dump["entries"][0].clone();
+        changed["hash"] = serde_json::Value::from("0123");
+        let mut copy = dump["entries"][0].clone();
+        copy["path"] = serde_json::Value::from("2023/07/copy.fit");
+        let dump = serde_json::json!({
+            "fitarchiver_index": dump["fitarchiver_index"],
+            "entries": [changed, copy],
+        });
+        fs::write(&dump_path, dump.to_string()).unwrap();
+        assert_eq!(
+            String::from("Loaded 0 entries with 2 conflicts."),
+            super::load_index(&options).unwrap()
+        );
+        fs::write(&dump_path, "{}").unwrap();
+        super::load_index(&options).expect_err("error expected");
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
@@ -0,0 +1,409 @@
+//! # Retention policies of an existing archive
+//!
+//! Devices recording all day, i.e. for monitoring the heart rate and steps, create thousands of
+//! files that are only of interest for a limited time. Archive files older than a retention age
+//! are deleted or compressed. Nothing is modified unless the selection is applied explicitly, so
+//! every policy is previewed first.
+
+use crate::checksum::{move_checksum, remove_checksum};
+use crate::clock::QUARANTINE_PATH;
+use crate::fitarchiver::{find_fit_files, parse_fit_file, ActivityData, ArchiverError, Result};
+use crate::index::{Index, INDEX_PATH};
+use crate::migrate::remove_empty_directories;
+use crate::objects::OBJECTS_PATH;
+use chrono::{DateTime, Days, Months, Utc};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+/// Minimum age of the archive files selected by a retention policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionAge {
+    /// Number of days, i.e. '30d'
+    Days(u32),
+    /// Number of weeks, i.e. '6w'
+    Weeks(u32),
+    /// Number of months, i.e. '18m'
+    Months(u32),
+    /// Number of years, i.e. '5y'
+    Years(u32),
+}
+
+impl Default for RetentionAge {
+    fn default() -> Self {
+        RetentionAge::Years(5)
+    }
+}
+
+impl FromStr for RetentionAge {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            let msg = format!(
+                "Invalid age '{}', expected a number followed by 'd', 'w', 'm' or 'y'",
+                s
+            );
+            ArchiverError::new(&msg)
+        };
+        let (number, unit) = s.split_at(s.len().saturating_sub(1));
+        let number = number.parse::<u32>().map_err(|_| invalid())?;
+        match unit {
+            "d" => Ok(RetentionAge::Days(number)),
+            "w" => Ok(RetentionAge::Weeks(number)),
+            "m" => Ok(RetentionAge::Months(number)),
+            "y" => Ok(RetentionAge::Years(number)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl RetentionAge {
+    /// Returns the time before which activities are older than the age, `None` if out of range
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Current time the age is based on.
+    pub(crate) fn cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match *self {
+            RetentionAge::Days(days) => now.checked_sub_days(Days::new(days.into())),
+            RetentionAge::Weeks(weeks) => now.checked_sub_days(Days::new(u64::from(weeks) * 7)),
+            RetentionAge::Months(months) => now.checked_sub_months(Months::new(months)),
+            RetentionAge::Years(years) => {
+                now.checked_sub_months(Months::new(years.checked_mul(12)?))
+            }
+        }
+    }
+}
+
+/// Action applied to the archive files selected by a retention policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PruneAction {
+    /// Delete the archive files
+    #[default]
+    Delete,
+    /// Replace the archive files by files compressed with gzip
+    Compress,
+}
+
+impl FromStr for PruneAction {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "delete" => Ok(PruneAction::Delete),
+            "compress" => Ok(PruneAction::Compress),
+            _ => {
+                let msg = format!(
+                    "Invalid prune action '{}', expected 'delete' or 'compress'",
+                    s
+                );
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+/// Options controlling the retention policy of an archive
+#[derive(Debug, Clone)]
+pub struct PruneOptions {
+    /// Base directory of the archive
+    pub directory: PathBuf,
+    /// Minimum age of the activities to prune
+    pub older_than: RetentionAge,
+    /// Only activities of the sports or files of the types, i.e. 'monitoring', all if empty
+    pub sports: Vec<String>,
+    /// Action applied to the selected archive files
+    pub action: PruneAction,
+    /// Modify the archive, otherwise the selected files are only shown
+    pub apply: bool,
+}
+
+impl Default for PruneOptions {
+    fn default() -> Self {
+        PruneOptions {
+            directory: PathBuf::from("."),
+            older_than: RetentionAge::default(),
+            sports: Vec::new(),
+            action: PruneAction::default(),
+            apply: false,
+        }
+    }
+}
+
+/// Returns true if an activity matches one of the sports
+///
+/// Files that are not activities are matched by their file type, where 'monitoring' matches
+/// 'monitoring_a', 'monitoring_b' and 'monitoring_daily'.
+///
+/// # Arguments
+///
+/// * `activity_data` - Activity data of the archive file.
+/// * `sports` - Sports or file types to match, all activities match if empty.
+fn matches_sport(activity_data: &ActivityData, sports: &[String]) -> bool {
+    sports.is_empty()
+        || sports.iter().any(|sport| {
+            *sport == activity_data.sport
+                || *sport == activity_data.file_type
+                || activity_data
+                    .file_type
+                    .strip_prefix(sport.as_str())
+                    .is_some_and(|suffix| suffix.starts_with('_'))
+        })
+}
+
+/// Compress an archive file with gzip and return the path of the compressed file
+///
+/// # Arguments
+///
+/// * `path` - Path of the archive file.
+fn compress_file(path: &Path) -> Result<PathBuf> {
+    let mut compressed_path = path.as_os_str().to_owned();
+    compressed_path.push(".gz");
+    let compressed_path = PathBuf::from(compressed_path);
+    if compressed_path.exists() {
+        let msg = format!(
+            "Unable to compress '{}', '{}' already exists",
+            path.display(),
+            compressed_path.display()
+        );
+        return Err(ArchiverError::new(&msg));
+    }
+
+    let msg = format!("Unable to create file '{}'", compressed_path.display());
+    let file = File::create(&compressed_path).map_err(|err| ArchiverError::io(&msg, err))?;
+    // gzip refuses to replace files with several links, i.e. in the hardlink layout
+    let result = Command::new("gzip")
+        .arg("-c")
+        .arg("-n")
+        .arg(path)
+        .stdout(file)
+        .output();
+    match result {
+        Ok(output) if output.status.success() => Ok(compressed_path),
+        Ok(output) => {
+            let _ = fs::remove_file(&compressed_path);
+            let msg = format!(
+                "Unable to compress '{}': {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            Err(ArchiverError::new(&msg))
+        }
+        Err(err) => {
+            let _ = fs::remove_file(&compressed_path);
+            Err(ArchiverError::io("Unable to run 'gzip'", err))
+        }
+    }
+}
+
+/// Delete or compress archive files older than the retention age
+///
+/// # Arguments
+///
+/// * `options` - Prune options.
+/// * `now` - Current time the age of the activities is based on.
+fn prune_at(options: &PruneOptions, now: DateTime<Utc>) -> Result<String> {
+    let Some(cutoff) = options.older_than.cutoff(now) else {
+        let msg = format!("Invalid age {:?}", options.older_than);
+        return Err(ArchiverError::new(&msg));
+    };
+
+    // an archive without index does not get one
+    let index = if options.apply && options.directory.join(INDEX_PATH).exists() {
+        Index::open(&options.directory, true)?
+    } else {
+        None
+    };
+
+    let files: Vec<PathBuf> = find_fit_files(&options.directory)?
+        .into_iter()
+        .filter(|path| {
+            !path.starts_with(options.directory.join(QUARANTINE_PATH))
+                && !path.starts_with(options.directory.join(OBJECTS_PATH))
+        })
+        .collect();
+
+    let mut prune_counter: usize = 0;
+    let mut error_counter: usize = 0;
+    for path in &files {
+        let result = parse_fit_file(path).and_then(|activity_data| {
+            if activity_data.timestamp >= cutoff || !matches_sport(&activity_data, &options.sports)
+            {
+                return Ok(None);
+            }
+            if !options.apply {
+                return Ok(Some("dry run"));
+            }
+
+            match options.action {
+                PruneAction::Delete => {
+                    fs::remove_file(path).map_err(|err| {
+                        let msg = format!("Unable to remove file '{}'", path.display());
+                        ArchiverError::io(&msg, err)
+                    })?;
+                    remove_checksum(path)?;
+                }
+                PruneAction::Compress => {
+                    let compressed_path = compress_file(path)?;
+                    fs::remove_file(path).map_err(|err| {
+                        let msg = format!("Unable to remove file '{}'", path.display());
+                        ArchiverError::io(&msg, err)
+                    })?;
+                    move_checksum(path, &compressed_path)?;
+                }
+            }
+            remove_empty_directories(&options.directory, path);
+            if let Some(index) = &index {
+                index.remove(path)?;
+            }
+            Ok(Some(match options.action {
+                PruneAction::Delete => "deleted",
+                PruneAction::Compress => "compressed",
+            }))
+        });
+
+        match result {
+            Ok(Some(status)) => {
+                println!("'{}' ... {}", path.display(), status);
+                prune_counter += 1;
+            }
+            Ok(None) => (),
+            Err(err) => {
+                eprintln!("{}", err);
+                error_counter += 1;
+            }
+        }
+    }
+
+    let action = match (options.action, options.apply) {
+        (PruneAction::Delete, true) => "Deleted",
+        (PruneAction::Compress, true) => "Compressed",
+        (PruneAction::Delete, false) => "Would delete",
+        (PruneAction::Compress, false) => "Would compress",
+    };
+    let msg = format!("{} {} of {} files", action, prune_counter, files.len());
+    let err = if error_counter == 0 {
+        String::new()
+    } else {
+        format!("with {} errors.", error_counter)
+    };
+
+    Ok([msg, err].join(" "))
+}
+
+/// Delete or compress archive files older than the retention age
+///
+/// The selected files are only shown unless the options apply the policy.
+///
+/// # Arguments
+///
+/// `options` - Prune options.
+pub fn prune(options: &PruneOptions) -> Result<String> {
+    prune_at(options, Utc::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test parsing retention ages
+    fn test_retention_age() {
+        let now = Utc.with_ymd_and_hms(2025, 3, 31, 12, 0, 0).unwrap();
+        for (age, cutoff) in [
+            ("5y", "2020-03-31 12:00:00 UTC"),
+            ("1m", "2025-02-28 12:00:00 UTC"),
+            ("2w", "2025-03-17 12:00:00 UTC"),
+            ("30d", "2025-03-01 12:00:00 UTC"),
+        ] {
+            let age: super::RetentionAge = age.parse().unwrap();
+            assert_eq!(cutoff, age.cutoff(now).unwrap().to_string());
+        }
+        for age in ["", "y", "5", "5h", "-5y"] {
+            age.parse::<super::RetentionAge>()
+                .expect_err("error expected");
+        }
+        assert!(super::RetentionAge::Years(u32::MAX).cutoff(now).is_none());
+    }
+
+    #[test]
+    /// Test deleting old activities of a sport
+    fn test_prune() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let directory = tmpdir.path().to_path_buf();
+        let archive_options = crate::fitarchiver::Options {
+            index: true,
+            directory: directory.clone(),
+            file_template: String::from("%Y/%m/$s"),
+            files: vec![PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("test")
+                .join("test_data_01.fit")],
+            checksums: true,
+            ..Default::default()
+        };
+        crate::fitarchiver::process_files(&archive_options).expect("unable to archive test file");
+        let file = directory.join("2023/07/running.fit");
+
+        let mut options = super::PruneOptions {
+            directory: directory.clone(),
+            older_than: super::RetentionAge::Years(1),
+            sports: vec![String::from("monitoring")],
+            ..Default::default()
+        };
+        let now = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(
+            "Would delete 0 of 1 files ",
+            super::prune_at(&options, now).unwrap()
+        );
+
+        // the selection is only shown unless applied
+        options.sports = vec![String::from("running")];
+        assert_eq!(
+            "Would delete 1 of 1 files ",
+            super::prune_at(&options, now).unwrap()
+        );
+        assert!(file.exists());
+
+        options.apply = true;
+        options.older_than = super::RetentionAge::Years(2);
+        assert_eq!(
+            "Deleted 0 of 1 files ",
+            super::prune_at(&options, now).unwrap()
+        );
+
+        options.older_than = super::RetentionAge::Months(6);
+        assert_eq!(
+            "Deleted 1 of 1 files ",
+            super::prune_at(&options, now).unwrap()
+        );
+        assert!(!file.exists());
+        assert!(!directory.join("2023").exists());
+
+        // the index no longer contains the activity
+        let connection = rusqlite::Connection::open(directory.join(super::INDEX_PATH)).unwrap();
+        let count: i64 = connection
+            .query_row("SELECT COUNT(*) FROM activities", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(0, count);
+
+        // compressed files replace the archive files and keep their checksum
+        crate::fitarchiver::process_files(&archive_options).expect("unable to archive test file");
+        options.action = super::PruneAction::Compress;
+        assert_eq!(
+            "Compressed 1 of 1 files ",
+            super::prune_at(&options, now).unwrap()
+        );
+        assert!(!file.exists());
+        let checksums =
+            fs::read_to_string(directory.join("2023/07").join(crate::CHECKSUM_FILE)).unwrap();
+        assert!(checksums.ends_with("  running.fit.gz\n"));
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
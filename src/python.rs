@@ -0,0 +1,211 @@
+//! # Python interface
+//!
+//! Python module `fitarchiver` exposing the FIT file parsing, the template expansion and the
+//! archiver itself so that scripts can share the implementation of the command line tool.
+
+use crate::fitarchiver::{archive_path, parse_fit_file, process_files, ActivityData, Options};
+use crate::index::INDEX_PATH;
+use crate::template::expand_formatstring;
+use chrono::{DateTime, Utc};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use std::path::{Path, PathBuf};
+
+create_exception!(
+    fitarchiver,
+    ArchiverError,
+    PyException,
+    "Error raised by the archiver."
+);
+
+/// Information extracted from a FIT file
+#[pyclass(name = "Activity", frozen)]
+struct PyActivity {
+    activity_data: ActivityData,
+}
+
+#[pymethods]
+impl PyActivity {
+    /// Sport type, i.e. 'running'
+    #[getter]
+    fn sport(&self) -> &str {
+        &self.activity_data.sport
+    }
+
+    /// Sport name, i.e. 'trail_run'
+    #[getter]
+    fn sport_name(&self) -> &str {
+        &self.activity_data.sport_name
+    }
+
+    /// Sport sub type, i.e. 'trail'
+    #[getter]
+    fn sub_sport(&self) -> &str {
+        &self.activity_data.sub_sport
+    }
+
+    /// Workout name, i.e. 'temporun_8km'
+    #[getter]
+    fn workout_name(&self) -> &str {
+        &self.activity_data.workout_name
+    }
+
+    /// UTC timestamp of activity start
+    #[getter]
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.activity_data.timestamp
+    }
+
+    /// Returns the template string with '%' and '$' tags expanded
+    fn expand(&self, template: &str) -> String {
+        expand_formatstring(template, &self.activity_data)
+    }
+
+    /// Returns the path of the archive file in the given directory
+    fn archive_path(&self, directory: &str, template: &str) -> String {
+        archive_path(Path::new(directory), template, &self.activity_data)
+            .display()
+            .to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Activity(sport='{}', sub_sport='{}', timestamp='{}')",
+            self.activity_data.sport, self.activity_data.sub_sport, self.activity_data.timestamp
+        )
+    }
+}
+
+/// Returns the activity data extracted from a FIT file
+#[pyfunction(name = "parse_fit_file")]
+fn py_parse_fit_file(path: &str) -> PyResult<PyActivity> {
+    match parse_fit_file(Path::new(path)) {
+        Ok(activity_data) => Ok(PyActivity { activity_data }),
+        Err(err) => Err(ArchiverError::new_err(err.details())),
+    }
+}
+
+/// Returns the template string with '%' and '$' tags expanded for an activity
+#[pyfunction(name = "expand_template")]
+fn py_expand_template(activity: &PyActivity, template: &str) -> String {
+    activity.expand(template)
+}
+
+/// Archive FIT files like the command line tool and return the summary
+#[pyfunction(name = "archive")]
+#[pyo3(signature = (files, directory=".", file_template=None, move_files=false, dry_run=false))]
+fn py_archive(
+    files: Vec<String>,
+    directory: &str,
+    file_template: Option<&str>,
+    move_files: bool,
+    dry_run: bool,
+) -> PyResult<String> {
+    if files.is_empty() {
+        return Err(PyValueError::new_err("No files given"));
+    }
+
+    // an existing index is kept up to date like by the command line tool
+    let mut options = Options {
+        directory: PathBuf::from(directory),
+        index: Path::new(directory).join(INDEX_PATH).exists(),
+        move_files,
+        dry_run,
+        files: files.iter().map(PathBuf::from).collect(),
+        ..Default::default()
+    };
+    if let Some(file_template) = file_template {
+        options.file_template = String::from(file_template);
+    }
+
+    match process_files(&options) {
+        Ok(msg) => Ok(msg),
+        Err(err) => Err(ArchiverError::new_err(err.details())),
+    }
+}
+
+/// Python module `fitarchiver`
+#[pymodule(name = "fitarchiver")]
+fn fitarchiver_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("ArchiverError", m.py().get_type::<ArchiverError>())?;
+    m.add_class::<PyActivity>()?;
+    m.add_function(wrap_pyfunction!(py_parse_fit_file, m)?)?;
+    m.add_function(wrap_pyfunction!(py_expand_template, m)?)?;
+    m.add_function(wrap_pyfunction!(py_archive, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArchiverError;
+    use pyo3::exceptions::PyValueError;
+    use pyo3::prelude::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    /// Returns the path of a file in the test data directory
+    fn test_data(name: &str) -> String {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test")
+            .join(name)
+            .display()
+            .to_string()
+    }
+
+    #[test]
+    /// Test parsing a FIT file and expanding templates through the Python interface
+    fn test_python_parse_fit_file() {
+        let activity = super::py_parse_fit_file(&test_data("test_data_01.fit")).unwrap();
+        assert_eq!("running", activity.sport());
+        assert_eq!("trail", activity.sub_sport());
+        assert_eq!(1690352524, activity.timestamp().timestamp());
+        assert_eq!(
+            "2023-running-trail",
+            super::py_expand_template(&activity, "%Y-$s-$S")
+        );
+        assert_eq!(
+            "archive/2023/07/trail_run.fit",
+            activity.archive_path("archive", "%Y/%m/$n")
+        );
+    }
+
+    #[test]
+    /// Test the mapping of errors to Python exceptions
+    fn test_python_errors() {
+        Python::initialize();
+        Python::attach(|py| {
+            let err = super::py_parse_fit_file(&test_data("corrupted.fit"))
+                .err()
+                .expect("error expected");
+            assert!(err.is_instance_of::<ArchiverError>(py));
+
+            let err =
+                super::py_archive(vec![], ".", None, false, false).expect_err("error expected");
+            assert!(err.is_instance_of::<PyValueError>(py));
+        });
+    }
+
+    #[test]
+    /// Test archiving files through the Python interface like the command line tool
+    fn test_python_archive() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let directory = tmpdir.path().to_str().unwrap();
+
+        let result = super::py_archive(
+            vec![test_data("test_data_01.fit")],
+            directory,
+            Some("%Y-$s"),
+            false,
+            false,
+        )
+        .unwrap();
+        assert_eq!(String::from("Processed 1 files "), result);
+        assert!(tmpdir.path().join("2023-running.fit").exists());
+        assert!(PathBuf::from(test_data("test_data_01.fit")).exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
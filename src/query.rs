@@ -0,0 +1,215 @@
+//! # Queries over an existing archive
+//!
+//! The archive files are selected by sport, subsport and time of their activity. The activity
+//! data is read from the index of the archive. Archives without index, or with an index created
+//! by an older version, are scanned by parsing all archive files.
+
+use crate::fitarchiver::{find_fit_files, parse_fit_file, ActivityData, ArchiverError, Result};
+use crate::index::Index;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::path::PathBuf;
+
+/// Options controlling the query of an archive
+#[derive(Debug, Clone)]
+pub struct QueryOptions {
+    /// Base directory of the archive
+    pub directory: PathBuf,
+    /// Only activities of the sport, i.e. 'running'
+    pub sport: Option<String>,
+    /// Only activities of the subsport, i.e. 'trail'
+    pub sub_sport: Option<String>,
+    /// Only activities of the year
+    pub year: Option<i32>,
+    /// Only activities on or after the day (UTC)
+    pub from: Option<NaiveDate>,
+    /// Only activities on or before the day (UTC)
+    pub to: Option<NaiveDate>,
+    /// Parse the archive files instead of reading the index
+    pub scan: bool,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        QueryOptions {
+            directory: PathBuf::from("."),
+            sport: None,
+            sub_sport: None,
+            year: None,
+            from: None,
+            to: None,
+            scan: false,
+        }
+    }
+}
+
+/// Start and exclusive end of a time range, `None` if unrestricted
+type TimeRange = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// Returns the start of the first and the end of the last day of the activities to select
+///
+/// Both are `None` if unrestricted, the end is exclusive.
+///
+/// # Arguments
+///
+/// * `options` - Query options.
+fn time_range(options: &QueryOptions) -> Result<TimeRange> {
+    let mut first = options.from;
+    let mut last = options.to;
+    if let Some(year) = options.year {
+        let invalid = || ArchiverError::new(&format!("Invalid year '{}'", year));
+        let start = NaiveDate::from_ymd_opt(year, 1, 1).ok_or_else(invalid)?;
+        let end = NaiveDate::from_ymd_opt(year, 12, 31).ok_or_else(invalid)?;
+        first = Some(first.map_or(start, |first| first.max(start)));
+        last = Some(last.map_or(end, |last| last.min(end)));
+    }
+
+    let start = first.map(|day| day.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    let end = last
+        .and_then(|day| day.succ_opt())
+        .map(|day| day.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    Ok((start, end))
+}
+
+/// Returns true if an activity matches the query
+///
+/// # Arguments
+///
+/// * `activity_data` - Activity data of the archive file.
+/// * `options` - Query options.
+/// * `start` - Start of the time range.
+/// * `end` - Exclusive end of the time range.
+fn matches(
+    activity_data: &ActivityData,
+    options: &QueryOptions,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> bool {
+    options
+        .sport
+        .as_ref()
+        .is_none_or(|sport| *sport == activity_data.sport)
+        && options
+            .sub_sport
+            .as_ref()
+            .is_none_or(|sub_sport| *sub_sport == activity_data.sub_sport)
+        && start.is_none_or(|start| activity_data.timestamp >= start)
+        && end.is_none_or(|end| activity_data.timestamp < end)
+}
+
+/// Returns the paths of the archive files whose activities match the query, one per line
+///
+/// The paths are ordered by the start time of the activities.
+///
+/// # Arguments
+///
+/// `options` - Query options.
+pub fn query(options: &QueryOptions) -> Result<String> {
+    let (start, end) = time_range(options)?;
+
+    let index = match options.scan {
+        true => None,
+        false => Index::open(&options.directory, false)?,
+    };
+    let paths = match index {
+        Some(index) => index.query_paths_by(
+            options.sport.as_deref(),
+            options.sub_sport.as_deref(),
+            start,
+            end,
+        )?,
+        None => {
+            let mut activities = Vec::new();
+            for path in find_fit_files(&options.directory)? {
+                match parse_fit_file(&path) {
+                    Ok(activity_data) => {
+                        if matches(&activity_data, options, start, end) {
+                            activities.push((activity_data.timestamp, path));
+                        }
+                    }
+                    Err(err) => eprintln!("{}", err),
+                }
+            }
+            activities.sort();
+            activities.into_iter().map(|(_, path)| path).collect()
+        }
+    };
+
+    Ok(paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test the time range of years and days
+    fn test_time_range() {
+        let mut options = super::QueryOptions {
+            year: Some(2023),
+            ..Default::default()
+        };
+        let (start, end) = super::time_range(&options).unwrap();
+        assert_eq!("2023-01-01 00:00:00 UTC", start.unwrap().to_string());
+        assert_eq!("2024-01-01 00:00:00 UTC", end.unwrap().to_string());
+
+        options.from = NaiveDate::from_ymd_opt(2023, 6, 1);
+        options.to = NaiveDate::from_ymd_opt(2024, 8, 31);
+        let (start, end) = super::time_range(&options).unwrap();
+        assert_eq!("2023-06-01 00:00:00 UTC", start.unwrap().to_string());
+        assert_eq!("2024-01-01 00:00:00 UTC", end.unwrap().to_string());
+
+        options.year = None;
+        let (_, end) = super::time_range(&options).unwrap();
+        assert_eq!("2024-09-01 00:00:00 UTC", end.unwrap().to_string());
+
+        options.year = Some(300000);
+        super::time_range(&options).expect_err("error expected");
+    }
+
+    #[test]
+    /// Test querying an archive by index and by scanning
+    fn test_query() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_options = crate::fitarchiver::Options {
+            directory: tmpdir.path().to_path_buf(),
+            file_template: String::from("%Y/$s-%Y%m%d"),
+            files: vec![PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("test")
+                .join("test_data_01.fit")],
+            ..Default::default()
+        };
+        crate::fitarchiver::process_files(&archive_options).expect("unable to archive test file");
+        let archived = tmpdir.path().join("2023/running-20230726.fit");
+
+        for scan in [false, true] {
+            let mut options = super::QueryOptions {
+                directory: tmpdir.path().to_path_buf(),
+                sport: Some(String::from("running")),
+                year: Some(2023),
+                scan,
+                ..Default::default()
+            };
+            assert_eq!(
+                archived.display().to_string(),
+                super::query(&options).unwrap()
+            );
+
+            options.from = NaiveDate::from_ymd_opt(2023, 7, 27);
+            assert_eq!("", super::query(&options).unwrap());
+
+            options.from = None;
+            options.sport = Some(String::from("cycling"));
+            assert_eq!("", super::query(&options).unwrap());
+        }
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
@@ -0,0 +1,338 @@
+//! # Archive directories on other hosts
+//!
+//! An archive directory given as URL, i.e. 'sftp://user@host/path' or 'webdav://host/path', is
+//! located on another host.
+//! The archive files are transferred directly to the host, the paths are the same as in a local
+//! archive. Features relying on local access to the archive, like the index, the manifest or
+//! the storage layouts, are not available.
+
+use crate::fitarchiver::{removes_source, ArchiverError, ConflictPolicy, Options, Result, Status};
+use crate::objects::{file_hash, StorageLayout};
+use crate::ssh::SshRemote;
+use crate::webdav::{WebDavRemote, WEBDAV_PASSWORD_VARIABLE, WEBDAV_USER_VARIABLE};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Access to an archive directory on another host
+pub(crate) trait Remote: Send + Sync {
+    /// Returns the path of the archive directory on the host
+    fn directory(&self) -> &Path;
+
+    /// Returns true if a file exists on the host
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file on the host.
+    fn exists(&self, path: &Path) -> Result<bool>;
+
+    /// Returns the SHA-256 hash of the content of a file on the host
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file on the host.
+    fn file_hash(&self, path: &Path) -> Result<String>;
+
+    /// Returns the modification time of a file on the host
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file on the host.
+    fn modified(&self, path: &Path) -> Result<SystemTime>;
+
+    /// Create a directory and its parents on the host
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the directory on the host.
+    fn create_directory(&self, path: &Path) -> Result<()>;
+
+    /// Transfer a local file to the host, replacing an existing file
+    ///
+    /// # Arguments
+    ///
+    /// * `source_path` - Path of the local file.
+    /// * `path` - Path of the file on the host.
+    fn upload(&self, source_path: &Path, path: &Path) -> Result<()>;
+}
+
+/// Returns the remote archive directory of a URL or `None` for a local directory
+///
+/// The credentials of WebDAV servers not given in the options are read from the environment.
+///
+/// # Arguments
+///
+/// * `options` - Archiver options with the archive directory as given by the user.
+pub(crate) fn open_remote(options: &Options) -> Result<Option<Box<dyn Remote>>> {
+    let url = options.directory.to_string_lossy();
+    match url.split_once("://") {
+        Some(("sftp", _)) => Ok(Some(Box::new(SshRemote::parse(&url)?))),
+        Some(("webdav" | "webdav+http", _)) => {
+            let user = options
+                .webdav_user
+                .clone()
+                .or_else(|| env::var(WEBDAV_USER_VARIABLE).ok());
+            let password = options
+                .webdav_password
+                .clone()
+                .or_else(|| env::var(WEBDAV_PASSWORD_VARIABLE).ok());
+            let remote = WebDavRemote::parse(&url, user.as_deref(), password.as_deref())?;
+            Ok(Some(Box::new(remote)))
+        }
+        Some((scheme, _)) => {
+            let msg = format!(
+                "Unsupported archive directory '{}', expected a local path, 'sftp://' or 'webdav://'",
+                url
+            );
+            match scheme.chars().all(|c| c.is_ascii_alphanumeric()) {
+                true => Err(ArchiverError::new(&msg)),
+                false => Ok(None),
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+/// Returns the archiver options for a remote archive directory
+///
+/// The options refer to the archive directory on the host, the index and the manifest are not
+/// written. Options that need local access to the archive are rejected.
+///
+/// # Arguments
+///
+/// * `remote` - Remote archive directory.
+/// * `options` - Archiver options.
+pub(crate) fn remote_options(remote: &dyn Remote, options: &Options) -> Result<Options> {
+    let unsupported = [
+        (options.layout != StorageLayout::Plain, "--layout"),
+        (options.git.is_some(), "--git"),
+        (options.upload.is_some(), "--upload"),
+        (options.sign_key.is_some(), "--sign"),
+        (options.checksums, "--checksums"),
+        (options.export_gpx, "--export-gpx"),
+        (options.skip_duplicates, "--skip-duplicates"),
+        (options.require_mount, "--require-mount"),
+        (options.retry_failed, "--retry-failed"),
+        (options.conflict_resolver.is_some(), "conflict_resolver"),
+    ];
+    if let Some((_, option)) = unsupported.iter().find(|(used, _)| *used) {
+        let msg = format!(
+            "Option '{}' is not supported for the remote archive directory '{}'",
+            option,
+            options.directory.display()
+        );
+        return Err(ArchiverError::new(&msg));
+    }
+
+    Ok(Options {
+        directory: remote.directory().to_path_buf(),
+        index: false,
+        manifest: false,
+        ..options.clone()
+    })
+}
+
+/// Transfer a file to a remote archive directory
+///
+/// Existing archive files are handled like in a local archive, but replaced files are not kept
+/// as backup.
+///
+/// # Arguments
+///
+/// * `remote` - Remote archive directory.
+/// * `source_path` - Path to the source file.
+/// * `archive_path` - Path to the archive file on the host.
+/// * `options` - Archiver options.
+pub(crate) fn archive_remote_file(
+    remote: &dyn Remote,
+    source_path: &Path,
+    archive_path: &Path,
+    options: &Options,
+) -> Result<Status> {
+    let source_hash = || {
+        file_hash(source_path).map_err(|err| {
+            let msg = format!("Unable to read file '{}'", source_path.display());
+            ArchiverError::io(&msg, err)
+        })
+    };
+    let remove_source = || {
+        fs::remove_file(source_path).map_err(|err| {
+            let msg = format!("Unable to remove file '{}'", source_path.display());
+            ArchiverError::io(&msg, err)
+        })
+    };
+
+    // resolve conflicts with an existing archive file
+    if remote.exists(archive_path)? {
+        if remote.file_hash(archive_path)? == source_hash()? {
+            if removes_source(options) {
+                remove_source()?;
+            }
+            return Ok(Status::Identical);
+        }
+        match options.on_conflict {
+            ConflictPolicy::Overwrite => (),
+            ConflictPolicy::Skip => return Ok(Status::Skipped),
+            ConflictPolicy::OverwriteIfNewer => {
+                let modified = fs::metadata(source_path)
+                    .and_then(|metadata| metadata.modified())
+                    .map_err(|err| {
+                        let msg = format!("Unable to read file '{}'", source_path.display());
+                        ArchiverError::io(&msg, err)
+                    })?;
+                if modified <= remote.modified(archive_path)? {
+                    return Ok(Status::Skipped);
+                }
+            }
+        }
+    }
+    if options.dry_run {
+        return Ok(Status::DryRun);
+    }
+
+    if let Some(parent) = archive_path.parent() {
+        remote.create_directory(parent)?;
+    }
+    remote.upload(source_path, archive_path)?;
+    if !options.move_files {
+        return Ok(Status::Copied);
+    }
+
+    // the source file is only removed once the transfer is verified
+    if remote.file_hash(archive_path)? != source_hash()? {
+        let msg = format!(
+            "Archive file '{}' differs from '{}'",
+            archive_path.display(),
+            source_path.display()
+        );
+        return Err(ArchiverError::new(&msg));
+    }
+    if removes_source(options) {
+        remove_source()?;
+    }
+    Ok(Status::Moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Remote;
+    use crate::fitarchiver::{ConflictPolicy, Options, Result, Status};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::time::SystemTime;
+    use tempdir::TempDir;
+
+    /// Remote archive directory backed by a local directory
+    struct LocalRemote(PathBuf);
+
+    impl Remote for LocalRemote {
+        fn directory(&self) -> &Path {
+            &self.0
+        }
+
+        fn exists(&self, path: &Path) -> Result<bool> {
+            Ok(path.exists())
+        }
+
+        fn file_hash(&self, path: &Path) -> Result<String> {
+            Ok(crate::objects::file_hash(path).unwrap())
+        }
+
+        fn modified(&self, path: &Path) -> Result<SystemTime> {
+            Ok(fs::metadata(path).unwrap().modified().unwrap())
+        }
+
+        fn create_directory(&self, path: &Path) -> Result<()> {
+            fs::create_dir_all(path).unwrap();
+            Ok(())
+        }
+
+        fn upload(&self, source_path: &Path, path: &Path) -> Result<()> {
+            fs::copy(source_path, path).unwrap();
+            Ok(())
+        }
+    }
+
+    #[test]
+    /// Test recognizing remote archive directories
+    fn test_open_remote() {
+        let open = |directory: &str| {
+            super::open_remote(&Options {
+                directory: PathBuf::from(directory),
+                ..Default::default()
+            })
+        };
+        assert!(open("/backup/activities").unwrap().is_none());
+        let remote = open("webdav://nas/dav/activities").unwrap().unwrap();
+        assert_eq!(Path::new("/dav/activities"), remote.directory());
+        let remote = open("sftp://pi@nas/srv/activities").unwrap().unwrap();
+        assert_eq!(Path::new("/srv/activities"), remote.directory());
+        assert!(open("ftp://nas/activities").is_err());
+
+        let options = Options {
+            directory: PathBuf::from("sftp://pi@nas/srv/activities"),
+            ..Default::default()
+        };
+        let remote_options = super::remote_options(remote.as_ref(), &options).unwrap();
+        assert_eq!(PathBuf::from("/srv/activities"), remote_options.directory);
+        assert!(!remote_options.index);
+        let options = Options {
+            checksums: true,
+            ..options
+        };
+        super::remote_options(remote.as_ref(), &options).expect_err("error expected");
+        // the failed files are recorded in the index
+        let options = Options {
+            checksums: false,
+            retry_failed: true,
+            ..options
+        };
+        super::remote_options(remote.as_ref(), &options).expect_err("error expected");
+    }
+
+    #[test]
+    /// Test transferring files to a remote archive directory
+    fn test_archive_remote_file() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let remote = LocalRemote(tmpdir.path().join("remote"));
+        let source_path = tmpdir.path().join("source.fit");
+        let archive_path = remote.0.join("2023/run.fit");
+        fs::write(&source_path, "new").unwrap();
+
+        let mut options = Options {
+            directory: remote.0.clone(),
+            move_files: true,
+            delete_sources: crate::SourceDeletion::Immediately,
+            on_conflict: ConflictPolicy::Skip,
+            ..Default::default()
+        };
+        let archive = |options: &Options| {
+            super::archive_remote_file(&remote, &source_path, &archive_path, options).unwrap()
+        };
+        options.dry_run = true;
+        assert_eq!(Status::DryRun, archive(&options));
+        assert!(!archive_path.exists());
+
+        options.dry_run = false;
+        options.move_files = false;
+        assert_eq!(Status::Copied, archive(&options));
+        assert_eq!("new", fs::read_to_string(&archive_path).unwrap());
+
+        // existing archive files are handled by the conflict policy
+        fs::write(&archive_path, "old").unwrap();
+        assert_eq!(Status::Skipped, archive(&options));
+        options.on_conflict = ConflictPolicy::Overwrite;
+        options.move_files = true;
+        assert_eq!(Status::Moved, archive(&options));
+        assert_eq!("new", fs::read_to_string(&archive_path).unwrap());
+        assert!(!source_path.exists());
+
+        fs::write(&source_path, "new").unwrap();
+        assert_eq!(Status::Identical, archive(&options));
+        assert!(!source_path.exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
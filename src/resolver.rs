@@ -0,0 +1,188 @@
+//! # Conflict resolvers of embedding applications
+//!
+//! Applications using the library decide about conflicts with existing archive files beyond the
+//! conflict policies of the command line, i.e. by asking the user or by comparing the recording
+//! devices. The resolver is asked only if the existing archive file has a different content,
+//! files archived before are still reported as already archived.
+
+use crate::fitarchiver::{same_content, same_file, ArchiverError, Result};
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// File involved in a conflict
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConflictFile {
+    /// Path of the file
+    pub path: PathBuf,
+    /// Size of the file in bytes
+    pub size: u64,
+    /// Modification time of the file
+    pub modified: SystemTime,
+}
+
+impl ConflictFile {
+    /// Returns the file information of a path
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file.
+    fn read(path: &Path) -> Result<ConflictFile> {
+        let metadata = fs::metadata(path).map_err(|err| {
+            let msg = format!("Unable to read file '{}'", path.display());
+            ArchiverError::io(&msg, err)
+        })?;
+        Ok(ConflictFile {
+            path: path.to_path_buf(),
+            size: metadata.len(),
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        })
+    }
+}
+
+/// Decision about a file conflicting with an existing archive file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// Keep the existing archive file as the archived version of the file, i.e. when it is the
+    /// better recording of the same activity. The file is reported as already archived and is
+    /// removed when moving files.
+    Keep,
+    /// Replace the existing archive file, it is kept as backup
+    Replace,
+    /// Archive the file under another file name in the same directory. If that file exists as
+    /// well, the conflict policy of the options applies.
+    Rename(String),
+    /// Do not archive the file and leave it untouched
+    Skip,
+}
+
+/// Resolver of conflicts with existing archive files
+///
+/// The resolver is asked for the files in the order of the list of files, so files are archived
+/// one after another when a resolver is set.
+pub trait ConflictResolver: fmt::Debug + Send + Sync {
+    /// Returns the decision about a file conflicting with an existing archive file
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - File to archive.
+    /// * `activity` - Activity data of the file to archive as in the JSON output, i.e.
+    ///   `activity["sport"]`.
+    /// * `existing` - Existing archive file with a different content.
+    fn resolve(
+        &self,
+        source: &ConflictFile,
+        activity: &serde_json::Value,
+        existing: &ConflictFile,
+    ) -> Resolution;
+}
+
+/// Returns the decision of a resolver about a conflict or `None` if there is no conflict
+///
+/// The new name of a renamed file is checked to be a file name.
+///
+/// # Arguments
+///
+/// * `resolver` - Resolver of the application.
+/// * `source_path` - Path to the source file.
+/// * `archive_path` - Path to the archive file.
+/// * `activity` - Activity data of the source file as JSON object.
+pub(crate) fn ask_resolver(
+    resolver: &dyn ConflictResolver,
+    source_path: &Path,
+    archive_path: &Path,
+    activity: &serde_json::Value,
+) -> Result<Option<Resolution>> {
+    if !archive_path.exists() || same_file(source_path, archive_path) {
+        return Ok(None);
+    }
+    let identical = same_content(source_path, archive_path).map_err(|err| {
+        let msg = format!(
+            "Unable to compare '{}' with '{}'",
+            source_path.display(),
+            archive_path.display()
+        );
+        ArchiverError::io(&msg, err)
+    })?;
+    if identical {
+        return Ok(None);
+    }
+
+    let source = ConflictFile::read(source_path)?;
+    let existing = ConflictFile::read(archive_path)?;
+    match resolver.resolve(&source, activity, &existing) {
+        Resolution::Rename(name) => {
+            let mut components = Path::new(&name).components();
+            match (components.next(), components.next()) {
+                (Some(Component::Normal(_)), None) => Ok(Some(Resolution::Rename(name))),
+                _ => {
+                    let msg = format!(
+                        "Invalid name '{}' for renaming '{}', expected a file name",
+                        name,
+                        source_path.display()
+                    );
+                    Err(ArchiverError::new(&msg))
+                }
+            }
+        }
+        resolution => Ok(Some(resolution)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConflictFile, ConflictResolver, Resolution};
+    use std::fs;
+    use tempdir::TempDir;
+
+    /// Resolver renaming files to the name of their sport
+    #[derive(Debug)]
+    struct SportResolver;
+
+    impl ConflictResolver for SportResolver {
+        fn resolve(
+            &self,
+            source: &ConflictFile,
+            activity: &serde_json::Value,
+            existing: &ConflictFile,
+        ) -> Resolution {
+            match source.size > existing.size {
+                true => Resolution::Rename(format!("{}.fit", activity["sport"].as_str().unwrap())),
+                false => Resolution::Skip,
+            }
+        }
+    }
+
+    #[test]
+    /// Test asking a resolver about conflicts with existing archive files
+    fn test_ask_resolver() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let source_path = tmpdir.path().join("source.fit");
+        let archive_path = tmpdir.path().join("2023/run.fit");
+        fs::create_dir_all(tmpdir.path().join("2023")).unwrap();
+        fs::write(&source_path, "longer").unwrap();
+        let activity = serde_json::json!({"sport": "running"});
+        let ask = || super::ask_resolver(&SportResolver, &source_path, &archive_path, &activity);
+
+        // only existing files with a different content are conflicts
+        assert_eq!(None, ask().unwrap());
+        fs::write(&archive_path, "longer").unwrap();
+        assert_eq!(None, ask().unwrap());
+        fs::write(&archive_path, "old").unwrap();
+        assert_eq!(
+            Some(Resolution::Rename(String::from("running.fit"))),
+            ask().unwrap()
+        );
+        fs::write(&archive_path, "much longer").unwrap();
+        assert_eq!(Some(Resolution::Skip), ask().unwrap());
+
+        let activity = serde_json::json!({"sport": "../running"});
+        fs::write(&archive_path, "old").unwrap();
+        super::ask_resolver(&SportResolver, &source_path, &archive_path, &activity)
+            .expect_err("error expected");
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
@@ -0,0 +1,229 @@
+//! # Restore of archived files to their original location
+//!
+//! The index records where each file was archived from and which device recorded it. Restoring
+//! copies an archived file back to that location, i.e. onto a device that has been reset, or into
+//! another directory.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use crate::index::{Index, INDEX_PATH};
+use crate::objects::file_hash;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Options controlling the restore of archived files
+#[derive(Debug, Clone)]
+pub struct RestoreOptions {
+    /// Base directory of the archive
+    pub directory: PathBuf,
+    /// Archived files to restore
+    pub files: Vec<PathBuf>,
+    /// Directory to restore the files to instead of their original location
+    pub target: Option<PathBuf>,
+    /// Do not copy any files, just show what will happen
+    pub dry_run: bool,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        RestoreOptions {
+            directory: PathBuf::from("."),
+            files: Vec::new(),
+            target: None,
+            dry_run: false,
+        }
+    }
+}
+
+/// Restore a single archived file and return its destination and status
+///
+/// # Arguments
+///
+/// * `index` - Index of the archive.
+/// * `path` - Path of the archived file.
+/// * `options` - Restore options.
+fn restore_file(
+    index: &Index,
+    path: &Path,
+    options: &RestoreOptions,
+) -> Result<(PathBuf, &'static str)> {
+    let Some((source_path, device)) = index.source(path)? else {
+        let msg = format!("No original location of '{}' in index", path.display());
+        return Err(ArchiverError::new(&msg));
+    };
+    let destination = match &options.target {
+        Some(target) => target.join(source_path.file_name().unwrap_or(path.as_os_str())),
+        None => {
+            // the device of the original location has to be mounted
+            if !source_path.parent().is_some_and(|parent| parent.is_dir()) {
+                let msg = format!(
+                    "Original location '{}' of '{}' is not available, device '{}' not mounted?",
+                    source_path.display(),
+                    path.display(),
+                    device
+                );
+                return Err(ArchiverError::new(&msg));
+            }
+            source_path
+        }
+    };
+
+    if destination.exists() {
+        let msg = format!(
+            "Unable to compare '{}' to '{}'",
+            path.display(),
+            destination.display()
+        );
+        let identical = file_hash(path)
+            .and_then(|hash| file_hash(&destination).map(|other| hash == other))
+            .map_err(|err| ArchiverError::io(&msg, err))?;
+        if identical {
+            return Ok((destination, "already restored"));
+        }
+        let msg = format!(
+            "Unable to restore '{}', '{}' already exists",
+            path.display(),
+            destination.display()
+        );
+        return Err(ArchiverError::new(&msg));
+    }
+    if options.dry_run {
+        return Ok((destination, "dry run"));
+    }
+
+    let msg = format!(
+        "Unable to restore '{}' to '{}'",
+        path.display(),
+        destination.display()
+    );
+    if let Some(target) = &options.target {
+        fs::create_dir_all(target).map_err(|err| ArchiverError::io(&msg, err))?;
+    }
+    fs::copy(path, &destination).map_err(|err| ArchiverError::io(&msg, err))?;
+    Ok((destination, "restored"))
+}
+
+/// Copy archived files back to the location they were archived from
+///
+/// # Arguments
+///
+/// `options` - Restore options.
+pub fn restore(options: &RestoreOptions) -> Result<String> {
+    if !options.directory.join(INDEX_PATH).exists() {
+        let msg = format!(
+            "No index found in archive '{}'",
+            options.directory.display()
+        );
+        return Err(ArchiverError::new(&msg));
+    }
+    let Some(index) = Index::open(&options.directory, false)? else {
+        return Ok(String::new());
+    };
+
+    let mut restore_counter: usize = 0;
+    let mut error_counter: usize = 0;
+    for path in &options.files {
+        match restore_file(&index, path, options) {
+            Ok((destination, status)) => {
+                println!(
+                    "'{}' -> '{}' ... {}",
+                    path.display(),
+                    destination.display(),
+                    status
+                );
+                if status != "already restored" {
+                    restore_counter += 1;
+                }
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                error_counter += 1;
+            }
+        }
+    }
+
+    let msg = format!(
+        "Restored {} of {} files",
+        restore_counter,
+        options.files.len()
+    );
+    let err = if error_counter == 0 {
+        String::new()
+    } else {
+        format!("with {} errors.", error_counter)
+    };
+
+    Ok([msg, err].join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test restoring archived files to their original location and to another directory
+    fn test_restore() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let device = tmpdir.path().join("GARMIN/Activity");
+        let archive = tmpdir.path().join("archive");
+        fs::create_dir_all(&device).expect("error during creating temporary directory");
+        let source = device.join("run.fit");
+        fs::copy(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("test")
+                .join("test_data_01.fit"),
+            &source,
+        )
+        .expect("unable to copy test file");
+
+        let mut options = super::RestoreOptions {
+            directory: archive.clone(),
+            ..super::RestoreOptions::default()
+        };
+        super::restore(&options).expect_err("error expected");
+
+        let archive_options = crate::fitarchiver::Options {
+            directory: archive.clone(),
+            files: vec![source.clone()],
+            index: true,
+            ..crate::fitarchiver::Options::default()
+        };
+        crate::fitarchiver::process_files(&archive_options).expect("unable to archive test file");
+        let index = crate::index::Index::open(&archive, false).unwrap().unwrap();
+        let archived = crate::fitarchiver::find_fit_files(&archive).unwrap();
+        assert_eq!(1, archived.len());
+        let (source_path, device_name) = index.source(&archived[0]).unwrap().unwrap();
+        assert_eq!(std::path::absolute(&source).unwrap(), source_path);
+        assert!(!device_name.is_empty());
+        drop(index);
+
+        // the file still exists on the device
+        options.files = archived.clone();
+        assert_eq!("Restored 0 of 1 files ", super::restore(&options).unwrap());
+
+        // the device has been reset
+        fs::remove_file(&source).unwrap();
+        options.dry_run = true;
+        assert_eq!("Restored 1 of 1 files ", super::restore(&options).unwrap());
+        assert!(!source.exists());
+        options.dry_run = false;
+        assert_eq!("Restored 1 of 1 files ", super::restore(&options).unwrap());
+        assert!(source.exists());
+
+        // the device is not mounted
+        fs::remove_dir_all(tmpdir.path().join("GARMIN")).unwrap();
+        assert_eq!(
+            "Restored 0 of 1 files with 1 errors.",
+            super::restore(&options).unwrap()
+        );
+
+        // restore into another directory
+        options.target = Some(tmpdir.path().join("restored"));
+        assert_eq!("Restored 1 of 1 files ", super::restore(&options).unwrap());
+        assert!(tmpdir.path().join("restored/run.fit").exists());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
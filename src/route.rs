@@ -0,0 +1,271 @@
+//! # Routes of activities
+//!
+//! Reference routes, i.e. benchmark loops, are registered as GPX tracks or routes or as FIT
+//! courses. The track of each activity is compared to them, an activity follows a route if most
+//! of its track is close to the route and most of the route is covered by the track. Repeated
+//! loops get the same name across the years, independent of the start time or direction.
+
+use crate::fitarchiver::{parse_fit_file, ArchiverError, Result};
+use crate::location::{distance, Position};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Minimum distance between the points kept of a track in meters
+const TRACK_SPACING: f64 = 25.0;
+
+/// Minimum share of the track near the route and of the route covered by the track
+const MIN_OVERLAP: f64 = 0.8;
+
+/// Reference route registered by the user
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    /// Name of the route, i.e. 'park_loop'
+    pub name: String,
+    /// Path of the GPX or FIT file containing the route
+    pub path: PathBuf,
+}
+
+impl FromStr for Route {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once('=') {
+            Some((name, path)) if !name.trim().is_empty() && !path.trim().is_empty() => Ok(Route {
+                name: name.trim().to_string(),
+                path: PathBuf::from(path.trim()),
+            }),
+            _ => {
+                let msg = format!("Invalid route '{}', expected '<name>=<file>'", s);
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+/// Points of a reference route
+#[derive(Debug, Clone)]
+pub(crate) struct RouteTrack {
+    /// Name of the route
+    name: String,
+    /// Points of the route with at least the track spacing between them
+    track: Vec<Position>,
+}
+
+/// Add a position to a track if it is not too close to the previous point
+///
+/// Thinning the track bounds the memory of long activities and the effort of comparing tracks.
+///
+/// # Arguments
+///
+/// * `track` - Track the position is added to.
+/// * `position` - Position in degrees.
+pub(crate) fn add_track_point(track: &mut Vec<Position>, position: Position) {
+    match track.last() {
+        Some(last) if distance(*last, position) < TRACK_SPACING => (),
+        _ => track.push(position),
+    }
+}
+
+/// Returns the value of a numeric attribute of an XML element
+///
+/// # Arguments
+///
+/// * `element` - Text of the start tag of the element.
+/// * `name` - Name of the attribute.
+fn attribute(element: &str, name: &str) -> Option<f64> {
+    let pattern = format!("{}=", name);
+    let start = element
+        .match_indices(&pattern)
+        .find(|(index, _)| {
+            element[..*index]
+                .chars()
+                .last()
+                .is_some_and(char::is_whitespace)
+        })
+        .map(|(index, _)| index + pattern.len())?;
+    let value = &element[start..];
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &value[1..];
+    value[..value.find(quote)?].trim().parse().ok()
+}
+
+/// Returns the track and route points of a GPX file
+///
+/// # Arguments
+///
+/// * `path` - Path of the GPX file.
+fn read_gpx_track(path: &Path) -> Result<Vec<Position>> {
+    let content = fs::read_to_string(path).map_err(|err| {
+        let msg = format!("Unable to read route '{}'", path.display());
+        ArchiverError::io(&msg, err)
+    })?;
+
+    let mut track = Vec::new();
+    for (index, _) in content.match_indices('<') {
+        let element = &content[index + 1..];
+        if !element.starts_with("trkpt") && !element.starts_with("rtept") {
+            continue;
+        }
+        let element = &element[..element.find('>').unwrap_or(element.len())];
+        if let (Some(latitude), Some(longitude)) =
+            (attribute(element, "lat"), attribute(element, "lon"))
+        {
+            add_track_point(&mut track, (latitude, longitude));
+        }
+    }
+    Ok(track)
+}
+
+/// Returns the points of the reference routes
+///
+/// GPX files are recognized by their extension, all other files are parsed as FIT courses.
+///
+/// # Arguments
+///
+/// * `routes` - Reference routes registered by the user.
+pub(crate) fn load_routes(routes: &[Route]) -> Result<Vec<RouteTrack>> {
+    routes
+        .iter()
+        .map(|route| {
+            let is_gpx = route
+                .path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("gpx"));
+            let track = match is_gpx {
+                true => read_gpx_track(&route.path)?,
+                false => parse_fit_file(&route.path)?.track,
+            };
+            if track.is_empty() {
+                let msg = format!("Route '{}' contains no positions", route.path.display());
+                return Err(ArchiverError::new(&msg));
+            }
+            Ok(RouteTrack {
+                name: route.name.clone(),
+                track,
+            })
+        })
+        .collect()
+}
+
+/// Returns the share of the points of a track within the tolerance of a reference track
+///
+/// The comparison stops as soon as the share cannot reach the minimum overlap anymore.
+///
+/// # Arguments
+///
+/// * `track` - Track to check.
+/// * `reference` - Reference track.
+/// * `tolerance` - Maximum distance of a point to the reference in meters.
+fn coverage(track: &[Position], reference: &[Position], tolerance: f64) -> f64 {
+    let allowed_misses = ((1.0 - MIN_OVERLAP) * track.len() as f64).floor() as usize;
+    let mut misses = 0;
+    for position in track {
+        if !reference
+            .iter()
+            .any(|point| distance(*point, *position) <= tolerance)
+        {
+            misses += 1;
+            if misses > allowed_misses {
+                return 0.0;
+            }
+        }
+    }
+    1.0 - misses as f64 / track.len() as f64
+}
+
+/// Returns the name of the route an activity follows or 'unknown'
+///
+/// The route with the largest overlap is selected if several routes match.
+///
+/// # Arguments
+///
+/// * `track` - Track of the activity.
+/// * `routes` - Points of the reference routes.
+/// * `tolerance` - Maximum distance of the track to the route in meters.
+pub(crate) fn match_route(track: &[Position], routes: &[RouteTrack], tolerance: f64) -> String {
+    if track.is_empty() {
+        return String::from("unknown");
+    }
+    routes
+        .iter()
+        .map(|route| {
+            let overlap = coverage(track, &route.track, tolerance);
+            let overlap = match overlap >= MIN_OVERLAP {
+                true => overlap.min(coverage(&route.track, track, tolerance)),
+                false => overlap,
+            };
+            (route, overlap)
+        })
+        .filter(|(_, overlap)| *overlap >= MIN_OVERLAP)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or_else(|| String::from("unknown"), |(route, _)| route.name.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Position;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    /// Returns a straight track heading north with a point every 10 meters
+    fn north(start: Position, meters: usize) -> Vec<Position> {
+        let mut track = Vec::new();
+        for step in 0..=meters / 10 {
+            let latitude = start.0 + step as f64 * 10.0 / 111_195.0;
+            super::add_track_point(&mut track, (latitude, start.1));
+        }
+        track
+    }
+
+    #[test]
+    /// Test matching activities against reference routes
+    fn test_match_route() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let gpx = r#"<?xml version="1.0"?>
+<gpx version="1.1"><rte><name>park</name>
+<rtept lat="48.0" lon='11.5'><ele>500</ele></rtept>
+<rtept lon="11.5" lat="48.009"></rtept>
+<wpt lat="49.0" lon="12.0"></wpt>
+</rte></gpx>"#;
+        let path = tmpdir.path().join("park.GPX");
+        fs::write(&path, gpx).unwrap();
+        let track = super::read_gpx_track(&path).unwrap();
+        assert_eq!(vec![(48.0, 11.5), (48.009, 11.5)], track);
+
+        "park".parse::<super::Route>().expect_err("error expected");
+        let routes = super::load_routes(
+            &[
+                "park=".to_string() + path.to_str().unwrap(),
+                "test=".to_string()
+                    + PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                        .join("test")
+                        .join("test_data_01.fit")
+                        .to_str()
+                        .unwrap(),
+            ]
+            .map(|route| route.parse().unwrap()),
+        )
+        .unwrap();
+        let routes = vec![super::RouteTrack {
+            track: north((48.0, 11.5), 1000),
+            ..routes[0].clone()
+        }];
+
+        // the direction and small deviations do not matter
+        let mut track = north((48.0, 11.50002), 1000);
+        assert_eq!("park", super::match_route(&track, &routes, 50.0));
+        track.reverse();
+        assert_eq!("park", super::match_route(&track, &routes, 50.0));
+        // part of the route or a longer activity is another route
+        let track = north((48.0, 11.5), 500);
+        assert_eq!("unknown", super::match_route(&track, &routes, 50.0));
+        let track = north((47.99, 11.5), 2000);
+        assert_eq!("unknown", super::match_route(&track, &routes, 50.0));
+        assert_eq!("unknown", super::match_route(&[], &routes, 50.0));
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
@@ -0,0 +1,220 @@
+//! # Signatures of the checksum files
+//!
+//! The checksum files and the manifest of an archive are signed with an Ed25519 key of
+//! `minisign` after each run. Unlike the checksums alone, the signatures detect a tampered
+//! archive where files and their checksums were changed together. The signatures are written
+//! next to the signed files with the extension `.minisig` and can be checked with `minisign -V`
+//! as well. The secret key must not have a password, so archiving runs unattended, i.e. created
+//! with `minisign -G -W`.
+
+use crate::checksum::find_checksum_files;
+use crate::fitarchiver::{ArchiverError, Result};
+use crate::manifest::MANIFEST_PATH;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Extension of the signature files
+pub const SIGNATURE_EXTENSION: &str = "minisig";
+
+/// Returns the path of the signature of a file, i.e. 'SHA256SUMS.minisig'
+///
+/// # Arguments
+///
+/// * `path` - Path of the signed file.
+fn signature_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(SIGNATURE_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Returns the files of an archive protected by signatures, the checksum files and the manifest
+///
+/// # Arguments
+///
+/// * `directory` - Base directory of the archive.
+fn signed_files(directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = find_checksum_files(directory)?;
+    let manifest = directory.join(MANIFEST_PATH);
+    if manifest.is_file() {
+        files.push(manifest);
+    }
+    Ok(files)
+}
+
+/// Returns true if the signature of a file is missing or older than the file
+///
+/// # Arguments
+///
+/// * `path` - Path of the signed file.
+fn needs_signature(path: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    match (modified(path), modified(&signature_path(path))) {
+        (Ok(file), Ok(signature)) => signature < file,
+        _ => true,
+    }
+}
+
+/// Returns the arguments of minisign signing files
+///
+/// # Arguments
+///
+/// * `secret_key` - Path of the secret key.
+/// * `files` - Paths of the files to sign.
+fn sign_arguments(secret_key: &Path, files: &[PathBuf]) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec!["-S".into(), "-s".into(), secret_key.into()];
+    args.push("-t".into());
+    args.push("signed by fitarchiver".into());
+    args.push("-m".into());
+    args.extend(files.iter().map(|path| path.into()));
+    args
+}
+
+/// Run minisign
+///
+/// # Arguments
+///
+/// * `args` - Arguments of minisign.
+fn minisign(args: &[OsString]) -> Result<()> {
+    match Command::new("minisign").args(args).output() {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => {
+            let msg = format!(
+                "Unable to run 'minisign': {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            Err(ArchiverError::new(&msg))
+        }
+        Err(err) => Err(ArchiverError::io("Unable to run 'minisign'", err)),
+    }
+}
+
+/// Sign the checksum files and the manifest of an archive changed since they were signed
+///
+/// Returns the number of signed files.
+///
+/// # Arguments
+///
+/// * `directory` - Base directory of the archive.
+/// * `secret_key` - Path of the secret key of minisign.
+pub(crate) fn sign_archive(directory: &Path, secret_key: &Path) -> Result<usize> {
+    let files: Vec<PathBuf> = signed_files(directory)?
+        .into_iter()
+        .filter(|path| needs_signature(path))
+        .collect();
+    if !files.is_empty() {
+        minisign(&sign_arguments(secret_key, &files))?;
+    }
+    Ok(files.len())
+}
+
+/// Signatures of an archive checked by verify
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct SignatureCheck {
+    /// Files with a valid signature
+    pub(crate) valid: usize,
+    /// Files whose signature does not match, i.e. tampered files
+    pub(crate) invalid: Vec<PathBuf>,
+    /// Files without signature
+    pub(crate) unsigned: Vec<PathBuf>,
+}
+
+/// Check the signatures of the checksum files and the manifest of an archive
+///
+/// # Arguments
+///
+/// * `directory` - Base directory of the archive.
+/// * `public_key` - Path of the public key of minisign.
+pub(crate) fn verify_signatures(directory: &Path, public_key: &Path) -> Result<SignatureCheck> {
+    let mut check = SignatureCheck::default();
+    for path in signed_files(directory)? {
+        if !signature_path(&path).is_file() {
+            check.unsigned.push(path);
+            continue;
+        }
+        let args: Vec<OsString> = vec![
+            "-V".into(),
+            "-q".into(),
+            "-p".into(),
+            public_key.into(),
+            "-m".into(),
+            path.clone().into(),
+        ];
+        match minisign(&args) {
+            Ok(()) => check.valid += 1,
+            // a missing minisign or key is no reason to suspect the archive
+            Err(err @ ArchiverError::Io { .. }) => return Err(err),
+            Err(_) if !public_key.is_file() => {
+                let msg = format!("Unable to read public key '{}'", public_key.display());
+                return Err(ArchiverError::new(&msg));
+            }
+            Err(_) => check.invalid.push(path),
+        }
+    }
+    Ok(check)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test selecting the files to sign
+    fn test_needs_signature() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let directory = tmpdir.path().join("2023/07");
+        fs::create_dir_all(&directory).unwrap();
+        let checksums = directory.join(crate::CHECKSUM_FILE);
+        fs::write(&checksums, "0123  run.fit\n").unwrap();
+        fs::write(tmpdir.path().join(crate::MANIFEST_PATH), "date\n").unwrap();
+
+        assert_eq!(
+            vec![checksums.clone(), tmpdir.path().join(crate::MANIFEST_PATH)],
+            super::signed_files(tmpdir.path()).unwrap()
+        );
+        assert!(super::needs_signature(&checksums));
+
+        let signature = directory.join("SHA256SUMS.minisig");
+        assert_eq!(signature, super::signature_path(&checksums));
+        fs::write(&signature, "untrusted comment").unwrap();
+        let now = SystemTime::now();
+        fs::File::options()
+            .write(true)
+            .open(&checksums)
+            .unwrap()
+            .set_modified(now - Duration::from_secs(60))
+            .unwrap();
+        assert!(!super::needs_signature(&checksums));
+
+        // new checksums invalidate the signature
+        fs::File::options()
+            .write(true)
+            .open(&checksums)
+            .unwrap()
+            .set_modified(now + Duration::from_secs(60))
+            .unwrap();
+        assert!(super::needs_signature(&checksums));
+
+        assert_eq!(
+            vec![
+                "-S",
+                "-s",
+                "archive.key",
+                "-t",
+                "signed by fitarchiver",
+                "-m",
+                "a/SHA256SUMS",
+                "MANIFEST.tsv"
+            ],
+            super::sign_arguments(
+                &PathBuf::from("archive.key"),
+                &[PathBuf::from("a/SHA256SUMS"), PathBuf::from("MANIFEST.tsv")]
+            )
+        );
+    }
+}
@@ -0,0 +1,396 @@
+//! # Names of sports unknown to the FIT file parser
+//!
+//! The FIT profile of the parser lags behind the firmware of new devices. Sports and sub sports
+//! added in later FIT SDK releases are reported as numeric codes. The tables below map these
+//! codes to the names used by the FIT SDK. New codes are added at the end of the tables, entries
+//! can be removed once the parser knows them.
+//!
+//! Short names of common sports and the naming strategies of multisport activities keep the
+//! names of multisport activities compact.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use fitparser::profile::field_types::{Sport, SubSport};
+use std::str::FromStr;
+
+/// Naming strategy of the sport of multisport activities
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MultisportNaming {
+    /// All sports joined, i.e. 'multisport_swimming_transition_cycling_transition_running'
+    #[default]
+    Joined,
+    /// Fixed label 'multisport'
+    Fixed,
+    /// Sport of the first leg, i.e. 'swimming'
+    First,
+    /// Name of the race detected from the legs, i.e. 'triathlon', or all sports joined
+    Detect,
+}
+
+impl FromStr for MultisportNaming {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "joined" => Ok(MultisportNaming::Joined),
+            "fixed" => Ok(MultisportNaming::Fixed),
+            "first" => Ok(MultisportNaming::First),
+            "detect" => Ok(MultisportNaming::Detect),
+            _ => {
+                let msg = format!(
+                    "Invalid multisport naming '{}', expected 'joined', 'fixed', 'first' or 'detect'",
+                    s
+                );
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+/// Directory of the activities of a sport used with templates without sport tags
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SportDirectory {
+    /// Sport of the activities, i.e. 'swimming'
+    pub sport: String,
+    /// Subsport of the activities, i.e. 'lap_swimming', `None` for all subsports
+    pub sub_sport: Option<String>,
+    /// Directory relative to the archive directory, i.e. 'pool'
+    pub directory: String,
+}
+
+impl FromStr for SportDirectory {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            let msg = format!(
+                "Invalid sport directory '{}', expected '<sport>[:<subsport>]=<directory>'",
+                s
+            );
+            ArchiverError::new(&msg)
+        };
+
+        let (sport, directory) = s.split_once('=').ok_or_else(invalid)?;
+        let (sport, sub_sport) = match sport.split_once(':') {
+            Some((sport, sub_sport)) => (sport.trim(), Some(sub_sport.trim())),
+            None => (sport.trim(), None),
+        };
+        let directory = directory.trim().trim_matches('/');
+        // the directory must stay within the archive
+        if sport.is_empty()
+            || sub_sport.is_some_and(|sub_sport| sub_sport.is_empty())
+            || directory.is_empty()
+            || directory
+                .split('/')
+                .any(|component| component.is_empty() || component == "." || component == "..")
+        {
+            return Err(invalid());
+        }
+
+        Ok(SportDirectory {
+            sport: sport.to_string(),
+            sub_sport: sub_sport.map(String::from),
+            directory: directory.to_string(),
+        })
+    }
+}
+
+/// Returns the directory of the sport of an activity
+///
+/// A directory given for the subsport takes precedence over the one given for the sport.
+///
+/// # Arguments
+///
+/// * `sport` - Sport of the activity.
+/// * `sub_sport` - Subsport of the activity.
+/// * `directories` - Directories of the sports.
+pub(crate) fn sport_directory<'a>(
+    sport: &str,
+    sub_sport: &str,
+    directories: &'a [SportDirectory],
+) -> Option<&'a str> {
+    directories
+        .iter()
+        .filter(|directory| directory.sport == sport)
+        .find(|directory| directory.sub_sport.as_deref() == Some(sub_sport))
+        .or_else(|| {
+            directories
+                .iter()
+                .find(|directory| directory.sport == sport && directory.sub_sport.is_none())
+        })
+        .map(|directory| directory.directory.as_str())
+}
+
+/// Races detected from the sports of their legs
+const RACES: &[(&[&str], &str)] = &[
+    (&["swimming", "cycling", "running"], "triathlon"),
+    (&["running", "cycling", "running"], "duathlon"),
+    (&["swimming", "running"], "aquathlon"),
+    (&["cycling", "running"], "bike_run"),
+    (&["swimming", "cycling"], "aquabike"),
+];
+
+/// Sports missing in the profile of the FIT file parser
+const SPORTS: &[(u8, &str)] = &[
+    (56, "shooting"),
+    (58, "winter_sport"),
+    (59, "grinding"),
+    (63, "video_gaming"),
+    (68, "para_sport"),
+    (70, "team_sport"),
+    (78, "water_sport"),
+    (79, "archery"),
+    (81, "motor_sports"),
+    (85, "pool_apnea"),
+    (86, "mobility"),
+    (87, "geocaching"),
+    (88, "canoeing"),
+];
+
+/// Sub sports missing in the profile of the FIT file parser
+const SUB_SPORTS: &[(u8, &str)] = &[
+    (63, "ccr_diving"),
+    (66, "expedition"),
+    (71, "indoor_grinding"),
+    (72, "hunting_with_dogs"),
+    (77, "esport"),
+    (78, "triathlon"),
+    (79, "duathlon"),
+    (80, "brick"),
+    (81, "swim_run"),
+    (82, "adventure_race"),
+    (83, "trucker_workout"),
+    (90, "field"),
+    (91, "ice"),
+    (92, "ultimate"),
+    (93, "platform"),
+    (98, "overland"),
+    (99, "trolling_motor"),
+    (121, "dynamic_apnea"),
+    (123, "enduro"),
+    (124, "rucking"),
+    (125, "rally"),
+    (126, "pool_triathlon"),
+    (127, "e_bike_enduro"),
+];
+
+/// Short names of common sports
+const SHORT_NAMES: &[(&str, &str)] = &[
+    ("swimming", "swim"),
+    ("cycling", "bike"),
+    ("running", "run"),
+    ("walking", "walk"),
+    ("hiking", "hike"),
+    ("rowing", "row"),
+    ("paddling", "paddle"),
+    ("kayaking", "kayak"),
+    ("stand_up_paddleboarding", "sup"),
+    ("cross_country_skiing", "xc_ski"),
+    ("alpine_skiing", "ski"),
+    ("e_biking", "ebike"),
+    ("mountaineering", "climb"),
+];
+
+/// Returns the name of a code from a table
+///
+/// # Arguments
+///
+/// * `table` - Table of codes and names.
+/// * `code` - Numeric code reported by the parser.
+fn lookup_name(table: &[(u8, &'static str)], code: i64) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(table_code, _)| i64::from(*table_code) == code)
+        .map(|(_, name)| *name)
+}
+
+/// Returns the code of a name from a table
+///
+/// # Arguments
+///
+/// * `table` - Table of codes and names.
+/// * `name` - Name of the sport or sub sport.
+fn lookup_code(table: &[(u8, &str)], name: &str) -> Option<u8> {
+    table
+        .iter()
+        .find(|(_, table_name)| *table_name == name)
+        .map(|(code, _)| *code)
+}
+
+/// Returns the name of a sport unknown to the parser
+///
+/// # Arguments
+///
+/// * `code` - Numeric code of the sport.
+pub(crate) fn sport_name(code: i64) -> Option<&'static str> {
+    lookup_name(SPORTS, code)
+}
+
+/// Returns the name of a sub sport unknown to the parser
+///
+/// # Arguments
+///
+/// * `code` - Numeric code of the sub sport.
+pub(crate) fn sub_sport_name(code: i64) -> Option<&'static str> {
+    lookup_name(SUB_SPORTS, code)
+}
+
+/// Returns the short name of a sport or the name itself if it has no short name
+///
+/// # Arguments
+///
+/// * `name` - Name of the sport, i.e. 'cycling'.
+pub(crate) fn short_sport_name(name: &str) -> &str {
+    SHORT_NAMES
+        .iter()
+        .find(|(long, _)| *long == name)
+        .map(|(_, short)| *short)
+        .unwrap_or(name)
+}
+
+/// Returns the sport of a multisport activity according to the naming strategy
+///
+/// Returns `None` if the joined sports are kept.
+///
+/// # Arguments
+///
+/// * `legs` - Sports of the legs in order without transitions.
+/// * `naming` - Naming strategy.
+pub(crate) fn multisport_name(legs: &[String], naming: MultisportNaming) -> Option<String> {
+    match naming {
+        MultisportNaming::Joined => None,
+        MultisportNaming::Fixed => Some(String::from("multisport")),
+        MultisportNaming::First => legs.first().cloned(),
+        MultisportNaming::Detect => RACES
+            .iter()
+            .find(|(pattern, _)| pattern.iter().eq(legs.iter()))
+            .map(|(_, race)| race.to_string()),
+    }
+}
+
+/// Returns the numeric code of a sport
+///
+/// # Arguments
+///
+/// * `name` - Name of the sport, i.e. 'running'.
+pub(crate) fn sport_code(name: &str) -> Option<u8> {
+    match Sport::from(name) {
+        Sport::UnknownVariant(_) => lookup_code(SPORTS, name),
+        sport => Some(sport.as_u8()),
+    }
+}
+
+/// Returns the numeric code of a sub sport
+///
+/// # Arguments
+///
+/// * `name` - Name of the sub sport, i.e. 'trail'.
+pub(crate) fn sub_sport_code(name: &str) -> Option<u8> {
+    match SubSport::from(name) {
+        SubSport::UnknownVariant(_) => lookup_code(SUB_SPORTS, name),
+        sub_sport => Some(sub_sport.as_u8()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fitparser::profile::field_types::{Sport, SubSport};
+
+    #[test]
+    /// Test parsing and selecting the directories of sports
+    fn test_sport_directory() {
+        let directories: Vec<super::SportDirectory> = [
+            "swimming=swim",
+            "swimming:lap_swimming=swim/pool/",
+            "cycling:indoor_cycling = trainer",
+        ]
+        .iter()
+        .map(|directory| directory.parse().unwrap())
+        .collect();
+        assert_eq!(
+            Some("swim/pool"),
+            super::sport_directory("swimming", "lap_swimming", &directories)
+        );
+        assert_eq!(
+            Some("swim"),
+            super::sport_directory("swimming", "open_water", &directories)
+        );
+        assert_eq!(
+            Some("trainer"),
+            super::sport_directory("cycling", "indoor_cycling", &directories)
+        );
+        assert_eq!(
+            None,
+            super::sport_directory("cycling", "road", &directories)
+        );
+
+        for directory in [
+            "swimming",
+            "=swim",
+            "swimming:=swim",
+            "swimming=",
+            "running=../run",
+        ] {
+            directory
+                .parse::<super::SportDirectory>()
+                .expect_err("error expected");
+        }
+    }
+
+    #[test]
+    /// Test mapping of codes unknown to the parser
+    fn test_sport_names() {
+        assert_eq!(Some("canoeing"), super::sport_name(88));
+        assert_eq!(Some("rucking"), super::sub_sport_name(124));
+        assert_eq!("bike", super::short_sport_name("cycling"));
+        assert_eq!("canoeing", super::short_sport_name("canoeing"));
+        assert_eq!(None, super::sport_name(1));
+        assert_eq!(None, super::sport_name(200));
+
+        assert_eq!(Some(1), super::sport_code("running"));
+        assert_eq!(Some(88), super::sport_code("canoeing"));
+        assert_eq!(Some(3), super::sub_sport_code("trail"));
+        assert_eq!(Some(124), super::sub_sport_code("rucking"));
+        assert_eq!(None, super::sport_code("unknown"));
+    }
+
+    #[test]
+    /// Test naming strategies of multisport activities
+    fn test_multisport_name() {
+        let legs: Vec<String> = ["swimming", "cycling", "running"]
+            .iter()
+            .map(|sport| sport.to_string())
+            .collect();
+        let name = |naming: &str| super::multisport_name(&legs, naming.parse().unwrap());
+
+        assert_eq!(None, name("joined"));
+        assert_eq!(Some(String::from("multisport")), name("fixed"));
+        assert_eq!(Some(String::from("swimming")), name("first"));
+        assert_eq!(Some(String::from("triathlon")), name("detect"));
+        assert_eq!(
+            None,
+            super::multisport_name(&legs[1..2], super::MultisportNaming::Detect)
+        );
+        "longest"
+            .parse::<super::MultisportNaming>()
+            .expect_err("error expected");
+    }
+
+    #[test]
+    /// Test that the tables contain only codes unknown to the parser
+    fn test_sport_tables() {
+        for (code, name) in super::SPORTS {
+            assert!(
+                matches!(Sport::from(*code), Sport::UnknownVariant(_)),
+                "{}",
+                name
+            );
+        }
+        for (code, name) in super::SUB_SPORTS {
+            assert!(
+                matches!(SubSport::from(*code), SubSport::UnknownVariant(_)),
+                "{}",
+                name
+            );
+        }
+    }
+}
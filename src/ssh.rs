@@ -0,0 +1,218 @@
+//! # Archive directories on SSH servers
+//!
+//! The archive directory 'sftp://[user@]host[:port]/path' is accessed with the `ssh` command, so
+//! the host names, keys and connection sharing of the SSH configuration apply. The server needs a
+//! POSIX shell with `sha256sum` and `stat`. Passwords cannot be entered, the authentication has to
+//! work without interaction, i.e. by a key loaded into the SSH agent.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use crate::remote::Remote;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, SystemTime};
+
+/// Archive directory on a host accessed by SSH
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SshRemote {
+    /// Destination passed to ssh, the host name with an optional user
+    destination: String,
+    /// Port of the SSH server if not the default one
+    port: Option<u16>,
+    /// Path of the archive directory on the host
+    directory: PathBuf,
+}
+
+/// Returns a path quoted for the POSIX shell
+///
+/// # Arguments
+///
+/// * `path` - Path to quote.
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"))
+}
+
+impl SshRemote {
+    /// Returns the archive directory of an 'sftp://[user@]host[:port]/path' URL
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - URL of the archive directory.
+    pub(crate) fn parse(url: &str) -> Result<SshRemote> {
+        let msg = format!(
+            "Invalid archive directory '{}', expected 'sftp://[user@]host[:port]/path'",
+            url
+        );
+        let Some((authority, path)) = url
+            .strip_prefix("sftp://")
+            .and_then(|rest| rest.split_once('/'))
+        else {
+            return Err(ArchiverError::new(&msg));
+        };
+        let (destination, port) = match authority.rsplit_once(':') {
+            Some((destination, port)) => match port.parse::<u16>() {
+                Ok(port) => (destination, Some(port)),
+                Err(_) => return Err(ArchiverError::new(&msg)),
+            },
+            None => (authority, None),
+        };
+        let host = destination.rsplit('@').next().unwrap_or_default();
+        if host.is_empty() || host.starts_with('-') {
+            return Err(ArchiverError::new(&msg));
+        }
+
+        Ok(SshRemote {
+            destination: destination.to_string(),
+            port,
+            directory: Path::new("/").join(path),
+        })
+    }
+
+    /// Run a shell command on the host
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - Shell command with quoted arguments.
+    /// * `input` - File passed as standard input of the command.
+    fn run(&self, command: &str, input: Option<File>) -> Result<Output> {
+        let mut ssh = Command::new("ssh");
+        // a password prompt would block the archiver
+        ssh.args(["-o", "BatchMode=yes"]);
+        if let Some(port) = self.port {
+            ssh.arg("-p").arg(port.to_string());
+        }
+        ssh.arg("--").arg(&self.destination).arg(command);
+        ssh.stdin(input.map_or_else(Stdio::null, Stdio::from));
+
+        let output = ssh.output().map_err(|err| {
+            let msg = format!("Unable to run ssh for '{}'", self.destination);
+            ArchiverError::io(&msg, err)
+        })?;
+        // ssh reports its own errors with the exit code 255
+        if output.status.code() == Some(255) {
+            let msg = format!(
+                "Unable to connect to '{}': {}",
+                self.destination,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return Err(ArchiverError::new(&msg));
+        }
+        Ok(output)
+    }
+
+    /// Run a shell command on the host and return its standard output
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - Shell command with quoted arguments.
+    /// * `input` - File passed as standard input of the command.
+    /// * `msg` - Error message if the command fails.
+    fn check(&self, command: &str, input: Option<File>, msg: &str) -> Result<String> {
+        let output = self.run(command, input)?;
+        if !output.status.success() {
+            let msg = format!(
+                "{} on '{}': {}",
+                msg,
+                self.destination,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return Err(ArchiverError::new(&msg));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+}
+
+impl Remote for SshRemote {
+    fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool> {
+        let output = self.run(&format!("test -e {}", quote(path)), None)?;
+        Ok(output.status.success())
+    }
+
+    fn file_hash(&self, path: &Path) -> Result<String> {
+        let msg = format!("Unable to read file '{}'", path.display());
+        let output = self.check(&format!("sha256sum -- {}", quote(path)), None, &msg)?;
+        match output.split_whitespace().next() {
+            Some(hash) => Ok(hash.to_string()),
+            None => Err(ArchiverError::new(&msg)),
+        }
+    }
+
+    fn modified(&self, path: &Path) -> Result<SystemTime> {
+        let msg = format!("Unable to read file '{}'", path.display());
+        let output = self.check(&format!("stat -c %Y -- {}", quote(path)), None, &msg)?;
+        match output.trim().parse::<u64>() {
+            Ok(seconds) => Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)),
+            Err(_) => Err(ArchiverError::new(&msg)),
+        }
+    }
+
+    fn create_directory(&self, path: &Path) -> Result<()> {
+        let msg = format!("Unable to create directory '{}'", path.display());
+        self.check(&format!("mkdir -p -- {}", quote(path)), None, &msg)
+            .map(|_| ())
+    }
+
+    fn upload(&self, source_path: &Path, path: &Path) -> Result<()> {
+        let msg = format!(
+            "Unable to copy '{}' to '{}'",
+            source_path.display(),
+            path.display()
+        );
+        let file = File::open(source_path).map_err(|err| ArchiverError::io(&msg, err))?;
+
+        // written under a temporary name, so an interrupted transfer never leaves a partial file
+        let mut temporary_path = path.as_os_str().to_owned();
+        temporary_path.push(".fitarchiver");
+        let temporary_path = PathBuf::from(temporary_path);
+        let command = format!(
+            "cat > {temporary} && mv -f -- {temporary} {path}",
+            temporary = quote(&temporary_path),
+            path = quote(path)
+        );
+        self.check(&command, Some(file), &msg).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    /// Test parsing the URLs of archive directories on SSH servers
+    fn test_parse() {
+        assert_eq!(
+            super::SshRemote {
+                destination: String::from("pi@nas.local"),
+                port: Some(2222),
+                directory: PathBuf::from("/srv/activities"),
+            },
+            super::SshRemote::parse("sftp://pi@nas.local:2222/srv/activities").unwrap()
+        );
+        assert_eq!(
+            super::SshRemote {
+                destination: String::from("nas"),
+                port: None,
+                directory: PathBuf::from("/"),
+            },
+            super::SshRemote::parse("sftp://nas/").unwrap()
+        );
+        for url in [
+            "sftp://nas",
+            "sftp:///srv",
+            "sftp://pi@/srv",
+            "sftp://nas:ssh/srv",
+            "sftp://-oProxyCommand=x/srv",
+        ] {
+            super::SshRemote::parse(url).expect_err("error expected");
+        }
+
+        assert_eq!(
+            "'/srv/Bob'\\''s runs'",
+            super::quote(Path::new("/srv/Bob's runs"))
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! # Device state files
+//!
+//! Garmin devices keep their lifetime statistics and configuration in FIT files, i.e.
+//! `GARMIN/Totals/totals.fit` with the sessions, time and distance of all activities per sport.
+//! These files are rewritten by the device instead of recording an activity, so they are
+//! archived as snapshots per device and day apart from the activities.
+
+use crate::fitarchiver::ActivityData;
+
+/// Default template of the archive paths of device state files
+pub const DEFAULT_DEVICE_STATE_TEMPLATE: &str = "device-state/$D/%Y-%m-%d-$f";
+
+/// File types of device state files, i.e. 'totals' for the lifetime totals per sport
+const DEVICE_STATE_TYPES: [&str; 4] = ["totals", "goals", "device", "settings"];
+
+/// Returns true if a file is a device state file instead of an activity
+///
+/// # Arguments
+///
+/// * `activity_data` - Data of the file.
+pub(crate) fn is_device_state(activity_data: &ActivityData) -> bool {
+    DEVICE_STATE_TYPES.contains(&activity_data.file_type.as_str())
+}
+
+/// Returns the device of a file, the product followed by the serial number if known
+///
+/// Two devices of the same model are told apart by the serial number, i.e. 'fenix7-3345678901'.
+///
+/// # Arguments
+///
+/// * `activity_data` - Data of the file.
+pub(crate) fn device_name(activity_data: &ActivityData) -> String {
+    match activity_data.serial_number {
+        Some(serial_number) => format!("{}-{}", activity_data.product, serial_number),
+        None => activity_data.product.clone(),
+    }
+}
+
+/// Write a totals file of a device with the lifetime totals of running and cycling
+///
+/// # Arguments
+///
+/// * `path` - Path of the FIT file.
+#[cfg(test)]
+pub(crate) fn write_totals_file(path: &std::path::Path) {
+    /// Returns the CRC of FIT files
+    fn crc(data: &[u8]) -> u16 {
+        const TABLE: [u16; 16] = [
+            0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800,
+            0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
+        ];
+        data.iter().fold(0, |crc, byte| {
+            let crc = (crc >> 4) ^ TABLE[usize::from(crc & 0xF)] ^ TABLE[usize::from(byte & 0xF)];
+            (crc >> 4) ^ TABLE[usize::from(crc & 0xF)] ^ TABLE[usize::from(byte >> 4)]
+        })
+    }
+
+    // seconds between 1970-01-01 and the FIT epoch 1989-12-31
+    const FIT_EPOCH: u32 = 631_065_600;
+    let time_created = 1_700_000_000 - FIT_EPOCH;
+    let timestamp = 1_720_000_000 - FIT_EPOCH;
+
+    let mut data = Vec::new();
+    // file id: type, manufacturer, product, serial number, time created
+    data.extend([0x40, 0, 0, 0, 0, 5]);
+    data.extend([0, 1, 0x00, 1, 2, 0x84, 2, 2, 0x84, 3, 4, 0x8C, 4, 4, 0x86]);
+    data.extend([0x00, 10]);
+    data.extend(1u16.to_le_bytes());
+    data.extend(3906u16.to_le_bytes());
+    data.extend(3_345_678_901u32.to_le_bytes());
+    data.extend(time_created.to_le_bytes());
+    // totals: timestamp, timer time, distance, calories, sport, sessions
+    data.extend([0x41, 0, 0, 33, 0, 6]);
+    data.extend([
+        253, 4, 0x86, 0, 4, 0x86, 1, 4, 0x86, 2, 4, 0x86, 3, 1, 0x00, 5, 2, 0x84,
+    ]);
+    for (sport, timer_time, distance, calories, sessions) in [
+        (1u8, 756_300u32, 1_834_215u32, 123_456u32, 152u16),
+        (2, 1_206_000, 8_412_900, 201_000, 97),
+    ] {
+        data.push(0x01);
+        data.extend(timestamp.to_le_bytes());
+        data.extend(timer_time.to_le_bytes());
+        data.extend(distance.to_le_bytes());
+        data.extend(calories.to_le_bytes());
+        data.push(sport);
+        data.extend(sessions.to_le_bytes());
+    }
+
+    let mut content = vec![14, 0x20];
+    content.extend(2132u16.to_le_bytes());
+    content.extend(u32::try_from(data.len()).unwrap().to_le_bytes());
+    content.extend(b".FIT");
+    content.extend(crc(&content).to_le_bytes());
+    content.extend(data);
+    content.extend(crc(&content).to_le_bytes());
+    std::fs::write(path, content).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test recognizing device state files
+    fn test_is_device_state() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let totals_path = tmpdir.path().join("totals.fit");
+        super::write_totals_file(&totals_path);
+
+        let activity_data = crate::fitarchiver::parse_fit_file(&totals_path).unwrap();
+        assert_eq!("totals", activity_data.file_type);
+        assert!(super::is_device_state(&activity_data));
+        assert_eq!("fenix7-3345678901", super::device_name(&activity_data));
+        // the snapshot is dated by the last update of the totals
+        assert_eq!(1_720_000_000, activity_data.timestamp.timestamp());
+
+        let activity_data = crate::fitarchiver::ActivityData {
+            file_type: String::from("activity"),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+        assert!(!super::is_device_state(&activity_data));
+        assert_eq!("unknown", super::device_name(&activity_data));
+    }
+}
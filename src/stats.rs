@@ -0,0 +1,364 @@
+//! # Statistics of an existing archive
+//!
+//! The index keeps the number, duration and distance of the activities per month and sport up
+//! to date on each run. The statistics are read from these rollups without parsing any file, so
+//! they are available instantly even for archives with thousands of activities. Archives without
+//! index are scanned by parsing all archive files.
+
+use crate::fitarchiver::{find_fit_files, parse_fit_file, ArchiverError, Result};
+use crate::index::{Index, MonthlyStats, INDEX_PATH};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Period the activities are grouped by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatsPeriod {
+    /// Calendar months (UTC), i.e. '2023-07'
+    #[default]
+    Month,
+    /// Calendar years (UTC), i.e. '2023'
+    Year,
+}
+
+impl FromStr for StatsPeriod {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "month" => Ok(StatsPeriod::Month),
+            "year" => Ok(StatsPeriod::Year),
+            _ => {
+                let msg = format!("Invalid period '{}', expected 'month' or 'year'", s);
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+/// Format of the statistics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatsFormat {
+    /// Table with a total line
+    #[default]
+    Text,
+    /// JSON array with one object per period and sport
+    Json,
+    /// CSV with a header line and one line per period and sport
+    Csv,
+}
+
+impl FromStr for StatsFormat {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(StatsFormat::Text),
+            "json" => Ok(StatsFormat::Json),
+            "csv" => Ok(StatsFormat::Csv),
+            _ => {
+                let msg = format!(
+                    "Invalid stats format '{}', expected 'text', 'json' or 'csv'",
+                    s
+                );
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+/// Options controlling the statistics of an archive
+#[derive(Debug, Clone)]
+pub struct StatsOptions {
+    /// Base directory of the archive
+    pub directory: PathBuf,
+    /// Period the activities are grouped by
+    pub period: StatsPeriod,
+    /// Format of the statistics
+    pub format: StatsFormat,
+    /// Parse the archive files instead of reading the index
+    pub scan: bool,
+}
+
+impl Default for StatsOptions {
+    fn default() -> Self {
+        StatsOptions {
+            directory: PathBuf::from("."),
+            period: StatsPeriod::Month,
+            format: StatsFormat::Text,
+            scan: false,
+        }
+    }
+}
+
+/// Returns the statistics of all months and sports of an archive by parsing its files
+///
+/// # Arguments
+///
+/// * `directory` - Base directory of the archive.
+fn scan_stats(directory: &Path) -> Result<Vec<MonthlyStats>> {
+    let mut stats: BTreeMap<(String, String), MonthlyStats> = BTreeMap::new();
+    for path in find_fit_files(directory)? {
+        let activity_data = match parse_fit_file(&path) {
+            Ok(val) => val,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+        let month = activity_data.timestamp.format("%Y-%m").to_string();
+        let row = stats
+            .entry((month.clone(), activity_data.sport.clone()))
+            .or_insert_with(|| MonthlyStats {
+                month,
+                sport: activity_data.sport.clone(),
+                count: 0,
+                duration: 0.0,
+                distance: 0.0,
+            });
+        row.count += 1;
+        row.duration += activity_data.duration.unwrap_or_default();
+        row.distance += activity_data.distance.unwrap_or_default();
+    }
+    Ok(stats.into_values().collect())
+}
+
+/// Returns the monthly statistics summed up per year and sport
+///
+/// # Arguments
+///
+/// * `stats` - Statistics ordered by month and sport.
+fn yearly_stats(stats: Vec<MonthlyStats>) -> Vec<MonthlyStats> {
+    let mut years: BTreeMap<(String, String), MonthlyStats> = BTreeMap::new();
+    for row in stats {
+        let year = row.month.chars().take(4).collect::<String>();
+        years
+            .entry((year.clone(), row.sport.clone()))
+            .and_modify(|total| {
+                total.count += row.count;
+                total.duration += row.duration;
+                total.distance += row.distance;
+            })
+            .or_insert(MonthlyStats { month: year, ..row });
+    }
+    years.into_values().collect()
+}
+
+/// Returns a duration in seconds as hours and minutes, i.e. '12:05'
+///
+/// # Arguments
+///
+/// * `seconds` - Duration in seconds.
+fn format_duration(seconds: f64) -> String {
+    let minutes = (seconds / 60.0).round() as u64;
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+/// Returns the statistics as table with one line per period and sport and a total line
+///
+/// # Arguments
+///
+/// * `stats` - Statistics ordered by period and sport.
+/// * `period` - Period of the statistics.
+fn format_table(stats: &[MonthlyStats], period: StatsPeriod) -> String {
+    let sport_width = stats
+        .iter()
+        .map(|row| row.sport.len())
+        .chain([5])
+        .max()
+        .unwrap_or_default();
+    let line = |month: &str, sport: &str, count: u32, duration: f64, distance: f64| {
+        format!(
+            "{:<7}  {:<sport_width$}  {:>10}  {:>9}  {:>13.1}",
+            month,
+            sport,
+            count,
+            format_duration(duration),
+            distance / 1000.0,
+        )
+    };
+
+    let period = match period {
+        StatsPeriod::Month => "Month",
+        StatsPeriod::Year => "Year",
+    };
+    let mut lines = vec![format!(
+        "{:<7}  {:<sport_width$}  {:>10}  {:>9}  {:>13}",
+        period, "Sport", "Activities", "Duration", "Distance (km)",
+    )];
+    lines.extend(stats.iter().map(|row| {
+        line(
+            &row.month,
+            &row.sport,
+            row.count,
+            row.duration,
+            row.distance,
+        )
+    }));
+    lines.push(line(
+        "Total",
+        "",
+        stats.iter().map(|row| row.count).sum(),
+        stats.iter().map(|row| row.duration).sum(),
+        stats.iter().map(|row| row.distance).sum(),
+    ));
+    lines.join("\n")
+}
+
+/// Returns the statistics as JSON array with one object per period and sport
+///
+/// # Arguments
+///
+/// * `stats` - Statistics ordered by period and sport.
+fn format_json(stats: &[MonthlyStats]) -> String {
+    json!(stats
+        .iter()
+        .map(|row| json!({
+            "period": row.month,
+            "sport": row.sport,
+            "count": row.count,
+            "duration": row.duration,
+            "distance": row.distance,
+        }))
+        .collect::<Vec<_>>())
+    .to_string()
+}
+
+/// Returns the statistics as CSV with a header line and one line per period and sport
+///
+/// # Arguments
+///
+/// * `stats` - Statistics ordered by period and sport.
+fn format_csv(stats: &[MonthlyStats]) -> String {
+    let mut lines = vec![String::from("period,sport,count,duration,distance")];
+    lines.extend(stats.iter().map(|row| {
+        format!(
+            "{},{},{},{:.0},{:.1}",
+            row.month, row.sport, row.count, row.duration, row.distance
+        )
+    }));
+    lines.join("\n")
+}
+
+/// Print the number, duration and distance of the activities per period and sport
+///
+/// The JSON and CSV formats are returned without summary, so they can be processed by other
+/// tools.
+///
+/// # Arguments
+///
+/// `options` - Stats options.
+pub fn stats(options: &StatsOptions) -> Result<String> {
+    let stats = if options.scan {
+        scan_stats(&options.directory)?
+    } else {
+        if !options.directory.join(INDEX_PATH).exists() {
+            let msg = format!(
+                "No index found in archive '{}', use '--scan' to parse all files",
+                options.directory.display()
+            );
+            return Err(ArchiverError::new(&msg));
+        }
+        // opened for writing to migrate indexes created by older versions
+        let Some(index) = Index::open(&options.directory, true)? else {
+            return Ok(String::new());
+        };
+        index.monthly_stats()?
+    };
+    let stats = match options.period {
+        StatsPeriod::Month => stats,
+        StatsPeriod::Year => yearly_stats(stats),
+    };
+
+    match options.format {
+        StatsFormat::Json => return Ok(format_json(&stats)),
+        StatsFormat::Csv => return Ok(format_csv(&stats)),
+        StatsFormat::Text => println!("{}", format_table(&stats, options.period)),
+    }
+    let periods = stats
+        .iter()
+        .map(|row| row.month.as_str())
+        .collect::<std::collections::HashSet<_>>()
+        .len();
+    let unit = match options.period {
+        StatsPeriod::Month => "months",
+        StatsPeriod::Year => "years",
+    };
+    Ok(format!(
+        "{} activities in {} {}",
+        stats.iter().map(|row| row.count).sum::<u32>(),
+        periods,
+        unit
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test printing the statistics of an archive
+    fn test_stats() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let mut options = super::StatsOptions {
+            directory: tmpdir.path().to_path_buf(),
+            ..Default::default()
+        };
+        super::stats(&options).expect_err("error expected");
+
+        let index = crate::index::Index::open(tmpdir.path(), true)
+            .unwrap()
+            .unwrap();
+        let activity = |month: u32, sport: &str| crate::fitarchiver::ActivityData {
+            timestamp: chrono::Utc
+                .with_ymd_and_hms(2023, month, 2, 6, 0, 0)
+                .unwrap(),
+            sport: String::from(sport),
+            duration: Some(1800.0),
+            distance: Some(5000.0),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+        index
+            .add(&tmpdir.path().join("a.fit"), &activity(7, "running"))
+            .unwrap();
+        index
+            .add(&tmpdir.path().join("b.fit"), &activity(7, "cycling"))
+            .unwrap();
+        index
+            .add(&tmpdir.path().join("c.fit"), &activity(8, "running"))
+            .unwrap();
+
+        assert_eq!("3 activities in 2 months", super::stats(&options).unwrap());
+        let table = super::format_table(&index.monthly_stats().unwrap(), options.period);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(5, lines.len());
+        assert_eq!(
+            "2023-07  cycling           1       0:30            5.0",
+            lines[1]
+        );
+        assert_eq!(
+            "Total                      3       1:30           15.0",
+            lines[4]
+        );
+
+        // summed up per year
+        options.period = super::StatsPeriod::Year;
+        assert_eq!("3 activities in 1 years", super::stats(&options).unwrap());
+        options.format = super::StatsFormat::Csv;
+        assert_eq!(
+            "period,sport,count,duration,distance\n2023,cycling,1,1800,5000.0\n2023,running,2,3600,10000.0",
+            super::stats(&options).unwrap()
+        );
+        options.format = super::StatsFormat::Json;
+        let json: serde_json::Value =
+            serde_json::from_str(&super::stats(&options).unwrap()).unwrap();
+        assert_eq!(Some(2), json[1]["count"].as_u64());
+        assert_eq!(Some("2023"), json[1]["period"].as_str());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
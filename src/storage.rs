@@ -0,0 +1,188 @@
+//! # Storage backends of the archive
+//!
+//! The archive files are written through a storage backend, so destinations other than a local
+//! directory are added without changing the processing of the files. The local backend writes
+//! into a directory of the file system and simulates dry runs in an overlay, the remote backend
+//! transfers the files to another host.
+
+use crate::destination::{check_free_space, wait_for_destination};
+use crate::fitarchiver::{
+    archive_file, create_archive_directory, simulate_archive_file, Options, Result, Status,
+};
+use crate::overlay::Overlay;
+use crate::remote::{archive_remote_file, open_remote, remote_options, Remote};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Destination the archive files are written to
+pub(crate) trait StorageBackend: Sync {
+    /// Check that the archive directory is available, waiting for it if requested
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Archiver options.
+    fn check_destination(&self, options: &Options) -> Result<()>;
+
+    /// Check that the files to archive fit into the archive directory
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Archiver options.
+    /// * `required` - Size of the files to archive in bytes.
+    fn check_free_space(&self, options: &Options, required: u64) -> Result<()>;
+
+    /// Returns true if independent files may be archived in parallel
+    fn is_parallel(&self) -> bool;
+
+    /// Create the directory of an archive file
+    ///
+    /// # Arguments
+    ///
+    /// * `archive_path` - Path to the archive file.
+    /// * `options` - Archiver options.
+    fn create_archive_directory(&self, archive_path: &Path, options: &Options) -> Result<()>;
+
+    /// Move or copy a file to the archive, a dry run only reports what would happen
+    ///
+    /// # Arguments
+    ///
+    /// * `source_path` - Path to the source file.
+    /// * `archive_path` - Path to the archive file.
+    /// * `options` - Archiver options.
+    fn archive_file(
+        &self,
+        source_path: &Path,
+        archive_path: &Path,
+        options: &Options,
+    ) -> Result<Status>;
+}
+
+/// Archive directory on the local file system
+#[derive(Debug, Default)]
+pub(crate) struct LocalStorage {
+    /// Directories and files planned by a dry run
+    overlay: Mutex<Overlay>,
+}
+
+impl StorageBackend for LocalStorage {
+    fn check_destination(&self, options: &Options) -> Result<()> {
+        wait_for_destination(&options.directory, options.require_mount, options.wait)
+    }
+
+    fn check_free_space(&self, options: &Options, required: u64) -> Result<()> {
+        check_free_space(&options.directory, required)
+    }
+
+    fn is_parallel(&self) -> bool {
+        true
+    }
+
+    fn create_archive_directory(&self, archive_path: &Path, options: &Options) -> Result<()> {
+        create_archive_directory(archive_path, options).map(|_| ())
+    }
+
+    fn archive_file(
+        &self,
+        source_path: &Path,
+        archive_path: &Path,
+        options: &Options,
+    ) -> Result<Status> {
+        if !options.dry_run {
+            return archive_file(source_path, archive_path, options);
+        }
+        let mut overlay = self.overlay.lock().unwrap_or_else(|err| err.into_inner());
+        simulate_archive_file(source_path, archive_path, options, &mut overlay)
+    }
+}
+
+/// Archive directory on another host
+pub(crate) struct RemoteStorage {
+    /// Access to the host
+    remote: Box<dyn Remote>,
+}
+
+impl StorageBackend for RemoteStorage {
+    fn check_destination(&self, _options: &Options) -> Result<()> {
+        Ok(())
+    }
+
+    fn check_free_space(&self, _options: &Options, _required: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn is_parallel(&self) -> bool {
+        false
+    }
+
+    fn create_archive_directory(&self, _archive_path: &Path, _options: &Options) -> Result<()> {
+        // the directories are created with the transfer, a dry run does not connect twice
+        Ok(())
+    }
+
+    fn archive_file(
+        &self,
+        source_path: &Path,
+        archive_path: &Path,
+        options: &Options,
+    ) -> Result<Status> {
+        archive_remote_file(self.remote.as_ref(), source_path, archive_path, options)
+    }
+}
+
+/// Returns the storage backend of the archive directory and the options adapted to it
+///
+/// # Arguments
+///
+/// * `options` - Archiver options with the archive directory as given by the user.
+pub(crate) fn open_storage(options: &Options) -> Result<(Box<dyn StorageBackend>, Options)> {
+    match open_remote(options)? {
+        Some(remote) => {
+            let options = remote_options(remote.as_ref(), options)?;
+            Ok((Box::new(RemoteStorage { remote }), options))
+        }
+        None => Ok((Box::new(LocalStorage::default()), options.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fitarchiver::{ConflictPolicy, Options, Status};
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test archiving files through the local storage backend
+    fn test_local_storage() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let source_path = tmpdir.path().join("source.fit");
+        let archive_path = tmpdir.path().join("archive/2023/run.fit");
+        fs::write(&source_path, "new").unwrap();
+
+        let mut options = Options {
+            directory: tmpdir.path().join("archive"),
+            on_conflict: ConflictPolicy::Skip,
+            dry_run: true,
+            ..Default::default()
+        };
+        let (storage, _) = super::open_storage(&options).unwrap();
+        assert!(storage.is_parallel());
+        let archive = |options: &Options| {
+            storage
+                .create_archive_directory(&archive_path, options)
+                .and_then(|_| storage.archive_file(&source_path, &archive_path, options))
+                .unwrap()
+        };
+
+        // a dry run remembers the files planned before
+        assert_eq!(Status::DryRun, archive(&options));
+        assert_eq!(Status::Identical, archive(&options));
+        assert!(!archive_path.exists());
+
+        options.dry_run = false;
+        assert_eq!(Status::Copied, archive(&options));
+        assert_eq!("new", fs::read_to_string(&archive_path).unwrap());
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
@@ -0,0 +1,1016 @@
+//! # Template expansion
+
+use crate::fitarchiver::{ActivityData, ArchiverError, Result};
+use crate::location::geohash;
+use crate::sports::short_sport_name;
+use crate::state::device_name;
+use aho_corasick::AhoCorasick;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Datelike, FixedOffset};
+use std::str::FromStr;
+
+/// Maximum number of characters of the notes in file names
+const NOTES_LENGTH: usize = 32;
+
+/// User variable of the templates, i.e. 'athlete=jan' for '${var:athlete}'
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    /// Name of the variable, i.e. 'athlete'
+    pub name: String,
+    /// Value inserted into the paths, i.e. 'jan'
+    pub value: String,
+}
+
+impl FromStr for Variable {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once('=') {
+            Some((name, value)) if is_variable_name(name) => Ok(Variable {
+                name: name.to_string(),
+                value: value.to_string(),
+            }),
+            _ => {
+                let msg = format!(
+                    "Invalid variable '{}', expected '<name>=<value>' with a name of letters, digits, '_' and '-'",
+                    s
+                );
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+/// Returns true if a text is a valid name of a user variable
+///
+/// # Arguments
+///
+/// * `name` - Name of the variable.
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Returns notes usable in file names
+///
+/// All characters except letters and digits are replaced by '_'. The result is lowercase and
+/// truncated to `NOTES_LENGTH` characters.
+///
+/// # Arguments
+///
+/// * `notes` - Notes of the activity.
+fn sanitize_notes(notes: &str) -> String {
+    let mut result = String::new();
+    for c in notes.chars() {
+        if result.chars().count() >= NOTES_LENGTH {
+            break;
+        }
+        if c.is_alphanumeric() {
+            result.extend(c.to_lowercase());
+        } else if !result.is_empty() && !result.ends_with('_') {
+            result.push('_');
+        }
+    }
+
+    match result.trim_end_matches('_') {
+        "" => String::from("unknown"),
+        val => val.to_string(),
+    }
+}
+
+/// Returns the text of a numeric code or 'unknown'
+///
+/// # Arguments
+///
+/// * `code` - Numeric code, i.e. of the sport.
+fn code_text(code: Option<u8>) -> String {
+    match code {
+        Some(code) => code.to_string(),
+        None => String::from("unknown"),
+    }
+}
+
+/// Returns the ISO 8601 week of the activity, i.e. '01'
+///
+/// The first days of January may belong to the last week of the previous year and the last days
+/// of December to the first week of the next year.
+///
+/// # Arguments
+///
+/// * `activity_data` - Activity data containing the timestamp.
+fn iso_week(activity_data: &ActivityData) -> String {
+    format!("{:02}", activity_data.local_timestamp().iso_week().week())
+}
+
+/// Returns the ISO 8601 week of the activity including its week-based year, i.e. '2025-W01'
+///
+/// # Arguments
+///
+/// * `activity_data` - Activity data containing the timestamp.
+fn iso_year_week(activity_data: &ActivityData) -> String {
+    let week = activity_data.local_timestamp().iso_week();
+    format!("{}-W{:02}", week.year(), week.week())
+}
+
+/// Returns the start of the activity in seconds since 1970-01-01 UTC, i.e. '1404810611'
+///
+/// # Arguments
+///
+/// * `activity_data` - Activity data containing the timestamp.
+fn epoch_seconds(activity_data: &ActivityData) -> String {
+    activity_data.timestamp.timestamp().to_string()
+}
+
+/// Returns the year and the zero-padded day of the year of the activity, i.e. '2014189'
+///
+/// Unlike the day of the month it sorts without separators, i.e. in flat object storage names.
+///
+/// # Arguments
+///
+/// * `activity_data` - Activity data containing the timestamp.
+fn year_day(activity_data: &ActivityData) -> String {
+    let timestamp = activity_data.local_timestamp();
+    format!("{}{:03}", timestamp.year(), timestamp.ordinal())
+}
+
+/// Returns a timestamp formatted with strftime() tags
+///
+/// Returns `None` if the format contains an unsupported tag. '%' in the result is escaped, so the
+/// result passes the expansion of the '%' tags of the template unchanged.
+///
+/// # Arguments
+///
+/// * `timestamp` - Timestamp to format.
+/// * `format` - Format with strftime() tags, i.e. '%Y%m%d'.
+fn format_timestamp(timestamp: DateTime<FixedOffset>, format: &str) -> Option<String> {
+    let items: Vec<Item> = StrftimeItems::new(format).collect();
+    if items.contains(&Item::Error) {
+        return None;
+    }
+    Some(
+        timestamp
+            .format_with_items(items.into_iter())
+            .to_string()
+            .replace('%', "%%"),
+    )
+}
+
+/// Returns the number of legs of the activity
+///
+/// # Arguments
+///
+/// * `activity_data` - Activity data containing the legs.
+fn leg_count(activity_data: &ActivityData) -> String {
+    match activity_data.legs.len() {
+        0 => String::from("unknown"),
+        count => count.to_string(),
+    }
+}
+
+/// Returns the number of steps of the structured workout of the activity
+///
+/// # Arguments
+///
+/// * `activity_data` - Activity data containing the workout.
+fn workout_step_count(activity_data: &ActivityData) -> String {
+    match activity_data.workout_steps {
+        0 => String::from("unknown"),
+        count => count.to_string(),
+    }
+}
+
+/// Returns the sports of the legs of the activity joined by '-'
+///
+/// Returns `None` if the modifier is not supported.
+///
+/// # Arguments
+///
+/// * `activity_data` - Activity data containing the legs.
+/// * `modifier` - 'full' for the names of the sports, 'short' for short names.
+fn leg_sports(activity_data: &ActivityData, modifier: &str) -> Option<String> {
+    let name: fn(&str) -> &str = match modifier {
+        "full" => |sport| sport,
+        "short" => short_sport_name,
+        _ => return None,
+    };
+    if activity_data.legs.is_empty() {
+        return Some(String::from("unknown"));
+    }
+    Some(
+        activity_data
+            .legs
+            .iter()
+            .map(|sport| name(sport))
+            .collect::<Vec<_>>()
+            .join("-"),
+    )
+}
+
+/// Returns a numeric value formatted according to a modifier
+///
+/// Returns `None` if the modifier is not supported for the tag.
+///
+/// # Arguments
+///
+/// * `tag` - Name of the numeric tag, i.e. 'd'.
+/// * `modifier` - Formatting modifier, i.e. 'km1'.
+/// * `value` - Value of the tag in its base unit (seconds, meters or beats per minute).
+fn format_numeric(tag: &str, modifier: &str, value: f64) -> Option<String> {
+    match (tag, modifier) {
+        (_, "int") => Some(format!("{:.0}", value)),
+        ("t", "hms") => {
+            let minutes = (value / 60.0).floor() as u64;
+            Some(format!("{}h{:02}m", minutes / 60, minutes % 60))
+        }
+        ("t", "min") => Some(format!("{:.0}min", value / 60.0)),
+        ("d", "m") => Some(format!("{:.0}m", value)),
+        ("d", unit) => {
+            let (factor, name, digits) = if let Some(digits) = unit.strip_prefix("km") {
+                (1000.0, "km", digits)
+            } else if let Some(digits) = unit.strip_prefix("mi") {
+                (1609.344, "mi", digits)
+            } else {
+                return None;
+            };
+            let digits = match digits {
+                "" => 0,
+                _ if digits.len() == 1 => digits.parse::<usize>().ok()?,
+                _ => return None,
+            };
+            Some(format!("{:.*}{}", digits, value / factor, name))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the expansion of a tag or `None` if the tag or modifier is not supported
+///
+/// # Arguments
+///
+/// * `tag` - Name of the tag without '$', i.e. 's'.
+/// * `modifier` - Optional formatting modifier of numeric tags, i.e. 'km1', strftime() tags of
+///   the period tags, i.e. '%Y%m%d', or the name of a user variable.
+/// * `activity_data` - Data that will be used for expansion of the tag.
+fn expand_tag(tag: &str, modifier: Option<&str>, activity_data: &ActivityData) -> Option<String> {
+    let (value, default_modifier) = match tag {
+        "var" => {
+            let name = modifier.filter(|name| is_variable_name(name))?;
+            // a variable given several times takes the last value, its value is no strftime()
+            // format
+            return Some(
+                activity_data
+                    .variables
+                    .iter()
+                    .rev()
+                    .find(|variable| variable.name == name)
+                    .map_or(String::from("unknown"), |variable| {
+                        variable.value.replace('%', "%%")
+                    }),
+            );
+        }
+        "begin" | "end" => {
+            let timestamp = match tag {
+                "begin" => activity_data.begin(),
+                _ => activity_data.end(),
+            };
+            return format_timestamp(
+                timestamp.with_timezone(&activity_data.utc_offset),
+                modifier.unwrap_or("%Y%m%d"),
+            );
+        }
+        "lat" | "lon" => {
+            let digits = modifier
+                .unwrap_or("2")
+                .parse::<usize>()
+                .ok()
+                .filter(|digits| *digits <= 6)?;
+            let coordinate = activity_data.start_position.map(|(latitude, longitude)| {
+                let value = if tag == "lat" { latitude } else { longitude };
+                format!("{:.*}", digits, value)
+            });
+            return Some(coordinate.unwrap_or(String::from("unknown")));
+        }
+        "H" => {
+            let precision = modifier
+                .unwrap_or("5")
+                .parse::<usize>()
+                .ok()
+                .filter(|precision| (1..=12).contains(precision))?;
+            return Some(
+                activity_data
+                    .start_position
+                    .map_or(String::from("unknown"), |position| {
+                        geohash(position, precision)
+                    }),
+            );
+        }
+        "t" => (activity_data.duration, "hms"),
+        "d" => (activity_data.distance, "km1"),
+        "h" => (activity_data.heart_rate, "int"),
+        "l" => return leg_sports(activity_data, modifier.unwrap_or("full")),
+        _ => {
+            let text = match tag {
+                "s" => activity_data.sport.clone(),
+                "n" => activity_data.sport_name.clone(),
+                "S" => activity_data.sub_sport.clone(),
+                "w" => activity_data.workout_name.clone(),
+                "q" => activity_data.day_sequence.to_string(),
+                "o" => sanitize_notes(&activity_data.notes),
+                "c" => code_text(activity_data.sport_code),
+                "C" => code_text(activity_data.sub_sport_code),
+                "r" => activity_data.start_location.clone(),
+                "R" => activity_data.route.clone(),
+                "g" => activity_data.city.clone(),
+                "G" => activity_data.country.clone(),
+                "k" => activity_data.kind.clone(),
+                "W" => iso_week(activity_data),
+                "V" => iso_year_week(activity_data),
+                "E" => epoch_seconds(activity_data),
+                "J" => year_day(activity_data),
+                "L" => leg_count(activity_data),
+                "D" => device_name(activity_data),
+                "f" => activity_data.file_type.clone(),
+                "i" => activity_data.workout_structure.clone(),
+                "I" => workout_step_count(activity_data),
+                _ => return None,
+            };
+            // text tags do not support modifiers
+            return modifier.is_none().then_some(text);
+        }
+    };
+
+    let modifier = modifier.unwrap_or(default_modifier);
+    match value {
+        Some(value) => format_numeric(tag, modifier, value),
+        None => format_numeric(tag, modifier, 0.0).map(|_| String::from("unknown")),
+    }
+}
+
+/// Returns a format string with all '${tag}' and '${tag:modifier}' tags expanded
+///
+/// Unsupported or unterminated tags are kept unchanged and returned as second element.
+///
+/// # Arguments
+///
+/// * `formatstring` - A format string containing '${...}' tags.
+/// * `activity_data` - Data that will be used for expansion of the tags.
+fn expand_braced_tags(formatstring: &str, activity_data: &ActivityData) -> (String, Vec<String>) {
+    let mut result = String::new();
+    let mut invalid = Vec::new();
+    let mut rest = formatstring;
+
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        let tag = &rest[start..];
+        let Some(end) = tag.find('}') else {
+            invalid.push(tag.to_string());
+            result.push_str(tag);
+            rest = "";
+            break;
+        };
+
+        let (name, modifier) = match tag[2..end].split_once(':') {
+            Some((name, modifier)) => (name, Some(modifier)),
+            None => (&tag[2..end], None),
+        };
+        match expand_tag(name, modifier, activity_data) {
+            Some(val) => result.push_str(&val),
+            None => {
+                invalid.push(tag[..=end].to_string());
+                result.push_str(&tag[..=end]);
+            }
+        }
+        rest = &tag[end + 1..];
+    }
+    result.push_str(rest);
+
+    (result, invalid)
+}
+
+/// Returns true if a template contains a tag expanding to the sport of the activity
+///
+/// # Arguments
+///
+/// * `formatstring` - A format string containing '%' and '$' tags.
+pub(crate) fn has_sport_tag(formatstring: &str) -> bool {
+    has_tag(formatstring, &['s', 'n', 'S', 'c', 'C', 'l'])
+}
+
+/// Returns true if a template contains one of the '$' tags
+///
+/// # Arguments
+///
+/// * `formatstring` - A format string containing '%' and '$' tags.
+/// * `names` - Names of the tags.
+fn has_tag(formatstring: &str, names: &[char]) -> bool {
+    formatstring.split('$').skip(1).any(|tag| {
+        let name = tag.strip_prefix('{').unwrap_or(tag);
+        name.starts_with(names)
+    })
+}
+
+/// Returns the names of the '%' tags of a template, i.e. 'Y' for '%Y' and '%-Y'
+///
+/// # Arguments
+///
+/// * `formatstring` - A format string containing '%' and '$' tags.
+fn time_tags(formatstring: &str) -> Vec<char> {
+    let mut tags = Vec::new();
+    let mut chars = formatstring.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        // flags and widths like in '%-d' or '%.3f' precede the name
+        if let Some(name) = chars
+            .by_ref()
+            .find(|c| !matches!(c, '-' | '_' | '0'..='9' | '^' | '#' | ':' | '.'))
+        {
+            tags.push(name);
+        }
+    }
+    tags
+}
+
+/// Returns warnings about a template whose archive paths are likely to collide
+///
+/// Activities are told apart by the date and the time of the day down to the seconds or by the
+/// day sequence '$q'.
+///
+/// # Arguments
+///
+/// * `formatstring` - A format string containing '%' and '$' tags.
+pub(crate) fn lint_template(formatstring: &str) -> Vec<String> {
+    let tags = time_tags(formatstring);
+    let has = |names: &[char]| tags.iter().any(|tag| names.contains(tag));
+    // tags giving the full date or time like '%F', '%s' or '$E' count for all of their parts
+    let full = ['c', 's', '+'];
+    let epoch = has_tag(formatstring, &['E']);
+    let year_day = has_tag(formatstring, &['J']) || epoch;
+    let year = has(&['Y', 'y', 'G', 'g', 'F', 'D', 'x']) || has(&full) || year_day;
+    let day = has(&['d', 'e', 'j', 'F', 'D', 'x']) || has(&full) || year_day;
+    let minute = has(&['M', 'R', 'T', 'X', 'r']) || has(&full) || epoch;
+    let second = has(&['S', 'T', 'X', 'r']) || has(&full) || epoch;
+    let sequence = has_tag(formatstring, &['q']);
+
+    let mut warnings = Vec::new();
+    if !day {
+        warnings.push(format!(
+            "Template '{}' contains no day of the activity, i.e. '%d' or '%F', activities of different days get the same path!",
+            formatstring
+        ));
+    } else if !year {
+        warnings.push(format!(
+            "Template '{}' contains no year of the activity, i.e. '%Y', activities of different years get the same path!",
+            formatstring
+        ));
+    }
+    if day && !second && !sequence {
+        let period = if minute { "minute" } else { "day" };
+        warnings.push(format!(
+            "Template '{}' contains neither seconds nor the day sequence '$q', activities starting within the same {} get the same path!",
+            formatstring, period
+        ));
+    }
+    warnings
+}
+
+/// Check that all '${...}' tags of a template are supported
+///
+/// # Arguments
+///
+/// * `formatstring` - A format string containing '%' and '$' tags.
+pub fn check_template(formatstring: &str) -> Result<()> {
+    let (_, invalid) = expand_braced_tags(formatstring, &ActivityData::new());
+    match invalid.first() {
+        Some(tag) => {
+            let msg = format!("Invalid tag '{}' in template '{}'", tag, formatstring);
+            Err(ArchiverError::new(&msg))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Check that all user variables of a template are defined
+///
+/// # Arguments
+///
+/// * `formatstring` - A format string containing '%' and '$' tags.
+/// * `variables` - User variables of the templates.
+pub(crate) fn check_variables(formatstring: &str, variables: &[Variable]) -> Result<()> {
+    for tag in formatstring.split("${var:").skip(1) {
+        let name = tag.split('}').next().unwrap_or_default();
+        if !variables.iter().any(|variable| variable.name == name) {
+            let msg = format!(
+                "Undefined variable '{}' in template '{}', define it with '--var {}=<value>'",
+                name, formatstring, name
+            );
+            return Err(ArchiverError::new(&msg));
+        }
+    }
+    Ok(())
+}
+
+/// Returns an expanded format string with '%' and '$' replaced
+///
+/// '%' tag are expanded using the timestamp of the acticity data in the timezone of its UTC
+/// offset. The '$' tag
+/// are expanded using other data from the activity.
+///
+/// # Arguments
+///
+/// * `formatstring` - A format string containing '%' and '$' tags.
+/// * `activity_data` - Data that will be used for expansion of the tags.
+pub(crate) fn expand_formatstring(formatstring: &str, activity_data: &ActivityData) -> String {
+    // tags with modifiers are expanded first, they may be followed by characters of a tag name
+    let (formatstring, _) = expand_braced_tags(formatstring, activity_data);
+
+    // the following code is not the most efficient one but makes the mappings obvious
+
+    // first define the mappings as slice for better visibility ...
+    let day_sequence = activity_data.day_sequence.to_string();
+    let duration = expand_tag("t", None, activity_data).unwrap();
+    let distance = expand_tag("d", None, activity_data).unwrap();
+    let heart_rate = expand_tag("h", None, activity_data).unwrap();
+    let notes = sanitize_notes(&activity_data.notes);
+    let sport_code = code_text(activity_data.sport_code);
+    let sub_sport_code = code_text(activity_data.sub_sport_code);
+    let week = iso_week(activity_data);
+    let year_week = iso_year_week(activity_data);
+    let epoch = epoch_seconds(activity_data);
+    let ordinal = year_day(activity_data);
+    let legs = leg_count(activity_data);
+    let leg_sports = leg_sports(activity_data, "full").unwrap();
+    let device = device_name(activity_data);
+    let steps = workout_step_count(activity_data);
+    let cell = expand_tag("H", None, activity_data).unwrap();
+    let mappings = [
+        ["$s", activity_data.sport.as_str()],
+        ["$n", activity_data.sport_name.as_str()],
+        ["$S", activity_data.sub_sport.as_str()],
+        ["$w", activity_data.workout_name.as_str()],
+        ["$q", day_sequence.as_str()],
+        ["$t", duration.as_str()],
+        ["$d", distance.as_str()],
+        ["$h", heart_rate.as_str()],
+        ["$o", notes.as_str()],
+        ["$c", sport_code.as_str()],
+        ["$C", sub_sport_code.as_str()],
+        ["$r", activity_data.start_location.as_str()],
+        ["$R", activity_data.route.as_str()],
+        ["$g", activity_data.city.as_str()],
+        ["$G", activity_data.country.as_str()],
+        ["$H", cell.as_str()],
+        ["$k", activity_data.kind.as_str()],
+        ["$W", week.as_str()],
+        ["$V", year_week.as_str()],
+        ["$E", epoch.as_str()],
+        ["$J", ordinal.as_str()],
+        ["$L", legs.as_str()],
+        ["$l", leg_sports.as_str()],
+        ["$D", device.as_str()],
+        ["$f", activity_data.file_type.as_str()],
+        ["$i", activity_data.workout_structure.as_str()],
+        ["$I", steps.as_str()],
+    ];
+
+    // ... then convert the slice to the required vectors
+    let tags: Vec<&str> = mappings.iter().map(|x| x[0]).collect();
+    let substitutions: Vec<&str> = mappings.iter().map(|x| x[1]).collect();
+
+    // replace all '$' tags with their substitutions (activity)
+    let result = AhoCorasick::new(tags)
+        .unwrap()
+        .replace_all(&formatstring, &substitutions);
+
+    // replace all '%' tags with their substitions (timestamp)
+    activity_data
+        .local_timestamp()
+        .format(&result.to_string())
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    #[test]
+    /// Test format string expansion
+    fn test_expand_formatstring() {
+        // setup activity data
+        let activity_data = crate::fitarchiver::ActivityData {
+            sport: String::from("running"),
+            sport_name: String::from("training"),
+            sub_sport: String::from("trail"),
+            workout_name: String::from("interval"),
+            timestamp: chrono::Utc.with_ymd_and_hms(2014, 7, 8, 9, 10, 11).unwrap(),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+
+        // default format string
+        assert_eq!(
+            String::from("2014/07/2014-07-08-091011-running"),
+            super::expand_formatstring("%Y/%m/%Y-%m-%d-%H%M%S-$s", &activity_data)
+        );
+
+        // single tags
+        assert_eq!(
+            String::from("running"),
+            super::expand_formatstring("$s", &activity_data)
+        );
+        assert_eq!(
+            String::from("training"),
+            super::expand_formatstring("$n", &activity_data)
+        );
+        assert_eq!(
+            String::from("trail"),
+            super::expand_formatstring("$S", &activity_data)
+        );
+        assert_eq!(
+            String::from("interval"),
+            super::expand_formatstring("$w", &activity_data)
+        );
+        assert_eq!(
+            String::from("1"),
+            super::expand_formatstring("$q", &activity_data)
+        );
+        assert_eq!(
+            String::from("unknown"),
+            super::expand_formatstring("$c", &activity_data)
+        );
+        assert_eq!(
+            String::from("unknown"),
+            super::expand_formatstring("$r", &activity_data)
+        );
+        assert_eq!(
+            String::from("home/run"),
+            super::expand_formatstring(
+                "${r}/run",
+                &crate::fitarchiver::ActivityData {
+                    start_location: String::from("home"),
+                    ..crate::fitarchiver::ActivityData::new()
+                }
+            )
+        );
+        assert_eq!(
+            String::from("park_loop/home"),
+            super::expand_formatstring(
+                "$R/$r",
+                &crate::fitarchiver::ActivityData {
+                    start_location: String::from("home"),
+                    route: String::from("park_loop"),
+                    ..crate::fitarchiver::ActivityData::new()
+                }
+            )
+        );
+        assert_eq!(
+            String::from("italy/bolzano/unknown"),
+            super::expand_formatstring(
+                "$G/${g}/$r",
+                &crate::fitarchiver::ActivityData {
+                    city: String::from("bolzano"),
+                    country: String::from("italy"),
+                    ..crate::fitarchiver::ActivityData::new()
+                }
+            )
+        );
+        assert_eq!(
+            String::from("u220d/u22/46.50_11.35/46.5 unknown-unknown"),
+            [
+                crate::fitarchiver::ActivityData {
+                    start_position: Some((46.4983, 11.3548)),
+                    ..crate::fitarchiver::ActivityData::new()
+                },
+                crate::fitarchiver::ActivityData::new()
+            ]
+            .iter()
+            .map(|activity_data| {
+                super::expand_formatstring("$H/${H:3}/${lat}_${lon}/${lat:1}", activity_data)
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+            .replace("/unknown/unknown_unknown/unknown", "-unknown")
+        );
+        assert_eq!(
+            String::from("race/run"),
+            super::expand_formatstring(
+                "${k}/run",
+                &crate::fitarchiver::ActivityData {
+                    kind: String::from("race"),
+                    ..crate::fitarchiver::ActivityData::new()
+                }
+            )
+        );
+        assert_eq!(
+            String::from("1-3"),
+            super::expand_formatstring(
+                "$c-$C",
+                &crate::fitarchiver::ActivityData {
+                    sport_code: Some(1),
+                    sub_sport_code: Some(3),
+                    ..crate::fitarchiver::ActivityData::new()
+                }
+            )
+        );
+        assert_eq!(
+            String::from("fenix7-3345678901/totals unknown/unknown"),
+            [
+                crate::fitarchiver::ActivityData {
+                    product: String::from("fenix7"),
+                    serial_number: Some(3345678901),
+                    file_type: String::from("totals"),
+                    ..crate::fitarchiver::ActivityData::new()
+                },
+                crate::fitarchiver::ActivityData::new()
+            ]
+            .iter()
+            .map(|activity_data| super::expand_formatstring("$D/${f}", activity_data))
+            .collect::<Vec<_>>()
+            .join(" ")
+        );
+
+        // sortable numeric forms of the start time
+        assert_eq!(
+            String::from("1404810611-2014189"),
+            super::expand_formatstring("$E-${J}", &activity_data)
+        );
+        assert_eq!(
+            String::from("2014005"),
+            super::expand_formatstring(
+                "$J",
+                &crate::fitarchiver::ActivityData {
+                    timestamp: chrono::Utc.with_ymd_and_hms(2014, 1, 5, 0, 0, 0).unwrap(),
+                    ..crate::fitarchiver::ActivityData::new()
+                }
+            )
+        );
+
+        // interval sessions are identified by the structure of their workout
+        assert_eq!(
+            String::from("6x(3min_on_2min_off)-12 unknown-unknown"),
+            [
+                crate::fitarchiver::ActivityData {
+                    workout_steps: 12,
+                    workout_structure: String::from("6x(3min_on_2min_off)"),
+                    ..crate::fitarchiver::ActivityData::new()
+                },
+                crate::fitarchiver::ActivityData::new()
+            ]
+            .iter()
+            .map(|activity_data| super::expand_formatstring("$i-${I}", activity_data))
+            .collect::<Vec<_>>()
+            .join(" ")
+        );
+
+        // the time tags are expanded in the timezone, the seconds since 1970 stay UTC
+        let late_start = crate::fitarchiver::ActivityData {
+            timestamp: chrono::Utc.with_ymd_and_hms(2014, 7, 8, 22, 30, 0).unwrap(),
+            utc_offset: chrono::FixedOffset::east_opt(7200).unwrap(),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+        assert_eq!(
+            String::from("2014/07/09-0030+0200-1404858600-2014190-20140709"),
+            super::expand_formatstring("%Y/%m/%d-%H%M%z-$E-$J-${begin}", &late_start)
+        );
+
+        // ISO weeks belong to the week-based year
+        assert_eq!(
+            String::from("28/2014-W28"),
+            super::expand_formatstring("$W/$V", &activity_data)
+        );
+        let week = |year, month, day| {
+            super::expand_formatstring(
+                "%Y/$W %G/$W ${V}",
+                &crate::fitarchiver::ActivityData {
+                    timestamp: chrono::Utc
+                        .with_ymd_and_hms(year, month, day, 12, 0, 0)
+                        .unwrap(),
+                    ..crate::fitarchiver::ActivityData::new()
+                },
+            )
+        };
+        assert_eq!("2024/01 2025/01 2025-W01", week(2024, 12, 31));
+        assert_eq!("2021/53 2020/53 2020-W53", week(2021, 1, 1));
+
+        // repeated tags
+        assert_eq!(
+            String::from("running-running-running-running"),
+            super::expand_formatstring("$s-$s-$s-$s", &activity_data)
+        );
+    }
+
+    #[test]
+    /// Test expansion of numeric tags with modifiers
+    fn test_expand_formatstring_modifiers() {
+        // setup activity data
+        let activity_data = crate::fitarchiver::ActivityData {
+            duration: Some(3912.5),
+            distance: Some(21097.5),
+            heart_rate: Some(151.6),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+
+        // default modifiers
+        assert_eq!(
+            String::from("1h05m-21.1km-152"),
+            super::expand_formatstring("$t-$d-$h", &activity_data)
+        );
+
+        // explicit modifiers
+        assert_eq!(
+            String::from("1h05m-65min-3912"),
+            super::expand_formatstring("${t:hms}-${t:min}-${t:int}", &activity_data)
+        );
+        assert_eq!(
+            String::from("21km-21.10km-13.1mi-21098m"),
+            super::expand_formatstring("${d:km}-${d:km2}-${d:mi1}-${d:m}", &activity_data)
+        );
+        assert_eq!(
+            String::from("152bpm-unknown"),
+            super::expand_formatstring("${h:int}bpm-${s}", &activity_data)
+        );
+
+        // missing values
+        assert_eq!(
+            String::from("unknown-unknown"),
+            super::expand_formatstring(
+                "${t:hms}-${d:km1}",
+                &crate::fitarchiver::ActivityData::new()
+            )
+        );
+
+        // unsupported tags are kept
+        assert_eq!(
+            String::from("${d:hms}-${x}"),
+            super::expand_formatstring("${d:hms}-${x}", &activity_data)
+        );
+    }
+
+    #[test]
+    /// Test expansion of the legs of multisport activities
+    fn test_expand_formatstring_legs() {
+        let activity_data = crate::fitarchiver::ActivityData {
+            legs: vec![
+                String::from("swimming"),
+                String::from("cycling"),
+                String::from("running"),
+            ],
+            ..crate::fitarchiver::ActivityData::new()
+        };
+        assert_eq!(
+            String::from("3leg_swimming-cycling-running"),
+            super::expand_formatstring("${L}leg_$l", &activity_data)
+        );
+        assert_eq!(
+            String::from("3leg_swim-bike-run"),
+            super::expand_formatstring("${L}leg_${l:short}", &activity_data)
+        );
+        assert_eq!(
+            String::from("unknown-unknown"),
+            super::expand_formatstring("$L-${l:short}", &crate::fitarchiver::ActivityData::new())
+        );
+        super::check_template("${l:full}").unwrap();
+        super::check_template("${l:int}").expect_err("error expected");
+    }
+
+    #[test]
+    /// Test expansion of the period of monitoring files
+    fn test_expand_formatstring_period() {
+        let activity_data = crate::fitarchiver::ActivityData {
+            timestamp: chrono::Utc.with_ymd_and_hms(2025, 1, 6, 0, 0, 0).unwrap(),
+            period: Some((
+                chrono::Utc.with_ymd_and_hms(2025, 1, 6, 0, 1, 0).unwrap(),
+                chrono::Utc
+                    .with_ymd_and_hms(2025, 1, 12, 23, 59, 0)
+                    .unwrap(),
+            )),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+        assert_eq!(
+            String::from("2025/wellness_20250106-20250112"),
+            super::expand_formatstring("%Y/wellness_${begin:%Y%m%d}-${end}", &activity_data)
+        );
+        assert_eq!(
+            String::from("0001-2359_100%"),
+            super::expand_formatstring("${begin:%H%M}-${end:%H%M}_100%%", &activity_data)
+        );
+        // files without timestamps in their messages use the start time
+        assert_eq!(
+            String::from("19700101-19700101"),
+            super::expand_formatstring("${begin}-${end}", &crate::fitarchiver::ActivityData::new())
+        );
+        super::check_template("${begin:%Y-%m-%d}").unwrap();
+        super::check_template("${end:%Q}").expect_err("error expected");
+    }
+
+    #[test]
+    /// Test expansion of notes
+    fn test_expand_formatstring_notes() {
+        let activity_data = crate::fitarchiver::ActivityData {
+            notes: String::from("  Hill repeats: 6x 400m / felt GREAT!\nsecond line"),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+        assert_eq!(
+            String::from("hill_repeats_6x_400m_felt_great"),
+            super::expand_formatstring("$o", &activity_data)
+        );
+        assert_eq!(
+            String::from("unknown"),
+            super::expand_formatstring("${o}", &crate::fitarchiver::ActivityData::new())
+        );
+    }
+
+    #[test]
+    /// Test detecting tags expanding to the sport
+    fn test_has_sport_tag() {
+        assert!(super::has_sport_tag(
+            crate::fitarchiver::DEFAULT_FILE_TEMPLATE
+        ));
+        assert!(super::has_sport_tag("%Y/${S}"));
+        assert!(super::has_sport_tag("%Y/${l:short}"));
+        assert!(!super::has_sport_tag("%Y/%m/%Y-%m-%d-%H%M%S"));
+        assert!(!super::has_sport_tag("%Y/$q-$d-${t:hms}-%s"));
+    }
+
+    #[test]
+    /// Test warnings about templates whose paths are likely to collide
+    fn test_lint_template() {
+        for template in [
+            crate::fitarchiver::DEFAULT_FILE_TEMPLATE,
+            "%Y/%m/%d-$q-$s",
+            "%F/%T",
+            "$s/%s",
+            "%Y/%-j-%H%M%S",
+            "$E-$s",
+            "${J}-%H%M%S",
+        ] {
+            assert!(super::lint_template(template).is_empty(), "{}", template);
+        }
+
+        let warnings = super::lint_template("$s");
+        assert_eq!(1, warnings.len());
+        assert!(warnings[0].contains("no day"));
+
+        let warnings = super::lint_template("%m/%d-%H%M-$s");
+        assert_eq!(2, warnings.len());
+        assert!(warnings[0].contains("no year"));
+        assert!(warnings[1].contains("same minute"));
+
+        let warnings = super::lint_template("%Y-%m-%d-$s-100%%S");
+        assert_eq!(1, warnings.len());
+        assert!(warnings[0].contains("same day"));
+    }
+
+    #[test]
+    /// Test checking templates for unsupported tags
+    fn test_check_template() {
+        super::check_template(crate::fitarchiver::DEFAULT_FILE_TEMPLATE).unwrap();
+        super::check_template("%Y/${d:km1}-${t:hms}-${h:int}-${s}").unwrap();
+        super::check_template("${d:km12}").expect_err("error expected");
+        super::check_template("${h:hms}").expect_err("error expected");
+        super::check_template("${s:int}").expect_err("error expected");
+        super::check_template("${d:km1").expect_err("error expected");
+        super::check_template("${var:athlete}/%Y").unwrap();
+        super::check_template("${var}").expect_err("error expected");
+        super::check_template("${var:a/b}").expect_err("error expected");
+        super::check_template("${H:3}/${lat:0}_${lon:6}").unwrap();
+        super::check_template("${H:13}").expect_err("error expected");
+        super::check_template("${lat:km}").expect_err("error expected");
+        super::check_template("${lon:7}").expect_err("error expected");
+    }
+
+    #[test]
+    /// Test expansion of user variables
+    fn test_expand_formatstring_variables() {
+        let variable = |s: &str| s.parse::<super::Variable>().unwrap();
+        let activity_data = crate::fitarchiver::ActivityData {
+            variables: vec![
+                variable("athlete=jan"),
+                variable("box=nas1"),
+                variable("athlete=eva"),
+                variable("effort=100%"),
+            ],
+            timestamp: chrono::Utc.with_ymd_and_hms(2023, 7, 26, 6, 22, 4).unwrap(),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+        assert_eq!(
+            String::from("nas1/eva/unknown-unknown"),
+            super::expand_formatstring("${var:box}/${var:athlete}/$s-${var:site}", &activity_data)
+        );
+        // a '%' in a value is no strftime() format
+        assert_eq!(
+            String::from("2023/2023-07-26-100%"),
+            super::expand_formatstring("%Y/%F-${var:effort}", &activity_data)
+        );
+
+        super::check_variables("${var:box}/$s", &activity_data.variables).unwrap();
+        super::check_variables("${var:site}/$s", &activity_data.variables)
+            .expect_err("error expected");
+        assert_eq!(String::from("dash-name"), variable("dash-name=").name);
+        for s in ["athlete", "=jan", "ath lete=jan", "a/b=c"] {
+            s.parse::<super::Variable>().expect_err("error expected");
+        }
+    }
+}
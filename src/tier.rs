@@ -0,0 +1,263 @@
+//! # Cold storage tiering of an existing archive
+//!
+//! Old activities are rarely accessed. They can be moved to a secondary "cold" directory, i.e. a
+//! cheaper disk or a mounted cloud bucket, while the index keeps track of their new location.
+
+use crate::checksum::move_checksum;
+use crate::fitarchiver::{find_fit_files, parse_fit_file, ArchiverError, Result};
+use crate::index::{Index, INDEX_PATH};
+use chrono::{DateTime, Months, Utc};
+use std::fs;
+use std::path::{self, Path, PathBuf};
+
+/// Options controlling the tiering policy
+#[derive(Debug, Clone)]
+pub struct TierOptions {
+    /// Base directory of the archive
+    pub directory: PathBuf,
+    /// Directory receiving the old archive files
+    pub cold_directory: PathBuf,
+    /// Minimum age of an activity in years to be moved to the cold directory
+    pub older_than: u32,
+    /// Do not modify the archive, just show what will happen
+    pub dry_run: bool,
+}
+
+impl Default for TierOptions {
+    fn default() -> Self {
+        TierOptions {
+            directory: PathBuf::from("."),
+            cold_directory: PathBuf::new(),
+            older_than: 5,
+            dry_run: false,
+        }
+    }
+}
+
+/// Move a file, copying it if it is moved to another file system
+///
+/// # Arguments
+///
+/// `source_path` - Path of the file.
+/// `target_path` - New path of the file.
+pub(crate) fn move_file(source_path: &Path, target_path: &Path) -> Result<()> {
+    if let Some(parent) = target_path.parent() {
+        if let Err(err) = fs::create_dir_all(parent) {
+            let msg = format!("Unable to create directory '{}'", parent.display());
+            return Err(ArchiverError::io(&msg, err));
+        }
+    }
+    if fs::rename(source_path, target_path).is_ok() {
+        return Ok(());
+    }
+
+    if let Err(err) = fs::copy(source_path, target_path) {
+        let _ = fs::remove_file(target_path);
+        let msg = format!("Unable to create file '{}'", target_path.display());
+        return Err(ArchiverError::io(&msg, err));
+    }
+    if let Err(err) = fs::remove_file(source_path) {
+        let msg = format!("Unable to remove file '{}'", source_path.display());
+        return Err(ArchiverError::io(&msg, err));
+    }
+    Ok(())
+}
+
+/// Returns an absolute path
+///
+/// # Arguments
+///
+/// `path` - Path to resolve.
+fn absolute(path: &Path) -> Result<PathBuf> {
+    path::absolute(path).map_err(|err| {
+        let msg = format!("Unable to resolve directory '{}'", path.display());
+        ArchiverError::io(&msg, err)
+    })
+}
+
+/// Move archive files older than the policy allows to the cold directory
+///
+/// The structure of the archive is kept in the cold directory.
+///
+/// # Arguments
+///
+/// `options` - Tier options.
+/// `now` - Current time the age of the activities is based on.
+fn tier_at(options: &TierOptions, now: DateTime<Utc>) -> Result<String> {
+    let directory = absolute(&options.directory)?;
+    let cold_directory = absolute(&options.cold_directory)?;
+    if directory.starts_with(&cold_directory) {
+        let msg = format!(
+            "Cold directory '{}' must not contain the archive directory",
+            options.cold_directory.display()
+        );
+        return Err(ArchiverError::new(&msg));
+    }
+    let Some(cutoff) = now.checked_sub_months(Months::new(options.older_than.saturating_mul(12)))
+    else {
+        let msg = format!("Invalid age of {} years", options.older_than);
+        return Err(ArchiverError::new(&msg));
+    };
+
+    // an archive without index does not get one
+    let index = if !options.dry_run && directory.join(INDEX_PATH).exists() {
+        Index::open(&directory, true)?
+    } else {
+        None
+    };
+
+    // the cold directory may be located inside of the archive directory
+    let files: Vec<PathBuf> = find_fit_files(&directory)?
+        .into_iter()
+        .filter(|path| !path.starts_with(&cold_directory))
+        .collect();
+
+    let mut move_counter: usize = 0;
+    let mut error_counter: usize = 0;
+    for path in &files {
+        let result = parse_fit_file(path).and_then(|activity_data| {
+            if activity_data.timestamp >= cutoff {
+                return Ok(None);
+            }
+            let relative = path.strip_prefix(&directory).unwrap_or(path);
+            let target_path = cold_directory.join(relative);
+            if target_path.exists() {
+                let msg = format!(
+                    "Unable to move '{}', '{}' already exists",
+                    path.display(),
+                    target_path.display()
+                );
+                return Err(ArchiverError::new(&msg));
+            }
+            if options.dry_run {
+                return Ok(Some((target_path, "dry run")));
+            }
+
+            move_file(path, &target_path)?;
+            move_checksum(path, &target_path)?;
+            if let Some(index) = &index {
+                index.relocate(path, &target_path)?;
+            }
+            Ok(Some((target_path, "moved")))
+        });
+
+        match result {
+            Ok(Some((target_path, status))) => {
+                println!(
+                    "'{}' -> '{}' ... {}",
+                    path.display(),
+                    target_path.display(),
+                    status
+                );
+                move_counter += 1;
+            }
+            Ok(None) => (),
+            Err(err) => {
+                eprintln!("{}", err);
+                error_counter += 1;
+            }
+        }
+    }
+
+    let msg = format!(
+        "Moved {} of {} files to cold storage",
+        move_counter,
+        files.len()
+    );
+    let err = if error_counter == 0 {
+        String::new()
+    } else {
+        format!("with {} errors.", error_counter)
+    };
+
+    Ok([msg, err].join(" "))
+}
+
+/// Move archive files older than the policy allows to the cold directory
+///
+/// # Arguments
+///
+/// `options` - Tier options.
+pub fn tier(options: &TierOptions) -> Result<String> {
+    tier_at(options, Utc::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test moving old activities to the cold directory
+    fn test_tier() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let directory = tmpdir.path().join("archive");
+        let file = directory.join("2023/07/run.fit");
+        fs::create_dir_all(file.parent().unwrap())
+            .expect("error during creating temporary directory");
+        fs::copy(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("test")
+                .join("test_data_01.fit"),
+            &file,
+        )
+        .expect("unable to create test file");
+        let index = crate::index::Index::open(&directory, true)
+            .unwrap()
+            .unwrap();
+        index
+            .add(&file, &crate::fitarchiver::parse_fit_file(&file).unwrap())
+            .unwrap();
+        drop(index);
+
+        let mut options = super::TierOptions {
+            directory: directory.clone(),
+            cold_directory: directory.join("cold"),
+            older_than: 2,
+            dry_run: false,
+        };
+        let cold_file = directory.join("cold/2023/07/run.fit");
+
+        // the activity is not old enough
+        let now = Utc.with_ymd_and_hms(2025, 7, 1, 0, 0, 0).unwrap();
+        assert_eq!(
+            "Moved 0 of 1 files to cold storage ",
+            super::tier_at(&options, now).unwrap()
+        );
+
+        let now = Utc.with_ymd_and_hms(2025, 8, 1, 0, 0, 0).unwrap();
+        options.dry_run = true;
+        super::tier_at(&options, now).unwrap();
+        assert!(file.exists());
+
+        options.dry_run = false;
+        assert_eq!(
+            "Moved 1 of 1 files to cold storage ",
+            super::tier_at(&options, now).unwrap()
+        );
+        assert!(!file.exists());
+        assert!(cold_file.exists());
+
+        // the index points to the new location
+        let connection = rusqlite::Connection::open(directory.join(super::INDEX_PATH)).unwrap();
+        let path: String = connection
+            .query_row("SELECT path FROM activities", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!("cold/2023/07/run.fit", path);
+
+        // files in the cold directory are not moved again
+        assert_eq!(
+            "Moved 0 of 0 files to cold storage ",
+            super::tier_at(&options, now).unwrap()
+        );
+
+        // the cold directory must not contain the archive
+        options.cold_directory = tmpdir.path().to_path_buf();
+        super::tier_at(&options, now).expect_err("error expected");
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
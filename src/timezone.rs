@@ -0,0 +1,231 @@
+//! # Timezone of the archive paths
+//!
+//! The '%' tags of the templates expand the start time in UTC by default, so activities started
+//! shortly after midnight local time end up on the previous day east of Greenwich. With a
+//! timezone the tags expand the local time instead. Named timezones come from the tz database
+//! built into the archiver, i.e. 'Europe/Berlin', other timezones are given as fixed offset to
+//! UTC, i.e. '+05:30'.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+/// Rules of the offsets of a timezone
+#[derive(Debug, Clone, Default, PartialEq)]
+enum Rules {
+    /// Coordinated universal time
+    #[default]
+    Utc,
+    /// Local time of the system, i.e. set by `TZ` or `/etc/localtime`
+    Local,
+    /// Timezone of the tz database, i.e. 'Europe/Berlin'
+    Zone(Tz),
+    /// Fixed offset to UTC, i.e. '+05:30'
+    Fixed(FixedOffset),
+}
+
+/// Timezone of the '%' tags of the templates, UTC by default
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timezone {
+    /// Rules of the offsets
+    rules: Rules,
+}
+
+impl FromStr for Timezone {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let rules = if s.eq_ignore_ascii_case("utc") {
+            Rules::Utc
+        } else if s == "local" {
+            Rules::Local
+        } else if let Ok(zone) = s.parse::<Tz>() {
+            Rules::Zone(zone)
+        } else if let Ok(offset) = s.parse::<FixedOffset>() {
+            Rules::Fixed(offset)
+        } else {
+            let msg = format!(
+                "Unknown timezone '{}', expected 'UTC', 'local', a name like 'Europe/Berlin' or an offset like '+05:30'",
+                s
+            );
+            return Err(ArchiverError::new(&msg));
+        };
+        Ok(Timezone { rules })
+    }
+}
+
+impl Timezone {
+    /// Returns the offset of the timezone to UTC at a time
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - UTC time.
+    pub(crate) fn offset(&self, timestamp: DateTime<Utc>) -> FixedOffset {
+        let timestamp = timestamp.naive_utc();
+        match &self.rules {
+            Rules::Utc => Utc.fix(),
+            Rules::Local => Local.offset_from_utc_datetime(&timestamp).fix(),
+            Rules::Zone(zone) => zone.offset_from_utc_datetime(&timestamp).fix(),
+            Rules::Fixed(offset) => *offset,
+        }
+    }
+
+    /// Returns the date of a time in the timezone
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - UTC time.
+    pub(crate) fn date(&self, timestamp: DateTime<Utc>) -> NaiveDate {
+        timestamp
+            .with_timezone(&self.offset(timestamp))
+            .date_naive()
+    }
+}
+
+/// Source of the local time of the '%' tags of the templates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeMode {
+    /// Convert the UTC start time to the timezone of the templates
+    #[default]
+    Timezone,
+    /// Use the local time recorded by the device, the timezone of the templates without it
+    LocalDevice,
+}
+
+impl FromStr for TimeMode {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "timezone" => Ok(TimeMode::Timezone),
+            "local-device" => Ok(TimeMode::LocalDevice),
+            _ => {
+                let msg = format!(
+                    "Invalid time mode '{}', expected 'timezone' or 'local-device'",
+                    s
+                );
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+impl TimeMode {
+    /// Returns the offset of the local time of an activity to UTC
+    ///
+    /// # Arguments
+    ///
+    /// * `timezone` - Timezone of the templates.
+    /// * `timestamp` - UTC start time of the activity.
+    /// * `device_offset` - Offset of the local time of the device, if recorded.
+    pub(crate) fn offset(
+        &self,
+        timezone: &Timezone,
+        timestamp: DateTime<Utc>,
+        device_offset: Option<FixedOffset>,
+    ) -> FixedOffset {
+        match (self, device_offset) {
+            (TimeMode::LocalDevice, Some(offset)) => offset,
+            _ => timezone.offset(timestamp),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    /// Returns the offset of a timezone in hours at a UTC time
+    fn hours(timezone: &super::Timezone, time: (i32, u32, u32, u32, u32)) -> f64 {
+        let (year, month, day, hour, minute) = time;
+        let timestamp = chrono::Utc
+            .with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap();
+        f64::from(timezone.offset(timestamp).local_minus_utc()) / 3600.0
+    }
+
+    #[test]
+    /// Test the offsets of named timezones
+    fn test_zone_timezone() {
+        let berlin: super::Timezone = "Europe/Berlin".parse().unwrap();
+        assert_eq!(1.0, hours(&berlin, (2026, 3, 29, 0, 59)));
+        assert_eq!(2.0, hours(&berlin, (2026, 3, 29, 1, 0)));
+        assert_eq!(2.0, hours(&berlin, (2026, 10, 25, 0, 59)));
+        assert_eq!(1.0, hours(&berlin, (2026, 10, 25, 1, 0)));
+        // no daylight saving time before 1980
+        assert_eq!(1.0, hours(&berlin, (1975, 7, 15, 12, 0)));
+
+        // daylight saving time spans the turn of the year
+        let sydney: super::Timezone = "Australia/Sydney".parse().unwrap();
+        assert_eq!(11.0, hours(&sydney, (2026, 1, 15, 12, 0)));
+        assert_eq!(10.0, hours(&sydney, (2026, 7, 15, 12, 0)));
+
+        let kolkata: super::Timezone = "Asia/Kolkata".parse().unwrap();
+        assert_eq!(5.5, hours(&kolkata, (2026, 7, 15, 12, 0)));
+        let new_york: super::Timezone = "America/New_York".parse().unwrap();
+        assert_eq!(-4.0, hours(&new_york, (2026, 7, 15, 12, 0)));
+        assert_eq!(-5.0, hours(&new_york, (2026, 1, 15, 12, 0)));
+
+        // the date follows the timezone
+        let timestamp = chrono::Utc
+            .with_ymd_and_hms(2026, 7, 14, 22, 30, 0)
+            .unwrap();
+        assert_eq!(
+            chrono::NaiveDate::from_ymd_opt(2026, 7, 15).unwrap(),
+            berlin.date(timestamp)
+        );
+        assert_eq!(
+            chrono::NaiveDate::from_ymd_opt(2026, 7, 14).unwrap(),
+            new_york.date(timestamp)
+        );
+    }
+
+    #[test]
+    /// Test parsing timezones
+    fn test_timezone() {
+        let kathmandu: super::Timezone = "+05:45".parse().unwrap();
+        assert_eq!(5.75, hours(&kathmandu, (2026, 7, 15, 12, 0)));
+        let azores: super::Timezone = "-01:00".parse().unwrap();
+        assert_eq!(-1.0, hours(&azores, (2026, 7, 15, 12, 0)));
+
+        assert_eq!(super::Timezone::default(), "UTC".parse().unwrap());
+        assert_eq!(super::Timezone::default(), "utc".parse().unwrap());
+        assert_eq!(
+            super::Rules::Local,
+            "local".parse::<super::Timezone>().unwrap().rules
+        );
+        for timezone in [
+            "",
+            "Mars/Olympus_Mons",
+            "CET-1CEST,M3.5.0,M10.5.0/3",
+            "+25:00",
+            "../../etc/passwd",
+        ] {
+            timezone.parse::<super::Timezone>().expect_err(timezone);
+        }
+    }
+
+    #[test]
+    /// Test choosing between the local time of the device and the timezone
+    fn test_time_mode() {
+        let timestamp = chrono::Utc.with_ymd_and_hms(2026, 7, 15, 12, 0, 0).unwrap();
+        let berlin: super::Timezone = "Europe/Berlin".parse().unwrap();
+        let device = chrono::FixedOffset::west_opt(4 * 3600);
+
+        let mode: super::TimeMode = "local-device".parse().unwrap();
+        assert_eq!(device.unwrap(), mode.offset(&berlin, timestamp, device));
+        assert_eq!(
+            7200,
+            mode.offset(&berlin, timestamp, None).local_minus_utc()
+        );
+        let mode: super::TimeMode = "timezone".parse().unwrap();
+        assert_eq!(
+            7200,
+            mode.offset(&berlin, timestamp, device).local_minus_utc()
+        );
+        "device"
+            .parse::<super::TimeMode>()
+            .expect_err("error expected");
+    }
+}
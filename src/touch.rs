@@ -0,0 +1,145 @@
+//! # Modification times of an existing archive
+
+use crate::fitarchiver::{find_fit_files, parse_fit_file, ArchiverError, Result};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Options controlling the update of modification times
+#[derive(Debug, Clone)]
+pub struct TouchOptions {
+    /// Base directory of the archive
+    pub directory: PathBuf,
+    /// Do not modify the archive, just show what will happen
+    pub dry_run: bool,
+}
+
+impl Default for TouchOptions {
+    fn default() -> Self {
+        TouchOptions {
+            directory: PathBuf::from("."),
+            dry_run: false,
+        }
+    }
+}
+
+/// Set the modification time of a file
+///
+/// # Arguments
+///
+/// `path` - Path to the file.
+/// `modified` - New modification time.
+fn set_modified(path: &Path, modified: SystemTime) -> Result<()> {
+    let msg = format!("Unable to set modification time of '{}'", path.display());
+    // opened for writing as some platforms require write access to change the times
+    let file = File::options()
+        .write(true)
+        .open(path)
+        .map_err(|err| ArchiverError::io(&msg, err))?;
+    file.set_modified(modified)
+        .map_err(|err| ArchiverError::io(&msg, err))
+}
+
+/// Set the modification time of all files in an archive to the start of their activity
+///
+/// # Arguments
+///
+/// `options` - Touch options.
+pub fn touch(options: &TouchOptions) -> Result<String> {
+    let files = find_fit_files(&options.directory)?;
+
+    let mut touch_counter: usize = 0;
+    let mut error_counter: usize = 0;
+    for path in &files {
+        let result = parse_fit_file(path).and_then(|activity_data| {
+            let modified = SystemTime::from(activity_data.timestamp);
+            let current = match fs::metadata(path).and_then(|metadata| metadata.modified()) {
+                Ok(val) => val,
+                Err(err) => {
+                    let msg = format!("Unable to read modification time of '{}'", path.display());
+                    return Err(ArchiverError::io(&msg, err));
+                }
+            };
+
+            if current == modified {
+                Ok(None)
+            } else if options.dry_run {
+                Ok(Some("dry run"))
+            } else {
+                set_modified(path, modified).map(|_| Some("touched"))
+            }
+        });
+
+        match result {
+            Ok(Some(status)) => {
+                println!("'{}' ... {}", path.display(), status);
+                touch_counter += 1;
+            }
+            Ok(None) => (),
+            Err(err) => {
+                eprintln!("{}", err);
+                error_counter += 1;
+            }
+        }
+    }
+
+    let msg = format!("Touched {} of {} files", touch_counter, files.len());
+    let err = if error_counter == 0 {
+        String::new()
+    } else {
+        format!("with {} errors.", error_counter)
+    };
+
+    Ok([msg, err].join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeZone, Utc};
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test setting the modification time to the start of the activity
+    fn test_touch() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let file = tmpdir.path().join("2023/07/run.fit");
+        fs::create_dir_all(file.parent().unwrap())
+            .expect("error during creating temporary directory");
+        fs::copy(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("test")
+                .join("test_data_01.fit"),
+            &file,
+        )
+        .expect("unable to create test file");
+        fs::write(tmpdir.path().join("2023/07/broken.fit"), "no FIT file")
+            .expect("unable to create test file");
+        let modified = || DateTime::<Utc>::from(fs::metadata(&file).unwrap().modified().unwrap());
+        let start = Utc.with_ymd_and_hms(2023, 7, 26, 6, 22, 4).unwrap();
+
+        let mut options = super::TouchOptions {
+            directory: tmpdir.path().to_path_buf(),
+            dry_run: true,
+        };
+        assert_eq!(
+            "Touched 1 of 2 files with 1 errors.",
+            super::touch(&options).unwrap()
+        );
+        assert_ne!(start, modified());
+
+        options.dry_run = false;
+        super::touch(&options).unwrap();
+        assert_eq!(start, modified());
+
+        // files with the correct modification time are not touched again
+        assert_eq!(
+            "Touched 0 of 2 files with 1 errors.",
+            super::touch(&options).unwrap()
+        );
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
@@ -0,0 +1,178 @@
+//! # Upgrade of the index of an existing archive
+//!
+//! The schema of the index is migrated whenever it is opened for writing, but columns added by a
+//! migration stay empty for the files archived before. Backfilling parses these files again to
+//! populate the new columns. The entries are written in chunks, so an interrupted backfill of a
+//! huge archive continues with the remaining files when it is started again.
+
+use crate::fitarchiver::{parallel_map, parse_fit_file, ArchiverError, Result};
+use crate::index::{Index, INDEX_PATH};
+use std::path::PathBuf;
+
+/// Number of files whose entries are written in one transaction
+const CHUNK_SIZE: usize = 100;
+
+/// Options controlling the upgrade of the index
+#[derive(Debug, Clone)]
+pub struct UpgradeOptions {
+    /// Base directory of the archive
+    pub directory: PathBuf,
+    /// Parse archived files again to populate columns added by the upgrade
+    pub backfill: bool,
+    /// Number of files parsed in parallel
+    pub jobs: usize,
+}
+
+impl Default for UpgradeOptions {
+    fn default() -> Self {
+        UpgradeOptions {
+            directory: PathBuf::from("."),
+            backfill: false,
+            jobs: 1,
+        }
+    }
+}
+
+/// Migrate the index to the current schema and optionally backfill the new columns
+///
+/// # Arguments
+///
+/// `options` - Upgrade options.
+pub fn upgrade_index(options: &UpgradeOptions) -> Result<String> {
+    if !options.directory.join(INDEX_PATH).exists() {
+        let msg = format!(
+            "No index found in archive '{}'",
+            options.directory.display()
+        );
+        return Err(ArchiverError::new(&msg));
+    }
+    // opening for writing migrates the schema
+    let Some(index) = Index::open(&options.directory, true)? else {
+        return Ok(String::new());
+    };
+    let mut msg = format!("Upgraded index to version {}", index.version()?);
+    if !options.backfill {
+        return Ok(msg);
+    }
+
+    let outdated = index.outdated_paths()?;
+    let mut backfill_counter: usize = 0;
+    let mut error_counter: usize = 0;
+    for chunk in outdated.chunks(CHUNK_SIZE) {
+        let parsed = parallel_map(chunk, options.jobs, |path| parse_fit_file(path));
+        index.transaction(|index| {
+            for (path, parsed) in chunk.iter().zip(parsed) {
+                match parsed.and_then(|activity_data| index.backfill(path, &activity_data)) {
+                    Ok(()) => {
+                        println!("'{}' ... backfilled", path.display());
+                        backfill_counter += 1;
+                    }
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        error_counter += 1;
+                    }
+                }
+            }
+            Ok(())
+        })?;
+    }
+
+    msg.push_str(&format!(", backfilled {} files", backfill_counter));
+    let err = if error_counter == 0 {
+        String::new()
+    } else {
+        format!("with {} errors.", error_counter)
+    };
+
+    Ok([msg, err].join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test backfilling the columns of entries created before they were added
+    fn test_upgrade_index() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let file = tmpdir.path().join("2023/07/run.fit");
+        fs::create_dir_all(file.parent().unwrap())
+            .expect("error during creating temporary directory");
+        fs::copy(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("test")
+                .join("test_data_01.fit"),
+            &file,
+        )
+        .expect("unable to copy test file");
+        let mut options = super::UpgradeOptions {
+            directory: tmpdir.path().to_path_buf(),
+            backfill: true,
+            jobs: 2,
+        };
+        super::upgrade_index(&options).expect_err("error expected");
+
+        // entries of an older version lack the columns added later
+        let index = crate::index::Index::open(tmpdir.path(), true)
+            .unwrap()
+            .unwrap();
+        let activity_data = crate::fitarchiver::ActivityData {
+            notes: String::from("Race"),
+            ..crate::fitarchiver::ActivityData::new()
+        };
+        index.add(&file, &activity_data).unwrap();
+        index
+            .add(&tmpdir.path().join("2023/07/gone.fit"), &activity_data)
+            .unwrap();
+        drop(index);
+        let path = tmpdir.path().join(crate::index::INDEX_PATH);
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "UPDATE activities SET duration = NULL, distance = NULL, entry_version = 0",
+            )
+            .unwrap();
+
+        options.backfill = false;
+        let version = super::upgrade_index(&options).unwrap();
+        options.backfill = true;
+        assert_eq!(
+            format!("{}, backfilled 1 files with 1 errors.", version),
+            super::upgrade_index(&options).unwrap()
+        );
+        let index = crate::index::Index::open(tmpdir.path(), false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            vec![tmpdir.path().join("2023/07/gone.fit")],
+            index.outdated_paths().unwrap()
+        );
+        assert!(index
+            .monthly_stats()
+            .unwrap()
+            .iter()
+            .any(|row| row.distance > 0.0));
+
+        // the notes given at archiving time are kept
+        let notes: String = rusqlite::Connection::open(&path)
+            .unwrap()
+            .query_row(
+                "SELECT notes FROM activities WHERE path = '2023/07/run.fit'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!("Race", notes);
+
+        // a repeated backfill continues with the remaining entries
+        assert_eq!(
+            format!("{}, backfilled 0 files with 1 errors.", version),
+            super::upgrade_index(&options).unwrap()
+        );
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
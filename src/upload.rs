@@ -0,0 +1,891 @@
+//! # Upload of archived activities
+//!
+//! Newly archived activities are uploaded to Strava with the `curl` command, so archiving is the
+//! only command needed after a workout. Strava grants access with OAuth tokens. The client ID and
+//! secret of an API application registered by the user and a refresh token authorized with the
+//! scope 'activity:write' are read from environment variables on the first upload. The access
+//! token and the refresh token replacing the old one on each refresh are stored in the
+//! configuration directory of the user, so the refresh token is needed only once.
+//!
+//! The content hashes of the uploaded files are recorded in the archive and files uploaded before
+//! are not uploaded again. Activities Strava already has, i.e. synchronized from the device, are
+//! recorded as uploaded as well.
+//!
+//! Archived files are queued in the archive and uploaded gradually within the rate limits of
+//! Strava, so a large historical import is continued by the following runs. A rejected request
+//! because of the rate limit blocks the uploads until the next 15 minute window of Strava, failed
+//! connections are retried with an increasing delay.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use crate::objects::content_hash;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+/// Environment variable with the client ID of the Strava API application
+pub const STRAVA_CLIENT_ID_VARIABLE: &str = "FITARCHIVER_STRAVA_CLIENT_ID";
+
+/// Environment variable with the client secret of the Strava API application
+pub const STRAVA_CLIENT_SECRET_VARIABLE: &str = "FITARCHIVER_STRAVA_CLIENT_SECRET";
+
+/// Environment variable with the initial refresh token of the Strava API application
+pub const STRAVA_REFRESH_TOKEN_VARIABLE: &str = "FITARCHIVER_STRAVA_REFRESH_TOKEN";
+
+/// Path of the file listing the content hashes of the uploaded files relative to the archive
+/// directory
+pub const STRAVA_UPLOADS_PATH: &str = ".fitarchiver/strava-uploads";
+
+/// Path of the file with the queue of pending uploads relative to the archive directory
+pub const STRAVA_QUEUE_PATH: &str = ".fitarchiver/strava-queue.json";
+
+/// Default maximum number of uploads per day (UTC)
+pub const DEFAULT_UPLOAD_DAILY_LIMIT: u32 = 500;
+
+/// Length of the rate limit windows of Strava in seconds
+const RATE_LIMIT_WINDOW: i64 = 15 * 60;
+
+/// Maximum delay of retrying a failed upload in seconds
+const MAX_RETRY_DELAY: i64 = 24 * 60 * 60;
+
+/// Path of the token file relative to the configuration directory of the user
+const TOKEN_PATH: &str = "fitarchiver/strava-token.json";
+
+/// Base URL of the Strava API
+const API_URL: &str = "https://www.strava.com/api/v3";
+
+/// URL refreshing the OAuth tokens
+const TOKEN_URL: &str = "https://www.strava.com/oauth/token";
+
+/// Seconds before its expiry an access token is refreshed
+const EXPIRY_MARGIN: i64 = 300;
+
+/// Number of times the processing of an upload is checked
+const STATUS_CHECKS: u32 = 10;
+
+/// Online service the archived activities are uploaded to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadService {
+    /// Upload to Strava
+    Strava,
+}
+
+impl FromStr for UploadService {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "strava" => Ok(UploadService::Strava),
+            _ => {
+                let msg = format!("Invalid upload service '{}', expected 'strava'", s);
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+}
+
+/// Result of uploading a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Upload {
+    /// The activity was uploaded
+    Uploaded,
+    /// The file was uploaded before
+    UploadedBefore,
+    /// The service already has the activity
+    Duplicate,
+}
+
+/// Outcome of an attempt to upload a queued file
+#[derive(Debug)]
+enum Attempt {
+    /// The file is uploaded or needs no upload
+    Done(Upload),
+    /// The rate limit of the service is exceeded, the file stays queued
+    RateLimited,
+    /// The upload failed temporarily, i.e. without connection, and is retried later
+    Retry(ArchiverError),
+    /// The upload was rejected and is not retried
+    Rejected(ArchiverError),
+}
+
+/// Archive file waiting for its upload
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pending {
+    /// Path of the archive file relative to the archive directory
+    path: PathBuf,
+    /// Number of failed attempts
+    attempts: u32,
+    /// Earliest time of the next attempt in seconds since the epoch
+    retry_at: i64,
+}
+
+/// Queue of the uploads persisted in the archive
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Queue {
+    /// Files waiting for their upload in the order they were archived
+    pending: Vec<Pending>,
+    /// No request is sent before this time in seconds since the epoch
+    blocked_until: i64,
+    /// Day of the counted uploads, i.e. '2025-01-06'
+    day: String,
+    /// Number of uploads of the day
+    uploads: u32,
+}
+
+impl Queue {
+    /// Returns the queue stored in a file, an empty queue if there is none
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - Content of the queue file.
+    fn from_json(content: &Value) -> Queue {
+        let number = |value: &Value, name: &str| value.get(name).and_then(Value::as_i64);
+        let pending = content
+            .get("pending")
+            .and_then(Value::as_array)
+            .map(|pending| {
+                pending
+                    .iter()
+                    .filter_map(|entry| {
+                        Some(Pending {
+                            path: PathBuf::from(entry.get("path")?.as_str()?),
+                            attempts: number(entry, "attempts").unwrap_or(0) as u32,
+                            retry_at: number(entry, "retry_at").unwrap_or(0),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Queue {
+            pending,
+            blocked_until: number(content, "blocked_until").unwrap_or(0),
+            day: content
+                .get("day")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            uploads: number(content, "uploads").unwrap_or(0) as u32,
+        }
+    }
+
+    /// Returns the queue as content of the queue file
+    fn to_json(&self) -> Value {
+        serde_json::json!({
+            "pending": self.pending.iter().map(|pending| serde_json::json!({
+                "path": pending.path,
+                "attempts": pending.attempts,
+                "retry_at": pending.retry_at,
+            })).collect::<Vec<_>>(),
+            "blocked_until": self.blocked_until,
+            "day": self.day,
+            "uploads": self.uploads,
+        })
+    }
+
+    /// Add a file to the end of the queue unless it is queued already
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the archive file relative to the archive directory.
+    fn push(&mut self, path: PathBuf) {
+        if !self.pending.iter().any(|pending| pending.path == path) {
+            self.pending.push(Pending {
+                path,
+                attempts: 0,
+                retry_at: 0,
+            });
+        }
+    }
+
+    /// Returns the number of uploads of the day
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Current time.
+    fn uploads_of_day(&self, now: DateTime<Utc>) -> u32 {
+        match self.day == now.format("%F").to_string() {
+            true => self.uploads,
+            false => 0,
+        }
+    }
+
+    /// Count an upload against the daily limit
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Current time.
+    fn count(&mut self, now: DateTime<Utc>) {
+        self.uploads = self.uploads_of_day(now) + 1;
+        self.day = now.format("%F").to_string();
+    }
+
+    /// Returns the time uploads are possible again or `None` if they are possible now
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Current time.
+    /// * `daily_limit` - Maximum number of uploads per day.
+    fn resume_at(&self, now: DateTime<Utc>, daily_limit: u32) -> Option<DateTime<Utc>> {
+        if self.uploads_of_day(now) >= daily_limit {
+            let tomorrow = now.date_naive().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            return Some(tomorrow.and_utc());
+        }
+        match now.timestamp() < self.blocked_until {
+            true => DateTime::from_timestamp(self.blocked_until, 0),
+            false => None,
+        }
+    }
+}
+
+/// Returns the start of the next rate limit window of Strava, windows start at the quarter hours
+///
+/// # Arguments
+///
+/// * `now` - Current time.
+fn next_window(now: DateTime<Utc>) -> i64 {
+    (now.timestamp() / RATE_LIMIT_WINDOW + 1) * RATE_LIMIT_WINDOW
+}
+
+/// Returns the delay of the next attempt after failed attempts in seconds
+///
+/// The delay starts with one minute and doubles with each attempt up to a day.
+///
+/// # Arguments
+///
+/// * `attempts` - Number of failed attempts.
+fn retry_delay(attempts: u32) -> i64 {
+    60i64
+        .saturating_mul(1 << attempts.saturating_sub(1).min(20))
+        .min(MAX_RETRY_DELAY)
+}
+
+/// Progress of the queued uploads
+#[derive(Debug, Default)]
+pub(crate) struct UploadProgress {
+    /// Number of files uploaded by this call
+    pub(crate) uploaded: usize,
+    /// Errors of files that failed to be uploaded
+    pub(crate) errors: Vec<ArchiverError>,
+    /// Number of files still waiting for their upload
+    pub(crate) pending: usize,
+    /// Time the rate limit allows uploads again, `None` if it did not stop the uploads
+    pub(crate) resume_at: Option<DateTime<Utc>>,
+}
+
+/// OAuth tokens of the Strava API application
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    /// Client ID of the application
+    client_id: String,
+    /// Client secret of the application
+    client_secret: String,
+    /// Token requesting a new access token
+    refresh_token: String,
+    /// Token authorizing the requests, empty if none was requested yet
+    access_token: String,
+    /// Expiry of the access token in seconds since the epoch
+    expires_at: i64,
+}
+
+impl Token {
+    /// Returns the tokens of the token file completed by the environment variables
+    ///
+    /// The client ID and secret of the environment variables take precedence, the refresh token
+    /// of the environment variable is only used without token file, because Strava replaces it.
+    ///
+    /// # Arguments
+    ///
+    /// * `stored` - Content of the token file, `None` if there is none.
+    /// * `variable` - Returns the value of an environment variable.
+    fn load(stored: Option<&Value>, variable: impl Fn(&str) -> Option<String>) -> Result<Token> {
+        let field = |name: &str| {
+            stored
+                .and_then(|stored| stored.get(name))
+                .and_then(|value| match value {
+                    Value::String(text) => Some(text.clone()),
+                    Value::Number(number) => Some(number.to_string()),
+                    _ => None,
+                })
+        };
+        let refresh_token = match stored {
+            Some(_) => field("refresh_token"),
+            None => variable(STRAVA_REFRESH_TOKEN_VARIABLE),
+        };
+        let (Some(client_id), Some(client_secret), Some(refresh_token)) = (
+            variable(STRAVA_CLIENT_ID_VARIABLE).or_else(|| field("client_id")),
+            variable(STRAVA_CLIENT_SECRET_VARIABLE).or_else(|| field("client_secret")),
+            refresh_token,
+        ) else {
+            let msg = format!(
+                "Missing Strava credentials, set {}, {} and {}",
+                STRAVA_CLIENT_ID_VARIABLE,
+                STRAVA_CLIENT_SECRET_VARIABLE,
+                STRAVA_REFRESH_TOKEN_VARIABLE
+            );
+            return Err(ArchiverError::new(&msg));
+        };
+        Ok(Token {
+            client_id,
+            client_secret,
+            refresh_token,
+            access_token: field("access_token").unwrap_or_default(),
+            expires_at: stored
+                .and_then(|stored| stored.get("expires_at"))
+                .and_then(Value::as_i64)
+                .unwrap_or(0),
+        })
+    }
+
+    /// Returns the tokens as content of the token file
+    fn to_json(&self) -> Value {
+        serde_json::json!({
+            "client_id": self.client_id,
+            "client_secret": self.client_secret,
+            "refresh_token": self.refresh_token,
+            "access_token": self.access_token,
+            "expires_at": self.expires_at,
+        })
+    }
+
+    /// Returns the tokens updated by the response of a token refresh
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - Response of the token URL.
+    fn refreshed(&self, response: &Value) -> Option<Token> {
+        Some(Token {
+            refresh_token: response.get("refresh_token")?.as_str()?.to_string(),
+            access_token: response.get("access_token")?.as_str()?.to_string(),
+            expires_at: response.get("expires_at")?.as_i64()?,
+            ..self.clone()
+        })
+    }
+}
+
+/// Returns a text quoted for the configuration of curl
+///
+/// # Arguments
+///
+/// * `text` - Text to quote.
+fn config_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Send a request with curl and return the HTTP status code and the JSON response
+///
+/// Secrets are passed in the configuration on the standard input of curl, so they do not show
+/// up in the list of processes.
+///
+/// # Arguments
+///
+/// * `config` - Configuration of curl, i.e. the authorization header.
+/// * `args` - Arguments of curl selecting the request, i.e. '--form'.
+/// * `url` - URL of the request.
+fn request(config: &str, args: &[&str], url: &str) -> Result<(u16, Value)> {
+    let msg = format!("Unable to run curl for '{}'", url);
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--config", "-"])
+        .args(["--write-out", "\n%{http_code}"])
+        .args(args)
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| ArchiverError::io(&msg, err))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(config.as_bytes())
+            .map_err(|err| ArchiverError::io(&msg, err))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|err| ArchiverError::io(&msg, err))?;
+    if !output.status.success() {
+        let msg = format!(
+            "Unable to connect to '{}': {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return Err(ArchiverError::new(&msg));
+    }
+
+    // the status code is written after the response
+    let response = String::from_utf8_lossy(&output.stdout);
+    let (body, code) = response.rsplit_once('\n').unwrap_or(("", &response));
+    match code.trim().parse() {
+        Ok(code) => Ok((code, serde_json::from_str(body).unwrap_or(Value::Null))),
+        Err(_) => {
+            let msg = format!("Invalid response of '{}'", url);
+            Err(ArchiverError::new(&msg))
+        }
+    }
+}
+
+/// Returns the result of a processed upload or `None` while Strava is still processing it
+///
+/// # Arguments
+///
+/// * `path` - Path of the uploaded file.
+/// * `status` - Status of the upload as returned by the Strava API.
+fn upload_result(path: &Path, status: &Value) -> Option<Result<Upload>> {
+    if let Some(error) = status.get("error").and_then(Value::as_str) {
+        // i.e. 'run.fit duplicate of activity 123456789'
+        if error.contains("duplicate of") {
+            return Some(Ok(Upload::Duplicate));
+        }
+        let msg = format!("Unable to upload '{}' to Strava: {}", path.display(), error);
+        return Some(Err(ArchiverError::new(&msg)));
+    }
+    match status.get("activity_id") {
+        Some(Value::Number(_)) => Some(Ok(Upload::Uploaded)),
+        _ => None,
+    }
+}
+
+/// Uploader of archived activities to Strava
+#[derive(Debug)]
+pub(crate) struct Uploader {
+    /// Base directory of the archive
+    directory: PathBuf,
+    /// Tokens authorizing the uploads
+    token: Token,
+    /// Content hashes of the files uploaded before
+    uploaded: HashSet<String>,
+    /// Files waiting for their upload and the state of the rate limit
+    queue: Queue,
+    /// Maximum number of uploads per day
+    daily_limit: u32,
+}
+
+impl Uploader {
+    /// Returns an uploader with a valid access token
+    ///
+    /// The access token is refreshed if it expires soon.
+    ///
+    /// # Arguments
+    ///
+    /// * `service` - Online service the activities are uploaded to.
+    /// * `directory` - Base directory of the archive.
+    /// * `daily_limit` - Maximum number of uploads per day.
+    pub(crate) fn open(
+        service: UploadService,
+        directory: &Path,
+        daily_limit: u32,
+    ) -> Result<Uploader> {
+        let UploadService::Strava = service;
+        let token_path = token_path();
+        let stored = token_path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str::<Value>(&content).ok());
+        let mut token = Token::load(stored.as_ref(), |name| env::var(name).ok())?;
+        if token.access_token.is_empty()
+            || token.expires_at < chrono::Utc::now().timestamp() + EXPIRY_MARGIN
+        {
+            token = refresh(&token)?;
+            if let Some(path) = &token_path {
+                write_token(path, &token)?;
+            }
+        }
+
+        let uploaded = fs::read_to_string(directory.join(STRAVA_UPLOADS_PATH))
+            .map(|content| content.lines().map(String::from).collect())
+            .unwrap_or_default();
+        let queue_path = directory.join(STRAVA_QUEUE_PATH);
+        let queue = match fs::read_to_string(&queue_path) {
+            Ok(content) => match serde_json::from_str::<Value>(&content) {
+                Ok(content) => Queue::from_json(&content),
+                Err(_) => {
+                    let msg = format!("Invalid upload queue '{}'", queue_path.display());
+                    return Err(ArchiverError::new(&msg));
+                }
+            },
+            Err(_) => Queue::default(),
+        };
+        Ok(Uploader {
+            directory: directory.to_path_buf(),
+            token,
+            uploaded,
+            queue,
+            daily_limit,
+        })
+    }
+
+    /// Queue an archived file for its upload
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the archive file.
+    pub(crate) fn enqueue(&mut self, path: &Path) {
+        let path = path.strip_prefix(&self.directory).unwrap_or(path);
+        self.queue.push(path.to_path_buf());
+    }
+
+    /// Upload the queued files within the rate limit and store the remaining queue
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Current time.
+    pub(crate) fn upload_pending(&mut self, now: DateTime<Utc>) -> Result<UploadProgress> {
+        let mut progress = UploadProgress::default();
+        let mut index = 0;
+        while index < self.queue.pending.len()
+            && self.queue.resume_at(now, self.daily_limit).is_none()
+        {
+            let pending = &self.queue.pending[index];
+            if pending.retry_at > now.timestamp() {
+                index += 1;
+                continue;
+            }
+            match self.send(&self.directory.join(&pending.path), now) {
+                Attempt::Done(upload) => {
+                    if upload != Upload::UploadedBefore {
+                        self.queue.count(now);
+                        progress.uploaded += 1;
+                    }
+                    self.queue.pending.remove(index);
+                }
+                Attempt::RateLimited => self.queue.blocked_until = next_window(now),
+                Attempt::Retry(err) => {
+                    let pending = &mut self.queue.pending[index];
+                    pending.attempts += 1;
+                    pending.retry_at = now.timestamp() + retry_delay(pending.attempts);
+                    progress.errors.push(err);
+                    index += 1;
+                }
+                Attempt::Rejected(err) => {
+                    self.queue.pending.remove(index);
+                    progress.errors.push(err);
+                }
+            }
+        }
+        progress.pending = self.queue.pending.len();
+        progress.resume_at = self.queue.resume_at(now, self.daily_limit);
+        self.save()?;
+        Ok(progress)
+    }
+
+    /// Write the queue to the archive
+    fn save(&self) -> Result<()> {
+        let path = self.directory.join(STRAVA_QUEUE_PATH);
+        let msg = format!("Unable to write file '{}'", path.display());
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| ArchiverError::io(&msg, err))?;
+        }
+        fs::write(&path, format!("{:#}\n", self.queue.to_json()))
+            .map_err(|err| ArchiverError::io(&msg, err))
+    }
+
+    /// Upload an archived file unless it was uploaded before
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the archive file.
+    /// * `now` - Current time.
+    fn send(&mut self, path: &Path, now: DateTime<Utc>) -> Attempt {
+        let msg = format!("Unable to read file '{}'", path.display());
+        let hash = match File::open(path).and_then(content_hash) {
+            Ok(hash) => hash,
+            Err(err) => return Attempt::Rejected(ArchiverError::io(&msg, err)),
+        };
+        if self.uploaded.contains(&hash) {
+            return Attempt::Done(Upload::UploadedBefore);
+        }
+
+        let config = format!(
+            "header = {}\n",
+            config_string(&format!(
+                "Authorization: Bearer {}",
+                self.token.access_token
+            ))
+        );
+        let file = format!("file=@{}", config_string(&path.to_string_lossy()));
+        let external_id = format!("external_id={}", hash);
+        let response = request(
+            &config,
+            &[
+                "--form",
+                &file,
+                "--form",
+                "data_type=fit",
+                "--form",
+                &external_id,
+            ],
+            &format!("{}/uploads", API_URL),
+        );
+        let mut status = match response {
+            Ok((201, status)) => status,
+            Ok((429, _)) => return Attempt::RateLimited,
+            Ok((code, _)) => {
+                let msg = format!(
+                    "Unable to upload '{}' to Strava: HTTP status {}",
+                    path.display(),
+                    code
+                );
+                return match code {
+                    500..=599 => Attempt::Retry(ArchiverError::new(&msg)),
+                    _ => Attempt::Rejected(ArchiverError::new(&msg)),
+                };
+            }
+            Err(err) => return Attempt::Retry(err),
+        };
+
+        // Strava processes uploads in the background, duplicates are only reported afterwards
+        let url = format!(
+            "{}/uploads/{}",
+            API_URL,
+            status.get("id").and_then(Value::as_u64).unwrap_or(0)
+        );
+        let mut result = upload_result(path, &status);
+        for _ in 0..STATUS_CHECKS {
+            if result.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_secs(1));
+            match request(&config, &[], &url) {
+                Ok((429, _)) => {
+                    self.queue.blocked_until = next_window(now);
+                    break;
+                }
+                Ok((_, response)) => status = response,
+                Err(_) => break,
+            }
+            result = upload_result(path, &status);
+        }
+        // an upload still processing is finished by Strava on its own
+        match result.unwrap_or(Ok(Upload::Uploaded)) {
+            Ok(upload) => match self.record(hash) {
+                Ok(_) => Attempt::Done(upload),
+                Err(err) => Attempt::Rejected(err),
+            },
+            Err(err) => Attempt::Rejected(err),
+        }
+    }
+
+    /// Record the content hash of an uploaded file in the archive
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - Content hash of the file.
+    fn record(&mut self, hash: String) -> Result<()> {
+        let path = self.directory.join(STRAVA_UPLOADS_PATH);
+        let msg = format!("Unable to write file '{}'", path.display());
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| ArchiverError::io(&msg, err))?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| writeln!(file, "{}", hash))
+            .map_err(|err| ArchiverError::io(&msg, err))?;
+        self.uploaded.insert(hash);
+        Ok(())
+    }
+}
+
+/// Returns the path of the token file of the user
+///
+/// The configuration directory is `$XDG_CONFIG_HOME` or `~/.config`.
+fn token_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|directory| !directory.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|directory| directory.join(TOKEN_PATH))
+}
+
+/// Returns the tokens with a new access token
+///
+/// # Arguments
+///
+/// * `token` - Tokens with the refresh token.
+fn refresh(token: &Token) -> Result<Token> {
+    let config = [
+        ("client_id", &token.client_id),
+        ("client_secret", &token.client_secret),
+        ("refresh_token", &token.refresh_token),
+    ]
+    .iter()
+    .map(|(name, value)| {
+        format!(
+            "data-urlencode = {}\n",
+            config_string(&format!("{}={}", name, value))
+        )
+    })
+    .collect::<String>();
+    let (code, response) = request(&config, &["--data", "grant_type=refresh_token"], TOKEN_URL)?;
+    match (code, token.refreshed(&response)) {
+        (200, Some(token)) => Ok(token),
+        _ => {
+            let msg = format!(
+                "Unable to refresh the Strava access token: HTTP status {}, check {}",
+                code, STRAVA_REFRESH_TOKEN_VARIABLE
+            );
+            Err(ArchiverError::new(&msg))
+        }
+    }
+}
+
+/// Write the tokens to the token file readable by the user only
+///
+/// # Arguments
+///
+/// * `path` - Path of the token file.
+/// * `token` - Tokens to write.
+fn write_token(path: &Path, token: &Token) -> Result<()> {
+    let msg = format!("Unable to write file '{}'", path.display());
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| ArchiverError::io(&msg, err))?;
+    }
+    let mut options = OpenOptions::new();
+    options.create(true).write(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{:#}", token.to_json()))
+        .map_err(|err| ArchiverError::io(&msg, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Queue, Token, Upload, UploadService};
+    use chrono::TimeZone;
+    use std::fs;
+    use std::path::Path;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test loading and refreshing the OAuth tokens
+    fn test_token() {
+        let variables = |name: &str| match name {
+            super::STRAVA_CLIENT_ID_VARIABLE => Some(String::from("4711")),
+            super::STRAVA_CLIENT_SECRET_VARIABLE => Some(String::from("secret")),
+            super::STRAVA_REFRESH_TOKEN_VARIABLE => Some(String::from("initial")),
+            _ => None,
+        };
+        let token = Token::load(None, variables).unwrap();
+        assert_eq!("initial", token.refresh_token);
+        assert_eq!("", token.access_token);
+        Token::load(None, |_| None).expect_err("error expected");
+
+        // Strava replaces the refresh token, the stored one takes precedence
+        let token = token
+            .refreshed(&serde_json::json!({
+                "token_type": "Bearer",
+                "access_token": "access",
+                "refresh_token": "rotated",
+                "expires_at": 1700000000,
+            }))
+            .unwrap();
+        let stored = token.to_json();
+        assert_eq!(token, Token::load(Some(&stored), variables).unwrap());
+        assert_eq!(token, Token::load(Some(&stored), |_| None).unwrap());
+        assert_eq!(
+            None,
+            token.refreshed(&serde_json::json!({"message": "Bad"}))
+        );
+
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let path = tmpdir.path().join("fitarchiver/strava-token.json");
+        super::write_token(&path, &token).unwrap();
+        let content: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(stored, content);
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test the queue of pending uploads and the rate limit
+    fn test_queue() {
+        let now = chrono::Utc.with_ymd_and_hms(2025, 1, 6, 10, 5, 0).unwrap();
+        let mut queue = Queue::default();
+        queue.push(PathBuf::from("2025/01/run.fit"));
+        queue.push(PathBuf::from("2025/01/ride.fit"));
+        queue.push(PathBuf::from("2025/01/run.fit"));
+        assert_eq!(2, queue.pending.len());
+        assert_eq!(None, queue.resume_at(now, 2));
+
+        // the queue survives the run
+        queue.pending[1].attempts = 2;
+        queue.pending[1].retry_at = 1736157900;
+        assert_eq!(queue, Queue::from_json(&queue.to_json()));
+        assert_eq!(Queue::default(), Queue::from_json(&serde_json::Value::Null));
+
+        // a rejected request blocks the uploads until the next window
+        queue.blocked_until = super::next_window(now);
+        assert_eq!(
+            Some(chrono::Utc.with_ymd_and_hms(2025, 1, 6, 10, 15, 0).unwrap()),
+            queue.resume_at(now, 2)
+        );
+        queue.blocked_until = 0;
+
+        // the daily limit is reset on the next day
+        queue.count(now);
+        queue.count(now);
+        assert_eq!(2, queue.uploads_of_day(now));
+        assert_eq!(
+            Some(chrono::Utc.with_ymd_and_hms(2025, 1, 7, 0, 0, 0).unwrap()),
+            queue.resume_at(now, 2)
+        );
+        let tomorrow = chrono::Utc.with_ymd_and_hms(2025, 1, 7, 0, 0, 1).unwrap();
+        assert_eq!(None, queue.resume_at(tomorrow, 2));
+        queue.count(tomorrow);
+        assert_eq!(1, queue.uploads);
+
+        // failed uploads are retried with increasing delays
+        assert_eq!(60, super::retry_delay(1));
+        assert_eq!(240, super::retry_delay(3));
+        assert_eq!(24 * 60 * 60, super::retry_delay(40));
+    }
+
+    #[test]
+    /// Test evaluating the status of uploads
+    fn test_upload_result() {
+        let path = Path::new("run.fit");
+        assert_eq!(UploadService::Strava, "strava".parse().unwrap());
+        "garmin"
+            .parse::<UploadService>()
+            .expect_err("error expected");
+
+        let status = |error: Option<&str>, activity_id: Option<u64>| {
+            serde_json::json!({
+                "id": 16486788,
+                "error": error,
+                "status": "Your activity is still being processed.",
+                "activity_id": activity_id,
+            })
+        };
+        assert!(super::upload_result(path, &status(None, None)).is_none());
+        assert_eq!(
+            Upload::Uploaded,
+            super::upload_result(path, &status(None, Some(6789)))
+                .unwrap()
+                .unwrap()
+        );
+        assert_eq!(
+            Upload::Duplicate,
+            super::upload_result(
+                path,
+                &status(Some("run.fit duplicate of activity 1234"), None)
+            )
+            .unwrap()
+            .unwrap()
+        );
+        super::upload_result(path, &status(Some("Improperly formatted data."), None))
+            .unwrap()
+            .expect_err("error expected");
+    }
+}
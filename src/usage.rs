@@ -0,0 +1,269 @@
+//! # Local usage statistics
+//!
+//! Users opting in with `--usage-stats` get the runs, the archived files and the errors counted
+//! in `stats.json` of the configuration directory, in total and per month. The file never leaves
+//! the computer, it only shows how the archive grew and how often errors occur with the `doctor`
+//! subcommand.
+
+use crate::fitarchiver::{ArchiverError, Result, Summary};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Path of the usage statistics relative to the configuration directory
+pub const USAGE_STATS_PATH: &str = "fitarchiver/stats.json";
+
+/// Options controlling the health report
+#[derive(Debug, Clone, Default)]
+pub struct DoctorOptions {
+    /// File of the usage statistics, `None` if the configuration directory is unknown
+    pub usage_stats: Option<PathBuf>,
+}
+
+/// Returns the default path of the usage statistics
+///
+/// The configuration directory is `$XDG_CONFIG_HOME` or `~/.config`.
+pub fn usage_stats_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|directory| !directory.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|directory| directory.join(USAGE_STATS_PATH))
+}
+
+/// Returns the usage statistics of a file, empty statistics if the file does not exist
+///
+/// # Arguments
+///
+/// * `path` - Path of the usage statistics.
+fn load(path: &Path) -> Result<Value> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(json!({})),
+        Err(err) => {
+            let msg = format!("Unable to read usage statistics '{}'", path.display());
+            return Err(ArchiverError::io(&msg, err));
+        }
+    };
+    match serde_json::from_str::<Value>(&content) {
+        Ok(stats) if stats.is_object() => Ok(stats),
+        _ => {
+            let msg = format!("Invalid usage statistics '{}'", path.display());
+            Err(ArchiverError::new(&msg))
+        }
+    }
+}
+
+/// Add to a counter of the usage statistics, missing objects and counters are created
+///
+/// # Arguments
+///
+/// * `stats` - Usage statistics.
+/// * `keys` - Keys of the objects containing the counter and of the counter.
+/// * `count` - Number to add.
+fn increment(stats: &mut Value, keys: &[&str], count: usize) {
+    let value = keys.iter().fold(stats, |value, key| {
+        if !value.is_object() {
+            *value = json!({});
+        }
+        value
+            .as_object_mut()
+            .unwrap()
+            .entry(*key)
+            .or_insert(json!(0))
+    });
+    *value = json!(value.as_u64().unwrap_or(0) + count as u64);
+}
+
+/// Returns a counter of the usage statistics, 0 if it is missing
+///
+/// # Arguments
+///
+/// * `stats` - Usage statistics.
+/// * `pointer` - JSON pointer of the counter, i.e. '/files/archived'.
+fn counter(stats: &Value, pointer: &str) -> u64 {
+    stats.pointer(pointer).and_then(Value::as_u64).unwrap_or(0)
+}
+
+/// Count a run in the usage statistics
+///
+/// # Arguments
+///
+/// * `path` - Path of the usage statistics.
+/// * `summary` - Numbers of files of the run.
+/// * `now` - Time of the run.
+pub(crate) fn record_run(path: &Path, summary: &Summary, now: DateTime<Utc>) -> Result<()> {
+    let mut stats = load(path)?;
+    let time = now.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    if stats.get("first_run").is_none() {
+        stats["first_run"] = json!(time);
+    }
+    stats["last_run"] = json!(time);
+
+    let month = now.format("%Y-%m").to_string();
+    for (keys, count) in [
+        (&["runs"][..], 1),
+        (&["files", "archived"], summary.processed),
+        (&["files", "identical"], summary.identical),
+        (&["files", "skipped"], summary.skipped),
+        (&["errors", "total"], summary.errors),
+        (&["errors", "damaged"], summary.damaged),
+        (&["errors", "invalid"], summary.invalid),
+        (&["errors", "kept"], summary.kept),
+        (&["months", &month, "runs"], 1),
+        (&["months", &month, "archived"], summary.processed),
+        (&["months", &month, "errors"], summary.errors),
+    ] {
+        increment(&mut stats, keys, count);
+    }
+
+    let msg = format!("Unable to write usage statistics '{}'", path.display());
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory).map_err(|err| ArchiverError::io(&msg, err))?;
+    }
+    let content = serde_json::to_string_pretty(&stats).unwrap();
+    fs::write(path, content + "\n").map_err(|err| ArchiverError::io(&msg, err))
+}
+
+/// Returns the usage statistics as report with one line per month
+///
+/// # Arguments
+///
+/// * `stats` - Usage statistics.
+fn format_report(stats: &Value) -> String {
+    let date = |key: &str| {
+        stats[key]
+            .as_str()
+            .and_then(|time| time.get(..10))
+            .unwrap_or("unknown")
+            .to_string()
+    };
+    let mut lines = vec![
+        format!(
+            "Runs:            {} since {}, last on {}",
+            counter(stats, "/runs"),
+            date("first_run"),
+            date("last_run")
+        ),
+        format!(
+            "Files archived:  {}, {} already archived, {} skipped",
+            counter(stats, "/files/archived"),
+            counter(stats, "/files/identical"),
+            counter(stats, "/files/skipped")
+        ),
+        format!(
+            "Errors:          {}, {} damaged FIT files, {} not FIT files, kept {} source files",
+            counter(stats, "/errors/total"),
+            counter(stats, "/errors/damaged"),
+            counter(stats, "/errors/invalid"),
+            counter(stats, "/errors/kept")
+        ),
+        String::new(),
+        format!(
+            "{:<7}  {:>6}  {:>8}  {:>6}",
+            "Month", "Runs", "Archived", "Errors"
+        ),
+    ];
+    // the months of the JSON object are ordered by their keys
+    if let Some(months) = stats["months"].as_object() {
+        lines.extend(months.iter().map(|(month, counts)| {
+            format!(
+                "{:<7}  {:>6}  {:>8}  {:>6}",
+                month,
+                counter(counts, "/runs"),
+                counter(counts, "/archived"),
+                counter(counts, "/errors")
+            )
+        }));
+    }
+    lines.join("\n")
+}
+
+/// Returns a report of the usage statistics
+///
+/// # Arguments
+///
+/// `options` - Doctor options.
+pub fn doctor(options: &DoctorOptions) -> Result<String> {
+    let Some(path) = &options.usage_stats else {
+        return Err(ArchiverError::new(
+            "Unable to find the configuration directory, neither XDG_CONFIG_HOME nor HOME is set",
+        ));
+    };
+    if !path.exists() {
+        return Ok(format!(
+            "No usage statistics in '{}', count them with '--usage-stats'",
+            path.display()
+        ));
+    }
+    Ok(format!(
+        "Usage statistics in '{}'\n\n{}",
+        path.display(),
+        format_report(&load(path)?)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test counting runs in the usage statistics
+    fn test_record_run() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let path = tmpdir.path().join("fitarchiver/stats.json");
+        let options = super::DoctorOptions {
+            usage_stats: Some(path.clone()),
+        };
+        assert!(super::doctor(&options)
+            .unwrap()
+            .starts_with("No usage statistics"));
+
+        let summary = crate::fitarchiver::Summary {
+            processed: 12,
+            identical: 3,
+            errors: 2,
+            damaged: 1,
+            ..Default::default()
+        };
+        let september = chrono::Utc.with_ymd_and_hms(2026, 9, 30, 20, 0, 0).unwrap();
+        let october = chrono::Utc.with_ymd_and_hms(2026, 10, 2, 8, 0, 0).unwrap();
+        super::record_run(&path, &summary, september).unwrap();
+        super::record_run(&path, &summary, october).unwrap();
+        super::record_run(&path, &Default::default(), october).unwrap();
+
+        let stats = super::load(&path).unwrap();
+        assert_eq!("2026-09-30T20:00:00Z", stats["first_run"]);
+        assert_eq!("2026-10-02T08:00:00Z", stats["last_run"]);
+        assert_eq!(3, stats["runs"]);
+        assert_eq!(24, stats["files"]["archived"]);
+        assert_eq!(2, stats["errors"]["damaged"]);
+        assert_eq!(0, stats["errors"]["invalid"]);
+        assert_eq!(2, stats["months"]["2026-10"]["runs"]);
+
+        assert_eq!(
+            [
+                format!("Usage statistics in '{}'", path.display()),
+                String::new(),
+                String::from("Runs:            3 since 2026-09-30, last on 2026-10-02"),
+                String::from("Files archived:  24, 6 already archived, 0 skipped"),
+                String::from(
+                    "Errors:          4, 2 damaged FIT files, 0 not FIT files, kept 0 source files"
+                ),
+                String::new(),
+                String::from("Month      Runs  Archived  Errors"),
+                String::from("2026-09       1        12       2"),
+                String::from("2026-10       2        12       2"),
+            ]
+            .join("\n"),
+            super::doctor(&options).unwrap()
+        );
+
+        fs::write(&path, "[]").unwrap();
+        super::record_run(&path, &summary, october).expect_err("error expected");
+    }
+}
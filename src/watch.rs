@@ -0,0 +1,489 @@
+//! # Continuous archiving of new files
+//!
+//! The watched directories, i.e. the mount point of a device, are polled for FIT files. A file is
+//! archived as soon as its size and modification time did not change between two polls, so that
+//! files still being written by the device are not archived half-way.
+//!
+//! Named watch sources, i.e. the mount point of a device, a Zwift folder and a Dropbox export
+//! folder, are watched together with their own template and filters. The files of all sources
+//! are archived one source after the other by the same process, so the sources share the archive
+//! and its index without writing to them at the same time.
+
+use crate::control::{listen, Control};
+use crate::device::device_activity_folders;
+use crate::fitarchiver::{
+    check_source_tree, input_files, print_template_warnings, process_files_summary, ArchiverError,
+    Options, Result,
+};
+use crate::metrics::{serve, Metrics};
+use crate::template::check_template;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Size and modification time of a file
+type FileState = (u64, Option<SystemTime>);
+
+/// Watched directory with its own template and filters, i.e. 'zwift=Zwift/Activities;indoor'
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchSource {
+    /// Name of the source used in the output, i.e. 'zwift'
+    pub name: String,
+    /// Watched directory
+    pub directory: PathBuf,
+    /// Format string of the archive files, the file template of the options if unset
+    pub file_template: Option<String>,
+    /// Additional extensions of FIT files, the extensions of the options if empty
+    pub extensions: Vec<String>,
+    /// Only archive activities with GPS positions
+    pub require_gps: bool,
+    /// Only archive activities without GPS positions
+    pub indoor_only: bool,
+}
+
+impl FromStr for WatchSource {
+    type Err = ArchiverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            let msg = format!(
+                "Invalid watch source '{}', expected '<name>=<directory>[;template=<template>][;extension=<extension>][;gps|;indoor]'",
+                s
+            );
+            ArchiverError::new(&msg)
+        };
+
+        let (name, rest) = s.split_once('=').ok_or_else(invalid)?;
+        let mut settings = rest.split(';');
+        let directory = settings.next().unwrap_or_default();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            || directory.is_empty()
+        {
+            return Err(invalid());
+        }
+        let mut source = WatchSource {
+            name: name.to_string(),
+            directory: PathBuf::from(directory),
+            file_template: None,
+            extensions: Vec::new(),
+            require_gps: false,
+            indoor_only: false,
+        };
+        for setting in settings {
+            match setting.split_once('=') {
+                Some(("template", template)) => {
+                    check_template(template)?;
+                    source.file_template = Some(template.to_string());
+                }
+                Some(("extension", extension)) => {
+                    source
+                        .extensions
+                        .push(extension.trim_start_matches('.').to_string());
+                }
+                None if setting == "gps" => source.require_gps = true,
+                None if setting == "indoor" => source.indoor_only = true,
+                _ => return Err(invalid()),
+            }
+        }
+        if source.require_gps && source.indoor_only {
+            return Err(invalid());
+        }
+        Ok(source)
+    }
+}
+
+/// Returns the options archiving the files of each watched source with their name
+///
+/// The directories in the list of files and the devices form an unnamed source with the options
+/// themselves. The named sources come first, so a file in several watched directories belongs to
+/// the named source.
+///
+/// # Arguments
+///
+/// * `options` - Archiver options with the watch sources.
+fn source_options(options: &Options) -> Vec<(Option<&str>, Options)> {
+    let mut sources: Vec<(Option<&str>, Options)> = options
+        .watch_sources
+        .iter()
+        .map(|source| {
+            let options = Options {
+                files: vec![source.directory.clone()],
+                file_template: source
+                    .file_template
+                    .clone()
+                    .unwrap_or(options.file_template.clone()),
+                extensions: match source.extensions.is_empty() {
+                    true => options.extensions.clone(),
+                    false => source.extensions.clone(),
+                },
+                require_gps: source.require_gps || options.require_gps && !source.indoor_only,
+                indoor_only: source.indoor_only || options.indoor_only && !source.require_gps,
+                from_device: false,
+                retry_failed: false,
+                ..options.clone()
+            };
+            (Some(source.name.as_str()), options)
+        })
+        .collect();
+    if !options.files.is_empty() || options.from_device || sources.is_empty() {
+        sources.push((None, options.clone()));
+    }
+    sources
+}
+
+/// Files seen while polling the watched directories
+#[derive(Debug, Default)]
+struct Watcher {
+    /// Files waiting to be unchanged for one poll interval
+    pending: HashMap<PathBuf, FileState>,
+    /// Files archived in the state they had when they were archived
+    archived: HashMap<PathBuf, FileState>,
+}
+
+impl Watcher {
+    /// Returns the files that are ready to be archived
+    ///
+    /// A file is ready if it is unchanged since the previous poll and has not been archived in
+    /// this state yet. Files that vanished are forgotten, so they are archived again if they
+    /// appear again, i.e. after the device has been connected again.
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - FIT files currently contained in the watched directories.
+    fn poll(&mut self, files: Vec<PathBuf>) -> Vec<PathBuf> {
+        let mut ready = Vec::new();
+        let mut pending = HashMap::new();
+        for path in files {
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            let state = (metadata.len(), metadata.modified().ok());
+            if self.archived.get(&path) == Some(&state) {
+                continue;
+            }
+            if self.pending.get(&path) == Some(&state) {
+                ready.push(path.clone());
+            }
+            pending.insert(path, state);
+        }
+
+        self.archived.retain(|path, _| path.exists());
+        self.pending = pending;
+        ready
+    }
+
+    /// Returns the files waiting to be archived in sorted order
+    fn queue(&self) -> Vec<PathBuf> {
+        let mut queue: Vec<PathBuf> = self.pending.keys().cloned().collect();
+        queue.sort();
+        queue
+    }
+
+    /// Remember files as archived
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - Archived files.
+    fn archived(&mut self, files: &[PathBuf]) {
+        for path in files {
+            if let Some(state) = self.pending.remove(path) {
+                self.archived.insert(path.clone(), state);
+            }
+        }
+    }
+}
+
+/// Archive FIT files as soon as they appear in the watched directories
+///
+/// The list of files of the options contains the watched directories. Directories that are
+/// missing, i.e. the mount point of a disconnected device, are polled until they appear. The
+/// activity folders of devices are watched as well if requested, whenever they are connected. The
+/// directories of the watch sources are archived with their own template and filters. This
+/// function only returns on errors that prevent archiving at all. With a control socket the
+/// archiver reports its status and can be paused or asked to poll immediately. With a metrics
+/// address the counters of the runs are served to Prometheus.
+///
+/// # Arguments
+///
+/// `options` - Archiver options.
+pub fn watch(options: &Options) -> Result<String> {
+    let sources = source_options(options);
+    for (_, source) in &sources {
+        check_template(&source.file_template)?;
+        // warned once instead of on each batch
+        if source.template_lint {
+            print_template_warnings(&source.file_template);
+        }
+        if let Some(path) = source
+            .files
+            .iter()
+            .find(|path| path.exists() && !path.is_dir())
+        {
+            let msg = format!("Unable to watch '{}', not a directory", path.display());
+            return Err(ArchiverError::new(&msg));
+        }
+        // errors of later polls are ignored
+        check_source_tree(source)?;
+    }
+
+    let control = Arc::new(Control::default());
+    if let Some(path) = &options.control_socket {
+        listen(&control, path)?;
+    }
+    let metrics = Arc::new(Metrics::default());
+    if let Some(address) = &options.metrics {
+        let address = serve(&metrics, address)?;
+        eprintln!("Serving metrics on 'http://{}/metrics'", address);
+    }
+
+    let mut watcher = Watcher::default();
+    loop {
+        // new files are left alone until archiving is resumed
+        if control.paused() {
+            control.wait(Duration::from_secs(options.poll_interval));
+            continue;
+        }
+
+        // a file in several watched directories belongs to the first source listing it
+        let mut owners: HashMap<PathBuf, usize> = HashMap::new();
+        let mut listed = Vec::new();
+        for (index, (_, source)) in sources.iter().enumerate() {
+            // devices are archived whenever they are connected
+            let mut folders: Vec<PathBuf> = source
+                .files
+                .iter()
+                .filter(|path| path.is_dir())
+                .cloned()
+                .collect();
+            if source.from_device {
+                folders.extend(device_activity_folders().unwrap_or_default());
+            }
+            let directories = Options {
+                files: folders,
+                from_device: false,
+                ..source.clone()
+            };
+            for path in input_files(&directories).unwrap_or_default() {
+                if !owners.contains_key(&path) {
+                    owners.insert(path.clone(), index);
+                    listed.push(path);
+                }
+            }
+        }
+        let ready = watcher.poll(listed);
+        control.set_queue(watcher.queue());
+
+        // the sources are archived one after the other, so they never write the index at once
+        for (index, (name, source)) in sources.iter().enumerate() {
+            let files: Vec<PathBuf> = ready
+                .iter()
+                .filter(|path| owners.get(*path) == Some(&index))
+                .cloned()
+                .collect();
+            if files.is_empty() {
+                continue;
+            }
+            let result = process_files_summary(&Options {
+                files: files.clone(),
+                template_lint: false,
+                from_device: false,
+                ..source.clone()
+            });
+            metrics.add_result(&result, Utc::now());
+            let result = result.map(|(msg, _)| match name {
+                Some(name) => format!("{}: {}", name, msg),
+                None => msg,
+            });
+            control.add_result(&files, &result);
+            match result {
+                Ok(msg) => {
+                    println!("{}", msg);
+                    watcher.archived(&files);
+                    control.set_queue(watcher.queue());
+                }
+                Err(err) => match name {
+                    Some(name) => eprintln!("ERROR: {}: {}", name, err),
+                    None => eprintln!("ERROR: {}", err),
+                },
+            }
+        }
+        control.wait(Duration::from_secs(options.poll_interval));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fitarchiver::Options;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    /// Test parsing watch sources
+    fn test_watch_source_from_str() {
+        let source = "zwift=/home/jan/Zwift/Activities;template=zwift/%Y/%F-%H%M%S;indoor"
+            .parse::<super::WatchSource>()
+            .unwrap();
+        assert_eq!("zwift", source.name);
+        assert_eq!(
+            PathBuf::from("/home/jan/Zwift/Activities"),
+            source.directory
+        );
+        assert_eq!(
+            Some(String::from("zwift/%Y/%F-%H%M%S")),
+            source.file_template
+        );
+        assert!(source.indoor_only);
+        assert!(!source.require_gps);
+
+        let source = "dropbox=exports;extension=.fit.bak;extension=;gps"
+            .parse::<super::WatchSource>()
+            .unwrap();
+        assert_eq!(
+            vec![String::from("fit.bak"), String::new()],
+            source.extensions
+        );
+        assert_eq!(None, source.file_template);
+        assert!(source.require_gps);
+
+        "zwift"
+            .parse::<super::WatchSource>()
+            .expect_err("error expected");
+        "=exports"
+            .parse::<super::WatchSource>()
+            .expect_err("error expected");
+        "a/b=exports"
+            .parse::<super::WatchSource>()
+            .expect_err("error expected");
+        "zwift="
+            .parse::<super::WatchSource>()
+            .expect_err("error expected");
+        "zwift=z;template=${x}"
+            .parse::<super::WatchSource>()
+            .expect_err("error expected");
+        "zwift=z;recursive"
+            .parse::<super::WatchSource>()
+            .expect_err("error expected");
+        "zwift=z;gps;indoor"
+            .parse::<super::WatchSource>()
+            .expect_err("error expected");
+    }
+
+    #[test]
+    /// Test the options archiving the files of each watched source
+    fn test_source_options() {
+        let options = Options {
+            files: vec![PathBuf::from("/media/GARMIN/Garmin/Activities")],
+            require_gps: true,
+            extensions: vec![String::from("fit.bak")],
+            watch_sources: vec![
+                "zwift=zwift;template=zwift/%F-%H%M%S;indoor"
+                    .parse()
+                    .unwrap(),
+                "dropbox=dropbox;extension=dat".parse().unwrap(),
+            ],
+            ..Default::default()
+        };
+        let sources = super::source_options(&options);
+        assert_eq!(
+            vec![Some("zwift"), Some("dropbox"), None],
+            sources.iter().map(|(name, _)| *name).collect::<Vec<_>>()
+        );
+
+        let (_, zwift) = &sources[0];
+        assert_eq!(vec![PathBuf::from("zwift")], zwift.files);
+        assert_eq!("zwift/%F-%H%M%S", zwift.file_template);
+        assert_eq!(vec![String::from("fit.bak")], zwift.extensions);
+        assert!(zwift.indoor_only);
+        assert!(!zwift.require_gps);
+
+        let (_, dropbox) = &sources[1];
+        assert_eq!(options.file_template, dropbox.file_template);
+        assert_eq!(vec![String::from("dat")], dropbox.extensions);
+        assert!(dropbox.require_gps);
+
+        let (_, default) = &sources[2];
+        assert_eq!(options.files, default.files);
+
+        // without a list of files only the named sources are watched
+        let options = Options {
+            files: Vec::new(),
+            ..options
+        };
+        assert_eq!(2, super::source_options(&options).len());
+    }
+
+    #[test]
+    /// Test archiving the files of several watch sources into the same archive
+    fn test_watch_sources() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let archive_dir = tmpdir.path().join("archive");
+        let garmin = tmpdir.path().join("garmin");
+        let zwift = tmpdir.path().join("zwift");
+        fs::create_dir_all(&garmin).unwrap();
+        fs::create_dir_all(&zwift).unwrap();
+        fs::write(garmin.join("run.fit"), b"garmin").unwrap();
+        fs::write(zwift.join("ride.fit"), b"zwift").unwrap();
+
+        let options = Options {
+            directory: archive_dir,
+            files: vec![garmin.clone()],
+            watch_sources: vec![format!("zwift={};indoor", zwift.display()).parse().unwrap()],
+            ..Default::default()
+        };
+        let sources = super::source_options(&options);
+        // each file is listed by its own source
+        let listed: Vec<Vec<PathBuf>> = sources
+            .iter()
+            .map(|(_, source)| super::input_files(source).unwrap())
+            .collect();
+        assert_eq!(
+            vec![vec![zwift.join("ride.fit")], vec![garmin.join("run.fit")]],
+            listed
+        );
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+
+    #[test]
+    /// Test detecting new and changed files that are ready to be archived
+    fn test_watcher_poll() {
+        let tmpdir = TempDir::new("fitarchive").expect("Error during creating temporary directory");
+        let file = tmpdir.path().join("run.fit");
+        fs::write(&file, b"first").unwrap();
+        let mut watcher = super::Watcher::default();
+
+        // a new file is ready if it did not change until the next poll
+        assert!(watcher.poll(vec![file.clone()]).is_empty());
+        assert_eq!(vec![file.clone()], watcher.poll(vec![file.clone()]));
+        watcher.archived(std::slice::from_ref(&file));
+        assert!(watcher.poll(vec![file.clone()]).is_empty());
+
+        // a changed file is archived again
+        fs::write(&file, b"second write").unwrap();
+        assert!(watcher.poll(vec![file.clone()]).is_empty());
+        assert_eq!(vec![file.clone()], watcher.poll(vec![file.clone()]));
+
+        // a file that is not archived is retried
+        assert_eq!(vec![file.clone()], watcher.poll(vec![file.clone()]));
+        watcher.archived(std::slice::from_ref(&file));
+
+        // a file appearing again is archived again
+        fs::remove_file(&file).unwrap();
+        assert!(watcher.poll(Vec::new()).is_empty());
+        fs::write(&file, b"second write").unwrap();
+        assert!(watcher.poll(vec![file.clone()]).is_empty());
+        assert_eq!(vec![file.clone()], watcher.poll(vec![file.clone()]));
+
+        // cleanup
+        fs::remove_dir_all(&tmpdir).expect("error during removing temporary directory");
+    }
+}
@@ -0,0 +1,330 @@
+//! # Archive directories on WebDAV servers
+//!
+//! The archive directory 'webdav://[user@]host[:port]/path' is accessed over HTTPS with the
+//! `curl` command, i.e. 'webdav://cloud.example.com/remote.php/dav/files/me/activities' on a
+//! Nextcloud server. 'webdav+http://' uses plain HTTP for servers in the local network. The
+//! credentials are passed to curl on its standard input, so they do not show up in the list of
+//! processes.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use crate::objects::content_hash;
+use crate::remote::Remote;
+use chrono::DateTime;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Environment variable with the user of the WebDAV server
+pub const WEBDAV_USER_VARIABLE: &str = "FITARCHIVER_WEBDAV_USER";
+
+/// Environment variable with the password of the WebDAV server
+pub const WEBDAV_PASSWORD_VARIABLE: &str = "FITARCHIVER_WEBDAV_PASSWORD";
+
+/// Archive directory on a WebDAV server
+#[derive(Debug)]
+pub(crate) struct WebDavRemote {
+    /// Scheme, host and port of the server, i.e. 'https://cloud.example.com'
+    origin: String,
+    /// User of the server
+    user: Option<String>,
+    /// Password of the user
+    password: Option<String>,
+    /// Path of the archive directory on the server
+    directory: PathBuf,
+    /// Directories known to exist, so they are created only once per run
+    created: Mutex<HashSet<PathBuf>>,
+}
+
+/// Returns a path with all characters except unreserved ones and '/' percent-encoded
+///
+/// # Arguments
+///
+/// * `path` - Path on the server.
+fn encode_path(path: &Path) -> String {
+    path.to_string_lossy()
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Returns a text quoted for the configuration of curl
+///
+/// # Arguments
+///
+/// * `text` - Text to quote.
+fn config_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl WebDavRemote {
+    /// Returns the archive directory of a 'webdav://[user@]host[:port]/path' URL
+    ///
+    /// A user given in the URL takes precedence over the given user.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - URL of the archive directory.
+    /// * `user` - User of the server.
+    /// * `password` - Password of the user.
+    pub(crate) fn parse(
+        url: &str,
+        user: Option<&str>,
+        password: Option<&str>,
+    ) -> Result<WebDavRemote> {
+        let msg = format!(
+            "Invalid archive directory '{}', expected 'webdav://[user@]host[:port]/path'",
+            url
+        );
+        let (scheme, rest) = if let Some(rest) = url.strip_prefix("webdav://") {
+            ("https", rest)
+        } else if let Some(rest) = url.strip_prefix("webdav+http://") {
+            ("http", rest)
+        } else {
+            return Err(ArchiverError::new(&msg));
+        };
+        let Some((authority, path)) = rest.split_once('/') else {
+            return Err(ArchiverError::new(&msg));
+        };
+        let (url_user, host) = match authority.rsplit_once('@') {
+            Some((user, host)) => (Some(user), host),
+            None => (None, authority),
+        };
+        if host.is_empty() || host.starts_with(':') || url_user == Some("") {
+            return Err(ArchiverError::new(&msg));
+        }
+
+        Ok(WebDavRemote {
+            origin: format!("{}://{}", scheme, host),
+            user: url_user.or(user).map(String::from),
+            password: password.map(String::from),
+            directory: Path::new("/").join(path),
+            created: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Returns the URL of a path on the server
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path on the server.
+    fn url(&self, path: &Path) -> String {
+        format!("{}{}", self.origin, encode_path(path))
+    }
+
+    /// Send a request to the server and return the HTTP status code and the response
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - Arguments of curl selecting the request, i.e. '--head'.
+    /// * `path` - Path on the server.
+    fn request(&self, args: &[&str], path: &Path) -> Result<(u16, Vec<u8>)> {
+        let mut config = String::new();
+        if let Some(user) = &self.user {
+            let credentials = format!("{}:{}", user, self.password.as_deref().unwrap_or(""));
+            config = format!("user = {}\n", config_string(&credentials));
+        }
+
+        let msg = format!("Unable to run curl for '{}'", self.url(path));
+        let mut child = Command::new("curl")
+            .args(["--silent", "--show-error", "--config", "-"])
+            .args(["--write-out", "\n%{http_code}"])
+            .args(args)
+            .arg(self.url(path))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| ArchiverError::io(&msg, err))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(config.as_bytes())
+                .map_err(|err| ArchiverError::io(&msg, err))?;
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|err| ArchiverError::io(&msg, err))?;
+        if !output.status.success() {
+            let msg = format!(
+                "Unable to connect to '{}': {}",
+                self.origin,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return Err(ArchiverError::new(&msg));
+        }
+
+        // the status code is written after the response
+        let mut response = output.stdout;
+        let split = response
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+            .unwrap_or(0);
+        let code = String::from_utf8_lossy(&response[split..]).trim().parse();
+        response.truncate(split);
+        match code {
+            Ok(code) => Ok((code, response)),
+            Err(_) => {
+                let msg = format!("Invalid response of '{}'", self.url(path));
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+
+    /// Send a request to the server and return the response if the status code is expected
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - Arguments of curl selecting the request, i.e. '--head'.
+    /// * `path` - Path on the server.
+    /// * `expected` - Expected HTTP status codes.
+    /// * `msg` - Error message for other status codes.
+    fn check(&self, args: &[&str], path: &Path, expected: &[u16], msg: &str) -> Result<Vec<u8>> {
+        let (code, response) = self.request(args, path)?;
+        if !expected.contains(&code) {
+            let msg = format!("{} on '{}': HTTP status {}", msg, self.origin, code);
+            return Err(ArchiverError::new(&msg));
+        }
+        Ok(response)
+    }
+}
+
+impl Remote for WebDavRemote {
+    fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool> {
+        match self.request(&["--head"], path)? {
+            (200..=299, _) => Ok(true),
+            (404, _) => Ok(false),
+            (code, _) => {
+                let msg = format!(
+                    "Unable to access '{}': HTTP status {}",
+                    self.url(path),
+                    code
+                );
+                Err(ArchiverError::new(&msg))
+            }
+        }
+    }
+
+    fn file_hash(&self, path: &Path) -> Result<String> {
+        let msg = format!("Unable to read file '{}'", path.display());
+        let response = self.check(&[], path, &[200], &msg)?;
+        content_hash(response.as_slice()).map_err(|err| ArchiverError::io(&msg, err))
+    }
+
+    fn modified(&self, path: &Path) -> Result<SystemTime> {
+        let msg = format!("Unable to read file '{}'", path.display());
+        let response = self.check(&["--head"], path, &[200], &msg)?;
+        String::from_utf8_lossy(&response)
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("last-modified"))
+            .and_then(|(_, value)| DateTime::parse_from_rfc2822(value.trim()).ok())
+            .map(SystemTime::from)
+            .ok_or_else(|| ArchiverError::new(&msg))
+    }
+
+    fn create_directory(&self, path: &Path) -> Result<()> {
+        let mut created = self.created.lock().unwrap_or_else(|err| err.into_inner());
+        // parents of the archive directory are never created
+        let mut missing: Vec<&Path> = path
+            .ancestors()
+            .take_while(|parent| parent.starts_with(&self.directory))
+            .filter(|parent| !created.contains(*parent))
+            .collect();
+        missing.reverse();
+        for directory in missing {
+            let msg = format!("Unable to create directory '{}'", directory.display());
+            // an existing collection is reported as not allowed
+            self.check(&["--request", "MKCOL"], directory, &[201, 405], &msg)?;
+            created.insert(directory.to_path_buf());
+        }
+        Ok(())
+    }
+
+    fn upload(&self, source_path: &Path, path: &Path) -> Result<()> {
+        let msg = format!(
+            "Unable to copy '{}' to '{}'",
+            source_path.display(),
+            path.display()
+        );
+        let source = source_path.to_string_lossy();
+
+        // written under a temporary name, so an interrupted transfer never leaves a partial file
+        let mut temporary_path = path.as_os_str().to_owned();
+        temporary_path.push(".fitarchiver");
+        let temporary_path = PathBuf::from(temporary_path);
+        self.check(
+            &["--upload-file", &source],
+            &temporary_path,
+            &[200, 201, 204],
+            &msg,
+        )?;
+        let destination = format!("Destination: {}", self.url(path));
+        self.check(
+            &[
+                "--request",
+                "MOVE",
+                "--header",
+                &destination,
+                "--header",
+                "Overwrite: T",
+            ],
+            &temporary_path,
+            &[201, 204],
+            &msg,
+        )
+        .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    #[test]
+    /// Test parsing the URLs of archive directories on WebDAV servers
+    fn test_parse() {
+        let remote = super::WebDavRemote::parse(
+            "webdav://cloud.example.com:8443/remote.php/dav/files/me/Activities 2023",
+            Some("me"),
+            Some("secret"),
+        )
+        .unwrap();
+        assert_eq!("https://cloud.example.com:8443", remote.origin);
+        assert_eq!(Some("me"), remote.user.as_deref());
+        assert_eq!(
+            Path::new("/remote.php/dav/files/me/Activities 2023"),
+            remote.directory
+        );
+        assert_eq!(
+            "https://cloud.example.com:8443/remote.php/dav/files/me/Activities%202023/run%231.fit",
+            remote.url(&remote.directory.join("run#1.fit"))
+        );
+
+        let remote =
+            super::WebDavRemote::parse("webdav+http://pi@nas/dav/", Some("me"), None).unwrap();
+        assert_eq!("http://nas", remote.origin);
+        assert_eq!(Some("pi"), remote.user.as_deref());
+
+        for url in [
+            "webdav://nas",
+            "webdav:///dav",
+            "webdav://@nas/dav",
+            "sftp://nas/dav",
+        ] {
+            super::WebDavRemote::parse(url, None, None).expect_err("error expected");
+        }
+
+        assert_eq!(r#""me:se\"c\\ret""#, super::config_string(r#"me:se"c\ret"#));
+    }
+}
@@ -0,0 +1,118 @@
+//! # Webhook notifications
+//!
+//! The result of each archived file and the summary of the run are posted as JSON to a URL with
+//! the `curl` command, i.e. to log every new activity in a home automation. The payloads are the
+//! objects of the JSON output. A failing webhook is reported but does not affect archiving.
+
+use crate::fitarchiver::{ArchiverError, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Maximum time in seconds for posting a payload
+const TIMEOUT: &str = "10";
+
+/// Returns the URL of a webhook if it is a HTTP or HTTPS URL
+///
+/// # Arguments
+///
+/// * `url` - URL of the webhook.
+pub fn parse_webhook_url(url: &str) -> Result<String> {
+    match url.split_once("://") {
+        Some(("http" | "https", host)) if !host.is_empty() => Ok(String::from(url)),
+        _ => {
+            let msg = format!(
+                "Invalid webhook URL '{}', expected 'http://' or 'https://'",
+                url
+            );
+            Err(ArchiverError::new(&msg))
+        }
+    }
+}
+
+/// Post a JSON payload to a webhook
+///
+/// The payload is passed to curl on its standard input.
+///
+/// # Arguments
+///
+/// * `url` - URL of the webhook.
+/// * `payload` - JSON object to post.
+pub(crate) fn post_webhook(url: &str, payload: &serde_json::Value) -> Result<()> {
+    let msg = format!("Unable to post to webhook '{}'", url);
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--max-time", TIMEOUT])
+        .args(["--header", "Content-Type: application/json"])
+        .args(["--data-binary", "@-", "--output", "-"])
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| ArchiverError::io(&msg, err))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(payload.to_string().as_bytes())
+            .map_err(|err| ArchiverError::io(&msg, err))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|err| ArchiverError::io(&msg, err))?;
+    if !output.status.success() {
+        let msg = format!(
+            "{}: {}",
+            msg,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return Err(ArchiverError::new(&msg));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    /// Test posting payloads to a webhook
+    fn test_post_webhook() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/activities", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line.trim().is_empty() {
+                    break;
+                }
+                request.push_str(&line);
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            (request, String::from_utf8(body).unwrap())
+        });
+
+        let payload = serde_json::json!({"type": "file", "action": "copied"});
+        super::post_webhook(&url, &payload).unwrap();
+        let (request, body) = server.join().unwrap();
+        assert!(request.starts_with("POST /activities HTTP/1.1"));
+        assert!(request.contains("Content-Type: application/json"));
+        assert_eq!(payload.to_string(), body);
+
+        assert_eq!(url, super::parse_webhook_url(&url).unwrap());
+        super::parse_webhook_url("ftp://nas/hook").expect_err("error expected");
+        super::parse_webhook_url("https://").expect_err("error expected");
+    }
+}
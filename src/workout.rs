@@ -0,0 +1,188 @@
+//! # Structure of structured workouts
+//!
+//! Activities executed from a structured workout contain the steps of the workout. The steps are
+//! summarized as signature, i.e. '15min_wu-6x(3min_on_2min_off)-10min_cd', so interval sessions
+//! are identified by their structure regardless of the name given to the workout.
+
+use fitparser::FitDataRecord;
+
+/// Step of a structured workout
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct WorkoutStep {
+    /// Index of the step within the workout
+    index: usize,
+    /// Type of the duration, i.e. 'time', 'distance' or 'repeat_until_steps_cmplt'
+    duration_type: String,
+    /// Time in seconds, distance in meters or index of the first step of a repeat
+    duration: Option<f64>,
+    /// Number of repetitions of a repeat
+    repetitions: Option<f64>,
+    /// Intensity, i.e. 'active' or 'rest'
+    intensity: String,
+}
+
+impl WorkoutStep {
+    /// Returns true if the step repeats previous steps
+    fn is_repeat(&self) -> bool {
+        self.duration_type.starts_with("repeat")
+    }
+
+    /// Returns the short text of a step, i.e. '3min_on'
+    fn text(&self) -> String {
+        let amount = match (self.duration_type.as_str(), self.duration) {
+            ("time", Some(seconds)) if seconds % 60.0 == 0.0 => format!("{}min", seconds / 60.0),
+            ("time", Some(seconds)) => format!("{}s", seconds.round()),
+            ("distance", Some(meters)) if meters >= 1000.0 => format!("{}km", meters / 1000.0),
+            ("distance", Some(meters)) => format!("{}m", meters.round()),
+            (duration_type, _) => duration_type.to_string(),
+        };
+        let intensity = match self.intensity.as_str() {
+            // steps without intensity are active
+            "" | "active" | "interval" => "on",
+            "rest" | "recovery" => "off",
+            "warmup" => "wu",
+            "cooldown" => "cd",
+            intensity => intensity,
+        };
+        format!("{}_{}", amount, intensity)
+    }
+}
+
+/// Returns a step of a workout from a workout step message
+///
+/// # Arguments
+///
+/// * `data` - Workout step message.
+/// * `position` - Position of the message, used without message index.
+pub(crate) fn workout_step(data: &FitDataRecord, position: usize) -> WorkoutStep {
+    let mut step = WorkoutStep {
+        index: position,
+        ..Default::default()
+    };
+    for field in data.fields() {
+        let value: Option<f64> = field.value().clone().try_into().ok();
+        match field.name() {
+            "message_index" => step.index = value.map_or(position, |index| index as usize),
+            "duration_type" => step.duration_type = field.value().to_string(),
+            "intensity" => step.intensity = field.value().to_string(),
+            "repeat_steps" | "target_value" if step.repetitions.is_none() => {
+                step.repetitions = value
+            }
+            // the duration is decoded as sub field of its type, i.e. 'duration_time'
+            "duration_value" | "duration_time" | "duration_distance" | "duration_step" => {
+                step.duration = value
+            }
+            _ => (), // ignore all other values
+        }
+    }
+    // the target value is only the number of repetitions of repeats
+    if !step.is_repeat() {
+        step.repetitions = None;
+    }
+    step
+}
+
+/// Returns the number of steps of a workout, not counting the repeats
+///
+/// # Arguments
+///
+/// * `steps` - Steps of the workout.
+pub(crate) fn step_count(steps: &[WorkoutStep]) -> usize {
+    steps.iter().filter(|step| !step.is_repeat()).count()
+}
+
+/// Returns the signature of the structure of a workout, i.e. '6x(3min_on_2min_off)'
+///
+/// Repeated steps are grouped in parentheses after the number of repetitions, steps outside of
+/// repeats are separated by '-'. Returns 'unknown' for activities without workout.
+///
+/// # Arguments
+///
+/// * `steps` - Steps of the workout.
+pub(crate) fn workout_signature(steps: &[WorkoutStep]) -> String {
+    let mut steps = steps.to_vec();
+    steps.sort_by_key(|step| step.index);
+
+    // index of the first step of each part and its text
+    let mut parts: Vec<(usize, String)> = Vec::new();
+    for step in &steps {
+        if !step.is_repeat() {
+            parts.push((step.index, step.text()));
+            continue;
+        }
+        let first = step.duration.map_or(0, |index| index as usize);
+        let start = parts.partition_point(|(index, _)| *index < first);
+        if start == parts.len() {
+            continue;
+        }
+        let repeated: Vec<String> = parts.drain(start..).map(|(_, text)| text).collect();
+        let repetitions = step
+            .repetitions
+            .map_or(String::from("n"), |count| count.to_string());
+        parts.push((first, format!("{}x({})", repetitions, repeated.join("_"))));
+    }
+
+    match parts.is_empty() {
+        true => String::from("unknown"),
+        false => parts
+            .into_iter()
+            .map(|(_, text)| text)
+            .collect::<Vec<_>>()
+            .join("-"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WorkoutStep;
+
+    /// Returns a step of a workout
+    fn step(index: usize, duration_type: &str, duration: f64, intensity: &str) -> WorkoutStep {
+        WorkoutStep {
+            index,
+            duration_type: String::from(duration_type),
+            duration: Some(duration),
+            repetitions: None,
+            intensity: String::from(intensity),
+        }
+    }
+
+    #[test]
+    /// Test the signature of the structure of workouts
+    fn test_workout_signature() {
+        let repeat = |index: usize, first: f64, repetitions: f64| WorkoutStep {
+            repetitions: Some(repetitions),
+            ..step(index, "repeat_until_steps_cmplt", first, "")
+        };
+        let steps = vec![
+            step(0, "time", 900.0, "warmup"),
+            step(1, "time", 180.0, "active"),
+            step(2, "time", 120.0, "recovery"),
+            repeat(3, 1.0, 6.0),
+            step(4, "distance", 400.0, "interval"),
+            step(5, "time", 90.0, "rest"),
+            repeat(6, 4.0, 4.0),
+            step(7, "distance", 1500.0, ""),
+            step(8, "open", 0.0, "cooldown"),
+        ];
+        assert_eq!(
+            "15min_wu-6x(3min_on_2min_off)-4x(400m_on_90s_off)-1.5km_on-open_cd",
+            super::workout_signature(&steps)
+        );
+        assert_eq!(7, super::step_count(&steps));
+
+        // nested repeats
+        let steps = vec![
+            step(0, "time", 60.0, "active"),
+            step(1, "time", 60.0, "rest"),
+            repeat(2, 0.0, 3.0),
+            step(3, "time", 300.0, "rest"),
+            repeat(4, 0.0, 2.0),
+        ];
+        assert_eq!(
+            "2x(3x(1min_on_1min_off)_5min_off)",
+            super::workout_signature(&steps)
+        );
+        assert_eq!("unknown", super::workout_signature(&[]));
+    }
+}
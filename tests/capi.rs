@@ -0,0 +1,88 @@
+//! Calls the C interface through its C declarations like a C program linking the library
+
+#![cfg(feature = "capi")]
+
+use std::ffi::{c_char, CStr, CString};
+use std::path::PathBuf;
+
+// link the library, its symbols are only referenced through the declarations below
+use fitarchiver as _;
+
+/// Opaque activity handle of `include/fitarchiver.h`
+#[repr(C)]
+struct FitArchiverActivity {
+    _private: [u8; 0],
+}
+
+extern "C" {
+    fn fitarchiver_parse_file(path: *const c_char) -> *mut FitArchiverActivity;
+    fn fitarchiver_activity_free(activity: *mut FitArchiverActivity);
+    fn fitarchiver_activity_timestamp(activity: *const FitArchiverActivity) -> i64;
+    fn fitarchiver_expand_template(
+        activity: *const FitArchiverActivity,
+        template: *const c_char,
+    ) -> *mut c_char;
+    fn fitarchiver_archive_path(
+        activity: *const FitArchiverActivity,
+        directory: *const c_char,
+        template: *const c_char,
+    ) -> *mut c_char;
+    fn fitarchiver_string_free(string: *mut c_char);
+    fn fitarchiver_last_error() -> *const c_char;
+}
+
+/// Returns the path of a file in the test data directory
+fn test_data(name: &str) -> CString {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("test")
+        .join(name);
+    CString::new(path.to_str().unwrap()).unwrap()
+}
+
+/// Returns a string of the interface and releases it
+///
+/// # Safety
+///
+/// `string` must be a string returned by the interface.
+unsafe fn take_string(string: *mut c_char) -> String {
+    assert!(!string.is_null());
+    let result = CStr::from_ptr(string).to_str().unwrap().to_string();
+    fitarchiver_string_free(string);
+    result
+}
+
+#[test]
+/// Test the exported functions with the C calling convention
+fn test_capi_exported_functions() {
+    unsafe {
+        let activity = fitarchiver_parse_file(test_data("test_data_01.fit").as_ptr());
+        assert!(!activity.is_null());
+        assert_eq!(1690352524, fitarchiver_activity_timestamp(activity));
+
+        let template = CString::new("%Y-$s-$S").unwrap();
+        assert_eq!(
+            "2023-running-trail",
+            take_string(fitarchiver_expand_template(activity, template.as_ptr()))
+        );
+
+        let directory = CString::new("archive").unwrap();
+        let template = CString::new("%Y/%m/$n").unwrap();
+        assert_eq!(
+            "archive/2023/07/trail_run.fit",
+            take_string(fitarchiver_archive_path(
+                activity,
+                directory.as_ptr(),
+                template.as_ptr()
+            ))
+        );
+        fitarchiver_activity_free(activity);
+
+        assert!(fitarchiver_parse_file(test_data("corrupted.fit").as_ptr()).is_null());
+        assert!(!fitarchiver_last_error().is_null());
+        assert!(fitarchiver_parse_file(std::ptr::null()).is_null());
+        assert_eq!(
+            "'path' is NULL",
+            CStr::from_ptr(fitarchiver_last_error()).to_str().unwrap()
+        );
+    }
+}